@@ -0,0 +1,127 @@
+//! `IdTracker` against the `HashSet<u32>` it replaced, over the default ID
+//! range with a long run's worth of IDs attempted: the memory each holds,
+//! and the cost of looking IDs up and marking them attempted.
+
+use beer_scape::download::{MAX_RECIPE_ID, MIN_RECIPE_ID};
+use beer_scape::ids::IdTracker;
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// IDs attempted before the measurements, a quarter of the range
+const ATTEMPTED: usize = 1_000_000;
+
+/// Counts the bytes allocated and not yet freed, to see what each set
+/// holds on to.
+struct Counting;
+
+static LIVE: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        LIVE.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        LIVE.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+fn attempted_ids() -> Vec<u32> {
+    let mut rng = StdRng::seed_from_u64(7);
+    (0..ATTEMPTED)
+        .map(|_| rng.gen_range(MIN_RECIPE_ID..=MAX_RECIPE_ID))
+        .collect()
+}
+
+fn tracker(ids: &[u32]) -> IdTracker {
+    let mut tracker = IdTracker::new(MIN_RECIPE_ID..=MAX_RECIPE_ID);
+    for &id in ids {
+        tracker.insert(id);
+    }
+    tracker
+}
+
+fn hash_set(ids: &[u32]) -> HashSet<u32> {
+    ids.iter().copied().collect()
+}
+
+/// Bytes still allocated once `build` has returned what it made.
+fn held_by<T>(build: impl FnOnce() -> T) -> (T, usize) {
+    let before = LIVE.load(Ordering::Relaxed);
+    let built = build();
+    (built, LIVE.load(Ordering::Relaxed) - before)
+}
+
+fn memory(_: &mut Criterion) {
+    let ids = attempted_ids();
+    let (tracker, tracker_bytes) = held_by(|| tracker(&ids));
+    let (set, set_bytes) = held_by(|| hash_set(&ids));
+    println!(
+        "memory after {} random picks from {}-{} ({} distinct): IdTracker {} KB, HashSet<u32> {} KB",
+        ATTEMPTED,
+        MIN_RECIPE_ID,
+        MAX_RECIPE_ID,
+        set.len(),
+        tracker_bytes / 1024,
+        set_bytes / 1024
+    );
+    black_box((tracker, set));
+}
+
+fn lookups(c: &mut Criterion) {
+    let ids = attempted_ids();
+    let tracker = tracker(&ids);
+    let set = hash_set(&ids);
+    let mut rng = StdRng::seed_from_u64(11);
+    let probes: Vec<u32> = (0..10_000)
+        .map(|_| rng.gen_range(MIN_RECIPE_ID..=MAX_RECIPE_ID))
+        .collect();
+
+    let mut group = c.benchmark_group("contains");
+    group.bench_function("IdTracker", |b| {
+        b.iter(|| probes.iter().filter(|&&id| tracker.contains(id)).count())
+    });
+    group.bench_function("HashSet<u32>", |b| {
+        b.iter(|| probes.iter().filter(|&&id| set.contains(&id)).count())
+    });
+    group.finish();
+
+    let mut group = c.benchmark_group("insert");
+    group.bench_function("IdTracker", |b| {
+        b.iter_batched(
+            || tracker.clone(),
+            |mut tracker| {
+                for &id in &probes {
+                    tracker.insert(id);
+                }
+                tracker
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.bench_function("HashSet<u32>", |b| {
+        b.iter_batched(
+            || set.clone(),
+            |mut set| {
+                for &id in &probes {
+                    set.insert(id);
+                }
+                set
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, memory, lookups);
+criterion_main!(benches);
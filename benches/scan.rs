@@ -0,0 +1,24 @@
+//! The startup scan of the recipes folder, over 10,000 files. It should
+//! take well under a second.
+
+use beer_scape::cli::StorageLayout;
+use beer_scape::collection::scan_stems;
+use criterion::{criterion_group, criterion_main, Criterion};
+use indicatif::ProgressBar;
+use std::fs;
+use tempfile::TempDir;
+
+fn scan(c: &mut Criterion) {
+    let dir = TempDir::new().unwrap();
+    for id in 0..10_000 {
+        fs::write(dir.path().join(format!("{}.bsmx", id)), "<Recipe/>").unwrap();
+    }
+    fs::write(dir.path().join("index.db"), "").unwrap();
+
+    c.bench_function("scan 10,000 files", |b| {
+        b.iter(|| scan_stems(dir.path(), StorageLayout::Flat, &ProgressBar::hidden()).unwrap())
+    });
+}
+
+criterion_group!(benches, scan);
+criterion_main!(benches);
@@ -0,0 +1,176 @@
+use crate::cli::ArchiveFormat;
+use chrono::{DateTime, Datelike, Timelike, Utc};
+use flate2::write::GzEncoder;
+use indicatif::ProgressBar;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::Path;
+use std::time::SystemTime;
+use zip::write::SimpleFileOptions;
+use zip::ZipWriter;
+
+/// Name of the file at the top of an archive listing what's in it.
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// What an archive holds, with a checksum for each file so a copy can be
+/// checked after it's been unpacked.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub created_at: DateTime<Utc>,
+    /// For --incremental archives, the archive they carry on from: only
+    /// files changed since it started are in this one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub since: Option<DateTime<Utc>>,
+    pub files: Vec<ManifestEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// Where the file is in the archive
+    pub path: String,
+    pub bytes: u64,
+    /// SHA-256 of the contents, in hex
+    pub sha256: String,
+}
+
+/// An archive being written straight to disk, one file at a time, so
+/// memory use doesn't grow with the collection.
+pub struct ArchiveWriter {
+    out: Out,
+    manifest: Manifest,
+}
+
+enum Out {
+    Zip(ZipWriter<BufWriter<File>>),
+    TarGz(tar::Builder<GzEncoder<BufWriter<File>>>),
+}
+
+impl ArchiveWriter {
+    pub fn create(
+        path: &Path,
+        format: ArchiveFormat,
+        since: Option<DateTime<Utc>>,
+    ) -> io::Result<Self> {
+        let file = BufWriter::new(File::create(path)?);
+        let out = match format {
+            ArchiveFormat::Zip => Out::Zip(ZipWriter::new(file)),
+            ArchiveFormat::TarGz => Out::TarGz(tar::Builder::new(GzEncoder::new(
+                file,
+                flate2::Compression::default(),
+            ))),
+        };
+        Ok(ArchiveWriter {
+            out,
+            manifest: Manifest {
+                created_at: Utc::now(),
+                since,
+                files: Vec::new(),
+            },
+        })
+    }
+
+    /// Copy the file at `path` into the archive as `name`, ticking `pb`
+    /// by each block's bytes as they go.
+    pub fn add_file(
+        &mut self,
+        name: &str,
+        path: &Path,
+        pb: &ProgressBar,
+    ) -> Result<(), Box<dyn Error>> {
+        let file = File::open(path)?;
+        let metadata = file.metadata()?;
+        let mut reader = Hashing {
+            inner: file,
+            hasher: Sha256::new(),
+            bytes: 0,
+            pb,
+        };
+        self.add(name, metadata.len(), metadata.modified()?, &mut reader)?;
+        self.manifest.files.push(ManifestEntry {
+            path: name.to_string(),
+            bytes: reader.bytes,
+            sha256: format!("{:x}", reader.hasher.finalize()),
+        });
+        Ok(())
+    }
+
+    /// Write the manifest and finish the archive, returning the manifest.
+    pub fn finish(mut self) -> Result<Manifest, Box<dyn Error>> {
+        let manifest = serde_json::to_vec_pretty(&self.manifest)?;
+        self.add(
+            MANIFEST_FILE,
+            manifest.len() as u64,
+            SystemTime::now(),
+            &mut manifest.as_slice(),
+        )?;
+        match self.out {
+            Out::Zip(zip) => {
+                zip.finish()?.flush()?;
+            }
+            Out::TarGz(tar) => {
+                tar.into_inner()?.finish()?.flush()?;
+            }
+        }
+        Ok(self.manifest)
+    }
+
+    fn add(
+        &mut self,
+        name: &str,
+        size: u64,
+        modified: SystemTime,
+        contents: &mut dyn Read,
+    ) -> Result<(), Box<dyn Error>> {
+        let modified: DateTime<Utc> = modified.into();
+        match &mut self.out {
+            Out::Zip(zip) => {
+                let mut options = SimpleFileOptions::default()
+                    .compression_method(zip::CompressionMethod::Deflated)
+                    .large_file(size >= u32::MAX as u64);
+                // Zip times can't go back before 1980
+                if let Ok(time) = zip::DateTime::from_date_and_time(
+                    modified.year() as u16,
+                    modified.month() as u8,
+                    modified.day() as u8,
+                    modified.hour() as u8,
+                    modified.minute() as u8,
+                    modified.second() as u8,
+                ) {
+                    options = options.last_modified_time(time);
+                }
+                zip.start_file(name, options)?;
+                io::copy(contents, zip)?;
+            }
+            Out::TarGz(tar) => {
+                let mut header = tar::Header::new_gnu();
+                header.set_size(size);
+                header.set_mode(0o644);
+                header.set_mtime(modified.timestamp().max(0) as u64);
+                header.set_cksum();
+                tar.append_data(&mut header, name, contents)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A reader that hashes and counts what passes through it.
+struct Hashing<'a, R> {
+    inner: R,
+    hasher: Sha256,
+    bytes: u64,
+    pb: &'a ProgressBar,
+}
+
+impl<R: Read> Read for Hashing<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        self.bytes += n as u64;
+        self.pb.inc(n as u64);
+        Ok(n)
+    }
+}
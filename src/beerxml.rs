@@ -0,0 +1,231 @@
+use crate::bsmx::{
+    non_negative, notes_text, number, parse_document, recorded_or_calculated_abv, text,
+    BsmxVersion, Element, Fermentable, Flocculation, Hop, MashStep, ParseError, Recipe, Style,
+    Yeast,
+};
+use quick_xml::escape::escape;
+use std::fmt::Write;
+
+/// Render a recipe as a BeerXML 1.0 document.
+///
+/// BeerXML has no place for a few of beerscape's fields, so IBU, color and
+/// ABV use the optional extension elements from appendix A.
+pub fn export_beerxml(recipe: &Recipe) -> String {
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<RECIPES>\n");
+    out.push_str("  <RECIPE>\n");
+    tag(&mut out, 4, "NAME", &recipe.name);
+    tag(&mut out, 4, "VERSION", "1");
+    tag(&mut out, 4, "TYPE", "All Grain");
+    tag(&mut out, 4, "BREWER", &recipe.brewer);
+
+    out.push_str("    <STYLE>\n");
+    tag(&mut out, 6, "NAME", &recipe.style.name);
+    tag(&mut out, 6, "VERSION", "1");
+    tag(&mut out, 6, "CATEGORY", &recipe.style.category);
+    tag(&mut out, 6, "CATEGORY_NUMBER", &recipe.style.number);
+    tag(&mut out, 6, "STYLE_LETTER", &recipe.style.letter);
+    tag(&mut out, 6, "STYLE_GUIDE", "BJCP");
+    out.push_str("    </STYLE>\n");
+
+    tag(&mut out, 4, "BATCH_SIZE", &recipe.batch_size_l.to_string());
+    tag(&mut out, 4, "BOIL_TIME", &recipe.boil_time_min.to_string());
+
+    out.push_str("    <FERMENTABLES>\n");
+    for fermentable in &recipe.fermentables {
+        out.push_str("      <FERMENTABLE>\n");
+        tag(&mut out, 8, "NAME", &fermentable.name);
+        tag(&mut out, 8, "VERSION", "1");
+        tag(&mut out, 8, "TYPE", "Grain");
+        tag(&mut out, 8, "AMOUNT", &fermentable.amount_kg.to_string());
+        tag(&mut out, 8, "COLOR", &fermentable.color_srm.to_string());
+        out.push_str("      </FERMENTABLE>\n");
+    }
+    out.push_str("    </FERMENTABLES>\n");
+
+    out.push_str("    <HOPS>\n");
+    for hop in &recipe.hops {
+        out.push_str("      <HOP>\n");
+        tag(&mut out, 8, "NAME", &hop.name);
+        tag(&mut out, 8, "VERSION", "1");
+        tag(&mut out, 8, "ALPHA", &hop.alpha_pct.to_string());
+        // BeerXML weighs everything in kilograms
+        tag(&mut out, 8, "AMOUNT", &(hop.amount_g / 1000.0).to_string());
+        tag(&mut out, 8, "USE", "Boil");
+        tag(&mut out, 8, "TIME", &hop.boil_time_min.to_string());
+        out.push_str("      </HOP>\n");
+    }
+    out.push_str("    </HOPS>\n");
+
+    out.push_str("    <YEASTS>\n");
+    for yeast in &recipe.yeasts {
+        out.push_str("      <YEAST>\n");
+        tag(&mut out, 8, "NAME", &yeast.name);
+        tag(&mut out, 8, "VERSION", "1");
+        tag(&mut out, 8, "LABORATORY", &yeast.lab);
+        tag(&mut out, 8, "PRODUCT_ID", &yeast.product_id);
+        tag(
+            &mut out,
+            8,
+            "MIN_TEMPERATURE",
+            &yeast.min_temp_c.to_string(),
+        );
+        tag(
+            &mut out,
+            8,
+            "MAX_TEMPERATURE",
+            &yeast.max_temp_c.to_string(),
+        );
+        tag(
+            &mut out,
+            8,
+            "ATTENUATION",
+            &yeast.attenuation_pct.to_string(),
+        );
+        tag(&mut out, 8, "FLOCCULATION", &yeast.flocculation.to_string());
+        out.push_str("      </YEAST>\n");
+    }
+    out.push_str("    </YEASTS>\n");
+
+    if !recipe.mash_steps.is_empty() {
+        out.push_str("    <MASH>\n");
+        tag(&mut out, 6, "NAME", "Mash");
+        tag(&mut out, 6, "VERSION", "1");
+        out.push_str("      <MASH_STEPS>\n");
+        for step in &recipe.mash_steps {
+            out.push_str("        <MASH_STEP>\n");
+            tag(&mut out, 10, "NAME", &step.name);
+            tag(&mut out, 10, "VERSION", "1");
+            tag(&mut out, 10, "TYPE", "Infusion");
+            tag(&mut out, 10, "STEP_TEMP", &step.temp_c.to_string());
+            tag(&mut out, 10, "STEP_TIME", &step.time_min.to_string());
+            out.push_str("        </MASH_STEP>\n");
+        }
+        out.push_str("      </MASH_STEPS>\n");
+        out.push_str("    </MASH>\n");
+    }
+
+    tag(&mut out, 4, "OG", &recipe.og.to_string());
+    tag(&mut out, 4, "FG", &recipe.fg.to_string());
+    tag(&mut out, 4, "IBU", &recipe.ibu.to_string());
+    tag(&mut out, 4, "EST_COLOR", &recipe.color_srm.to_string());
+    tag(&mut out, 4, "ABV", &recipe.abv.to_string());
+    if let Some(notes) = &recipe.notes {
+        tag(&mut out, 4, "NOTES", notes);
+    }
+    if let Some(taste_notes) = &recipe.taste_notes {
+        tag(&mut out, 4, "TASTE_NOTES", taste_notes);
+    }
+    out.push_str("  </RECIPE>\n</RECIPES>\n");
+    out
+}
+
+fn tag(out: &mut String, indent: usize, name: &str, value: &str) {
+    let _ = writeln!(
+        out,
+        "{:indent$}<{name}>{}</{name}>",
+        "",
+        escape(value),
+        indent = indent,
+        name = name
+    );
+}
+
+/// Read the first recipe from a BeerXML document.
+pub fn parse_beerxml(xml: &[u8]) -> Result<Recipe, ParseError> {
+    let document = parse_document(xml)?;
+    let recipe = document.find("RECIPE").ok_or(ParseError::MissingRecipe)?;
+
+    let style = recipe
+        .child("STYLE")
+        .map(|s| Style {
+            name: text(s, "NAME"),
+            category: text(s, "CATEGORY"),
+            number: text(s, "CATEGORY_NUMBER"),
+            letter: text(s, "STYLE_LETTER"),
+        })
+        .unwrap_or_default();
+
+    let mut fermentables = Vec::new();
+    for fermentable in children(recipe, "FERMENTABLES", "FERMENTABLE") {
+        fermentables.push(Fermentable {
+            name: text(fermentable, "NAME"),
+            amount_kg: non_negative(fermentable, "AMOUNT")?,
+            color_srm: number(fermentable, "COLOR")?,
+        });
+    }
+
+    let mut hops = Vec::new();
+    for hop in children(recipe, "HOPS", "HOP") {
+        hops.push(Hop {
+            name: text(hop, "NAME"),
+            amount_g: non_negative(hop, "AMOUNT")? * 1000.0,
+            alpha_pct: number(hop, "ALPHA")?,
+            boil_time_min: non_negative(hop, "TIME")?,
+        });
+    }
+
+    let mut yeasts = Vec::new();
+    for yeast in children(recipe, "YEASTS", "YEAST") {
+        yeasts.push(Yeast {
+            name: text(yeast, "NAME"),
+            lab: text(yeast, "LABORATORY"),
+            product_id: text(yeast, "PRODUCT_ID"),
+            min_temp_c: number(yeast, "MIN_TEMPERATURE")?,
+            max_temp_c: number(yeast, "MAX_TEMPERATURE")?,
+            attenuation_pct: number(yeast, "ATTENUATION")?,
+            flocculation: Flocculation::from_name(&text(yeast, "FLOCCULATION")),
+        });
+    }
+
+    let mut mash_steps = Vec::new();
+    if let Some(mash) = recipe.child("MASH") {
+        for step in children(mash, "MASH_STEPS", "MASH_STEP") {
+            mash_steps.push(MashStep {
+                name: text(step, "NAME"),
+                temp_c: number(step, "STEP_TEMP")?,
+                time_min: non_negative(step, "STEP_TIME")?,
+            });
+        }
+    }
+
+    let og = number(recipe, "OG")?;
+    let fg = number(recipe, "FG")?;
+    let (abv, abv_source) = recorded_or_calculated_abv(number(recipe, "ABV")?, og, fg);
+
+    let name = text(recipe, "NAME");
+    let notes = notes_text(recipe, &["NOTES"], &name);
+    let taste_notes = notes_text(recipe, &["TASTE_NOTES"], &name);
+    Ok(Recipe {
+        name,
+        brewer: text(recipe, "BREWER"),
+        style,
+        og,
+        fg,
+        abv,
+        abv_source,
+        ibu: number(recipe, "IBU")?,
+        color_srm: number(recipe, "EST_COLOR")?,
+        batch_size_l: non_negative(recipe, "BATCH_SIZE")?,
+        boil_time_min: non_negative(recipe, "BOIL_TIME")?,
+        fermentables,
+        hops,
+        yeasts,
+        mash_steps,
+        notes,
+        taste_notes,
+        bsmx_version: BsmxVersion::Unknown,
+        // BeerXML is always metric
+        recorded: None,
+    })
+}
+
+fn children<'a>(
+    recipe: &'a Element,
+    list: &str,
+    item: &'a str,
+) -> impl Iterator<Item = &'a Element> {
+    recipe
+        .child(list)
+        .into_iter()
+        .flat_map(move |l| l.children.iter().filter(move |c| c.name == item))
+}
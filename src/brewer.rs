@@ -0,0 +1,32 @@
+/// Values BeerSmith users put in the brewer field when there's nobody to
+/// credit. Compared after case-folding.
+const PLACEHOLDERS: &[&str] = &[
+    "",
+    "-",
+    "--",
+    "?",
+    "unknown",
+    "anonymous",
+    "anon",
+    "n/a",
+    "na",
+    "none",
+    "brewer",
+];
+
+/// A brewer as it should be shown: trimmed, with runs of whitespace
+/// collapsed to one space.
+pub fn display_name(brewer: &str) -> String {
+    brewer.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Key for grouping brewers regardless of case and spacing, or `None` for
+/// empty and placeholder values like "Unknown" or "-".
+pub fn key(brewer: &str) -> Option<String> {
+    let key = display_name(brewer).to_lowercase();
+    if PLACEHOLDERS.contains(&key.as_str()) {
+        None
+    } else {
+        Some(key)
+    }
+}
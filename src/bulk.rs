@@ -0,0 +1,262 @@
+use crate::bsmx::{parse_document, split_recipes, write_document, Element};
+use crate::collection::Provenance;
+use crate::download::{
+    accept_compressed, attempt, outcome_of, rate_limited, read_body, request_user_agent,
+    send_download, store_recipe, DownloadConfig, Outcome,
+};
+use reqwest::header::USER_AGENT;
+use reqwest::{Client, StatusCode, Url};
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+use tracing::{debug, warn};
+
+/// IDs asked for in each bulk request unless --bulk-size says otherwise
+pub const DEFAULT_BULK_SIZE: u16 = 20;
+/// Where the bulk export is unless --bulk-url says otherwise
+pub const DEFAULT_BULK_URL: &str = "{base_url}/export.php?ids={ids}";
+/// Bulk requests failing in a row after which a run goes back to single
+/// requests for good
+pub const BULK_FAILURE_LIMIT: usize = 3;
+/// Element a recipe in a bulk export carries its ID in
+pub const RECIPE_ID_ELEMENT: &str = "F_R_ID";
+
+/// How a run fetches several IDs per request through the bulk export, and
+/// whether the export has been working.
+#[derive(Debug)]
+pub struct BulkFetch {
+    pub size: usize,
+    url_template: String,
+    /// Bulk requests that have failed since the last one that worked
+    failures: AtomicUsize,
+}
+
+impl BulkFetch {
+    pub fn new(size: usize, url_template: &str) -> Result<Self, String> {
+        if !url_template.contains("{ids}") {
+            return Err(format!(
+                "--bulk-url {:?} has no {{ids}} for the IDs to go in",
+                url_template
+            ));
+        }
+        Ok(BulkFetch {
+            size,
+            url_template: url_template.to_string(),
+            failures: AtomicUsize::new(0),
+        })
+    }
+
+    /// The export URL asking for `ids`.
+    pub fn url(&self, base_url: &str, ids: &[u32]) -> String {
+        let ids: Vec<String> = ids.iter().map(u32::to_string).collect();
+        self.url_template
+            .replace("{base_url}", base_url)
+            .replace("{ids}", &ids.join(","))
+    }
+
+    /// Whether IDs should still be grouped, i.e. the export hasn't failed
+    /// [`BULK_FAILURE_LIMIT`] times in a row.
+    pub fn active(&self) -> bool {
+        self.failures.load(Ordering::Relaxed) < BULK_FAILURE_LIMIT
+    }
+
+    /// IDs to put in each request: `size` while the export works, one
+    /// after it has given up.
+    pub fn group_size(&self) -> usize {
+        if self.active() {
+            self.size
+        } else {
+            1
+        }
+    }
+
+    fn failed(&self) {
+        let failures = self.failures.fetch_add(1, Ordering::Relaxed) + 1;
+        if failures == BULK_FAILURE_LIMIT {
+            warn!(
+                "The bulk export failed {} times in a row; fetching one ID per request from now on",
+                failures
+            );
+        }
+    }
+
+    fn worked(&self) {
+        self.failures.store(0, Ordering::Relaxed);
+    }
+}
+
+/// Split a bulk export into one document per recipe, by the ID each one
+/// carries in [`RECIPE_ID_ELEMENT`]. If none of them carry one and there
+/// are as many recipes as `ids`, they're taken to be in the order asked
+/// for. IDs not asked for are ignored, as are recipes without an ID when
+/// others have one.
+pub fn recipes_by_id(root: &Element, ids: &[u32]) -> HashMap<u32, Element> {
+    let documents = split_recipes(root).unwrap_or_else(|| {
+        let mut recipes = Vec::new();
+        root.find_all("Recipe", &mut recipes);
+        if recipes.is_empty() {
+            Vec::new()
+        } else {
+            vec![root.clone()]
+        }
+    });
+    let carried: Vec<Option<u32>> = documents.iter().map(carried_id).collect();
+    if carried.iter().all(Option::is_none) && documents.len() == ids.len() {
+        return ids.iter().copied().zip(documents).collect();
+    }
+    let unlabelled = carried.iter().filter(|id| id.is_none()).count();
+    if unlabelled > 0 {
+        warn!(
+            unlabelled,
+            "bulk export has recipes without an ID; skipping them"
+        );
+    }
+    carried
+        .into_iter()
+        .zip(documents)
+        .filter_map(|(id, document)| id.filter(|id| ids.contains(id)).map(|id| (id, document)))
+        .collect()
+}
+
+fn carried_id(document: &Element) -> Option<u32> {
+    let mut recipes = Vec::new();
+    document.find_all("Recipe", &mut recipes);
+    recipes
+        .first()?
+        .child_text(RECIPE_ID_ELEMENT)?
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// Fetch `ids` with one bulk request and classify each. IDs missing from
+/// the export count as not found, as if they'd answered 404. If the export
+/// fails, each ID is fetched on its own instead.
+pub(crate) async fn attempt_group(
+    client: &Client,
+    config: &DownloadConfig,
+    bulk: &BulkFetch,
+    ids: &[u32],
+) -> Vec<(u32, Outcome)> {
+    match fetch_group(client, config, bulk, ids).await {
+        Ok(Some(outcomes)) => {
+            bulk.worked();
+            outcomes
+        }
+        Ok(None) => {
+            bulk.failed();
+            fetch_singly(client, config, ids).await
+        }
+        Err(e) => {
+            warn!(error = %e, "bulk request failed; fetching its IDs one at a time");
+            bulk.failed();
+            fetch_singly(client, config, ids).await
+        }
+    }
+}
+
+async fn fetch_singly(
+    client: &Client,
+    config: &DownloadConfig,
+    ids: &[u32],
+) -> Vec<(u32, Outcome)> {
+    let mut outcomes = Vec::with_capacity(ids.len());
+    for &id in ids {
+        outcomes.push((id, attempt(client, config, id).await));
+    }
+    outcomes
+}
+
+/// The outcome for each ID, or `None` if the export didn't give a usable
+/// answer.
+async fn fetch_group(
+    client: &Client,
+    config: &DownloadConfig,
+    bulk: &BulkFetch,
+    ids: &[u32],
+) -> Result<Option<Vec<(u32, Outcome)>>, Box<dyn Error + Send + Sync>> {
+    let url = bulk.url(&config.base_url, ids);
+    config.requests.pass().await;
+    let sent = Instant::now();
+    let user_agent = request_user_agent(config);
+    let request = accept_compressed(client.get(&url).header(USER_AGENT, user_agent), config);
+    let response = send_download(request, config).await?;
+    config.responses.headers(sent.elapsed());
+    let status = response.status();
+    // Not the export's fault; the run renews the session and retries them
+    if status == StatusCode::UNAUTHORIZED {
+        return Ok(Some(
+            ids.iter()
+                .map(|&id| (id, Outcome::SessionExpired))
+                .collect(),
+        ));
+    }
+    if status == StatusCode::TOO_MANY_REQUESTS {
+        let retry_after = rate_limited(config, response.headers());
+        return Ok(Some(
+            ids.iter()
+                .map(|&id| (id, Outcome::RateLimited { retry_after }))
+                .collect(),
+        ));
+    }
+    if !status.is_success() {
+        warn!(
+            status = status.as_u16(),
+            "bulk export answered with an error"
+        );
+        return Ok(None);
+    }
+    let provenance = Provenance {
+        user_agent: Some(String::from_utf8_lossy(user_agent.as_bytes()).into_owned()),
+        final_url: (Url::parse(&url).ok().as_ref() != Some(response.url()))
+            .then(|| response.url().to_string()),
+    };
+
+    // Exports of many recipes are the big downloads most worth watching
+    let file_bar = config.file_bars.as_ref().and_then(|bars| {
+        let name = format!("export of {} recipes", ids.len());
+        bars.start(&name, 0, response.content_length())
+    });
+    let reading = Instant::now();
+    let body = read_body(
+        response,
+        Vec::new(),
+        None,
+        &config.transfers,
+        config.bandwidth.as_deref(),
+        config.read_timeout,
+        file_bar.as_ref(),
+    )
+    .await;
+    config.responses.body(reading.elapsed());
+    let Some(root) = body?.and_then(|content| parse_document(&content).ok()) else {
+        warn!("bulk export isn't a well-formed document");
+        return Ok(None);
+    };
+
+    let mut recipes = recipes_by_id(&root, ids);
+    debug!(asked = ids.len(), found = recipes.len(), "bulk export");
+    let mut outcomes = Vec::with_capacity(ids.len());
+    for &id in ids {
+        let outcome = match recipes.remove(&id) {
+            Some(document) => outcome_of(
+                config,
+                store_recipe(
+                    config,
+                    format!("{}.bsmx", id),
+                    write_document(&document).into_bytes(),
+                    status,
+                    &provenance,
+                )
+                .await,
+            ),
+            None => Outcome::Failed {
+                category: "not-found",
+                status: Some(StatusCode::NOT_FOUND.as_u16()),
+            },
+        };
+        outcomes.push((id, outcome));
+    }
+    Ok(Some(outcomes))
+}
@@ -0,0 +1,1431 @@
+use crate::bsmx::DEFAULT_PARSE_TIMEOUT_MS;
+use crate::bulk::{DEFAULT_BULK_SIZE, DEFAULT_BULK_URL};
+use crate::commands::daemon::{DEFAULT_CYCLE_ATTEMPTS, DEFAULT_JITTER};
+use crate::commands::deduplicate::DEFAULT_SIMILARITY;
+use crate::commands::discover::{DEFAULT_MAX_PAGES, DISCOVERED_IDS_FILE};
+use crate::download::{
+    RequestHeader, DEFAULT_BASE_URL, DEFAULT_CONNECT_TIMEOUT_MS, DEFAULT_FRONTIER_MISSES,
+    DEFAULT_HIT_RATE_WINDOW, DEFAULT_MAX_ATTEMPTS, DEFAULT_MAX_REDIRECTS, DEFAULT_MIN_HIT_RATE,
+    DEFAULT_OVERLAP, DEFAULT_READ_TIMEOUT_MS, DEFAULT_REQUEST_TIMEOUT_MS, DEFAULT_TARGET,
+    MAX_RECIPE_ID, MIN_RECIPE_ID,
+};
+use crate::fixtures::FIXTURES_DIR;
+use crate::ids::IdRange;
+use crate::limits::{ClockTime, RunDuration};
+use crate::login::{DEFAULT_LOGIN_PATH, DEFAULT_PASSWORD_FIELD, DEFAULT_USERNAME_FIELD};
+use crate::progress::BarStyle;
+use crate::sampling::DEFAULT_EXPLORATION;
+use crate::stratify::{DEFAULT_OVERSAMPLE, DEFAULT_SAMPLE_PROBE};
+use crate::tags::parse_tag;
+use crate::throttle::ByteSize;
+use crate::RECIPES_DIR;
+use chrono::{DateTime, FixedOffset};
+use clap::{ArgAction, ArgGroup, Args, Parser, Subcommand, ValueEnum};
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+
+#[derive(Debug, Parser)]
+#[command(
+    name = "beerscape",
+    version,
+    about = "Concurrent BeerSmith recipe downloader",
+    after_help = "\
+Exit status of a download run, which also prints a one-line status to stderr:
+  0  finished: the target was reached or every listed ID was tried
+  2  stopped early by a limit such as the daily quota or --deadline
+  3  interrupted by SIGINT or SIGTERM
+  4  bad configuration or an I/O error
+  5  gave up after too many requests in a row could not reach the site"
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    #[command(flatten)]
+    pub download: DownloadArgs,
+
+    /// Log more detail; repeat for even more
+    #[arg(short, long, action = ArgAction::Count, global = true)]
+    pub verbose: u8,
+
+    /// Only log errors; a download run prints just its final summary
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    pub quiet: bool,
+
+    /// Also write JSON logs, at debug level or finer, to this file
+    #[arg(long, value_name = "PATH", global = true)]
+    pub log_file: Option<PathBuf>,
+
+    /// Threads for parsing recipes when indexing or loading the whole
+    /// collection [default: one per logical CPU]
+    #[arg(long, value_name = "N", global = true, value_parser = clap::value_parser!(u16).range(1..))]
+    pub parse_threads: Option<u16>,
+
+    /// Formula for the ABV of recipes that record OG and FG but no ABV
+    #[arg(long, value_enum, value_name = "FORMULA", global = true, default_value_t = AbvFormula::Standard)]
+    pub abv_formula: AbvFormula,
+
+    /// Give up on parsing a recipe after this many milliseconds, setting
+    /// the file aside in recipes/parse_failed/
+    #[arg(long, value_name = "MS", global = true, default_value_t = DEFAULT_PARSE_TIMEOUT_MS, value_parser = clap::value_parser!(u64).range(1..))]
+    pub parse_timeout_ms: u64,
+}
+
+/// Options for a download run, used when no subcommand is given.
+#[derive(Debug, Clone, Args)]
+#[command(group(ArgGroup::new("sampling").multiple(true).args(["dry_run", "stratified"])))]
+pub struct DownloadArgs {
+    /// How to choose IDs
+    #[arg(long, value_enum, default_value_t = Mode::Random)]
+    pub mode: Mode,
+
+    /// In newest mode, stop after this many IDs in a row have no recipe
+    #[arg(long, value_name = "N", default_value_t = DEFAULT_FRONTIER_MISSES)]
+    pub frontier_misses: usize,
+
+    /// In newest mode, start this many IDs below the highest downloaded one,
+    /// to catch recipes published late
+    #[arg(long, value_name = "N", default_value_t = DEFAULT_OVERLAP)]
+    pub overlap: u32,
+
+    /// Stop after this many downloads per calendar day; 0 removes the limit
+    /// [default: the limit from the previous run]
+    #[arg(long, value_name = "N")]
+    pub daily_quota: Option<usize>,
+
+    /// Stop starting new downloads after this long, e.g. 5h or 1h30m
+    #[arg(long, value_name = "DURATION")]
+    pub max_duration: Option<RunDuration>,
+
+    /// Stop starting new downloads at this local time, e.g. 07:00
+    #[arg(long, value_name = "HH:MM")]
+    pub deadline: Option<ClockTime>,
+
+    /// Stop after this many download attempts; 0 removes the limit
+    #[arg(long, value_name = "N", default_value_t = DEFAULT_MAX_ATTEMPTS)]
+    pub max_attempts: usize,
+
+    /// Recipes to have in the collection before a random run stops
+    #[arg(long, value_name = "N", default_value_t = DEFAULT_TARGET)]
+    pub target: usize,
+
+    /// Stop once fewer than this percentage of the last --hit-rate-window
+    /// attempts found a recipe; 0 disables the check
+    #[arg(long, value_name = "PERCENT", default_value_t = DEFAULT_MIN_HIT_RATE)]
+    pub min_hit_rate: f64,
+
+    /// Attempts the --min-hit-rate check looks back over
+    #[arg(long, value_name = "N", default_value_t = DEFAULT_HIT_RATE_WINDOW)]
+    pub hit_rate_window: usize,
+
+    /// Netscape/Mozilla cookies.txt file with a login session for the site
+    #[arg(long, visible_alias = "cookie-file", value_name = "PATH")]
+    pub cookies: Option<PathBuf>,
+
+    /// Write the cookies back to the --cookies file when the run ends,
+    /// including any the site set or refreshed along the way
+    #[arg(long, requires = "cookies")]
+    pub save_cookies: bool,
+
+    /// Log in to the site as this user before the run, and again whenever
+    /// the session runs out [default: BEERSCAPE_USERNAME from the
+    /// environment]
+    #[arg(long, value_name = "NAME")]
+    pub username: Option<String>,
+
+    /// Password for --username. Visible to other users of the machine
+    /// here, so better given as BEERSCAPE_PASSWORD in the environment
+    #[arg(long, value_name = "PASSWORD")]
+    pub password: Option<String>,
+
+    /// The site's login form the credentials are posted to: a URL, or a
+    /// path under --base-url
+    #[arg(long, value_name = "URL", default_value = DEFAULT_LOGIN_PATH)]
+    pub login_url: String,
+
+    /// Name of the login form's username field
+    #[arg(long, value_name = "NAME", default_value = DEFAULT_USERNAME_FIELD)]
+    pub username_field: String,
+
+    /// Name of the login form's password field
+    #[arg(long, value_name = "NAME", default_value = DEFAULT_PASSWORD_FIELD)]
+    pub password_field: String,
+
+    /// Site to download from; recipes are fetched from URL/download.php?id=N.
+    /// Repeat for mirrors of the site, which recipe downloads fail over to
+    /// in the order given
+    #[arg(long, value_name = "URL", default_value = DEFAULT_BASE_URL)]
+    pub base_url: Vec<String>,
+
+    /// Answer recipe requests with files picked at random from this
+    /// directory instead of the site, 10% of them with a 404 and 5% with a
+    /// timeout, to run the whole pipeline offline
+    #[arg(
+        long,
+        value_name = "FIXTURE_DIR",
+        conflicts_with_all = [
+            "dry_run",
+            "stratified",
+            "filter_style",
+            "respect_robots_txt",
+            "proxy",
+            "proxy_list",
+            "username",
+            "fetch_mode",
+        ]
+    )]
+    pub test_mode: Option<PathBuf>,
+
+    /// How long each --test-mode request takes to answer
+    #[arg(long, value_name = "MS", default_value_t = 50, requires = "test_mode")]
+    pub mock_latency_ms: u64,
+
+    /// Lowest recipe ID to pick at random
+    #[arg(long, value_name = "ID", default_value_t = MIN_RECIPE_ID)]
+    pub min_id: u32,
+
+    /// Highest recipe ID to pick at random
+    #[arg(long, value_name = "ID", default_value_t = MAX_RECIPE_ID)]
+    pub max_id: u32,
+
+    /// Probe --sample random IDs between --min-id and --max-id and report
+    /// the hit rate, without writing any recipes
+    #[arg(long, conflicts_with_all = ["id_file", "ids", "id_range", "filter_style"])]
+    pub dry_run: bool,
+
+    /// IDs to probe in a dry run, or recipes to download with --stratified
+    #[arg(long, value_name = "N", default_value_t = 1000, requires = "sampling")]
+    pub sample: usize,
+
+    /// Download --sample recipes spread across styles the way the site's
+    /// are, so common styles don't crowd out the rest. A first pass parses
+    /// recipes at random, without keeping them, to learn the style mix
+    #[arg(long, conflicts_with_all = ["dry_run", "id_file", "ids", "id_range", "filter_style"])]
+    pub stratified: bool,
+
+    /// Recipes the first pass of --stratified parses
+    #[arg(long, value_name = "M", default_value_t = DEFAULT_SAMPLE_PROBE, requires = "stratified")]
+    pub sample_probe: usize,
+
+    /// Let each style take up to this many times its share of --stratified
+    #[arg(long, value_name = "FACTOR", default_value_t = DEFAULT_OVERSAMPLE, requires = "stratified")]
+    pub oversample: f64,
+
+    /// How random IDs are picked: evenly over the range, or favouring the
+    /// parts of it that have been turning up recipes
+    #[arg(long, value_enum, default_value_t = Strategy::Uniform)]
+    pub strategy: Strategy,
+
+    /// Share of adaptive picks made from a part of the range chosen
+    /// evenly, so sparse parts are still probed, and of --since-id picks
+    /// made evenly over the whole range
+    #[arg(
+        long,
+        visible_alias = "exploration-rate",
+        value_name = "FRACTION",
+        default_value_t = DEFAULT_EXPLORATION
+    )]
+    pub exploration: f64,
+
+    /// Favour random IDs from N up, where the newest recipes are: picks
+    /// follow a triangular distribution rising from N to --max-id, apart
+    /// from the --exploration-rate share made over the whole range
+    #[arg(long, value_name = "N", conflicts_with_all = ["strategy", "compare_strategies"])]
+    pub since_id: Option<u32>,
+
+    /// Split a dry run's sample between the two strategies and report the
+    /// hit rate of each
+    #[arg(long, requires = "dry_run")]
+    pub compare_strategies: bool,
+
+    /// Download the recipe IDs listed in this file, one per line, instead
+    /// of random ones. Blank lines and lines starting with # are ignored
+    #[arg(long, visible_alias = "ids-file", value_name = "PATH")]
+    pub id_file: Option<PathBuf>,
+
+    /// Download these recipe IDs instead of random ones; may be combined
+    /// with --id-file and --id-range
+    #[arg(long, value_name = "ID,...", value_delimiter = ',')]
+    pub ids: Vec<u32>,
+
+    /// Download every recipe ID from A to B inclusive instead of random
+    /// ones; may be repeated, and combined with --id-file
+    #[arg(long, value_name = "A-B")]
+    pub id_range: Vec<IdRange>,
+
+    /// Carry on a sequential walk from ID N, taking the IDs below it as
+    /// already tried. In --mode sequential this moves the range's saved
+    /// position
+    #[arg(long, value_name = "N")]
+    pub start_at_id: Option<u32>,
+
+    /// Download the recipes the site's style search lists under this style
+    /// or category, instead of random ones; may be repeated
+    #[arg(long, value_name = "STYLE")]
+    pub filter_style: Vec<String>,
+
+    /// Pages of each --filter-style search to read
+    #[arg(long, value_name = "N", default_value_t = DEFAULT_MAX_PAGES)]
+    pub filter_max_pages: u32,
+
+    /// Once the --filter-style recipes are done, carry on with random IDs up
+    /// to the usual target instead of stopping
+    #[arg(long, requires = "filter_style")]
+    pub fallback_random: bool,
+
+    /// Download listed IDs even if they're already in the collection, and
+    /// go ahead even where robots.txt disallows it with
+    /// --respect-robots-txt
+    #[arg(long)]
+    pub force: bool,
+
+    /// Read the site's robots.txt first: stop if it disallows downloads,
+    /// and take its Crawl-delay as --rate-limit unless one is given
+    #[arg(long)]
+    pub respect_robots_txt: bool,
+
+    /// Start straight away, without first fetching one recipe to check
+    /// the site answers
+    #[arg(long)]
+    pub skip_healthcheck: bool,
+
+    /// Recipe ID known to exist, fetched before the run starts [default:
+    /// the lowest ID in the index, or the site root if it's empty]
+    #[arg(long, value_name = "ID")]
+    pub healthcheck_id: Option<u32>,
+
+    /// Leave at least this many seconds between requests, sending them
+    /// one at a time instead of in concurrent batches
+    #[arg(long, value_name = "SECS")]
+    pub rate_limit: Option<f64>,
+
+    /// Send at most this many requests a second, counting retries and
+    /// prechecks, however many are in flight. Replaces the short pause
+    /// between batches
+    #[arg(long, value_name = "N")]
+    pub max_rps: Option<f64>,
+
+    /// Cap the download rate of all transfers together, e.g. 2MB/s or
+    /// 500KiB/s
+    #[arg(long, value_name = "RATE")]
+    pub max_bandwidth: Option<ByteSize>,
+
+    /// Bytes that may arrive at once before --max-bandwidth holds transfers
+    /// back [default: one second's worth]
+    #[arg(long, value_name = "SIZE", requires = "max_bandwidth")]
+    pub bandwidth_burst: Option<ByteSize>,
+
+    /// Save each recipe's parsed metadata to a <stem>.json file beside it
+    #[arg(long)]
+    pub sidecar: bool,
+
+    /// Trust the certificates in this PEM file as well as the system's,
+    /// e.g. for a private server with a self-signed certificate
+    #[arg(long, value_name = "PATH")]
+    pub tls_ca_bundle: Option<PathBuf>,
+
+    /// Verify TLS certificates (the default)
+    #[arg(long, overrides_with = "no_verify_ssl")]
+    pub verify_ssl: bool,
+
+    /// Accept any TLS certificate, even invalid or forged ones. Insecure;
+    /// prefer --tls-ca-bundle
+    #[arg(long, overrides_with = "verify_ssl", conflicts_with = "tls_ca_bundle")]
+    pub no_verify_ssl: bool,
+
+    /// Discard downloads scoring below this completeness (0 to 1), counting
+    /// them as failures so they aren't tried again
+    #[arg(long, value_name = "SCORE")]
+    pub skip_incomplete: Option<f64>,
+
+    /// Give up connecting to the site after this many milliseconds
+    #[arg(long, value_name = "MS", default_value_t = DEFAULT_CONNECT_TIMEOUT_MS, value_parser = clap::value_parser!(u64).range(1..))]
+    pub connect_timeout_ms: u64,
+
+    /// Give up on a download that has sent nothing for this many
+    /// milliseconds
+    #[arg(long, value_name = "MS", default_value_t = DEFAULT_READ_TIMEOUT_MS, value_parser = clap::value_parser!(u64).range(1..))]
+    pub read_timeout_ms: u64,
+
+    /// Give up on a request that hasn't finished in this many
+    /// milliseconds, connecting included
+    #[arg(long, value_name = "MS", default_value_t = DEFAULT_REQUEST_TIMEOUT_MS, value_parser = clap::value_parser!(u64).range(1..))]
+    pub request_timeout_ms: u64,
+
+    /// --connect-timeout-ms in seconds
+    #[arg(long, value_name = "SECS", conflicts_with = "connect_timeout_ms")]
+    pub connect_timeout: Option<f64>,
+
+    /// --read-timeout-ms in seconds
+    #[arg(long, value_name = "SECS", conflicts_with = "read_timeout_ms")]
+    pub read_timeout: Option<f64>,
+
+    /// --request-timeout-ms in seconds
+    #[arg(long, value_name = "SECS", conflicts_with = "request_timeout_ms")]
+    pub request_timeout: Option<f64>,
+
+    /// Let a recipe download run past --request-timeout-ms for as long as
+    /// it keeps arriving; the request timeout then only covers waiting for
+    /// the response to start
+    #[arg(long)]
+    pub no_total_timeout_when_streaming: bool,
+
+    /// Give up on a request after following this many redirects
+    #[arg(long, value_name = "N", default_value_t = DEFAULT_MAX_REDIRECTS)]
+    pub max_redirects: usize,
+
+    /// Refuse redirects to another host, which fail as "redirect". Without
+    /// this they're followed, leaving the --header values behind
+    #[arg(long)]
+    pub same_host_redirects_only: bool,
+
+    /// Don't ask for recipes gzip- or brotli-compressed in transit, for
+    /// servers whose compressed responses are broken. How they're stored
+    /// is --output-compression's business
+    #[arg(long)]
+    pub no_compression: bool,
+
+    /// Make outbound connections from this local address, e.g. to pick an
+    /// interface on a multi-homed host
+    #[arg(long, value_name = "ADDR")]
+    pub bind_address: Option<IpAddr>,
+
+    /// Send every request through this proxy: http://HOST:PORT or
+    /// socks5://HOST:PORT, with USER:PASSWORD@ if it needs a login
+    /// [default: HTTPS_PROXY or ALL_PROXY from the environment]
+    #[arg(long, value_name = "URL")]
+    pub proxy: Option<String>,
+
+    /// Login for the proxy, overriding any in its URL
+    #[arg(long, value_name = "USER[:PASSWORD]")]
+    pub proxy_user: Option<String>,
+
+    /// Spread requests over the proxies in this file, one URL per line, as
+    /// --proxy takes them. Ones that keep failing are set aside for a while.
+    /// On Unix, a SIGHUP reads the file again
+    #[arg(long, value_name = "PATH", conflicts_with = "proxy")]
+    pub proxy_list: Option<PathBuf>,
+
+    /// How requests are spread over --proxy-list
+    #[arg(long, value_enum, value_name = "ORDER", default_value_t = ProxyRotation::RoundRobin)]
+    pub proxy_rotation: ProxyRotation,
+
+    /// Requests in flight through each --proxy-list proxy at once
+    /// [default: no limit of its own]
+    #[arg(long, value_name = "N", requires = "proxy_list", value_parser = clap::value_parser!(u16).range(1..))]
+    pub per_proxy_concurrency: Option<u16>,
+
+    /// Send a header with every request, e.g. "X-Api-Key: abc123"; can be
+    /// given more than once. One named like a built-in header, such as
+    /// User-Agent, replaces it
+    #[arg(long, value_name = "NAME:VALUE")]
+    pub header: Vec<RequestHeader>,
+
+    /// Send this User-Agent instead of the built-in one
+    #[arg(long, value_name = "STRING", conflicts_with_all = ["user_agents", "ua_strategy"])]
+    pub user_agent: Option<String>,
+
+    /// Rotate through the User-Agents in this file, one per line, rather
+    /// than the built-in pool
+    #[arg(long, value_name = "PATH")]
+    pub user_agents: Option<PathBuf>,
+
+    /// Rotate User-Agents: keep to one picked at random for the run, or pick
+    /// one for each request [default: per-run with --user-agents, otherwise
+    /// the built-in User-Agent throughout]
+    #[arg(long, value_enum, value_name = "STRATEGY")]
+    pub ua_strategy: Option<UaStrategy>,
+
+    /// Connect over IPv4 only, IPv6 only, or whichever the site resolves to
+    /// [default: both, or the version of --bind-address]
+    #[arg(long, value_enum)]
+    pub ip_version: Option<IpVersion>,
+
+    /// Check each ID with a cheaper request before downloading it, to save
+    /// bandwidth on IDs with no recipe
+    #[arg(long, value_enum, value_name = "METHOD")]
+    pub precheck: Option<Precheck>,
+
+    /// Fetch recipes one ID per request, or several at a time through the
+    /// site's bulk export
+    #[arg(long, value_enum, value_name = "MODE", default_value_t = FetchMode::Single)]
+    pub fetch_mode: FetchMode,
+
+    /// IDs asked for in each bulk export request
+    #[arg(long, value_name = "N", default_value_t = DEFAULT_BULK_SIZE, value_parser = clap::value_parser!(u16).range(1..))]
+    pub bulk_size: u16,
+
+    /// Bulk export URL, where {base_url} is --base-url and {ids} the IDs
+    /// asked for, separated by commas
+    #[arg(long, value_name = "TEMPLATE", default_value = DEFAULT_BULK_URL)]
+    pub bulk_url: String,
+
+    /// How files are arranged in the recipes directory. Switching an
+    /// existing collection takes `beerscape migrate-layout`
+    /// [default: the collection's current layout, or flat]
+    #[arg(long, value_enum)]
+    pub storage_layout: Option<StorageLayout>,
+
+    /// Compress recipes as they're saved. Files stored any way are read
+    /// back alike; `beerscape compress`, `decompress` and `recompress`
+    /// convert the ones already saved
+    #[arg(long, value_enum, value_name = "FORMAT", default_value_t = Compression::None)]
+    pub output_compression: Compression,
+
+    /// Level for --output-compression: for zstd 1 (fastest) to 22
+    /// (smallest), for gzip 1 to 9 [default: the format's own]
+    #[arg(long, value_name = "LEVEL", value_parser = clap::value_parser!(i32).range(1..=22))]
+    pub compression_level: Option<i32>,
+
+    /// How to report progress: a bar for people, or JSON lines on stdout
+    /// for a supervising program
+    #[arg(long, value_enum, default_value_t = ProgressMode::Bar)]
+    pub progress: ProgressMode,
+
+    /// Don't draw the progress bar; print a status line every
+    /// --status-interval seconds instead. This is automatic when stdout
+    /// isn't a terminal
+    #[arg(long, conflicts_with = "progress")]
+    pub no_progress: bool,
+
+    /// Look of the progress bar: compact (one short line), verbose (the
+    /// ETA, rates and counts over several lines), json (a JSON status
+    /// object on stdout every second instead of a bar), none (just the
+    /// final summary), or an indicatif template of your own, such as
+    /// "{bar:40} {pos}/{len} {msg}"
+    #[arg(long, value_name = "STYLE", conflicts_with_all = ["progress", "no_progress"])]
+    pub progress_style: Option<BarStyle>,
+
+    /// Give each recipe file announced as bigger than this a bar of its
+    /// own, under the progress bar, while it downloads
+    #[arg(long, value_name = "SIZE", default_value = "1MiB")]
+    pub file_bar_threshold: ByteSize,
+
+    /// Seconds between status lines when there's no progress bar
+    #[arg(long, value_name = "SECS", default_value_t = 60)]
+    pub status_interval: u64,
+
+    /// Show byte counts and rates as plain numbers of bytes instead of
+    /// KiB, MiB or GiB
+    #[arg(long = "bytes")]
+    pub raw_bytes: bool,
+
+    /// Serve Prometheus metrics for the run at http://ADDR/metrics
+    #[arg(long, value_name = "ADDR")]
+    pub metrics_addr: Option<SocketAddr>,
+
+    /// Serve the metrics on this port on all interfaces; short for
+    /// --metrics-addr 0.0.0.0:PORT
+    #[arg(long, value_name = "PORT", conflicts_with = "metrics_addr")]
+    pub metrics_port: Option<u16>,
+
+    /// Write a JSON report of the run to .beerscape/reports/
+    #[arg(long)]
+    pub report: bool,
+
+    /// Write the run report to this file instead
+    #[arg(long, value_name = "PATH")]
+    pub report_file: Option<PathBuf>,
+
+    /// Also log every attempt, download and parsed recipe to this SQLite
+    /// database, which `beerscape db` searches
+    #[arg(long, value_name = "PATH")]
+    pub db: Option<PathBuf>,
+
+    /// Seconds between heartbeat events with --progress json; 0 disables
+    /// them
+    #[arg(long, value_name = "SECS", default_value_t = 30)]
+    pub heartbeat: u64,
+
+    /// POST a JSON summary of the run to this URL when it ends
+    #[arg(long, value_name = "URL")]
+    pub notify_webhook: Option<String>,
+
+    /// Only send the --notify-webhook summary when some downloads failed
+    #[arg(long, requires = "notify_webhook")]
+    pub notify_on_error_only: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Mode {
+    /// Pick at random until the recipe target is reached
+    Random,
+    /// Walk from --min-id to --max-id in order, carrying on where the last
+    /// sequential run over the same range stopped
+    Sequential,
+    /// Walk up from the highest ID downloaded so far until the IDs run out
+    Newest,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Strategy {
+    Uniform,
+    Adaptive,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum IpVersion {
+    #[value(name = "4")]
+    V4,
+    #[value(name = "6")]
+    V6,
+    Both,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum UaStrategy {
+    /// One User-Agent for the whole run
+    PerRun,
+    /// A User-Agent picked for each recipe request
+    PerRequest,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ProxyRotation {
+    /// Each proxy in turn
+    RoundRobin,
+    /// A proxy picked at random for each request
+    Random,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum FetchMode {
+    /// One download.php request per ID
+    Single,
+    /// --bulk-size IDs per request to the bulk export, falling back to
+    /// single requests while it fails
+    Bulk,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Precheck {
+    /// A HEAD request, falling back to plain GETs if the server doesn't
+    /// answer HEAD properly
+    Head,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageLayout {
+    /// Every file directly in recipes/
+    #[default]
+    Flat,
+    /// recipes/<first two hex digits>/<SHA-256 of the file>.bsmx
+    Cas,
+    /// recipes/<style>/<file name>
+    Style,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Compression {
+    /// Plain .bsmx files
+    #[default]
+    None,
+    /// .bsmx.gz files
+    Gzip,
+    /// .bsmx.zst files
+    Zstd,
+}
+
+/// What `archive` writes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ArchiveFormat {
+    Zip,
+    #[value(name = "tar.gz")]
+    TarGz,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ProgressMode {
+    Bar,
+    Json,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Pack the collection into a zip or tar.gz archive
+    Archive(ArchiveArgs),
+
+    /// Take a snapshot of the collection, sharing unchanged files with the
+    /// last one, and delete old snapshots
+    Backup(BackupArgs),
+
+    /// Measure parser throughput on the downloaded recipes
+    Benchmark(BenchmarkArgs),
+
+    /// Browse the indexed collection in a terminal UI
+    Browse,
+
+    /// Group recipes by their vital statistics and write clusters.json
+    Cluster(ClusterArgs),
+
+    /// Work with the --db activity database
+    Db {
+        #[command(subcommand)]
+        db: DbCommand,
+    },
+
+    /// Score how similar two recipes are and show their ingredient
+    /// differences, or list how two recipes directories differ
+    Compare(CompareArgs),
+
+    /// Stay running, topping the collection up every --interval with the
+    /// newest recipes and a bounded random crawl toward --target
+    Daemon(DaemonArgs),
+
+    /// Find duplicate recipes and write duplicates_report.json
+    Deduplicate(DeduplicateArgs),
+
+    /// Collect recipe IDs from the site's search pages, for --ids-file
+    Discover(DiscoverArgs),
+
+    /// Write every recipe's parsed contents as JSON
+    Export(ExportArgs),
+
+    /// Copy recipes picked at random from the collection into a fixture
+    /// directory for `download --test-mode`
+    Fixtures(FixturesArgs),
+
+    /// Reformat recipes with consistent indentation, UTF-8 and LF endings
+    Fmt(FmtArgs),
+
+    /// Fetch a few known recipes to check the site is up, with the options
+    /// given before the subcommand; exits 1 if some fail and 2 if all do
+    HealthCheck(HealthCheckArgs),
+
+    /// Total up the hops used across recipes, and what to buy
+    HopInventory(HopInventoryArgs),
+
+    /// Rebuild the recipe index from the files on disk
+    Index,
+
+    /// List indexed recipes
+    List(ListArgs),
+
+    /// Copy the recipes in another recipes directory into this one,
+    /// skipping duplicates
+    Merge(MergeArgs),
+
+    /// Upgrade state files written by older versions of beerscape
+    Migrate,
+
+    /// Move the collection's files into another storage layout
+    MigrateLayout(MigrateLayoutArgs),
+
+    /// Rewrite recipes as UTF-8, fixing Latin-1 and Windows-1252 files
+    Normalize(NormalizeArgs),
+
+    /// Print recipes picked at random from the collection, for inspiration
+    RandomRecipe(RandomRecipeArgs),
+
+    /// Store the collection's files with another compression
+    Recompress(RecompressArgs),
+
+    /// Store the collection's files compressed with zstd
+    Compress(CompressArgs),
+
+    /// Store the collection's files uncompressed
+    Decompress,
+
+    /// Delete low-quality recipes
+    Prune(PruneArgs),
+
+    /// Upload the collection to S3-compatible storage
+    Push(RemoteArgs),
+
+    /// Download a collection pushed to S3-compatible storage
+    Pull(RemoteArgs),
+
+    /// Reports over the downloaded collection
+    Report {
+        #[command(subcommand)]
+        report: ReportCommand,
+    },
+
+    /// List past download runs and how their success rate has changed
+    Reports(ReportsArgs),
+
+    /// Serve the indexed collection over HTTP, read-only
+    Serve(ServeArgs),
+
+    /// Split multi-recipe exports into one file per recipe
+    Split(SplitArgs),
+
+    /// Copy the recipes one recipes directory is missing from another,
+    /// reporting files that differ under the same name
+    Sync(SyncArgs),
+
+    /// Tag recipes with your own labels, kept in recipes/tags.json
+    Tag {
+        #[command(subcommand)]
+        tag: TagCommand,
+    },
+
+    /// Rank the collection by seniority, completeness or ingredient count
+    TopRecipes(TopRecipesArgs),
+}
+
+#[derive(Debug, Subcommand)]
+pub enum DbCommand {
+    /// Load the recipes on disk and the failures in past run reports
+    Import,
+
+    /// Find recipes in the database
+    Search(DbSearchArgs),
+}
+
+#[derive(Debug, Subcommand)]
+pub enum TagCommand {
+    /// Give a recipe file a tag
+    Add(TagArgs),
+
+    /// Take a tag off a recipe file
+    Remove(TagArgs),
+
+    /// Show a recipe file's tags
+    List(TagListArgs),
+
+    /// List the recipe files with a tag
+    Search(TagSearchArgs),
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ReportCommand {
+    /// Brewers by number of recipes, with their average vital statistics
+    Authors,
+
+    /// Check recipes against the BJCP 2021 style ranges
+    StyleFit(StyleFitArgs),
+
+    /// Yeast strains by number of recipes using them
+    Yeast(YeastReportArgs),
+
+    /// How complete the recipes are, as a distribution of scores
+    Completeness,
+
+    /// How many recipes have notes or tasting notes, and how long they run
+    Notes,
+
+    /// Recipes by the BeerSmith version that wrote them
+    Versions,
+
+    /// How dark the recipes are, as a histogram of SRM colour
+    Color,
+}
+
+/// How a report is printed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ReportFormat {
+    Table,
+    Json,
+    Csv,
+}
+
+#[derive(Debug, Args)]
+pub struct ArchiveArgs {
+    /// [default: tar.gz if --output ends in .tar.gz or .tgz, otherwise zip]
+    #[arg(long, value_enum)]
+    pub format: Option<ArchiveFormat>,
+
+    /// Where to write the archive
+    #[arg(short, long, value_name = "PATH")]
+    pub output: PathBuf,
+
+    /// Put every recipe in one folder, instead of the storage layout's
+    /// subfolders. Leaves out the index, which names files by where the
+    /// layout keeps them
+    #[arg(long)]
+    pub flat: bool,
+
+    /// Only files changed since the last whole-collection archive started
+    #[arg(long)]
+    pub incremental: bool,
+
+    #[command(flatten)]
+    pub filter: FilterArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct BackupArgs {
+    /// Folder to keep the snapshots in
+    #[arg(long, value_name = "PATH")]
+    pub dest: PathBuf,
+
+    /// Snapshots to keep, newest first
+    #[arg(long, default_value_t = 7)]
+    pub keep: usize,
+}
+
+/// Searching the index, for commands that work on a chosen part of the
+/// collection.
+#[derive(Debug, Args)]
+pub struct FilterArgs {
+    /// Only indexed recipes with this in the name, style or brewer,
+    /// ignoring case
+    #[arg(long)]
+    pub query: Option<String>,
+
+    /// Only indexed recipes whose style name has this in it, ignoring case
+    #[arg(long)]
+    pub style: Option<String>,
+
+    /// Only indexed recipes by this brewer, ignoring case and spacing
+    #[arg(long)]
+    pub brewer: Option<String>,
+
+    #[arg(long, value_name = "PERCENT")]
+    pub min_abv: Option<f64>,
+
+    #[arg(long, value_name = "PERCENT")]
+    pub max_abv: Option<f64>,
+
+    /// Only indexed recipes with this tag, or one of these comma-separated
+    /// tags. Repeat to require several
+    #[arg(long, value_name = "TAG[,TAG...]")]
+    pub tag: Vec<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct BenchmarkArgs {
+    /// Number of passes over the collection [default: 100, or as many as
+    /// fit in a few seconds for small collections]
+    #[arg(long)]
+    pub iterations: Option<usize>,
+
+    /// Only benchmark files whose name matches this glob
+    #[arg(long, value_name = "GLOB")]
+    pub filter: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct ClusterArgs {
+    /// Number of clusters
+    #[arg(long, default_value_t = 20)]
+    pub k: usize,
+
+    /// Seed for the random starting points, to reproduce a run [default:
+    /// random, printed at the end]
+    #[arg(long)]
+    pub seed: Option<u64>,
+}
+
+#[derive(Debug, Args)]
+pub struct CompareArgs {
+    /// A recipe file, or a recipes directory
+    pub first: PathBuf,
+    pub second: PathBuf,
+
+    /// Comparing directories, also write how each file differs to this CSV
+    #[arg(long, value_name = "FILE")]
+    pub csv: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct DbSearchArgs {
+    /// Substring of the recipe name, ignoring case
+    #[arg(long)]
+    pub name: Option<String>,
+
+    /// Substring of the style name, ignoring case
+    #[arg(long)]
+    pub style: Option<String>,
+
+    /// Substring of the brewer, ignoring case
+    #[arg(long)]
+    pub brewer: Option<String>,
+
+    #[arg(long, value_name = "PERCENT")]
+    pub min_abv: Option<f64>,
+
+    #[arg(long, value_name = "PERCENT")]
+    pub max_abv: Option<f64>,
+
+    #[arg(long, value_name = "IBU")]
+    pub min_ibu: Option<f64>,
+
+    #[arg(long, value_name = "IBU")]
+    pub max_ibu: Option<f64>,
+
+    /// Substring of the recipe's notes or tasting notes, ignoring case
+    #[arg(long, value_name = "TEXT")]
+    pub notes_contains: Option<String>,
+
+    /// Only recipes with this tag, or one of these comma-separated tags.
+    /// Repeat to require several
+    #[arg(long, value_name = "TAG[,TAG...]")]
+    pub tag: Vec<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct DaemonArgs {
+    /// Time between cycles, e.g. 6h or 90m
+    #[arg(long, value_name = "DURATION", default_value = "6h")]
+    pub interval: RunDuration,
+
+    /// Recipes to have in the collection; once there, cycles only fetch
+    /// the newest [default: --target]
+    #[arg(long, value_name = "N")]
+    pub target: Option<usize>,
+
+    /// Attempts each cycle's random crawl toward --target may make
+    #[arg(long, value_name = "N", default_value_t = DEFAULT_CYCLE_ATTEMPTS)]
+    pub cycle_attempts: usize,
+
+    /// Wake up to this fraction of the interval early or late, at random,
+    /// so cycles don't land on the site at the same times every day
+    #[arg(long, value_name = "FRACTION", default_value_t = DEFAULT_JITTER)]
+    pub jitter: f64,
+
+    /// Longest the interval grows to, doubling after each cycle that finds
+    /// the site unhealthy
+    #[arg(long, value_name = "DURATION", default_value = "24h")]
+    pub max_interval: RunDuration,
+
+    /// Exit after this many cycles [default: run until SIGTERM]
+    #[arg(long, value_name = "N")]
+    pub max_cycles: Option<usize>,
+
+    /// Print when the last cycle ran and what it did, then exit
+    #[arg(long, exclusive = true)]
+    pub status: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct DeduplicateArgs {
+    /// Compare recipe names instead of file contents
+    #[arg(long)]
+    pub fuzzy: bool,
+
+    /// Match recipes with the same ingredients in the same proportions,
+    /// whatever their batch size
+    #[arg(long, conflicts_with = "fuzzy")]
+    pub by_fingerprint: bool,
+
+    /// Minimum name similarity (0-1) for a fuzzy match
+    #[arg(long, default_value_t = DEFAULT_SIMILARITY, requires = "fuzzy")]
+    pub similarity_threshold: f64,
+
+    /// Delete the second file of every confirmed duplicate pair
+    #[arg(long)]
+    pub auto_remove: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct HealthCheckArgs {
+    /// Recipe IDs known to exist [default: 1000,10000,100000]
+    #[arg(long, value_name = "ID,...", value_delimiter = ',')]
+    pub test_ids: Vec<u32>,
+}
+
+#[derive(Debug, Args)]
+pub struct DiscoverArgs {
+    /// Search terms [default: list every recipe]
+    #[arg(long)]
+    pub query: Option<String>,
+
+    /// Stop after this many pages
+    #[arg(long, value_name = "N", default_value_t = DEFAULT_MAX_PAGES)]
+    pub max_pages: u32,
+
+    /// File to write the IDs to, one per line with the recipe's name as a
+    /// comment
+    #[arg(long, value_name = "PATH", default_value = DISCOVERED_IDS_FILE)]
+    pub output: PathBuf,
+
+    /// Download the recipes found straight away, with the options given
+    /// before the subcommand
+    #[arg(long)]
+    pub download: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct ExportArgs {
+    /// json writes one array; ndjson writes a line per recipe as it's
+    /// read, for piping into jq or a message queue
+    #[arg(long, value_enum, default_value_t = ExportFormat::Json)]
+    pub output_format: ExportFormat,
+
+    /// Write to this file instead of stdout [default with --since or
+    /// --since-last-export: recipes_delta_<timestamp>.json]
+    #[arg(long, short, value_name = "PATH")]
+    pub output: Option<PathBuf>,
+
+    /// Only recipes whose file changed after this RFC 3339 time, e.g.
+    /// 2024-05-01T00:00:00Z
+    #[arg(long, value_name = "TIMESTAMP", value_parser = DateTime::parse_from_rfc3339)]
+    pub since: Option<DateTime<FixedOffset>>,
+
+    /// Only recipes whose file changed since the newest delta export in the
+    /// current directory was written
+    #[arg(long, conflicts_with = "since")]
+    pub since_last_export: bool,
+
+    /// Give quantities in liters, kilograms, grams and degrees Celsius, or
+    /// in gallons, pounds, ounces and degrees Fahrenheit, with the fields
+    /// renamed to match
+    #[arg(long, value_enum, default_value_t = Units::Metric)]
+    pub units: Units,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ExportFormat {
+    Json,
+    Ndjson,
+}
+
+#[derive(Debug, Args)]
+#[command(group(ArgGroup::new("target").required(true).args(["paths", "all"])))]
+pub struct FmtArgs {
+    /// Files to reformat
+    pub paths: Vec<PathBuf>,
+
+    /// Reformat every recipe in the collection
+    #[arg(long)]
+    pub all: bool,
+
+    /// Only list files that would change; exits non-zero if there are any
+    #[arg(long)]
+    pub check: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct HopInventoryArgs {
+    /// Only recipes whose path matches this glob, e.g. "recipes/*IPA*.bsmx"
+    /// [default: every recipe in the collection]
+    #[arg(long, value_name = "GLOB")]
+    pub recipes: Option<String>,
+
+    /// Brew each recipe this many times
+    #[arg(long, value_name = "N", default_value_t = 1)]
+    pub batch_count: u32,
+
+    /// Also write the inventory to hop_inventory.csv
+    #[arg(long)]
+    pub export_csv: bool,
+
+    /// CSV of hops on hand, with variety and amount_g or amount_oz
+    /// columns; prints a shopping list of the shortfall
+    #[arg(long, value_name = "PATH")]
+    pub merge: Option<PathBuf>,
+
+    /// Show weights in grams or ounces
+    #[arg(long, value_enum, default_value_t = Units::Metric)]
+    pub units: Units,
+}
+
+/// How a recipe's ABV is worked out from its gravities when the file
+/// doesn't record one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum AbvFormula {
+    /// (OG - FG) * 131.25
+    Standard,
+    /// The Daniels formula, closer for strong beers
+    Accurate,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Units {
+    Metric,
+    Imperial,
+}
+
+#[derive(Debug, Args)]
+pub struct ListArgs {
+    /// Only recipes by this brewer, ignoring case and spacing
+    #[arg(long)]
+    pub brewer: Option<String>,
+
+    /// Only recipes with this tag, or one of these comma-separated tags.
+    /// Repeat to require several
+    #[arg(long, value_name = "TAG[,TAG...]")]
+    pub tag: Vec<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct MergeArgs {
+    /// Recipes directory to copy from, e.g. another machine's
+    #[arg(long, value_name = "PATH")]
+    pub source: PathBuf,
+
+    /// Recipes directory to copy into
+    #[arg(long, value_name = "PATH", default_value = RECIPES_DIR)]
+    pub dest: PathBuf,
+
+    /// Settle every near-duplicate this way instead of asking
+    #[arg(long, value_enum)]
+    pub keep: Option<Keep>,
+
+    /// Only print what would be copied
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+/// Which copy of a near-duplicate a merge keeps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Keep {
+    /// The source's, replacing the destination's
+    Source,
+    /// The destination's, leaving it as it is
+    Dest,
+    /// Both, the source's under a name of its own if they share one
+    Both,
+}
+
+#[derive(Debug, Args)]
+pub struct MigrateLayoutArgs {
+    /// Layout to move the files into
+    #[arg(long, value_enum)]
+    pub to: StorageLayout,
+}
+
+#[derive(Debug, Args)]
+pub struct FixturesArgs {
+    /// How many recipes to copy
+    #[arg(long, default_value_t = 50)]
+    pub count: usize,
+
+    /// Directory to copy them into
+    #[arg(long, value_name = "DIR", default_value = FIXTURES_DIR)]
+    pub output: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct RandomRecipeArgs {
+    /// How many different recipes to pick
+    #[arg(long, default_value_t = 1)]
+    pub count: usize,
+
+    /// Seed for the pick, so the same seed picks the same recipes from the
+    /// same collection [default: random]
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    /// Open each recipe picked in the application the system opens .bsmx
+    /// files with
+    #[arg(long)]
+    pub open: bool,
+
+    #[command(flatten)]
+    pub filter: FilterArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct RecompressArgs {
+    /// Compression to store the files with
+    #[arg(long, value_enum)]
+    pub to: Compression,
+
+    /// Level to compress at: for zstd 1 (fastest) to 22 (smallest), for
+    /// gzip 1 to 9 [default: the format's own]
+    #[arg(long, value_parser = clap::value_parser!(i32).range(1..=22))]
+    pub level: Option<i32>,
+}
+
+#[derive(Debug, Args)]
+pub struct SyncArgs {
+    /// Recipes directory to copy from
+    #[arg(value_name = "SOURCE")]
+    pub source: PathBuf,
+
+    /// Recipes directory to copy into, and whose index is updated
+    #[arg(value_name = "DEST")]
+    pub dest: PathBuf,
+
+    /// Also copy what the destination has and the source doesn't into the
+    /// source
+    #[arg(long)]
+    pub bidirectional: bool,
+
+    /// Only print what would be copied
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct RemoteArgs {
+    /// Bucket and prefix to sync with
+    #[arg(long, value_name = "s3://BUCKET/PREFIX")]
+    pub remote: String,
+
+    /// S3-compatible service to use instead of AWS, e.g.
+    /// https://s3.us-west-004.backblazeb2.com
+    #[arg(long, value_name = "URL")]
+    pub endpoint: Option<String>,
+
+    /// [default: AWS_REGION, AWS_DEFAULT_REGION, the profile's region or
+    /// us-east-1]
+    #[arg(long)]
+    pub region: Option<String>,
+
+    /// Profile in ~/.aws/credentials and ~/.aws/config [default:
+    /// AWS_PROFILE, or default]
+    #[arg(long)]
+    pub profile: Option<String>,
+
+    /// Access key, instead of the AWS environment variables or profile
+    #[arg(long, requires = "secret_access_key")]
+    pub access_key_id: Option<String>,
+
+    #[arg(long, requires = "access_key_id")]
+    pub secret_access_key: Option<String>,
+
+    /// Files transferred at once
+    #[arg(long, default_value_t = 4)]
+    pub jobs: usize,
+
+    /// Times to retry a request that times out or gets a 429 or server
+    /// error
+    #[arg(long, default_value_t = 3)]
+    pub retries: u32,
+
+    /// List what would be transferred, without transferring it
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct CompressArgs {
+    /// zstd level, from 1 (fastest) to 22 (smallest) [default: 3]
+    #[arg(long, value_parser = clap::value_parser!(i32).range(1..=22))]
+    pub level: Option<i32>,
+}
+
+#[derive(Debug, Args)]
+pub struct NormalizeArgs {
+    /// Files to normalize [default: every recipe in the collection]
+    pub paths: Vec<PathBuf>,
+
+    /// Only list the files that would be rewritten
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Args)]
+#[command(group(
+    ArgGroup::new("criteria")
+        .required(true)
+        .multiple(true)
+        .args(["no_ingredients", "no_style", "no_name", "invalid_gravity", "confirmed_duplicates"])
+))]
+pub struct PruneArgs {
+    /// Recipes with no fermentables, hops or yeast
+    #[arg(long)]
+    pub no_ingredients: bool,
+
+    /// Recipes without a style name
+    #[arg(long)]
+    pub no_style: bool,
+
+    /// Recipes without a name
+    #[arg(long)]
+    pub no_name: bool,
+
+    /// Recipes with an OG or FG that can't be right
+    #[arg(long)]
+    pub invalid_gravity: bool,
+
+    /// The later copy of each confirmed duplicate, exact or fuzzy
+    #[arg(long)]
+    pub confirmed_duplicates: bool,
+
+    /// Only list the files that would be pruned
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Move pruned files here instead of deleting them
+    #[arg(long, value_name = "PATH")]
+    pub move_to: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct ReportsArgs {
+    /// Run reports to read [default: every report in .beerscape/reports]
+    pub paths: Vec<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct ServeArgs {
+    #[arg(long, default_value_t = 8080)]
+    pub port: u16,
+
+    /// Address to listen on; use 0.0.0.0 to share on the local network
+    #[arg(long, default_value = "127.0.0.1")]
+    pub bind: IpAddr,
+}
+
+#[derive(Debug, Args)]
+pub struct SplitArgs {
+    /// Files to split [default: every recipe in the collection]
+    pub paths: Vec<PathBuf>,
+
+    /// Only list the files that would be split
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct TagArgs {
+    /// Recipe file, relative to the recipes directory
+    pub filename: PathBuf,
+
+    #[arg(value_parser = parse_tag)]
+    pub tag: String,
+}
+
+#[derive(Debug, Args)]
+pub struct TagListArgs {
+    /// Recipe file, relative to the recipes directory
+    pub filename: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct TagSearchArgs {
+    #[arg(value_parser = parse_tag)]
+    pub tag: String,
+}
+
+#[derive(Debug, Args)]
+pub struct StyleFitArgs {
+    /// Print every recipe with its out-of-range values
+    #[arg(long)]
+    pub details: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct TopRecipesArgs {
+    /// What to rank by
+    #[arg(long, value_enum, default_value_t = RankBy::Combined)]
+    pub rank_by: RankBy,
+
+    /// Number of recipes to show
+    #[arg(long, value_name = "N", default_value_t = 20)]
+    pub top: usize,
+
+    /// Leave out recipes scoring below this completeness (0 to 1)
+    #[arg(long, value_name = "SCORE", default_value_t = 0.0)]
+    pub min_completeness: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum RankBy {
+    /// Lowest site ID first, as the longest posted
+    Id,
+    /// Highest completeness score first
+    Completeness,
+    /// Most fermentables, hops and yeasts first
+    Ingredients,
+    /// The mean of the other three, each scaled to 0-1
+    Combined,
+}
+
+#[derive(Debug, Args)]
+pub struct YeastReportArgs {
+    /// Only strains from labs whose name contains this, ignoring case
+    #[arg(long, value_name = "PATTERN")]
+    pub lab: Option<String>,
+
+    /// How to print the report
+    #[arg(long, value_enum, default_value_t = ReportFormat::Table)]
+    pub format: ReportFormat,
+}
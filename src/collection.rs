@@ -0,0 +1,462 @@
+use crate::bsmx::{
+    parse_bsmx, parse_document, split_recipes, within_parse_timeout, write_document, ParseTimedOut,
+    Recipe,
+};
+use crate::cli::{Compression, StorageLayout};
+use crate::compression::{bsmx_stem, is_bsmx, read_bsmx, write_bsmx};
+use crate::fingerprint::fingerprint_hex;
+use crate::index::{Filter, Index, IndexEntry, INDEX_FILE};
+use crate::layout::uncompressed_name;
+use crate::state::{State, STATE_FILE};
+use crate::tags::TAGS_FILE;
+use crate::RECIPES_DIR;
+use glob::glob;
+use indicatif::ProgressBar;
+use rayon::iter::{IntoParallelRefIterator, ParallelBridge, ParallelIterator};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tracing::warn;
+
+/// Subfolder of the recipes directory that split multi-recipe files are
+/// moved to.
+pub const ARCHIVE_DIR: &str = "archive";
+/// Subfolder of the recipes directory that recipes taking longer than the
+/// parse timeout are moved to, for a closer look.
+pub const PARSE_FAILED_DIR: &str = "parse_failed";
+
+/// The parsed contents of the recipes directory.
+pub struct Collection {
+    pub recipes: Vec<(PathBuf, Recipe)>,
+    pub unreadable: usize,
+}
+
+/// Paths of every recipe file in the recipes directory, compressed or not,
+/// in name order.
+pub fn recipe_files() -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let dir = Path::new(RECIPES_DIR);
+    recipe_files_in(dir, State::load(dir)?.storage_layout)
+}
+
+/// Paths of the recipe files in `dir` where `layout` keeps them, in name
+/// order. Split files moved to an archive folder are left out.
+pub fn recipe_files_in(dir: &Path, layout: StorageLayout) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut paths: Vec<PathBuf> = glob(&format!("{}/{}", dir.display(), layout.pattern()))?
+        .flatten()
+        .filter(|path| is_bsmx(path) && !set_aside(path))
+        .collect();
+    paths.sort();
+    Ok(paths)
+}
+
+/// The collection's own files, kept in the recipes directory beside the
+/// recipes
+pub const COLLECTION_FILES: [&str; 3] = [INDEX_FILE, TAGS_FILE, STATE_FILE];
+
+/// Every recipe file in `dir` with its sidecar, then the collection's own
+/// files: everything a copy of the collection needs.
+pub fn collection_files(dir: &Path) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let layout = State::load(dir)?.storage_layout;
+    let mut paths = Vec::new();
+    for path in recipe_files_in(dir, layout)? {
+        let sidecar = sidecar_path(&path);
+        paths.push(path);
+        if sidecar.is_file() {
+            paths.push(sidecar);
+        }
+    }
+    paths.extend(
+        COLLECTION_FILES
+            .iter()
+            .map(|name| dir.join(name))
+            .filter(|path| path.is_file()),
+    );
+    Ok(paths)
+}
+
+/// A recipe file and the SHA-256 of its BSMX, whatever its compression.
+#[derive(Debug, Clone)]
+pub struct HashedFile {
+    pub path: PathBuf,
+    /// The name it was downloaded under, whatever the layout and
+    /// compression
+    pub name: String,
+    pub hash: [u8; 32],
+}
+
+/// Hash every recipe file in `dir` where `layout` keeps them, on all cores,
+/// in name order. Ticks `pb` once per file. A directory that doesn't exist
+/// yet is an empty collection.
+pub fn hash_files(
+    dir: &Path,
+    layout: StorageLayout,
+    pb: &ProgressBar,
+) -> Result<Vec<HashedFile>, Box<dyn Error>> {
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let paths = recipe_files_in(dir, layout)?;
+    pb.set_length(paths.len() as u64);
+    let files: io::Result<Vec<HashedFile>> = paths
+        .par_iter()
+        .map(|path| {
+            let contents = read_bsmx(path)
+                .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+            pb.inc(1);
+            Ok(HashedFile {
+                path: path.clone(),
+                name: uncompressed_name(path),
+                hash: Sha256::digest(&contents).into(),
+            })
+        })
+        .collect();
+    Ok(files?)
+}
+
+/// Stems of every recipe file in `dir`, compressed or not, or in its subfolders for the
+/// nested layouts, gathered on all cores so that tens of thousands of
+/// files scan quickly. Ticks `pb` once per directory entry.
+pub fn scan_stems(
+    dir: &Path,
+    layout: StorageLayout,
+    pb: &ProgressBar,
+) -> io::Result<HashSet<String>> {
+    let folders = match layout {
+        StorageLayout::Flat => vec![dir.to_path_buf()],
+        StorageLayout::Cas | StorageLayout::Style => {
+            let mut folders = Vec::new();
+            for entry in fs::read_dir(dir)? {
+                let path = entry?.path();
+                let name = path.file_name();
+                if path.is_dir()
+                    && name != Some(ARCHIVE_DIR.as_ref())
+                    && name != Some(PARSE_FAILED_DIR.as_ref())
+                {
+                    folders.push(path);
+                }
+            }
+            folders
+        }
+    };
+    let stems = Mutex::new(HashSet::new());
+    for folder in folders {
+        fs::read_dir(folder)?
+            .par_bridge()
+            .flatten()
+            .for_each(|entry| {
+                pb.inc(1);
+                let path = entry.path();
+                if let Some(stem) = bsmx_stem(&path) {
+                    stems.lock().unwrap().insert(stem.to_string());
+                }
+            });
+    }
+    Ok(stems.into_inner().unwrap())
+}
+
+// Whether a file is in the archive or parse_failed folder
+fn set_aside(path: &Path) -> bool {
+    path.parent()
+        .and_then(|parent| parent.file_name())
+        .is_some_and(|name| name == ARCHIVE_DIR || name == PARSE_FAILED_DIR)
+}
+
+/// Parse every recipe in the collection, on all cores, keeping name order.
+/// Files that fail to read or parse are counted rather than aborting the
+/// whole load.
+pub fn load_recipes() -> Result<Collection, Box<dyn Error>> {
+    let parsed: Vec<(PathBuf, Option<Recipe>)> = recipe_files()?
+        .par_iter()
+        .map(|path| {
+            (
+                path.clone(),
+                read_or_set_aside(Path::new(RECIPES_DIR), path),
+            )
+        })
+        .collect();
+    let mut collection = Collection {
+        recipes: Vec::with_capacity(parsed.len()),
+        unreadable: 0,
+    };
+    for (path, recipe) in parsed {
+        match recipe {
+            Some(recipe) => collection.recipes.push((path, recipe)),
+            None => collection.unreadable += 1,
+        }
+    }
+    Ok(collection)
+}
+
+/// Load one recipe, from its sidecar if it has an up-to-date one and by
+/// parsing the BSMX otherwise.
+pub fn read_recipe(path: &Path) -> Result<Recipe, Box<dyn Error>> {
+    if let Some(recipe) = read_sidecar(path) {
+        return Ok(recipe);
+    }
+    let contents = read_bsmx(path)?;
+    Ok(within_parse_timeout(move || parse_bsmx(&contents))??)
+}
+
+/// Load a recipe for a pass over the collection in `dir`, moving it to the
+/// parse_failed folder if parsing it takes too long, so that later passes
+/// don't wait on it again.
+pub fn read_or_set_aside(dir: &Path, path: &Path) -> Option<Recipe> {
+    match read_recipe(path) {
+        Ok(recipe) => Some(recipe),
+        Err(e) if e.is::<ParseTimedOut>() => {
+            let folder = dir.join(PARSE_FAILED_DIR);
+            let target = folder.join(file_name(path));
+            match fs::create_dir_all(&folder).and_then(|_| fs::rename(path, &target)) {
+                Ok(()) => {
+                    warn!(file = %path.display(), moved_to = %target.display(), "{}", e)
+                }
+                Err(moving) => {
+                    warn!(file = %path.display(), error = %moving, "{}; could not set it aside", e)
+                }
+            }
+            None
+        }
+        Err(_) => None,
+    }
+}
+
+/// The `<stem>.json` file holding a recipe's parsed metadata, shared by
+/// every compression of the recipe.
+pub fn sidecar_path(path: &Path) -> PathBuf {
+    match bsmx_stem(path) {
+        Some(stem) => path.with_file_name(format!("{}.json", stem)),
+        None => path.with_extension("json"),
+    }
+}
+
+/// How a recipe was downloaded, as far as its sidecar records it.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Provenance {
+    /// The User-Agent it was requested with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_agent: Option<String>,
+    /// Where it ended up being fetched from, if a redirect took it away
+    /// from the URL asked for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub final_url: Option<String>,
+}
+
+/// A sidecar's contents: the recipe, plus its ingredient fingerprint and
+/// how it was downloaded, which reading the sidecar back ignores.
+#[derive(Serialize)]
+struct Sidecar<'a> {
+    #[serde(flatten)]
+    recipe: &'a Recipe,
+    fingerprint: String,
+    #[serde(flatten)]
+    provenance: &'a Provenance,
+}
+
+pub fn write_sidecar(
+    path: &Path,
+    recipe: &Recipe,
+    provenance: &Provenance,
+) -> Result<(), Box<dyn Error>> {
+    let sidecar = Sidecar {
+        recipe,
+        fingerprint: fingerprint_hex(recipe),
+        provenance,
+    };
+    write_atomic(&sidecar_path(path), &serde_json::to_vec_pretty(&sidecar)?)?;
+    Ok(())
+}
+
+/// How a sidecar says its recipe was downloaded.
+pub fn sidecar_provenance(sidecar: &Path) -> Provenance {
+    fs::read(sidecar)
+        .ok()
+        .and_then(|json| serde_json::from_slice(&json).ok())
+        .unwrap_or_default()
+}
+
+// A sidecar older than its recipe is ignored, since the recipe may have
+// been edited since
+fn read_sidecar(path: &Path) -> Option<Recipe> {
+    let sidecar = sidecar_path(path);
+    let written = fs::metadata(&sidecar).and_then(|m| m.modified()).ok()?;
+    let modified = fs::metadata(path).and_then(|m| m.modified()).ok()?;
+    if written < modified {
+        return None;
+    }
+    serde_json::from_slice(&fs::read(&sidecar).ok()?).ok()
+}
+
+/// Replace a file's contents by writing beside it and renaming, so a crash
+/// can't leave a half-written recipe behind.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let tmp = tmp_path(path);
+    fs::write(&tmp, contents)?;
+    fs::rename(&tmp, path)
+}
+
+/// Scratch file next to `path` for writes that get renamed into place.
+pub fn tmp_path(path: &Path) -> PathBuf {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    PathBuf::from(tmp)
+}
+
+/// Index a freshly downloaded file, splitting it first if it holds several
+/// recipes.
+pub fn index_download(index: &Index, path: &Path, recipe_id: u32) -> Result<(), Box<dyn Error>> {
+    if !split_file(index, path, Some(recipe_id))?.is_empty() {
+        return Ok(());
+    }
+    let recipe = read_recipe(path)?;
+    let mut entry = IndexEntry::new(&index.name_of(path), &recipe).with_sizes(path);
+    entry.recipe_id = Some(recipe_id);
+    index.upsert(&entry)?;
+    Ok(())
+}
+
+/// Split a file holding several recipes into one file per recipe, named
+/// `<parent-id>-<n> <recipe name>.bsmx`, compressed like the original, index
+/// each part under the parent's ID and move the original to the archive
+/// folder.
+///
+/// Returns the new files, or nothing if `path` holds a single recipe. When
+/// `recipe_id` isn't given it comes from the index, or a numeric file name.
+/// If the original has a sidecar, it is archived too and each part gets
+/// its own.
+pub fn split_file(
+    index: &Index,
+    path: &Path,
+    recipe_id: Option<u32>,
+) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let documents = match split_recipes(&parse_document(&read_bsmx(path)?)?) {
+        Some(documents) => documents,
+        None => return Ok(Vec::new()),
+    };
+
+    let filename = index.name_of(path);
+    let stem = bsmx_stem(path).unwrap_or_default();
+    let recipe_id = match recipe_id {
+        Some(id) => Some(id),
+        None => index.recipe_id(&filename)?.or_else(|| stem.parse().ok()),
+    };
+    let parent = recipe_id.map_or_else(|| stem.to_string(), |id| id.to_string());
+    let extension = Compression::of(path).extension();
+
+    // Every part has to parse on its own before anything is written
+    let mut parts = Vec::new();
+    for (n, document) in documents.iter().enumerate() {
+        let contents = write_document(document);
+        let recipe = parse_bsmx(contents.as_bytes())?;
+        let name = file_name_part(&recipe.name);
+        let part_name = if name.is_empty() {
+            format!("{}-{}.bsmx{}", parent, n + 1, extension)
+        } else {
+            format!("{}-{} {}.bsmx{}", parent, n + 1, name, extension)
+        };
+        parts.push((part_name, contents, recipe));
+    }
+
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let sidecar = sidecar_path(path);
+    let has_sidecar = sidecar.exists();
+    // The parts were downloaded with whatever the original was
+    let provenance = if has_sidecar {
+        sidecar_provenance(&sidecar)
+    } else {
+        Provenance::default()
+    };
+    let mut written = Vec::new();
+    for (part_name, contents, recipe) in &parts {
+        let part_path = dir.join(part_name);
+        write_bsmx(&part_path, contents.as_bytes())?;
+        if has_sidecar {
+            write_sidecar(&part_path, recipe, &provenance)?;
+        }
+        written.push(part_path);
+    }
+
+    let archived = dir.join(ARCHIVE_DIR).join(file_name(path));
+    fs::create_dir_all(dir.join(ARCHIVE_DIR))?;
+    fs::rename(path, &archived)?;
+    if has_sidecar {
+        fs::rename(&sidecar, sidecar_path(&archived))?;
+    }
+
+    let tx = index.transaction()?;
+    for (n, (part_name, _, recipe)) in parts.iter().enumerate() {
+        let part_path = dir.join(part_name);
+        let mut entry = IndexEntry::new(&index.name_of(&part_path), recipe).with_sizes(&part_path);
+        entry.recipe_id = recipe_id;
+        entry.sub_id = Some(n as u32 + 1);
+        index.upsert(&entry)?;
+    }
+    index.remove(&filename)?;
+    tx.commit()?;
+
+    Ok(written)
+}
+
+/// The indexed recipes `filter` matches whose files are still there.
+pub fn indexed_files_matching(dir: &Path, filter: &Filter) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let index = Index::open(dir)?;
+    if index.count()? == 0 {
+        return Err("filtering needs the index, which is empty; run `beerscape index`".into());
+    }
+    let mut paths = vec![];
+    for entry in index.entries()? {
+        if !filter.matches(&entry) {
+            continue;
+        }
+        let path = dir.join(&entry.filename);
+        if path.is_file() {
+            paths.push(path);
+        } else {
+            warn!(
+                "{} is in the index but not on disk; run `beerscape index`",
+                entry.filename
+            );
+        }
+    }
+    Ok(paths)
+}
+
+/// `path` relative to `dir`, with `/` between the parts, as the index
+/// names files. A path outside `dir` is named by its file name alone.
+pub fn stored_name(dir: &Path, path: &Path) -> String {
+    match path.strip_prefix(dir) {
+        Ok(relative) => relative
+            .iter()
+            .map(|part| part.to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/"),
+        Err(_) => file_name(path),
+    }
+}
+
+pub fn file_name(path: &Path) -> String {
+    path.file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// A recipe name with the characters file systems reject replaced.
+fn file_name_part(name: &str) -> String {
+    let safe: String = name
+        .chars()
+        .map(|c| {
+            if c.is_control() || "/\\:*?\"<>|".contains(c) {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect();
+    let short: String = safe.trim().chars().take(100).collect();
+    short.trim_end().to_string()
+}
@@ -0,0 +1,108 @@
+use crate::bsmx::Recipe;
+
+const LB_PER_KG: f64 = 2.204_62;
+const L_PER_GAL: f64 = 3.785_41;
+
+/// Approximate sRGB colours of beer at 1 to 40 SRM, from the reference
+/// charts brewing software commonly draws colour swatches with. Beer
+/// darker than 40 SRM looks black either way.
+const SRM_RGB: [(u8, u8, u8); 40] = [
+    (0xFF, 0xE6, 0x99),
+    (0xFF, 0xD8, 0x78),
+    (0xFF, 0xCA, 0x5A),
+    (0xFF, 0xBF, 0x42),
+    (0xFB, 0xB1, 0x23),
+    (0xF8, 0xA6, 0x00),
+    (0xF3, 0x9C, 0x00),
+    (0xEA, 0x8F, 0x00),
+    (0xE5, 0x85, 0x00),
+    (0xDE, 0x7C, 0x00),
+    (0xD7, 0x72, 0x00),
+    (0xCF, 0x69, 0x00),
+    (0xCB, 0x62, 0x00),
+    (0xC3, 0x59, 0x00),
+    (0xBB, 0x51, 0x00),
+    (0xB5, 0x4C, 0x00),
+    (0xB0, 0x45, 0x00),
+    (0xA6, 0x3E, 0x00),
+    (0xA1, 0x37, 0x00),
+    (0x9B, 0x32, 0x00),
+    (0x95, 0x2D, 0x00),
+    (0x8E, 0x29, 0x00),
+    (0x88, 0x23, 0x00),
+    (0x82, 0x1E, 0x00),
+    (0x7B, 0x1A, 0x00),
+    (0x77, 0x19, 0x00),
+    (0x70, 0x14, 0x00),
+    (0x6A, 0x0E, 0x00),
+    (0x66, 0x0D, 0x00),
+    (0x5E, 0x0B, 0x00),
+    (0x5A, 0x0A, 0x02),
+    (0x60, 0x09, 0x03),
+    (0x52, 0x09, 0x07),
+    (0x4C, 0x05, 0x05),
+    (0x47, 0x06, 0x06),
+    (0x44, 0x06, 0x07),
+    (0x3F, 0x07, 0x08),
+    (0x3B, 0x06, 0x07),
+    (0x3A, 0x07, 0x0B),
+    (0x36, 0x08, 0x0A),
+];
+
+/// Estimate beer colour in SRM from the malt colour units of a grist,
+/// with Morey's equation. MCU is the sum over the fermentables of their
+/// colour in °L times their weight in pounds, over the batch in gallons.
+pub fn morey_srm(mcu: f64) -> f64 {
+    if mcu <= 0.0 {
+        return 0.0;
+    }
+    1.4922 * mcu.powf(0.6859)
+}
+
+/// The recipe's colour as the file recorded it, or if it didn't, as
+/// Morey's equation estimates it from the fermentables. Zero when there's
+/// neither to go on.
+pub fn recipe_srm(recipe: &Recipe) -> f64 {
+    if recipe.color_srm > 0.0 || recipe.batch_size_l <= 0.0 {
+        return recipe.color_srm;
+    }
+    let gallons = recipe.batch_size_l / L_PER_GAL;
+    let mcu = recipe
+        .fermentables
+        .iter()
+        .map(|f| f.color_srm * f.amount_kg * LB_PER_KG)
+        .sum::<f64>()
+        / gallons;
+    morey_srm(mcu)
+}
+
+/// The approximate colour of beer at `srm`, interpolated between the whole
+/// SRM values of the reference chart. Anything under 1 SRM is drawn as 1,
+/// anything over 40 as 40.
+pub fn srm_to_rgb(srm: f64) -> (u8, u8, u8) {
+    let srm = if srm.is_nan() {
+        1.0
+    } else {
+        srm.clamp(1.0, 40.0)
+    };
+    let below = srm.floor() as usize;
+    let above = srm.ceil() as usize;
+    let (r0, g0, b0) = SRM_RGB[below - 1];
+    let (r1, g1, b1) = SRM_RGB[above - 1];
+    let t = srm - below as f64;
+    let mix = |a: u8, b: u8| (a as f64 + (b as f64 - a as f64) * t).round() as u8;
+    (mix(r0, r1), mix(g0, g1), mix(b0, b1))
+}
+
+/// [`srm_to_rgb`] as a CSS colour, like `#FFE699`.
+pub fn srm_to_hex(srm: f64) -> String {
+    let (r, g, b) = srm_to_rgb(srm);
+    format!("#{:02X}{:02X}{:02X}", r, g, b)
+}
+
+/// `text` in the colour of beer at `srm`, with 24-bit ANSI escape codes,
+/// for terminals that understand them.
+pub fn paint(text: &str, srm: f64) -> String {
+    let (r, g, b) = srm_to_rgb(srm);
+    format!("\x1b[38;2;{};{};{}m{}\x1b[0m", r, g, b, text)
+}
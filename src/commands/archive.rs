@@ -0,0 +1,172 @@
+use crate::archive::ArchiveWriter;
+use crate::cli::{ArchiveArgs, ArchiveFormat};
+use crate::collection::{
+    file_name, indexed_files_matching, recipe_files_in, sidecar_path, stored_name, tmp_path,
+};
+use crate::index::INDEX_FILE;
+use crate::progress::format_bytes;
+use crate::state::{State, STATE_FILE};
+use crate::tags::TAGS_FILE;
+use crate::RECIPES_DIR;
+use chrono::{DateTime, Utc};
+use indicatif::{ProgressBar, ProgressStyle};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use tracing::{info, warn};
+
+/// Pack the recipes, or those matching the filters, into one archive, with
+/// their sidecars, the collection's own files and a manifest of checksums.
+/// Archives of the whole collection are remembered, so the next
+/// --incremental one only takes what has changed since.
+pub fn run(args: &ArchiveArgs) -> Result<(), Box<dyn Error>> {
+    let dir = Path::new(RECIPES_DIR);
+    if !dir.is_dir() {
+        println!("No recipes directory at {}/", RECIPES_DIR);
+        return Ok(());
+    }
+    let mut state = State::load(dir)?;
+    let started = Utc::now();
+    let format = args.format.unwrap_or_else(|| {
+        let output = args.output.to_string_lossy();
+        if output.ends_with(".tar.gz") || output.ends_with(".tgz") {
+            ArchiveFormat::TarGz
+        } else {
+            ArchiveFormat::Zip
+        }
+    });
+
+    let filter = args.filter.to_filter();
+    let filtered = !filter.is_empty();
+    let mut recipes = if filtered {
+        indexed_files_matching(dir, &filter)?
+    } else {
+        recipe_files_in(dir, state.storage_layout)?
+    };
+
+    let since = match (args.incremental, state.last_archive) {
+        (true, None) => {
+            info!("No earlier archive of the whole collection; taking everything");
+            None
+        }
+        (true, since) => since,
+        (false, _) => None,
+    };
+    if let Some(since) = since {
+        recipes.retain(|path| changed_since(path, since));
+    }
+
+    let mut files = vec![];
+    for path in &recipes {
+        files.push((archived_name(dir, path, args.flat), path.clone()));
+        let sidecar = sidecar_path(path);
+        if sidecar.is_file() {
+            files.push((archived_name(dir, &sidecar, args.flat), sidecar));
+        }
+    }
+    if args.flat {
+        let mut seen: HashMap<&str, &Path> = HashMap::new();
+        for (name, path) in &files {
+            if let Some(other) = seen.insert(name, path) {
+                return Err(format!(
+                    "{} and {} would both be archived as {}; leave out --flat to keep them apart",
+                    other.display(),
+                    path.display(),
+                    name
+                )
+                .into());
+            }
+        }
+    } else {
+        for extra in [INDEX_FILE, TAGS_FILE, STATE_FILE] {
+            let path = dir.join(extra);
+            if path.is_file() {
+                files.push((archived_name(dir, &path, false), path));
+            }
+        }
+    }
+
+    let total = files
+        .iter()
+        .map(|(_, path)| fs::metadata(path).map_or(0, |m| m.len()))
+        .sum();
+    let pb = ProgressBar::new(total);
+    pb.set_style(
+        ProgressStyle::default_bar()
+            .template("Archiving [{bar:50.cyan/blue}] {bytes}/{total_bytes} ({eta})")?
+            .progress_chars("#>-"),
+    );
+
+    // Written under another name until it's complete, so a failed run
+    // never leaves something that looks like a finished archive
+    let partial = tmp_path(&args.output);
+    let mut writer = ArchiveWriter::create(&partial, format, since)?;
+    let mut missing = 0;
+    for (name, path) in &files {
+        match writer.add_file(name, path, &pb) {
+            Ok(()) => {}
+            // Removed since the list was made, which only costs the file
+            Err(e) if !path.exists() => {
+                warn!(error = %e, "{} went away while archiving", path.display());
+                missing += 1;
+            }
+            Err(e) => {
+                let _ = fs::remove_file(&partial);
+                return Err(format!("{}: {}", path.display(), e).into());
+            }
+        }
+    }
+    let manifest = writer.finish()?;
+    fs::rename(&partial, &args.output)?;
+    pb.finish_and_clear();
+
+    if !filtered {
+        state.last_archive = Some(started);
+        state.save(dir)?;
+    }
+
+    let contents: u64 = manifest.files.iter().map(|f| f.bytes).sum();
+    println!("Archive:");
+    println!("--------");
+    println!("Path: {}", args.output.display());
+    println!(
+        "Format: {}",
+        match format {
+            ArchiveFormat::Zip => "zip",
+            ArchiveFormat::TarGz => "tar.gz",
+        }
+    );
+    if let Some(since) = since {
+        println!("Changed Since: {}", since.format("%Y-%m-%d %H:%M:%S UTC"));
+    }
+    println!("Recipes: {}", recipes.len());
+    println!("Files: {}", manifest.files.len());
+    if missing > 0 {
+        println!("Gone Before They Were Read: {}", missing);
+    }
+    println!("Contents: {}", format_bytes(contents, false));
+    println!(
+        "Archive Size: {}",
+        format_bytes(fs::metadata(&args.output)?.len(), false)
+    );
+    Ok(())
+}
+
+/// Where `path` goes in the archive: under a recipes folder, so the archive
+/// unpacks into a collection beerscape can use, and with its subfolders
+/// unless `flat`.
+fn archived_name(dir: &Path, path: &Path, flat: bool) -> String {
+    let name = if flat {
+        file_name(path)
+    } else {
+        stored_name(dir, path)
+    };
+    format!("{}/{}", RECIPES_DIR, name)
+}
+
+fn changed_since(path: &Path, since: DateTime<Utc>) -> bool {
+    fs::metadata(path)
+        .and_then(|m| m.modified())
+        .map_or(true, |modified| DateTime::<Utc>::from(modified) > since)
+}
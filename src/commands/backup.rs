@@ -0,0 +1,221 @@
+use crate::archive::ManifestEntry;
+use crate::cli::BackupArgs;
+use crate::collection::{collection_files, stored_name, tmp_path};
+use crate::progress::format_bytes;
+use crate::RECIPES_DIR;
+use chrono::{DateTime, Utc};
+use indicatif::{ProgressBar, ProgressStyle};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+/// Name of the file in each snapshot recording what it holds.
+pub const SNAPSHOT_MANIFEST: &str = "snapshot.json";
+
+/// What a snapshot holds and how it was made.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub created_at: DateTime<Utc>,
+    /// Every file, relative to the snapshot, with its checksum
+    pub files: Vec<ManifestEntry>,
+    /// Files hard-linked to the same file in the snapshot before
+    pub linked: usize,
+    pub copied: usize,
+    pub bytes: u64,
+    pub copied_bytes: u64,
+    /// Whether every file read back with the checksum it was taken with
+    pub verified: bool,
+}
+
+/// Take a snapshot of the collection in a new timestamped folder under
+/// --dest, hard-linking each file that hasn't changed since the last good
+/// snapshot to its copy there so that only changes take space. Every file
+/// is read back against its checksum, and once the snapshot checks out,
+/// all but the newest --keep are deleted.
+pub fn run(args: &BackupArgs) -> Result<(), Box<dyn Error>> {
+    let dir = Path::new(RECIPES_DIR);
+    if !dir.is_dir() {
+        println!("No recipes directory at {}/", RECIPES_DIR);
+        return Ok(());
+    }
+    if args.keep == 0 {
+        return Err("--keep must be at least 1".into());
+    }
+    fs::create_dir_all(&args.dest)?;
+    let snapshots = snapshots(&args.dest)?;
+    // Only a snapshot known to be good is worth sharing files with
+    let previous = snapshots
+        .iter()
+        .rev()
+        .find(|(_, snapshot)| snapshot.verified);
+    let previous_files: HashMap<&str, &str> = previous
+        .map(|(_, snapshot)| {
+            snapshot
+                .files
+                .iter()
+                .map(|entry| (entry.path.as_str(), entry.sha256.as_str()))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let created_at = Utc::now();
+    let name = created_at.format("%Y-%m-%dT%H%M%S%.3fZ").to_string();
+    let target = args.dest.join(&name);
+    // Built under another name, so a run that dies partway never leaves
+    // something that looks like a snapshot
+    let partial = tmp_path(&target);
+    fs::create_dir_all(&partial)?;
+
+    let paths = collection_files(dir)?;
+    let pb = ProgressBar::new(paths.len() as u64);
+    if let Ok(style) =
+        ProgressStyle::default_bar().template("Backing up [{bar:50.cyan/blue}] {pos}/{len} ({eta})")
+    {
+        pb.set_style(style.progress_chars("#>-"));
+    }
+    let mut snapshot = Snapshot {
+        created_at,
+        files: Vec::with_capacity(paths.len()),
+        linked: 0,
+        copied: 0,
+        bytes: 0,
+        copied_bytes: 0,
+        verified: false,
+    };
+    let mut links_work = true;
+    for path in &paths {
+        let contents = fs::read(path)?;
+        let entry = ManifestEntry {
+            path: format!("{}/{}", RECIPES_DIR, stored_name(dir, path)),
+            bytes: contents.len() as u64,
+            sha256: format!("{:x}", Sha256::digest(&contents)),
+        };
+        let copy = partial.join(&entry.path);
+        if let Some(parent) = copy.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let unchanged = previous
+            .filter(|_| previous_files.get(entry.path.as_str()) == Some(&entry.sha256.as_str()));
+        let linked = match unchanged {
+            Some((previous_dir, _)) if links_work => {
+                match fs::hard_link(previous_dir.join(&entry.path), &copy) {
+                    Ok(()) => true,
+                    // Deleted from the old snapshot, which says nothing
+                    // about the filesystem
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => false,
+                    Err(e) => {
+                        warn!(error = %e, "can't hard-link in {}; copying every file in full", args.dest.display());
+                        links_work = false;
+                        false
+                    }
+                }
+            }
+            _ => false,
+        };
+        if linked {
+            snapshot.linked += 1;
+        } else {
+            fs::write(&copy, &contents)?;
+            snapshot.copied += 1;
+            snapshot.copied_bytes += entry.bytes;
+        }
+        snapshot.bytes += entry.bytes;
+        snapshot.files.push(entry);
+        pb.inc(1);
+    }
+    pb.finish_and_clear();
+
+    let failures = verify(&partial, &snapshot.files);
+    snapshot.verified = failures.is_empty();
+    fs::write(
+        partial.join(SNAPSHOT_MANIFEST),
+        serde_json::to_string_pretty(&snapshot)?,
+    )?;
+    fs::rename(&partial, &target)?;
+
+    println!("Backup:");
+    println!("-------");
+    println!("Snapshot: {}", target.display());
+    println!(
+        "Files: {} ({})",
+        snapshot.files.len(),
+        format_bytes(snapshot.bytes, false)
+    );
+    println!("Hard-Linked: {}", snapshot.linked);
+    println!(
+        "Copied: {} ({})",
+        snapshot.copied,
+        format_bytes(snapshot.copied_bytes, false)
+    );
+    // Pruning could leave nothing but a bad copy
+    if !snapshot.verified {
+        println!("Verified: no");
+        for (path, error) in &failures {
+            println!("  {}: {}", path, error);
+        }
+        return Err(format!(
+            "{} files in {} don't match what was backed up; refusing to prune",
+            failures.len(),
+            target.display()
+        )
+        .into());
+    }
+    println!("Verified: yes");
+
+    let pruned = prune(&args.dest, args.keep)?;
+    println!("Pruned: {}", pruned.len());
+    for path in &pruned {
+        println!("  {}", path.display());
+    }
+    Ok(())
+}
+
+/// The snapshots in `dest`, oldest first. Folders without a readable
+/// manifest aren't snapshots, and are left alone.
+pub fn snapshots(dest: &Path) -> io::Result<Vec<(PathBuf, Snapshot)>> {
+    let mut snapshots = Vec::new();
+    for entry in fs::read_dir(dest)? {
+        let path = entry?.path();
+        let Ok(manifest) = fs::read(path.join(SNAPSHOT_MANIFEST)) else {
+            continue;
+        };
+        match serde_json::from_slice(&manifest) {
+            Ok(snapshot) => snapshots.push((path, snapshot)),
+            Err(e) => warn!(error = %e, "{} has an unreadable manifest", path.display()),
+        }
+    }
+    // Named by when they were taken, so name order is age order
+    snapshots.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(snapshots)
+}
+
+/// Read every file in a snapshot back, returning those that are missing or
+/// don't match their checksum.
+fn verify(snapshot: &Path, files: &[ManifestEntry]) -> Vec<(String, String)> {
+    let mut failures = Vec::new();
+    for entry in files {
+        match fs::read(snapshot.join(&entry.path)) {
+            Ok(contents) if format!("{:x}", Sha256::digest(&contents)) == entry.sha256 => {}
+            Ok(_) => failures.push((entry.path.clone(), "checksum doesn't match".to_string())),
+            Err(e) => failures.push((entry.path.clone(), e.to_string())),
+        }
+    }
+    failures
+}
+
+/// Delete all but the newest `keep` snapshots.
+fn prune(dest: &Path, keep: usize) -> io::Result<Vec<PathBuf>> {
+    let snapshots = snapshots(dest)?;
+    let excess = snapshots.len().saturating_sub(keep);
+    let mut pruned = Vec::new();
+    for (path, _) in snapshots.into_iter().take(excess) {
+        fs::remove_dir_all(&path)?;
+        pruned.push(path);
+    }
+    Ok(pruned)
+}
@@ -0,0 +1,103 @@
+use crate::bsmx::parse_bsmx;
+use crate::cli::BenchmarkArgs;
+use crate::collection::recipe_files;
+use crate::compression::read_bsmx;
+use crate::RECIPES_DIR;
+use glob::Pattern;
+use std::error::Error;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const DEFAULT_ITERATIONS: usize = 100;
+// Collections smaller than this are parsed repeatedly until
+// SMALL_COLLECTION_DURATION has passed instead of a fixed number of passes
+const LARGE_COLLECTION: usize = 1_000;
+const SMALL_COLLECTION_DURATION: Duration = Duration::from_secs(5);
+
+pub fn run(args: &BenchmarkArgs) -> Result<(), Box<dyn Error>> {
+    let filter = args.filter.as_deref().map(Pattern::new).transpose()?;
+
+    // Load everything up front so disk IO stays out of the measurements
+    let mut files = Vec::new();
+    for path in recipe_files()? {
+        if let Some(filter) = &filter {
+            let name = path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .unwrap_or_default();
+            if !filter.matches(name) {
+                continue;
+            }
+        }
+        files.push(read_bsmx(&path)?);
+    }
+
+    if files.is_empty() {
+        println!("No recipes to benchmark in {}/", RECIPES_DIR);
+        return Ok(());
+    }
+
+    let collection_bytes: usize = files.iter().map(Vec::len).sum();
+    println!(
+        "Benchmarking parser on {} files ({} bytes)...",
+        files.len(),
+        collection_bytes
+    );
+
+    let mut samples: Vec<u64> = Vec::new();
+    let mut failures = 0;
+    let mut passes = 0;
+    let started = Instant::now();
+
+    loop {
+        for bytes in &files {
+            let start = Instant::now();
+            let result = black_box(parse_bsmx(black_box(bytes)));
+            samples.push(start.elapsed().as_nanos() as u64);
+            if passes == 0 && result.is_err() {
+                failures += 1;
+            }
+        }
+        passes += 1;
+
+        let done = match args.iterations {
+            Some(iterations) => passes >= iterations,
+            None if files.len() >= LARGE_COLLECTION => passes >= DEFAULT_ITERATIONS,
+            None => started.elapsed() >= SMALL_COLLECTION_DURATION,
+        };
+        if done {
+            break;
+        }
+    }
+
+    let parse_time: u64 = samples.iter().sum();
+    let bytes_processed = collection_bytes as u64 * passes as u64;
+    samples.sort_unstable();
+
+    println!("\nBenchmark Results:");
+    println!("------------------");
+    println!("Files Parsed: {} ({} failed)", files.len(), failures);
+    println!("Passes: {}", passes);
+    println!("Bytes Processed: {}", bytes_processed);
+    println!(
+        "Mean Parse Time: {:?}",
+        Duration::from_nanos(parse_time / samples.len() as u64)
+    );
+    println!(
+        "Latency p50/p95/p99: {:?} / {:?} / {:?}",
+        percentile(&samples, 50.0),
+        percentile(&samples, 95.0),
+        percentile(&samples, 99.0)
+    );
+    println!(
+        "Throughput: {:.1} MB/s",
+        bytes_processed as f64 / 1_000_000.0 / (parse_time as f64 / 1e9)
+    );
+
+    Ok(())
+}
+
+fn percentile(sorted: &[u64], p: f64) -> Duration {
+    let index = ((p / 100.0) * (sorted.len() - 1) as f64).round() as usize;
+    Duration::from_nanos(sorted[index])
+}
@@ -0,0 +1,392 @@
+use crate::bsmx::Recipe;
+use crate::collection::{read_recipe, stored_name};
+use crate::index::{Filter, Index, IndexEntry, INDEX_FILE};
+use crate::RECIPES_DIR;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Modifier, Style, Stylize};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, Paragraph, Row, Table, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+use std::collections::BTreeSet;
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Recipes marked for export, one path per line.
+const MARKED_FILE: &str = "marked_recipes.txt";
+
+pub fn run() -> Result<(), Box<dyn Error>> {
+    let dir = Path::new(RECIPES_DIR);
+    if !dir.join(INDEX_FILE).exists() {
+        println!("No recipe index found; run `beerscape index` to build one");
+        return Ok(());
+    }
+    let index = Index::open(dir)?;
+    if index.count()? == 0 {
+        println!("The index is empty; run `beerscape index` to build it");
+        return Ok(());
+    }
+
+    let mut app = App::new(index)?;
+    let mut terminal = ratatui::init();
+    let result = app.run(&mut terminal);
+    ratatui::restore();
+    result?;
+
+    if app.marks_changed {
+        let mut list = String::new();
+        for filename in &app.marked {
+            list.push_str(&format!("{}\n", recipe_path(filename).display()));
+        }
+        fs::write(MARKED_FILE, list)?;
+        println!(
+            "{} recipes marked for export; list saved to {}",
+            app.marked.len(),
+            MARKED_FILE
+        );
+    }
+    Ok(())
+}
+
+struct App {
+    index: Index,
+    query: String,
+    searching: bool,
+    /// Number of recipes matching the query
+    total: usize,
+    selected: usize,
+    /// Position of the first row on screen
+    top: usize,
+    /// The rows on screen, fetched from the index as the view moves
+    rows: Vec<IndexEntry>,
+    /// `(top, height)` that `rows` was fetched for, if still current
+    window: Option<(usize, usize)>,
+    detail: Option<(String, Vec<Line<'static>>)>,
+    marked: BTreeSet<String>,
+    marks_changed: bool,
+    status: String,
+}
+
+impl App {
+    fn new(index: Index) -> Result<Self, Box<dyn Error>> {
+        // Keep marks from earlier sessions
+        let marked = fs::read_to_string(MARKED_FILE)
+            .unwrap_or_default()
+            .lines()
+            .map(|line| stored_name(Path::new(RECIPES_DIR), Path::new(line)))
+            .collect();
+
+        let mut app = App {
+            index,
+            query: String::new(),
+            searching: false,
+            total: 0,
+            selected: 0,
+            top: 0,
+            rows: Vec::new(),
+            window: None,
+            detail: None,
+            marked,
+            marks_changed: false,
+            status: String::new(),
+        };
+        app.requery()?;
+        Ok(app)
+    }
+
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> Result<(), Box<dyn Error>> {
+        loop {
+            let size = terminal.size()?;
+            let (list, _, _) = layout(Rect::new(0, 0, size.width, size.height));
+            // Borders and the header row take three lines
+            self.refresh(list.height.saturating_sub(3) as usize)?;
+            terminal.draw(|frame| self.draw(frame))?;
+
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+
+            if self.searching {
+                match key.code {
+                    KeyCode::Char(c) => {
+                        self.query.push(c);
+                        self.requery()?;
+                    }
+                    KeyCode::Backspace => {
+                        self.query.pop();
+                        self.requery()?;
+                    }
+                    KeyCode::Esc => {
+                        self.query.clear();
+                        self.searching = false;
+                        self.requery()?;
+                    }
+                    KeyCode::Enter => self.searching = false,
+                    KeyCode::Down => self.move_by(1),
+                    KeyCode::Up => self.move_by(-1),
+                    _ => {}
+                }
+                continue;
+            }
+
+            let page = list.height.saturating_sub(3).max(1) as isize;
+            self.status.clear();
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                KeyCode::Down | KeyCode::Char('j') => self.move_by(1),
+                KeyCode::Up | KeyCode::Char('k') => self.move_by(-1),
+                KeyCode::PageDown => self.move_by(page),
+                KeyCode::PageUp => self.move_by(-page),
+                KeyCode::Home | KeyCode::Char('g') => self.selected = 0,
+                KeyCode::End | KeyCode::Char('G') => self.selected = self.total.saturating_sub(1),
+                KeyCode::Char('/') => self.searching = true,
+                KeyCode::Char('e') => self.edit(terminal)?,
+                KeyCode::Char('y') => self.copy_path()?,
+                KeyCode::Char(' ') | KeyCode::Char('m') => self.toggle_mark(),
+                _ => {}
+            }
+        }
+    }
+
+    /// Start over at the top of the results for the current query.
+    fn requery(&mut self) -> rusqlite::Result<()> {
+        self.total = self.index.count_matching(&self.filter())?;
+        self.selected = 0;
+        self.top = 0;
+        self.window = None;
+        Ok(())
+    }
+
+    fn filter(&self) -> Filter {
+        Filter {
+            text: self.query.clone(),
+            ..Default::default()
+        }
+    }
+
+    fn move_by(&mut self, delta: isize) {
+        let last = self.total.saturating_sub(1) as isize;
+        self.selected = (self.selected as isize + delta).clamp(0, last) as usize;
+    }
+
+    /// Scroll so the selection is visible and fetch the rows on screen.
+    fn refresh(&mut self, height: usize) -> rusqlite::Result<()> {
+        if self.selected < self.top {
+            self.top = self.selected;
+        } else if height > 0 && self.selected >= self.top + height {
+            self.top = self.selected + 1 - height;
+        }
+        if self.window != Some((self.top, height)) {
+            self.rows = self.index.matching(&self.filter(), self.top, height)?;
+            self.window = Some((self.top, height));
+        }
+
+        let filename = self.current().map(|entry| entry.filename.clone());
+        match filename {
+            Some(filename) if self.detail.as_ref().map(|d| &d.0) != Some(&filename) => {
+                let lines = match read_recipe(&recipe_path(&filename)) {
+                    Ok(recipe) => brew_sheet(&recipe),
+                    Err(e) => vec![Line::from(format!("Couldn't read recipe: {}", e))],
+                };
+                self.detail = Some((filename, lines));
+            }
+            Some(_) => {}
+            None => self.detail = None,
+        }
+        Ok(())
+    }
+
+    fn current(&self) -> Option<&IndexEntry> {
+        self.rows.get(self.selected.checked_sub(self.top)?)
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let (list, detail, status) = layout(frame.area());
+
+        let title = if self.query.is_empty() {
+            format!(" Recipes ({}) ", self.total)
+        } else {
+            format!(" Recipes matching {:?} ({}) ", self.query, self.total)
+        };
+        let rows = self.rows.iter().enumerate().map(|(i, entry)| {
+            let mark = if self.marked.contains(&entry.filename) {
+                "*"
+            } else {
+                " "
+            };
+            let row = Row::new(vec![
+                mark.to_string(),
+                entry.name.clone(),
+                entry.style.clone(),
+                format!("{:.1}", entry.abv),
+                format!("{:.0}", entry.ibu),
+            ]);
+            if self.top + i == self.selected {
+                row.style(Style::new().add_modifier(Modifier::REVERSED))
+            } else {
+                row
+            }
+        });
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Length(1),
+                Constraint::Fill(1),
+                Constraint::Length(24),
+                Constraint::Length(5),
+                Constraint::Length(4),
+            ],
+        )
+        .header(Row::new(vec!["", "Name", "Style", "ABV", "IBU"]).bold())
+        .block(Block::default().borders(Borders::ALL).title(title));
+        frame.render_widget(table, list);
+
+        let lines = self
+            .detail
+            .as_ref()
+            .map(|(_, lines)| lines.clone())
+            .unwrap_or_default();
+        let sheet = Paragraph::new(lines)
+            .wrap(Wrap { trim: false })
+            .block(Block::default().borders(Borders::ALL).title(" Recipe "));
+        frame.render_widget(sheet, detail);
+
+        let line = if self.searching {
+            format!("/{}", self.query)
+        } else if !self.status.is_empty() {
+            self.status.clone()
+        } else {
+            format!(
+                "↑↓ move  / search  e edit  y copy path  space mark  q quit    {} marked",
+                self.marked.len()
+            )
+        };
+        frame.render_widget(Paragraph::new(line), status);
+    }
+
+    /// Open the selected recipe in $VISUAL or $EDITOR.
+    fn edit(&mut self, terminal: &mut DefaultTerminal) -> Result<(), Box<dyn Error>> {
+        let Some(entry) = self.current() else {
+            return Ok(());
+        };
+        let path = recipe_path(&entry.filename);
+        let editor = env::var("VISUAL")
+            .or_else(|_| env::var("EDITOR"))
+            .unwrap_or_else(|_| "vi".to_string());
+        let mut words = editor.split_whitespace();
+        let program = words.next().unwrap_or("vi");
+
+        ratatui::restore();
+        let result = Command::new(program).args(words).arg(&path).status();
+        *terminal = ratatui::init();
+
+        self.status = match result {
+            Ok(status) if status.success() => format!("Edited {}", path.display()),
+            Ok(status) => format!("{} exited with {}", program, status),
+            Err(e) => format!("Couldn't start {}: {}", program, e),
+        };
+        // Show the edited file, not the cached copy
+        self.detail = None;
+        Ok(())
+    }
+
+    /// Put the selected recipe's path on the clipboard with an OSC 52
+    /// escape, which most terminals (and tmux) pass to the system clipboard.
+    fn copy_path(&mut self) -> io::Result<()> {
+        let Some(entry) = self.current() else {
+            return Ok(());
+        };
+        let path = recipe_path(&entry.filename).display().to_string();
+        let mut stdout = io::stdout();
+        write!(stdout, "\x1b]52;c;{}\x07", STANDARD.encode(&path))?;
+        stdout.flush()?;
+        self.status = format!("Copied {}", path);
+        Ok(())
+    }
+
+    fn toggle_mark(&mut self) {
+        let Some(filename) = self.current().map(|entry| entry.filename.clone()) else {
+            return;
+        };
+        if !self.marked.remove(&filename) {
+            self.marked.insert(filename);
+        }
+        self.marks_changed = true;
+        self.move_by(1);
+    }
+}
+
+/// Split the screen into the recipe list, the detail pane and a status line.
+fn layout(area: Rect) -> (Rect, Rect, Rect) {
+    let [main, status] = Layout::vertical([Constraint::Fill(1), Constraint::Length(1)]).areas(area);
+    let [list, detail] =
+        Layout::horizontal([Constraint::Percentage(55), Constraint::Percentage(45)]).areas(main);
+    (list, detail, status)
+}
+
+fn recipe_path(filename: &str) -> PathBuf {
+    Path::new(RECIPES_DIR).join(filename)
+}
+
+/// A recipe laid out the way a brew sheet reads: vitals, then the grain
+/// bill, hop schedule and yeast.
+fn brew_sheet(recipe: &Recipe) -> Vec<Line<'static>> {
+    let mut lines = vec![Line::from(recipe.name.clone()).bold()];
+    if !recipe.brewer.is_empty() {
+        lines.push(Line::from(format!("by {}", recipe.brewer)));
+    }
+    let style = &recipe.style;
+    if !style.name.is_empty() {
+        lines.push(Line::from(format!(
+            "{} ({}{})",
+            style.name, style.number, style.letter
+        )));
+    }
+    lines.push(Line::from(""));
+    lines.push(Line::from(format!(
+        "Batch {:.1} L   Boil {:.0} min",
+        recipe.batch_size_l, recipe.boil_time_min
+    )));
+    lines.push(Line::from(format!(
+        "OG {:.3}   FG {:.3}   ABV {:.1}%",
+        recipe.og, recipe.fg, recipe.abv
+    )));
+    lines.push(Line::from(format!(
+        "IBU {:.0}   Color {:.1} SRM",
+        recipe.ibu, recipe.color_srm
+    )));
+
+    lines.push(Line::from(""));
+    lines.push(Line::from("Fermentables").bold());
+    for fermentable in &recipe.fermentables {
+        lines.push(Line::from(format!(
+            "  {:>6.2} kg  {} ({:.0} SRM)",
+            fermentable.amount_kg, fermentable.name, fermentable.color_srm
+        )));
+    }
+
+    lines.push(Line::from(""));
+    lines.push(Line::from("Hops").bold());
+    for hop in &recipe.hops {
+        lines.push(Line::from(format!(
+            "  {:>6.1} g   {} {:.1}% @ {:.0} min",
+            hop.amount_g, hop.name, hop.alpha_pct, hop.boil_time_min
+        )));
+    }
+
+    lines.push(Line::from(""));
+    lines.push(Line::from("Yeast").bold());
+    for yeast in &recipe.yeasts {
+        lines.push(Line::from(format!("  {}", yeast.name)));
+    }
+    lines
+}
@@ -0,0 +1,292 @@
+use crate::bsmx::Recipe;
+use crate::cli::ClusterArgs;
+use crate::collection::{file_name, load_recipes};
+use rand::rngs::StdRng;
+use rand::seq::index::sample;
+use rand::{Rng, SeedableRng};
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+
+const REPORT_FILE: &str = "clusters.json";
+const RESTARTS: usize = 10;
+// Lloyd's algorithm almost always settles long before this
+const MAX_ITERATIONS: usize = 300;
+
+/// abv, ibu, srm, og, fg, batch_size_l
+const FEATURES: usize = 6;
+
+/// The result of clustering points into groups around centroids.
+#[derive(Debug, Clone)]
+pub struct KMeans<const D: usize> {
+    pub centroids: Vec<[f64; D]>,
+    /// Index into `centroids` for each point
+    pub assignments: Vec<usize>,
+    /// Sum of squared distances from each point to its centroid
+    pub inertia: f64,
+}
+
+#[derive(Debug, Serialize)]
+struct Centroid {
+    abv: f64,
+    ibu: f64,
+    srm: f64,
+    og: f64,
+    fg: f64,
+    batch_size_l: f64,
+}
+
+#[derive(Debug, Serialize)]
+struct Cluster {
+    centroid: Centroid,
+    member_count: usize,
+    dominant_style: String,
+    members: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct ClusterReport {
+    k: usize,
+    seed: u64,
+    clustered: usize,
+    excluded: usize,
+    clusters: Vec<Cluster>,
+}
+
+pub fn run(args: &ClusterArgs) -> Result<(), Box<dyn Error>> {
+    let collection = load_recipes()?;
+
+    let mut recipes = Vec::new();
+    let mut paths = Vec::new();
+    let mut features = Vec::new();
+    for (path, recipe) in &collection.recipes {
+        if let Some(f) = recipe_features(recipe) {
+            recipes.push(recipe);
+            paths.push(file_name(path));
+            features.push(f);
+        }
+    }
+    let excluded = collection.recipes.len() - recipes.len();
+
+    println!(
+        "Clustering {} recipes ({} excluded for missing values, {} unreadable)",
+        recipes.len(),
+        excluded,
+        collection.unreadable
+    );
+    if recipes.is_empty() {
+        return Ok(());
+    }
+
+    let (min, max) = ranges(&features);
+    let normalized: Vec<[f64; FEATURES]> =
+        features.iter().map(|f| normalize(f, &min, &max)).collect();
+
+    let seed = args.seed.unwrap_or_else(|| rand::thread_rng().gen());
+    let mut rng = StdRng::seed_from_u64(seed);
+    let result = kmeans(&normalized, args.k, RESTARTS, &mut rng);
+
+    let mut clusters: Vec<Cluster> = result
+        .centroids
+        .iter()
+        .enumerate()
+        .map(|(i, centroid)| {
+            let members: Vec<usize> = (0..recipes.len())
+                .filter(|&p| result.assignments[p] == i)
+                .collect();
+            let c = denormalize(centroid, &min, &max);
+            Cluster {
+                centroid: Centroid {
+                    abv: c[0],
+                    ibu: c[1],
+                    srm: c[2],
+                    og: c[3],
+                    fg: c[4],
+                    batch_size_l: c[5],
+                },
+                member_count: members.len(),
+                dominant_style: dominant_style(members.iter().map(|&p| recipes[p])),
+                members: members.iter().map(|&p| paths[p].clone()).collect(),
+            }
+        })
+        .filter(|cluster| cluster.member_count > 0)
+        .collect();
+    clusters.sort_by_key(|cluster| Reverse(cluster.member_count));
+
+    println!();
+    println!("Clusters:");
+    println!("---------");
+    println!(
+        "{:<32} {:>7} {:>6} {:>5} {:>5} {:>6}",
+        "Dominant Style", "Recipes", "ABV", "IBU", "SRM", "OG"
+    );
+    for cluster in &clusters {
+        println!(
+            "{:<32} {:>7} {:>5.1}% {:>5.0} {:>5.1} {:>6.3}",
+            cluster.dominant_style,
+            cluster.member_count,
+            cluster.centroid.abv,
+            cluster.centroid.ibu,
+            cluster.centroid.srm,
+            cluster.centroid.og
+        );
+    }
+
+    let report = ClusterReport {
+        k: args.k,
+        seed,
+        clustered: recipes.len(),
+        excluded,
+        clusters,
+    };
+    fs::write(REPORT_FILE, serde_json::to_string_pretty(&report)?)?;
+    println!();
+    println!("Seed: {}", seed);
+    println!("Report written to {}", REPORT_FILE);
+
+    Ok(())
+}
+
+/// A recipe's numeric features, or `None` if any of them is missing (zero).
+fn recipe_features(recipe: &Recipe) -> Option<[f64; FEATURES]> {
+    let f = [
+        recipe.abv,
+        recipe.ibu,
+        recipe.color_srm,
+        recipe.og,
+        recipe.fg,
+        recipe.batch_size_l,
+    ];
+    f.iter().all(|v| v.is_finite() && *v > 0.0).then_some(f)
+}
+
+fn ranges(points: &[[f64; FEATURES]]) -> ([f64; FEATURES], [f64; FEATURES]) {
+    let mut min = [f64::INFINITY; FEATURES];
+    let mut max = [f64::NEG_INFINITY; FEATURES];
+    for point in points {
+        for d in 0..FEATURES {
+            min[d] = min[d].min(point[d]);
+            max[d] = max[d].max(point[d]);
+        }
+    }
+    (min, max)
+}
+
+fn normalize(
+    point: &[f64; FEATURES],
+    min: &[f64; FEATURES],
+    max: &[f64; FEATURES],
+) -> [f64; FEATURES] {
+    // A feature every recipe shares tells the clusters nothing
+    std::array::from_fn(|d| {
+        let span = max[d] - min[d];
+        if span > 0.0 {
+            (point[d] - min[d]) / span
+        } else {
+            0.0
+        }
+    })
+}
+
+fn denormalize(
+    point: &[f64; FEATURES],
+    min: &[f64; FEATURES],
+    max: &[f64; FEATURES],
+) -> [f64; FEATURES] {
+    std::array::from_fn(|d| min[d] + point[d] * (max[d] - min[d]))
+}
+
+/// The most common style name among `recipes`, ties going to the name that
+/// sorts first.
+fn dominant_style<'a>(recipes: impl Iterator<Item = &'a Recipe>) -> String {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for recipe in recipes {
+        let style = recipe.style.name.trim();
+        if !style.is_empty() {
+            *counts.entry(style).or_default() += 1;
+        }
+    }
+    counts
+        .into_iter()
+        .max_by(|a, b| a.1.cmp(&b.1).then(b.0.cmp(a.0)))
+        .map_or_else(|| "(no style)".to_string(), |(style, _)| style.to_string())
+}
+
+/// Cluster `points` into at most `k` groups with Lloyd's algorithm, keeping
+/// the best of `restarts` runs from different random starting centroids.
+pub fn kmeans<const D: usize>(
+    points: &[[f64; D]],
+    k: usize,
+    restarts: usize,
+    rng: &mut impl Rng,
+) -> KMeans<D> {
+    let k = k.clamp(1, points.len().max(1));
+    (0..restarts.max(1))
+        .map(|_| lloyd(points, k, rng))
+        .min_by(|a, b| a.inertia.total_cmp(&b.inertia))
+        .expect("at least one restart")
+}
+
+fn lloyd<const D: usize>(points: &[[f64; D]], k: usize, rng: &mut impl Rng) -> KMeans<D> {
+    // Start from k distinct points picked at random
+    let mut centroids: Vec<[f64; D]> = sample(rng, points.len(), k.min(points.len()))
+        .iter()
+        .map(|i| points[i])
+        .collect();
+    let mut assignments = vec![usize::MAX; points.len()];
+
+    for _ in 0..MAX_ITERATIONS {
+        let mut changed = false;
+        for (point, assignment) in points.iter().zip(&mut assignments) {
+            let nearest = nearest(point, &centroids);
+            if nearest != *assignment {
+                *assignment = nearest;
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+
+        let mut sums = vec![[0.0; D]; centroids.len()];
+        let mut counts = vec![0usize; centroids.len()];
+        for (point, &c) in points.iter().zip(&assignments) {
+            counts[c] += 1;
+            for d in 0..D {
+                sums[c][d] += point[d];
+            }
+        }
+        // A cluster that lost all its points keeps its old centroid
+        for (c, centroid) in centroids.iter_mut().enumerate() {
+            if counts[c] > 0 {
+                *centroid = std::array::from_fn(|d| sums[c][d] / counts[c] as f64);
+            }
+        }
+    }
+
+    let inertia = points
+        .iter()
+        .zip(&assignments)
+        .map(|(point, &c)| distance_squared(point, &centroids[c]))
+        .sum();
+    KMeans {
+        centroids,
+        assignments,
+        inertia,
+    }
+}
+
+fn nearest<const D: usize>(point: &[f64; D], centroids: &[[f64; D]]) -> usize {
+    centroids
+        .iter()
+        .map(|c| distance_squared(point, c))
+        .enumerate()
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map_or(0, |(i, _)| i)
+}
+
+fn distance_squared<const D: usize>(a: &[f64; D], b: &[f64; D]) -> f64 {
+    a.iter().zip(b).map(|(x, y)| (x - y).powi(2)).sum()
+}
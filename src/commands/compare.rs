@@ -0,0 +1,326 @@
+use crate::brewer;
+use crate::bsmx::Recipe;
+use crate::cli::CompareArgs;
+use crate::collection::{hash_files, read_recipe, HashedFile};
+use crate::commands::report::csv_field;
+use crate::state::State;
+use crate::styles::{find_guideline, normalize};
+use indicatif::{ProgressBar, ProgressStyle};
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use std::collections::{BTreeSet, HashMap};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+const INGREDIENT_WEIGHT: f64 = 0.4;
+const STYLE_WEIGHT: f64 = 0.3;
+const NUMERIC_WEIGHT: f64 = 0.3;
+
+// ABV, IBU, SRM and OG are scaled by these spans, wide enough to cover
+// nearly every beer, before measuring distance
+const ABV_SPAN: f64 = 15.0;
+const IBU_SPAN: f64 = 120.0;
+const SRM_SPAN: f64 = 40.0;
+const OG_SPAN: f64 = 0.130;
+
+/// How alike two recipes are, overall and by component, each from 0 to 1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Similarity {
+    pub overall: f64,
+    /// Jaccard similarity of hop and fermentable names
+    pub ingredients: f64,
+    /// 1 for the same style, 0.5 for the same category, 0 otherwise
+    pub style: f64,
+    /// 1 minus the normalized distance between ABV, IBU, SRM and OG
+    pub numeric: f64,
+}
+
+/// How a file in one directory stands with the other directory.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Standing {
+    Identical,
+    /// The other has a file of the same name with other contents
+    Changed,
+    /// The other has the same contents under another name
+    Renamed,
+    OnlyInFirst,
+    OnlyInSecond,
+}
+
+impl Standing {
+    fn label(self) -> &'static str {
+        match self {
+            Standing::Identical => "identical",
+            Standing::Changed => "different-content",
+            Standing::Renamed => "renamed",
+            Standing::OnlyInFirst => "only-in-first",
+            Standing::OnlyInSecond => "only-in-second",
+        }
+    }
+}
+
+/// A file in either directory, and its counterpart in the other.
+struct Row<'a> {
+    standing: Standing,
+    first: Option<&'a Path>,
+    second: Option<&'a Path>,
+    /// For a file the other directory doesn't have the bytes of, a file
+    /// there with the same recipe name and brewer
+    same_recipe: Option<&'a Path>,
+}
+
+/// A recipe's name and brewer, case and spacing aside.
+type RecipeKey = (String, Option<String>);
+
+pub fn run(args: &CompareArgs) -> Result<(), Box<dyn Error>> {
+    match (args.first.is_dir(), args.second.is_dir()) {
+        (true, true) => return compare_dirs(args),
+        (false, false) => {}
+        _ => return Err("compare two recipe files, or two recipes directories".into()),
+    }
+    let first = read_recipe(&args.first)?;
+    let second = read_recipe(&args.second)?;
+    let score = similarity(&first, &second);
+
+    println!("{}", display_name(&first, &args.first.to_string_lossy()));
+    println!("{}", display_name(&second, &args.second.to_string_lossy()));
+    println!();
+    println!("Similarity: {:.2}", score.overall);
+    println!("---------------");
+    println!(
+        "Ingredients: {:.2} (weight {})",
+        score.ingredients, INGREDIENT_WEIGHT
+    );
+    println!("Style:       {:.2} (weight {})", score.style, STYLE_WEIGHT);
+    println!(
+        "Numbers:     {:.2} (weight {})",
+        score.numeric, NUMERIC_WEIGHT
+    );
+
+    let (a, b) = (ingredients(&first), ingredients(&second));
+    println!();
+    println!("{:<40} Second", "First");
+    for shared in a.intersection(&b) {
+        println!("  {:<38}   {}", shared, shared);
+    }
+    for removed in a.difference(&b) {
+        println!("- {:<38}", removed);
+    }
+    for added in b.difference(&a) {
+        println!("  {:<38} + {}", "", added);
+    }
+
+    Ok(())
+}
+
+/// Match the files in two recipes directories by name and by content hash,
+/// and guess which of those that differ are the same recipe by name and
+/// brewer. Every lookup is a hash map's, so tens of thousands of files a
+/// side compare quickly.
+fn compare_dirs(args: &CompareArgs) -> Result<(), Box<dyn Error>> {
+    let first = hash_dir(&args.first)?;
+    let second = hash_dir(&args.second)?;
+    let first_names: HashMap<&str, &HashedFile> =
+        first.iter().map(|f| (f.name.as_str(), f)).collect();
+    let second_names: HashMap<&str, &HashedFile> =
+        second.iter().map(|f| (f.name.as_str(), f)).collect();
+    let first_hashes: HashMap<[u8; 32], &HashedFile> = first.iter().map(|f| (f.hash, f)).collect();
+    let second_hashes: HashMap<[u8; 32], &HashedFile> =
+        second.iter().map(|f| (f.hash, f)).collect();
+
+    let first_recipes = recipe_keys(&first, &second_hashes);
+    let second_recipes = recipe_keys(&second, &first_hashes);
+    let first_by_recipe = by_recipe(&first_recipes);
+    let second_by_recipe = by_recipe(&second_recipes);
+
+    let mut rows = Vec::with_capacity(first.len() + second.len());
+    for file in &first {
+        let (standing, other) = match second_names.get(file.name.as_str()) {
+            Some(other) if other.hash == file.hash => (Standing::Identical, Some(other)),
+            Some(other) => (Standing::Changed, Some(other)),
+            None => match second_hashes.get(&file.hash) {
+                Some(other) => (Standing::Renamed, Some(other)),
+                None => (Standing::OnlyInFirst, None),
+            },
+        };
+        rows.push(Row {
+            standing,
+            first: Some(&file.path),
+            second: other.map(|other| other.path.as_path()),
+            same_recipe: first_recipes
+                .get(file.path.as_path())
+                .and_then(|key| second_by_recipe.get(key))
+                .copied(),
+        });
+    }
+    for file in &second {
+        if !first_names.contains_key(file.name.as_str()) && !first_hashes.contains_key(&file.hash) {
+            rows.push(Row {
+                standing: Standing::OnlyInSecond,
+                first: None,
+                second: Some(&file.path),
+                same_recipe: second_recipes
+                    .get(file.path.as_path())
+                    .and_then(|key| first_by_recipe.get(key))
+                    .copied(),
+            });
+        }
+    }
+
+    let count = |standing| rows.iter().filter(|row| row.standing == standing).count();
+    println!("Directory Comparison:");
+    println!("---------------------");
+    println!("First: {} ({} files)", args.first.display(), first.len());
+    println!("Second: {} ({} files)", args.second.display(), second.len());
+    println!("Identical: {}", count(Standing::Identical));
+    println!("Only in First: {}", count(Standing::OnlyInFirst));
+    println!("Only in Second: {}", count(Standing::OnlyInSecond));
+    println!("Same Name, Different Content: {}", count(Standing::Changed));
+    println!("Same Content, Different Name: {}", count(Standing::Renamed));
+    println!(
+        "Same Recipe by Name and Brewer: {}",
+        rows.iter()
+            .filter(|row| row.first.is_some() && row.same_recipe.is_some())
+            .count()
+    );
+
+    if let Some(csv_path) = &args.csv {
+        let path_field = |path: Option<&Path>| {
+            csv_field(&path.map_or(String::new(), |path| path.display().to_string())).into_owned()
+        };
+        let mut csv = String::from("status,first,second,same_recipe_as\n");
+        for row in &rows {
+            csv.push_str(&format!(
+                "{},{},{},{}\n",
+                row.standing.label(),
+                path_field(row.first),
+                path_field(row.second),
+                path_field(row.same_recipe)
+            ));
+        }
+        fs::write(csv_path, csv)?;
+        println!("Details written to {}", csv_path.display());
+    }
+    Ok(())
+}
+
+fn hash_dir(dir: &Path) -> Result<Vec<HashedFile>, Box<dyn Error>> {
+    let pb = ProgressBar::new(0);
+    if let Ok(style) = ProgressStyle::default_bar().template(&format!(
+        "Hashing {} [{{bar:50.cyan/blue}}] {{pos}}/{{len}} ({{eta}})",
+        dir.display()
+    )) {
+        pb.set_style(style.progress_chars("#>-"));
+    }
+    let files = hash_files(dir, State::load(dir)?.storage_layout, &pb)?;
+    pb.finish_and_clear();
+    Ok(files)
+}
+
+/// The recipe name and brewer of each of `files` that `other` doesn't have
+/// the bytes of, parsed on all cores. Files that don't parse or have no
+/// recipe name are left out.
+fn recipe_keys<'a>(
+    files: &'a [HashedFile],
+    other: &HashMap<[u8; 32], &HashedFile>,
+) -> HashMap<&'a Path, RecipeKey> {
+    files
+        .par_iter()
+        .filter(|file| !other.contains_key(&file.hash))
+        .filter_map(|file| {
+            let recipe = read_recipe(&file.path).ok()?;
+            let name = brewer::display_name(&recipe.name).to_lowercase();
+            if name.is_empty() {
+                return None;
+            }
+            Some((file.path.as_path(), (name, brewer::key(&recipe.brewer))))
+        })
+        .collect()
+}
+
+/// The first file with each recipe key.
+fn by_recipe<'a>(recipes: &HashMap<&'a Path, RecipeKey>) -> HashMap<RecipeKey, &'a Path> {
+    let mut by_recipe = HashMap::new();
+    for (path, key) in recipes {
+        by_recipe
+            .entry(key.clone())
+            .and_modify(|first: &mut &Path| *first = (*first).min(*path))
+            .or_insert(*path);
+    }
+    by_recipe
+}
+
+fn display_name(recipe: &Recipe, path: &str) -> String {
+    if recipe.name.is_empty() {
+        path.to_string()
+    } else {
+        format!("{} ({})", recipe.name, path)
+    }
+}
+
+/// Score how alike two recipes are, as a weighted sum of ingredient
+/// overlap, style match and closeness of their vital statistics.
+pub fn similarity(a: &Recipe, b: &Recipe) -> Similarity {
+    let ingredients = jaccard(&ingredients(a), &ingredients(b));
+    let style = style_match(a, b);
+    let numeric = numeric_proximity(a, b);
+    Similarity {
+        overall: INGREDIENT_WEIGHT * ingredients + STYLE_WEIGHT * style + NUMERIC_WEIGHT * numeric,
+        ingredients,
+        style,
+        numeric,
+    }
+}
+
+/// Hop and fermentable names, trimmed and lowercased so spelling
+/// differences in case don't count.
+fn ingredients(recipe: &Recipe) -> BTreeSet<String> {
+    let hops = recipe.hops.iter().map(|h| &h.name);
+    let malts = recipe.fermentables.iter().map(|f| &f.name);
+    hops.chain(malts)
+        .map(|name| name.trim().to_lowercase())
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+fn jaccard(a: &BTreeSet<String>, b: &BTreeSet<String>) -> f64 {
+    let union = a.union(b).count();
+    if union == 0 {
+        // Two empty lists are the same list
+        return 1.0;
+    }
+    a.intersection(b).count() as f64 / union as f64
+}
+
+fn style_match(a: &Recipe, b: &Recipe) -> f64 {
+    match (find_guideline(&a.style), find_guideline(&b.style)) {
+        (Some(x), Some(y)) if x.code == y.code => 1.0,
+        (Some(x), Some(y)) if x.category == y.category => 0.5,
+        (Some(_), Some(_)) => 0.0,
+        // Styles outside the guidelines can still be compared by name and
+        // category number
+        _ => {
+            let (x, y) = (&a.style, &b.style);
+            let name = normalize(&x.name);
+            if !name.is_empty() && name == normalize(&y.name) {
+                1.0
+            } else if !x.number.trim().is_empty() && x.number.trim() == y.number.trim() {
+                0.5
+            } else {
+                0.0
+            }
+        }
+    }
+}
+
+fn numeric_proximity(a: &Recipe, b: &Recipe) -> f64 {
+    let diffs = [
+        (a.abv - b.abv) / ABV_SPAN,
+        (a.ibu - b.ibu) / IBU_SPAN,
+        (a.color_srm - b.color_srm) / SRM_SPAN,
+        (a.og - b.og) / OG_SPAN,
+    ];
+    let distance =
+        (diffs.iter().map(|d| d.abs().min(1.0).powi(2)).sum::<f64>() / diffs.len() as f64).sqrt();
+    (1.0 - distance).clamp(0.0, 1.0)
+}
@@ -0,0 +1,418 @@
+use crate::cli::{DaemonArgs, DownloadArgs, Mode};
+use crate::collection::{recipe_files, write_atomic};
+use crate::download::{self, build_client, download_config, watch_for_interrupt, RunError};
+use crate::preflight;
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+/// Attempts a cycle's random crawl makes unless --cycle-attempts says
+/// otherwise
+pub const DEFAULT_CYCLE_ATTEMPTS: usize = 5_000;
+/// Share of the interval wake times move by unless --jitter says otherwise
+pub const DEFAULT_JITTER: f64 = 0.1;
+/// Where the daemon records what it's doing and what its last cycle did
+pub const STATUS_FILE: &str = ".beerscape/daemon.json";
+/// Held by whichever process is running a cycle
+pub const LOCK_FILE: &str = ".beerscape/daemon.lock";
+
+/// What the daemon is doing, saved to [`STATUS_FILE`] whenever that
+/// changes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DaemonStatus {
+    pub pid: u32,
+    pub started_at: DateTime<Utc>,
+    /// `running`, `sleeping` or `stopped`
+    pub state: String,
+    /// Cycles run, counting ones skipped because another was running
+    pub cycles: usize,
+    /// The wait before the next cycle, longer than --interval while the
+    /// site is unhealthy
+    pub interval_secs: u64,
+    /// Cycles in a row that found the site unhealthy
+    pub unhealthy_streak: u32,
+    pub next_wake: Option<DateTime<Utc>>,
+    pub last_cycle: Option<Cycle>,
+}
+
+/// What one cycle did.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Cycle {
+    pub number: usize,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    /// Whether the site answered the health check and every run reached it
+    pub healthy: bool,
+    /// How the newest-ID sync ended, if there was one
+    pub newest: Option<String>,
+    /// How the random crawl toward the target ended, if there was one
+    pub crawl: Option<String>,
+    /// Recipes in the collection afterwards
+    pub recipes: usize,
+}
+
+/// Wake every --interval to fetch the recipes published since the last
+/// cycle and, while the collection is short of --target, crawl toward it
+/// for up to --cycle-attempts. SIGTERM or SIGINT stops the daemon between
+/// cycles, or during one after the batch in flight.
+pub async fn run(
+    args: &DaemonArgs,
+    download: &DownloadArgs,
+    quiet: bool,
+) -> Result<(), Box<dyn Error>> {
+    if args.status {
+        return print_status();
+    }
+    if !(0.0..1.0).contains(&args.jitter) {
+        return Err(format!("--jitter {} is not a fraction from 0 up to 1", args.jitter).into());
+    }
+    if download.mode != Mode::Random
+        || download.dry_run
+        || download.id_file.is_some()
+        || !download.ids.is_empty()
+        || !download.id_range.is_empty()
+        || !download.filter_style.is_empty()
+        || download.stratified
+    {
+        return Err("the daemon chooses its own IDs, so it can't be combined with --mode, --dry-run, --id-file, --ids, --id-range, --filter-style or --stratified".into());
+    }
+    // Every cycle's run would try to serve them again on the same address
+    if download.metrics_addr.is_some() || download.metrics_port.is_some() {
+        return Err("--metrics-addr and --metrics-port can't be used with the daemon".into());
+    }
+    let mut download = download.clone();
+    if let Some(target) = args.target {
+        download.target = target;
+    }
+
+    let interrupted = watch_for_interrupt()?;
+    let status_file = Path::new(STATUS_FILE);
+    let mut status = DaemonStatus {
+        pid: process::id(),
+        started_at: Utc::now(),
+        state: "running".to_string(),
+        cycles: 0,
+        interval_secs: args.interval.0.as_secs(),
+        unhealthy_streak: 0,
+        next_wake: None,
+        last_cycle: None,
+    };
+    let mut rng = rand::thread_rng();
+    info!(
+        "Daemon started: a cycle every {} toward {} recipes",
+        format_wait(args.interval.0),
+        download.target
+    );
+    loop {
+        status.cycles += 1;
+        status.state = "running".to_string();
+        status.next_wake = None;
+        save_status(status_file, &status)?;
+        match CycleLock::acquire(Path::new(LOCK_FILE))? {
+            Some(_lock) => {
+                let cycle =
+                    match run_cycle(status.cycles, args, &download, quiet, &interrupted).await {
+                        Ok(cycle) => cycle,
+                        Err(e) => {
+                            status.state = "stopped".to_string();
+                            save_status(status_file, &status)?;
+                            return Err(e);
+                        }
+                    };
+                info!(
+                    cycle = cycle.number,
+                    healthy = cycle.healthy,
+                    newest = cycle.newest.as_deref().unwrap_or("skipped"),
+                    crawl = cycle.crawl.as_deref().unwrap_or("skipped"),
+                    recipes = cycle.recipes,
+                    "Cycle {} done",
+                    cycle.number
+                );
+                if cycle.healthy {
+                    status.unhealthy_streak = 0;
+                } else {
+                    status.unhealthy_streak += 1;
+                }
+                status.last_cycle = Some(cycle);
+            }
+            None => warn!(
+                "Another process is running a cycle (see {}); skipping this one",
+                LOCK_FILE
+            ),
+        }
+        if interrupted.load(Ordering::Relaxed)
+            || args.max_cycles.is_some_and(|max| status.cycles >= max)
+        {
+            break;
+        }
+
+        let interval = backoff(
+            args.interval.0,
+            status.unhealthy_streak,
+            args.max_interval.0,
+        );
+        if status.unhealthy_streak > 0 {
+            warn!(
+                "The site is unhealthy; backing off to a cycle every {}",
+                format_wait(interval)
+            );
+        }
+        let wait = jittered(interval, args.jitter, &mut rng);
+        status.state = "sleeping".to_string();
+        status.interval_secs = interval.as_secs();
+        status.next_wake = chrono::Duration::from_std(wait)
+            .ok()
+            .map(|wait| Utc::now() + wait);
+        save_status(status_file, &status)?;
+        info!("Next cycle in {}", format_wait(wait));
+        if !sleep_unless_interrupted(wait, &interrupted).await {
+            break;
+        }
+    }
+    status.state = "stopped".to_string();
+    status.next_wake = None;
+    save_status(status_file, &status)?;
+    info!("Daemon stopped after {} cycles", status.cycles);
+    Ok(())
+}
+
+/// Check the site, then fetch the newest recipes and crawl toward the
+/// target. A run that fails fatally ends the daemon; any other ending is
+/// recorded and the cycle carries on.
+async fn run_cycle(
+    number: usize,
+    args: &DaemonArgs,
+    download: &DownloadArgs,
+    quiet: bool,
+    interrupted: &Arc<AtomicBool>,
+) -> Result<Cycle, Box<dyn Error>> {
+    let started_at = Utc::now();
+    info!("Cycle {} starting", number);
+    let mut healthy = download.skip_healthcheck || site_answers(download).await?;
+    let mut newest = None;
+    let mut crawl = None;
+    // Checked once here rather than by every run
+    let mut run_args = download.clone();
+    run_args.skip_healthcheck = true;
+    if healthy && !recipe_files()?.is_empty() {
+        run_args.mode = Mode::Newest;
+        let (outcome, reached) = run_once(&run_args, quiet, interrupted).await?;
+        newest = Some(outcome);
+        healthy = reached;
+    }
+    if healthy && !interrupted.load(Ordering::Relaxed) && recipe_files()?.len() < download.target {
+        run_args.mode = Mode::Random;
+        run_args.max_attempts = args.cycle_attempts;
+        let (outcome, reached) = run_once(&run_args, quiet, interrupted).await?;
+        crawl = Some(outcome);
+        healthy = reached;
+    }
+    Ok(Cycle {
+        number,
+        started_at,
+        finished_at: Utc::now(),
+        healthy,
+        newest,
+        crawl,
+        recipes: recipe_files()?.len(),
+    })
+}
+
+/// One download run, as its status line and whether it could reach the
+/// site. Only a fatal error is passed on.
+async fn run_once(
+    args: &DownloadArgs,
+    quiet: bool,
+    interrupted: &Arc<AtomicBool>,
+) -> Result<(String, bool), Box<dyn Error>> {
+    match download::run_with_interrupt(args, quiet, Some(interrupted.clone())).await {
+        Ok(summary) => Ok((format!("complete: {}", summary), true)),
+        Err(RunError::Fatal(e)) => Err(e),
+        Err(e @ RunError::CircuitOpen { .. }) => Ok((e.to_string(), false)),
+        Err(e) => Ok((e.to_string(), true)),
+    }
+}
+
+/// Whether the site answers at all: the --healthcheck-id recipe, or else
+/// the site root, which needs no login.
+async fn site_answers(args: &DownloadArgs) -> Result<bool, Box<dyn Error>> {
+    let config = download_config(args)?;
+    if config.test_mode.is_some() {
+        return Ok(true);
+    }
+    let preflight = preflight::check(&build_client(&config)?, &config, args.healthcheck_id).await;
+    if !preflight.passed() {
+        warn!("Health check failed: {}", preflight);
+    }
+    Ok(preflight.passed())
+}
+
+/// The wait before the next cycle: `interval`, doubled for each cycle in a
+/// row that found the site unhealthy, up to `max` (or `interval` itself if
+/// that's longer).
+pub fn backoff(interval: Duration, unhealthy_streak: u32, max: Duration) -> Duration {
+    let longest = max.max(interval);
+    2u32.checked_pow(unhealthy_streak)
+        .and_then(|factor| interval.checked_mul(factor))
+        .map_or(longest, |wait| wait.min(longest))
+}
+
+/// `wait` made earlier or later by up to `jitter` of itself, at random.
+pub fn jittered(wait: Duration, jitter: f64, rng: &mut impl Rng) -> Duration {
+    if jitter <= 0.0 {
+        return wait;
+    }
+    wait.mul_f64(1.0 + rng.gen_range(-jitter..=jitter))
+}
+
+/// Sleep for `wait`, returning early, with false, once `interrupted` is
+/// set.
+async fn sleep_unless_interrupted(wait: Duration, interrupted: &AtomicBool) -> bool {
+    let until = Instant::now() + wait;
+    while !interrupted.load(Ordering::Relaxed) {
+        let left = until.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return true;
+        }
+        tokio::time::sleep(left.min(Duration::from_millis(250))).await;
+    }
+    false
+}
+
+/// The lock on running a cycle, so that two daemons, or a daemon and a
+/// stray second copy, never download into one collection at once. The
+/// file holds the owner's process ID and goes away when this is dropped.
+struct CycleLock(PathBuf);
+
+impl CycleLock {
+    /// Take the lock, or `None` if another live process holds it. A lock
+    /// left behind by a process that has gone is taken over.
+    fn acquire(path: &Path) -> io::Result<Option<CycleLock>> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        for _ in 0..2 {
+            match OpenOptions::new().write(true).create_new(true).open(path) {
+                Ok(mut file) => {
+                    write!(file, "{}", process::id())?;
+                    return Ok(Some(CycleLock(path.to_path_buf())));
+                }
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                    let holder = fs::read_to_string(path).unwrap_or_default();
+                    if is_running(holder.trim()) {
+                        return Ok(None);
+                    }
+                    warn!(
+                        "Taking over {} from process {}, which is gone",
+                        path.display(),
+                        holder.trim()
+                    );
+                    fs::remove_file(path)?;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl Drop for CycleLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+/// Whether the process with this ID is still running. Only Linux can tell
+/// cheaply, through /proc; elsewhere, and for a lock whose owner hasn't
+/// written its ID yet, the answer is yes.
+fn is_running(pid: &str) -> bool {
+    match pid.parse::<u32>() {
+        Ok(pid) if cfg!(target_os = "linux") => Path::new("/proc").join(pid.to_string()).exists(),
+        _ => true,
+    }
+}
+
+fn save_status(path: &Path, status: &DaemonStatus) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    // Replaced whole, so --status never reads half of it
+    write_atomic(path, serde_json::to_string_pretty(status)?.as_bytes())
+}
+
+/// The status file's contents, if a daemon has written one.
+pub fn load_status(path: &Path) -> Result<Option<DaemonStatus>, Box<dyn Error>> {
+    match fs::read(path) {
+        Ok(json) => Ok(Some(serde_json::from_slice(&json)?)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn print_status() -> Result<(), Box<dyn Error>> {
+    let Some(status) = load_status(Path::new(STATUS_FILE))? else {
+        println!("No daemon has run here yet");
+        return Ok(());
+    };
+    // A daemon killed outright never got to say it had stopped
+    let state = if status.state != "stopped" && !is_running(&status.pid.to_string()) {
+        "stopped (exited without saying so)".to_string()
+    } else {
+        status.state.clone()
+    };
+    println!("Daemon Status:");
+    println!("--------------");
+    println!("State: {} (pid {})", state, status.pid);
+    println!("Started: {}", status.started_at.to_rfc3339());
+    println!("Cycles: {}", status.cycles);
+    println!(
+        "Interval: {}",
+        format_wait(Duration::from_secs(status.interval_secs))
+    );
+    if status.unhealthy_streak > 0 {
+        println!("Unhealthy Cycles in a Row: {}", status.unhealthy_streak);
+    }
+    if let Some(wake) = status.next_wake {
+        println!("Next Cycle: {}", wake.to_rfc3339());
+    }
+    match &status.last_cycle {
+        None => println!("Last Cycle: none finished yet"),
+        Some(cycle) => {
+            println!("Last Cycle: {}", cycle.number);
+            println!("  Started: {}", cycle.started_at.to_rfc3339());
+            println!("  Finished: {}", cycle.finished_at.to_rfc3339());
+            println!(
+                "  Site Healthy: {}",
+                if cycle.healthy { "yes" } else { "no" }
+            );
+            println!(
+                "  Newest IDs: {}",
+                cycle.newest.as_deref().unwrap_or("skipped")
+            );
+            println!(
+                "  Random Crawl: {}",
+                cycle.crawl.as_deref().unwrap_or("skipped")
+            );
+            println!("  Recipes: {}", cycle.recipes);
+        }
+    }
+    Ok(())
+}
+
+/// Hours and minutes, e.g. `6h 00m`, or seconds for a wait under a minute.
+fn format_wait(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    if secs < 60 {
+        return format!("{}s", secs);
+    }
+    format!("{}h {:02}m", secs / 3600, secs % 3600 / 60)
+}
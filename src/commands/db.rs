@@ -0,0 +1,115 @@
+use crate::cli::{DbSearchArgs, DownloadArgs};
+use crate::collection::recipe_files;
+use crate::compression::{bsmx_stem, read_bsmx};
+use crate::db::{ActivityDb, Attempt, RecipeQuery};
+use crate::download::recipe_url;
+use crate::index::Index;
+use crate::run_report::{RunReport, REPORTS_DIR};
+use crate::tags::{matches_tags, tag_filter, Tags};
+use crate::RECIPES_DIR;
+use chrono::{DateTime, Utc};
+use glob::glob;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use tracing::warn;
+
+/// Fill the database from what earlier runs left behind: the recipe files,
+/// with the IDs the index knows them by, and the failed IDs in run
+/// reports. Running it again adds only what's new.
+pub fn import(download: &DownloadArgs) -> Result<(), Box<dyn Error>> {
+    let db = open(download)?;
+    let base_url = download.base_url[0].trim_end_matches('/');
+    let before = db.counts()?;
+    let tx = db.transaction()?;
+
+    let dir = Path::new(RECIPES_DIR);
+    let mut files = 0;
+    if dir.is_dir() {
+        let index = Index::open(dir)?;
+        for path in recipe_files()? {
+            let filename = index.name_of(&path);
+            let id = match index.recipe_id(&filename)? {
+                Some(id) => Some(id),
+                None => bsmx_stem(&path).and_then(|stem| stem.parse().ok()),
+            };
+            let url = id.map(|id| recipe_url(base_url, id));
+            // The file's age is the best guess at when it was downloaded
+            let modified: DateTime<Utc> = fs::metadata(&path)?.modified()?.into();
+            db.record_file(id, url.as_deref(), &filename, &read_bsmx(&path)?, modified)?;
+            files += 1;
+        }
+    }
+
+    let mut reports = 0;
+    for path in glob(&format!("{}/*.json", REPORTS_DIR))?.flatten() {
+        let report = match RunReport::load(&path) {
+            Ok(report) => report,
+            Err(e) => {
+                warn!(file = %path.display(), error = %e, "skipping");
+                continue;
+            }
+        };
+        for &id in &report.failed_ids {
+            db.record_attempt(&Attempt {
+                id,
+                attempted_at: report.started_at,
+                http_status: None,
+                error_kind: None,
+            })?;
+        }
+        reports += 1;
+    }
+    tx.commit()?;
+
+    let after = db.counts()?;
+    println!("Import Summary:");
+    println!("---------------");
+    println!("Recipe Files Read: {}", files);
+    println!("Run Reports Read: {}", reports);
+    println!("New Downloads: {}", after.downloads - before.downloads);
+    println!("New Attempts: {}", after.attempts - before.attempts);
+    println!("Recipes Stored: {}", after.recipes);
+    Ok(())
+}
+
+pub fn search(download: &DownloadArgs, args: &DbSearchArgs) -> Result<(), Box<dyn Error>> {
+    let db = open(download)?;
+    let found = db.search(&RecipeQuery {
+        name: args.name.clone(),
+        style: args.style.clone(),
+        brewer: args.brewer.clone(),
+        min_abv: args.min_abv,
+        max_abv: args.max_abv,
+        min_ibu: args.min_ibu,
+        max_ibu: args.max_ibu,
+        notes_contains: args.notes_contains.clone(),
+    })?;
+    // Tags are kept with the recipes, under the names the database has
+    let wanted = tag_filter(&args.tag);
+    let tags = if wanted.is_empty() {
+        Tags::default()
+    } else {
+        Tags::load(Path::new(RECIPES_DIR))?
+    };
+    let found: Vec<_> = found
+        .into_iter()
+        .filter(|recipe| matches_tags(tags.of(&recipe.filename), &wanted))
+        .collect();
+    for recipe in &found {
+        println!(
+            "{:<40} {:<28} {:>5.1}%  {}",
+            recipe.name, recipe.style, recipe.abv, recipe.filename
+        );
+    }
+    println!("{} recipes", found.len());
+    Ok(())
+}
+
+fn open(download: &DownloadArgs) -> Result<ActivityDb, Box<dyn Error>> {
+    let path = download
+        .db
+        .as_deref()
+        .ok_or("give the database with --db PATH before the subcommand")?;
+    Ok(ActivityDb::open(path)?)
+}
@@ -0,0 +1,254 @@
+use crate::brewer;
+use crate::bsmx::{parse_bsmx, Recipe};
+use crate::cli::DeduplicateArgs;
+use crate::collection::{hash_files, load_recipes, sidecar_path, HashedFile};
+use crate::compression::read_bsmx;
+use crate::fingerprint::{has_ingredients, ingredient_fingerprint};
+use crate::state::State;
+use crate::styles::normalize;
+use crate::RECIPES_DIR;
+use indicatif::{ProgressBar, ProgressStyle};
+use serde::Serialize;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use strsim::normalized_levenshtein;
+
+const REPORT_FILE: &str = "duplicates_report.json";
+/// Name similarity a fuzzy match needs unless told otherwise
+pub const DEFAULT_SIMILARITY: f64 = 0.9;
+// How far apart two OGs can be and still count as the same recipe
+const OG_TOLERANCE: f64 = 0.004;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum DuplicateKind {
+    ConfirmedDuplicate,
+    PossibleDuplicate,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DuplicatePair {
+    pub kind: DuplicateKind,
+    pub similarity: f64,
+    pub first: PathBuf,
+    pub second: PathBuf,
+    pub first_name: String,
+    pub second_name: String,
+}
+
+#[derive(Debug, Serialize)]
+struct DuplicatesReport {
+    mode: &'static str,
+    duplicates: Vec<DuplicatePair>,
+}
+
+pub fn run(args: &DeduplicateArgs) -> Result<(), Box<dyn Error>> {
+    let duplicates = if args.fuzzy {
+        fuzzy_duplicates(args.similarity_threshold)?
+    } else if args.by_fingerprint {
+        fingerprint_duplicates()?
+    } else {
+        exact_duplicates()?
+    };
+
+    let confirmed: Vec<&DuplicatePair> = duplicates
+        .iter()
+        .filter(|d| d.kind == DuplicateKind::ConfirmedDuplicate)
+        .collect();
+    println!(
+        "Found {} duplicate pairs ({} confirmed, {} possible)",
+        duplicates.len(),
+        confirmed.len(),
+        duplicates.len() - confirmed.len()
+    );
+
+    if args.auto_remove {
+        let mut removed = 0;
+        for pair in &confirmed {
+            // A file can be the second half of several pairs
+            if pair.second.exists() && pair.first.exists() {
+                fs::remove_file(&pair.second)?;
+                let sidecar = sidecar_path(&pair.second);
+                if sidecar.exists() {
+                    fs::remove_file(sidecar)?;
+                }
+                println!("Removed {}", pair.second.display());
+                removed += 1;
+            }
+        }
+        println!("Removed {} duplicate files", removed);
+    }
+
+    let report = DuplicatesReport {
+        mode: if args.fuzzy {
+            "fuzzy"
+        } else if args.by_fingerprint {
+            "fingerprint"
+        } else {
+            "exact"
+        },
+        duplicates,
+    };
+    fs::write(REPORT_FILE, serde_json::to_string_pretty(&report)?)?;
+    println!("Report written to {}", REPORT_FILE);
+
+    Ok(())
+}
+
+/// Byte-identical files, which are always confirmed duplicates.
+pub fn exact_duplicates() -> Result<Vec<DuplicatePair>, Box<dyn Error>> {
+    let mut by_hash: HashMap<[u8; 32], PathBuf> = HashMap::new();
+    let mut duplicates = Vec::new();
+
+    let dir = Path::new(RECIPES_DIR);
+    let files = hash_files(
+        dir,
+        State::load(dir)?.storage_layout,
+        &ProgressBar::hidden(),
+    )?;
+    for HashedFile { path, hash, .. } in files {
+        match by_hash.get(&hash) {
+            Some(first) => duplicates.push(DuplicatePair {
+                kind: DuplicateKind::ConfirmedDuplicate,
+                similarity: 1.0,
+                first_name: recipe_name(first),
+                second_name: recipe_name(&path),
+                first: first.clone(),
+                second: path,
+            }),
+            None => {
+                by_hash.insert(hash, path);
+            }
+        }
+    }
+
+    Ok(duplicates)
+}
+
+/// Recipes with the same ingredient fingerprint, i.e. the same recipe at
+/// different batch sizes, which are always confirmed duplicates. Each is
+/// paired with the first file of its fingerprint. Recipes without any
+/// ingredients aren't compared.
+pub fn fingerprint_duplicates() -> Result<Vec<DuplicatePair>, Box<dyn Error>> {
+    let collection = load_recipes()?;
+    let mut by_fingerprint: HashMap<[u8; 32], (&PathBuf, &Recipe)> = HashMap::new();
+    let mut duplicates = Vec::new();
+
+    // Files come in name order, so the first of each fingerprint is kept
+    for (path, recipe) in &collection.recipes {
+        if !has_ingredients(recipe) {
+            continue;
+        }
+        match by_fingerprint.entry(ingredient_fingerprint(recipe)) {
+            Entry::Occupied(first) => {
+                let (first_path, first) = *first.get();
+                duplicates.push(DuplicatePair {
+                    kind: DuplicateKind::ConfirmedDuplicate,
+                    similarity: 1.0,
+                    first: first_path.clone(),
+                    second: path.clone(),
+                    first_name: first.name.clone(),
+                    second_name: recipe.name.clone(),
+                });
+            }
+            Entry::Vacant(slot) => {
+                slot.insert((path, recipe));
+            }
+        }
+    }
+
+    Ok(duplicates)
+}
+
+/// Pairs of recipes whose names are at least `threshold` similar. Pairs
+/// that also agree on style and OG, or share a brewer, are confirmed.
+pub fn fuzzy_duplicates(threshold: f64) -> Result<Vec<DuplicatePair>, Box<dyn Error>> {
+    let collection = load_recipes()?;
+    let mut recipes: Vec<(String, &PathBuf, &Recipe)> = collection
+        .recipes
+        .iter()
+        .map(|(path, recipe)| (name_key(&recipe.name), path, recipe))
+        .filter(|(key, _, _)| !key.is_empty())
+        .collect();
+
+    // Sorting by length lets each name stop comparing once the others are
+    // too long to ever reach the threshold
+    recipes.sort_by_key(|(key, _, _)| key.chars().count());
+    let lengths: Vec<usize> = recipes.iter().map(|(k, _, _)| k.chars().count()).collect();
+
+    let pb = ProgressBar::new(recipes.len() as u64);
+    pb.set_style(
+        ProgressStyle::default_bar()
+            .template("Comparing names [{bar:50.cyan/blue}] {pos}/{len} ({eta})")?
+            .progress_chars("#>-"),
+    );
+
+    let mut duplicates = Vec::new();
+    for i in 0..recipes.len() {
+        pb.inc(1);
+        let (key_a, path_a, a) = &recipes[i];
+        for j in i + 1..recipes.len() {
+            // Normalized Levenshtein can't exceed min/max of the lengths
+            if (lengths[i] as f64) < threshold * lengths[j] as f64 {
+                break;
+            }
+            let (key_b, path_b, b) = &recipes[j];
+            let similarity = normalized_levenshtein(key_a, key_b);
+            if similarity < threshold {
+                continue;
+            }
+
+            let same_recipe = same_style(a, b) && (a.og - b.og).abs() <= OG_TOLERANCE;
+            let kind = if same_recipe || same_brewer(a, b) {
+                DuplicateKind::ConfirmedDuplicate
+            } else {
+                DuplicateKind::PossibleDuplicate
+            };
+            // Report in file order so --auto-remove keeps the first one
+            let (first, second) = if path_a <= path_b {
+                ((path_a, a), (path_b, b))
+            } else {
+                ((path_b, b), (path_a, a))
+            };
+            duplicates.push(DuplicatePair {
+                kind,
+                similarity,
+                first: first.0.to_path_buf(),
+                second: second.0.to_path_buf(),
+                first_name: first.1.name.clone(),
+                second_name: second.1.name.clone(),
+            });
+        }
+    }
+    pb.finish_and_clear();
+
+    duplicates.sort_by(|a, b| a.first.cmp(&b.first).then(a.second.cmp(&b.second)));
+    Ok(duplicates)
+}
+
+/// Normalized recipe name for comparison: "American I.P.A." and
+/// "american ipa" both become "american ipa".
+pub fn name_key(name: &str) -> String {
+    normalize(&name.replace(['.', '\''], ""))
+}
+
+fn same_style(a: &Recipe, b: &Recipe) -> bool {
+    normalize(&a.style.name) == normalize(&b.style.name)
+}
+
+fn same_brewer(a: &Recipe, b: &Recipe) -> bool {
+    match (brewer::key(&a.brewer), brewer::key(&b.brewer)) {
+        (Some(a), Some(b)) => a == b,
+        _ => false,
+    }
+}
+
+fn recipe_name(path: &Path) -> String {
+    read_bsmx(path)
+        .ok()
+        .and_then(|bytes| parse_bsmx(&bytes).ok())
+        .map(|recipe| recipe.name)
+        .unwrap_or_default()
+}
@@ -0,0 +1,197 @@
+use crate::cli::{DiscoverArgs, DownloadArgs};
+use crate::download::{self, batch_delay, build_client, download_config, watch_for_interrupt};
+use crate::limits::TimeLimit;
+use crate::listing::{listing_url, parse_listing, ListedRecipe, ListingPage, Search};
+use crate::throttle::RequestGate;
+use reqwest::{Client, StatusCode};
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs;
+use std::sync::atomic::Ordering;
+use std::time::Instant;
+use tracing::{info, warn};
+
+/// Pages fetched unless --max-pages says otherwise
+pub const DEFAULT_MAX_PAGES: u32 = 100;
+/// File the IDs are written to unless --output says otherwise
+pub const DISCOVERED_IDS_FILE: &str = "discovered_ids.txt";
+
+/// What reading one of the site's listings turned up.
+#[derive(Debug, Default)]
+pub struct Crawl {
+    /// Recipes in the order they were found, each once
+    pub recipes: Vec<ListedRecipe>,
+    /// Pages read, not counting the second look at the first
+    pub pages: usize,
+    /// Why the crawl stopped
+    pub reason: &'static str,
+    seen: HashSet<u32>,
+}
+
+impl Crawl {
+    /// Add the recipes not seen before, returning how many there were.
+    fn add(&mut self, recipes: Vec<ListedRecipe>) -> usize {
+        let before = self.recipes.len();
+        for recipe in recipes {
+            if self.seen.insert(recipe.id) {
+                self.recipes.push(recipe);
+            }
+        }
+        self.recipes.len() - before
+    }
+}
+
+/// Walk the site's search or listing pages collecting recipe IDs, then
+/// write them out and, with --download, fetch them.
+pub async fn run(
+    args: &DiscoverArgs,
+    download: &DownloadArgs,
+    quiet: bool,
+) -> Result<(), Box<dyn Error>> {
+    let config = download_config(download)?;
+    let client = build_client(&config)?;
+    if let Some(login) = &config.login {
+        login.log_in(&client, &config).await?;
+    }
+    let time_limit = TimeLimit::new(
+        Instant::now(),
+        download.max_duration.map(|d| d.0),
+        download.deadline.map(|t| t.0),
+    );
+    let interrupted = watch_for_interrupt()?;
+    let search = match &args.query {
+        Some(query) => Search::Query(query),
+        None => Search::All,
+    };
+    let found = crawl(
+        &client,
+        &config.base_url,
+        &config.requests,
+        search,
+        args.max_pages,
+        || {
+            if interrupted.load(Ordering::Relaxed) {
+                return Some("interrupted");
+            }
+            time_limit.filter(TimeLimit::is_reached).map(|l| l.reason)
+        },
+    )
+    .await?;
+
+    let mut list = match &args.query {
+        Some(query) => format!("# beerscape discover --query {:?}\n", query),
+        None => "# beerscape discover\n".to_string(),
+    };
+    for recipe in &found.recipes {
+        if recipe.name.is_empty() {
+            list.push_str(&format!("{}\n", recipe.id));
+        } else {
+            list.push_str(&format!("{} # {}\n", recipe.id, recipe.name));
+        }
+    }
+    fs::write(&args.output, list)?;
+
+    println!("Discovery Summary:");
+    println!("------------------");
+    println!("Pages Read: {}", found.pages);
+    println!("Stopped At: {}", found.reason);
+    println!("Recipe IDs Found: {}", found.recipes.len());
+    println!("IDs Written To: {}", args.output.display());
+
+    if args.download && !found.recipes.is_empty() {
+        println!();
+        let mut download = download.clone();
+        download
+            .ids
+            .extend(found.recipes.iter().map(|recipe| recipe.id));
+        download::exit_with(download::run(&download, quiet).await);
+    }
+    Ok(())
+}
+
+/// Read the pages of `search` in order, collecting the recipes they link
+/// to, until there's no next page, two pages in a row add nothing new,
+/// `max_pages` have been read or `stop` gives a reason to. Pages are spaced
+/// out like download batches, and pass through `requests` like them.
+///
+/// Listings change while they're read. Recipes added at the top push
+/// others onto later pages, where they turn up again and are dropped as
+/// repeats, so one page of nothing new isn't taken as the end; and the
+/// first page is read again afterwards for anything added meanwhile.
+pub async fn crawl(
+    client: &Client,
+    base_url: &str,
+    requests: &RequestGate,
+    search: Search<'_>,
+    max_pages: u32,
+    stop: impl Fn() -> Option<&'static str>,
+) -> Result<Crawl, Box<dyn Error>> {
+    let mut crawl = Crawl {
+        reason: "max-pages",
+        ..Default::default()
+    };
+    let mut fruitless = 0;
+    for page in 1..=max_pages {
+        if let Some(reason) = stop() {
+            crawl.reason = reason;
+            return Ok(crawl);
+        }
+        if page > 1 {
+            tokio::time::sleep(batch_delay(requests)).await;
+        }
+        requests.pass().await;
+        let Some(listing) = fetch_page(client, base_url, search, page).await? else {
+            crawl.reason = "http-error";
+            break;
+        };
+        crawl.pages += 1;
+        let new = crawl.add(listing.recipes);
+        info!(page, new, "read listing page");
+        if !listing.has_next {
+            crawl.reason = "last-page";
+            break;
+        }
+        fruitless = if new == 0 { fruitless + 1 } else { 0 };
+        // Some sites repeat their last page for any page number past it
+        if fruitless == 2 {
+            crawl.reason = "last-page";
+            break;
+        }
+    }
+
+    if crawl.pages > 1 && stop().is_none() {
+        tokio::time::sleep(batch_delay(requests)).await;
+        requests.pass().await;
+        if let Ok(Some(listing)) = fetch_page(client, base_url, search, 1).await {
+            let new = crawl.add(listing.recipes);
+            if new > 0 {
+                info!(new, "recipes added to the listing while it was read");
+            }
+        }
+    }
+    Ok(crawl)
+}
+
+/// One page of a listing, or `None` if the server answered with an error
+/// past the first page, which most likely means the listing ran out.
+async fn fetch_page(
+    client: &Client,
+    base_url: &str,
+    search: Search<'_>,
+    page: u32,
+) -> Result<Option<ListingPage>, Box<dyn Error>> {
+    let url = listing_url(base_url, search, page)?;
+    let response = client.get(url.clone()).send().await?;
+    let status = response.status();
+    if status == StatusCode::UNAUTHORIZED {
+        return Err("the server requires a login session; pass --cookies or --username".into());
+    }
+    if !status.is_success() {
+        if page == 1 {
+            return Err(format!("{}: HTTP {}", url, status.as_u16()).into());
+        }
+        warn!(status = status.as_u16(), "stopping at page {}", page);
+        return Ok(None);
+    }
+    Ok(Some(parse_listing(&response.text().await?)))
+}
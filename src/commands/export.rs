@@ -0,0 +1,193 @@
+use crate::bsmx::Recipe;
+use crate::cli::{ExportArgs, ExportFormat, Units};
+use crate::collection::{file_name, read_recipe, recipe_files};
+use crate::normalize::convert_json;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use tracing::warn;
+
+/// One exported recipe: its parsed contents plus the file it came from.
+#[derive(Debug, Serialize)]
+pub struct RecipeJson {
+    pub filename: String,
+    #[serde(flatten)]
+    pub recipe: Recipe,
+}
+
+/// A recipe as it's written out: as read, or converted to other units.
+#[derive(Serialize)]
+#[serde(untagged)]
+enum Exported {
+    Metric(Box<RecipeJson>),
+    Converted(Value),
+}
+
+impl Exported {
+    fn new(recipe: RecipeJson, units: Units) -> serde_json::Result<Self> {
+        if units == Units::Metric {
+            return Ok(Exported::Metric(Box::new(recipe)));
+        }
+        let mut value = serde_json::to_value(recipe)?;
+        convert_json(&mut value, units);
+        Ok(Exported::Converted(value))
+    }
+}
+
+/// Start of the files delta exports write to, followed by a timestamp
+pub const DELTA_PREFIX: &str = "recipes_delta_";
+
+pub fn run(args: &ExportArgs) -> Result<(), Box<dyn Error>> {
+    let started = SystemTime::now();
+    let since = match (args.since, args.since_last_export) {
+        (Some(since), _) => Some(SystemTime::from(since)),
+        (None, true) => {
+            let last = last_export(Path::new("."))?;
+            if last.is_none() {
+                println!("No earlier delta export found; exporting every recipe");
+            }
+            Some(last.unwrap_or(SystemTime::UNIX_EPOCH))
+        }
+        (None, false) => None,
+    };
+    let mut paths = recipe_files()?;
+    let mut output = args.output.clone();
+    let mut file = File::options();
+    file.write(true).create(true).truncate(true);
+    if let Some(since) = since {
+        paths = modified_since(paths, since)?;
+        if output.is_none() {
+            output = Some(delta_path(Utc::now(), args.output_format));
+            // Never replace an earlier delta, which may not have been
+            // picked up yet
+            file.create_new(true);
+        }
+    }
+
+    let result = match &output {
+        Some(path) => {
+            let file = file
+                .open(path)
+                .map_err(|e| format!("{}: {}", path.display(), e))?;
+            let mut out = BufWriter::new(file);
+            export(&paths, args.output_format, args.units, &mut out).and_then(|n| {
+                out.flush()?;
+                // Dated from the start, so recipes saved during the export
+                // are in the next delta
+                if since.is_some() {
+                    out.get_ref().set_modified(started)?;
+                }
+                Ok(n)
+            })
+        }
+        None => export(
+            &paths,
+            args.output_format,
+            args.units,
+            &mut io::stdout().lock(),
+        ),
+    };
+    let exported = match result {
+        Ok(exported) => exported,
+        // The reader went away, as `| head` does; that's not an error
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+    if let Some(path) = &output {
+        println!("Exported {} recipes to {}", exported, path.display());
+    }
+    Ok(())
+}
+
+/// The files in `paths` modified after `since`.
+pub fn modified_since(paths: Vec<PathBuf>, since: SystemTime) -> io::Result<Vec<PathBuf>> {
+    let mut changed = Vec::new();
+    for path in paths {
+        if fs::metadata(&path)?.modified()? > since {
+            changed.push(path);
+        }
+    }
+    Ok(changed)
+}
+
+/// When the newest delta export in `dir` was written, if there is one.
+pub fn last_export(dir: &Path) -> io::Result<Option<SystemTime>> {
+    let mut last = None;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry
+            .file_name()
+            .to_string_lossy()
+            .starts_with(DELTA_PREFIX)
+        {
+            let modified = entry.metadata()?.modified()?;
+            last = last.max(Some(modified));
+        }
+    }
+    Ok(last)
+}
+
+/// The file a delta export made at `at` is written to.
+pub fn delta_path(at: DateTime<Utc>, format: ExportFormat) -> PathBuf {
+    let extension = match format {
+        ExportFormat::Json => "json",
+        ExportFormat::Ndjson => "ndjson",
+    };
+    PathBuf::from(format!(
+        "{}{}.{}",
+        DELTA_PREFIX,
+        at.format("%Y%m%d-%H%M%S"),
+        extension
+    ))
+}
+
+/// Write the recipes in `paths` to `out` in `units`, skipping any that
+/// can't be read, and return how many were written.
+///
+/// NDJSON is written as each recipe is parsed and flushed line by line, so
+/// a downstream process sees recipes while the export is still running.
+/// JSON is a single array, written once every recipe has been read.
+pub fn export(
+    paths: &[PathBuf],
+    format: ExportFormat,
+    units: Units,
+    out: &mut dyn Write,
+) -> io::Result<usize> {
+    let recipes = paths.iter().filter_map(|path| match read_recipe(path) {
+        Ok(recipe) => Some(Exported::new(
+            RecipeJson {
+                filename: file_name(path),
+                recipe,
+            },
+            units,
+        )),
+        Err(e) => {
+            warn!(path = %path.display(), error = %e, "skipping unreadable recipe");
+            None
+        }
+    });
+
+    match format {
+        ExportFormat::Json => {
+            let recipes = recipes.collect::<serde_json::Result<Vec<_>>>()?;
+            serde_json::to_writer_pretty(&mut *out, &recipes)?;
+            writeln!(out)?;
+            Ok(recipes.len())
+        }
+        ExportFormat::Ndjson => {
+            let mut exported = 0;
+            for recipe in recipes {
+                serde_json::to_writer(&mut *out, &recipe?)?;
+                writeln!(out)?;
+                out.flush()?;
+                exported += 1;
+            }
+            Ok(exported)
+        }
+    }
+}
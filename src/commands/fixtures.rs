@@ -0,0 +1,34 @@
+use crate::cli::FixturesArgs;
+use crate::collection::recipe_files;
+use crate::compression::read_bsmx;
+use crate::layout::uncompressed_name;
+use rand::seq::SliceRandom;
+use std::error::Error;
+use std::fs;
+
+/// Copy --count recipes picked at random from the collection into the
+/// fixture directory, uncompressed and flat, for `download --test-mode` to
+/// answer requests with.
+pub fn run(args: &FixturesArgs) -> Result<(), Box<dyn Error>> {
+    let candidates = recipe_files()?;
+    if candidates.is_empty() {
+        println!("No recipes to copy; download some first");
+        return Ok(());
+    }
+    fs::create_dir_all(&args.output)?;
+    let picked: Vec<_> = candidates
+        .choose_multiple(&mut rand::thread_rng(), args.count)
+        .collect();
+    for path in &picked {
+        fs::write(
+            args.output.join(uncompressed_name(path)),
+            read_bsmx(path).map_err(|e| format!("{}: {}", path.display(), e))?,
+        )?;
+    }
+    println!(
+        "Copied {} recipes to {}",
+        picked.len(),
+        args.output.display()
+    );
+    Ok(())
+}
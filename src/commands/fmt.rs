@@ -0,0 +1,74 @@
+use crate::bsmx::{parse_document, write_document};
+use crate::cli::{Compression, FmtArgs};
+use crate::collection::{recipe_files, tmp_path};
+use std::error::Error;
+use std::fs;
+use std::process;
+use tracing::warn;
+
+pub fn run(args: &FmtArgs) -> Result<(), Box<dyn Error>> {
+    let paths = if args.all {
+        recipe_files()?
+    } else {
+        args.paths.clone()
+    };
+
+    let mut changed = 0;
+    let mut reformatted = 0;
+    let mut skipped = 0;
+    for path in &paths {
+        let compression = Compression::of(path);
+        let bytes = compression.read(path)?;
+        let document = match parse_document(&bytes) {
+            Ok(document) => document,
+            Err(e) => {
+                warn!(file = %path.display(), error = %e, "skipping");
+                skipped += 1;
+                continue;
+            }
+        };
+
+        let formatted = write_document(&document);
+        if formatted.as_bytes() == bytes {
+            continue;
+        }
+
+        changed += 1;
+        if args.check {
+            println!("{}", path.display());
+            continue;
+        }
+
+        // Only replace the original once the written copy is known to
+        // parse to exactly the same document
+        let tmp = tmp_path(path);
+        compression.write(&tmp, formatted.as_bytes(), None)?;
+        let written = compression.read(&tmp)?;
+        if parse_document(&written).ok().as_ref() != Some(&document) {
+            fs::remove_file(&tmp)?;
+            warn!(
+                file = %path.display(),
+                "skipping: formatted copy doesn't match the original"
+            );
+            skipped += 1;
+            continue;
+        }
+        fs::rename(&tmp, path)?;
+        reformatted += 1;
+    }
+
+    if args.check {
+        println!("{} of {} files would be reformatted", changed, paths.len());
+        if changed > 0 {
+            process::exit(1);
+        }
+    } else {
+        println!(
+            "Reformatted {} of {} files ({} skipped)",
+            reformatted,
+            paths.len(),
+            skipped
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,121 @@
+use crate::bsmx::parse_bsmx;
+use crate::cli::{DownloadArgs, HealthCheckArgs};
+use crate::download::{
+    build_client, download_config, error_category, recipe_url, request_user_agent, DownloadConfig,
+    EXIT_FATAL,
+};
+use crate::probe::wilson_interval;
+use reqwest::header::USER_AGENT;
+use reqwest::Client;
+use std::error::Error;
+use std::process;
+use std::time::{Duration, Instant};
+
+/// Long-standing recipes on the default site, probed unless --test-ids
+/// names others
+pub const DEFAULT_TEST_IDS: [u32; 3] = [1_000, 10_000, 100_000];
+/// Exit status when some probes failed but not all
+pub const EXIT_DEGRADED: i32 = 1;
+/// Exit status when every probe failed
+pub const EXIT_DOWN: i32 = 2;
+
+/// How one probe went.
+#[derive(Debug, PartialEq)]
+pub enum Probe {
+    /// A well-formed recipe, and its name
+    Ok(String),
+    /// Why not: a failure category, or the HTTP status
+    Fail(String),
+}
+
+/// Fetch each test ID once with the options a download run would use,
+/// print how each went and how long it took, and exit 0 if every probe
+/// found a recipe, 1 if some did and 2 if none did. Bad options exit 4,
+/// as they do for a download run, so a script never mistakes them for the
+/// site being down; so does a login that fails.
+pub async fn run(args: &HealthCheckArgs, download: &DownloadArgs) -> Result<(), Box<dyn Error>> {
+    let (config, client) = match setup(download).await {
+        Ok(setup) => setup,
+        Err(e) => {
+            eprintln!("beerscape: fatal: {}", e);
+            process::exit(EXIT_FATAL);
+        }
+    };
+    let ids = if args.test_ids.is_empty() {
+        DEFAULT_TEST_IDS.to_vec()
+    } else {
+        args.test_ids.clone()
+    };
+
+    println!("Health Check:");
+    println!("-------------");
+    println!("Site: {}", config.base_url);
+    let mut passed = 0;
+    let mut latencies = vec![];
+    for &id in &ids {
+        let (probe, elapsed) = probe(&client, &config, id).await;
+        let millis = elapsed.as_millis();
+        match &probe {
+            Probe::Ok(name) => {
+                passed += 1;
+                latencies.push(elapsed);
+                println!("  OK    {:<10} {:>6} ms  {}", id, millis, name);
+            }
+            Probe::Fail(reason) => {
+                println!("  FAIL  {:<10} {:>6} ms  {}", id, millis, reason)
+            }
+        }
+    }
+
+    println!("Passed: {} of {}", passed, ids.len());
+    if !latencies.is_empty() {
+        let mean = latencies.iter().sum::<Duration>() / latencies.len() as u32;
+        println!("Average Latency: {} ms", mean.as_millis());
+    }
+    if let Some((low, high)) = wilson_interval(passed, ids.len()) {
+        println!(
+            "Expected Success Rate: {:.0}% (95% CI {:.0}%-{:.0}%)",
+            passed as f64 / ids.len() as f64 * 100.0,
+            low * 100.0,
+            high * 100.0
+        );
+    }
+
+    match passed {
+        n if n == ids.len() => Ok(()),
+        0 => process::exit(EXIT_DOWN),
+        _ => process::exit(EXIT_DEGRADED),
+    }
+}
+
+async fn setup(download: &DownloadArgs) -> Result<(DownloadConfig, Client), Box<dyn Error>> {
+    let config = download_config(download)?;
+    let client = build_client(&config)?;
+    if let Some(login) = &config.login {
+        login.log_in(&client, &config).await?;
+    }
+    Ok((config, client))
+}
+
+/// Fetch one recipe without keeping it, timing the whole round trip.
+pub async fn probe(client: &Client, config: &DownloadConfig, id: u32) -> (Probe, Duration) {
+    let started = Instant::now();
+    let request = client
+        .get(recipe_url(&config.base_url, id))
+        .header(USER_AGENT, request_user_agent(config));
+    let proxied = config.proxy.is_some();
+    let probe = match request.send().await {
+        Err(e) => Probe::Fail(error_category(&e, proxied).to_string()),
+        Ok(response) if !response.status().is_success() => {
+            Probe::Fail(format!("HTTP {}", response.status().as_u16()))
+        }
+        Ok(response) => match response.bytes().await {
+            Err(e) => Probe::Fail(error_category(&e, proxied).to_string()),
+            Ok(body) => match parse_bsmx(&body) {
+                Ok(recipe) => Probe::Ok(recipe.name),
+                Err(e) => Probe::Fail(format!("not a recipe: {}", e)),
+            },
+        },
+    };
+    (probe, started.elapsed())
+}
@@ -0,0 +1,269 @@
+use crate::bsmx::Recipe;
+use crate::cli::{HopInventoryArgs, Units};
+use crate::collection::{read_recipe, recipe_files};
+use crate::commands::report::csv_field;
+use crate::normalize::OZ_TO_G;
+use glob::glob;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// File `--export-csv` writes the inventory to.
+pub const INVENTORY_CSV: &str = "hop_inventory.csv";
+
+/// One hop variety's use across the selected recipes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HopTotal {
+    /// First spelling seen, tidied of surrounding whitespace
+    pub name: String,
+    /// Total across every addition, scaled by the batch count
+    pub amount_g: f64,
+    /// Recipes using the variety at least once
+    pub recipes: usize,
+    /// Mean alpha acid of the additions that recorded one
+    pub alpha_pct: f64,
+}
+
+/// A variety the selected recipes need more of than is on hand.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Shortfall {
+    pub name: String,
+    pub needed_g: f64,
+    pub on_hand_g: f64,
+}
+
+impl Shortfall {
+    pub fn to_buy_g(&self) -> f64 {
+        self.needed_g - self.on_hand_g
+    }
+}
+
+pub fn run(args: &HopInventoryArgs) -> Result<(), Box<dyn Error>> {
+    let paths = match &args.recipes {
+        Some(pattern) => {
+            let mut paths: Vec<PathBuf> = glob(pattern)?.flatten().collect();
+            paths.sort();
+            paths
+        }
+        None => recipe_files()?,
+    };
+    let mut recipes = Vec::new();
+    let mut unreadable = 0;
+    for path in &paths {
+        match read_recipe(path) {
+            Ok(recipe) => recipes.push(recipe),
+            Err(_) => unreadable += 1,
+        }
+    }
+    let totals = hop_totals(&recipes, args.batch_count);
+    let (unit, per_gram) = match args.units {
+        Units::Metric => ("g", 1.0),
+        Units::Imperial => ("oz", 1.0 / OZ_TO_G),
+    };
+
+    println!("Hop Inventory:");
+    println!("--------------");
+    println!(
+        "{:<32} {:>12} {:>8} {:>8}",
+        "Hop",
+        format!("Total ({})", unit),
+        "Recipes",
+        "Alpha"
+    );
+    for hop in &totals {
+        let alpha = if hop.alpha_pct == 0.0 {
+            "-".to_string()
+        } else {
+            format!("{:.1}%", hop.alpha_pct)
+        };
+        println!(
+            "{:<32} {:>12.1} {:>8} {:>8}",
+            hop.name,
+            hop.amount_g * per_gram,
+            hop.recipes,
+            alpha
+        );
+    }
+    println!();
+    println!("Varieties: {}", totals.len());
+    println!("Recipes: {}", recipes.len());
+    if args.batch_count != 1 {
+        println!("Batches of Each: {}", args.batch_count);
+    }
+    println!("Unreadable Files: {}", unreadable);
+
+    if args.export_csv {
+        let mut csv = format!("variety,amount_{},recipes,alpha_pct\n", unit);
+        for hop in &totals {
+            csv.push_str(&format!(
+                "{},{:.1},{},{:.1}\n",
+                csv_field(&hop.name),
+                hop.amount_g * per_gram,
+                hop.recipes,
+                hop.alpha_pct
+            ));
+        }
+        fs::write(INVENTORY_CSV, csv)?;
+        println!("Inventory written to {}", INVENTORY_CSV);
+    }
+
+    if let Some(path) = &args.merge {
+        let on_hand = read_on_hand(path)?;
+        let shortfalls = shopping_list(&totals, &on_hand);
+        println!();
+        println!("Shopping List:");
+        println!("--------------");
+        if shortfalls.is_empty() {
+            println!("Everything needed is on hand");
+        } else {
+            println!(
+                "{:<32} {:>12} {:>12} {:>12}",
+                "Hop",
+                format!("Need ({})", unit),
+                format!("Have ({})", unit),
+                format!("Buy ({})", unit)
+            );
+            for shortfall in &shortfalls {
+                println!(
+                    "{:<32} {:>12.1} {:>12.1} {:>12.1}",
+                    shortfall.name,
+                    shortfall.needed_g * per_gram,
+                    shortfall.on_hand_g * per_gram,
+                    shortfall.to_buy_g() * per_gram
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Hop varieties across `recipes`, with amounts multiplied by
+/// `batch_count`, most used by weight first. Varieties are matched by name,
+/// ignoring case and surrounding whitespace.
+pub fn hop_totals(recipes: &[Recipe], batch_count: u32) -> Vec<HopTotal> {
+    // key -> (total, sum and count of recorded alpha acids)
+    let mut varieties: HashMap<String, (HopTotal, f64, usize)> = HashMap::new();
+
+    for recipe in recipes {
+        let mut seen = Vec::new();
+        for hop in &recipe.hops {
+            let key = hop.name.trim().to_lowercase();
+            if key.is_empty() {
+                continue;
+            }
+            let (total, alpha_sum, alphas) = varieties.entry(key.clone()).or_insert_with(|| {
+                let total = HopTotal {
+                    name: hop.name.trim().to_string(),
+                    amount_g: 0.0,
+                    recipes: 0,
+                    alpha_pct: 0.0,
+                };
+                (total, 0.0, 0)
+            });
+            total.amount_g += hop.amount_g * batch_count as f64;
+            if hop.alpha_pct > 0.0 {
+                *alpha_sum += hop.alpha_pct;
+                *alphas += 1;
+            }
+            if !seen.contains(&key) {
+                total.recipes += 1;
+                seen.push(key);
+            }
+        }
+    }
+
+    let mut totals: Vec<HopTotal> = varieties
+        .into_values()
+        .map(|(mut total, alpha_sum, alphas)| {
+            if alphas > 0 {
+                total.alpha_pct = alpha_sum / alphas as f64;
+            }
+            total
+        })
+        .collect();
+    totals.sort_by(|a, b| {
+        b.amount_g
+            .total_cmp(&a.amount_g)
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    totals
+}
+
+/// Grams on hand of each variety, keyed by lowercased name, from a CSV with
+/// a header row naming a `variety` column and an `amount_g` or `amount_oz`
+/// column, as `--export-csv` writes. Other columns are ignored.
+pub fn read_on_hand(path: &Path) -> Result<HashMap<String, f64>, Box<dyn Error>> {
+    let text = fs::read_to_string(path)?;
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty());
+    let header = csv_record(lines.next().map_or("", |(_, line)| line));
+    let column = |name: &str| {
+        header
+            .iter()
+            .position(|h| h.trim().eq_ignore_ascii_case(name))
+    };
+    let variety = column("variety")
+        .ok_or_else(|| format!("{}: no variety column in the header", path.display()))?;
+    let (amount, per_unit) = match (column("amount_g"), column("amount_oz")) {
+        (Some(i), _) => (i, 1.0),
+        (None, Some(i)) => (i, OZ_TO_G),
+        (None, None) => {
+            return Err(format!(
+                "{}: no amount_g or amount_oz column in the header",
+                path.display()
+            )
+            .into())
+        }
+    };
+
+    let mut on_hand = HashMap::new();
+    for (n, line) in lines {
+        let record = csv_record(line);
+        let name = record.get(variety).map_or("", |s| s.trim());
+        let value = record.get(amount).map_or("", |s| s.trim());
+        let grams: f64 = value
+            .parse()
+            .map_err(|_| format!("{}:{}: invalid amount {:?}", path.display(), n + 1, value))?;
+        *on_hand.entry(name.to_lowercase()).or_insert(0.0) += grams * per_unit;
+    }
+    Ok(on_hand)
+}
+
+/// The varieties in `totals` with less on hand than needed, in the same
+/// order.
+pub fn shopping_list(totals: &[HopTotal], on_hand: &HashMap<String, f64>) -> Vec<Shortfall> {
+    totals
+        .iter()
+        .map(|hop| Shortfall {
+            name: hop.name.clone(),
+            needed_g: hop.amount_g,
+            on_hand_g: on_hand
+                .get(&hop.name.to_lowercase())
+                .copied()
+                .unwrap_or(0.0),
+        })
+        .filter(|shortfall| shortfall.to_buy_g() > 0.0)
+        .collect()
+}
+
+/// Split one CSV line into fields, undoing the quoting `csv_field` adds.
+fn csv_record(line: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                fields.last_mut().unwrap().push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(String::new()),
+            c => fields.last_mut().unwrap().push(c),
+        }
+    }
+    fields
+}
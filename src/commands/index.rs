@@ -0,0 +1,71 @@
+use crate::collection::{read_or_set_aside, recipe_files, stored_name};
+use crate::compression::bsmx_stem;
+use crate::index::{Index, IndexEntry};
+use crate::tags::Tags;
+use crate::RECIPES_DIR;
+use indicatif::{ProgressBar, ProgressStyle};
+use rayon::prelude::*;
+use std::collections::HashSet;
+use std::error::Error;
+use std::path::Path;
+
+/// Bring the index in line with the files on disk.
+pub fn run() -> Result<(), Box<dyn Error>> {
+    let index = Index::open(Path::new(RECIPES_DIR))?;
+    let paths = recipe_files()?;
+
+    let pb = ProgressBar::new(paths.len() as u64);
+    pb.set_style(
+        ProgressStyle::default_bar()
+            .template("Indexing [{bar:50.cyan/blue}] {pos}/{len} ({eta})")?
+            .progress_chars("#>-"),
+    );
+
+    // Parsing is the slow part and each file stands alone, so it's spread
+    // over every core; the index itself is written from this thread
+    let entries: Vec<Option<IndexEntry>> = paths
+        .par_iter()
+        .map(|path| {
+            let recipe = read_or_set_aside(Path::new(RECIPES_DIR), path);
+            pb.inc(1);
+            let filename = stored_name(Path::new(RECIPES_DIR), path);
+            let mut entry = IndexEntry::new(&filename, &recipe?).with_sizes(path);
+            // Files saved without a Content-Disposition name are named
+            // after their ID
+            entry.recipe_id = bsmx_stem(path).and_then(|stem| stem.parse().ok());
+            Some(entry)
+        })
+        .collect();
+
+    let tx = index.transaction()?;
+    let mut indexed = HashSet::new();
+    let mut unreadable = 0;
+    for entry in entries {
+        match entry {
+            Some(entry) => {
+                index.upsert(&entry)?;
+                indexed.insert(entry.filename);
+            }
+            None => unreadable += 1,
+        }
+    }
+
+    let mut removed = 0;
+    for filename in index.filenames()? {
+        if !indexed.contains(&filename) {
+            index.remove(&filename)?;
+            removed += 1;
+        }
+    }
+    index.sync_tags(&Tags::load(Path::new(RECIPES_DIR))?)?;
+    tx.commit()?;
+    pb.finish_and_clear();
+
+    println!(
+        "Indexed {} recipes ({} unreadable, {} stale entries removed)",
+        indexed.len(),
+        unreadable,
+        removed
+    );
+    Ok(())
+}
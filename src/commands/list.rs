@@ -0,0 +1,33 @@
+use crate::cli::ListArgs;
+use crate::index::{Filter, Index};
+use crate::tags::tag_filter;
+use crate::RECIPES_DIR;
+use std::error::Error;
+use std::path::Path;
+
+pub fn run(args: &ListArgs) -> Result<(), Box<dyn Error>> {
+    let index = Index::open(Path::new(RECIPES_DIR))?;
+    if index.count()? == 0 {
+        println!("The index is empty; run `beerscape index` to build it");
+        return Ok(());
+    }
+
+    let entries = match &args.brewer {
+        Some(brewer) => index.entries_by_brewer(brewer)?,
+        None => index.entries()?,
+    };
+    let filter = Filter {
+        tags: tag_filter(&args.tag),
+        ..Default::default()
+    };
+    let entries: Vec<_> = entries.into_iter().filter(|e| filter.matches(e)).collect();
+
+    for entry in &entries {
+        println!(
+            "{:<40} {:<28} {:>5.1}%  {}",
+            entry.name, entry.style, entry.abv, entry.filename
+        );
+    }
+    println!("{} recipes", entries.len());
+    Ok(())
+}
@@ -0,0 +1,386 @@
+use crate::bsmx::{parse_bsmx, Recipe};
+use crate::cli::{Compression, Keep, MergeArgs, StorageLayout};
+use crate::collection::{recipe_files_in, sidecar_path};
+use crate::compression::{bsmx_stem, read_bsmx};
+use crate::fingerprint::{has_ingredients, ingredient_fingerprint};
+use crate::index::{Index, IndexEntry, INDEX_FILE};
+use crate::layout::uncompressed_name;
+use crate::state::State;
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+
+/// A recipe file in one of the collections being merged.
+struct Member {
+    path: PathBuf,
+    /// SHA-256 of the BSMX, whatever the file's compression
+    hash: [u8; 32],
+    /// Ingredient fingerprint, for recipes that parse and list some
+    fingerprint: Option<[u8; 32]>,
+    recipe: Option<Recipe>,
+}
+
+/// What to do with a source file.
+enum Plan {
+    /// Copy it under this name, which nothing in the destination has
+    Copy(String),
+    /// The destination has the same bytes already
+    Duplicate,
+    /// The destination has this file under the same name or with the same
+    /// ingredients; the source's would go under the name given
+    Conflict(PathBuf, String),
+}
+
+/// What a merge did, or would do.
+#[derive(Debug, Default)]
+struct Merged {
+    copied: usize,
+    duplicates: usize,
+    kept_source: usize,
+    kept_dest: usize,
+    kept_both: usize,
+    unresolved: usize,
+}
+
+/// Copy the recipes in --source that --dest doesn't have into it, in the
+/// destination's layout. Byte-identical files are skipped. One with the
+/// same name or the same ingredients as a file already there is a
+/// near-duplicate, settled by --keep or by asking, and left alone when
+/// there's nobody to ask. The destination ends up with the state file of
+/// whichever collection is larger, retrying the IDs either had left to
+/// retry.
+pub fn run(args: &MergeArgs) -> Result<(), Box<dyn Error>> {
+    if !args.source.is_dir() {
+        return Err(format!("no recipes directory at {}", args.source.display()).into());
+    }
+    let source_state = State::load(&args.source)?;
+    let dest_state = State::load(&args.dest)?;
+    let layout = dest_state.storage_layout;
+    let sources = scan(&args.source, source_state.storage_layout)?;
+    let dests = scan(&args.dest, layout)?;
+
+    let source_index = open_index(&args.source)?;
+    let dest_index = if args.dry_run {
+        None
+    } else {
+        fs::create_dir_all(&args.dest)?;
+        Some(Index::open(&args.dest)?)
+    };
+    let interactive = args.keep.is_none() && !args.dry_run && io::stdin().is_terminal();
+    let verb = if args.dry_run { "Would copy" } else { "Copied" };
+
+    let source_hashes: HashSet<[u8; 32]> = sources.iter().map(|m| m.hash).collect();
+    let mut hashes: HashSet<[u8; 32]> = dests.iter().map(|m| m.hash).collect();
+    let mut fingerprints: HashMap<[u8; 32], &Path> = HashMap::new();
+    // A file the source has too isn't anything's near-duplicate: the
+    // source has chosen to keep both
+    for member in dests.iter().filter(|m| !source_hashes.contains(&m.hash)) {
+        if let Some(fingerprint) = member.fingerprint {
+            fingerprints.entry(fingerprint).or_insert(&member.path);
+        }
+    }
+    // Destination files that some source file is a near-duplicate of
+    let mut conflicted = HashSet::new();
+    let mut merged = Merged::default();
+    for member in &sources {
+        let plan = plan(&args.dest, layout, member, &hashes, &fingerprints)?;
+        // Only the first of several identical source files is copied
+        hashes.insert(member.hash);
+        let (existing, name) = match plan {
+            Plan::Duplicate => {
+                merged.duplicates += 1;
+                continue;
+            }
+            Plan::Copy(name) => {
+                println!("{} {} -> {}", verb, member.path.display(), name);
+                if let Some(index) = &dest_index {
+                    copy(
+                        source_index.as_ref(),
+                        &member.path,
+                        member.recipe.as_ref(),
+                        &args.dest,
+                        index,
+                        &name,
+                    )?;
+                }
+                merged.copied += 1;
+                continue;
+            }
+            Plan::Conflict(existing, name) => (existing, name),
+        };
+
+        let keep = match args.keep {
+            Some(keep) => Some(keep),
+            None if interactive => ask(&member.path, &existing)?,
+            None => None,
+        };
+        let Some(keep) = keep else {
+            println!(
+                "Conflict: {} and {}; left as they are",
+                member.path.display(),
+                existing.display()
+            );
+            merged.unresolved += 1;
+            conflicted.insert(existing);
+            continue;
+        };
+        println!(
+            "Conflict: {} and {}; keeping {}",
+            member.path.display(),
+            existing.display(),
+            match keep {
+                Keep::Source => "the source's",
+                Keep::Dest => "the destination's",
+                Keep::Both => "both",
+            }
+        );
+        match keep {
+            Keep::Dest => merged.kept_dest += 1,
+            Keep::Source => {
+                if let Some(index) = &dest_index {
+                    remove(index, &existing)?;
+                    copy(
+                        source_index.as_ref(),
+                        &member.path,
+                        member.recipe.as_ref(),
+                        &args.dest,
+                        index,
+                        &name,
+                    )?;
+                }
+                merged.kept_source += 1;
+            }
+            Keep::Both => {
+                let name = if args.dest.join(&name).exists() {
+                    alternative_name(layout, member)?
+                } else {
+                    name
+                };
+                if let Some(index) = &dest_index {
+                    copy(
+                        source_index.as_ref(),
+                        &member.path,
+                        member.recipe.as_ref(),
+                        &args.dest,
+                        index,
+                        &name,
+                    )?;
+                }
+                merged.kept_both += 1;
+            }
+        }
+        conflicted.insert(existing);
+    }
+
+    let only_in_dest = dests
+        .iter()
+        .filter(|m| !source_hashes.contains(&m.hash) && !conflicted.contains(&m.path))
+        .count();
+
+    // The larger collection has the longer history worth keeping
+    let from_source = sources.len() > dests.len();
+    if !args.dry_run {
+        let (mut state, other) = if from_source {
+            let retry_ids = dest_state.retry_ids;
+            (
+                State {
+                    storage_layout: layout,
+                    ..source_state
+                },
+                retry_ids,
+            )
+        } else {
+            (dest_state, source_state.retry_ids)
+        };
+        state.retry_ids.extend(other);
+        state.retry_ids.sort_unstable();
+        state.retry_ids.dedup();
+        state.save(&args.dest)?;
+    }
+
+    let conflicts = merged.kept_source + merged.kept_dest + merged.kept_both + merged.unresolved;
+    println!();
+    println!("Merge Summary:");
+    println!("--------------");
+    println!(
+        "{}: {}",
+        if args.dry_run { "Would Copy" } else { "Copied" },
+        merged.copied + merged.kept_source + merged.kept_both
+    );
+    println!("Skipped as Duplicates: {}", merged.duplicates);
+    println!("Conflicts: {}", conflicts);
+    if conflicts > 0 {
+        println!("  Kept Source: {}", merged.kept_source);
+        println!("  Kept Destination: {}", merged.kept_dest);
+        println!("  Kept Both: {}", merged.kept_both);
+        println!("  Unresolved: {}", merged.unresolved);
+    }
+    println!("Only in Destination: {}", only_in_dest);
+    println!(
+        "State File: {}",
+        if from_source {
+            "the source's"
+        } else {
+            "the destination's"
+        }
+    );
+    Ok(())
+}
+
+/// Hash and parse every recipe file in `dir`, on all cores. A directory
+/// that doesn't exist yet is an empty collection.
+fn scan(dir: &Path, layout: StorageLayout) -> Result<Vec<Member>, Box<dyn Error>> {
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let members: io::Result<Vec<Member>> = recipe_files_in(dir, layout)?
+        .par_iter()
+        .map(|path| {
+            let contents = read_bsmx(path)
+                .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+            let recipe = parse_bsmx(&contents).ok();
+            Ok(Member {
+                path: path.clone(),
+                hash: Sha256::digest(&contents).into(),
+                fingerprint: recipe
+                    .as_ref()
+                    .filter(|r| has_ingredients(r))
+                    .map(ingredient_fingerprint),
+                recipe,
+            })
+        })
+        .collect();
+    Ok(members?)
+}
+
+fn plan(
+    dest: &Path,
+    layout: StorageLayout,
+    member: &Member,
+    hashes: &HashSet<[u8; 32]>,
+    fingerprints: &HashMap<[u8; 32], &Path>,
+) -> Result<Plan, Box<dyn Error>> {
+    if hashes.contains(&member.hash) {
+        return Ok(Plan::Duplicate);
+    }
+    let name = dest_name(layout, member, &uncompressed_name(&member.path))?;
+    let target = dest.join(&name);
+    if target.exists() {
+        return Ok(Plan::Conflict(target, name));
+    }
+    Ok(
+        match member.fingerprint.and_then(|f| fingerprints.get(&f)) {
+            Some(existing) => Plan::Conflict(existing.to_path_buf(), name),
+            None => Plan::Copy(name),
+        },
+    )
+}
+
+/// Where the destination's layout keeps a source file downloaded as
+/// `filename`, compressed as it is now.
+fn dest_name(
+    layout: StorageLayout,
+    member: &Member,
+    filename: &str,
+) -> Result<String, Box<dyn Error>> {
+    Ok(format!(
+        "{}{}",
+        layout.path_for(filename, &read_bsmx(&member.path)?, member.recipe.as_ref()),
+        Compression::of(&member.path).extension()
+    ))
+}
+
+/// A name for the source's copy of a recipe whose name the destination's
+/// copy already has.
+fn alternative_name(layout: StorageLayout, member: &Member) -> Result<String, Box<dyn Error>> {
+    let stem = bsmx_stem(&member.path).unwrap_or_default();
+    dest_name(layout, member, &format!("{} (source).bsmx", stem))
+}
+
+pub(crate) fn open_index(dir: &Path) -> Result<Option<Index>, Box<dyn Error>> {
+    if !dir.join(INDEX_FILE).exists() {
+        return Ok(None);
+    }
+    Ok(Some(Index::open(dir)?))
+}
+
+/// Copy a source file and its sidecar into the destination as `name`, and
+/// index it there as the source's index has it.
+pub(crate) fn copy(
+    source_index: Option<&Index>,
+    path: &Path,
+    recipe: Option<&Recipe>,
+    dest: &Path,
+    index: &Index,
+    name: &str,
+) -> Result<(), Box<dyn Error>> {
+    let target = dest.join(name);
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::copy(path, &target)?;
+    // Copied after the recipe, so it's newer and still trusted
+    let sidecar = sidecar_path(path);
+    if sidecar.exists() {
+        fs::copy(&sidecar, sidecar_path(&target))?;
+    }
+
+    let known = match source_index {
+        Some(source_index) => source_index.get(&source_index.name_of(path))?,
+        None => None,
+    };
+    let entry = match (known, recipe) {
+        (Some(entry), _) => IndexEntry {
+            filename: name.to_string(),
+            ..entry
+        }
+        .with_sizes(&target),
+        (None, Some(recipe)) => {
+            let mut entry = IndexEntry::new(name, recipe).with_sizes(&target);
+            entry.recipe_id = bsmx_stem(path).and_then(|stem| stem.parse().ok());
+            entry
+        }
+        (None, None) => return Ok(()),
+    };
+    index.upsert(&entry)?;
+    Ok(())
+}
+
+/// Delete a destination file the source's copy replaces.
+fn remove(index: &Index, path: &Path) -> Result<(), Box<dyn Error>> {
+    fs::remove_file(path)?;
+    let sidecar = sidecar_path(path);
+    if sidecar.exists() {
+        fs::remove_file(sidecar)?;
+    }
+    index.remove(&index.name_of(path))?;
+    Ok(())
+}
+
+/// Ask which copy of a near-duplicate to keep; `None` leaves both as they
+/// are.
+fn ask(source: &Path, dest: &Path) -> io::Result<Option<Keep>> {
+    loop {
+        print!(
+            "{} and {} look like the same recipe. Keep [s]ource, [d]estination, [b]oth, or [l]eave them? ",
+            source.display(),
+            dest.display()
+        );
+        io::stdout().flush()?;
+        let mut line = String::new();
+        if io::stdin().read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        match line.trim().to_lowercase().as_str() {
+            "s" | "source" => return Ok(Some(Keep::Source)),
+            "d" | "dest" | "destination" => return Ok(Some(Keep::Dest)),
+            "b" | "both" => return Ok(Some(Keep::Both)),
+            "" | "l" | "leave" => return Ok(None),
+            _ => {}
+        }
+    }
+}
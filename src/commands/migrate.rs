@@ -0,0 +1,20 @@
+use crate::migrate::migrate;
+use crate::RECIPES_DIR;
+use std::error::Error;
+use std::path::Path;
+
+/// Upgrade the state files in the recipes directory to this build's
+/// schema versions.
+pub fn run() -> Result<(), Box<dyn Error>> {
+    let applied = migrate(Path::new(RECIPES_DIR))?;
+    if applied.is_empty() {
+        println!("State files are up to date");
+    }
+    for upgrade in &applied {
+        println!(
+            "Migrated {} from version {} to {}",
+            upgrade.file, upgrade.from, upgrade.to
+        );
+    }
+    Ok(())
+}
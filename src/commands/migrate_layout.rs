@@ -0,0 +1,33 @@
+use crate::cli::MigrateLayoutArgs;
+use crate::index::Index;
+use crate::layout::relayout;
+use crate::state::State;
+use crate::RECIPES_DIR;
+use std::error::Error;
+use std::path::Path;
+
+/// Move the recipes directory into another storage layout.
+pub fn run(args: &MigrateLayoutArgs) -> Result<(), Box<dyn Error>> {
+    let dir = Path::new(RECIPES_DIR);
+    if !dir.is_dir() {
+        println!("No recipes directory at {}/", RECIPES_DIR);
+        return Ok(());
+    }
+    let from = State::load(dir)?.storage_layout;
+    let result = relayout(dir, &Index::open(dir)?, args.to)?;
+
+    println!("Layout Migration:");
+    println!("-----------------");
+    println!(
+        "Layout: {} -> {}",
+        format!("{:?}", from).to_lowercase(),
+        format!("{:?}", args.to).to_lowercase()
+    );
+    println!("Moved: {}", result.moved);
+    println!("Already in Place: {}", result.in_place);
+    println!("Left Where They Were: {}", result.skipped.len());
+    for (path, reason) in &result.skipped {
+        println!("  {}: {}", path.display(), reason);
+    }
+    Ok(())
+}
@@ -0,0 +1,32 @@
+pub mod archive;
+pub mod backup;
+pub mod benchmark;
+pub mod browse;
+pub mod cluster;
+pub mod compare;
+pub mod daemon;
+pub mod db;
+pub mod deduplicate;
+pub mod discover;
+pub mod export;
+pub mod fixtures;
+pub mod fmt;
+pub mod health_check;
+pub mod hop_inventory;
+pub mod index;
+pub mod list;
+pub mod merge;
+pub mod migrate;
+pub mod migrate_layout;
+pub mod normalize;
+pub mod prune;
+pub mod random_recipe;
+pub mod recompress;
+pub mod remote;
+pub mod report;
+pub mod reports;
+pub mod serve;
+pub mod split;
+pub mod sync;
+pub mod tag;
+pub mod top_recipes;
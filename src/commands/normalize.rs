@@ -0,0 +1,42 @@
+use crate::bsmx::to_utf8_document;
+use crate::cli::NormalizeArgs;
+use crate::collection::recipe_files;
+use crate::compression::{read_bsmx, write_bsmx};
+use std::error::Error;
+
+pub fn run(args: &NormalizeArgs) -> Result<(), Box<dyn Error>> {
+    let paths = if args.paths.is_empty() {
+        recipe_files()?
+    } else {
+        args.paths.clone()
+    };
+
+    let mut rewritten = 0;
+    for path in &paths {
+        let bytes = read_bsmx(path)?;
+        let Some(utf8) = to_utf8_document(&bytes) else {
+            continue;
+        };
+
+        rewritten += 1;
+        if args.dry_run {
+            println!("Would normalize {}", path.display());
+            continue;
+        }
+
+        write_bsmx(path, &utf8)?;
+        println!("Normalized {}", path.display());
+    }
+
+    println!(
+        "{} of {} files {}",
+        rewritten,
+        paths.len(),
+        if args.dry_run {
+            "need normalizing"
+        } else {
+            "normalized"
+        }
+    );
+    Ok(())
+}
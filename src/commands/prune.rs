@@ -0,0 +1,126 @@
+use crate::bsmx::Recipe;
+use crate::cli::PruneArgs;
+use crate::collection::{load_recipes, sidecar_path};
+use crate::commands::deduplicate::{
+    exact_duplicates, fuzzy_duplicates, DuplicateKind, DEFAULT_SIMILARITY,
+};
+use crate::index::Index;
+use crate::RECIPES_DIR;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub fn run(args: &PruneArgs) -> Result<(), Box<dyn Error>> {
+    let collection = load_recipes()?;
+
+    // file -> why it's being pruned
+    let mut pruned: BTreeMap<PathBuf, Vec<&str>> = BTreeMap::new();
+    for (path, recipe) in &collection.recipes {
+        let reasons = problems(args, recipe);
+        if !reasons.is_empty() {
+            pruned.insert(path.clone(), reasons);
+        }
+    }
+
+    if args.confirmed_duplicates {
+        let mut duplicates = exact_duplicates()?;
+        duplicates.extend(fuzzy_duplicates(DEFAULT_SIMILARITY)?);
+        for pair in duplicates {
+            // Keep the first of each pair, as deduplicate --auto-remove does
+            if pair.kind == DuplicateKind::ConfirmedDuplicate && !pruned.contains_key(&pair.first) {
+                pruned.entry(pair.second).or_default().push("duplicate");
+            }
+        }
+    }
+
+    let index = Index::open(Path::new(RECIPES_DIR))?;
+    let tx = index.transaction()?;
+    let mut freed = 0;
+    for (path, reasons) in &pruned {
+        let mut reasons = reasons.clone();
+        reasons.dedup();
+        println!("{}: {}", path.display(), reasons.join(", "));
+
+        let sidecar = sidecar_path(path);
+        let files: Vec<&Path> = if sidecar.exists() {
+            vec![path, &sidecar]
+        } else {
+            vec![path]
+        };
+        for file in files {
+            freed += fs::metadata(file)?.len();
+            if args.dry_run {
+                continue;
+            }
+            match &args.move_to {
+                Some(dir) => move_file(file, dir)?,
+                None => fs::remove_file(file)?,
+            }
+        }
+        if !args.dry_run {
+            index.remove(&index.name_of(path))?;
+        }
+    }
+    tx.commit()?;
+
+    println!();
+    println!("Prune Summary:");
+    println!("--------------");
+    if args.dry_run {
+        println!("Would Prune: {}", pruned.len());
+        println!("Bytes That Would Be Freed: {}", format_bytes(freed));
+    } else {
+        match &args.move_to {
+            Some(dir) => println!("Moved to {}: {}", dir.display(), pruned.len()),
+            None => println!("Deleted: {}", pruned.len()),
+        }
+        println!("Bytes Freed: {}", format_bytes(freed));
+    }
+    println!("Unreadable Files (kept): {}", collection.unreadable);
+
+    Ok(())
+}
+
+/// The selected criteria a recipe fails.
+fn problems(args: &PruneArgs, recipe: &Recipe) -> Vec<&'static str> {
+    let mut reasons = Vec::new();
+    let no_ingredients =
+        recipe.fermentables.is_empty() && recipe.hops.is_empty() && recipe.yeasts.is_empty();
+    if args.no_ingredients && no_ingredients {
+        reasons.push("no ingredients");
+    }
+    if args.no_style && recipe.style.name.trim().is_empty() {
+        reasons.push("no style");
+    }
+    if args.no_name && recipe.name.trim().is_empty() {
+        reasons.push("no name");
+    }
+    if args.invalid_gravity && !plausible_gravity(recipe) {
+        reasons.push("invalid gravity");
+    }
+    reasons
+}
+
+// Wider than any real beer, so only missing or garbled values get caught
+fn plausible_gravity(recipe: &Recipe) -> bool {
+    (1.000..=1.200).contains(&recipe.og)
+        && (0.990..=1.100).contains(&recipe.fg)
+        && recipe.fg <= recipe.og
+}
+
+/// Move a file into `dir`, copying when a rename can't cross file systems.
+fn move_file(path: &Path, dir: &Path) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    let target = dir.join(path.file_name().unwrap_or_default());
+    if fs::rename(path, &target).is_err() {
+        fs::copy(path, &target)?;
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+fn format_bytes(bytes: u64) -> String {
+    format!("{} ({:.1} MB)", bytes, bytes as f64 / 1_000_000.0)
+}
@@ -0,0 +1,100 @@
+use crate::bsmx::Recipe;
+use crate::cli::RandomRecipeArgs;
+use crate::collection::{indexed_files_matching, read_recipe, recipe_files};
+use crate::RECIPES_DIR;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use std::error::Error;
+use std::path::Path;
+use tracing::warn;
+
+/// Pick --count different recipes at random, every file (or every one the
+/// filters match) as likely as any other, and print each one's summary.
+pub fn run(args: &RandomRecipeArgs) -> Result<(), Box<dyn Error>> {
+    let filter = args.filter.to_filter();
+    // Both come sorted, so a seed always picks from the same order
+    let candidates = if filter.is_empty() {
+        recipe_files()?
+    } else {
+        indexed_files_matching(Path::new(RECIPES_DIR), &filter)?
+    };
+    if candidates.is_empty() {
+        println!("No recipes to pick from");
+        return Ok(());
+    }
+
+    let mut rng = match args.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+    for (n, path) in candidates.choose_multiple(&mut rng, args.count).enumerate() {
+        if n > 0 {
+            println!();
+        }
+        match read_recipe(path) {
+            Ok(recipe) => print_recipe(&recipe, &path.display().to_string()),
+            Err(e) => println!("{}: unreadable: {}", path.display(), e),
+        }
+        if args.open {
+            if let Err(e) = open::that_detached(path) {
+                warn!(error = %e, "could not open {}", path.display());
+            }
+        }
+    }
+    Ok(())
+}
+
+fn print_recipe(recipe: &Recipe, path: &str) {
+    let title = if recipe.name.is_empty() {
+        path.to_string()
+    } else {
+        format!("{} ({})", recipe.name, path)
+    };
+    println!("{}", title);
+    println!("{}", "-".repeat(title.chars().count()));
+    let style = &recipe.style;
+    if !style.name.is_empty() {
+        println!("Style: {} ({}{})", style.name, style.number, style.letter);
+    }
+    if !recipe.brewer.is_empty() {
+        println!("Brewer: {}", recipe.brewer);
+    }
+    println!(
+        "OG {:.3}  FG {:.3}  ABV {:.1}%  IBU {:.0}  SRM {:.1}",
+        recipe.og, recipe.fg, recipe.abv, recipe.ibu, recipe.color_srm
+    );
+    println!(
+        "Batch {:.1} L, boiled {:.0} min",
+        recipe.batch_size_l, recipe.boil_time_min
+    );
+
+    if !recipe.fermentables.is_empty() {
+        println!("Fermentables:");
+    }
+    for fermentable in &recipe.fermentables {
+        println!(
+            "  {:>6.2} kg  {} ({:.0} SRM)",
+            fermentable.amount_kg, fermentable.name, fermentable.color_srm
+        );
+    }
+    if !recipe.hops.is_empty() {
+        println!("Hops:");
+    }
+    for hop in &recipe.hops {
+        println!(
+            "  {:>6.1} g   {} ({:.1}% AA) at {:.0} min",
+            hop.amount_g, hop.name, hop.alpha_pct, hop.boil_time_min
+        );
+    }
+    if !recipe.yeasts.is_empty() {
+        println!("Yeast:");
+    }
+    for yeast in &recipe.yeasts {
+        match (yeast.lab.is_empty(), yeast.product_id.is_empty()) {
+            (false, false) => println!("  {} ({} {})", yeast.name, yeast.lab, yeast.product_id),
+            (false, true) => println!("  {} ({})", yeast.name, yeast.lab),
+            _ => println!("  {}", yeast.name),
+        }
+    }
+}
@@ -0,0 +1,54 @@
+use crate::cli::{CompressArgs, Compression, RecompressArgs};
+use crate::compression::recompress;
+use crate::index::Index;
+use crate::progress::format_bytes;
+use crate::state::State;
+use crate::RECIPES_DIR;
+use std::error::Error;
+use std::path::Path;
+
+/// Rewrite the recipes directory's files with another compression.
+pub fn run(args: &RecompressArgs) -> Result<(), Box<dyn Error>> {
+    convert(args.to, args.level)
+}
+
+/// Store every recipe as `.bsmx.zst`.
+pub fn compress(args: &CompressArgs) -> Result<(), Box<dyn Error>> {
+    convert(Compression::Zstd, args.level)
+}
+
+/// Store every recipe as plain `.bsmx`.
+pub fn decompress() -> Result<(), Box<dyn Error>> {
+    convert(Compression::None, None)
+}
+
+fn convert(to: Compression, level: Option<i32>) -> Result<(), Box<dyn Error>> {
+    let dir = Path::new(RECIPES_DIR);
+    if !dir.is_dir() {
+        println!("No recipes directory at {}/", RECIPES_DIR);
+        return Ok(());
+    }
+    let layout = State::load(dir)?.storage_layout;
+    let result = recompress(dir, layout, &Index::open(dir)?, to, level)?;
+
+    println!("Recompression:");
+    println!("--------------");
+    println!("Compression: {}", format!("{:?}", to).to_lowercase());
+    if let Some(level) = level {
+        println!("Level: {}", level);
+    }
+    println!("Rewritten: {}", result.rewritten);
+    println!("Already Stored That Way: {}", result.unchanged);
+    if result.rewritten > 0 {
+        println!(
+            "Size of Rewritten Files: {} -> {}",
+            format_bytes(result.bytes_before, false),
+            format_bytes(result.bytes_after, false)
+        );
+    }
+    println!("Left as They Were: {}", result.skipped.len());
+    for (path, reason) in &result.skipped {
+        println!("  {}: {}", path.display(), reason);
+    }
+    Ok(())
+}
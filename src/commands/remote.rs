@@ -0,0 +1,338 @@
+use crate::archive::{Manifest, ManifestEntry, MANIFEST_FILE};
+use crate::cli::RemoteArgs;
+use crate::collection::{collection_files, stored_name, tmp_path, COLLECTION_FILES};
+use crate::progress::format_bytes;
+use crate::remote::S3Client;
+use crate::RECIPES_DIR;
+use chrono::Utc;
+use indicatif::{ProgressBar, ProgressStyle};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Component, Path};
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+/// Upload every file whose contents differ from what the remote manifest
+/// records, then the manifest itself.
+pub async fn push(args: &RemoteArgs) -> Result<(), Box<dyn Error>> {
+    let dir = Path::new(RECIPES_DIR);
+    if !dir.is_dir() {
+        println!("No recipes directory at {}/", RECIPES_DIR);
+        return Ok(());
+    }
+    let client = S3Client::new(args)?;
+    let remote = remote_manifest(&client).await?;
+    let remote_files = by_path(remote.as_ref());
+
+    let local = local_manifest(dir)?;
+    let changed: Vec<&ManifestEntry> = local
+        .files
+        .iter()
+        .filter(|entry| remote_files.get(entry.path.as_str()) != Some(&entry.sha256.as_str()))
+        .collect();
+    // The collection's own files go up after the recipes, so a remote never
+    // lists recipes it doesn't have
+    let (recipes, collection): (Vec<&ManifestEntry>, Vec<&ManifestEntry>) = changed
+        .iter()
+        .copied()
+        .partition(|entry| !COLLECTION_FILES.contains(&entry.path.as_str()));
+
+    if args.dry_run {
+        print_plan("Would Upload", &changed, local.files.len());
+        return Ok(());
+    }
+
+    let mut uploaded = Vec::new();
+    let mut failed = Vec::new();
+    for group in [recipes, collection] {
+        let names: Vec<String> = group.iter().map(|entry| entry.path.clone()).collect();
+        let pb = transfer_bar("Uploading", names.len());
+        let (done, errors) = transfer(&client, names, args.jobs, &pb, |client, name| async move {
+            let contents = fs::read(Path::new(RECIPES_DIR).join(&name))?;
+            client.put(&name, contents).await
+        })
+        .await;
+        pb.finish_and_clear();
+        uploaded.extend(done);
+        failed.extend(errors);
+    }
+
+    // What the remote holds now: whatever it had, with each file that got
+    // there replaced by this copy
+    let mut files: HashMap<String, ManifestEntry> = remote
+        .map(|manifest| manifest.files)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|entry| (entry.path.clone(), entry))
+        .collect();
+    for entry in &local.files {
+        if !failed.iter().any(|(name, _)| name == &entry.path) {
+            files.insert(entry.path.clone(), entry.clone());
+        }
+    }
+    let mut files: Vec<ManifestEntry> = files.into_values().collect();
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    let manifest = Manifest {
+        created_at: Utc::now(),
+        since: None,
+        files,
+    };
+    client
+        .put(MANIFEST_FILE, serde_json::to_vec_pretty(&manifest)?)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let bytes = local
+        .files
+        .iter()
+        .filter(|entry| uploaded.contains(&entry.path))
+        .map(|entry| entry.bytes)
+        .sum();
+    print_summary(
+        "Push",
+        &client,
+        local.files.len(),
+        uploaded.len(),
+        bytes,
+        &failed,
+    );
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("{} files could not be uploaded", failed.len()).into())
+    }
+}
+
+/// Download every file in the remote manifest that's missing here or
+/// differs, checking each against its checksum before it replaces
+/// anything.
+pub async fn pull(args: &RemoteArgs) -> Result<(), Box<dyn Error>> {
+    let dir = Path::new(RECIPES_DIR);
+    let client = S3Client::new(args)?;
+    let Some(remote) = remote_manifest(&client).await? else {
+        return Err(format!(
+            "{} has no {}; push a collection there first",
+            client.location(),
+            MANIFEST_FILE
+        )
+        .into());
+    };
+    for entry in &remote.files {
+        if !is_safe_path(&entry.path) {
+            return Err(format!("the remote manifest lists an unsafe path: {}", entry.path).into());
+        }
+    }
+
+    let changed: Vec<&ManifestEntry> = remote
+        .files
+        .iter()
+        .filter(|entry| {
+            fs::read(dir.join(&entry.path))
+                .map_or(true, |contents| sha256(&contents) != entry.sha256)
+        })
+        .collect();
+
+    if args.dry_run {
+        print_plan("Would Download", &changed, remote.files.len());
+        return Ok(());
+    }
+
+    fs::create_dir_all(dir)?;
+    let expected: Arc<HashMap<String, String>> = Arc::new(
+        changed
+            .iter()
+            .map(|entry| (entry.path.clone(), entry.sha256.clone()))
+            .collect(),
+    );
+    let names: Vec<String> = changed.iter().map(|entry| entry.path.clone()).collect();
+    let pb = transfer_bar("Downloading", names.len());
+    let (downloaded, failed) = transfer(&client, names, args.jobs, &pb, |client, name| {
+        let expected = expected.clone();
+        async move {
+            let contents = client
+                .get(&name)
+                .await?
+                .ok_or("listed in the manifest but not on the remote")?;
+            if sha256(&contents) != expected[&name] {
+                return Err("checksum doesn't match the manifest".into());
+            }
+            let path = Path::new(RECIPES_DIR).join(&name);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let tmp = tmp_path(&path);
+            fs::write(&tmp, &contents)?;
+            fs::rename(&tmp, &path)?;
+            Ok(())
+        }
+    })
+    .await;
+    pb.finish_and_clear();
+
+    let bytes = changed
+        .iter()
+        .filter(|entry| downloaded.contains(&entry.path))
+        .map(|entry| entry.bytes)
+        .sum();
+    print_summary(
+        "Pull",
+        &client,
+        remote.files.len(),
+        downloaded.len(),
+        bytes,
+        &failed,
+    );
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("{} files could not be downloaded", failed.len()).into())
+    }
+}
+
+async fn remote_manifest(client: &S3Client) -> Result<Option<Manifest>, Box<dyn Error>> {
+    match client.get(MANIFEST_FILE).await.map_err(|e| e.to_string())? {
+        Some(body) => Ok(Some(serde_json::from_slice(&body).map_err(|e| {
+            format!(
+                "{} at {} is unreadable: {}",
+                MANIFEST_FILE,
+                client.location(),
+                e
+            )
+        })?)),
+        None => Ok(None),
+    }
+}
+
+fn by_path(manifest: Option<&Manifest>) -> HashMap<&str, &str> {
+    manifest
+        .map(|manifest| {
+            manifest
+                .files
+                .iter()
+                .map(|entry| (entry.path.as_str(), entry.sha256.as_str()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Every recipe with its sidecar, then the collection's own files, named
+/// as they are under the recipes directory.
+fn local_manifest(dir: &Path) -> Result<Manifest, Box<dyn Error>> {
+    let mut files = Vec::new();
+    for path in collection_files(dir)? {
+        let contents = fs::read(&path)?;
+        files.push(ManifestEntry {
+            path: stored_name(dir, &path),
+            bytes: contents.len() as u64,
+            sha256: sha256(&contents),
+        });
+    }
+    Ok(Manifest {
+        created_at: Utc::now(),
+        since: None,
+        files,
+    })
+}
+
+/// Run `job` on each name, up to `jobs` at a time, returning the names it
+/// worked for and the errors of those it didn't.
+async fn transfer<F, Fut>(
+    client: &S3Client,
+    names: Vec<String>,
+    jobs: usize,
+    pb: &ProgressBar,
+    job: F,
+) -> (Vec<String>, Vec<(String, String)>)
+where
+    F: Fn(S3Client, String) -> Fut,
+    Fut: std::future::Future<Output = Result<(), Box<dyn Error + Send + Sync>>> + Send + 'static,
+{
+    let slots = Arc::new(Semaphore::new(jobs.max(1)));
+    let mut tasks = JoinSet::new();
+    for name in names {
+        let slots = slots.clone();
+        let work = job(client.clone(), name.clone());
+        tasks.spawn(async move {
+            let _slot = slots.acquire_owned().await;
+            (name, work.await.map_err(|e| e.to_string()))
+        });
+    }
+    let mut done = Vec::new();
+    let mut failed = Vec::new();
+    while let Some(joined) = tasks.join_next().await {
+        match joined {
+            Ok((name, Ok(()))) => done.push(name),
+            Ok((name, Err(e))) => failed.push((name, e)),
+            Err(e) => failed.push((String::new(), e.to_string())),
+        }
+        pb.inc(1);
+    }
+    failed.sort();
+    (done, failed)
+}
+
+fn transfer_bar(action: &str, len: usize) -> ProgressBar {
+    let pb = ProgressBar::new(len as u64);
+    if let Ok(style) = ProgressStyle::default_bar().template(&format!(
+        "{} [{{bar:50.cyan/blue}}] {{pos}}/{{len}} ({{eta}})",
+        action
+    )) {
+        pb.set_style(style.progress_chars("#>-"));
+    }
+    pb
+}
+
+/// Whether a manifest path stays inside the recipes directory.
+fn is_safe_path(path: &str) -> bool {
+    !path.is_empty()
+        && Path::new(path)
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+}
+
+fn sha256(contents: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(contents))
+}
+
+fn print_plan(heading: &str, changed: &[&ManifestEntry], total: usize) {
+    for entry in changed {
+        println!("{} ({})", entry.path, format_bytes(entry.bytes, false));
+    }
+    let bytes = changed.iter().map(|entry| entry.bytes).sum();
+    println!(
+        "{}: {} of {} files, {}",
+        heading,
+        changed.len(),
+        total,
+        format_bytes(bytes, false)
+    );
+}
+
+fn print_summary(
+    title: &str,
+    client: &S3Client,
+    total: usize,
+    transferred: usize,
+    bytes: u64,
+    failed: &[(String, String)],
+) {
+    println!("{}:", title);
+    println!("{}", "-".repeat(title.len() + 1));
+    println!("Remote: {}", client.location());
+    println!("Files: {}", total);
+    println!(
+        "Transferred: {} ({})",
+        transferred,
+        format_bytes(bytes, false)
+    );
+    println!("Already Up to Date: {}", total - transferred - failed.len());
+    if !failed.is_empty() {
+        println!("Failed: {}", failed.len());
+        for (name, error) in failed {
+            println!("  {}: {}", name, error);
+        }
+    }
+}
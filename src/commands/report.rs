@@ -0,0 +1,504 @@
+use crate::brewer;
+use crate::bsmx::{BsmxVersion, Recipe, TRUNCATED};
+use crate::cli::{ReportFormat, StyleFitArgs, YeastReportArgs};
+use crate::collection::load_recipes;
+use crate::color::{paint, recipe_srm};
+use crate::completeness::{completeness_score, score_distribution};
+use crate::index::{Index, IndexEntry};
+use crate::styles::{find_guideline, Range, StyleGuideline, BJCP_2021};
+use crate::RECIPES_DIR;
+use serde::Serialize;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::error::Error;
+use std::io::{self, IsTerminal};
+use std::path::Path;
+
+const NO_BREWER: &str = "(no brewer)";
+
+/// Width of the SRM histogram's bins, and how many of them there are
+/// before the last takes in everything darker.
+const SRM_BIN: f64 = 2.0;
+const SRM_BINS: usize = 20;
+const HISTOGRAM_WIDTH: usize = 40;
+
+pub fn style_fit(args: &StyleFitArgs) -> Result<(), Box<dyn Error>> {
+    let collection = load_recipes()?;
+
+    // category -> (in style, out of style)
+    let mut by_category: HashMap<&str, (usize, usize)> = HashMap::new();
+    let mut unrecognized = 0;
+
+    for (path, recipe) in &collection.recipes {
+        let Some(guideline) = find_guideline(&recipe.style) else {
+            unrecognized += 1;
+            continue;
+        };
+
+        let problems = out_of_range(recipe, guideline);
+        let counts = by_category.entry(guideline.category).or_default();
+        if problems.is_empty() {
+            counts.0 += 1;
+        } else {
+            counts.1 += 1;
+        }
+
+        if args.details {
+            let name = if recipe.name.is_empty() {
+                path.display().to_string()
+            } else {
+                recipe.name.clone()
+            };
+            println!("{} [{} {}]", name, guideline.code, guideline.name);
+            if problems.is_empty() {
+                println!("  in style");
+            }
+            for problem in &problems {
+                println!("  {}", problem);
+            }
+        }
+    }
+
+    if args.details {
+        println!();
+    }
+    println!("Style Fit Summary:");
+    println!("------------------");
+    println!(
+        "{:<32} {:>8} {:>12}",
+        "Category", "In Style", "Out of Style"
+    );
+
+    // Keep the guideline's category order rather than sorting by name
+    let mut categories: Vec<&str> = BJCP_2021.iter().map(|g| g.category).collect();
+    categories.dedup();
+    for category in categories {
+        if let Some((in_style, out_of_style)) = by_category.get(category) {
+            println!("{:<32} {:>8} {:>12}", category, in_style, out_of_style);
+        }
+    }
+
+    println!();
+    println!("Unrecognized Style: {}", unrecognized);
+    println!("Unreadable Files: {}", collection.unreadable);
+
+    Ok(())
+}
+
+pub fn completeness() -> Result<(), Box<dyn Error>> {
+    let collection = load_recipes()?;
+    let recipes = collection.recipes.iter().map(|(_, recipe)| recipe);
+    let counts = score_distribution(recipes.clone());
+    let total = collection.recipes.len();
+
+    println!("Completeness:");
+    println!("-------------");
+    println!("{:>5} {:>8} {:>7}", "Score", "Recipes", "Share");
+    for (tenths, &count) in counts.iter().enumerate().rev() {
+        let share = if total == 0 {
+            0.0
+        } else {
+            count as f64 / total as f64 * 100.0
+        };
+        println!("{:>5.1} {:>8} {:>6.1}%", tenths as f64 / 10.0, count, share);
+    }
+
+    println!();
+    println!("Recipes: {}", total);
+    if total > 0 {
+        let mean = recipes.map(completeness_score).sum::<f64>() / total as f64;
+        println!("Mean Score: {:.2}", mean);
+    }
+    println!("Unreadable Files: {}", collection.unreadable);
+
+    Ok(())
+}
+
+pub fn notes() -> Result<(), Box<dyn Error>> {
+    let collection = load_recipes()?;
+    let total = collection.recipes.len();
+    let mut with_notes = 0;
+    let mut with_taste_notes = 0;
+    let mut with_either = 0;
+    let mut truncated = 0;
+    // Characters of both kinds together, over the recipes with either
+    let mut length = 0;
+    for (_, recipe) in &collection.recipes {
+        let kinds = [&recipe.notes, &recipe.taste_notes];
+        if kinds.iter().all(|notes| notes.is_none()) {
+            continue;
+        }
+        with_either += 1;
+        with_notes += usize::from(recipe.notes.is_some());
+        with_taste_notes += usize::from(recipe.taste_notes.is_some());
+        for notes in kinds.into_iter().flatten() {
+            length += notes.chars().count();
+            truncated += usize::from(notes.ends_with(TRUNCATED));
+        }
+    }
+    let share = |count: usize| {
+        if total == 0 {
+            0.0
+        } else {
+            count as f64 / total as f64 * 100.0
+        }
+    };
+
+    println!("Notes:");
+    println!("------");
+    println!("Recipes: {}", total);
+    println!(
+        "With Any Notes: {} ({:.1}%)",
+        with_either,
+        share(with_either)
+    );
+    println!("With Notes: {} ({:.1}%)", with_notes, share(with_notes));
+    println!(
+        "With Tasting Notes: {} ({:.1}%)",
+        with_taste_notes,
+        share(with_taste_notes)
+    );
+    if with_either > 0 {
+        println!(
+            "Average Notes Length: {:.0} characters",
+            length as f64 / with_either as f64
+        );
+    }
+    println!("Truncated: {}", truncated);
+    println!("Unreadable Files: {}", collection.unreadable);
+
+    Ok(())
+}
+
+pub fn versions() -> Result<(), Box<dyn Error>> {
+    let collection = load_recipes()?;
+    let mut counts: BTreeMap<BsmxVersion, usize> = BTreeMap::new();
+    for (_, recipe) in &collection.recipes {
+        *counts.entry(recipe.bsmx_version).or_default() += 1;
+    }
+    let total = collection.recipes.len();
+
+    println!("Versions:");
+    println!("---------");
+    println!("{:<12} {:>8} {:>7}", "Version", "Recipes", "Share");
+    for (version, &count) in &counts {
+        let share = count as f64 / total as f64 * 100.0;
+        println!("{:<12} {:>8} {:>6.1}%", version.to_string(), count, share);
+    }
+
+    println!();
+    println!("Recipes: {}", total);
+    println!("Unreadable Files: {}", collection.unreadable);
+
+    Ok(())
+}
+
+pub fn color() -> Result<(), Box<dyn Error>> {
+    let collection = load_recipes()?;
+    let mut counts = [0usize; SRM_BINS + 1];
+    let mut unknown = 0;
+    for (_, recipe) in &collection.recipes {
+        let srm = recipe_srm(recipe);
+        if srm <= 0.0 {
+            unknown += 1;
+            continue;
+        }
+        counts[((srm / SRM_BIN) as usize).min(SRM_BINS)] += 1;
+    }
+    let most = counts.iter().copied().max().unwrap_or(0).max(1);
+    // Colour each bar like the beer in it where the terminal can show it
+    let colored = io::stdout().is_terminal();
+
+    println!("Color:");
+    println!("------");
+    println!("{:>7} {:>8}", "SRM", "Recipes");
+    for (bin, &count) in counts.iter().enumerate() {
+        let low = bin as f64 * SRM_BIN;
+        let label = if bin == SRM_BINS {
+            format!("{:.0}+", low)
+        } else {
+            format!("{:.0}-{:.0}", low, low + SRM_BIN)
+        };
+        let bar = "█".repeat((count * HISTOGRAM_WIDTH).div_ceil(most));
+        let bar = if colored {
+            paint(&bar, low + SRM_BIN / 2.0)
+        } else {
+            bar
+        };
+        println!("{:>7} {:>8} {}", label, count, bar);
+    }
+
+    println!();
+    println!("Recipes: {}", collection.recipes.len());
+    println!("No Color: {}", unknown);
+    println!("Unreadable Files: {}", collection.unreadable);
+
+    Ok(())
+}
+
+/// Describe each vital statistic that falls outside the style's range.
+/// Values of zero are treated as not recorded and skipped.
+fn out_of_range(recipe: &Recipe, guideline: &StyleGuideline) -> Vec<String> {
+    let checks = [
+        ("OG", recipe.og, guideline.og, 3),
+        ("FG", recipe.fg, guideline.fg, 3),
+        ("IBU", recipe.ibu, guideline.ibu, 0),
+        ("SRM", recipe.color_srm, guideline.srm, 1),
+        ("ABV", recipe.abv, guideline.abv, 1),
+    ];
+
+    checks
+        .iter()
+        .filter(|(_, value, range, _)| *value != 0.0 && !range.contains(*value))
+        .map(|(label, value, Range { min, max }, precision)| {
+            format!(
+                "{} {:.p$} (expected {:.p$}-{:.p$})",
+                label,
+                value,
+                min,
+                max,
+                p = *precision
+            )
+        })
+        .collect()
+}
+
+pub fn authors() -> Result<(), Box<dyn Error>> {
+    let index = Index::open(Path::new(RECIPES_DIR))?;
+    let entries = index.entries()?;
+    if entries.is_empty() {
+        println!("The index is empty; run `beerscape index` to build it");
+        return Ok(());
+    }
+
+    // brewer key -> recipes, with "Unknown", "-" and the like kept apart
+    let mut by_brewer: HashMap<String, Vec<&IndexEntry>> = HashMap::new();
+    let mut anonymous = Vec::new();
+    for entry in &entries {
+        match brewer::key(&entry.brewer) {
+            Some(key) => by_brewer.entry(key).or_default().push(entry),
+            None => anonymous.push(entry),
+        }
+    }
+
+    let mut authors: Vec<(String, Vec<&IndexEntry>)> = by_brewer
+        .into_values()
+        .map(|recipes| (display_name(&recipes), recipes))
+        .collect();
+    authors.sort_by(|a, b| b.1.len().cmp(&a.1.len()).then_with(|| a.0.cmp(&b.0)));
+
+    println!("Authors:");
+    println!("--------");
+    println!(
+        "{:<32} {:>7} {:>7} {:>5} {:>4} {:>5}",
+        "Brewer", "Recipes", "Avg OG", "ABV", "IBU", "SRM"
+    );
+    for (name, recipes) in &authors {
+        print_author(name, recipes);
+    }
+    if !anonymous.is_empty() {
+        print_author(NO_BREWER, &anonymous);
+    }
+
+    println!();
+    println!("Brewers: {}", authors.len());
+    println!("Recipes Without a Brewer: {}", anonymous.len());
+
+    Ok(())
+}
+
+/// The most common spelling of a brewer's name, preferring the first seen
+/// on a tie.
+fn display_name(recipes: &[&IndexEntry]) -> String {
+    let mut counts: Vec<(&str, usize)> = Vec::new();
+    for entry in recipes {
+        match counts.iter_mut().find(|(name, _)| *name == entry.brewer) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((&entry.brewer, 1)),
+        }
+    }
+    let mut best = counts[0];
+    for candidate in counts {
+        if candidate.1 > best.1 {
+            best = candidate;
+        }
+    }
+    best.0.to_string()
+}
+
+fn print_author(name: &str, recipes: &[&IndexEntry]) {
+    println!(
+        "{:<32} {:>7} {:>7} {:>5} {:>4} {:>5}",
+        name,
+        recipes.len(),
+        average(recipes, |e| e.og, 3),
+        average(recipes, |e| e.abv, 1),
+        average(recipes, |e| e.ibu, 0),
+        average(recipes, |e| e.color_srm, 1)
+    );
+}
+
+/// Mean of a statistic over the recipes that record it (non-zero), or "-"
+/// if none do.
+fn average(
+    recipes: &[&IndexEntry],
+    value: impl Fn(&IndexEntry) -> f64,
+    precision: usize,
+) -> String {
+    let values: Vec<f64> = recipes
+        .iter()
+        .map(|e| value(e))
+        .filter(|v| *v != 0.0)
+        .collect();
+    if values.is_empty() {
+        return "-".to_string();
+    }
+    format!(
+        "{:.p$}",
+        values.iter().sum::<f64>() / values.len() as f64,
+        p = precision
+    )
+}
+
+/// How many recipes use one yeast strain, with its typical figures.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct YeastUsage {
+    pub name: String,
+    pub lab: String,
+    pub product_id: String,
+    /// Means over the recipes that record each figure, or 0 if none do
+    pub attenuation_pct: f64,
+    pub min_temp_c: f64,
+    pub max_temp_c: f64,
+    pub recipes: usize,
+}
+
+pub fn yeast(args: &YeastReportArgs) -> Result<(), Box<dyn Error>> {
+    let collection = load_recipes()?;
+    let recipes: Vec<Recipe> = collection.recipes.into_iter().map(|(_, r)| r).collect();
+    let usage = yeast_usage(&recipes, args.lab.as_deref());
+
+    match args.format {
+        ReportFormat::Json => println!("{}", serde_json::to_string_pretty(&usage)?),
+        ReportFormat::Csv => {
+            println!("name,lab,product_id,attenuation_pct,min_temp_c,max_temp_c,recipes");
+            for strain in &usage {
+                println!(
+                    "{},{},{},{:.1},{:.1},{:.1},{}",
+                    csv_field(&strain.name),
+                    csv_field(&strain.lab),
+                    csv_field(&strain.product_id),
+                    strain.attenuation_pct,
+                    strain.min_temp_c,
+                    strain.max_temp_c,
+                    strain.recipes
+                );
+            }
+        }
+        ReportFormat::Table => {
+            println!("Yeast Usage:");
+            println!("------------");
+            println!(
+                "{:<32} {:<16} {:<10} {:>6} {:>11} {:>7}",
+                "Yeast", "Lab", "Product", "Atten.", "Temp (C)", "Recipes"
+            );
+            for strain in &usage {
+                let attenuation = if strain.attenuation_pct == 0.0 {
+                    "-".to_string()
+                } else {
+                    format!("{:.0}%", strain.attenuation_pct)
+                };
+                let temp = if strain.max_temp_c == 0.0 {
+                    "-".to_string()
+                } else {
+                    format!("{:.0}-{:.0}", strain.min_temp_c, strain.max_temp_c)
+                };
+                println!(
+                    "{:<32} {:<16} {:<10} {:>6} {:>11} {:>7}",
+                    strain.name, strain.lab, strain.product_id, attenuation, temp, strain.recipes
+                );
+            }
+            println!();
+            println!("Strains: {}", usage.len());
+            println!("Unreadable Files: {}", collection.unreadable);
+        }
+    }
+    Ok(())
+}
+
+/// Yeast strains across `recipes`, most used first. Strains are told apart
+/// by lab and product ID, or by name when there's no product ID, ignoring
+/// case; a recipe pitching the same strain twice counts once. `lab` keeps
+/// only labs whose name contains it, ignoring case.
+pub fn yeast_usage(recipes: &[Recipe], lab: Option<&str>) -> Vec<YeastUsage> {
+    let lab = lab.map(str::to_lowercase);
+    // key -> (first spelling seen, recipes, sums and counts of recorded
+    // attenuation, min and max temperature)
+    let mut strains: HashMap<String, (YeastUsage, [(f64, usize); 3])> = HashMap::new();
+
+    for recipe in recipes {
+        let mut seen = HashSet::new();
+        for yeast in &recipe.yeasts {
+            if let Some(lab) = &lab {
+                if !yeast.lab.to_lowercase().contains(lab.as_str()) {
+                    continue;
+                }
+            }
+            let key = if yeast.product_id.trim().is_empty() {
+                yeast.name.trim().to_lowercase()
+            } else {
+                format!(
+                    "{}\u{0}{}",
+                    yeast.lab.trim().to_lowercase(),
+                    yeast.product_id.trim().to_lowercase()
+                )
+            };
+            if key.is_empty() || !seen.insert(key.clone()) {
+                continue;
+            }
+
+            let (usage, sums) = strains.entry(key).or_insert_with(|| {
+                let usage = YeastUsage {
+                    name: yeast.name.trim().to_string(),
+                    lab: yeast.lab.trim().to_string(),
+                    product_id: yeast.product_id.trim().to_string(),
+                    attenuation_pct: 0.0,
+                    min_temp_c: 0.0,
+                    max_temp_c: 0.0,
+                    recipes: 0,
+                };
+                (usage, [(0.0, 0); 3])
+            });
+            usage.recipes += 1;
+            let values = [yeast.attenuation_pct, yeast.min_temp_c, yeast.max_temp_c];
+            for ((sum, count), value) in sums.iter_mut().zip(values) {
+                if value != 0.0 {
+                    *sum += value;
+                    *count += 1;
+                }
+            }
+        }
+    }
+
+    let mean = |(sum, count): (f64, usize)| if count == 0 { 0.0 } else { sum / count as f64 };
+    let mut usage: Vec<YeastUsage> = strains
+        .into_values()
+        .map(|(mut usage, [attenuation, min_temp, max_temp])| {
+            usage.attenuation_pct = mean(attenuation);
+            usage.min_temp_c = mean(min_temp);
+            usage.max_temp_c = mean(max_temp);
+            usage
+        })
+        .collect();
+    usage.sort_by(|a, b| b.recipes.cmp(&a.recipes).then_with(|| a.name.cmp(&b.name)));
+    usage
+}
+
+/// Quote a CSV field if it holds a comma, quote or line break.
+pub(crate) fn csv_field(value: &str) -> Cow<'_, str> {
+    if value.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", value.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(value)
+    }
+}
@@ -0,0 +1,85 @@
+use crate::cli::ReportsArgs;
+use crate::run_report::{RunReport, REPORTS_DIR};
+use glob::glob;
+use std::error::Error;
+use tracing::warn;
+
+const BAR_WIDTH: usize = 20;
+
+pub fn run(args: &ReportsArgs) -> Result<(), Box<dyn Error>> {
+    let paths = if args.paths.is_empty() {
+        glob(&format!("{}/*.json", REPORTS_DIR))?
+            .flatten()
+            .collect()
+    } else {
+        args.paths.clone()
+    };
+
+    let mut reports = Vec::new();
+    for path in &paths {
+        match RunReport::load(path) {
+            Ok(report) => reports.push(report),
+            Err(e) => warn!(file = %path.display(), error = %e, "skipping"),
+        }
+    }
+    if reports.is_empty() {
+        println!("No run reports found; run with --report to record one");
+        return Ok(());
+    }
+    reports.sort_by_key(|r| r.started_at);
+
+    println!("Download Runs:");
+    println!("--------------");
+    println!(
+        "{:<17} {:>8} {:>10} {:>9} {:>8}",
+        "Started", "Duration", "Downloaded", "Attempted", "Success"
+    );
+    for report in &reports {
+        let minutes = (report.finished_at - report.started_at).num_minutes();
+        let rate = report.success_rate();
+        println!(
+            "{:<17} {:>5}:{:02} {:>10} {:>9} {:>8} {}",
+            report.started_at.format("%Y-%m-%d %H:%M"),
+            minutes / 60,
+            minutes % 60,
+            report.downloaded,
+            report.attempted,
+            rate.map_or("-".to_string(), |r| format!("{:.1}%", r * 100.0)),
+            "#".repeat((rate.unwrap_or(0.0) * BAR_WIDTH as f64).round() as usize)
+        );
+    }
+
+    if let Some(slope) = trend(&reports) {
+        println!();
+        println!("Success rate trend: {:+.2} points per day", slope * 100.0);
+    }
+
+    Ok(())
+}
+
+/// Least-squares slope of success rate against time in days, across the
+/// runs that attempted anything.
+fn trend(reports: &[RunReport]) -> Option<f64> {
+    let first = reports.first()?.started_at;
+    let points: Vec<(f64, f64)> = reports
+        .iter()
+        .filter_map(|r| {
+            let days = (r.started_at - first).num_seconds() as f64 / 86_400.0;
+            r.success_rate().map(|rate| (days, rate))
+        })
+        .collect();
+    if points.len() < 2 {
+        return None;
+    }
+
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|p| p.0).sum::<f64>() / n;
+    let mean_y = points.iter().map(|p| p.1).sum::<f64>() / n;
+    let covariance: f64 = points
+        .iter()
+        .map(|(x, y)| (x - mean_x) * (y - mean_y))
+        .sum();
+    let variance: f64 = points.iter().map(|(x, _)| (x - mean_x).powi(2)).sum();
+    // Runs started at the same moment say nothing about change over time
+    (variance > 0.0).then(|| covariance / variance)
+}
@@ -0,0 +1,608 @@
+use crate::beerxml::export_beerxml;
+use crate::brewer;
+use crate::bsmx::Recipe;
+use crate::cli::{Compression, ServeArgs};
+use crate::collection::read_recipe;
+use crate::color::srm_to_hex;
+use crate::compression::bsmx_stem;
+use crate::index::{Filter, Index, IndexEntry, INDEX_FILE};
+use crate::RECIPES_DIR;
+use axum::extract::{Path as UrlPath, Query, State};
+use axum::http::{header, StatusCode};
+use axum::response::{Html, IntoResponse, Json, Response};
+use axum::routing::{get, post};
+use axum::Router;
+use chrono::{DateTime, Utc};
+use quick_xml::escape::escape;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashSet};
+use std::error::Error;
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use tracing::{info, warn};
+
+const DEFAULT_PER_PAGE: usize = 50;
+const MAX_PER_PAGE: usize = 500;
+
+pub async fn run(args: &ServeArgs) -> Result<(), Box<dyn Error>> {
+    let dir = Path::new(RECIPES_DIR);
+    if !dir.join(INDEX_FILE).exists() {
+        println!("No recipe index found; run `beerscape index` to build one");
+        return Ok(());
+    }
+    let collection = Collection::load(dir)?;
+    let count = collection.catalog().entries.len();
+    #[cfg(unix)]
+    reload_on_sighup(collection.clone())?;
+
+    let addr = SocketAddr::new(args.bind, args.port);
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    println!("Serving {} recipes at http://{}", count, addr);
+    axum::serve(listener, router(collection)).await?;
+    Ok(())
+}
+
+/// The pages and the JSON API over `collection`.
+pub fn router(collection: Collection) -> Router {
+    Router::new()
+        .route("/", get(list_page))
+        .route("/recipes/{id}", get(detail_page))
+        .route("/recipes/{id}/bsmx", get(bsmx_file))
+        .route("/recipes/{id}/beerxml", get(beerxml_file))
+        .route("/api/recipes", get(api_list))
+        .route("/api/recipes/{id}", get(api_detail))
+        .route("/api/recipes/{id}/raw", get(api_raw))
+        .route("/api/stats", get(api_stats))
+        .route("/api/search", post(api_search))
+        .route("/api/reload", post(api_reload))
+        .with_state(collection)
+}
+
+/// A recipes directory with its index held in memory. The index is read
+/// once and then only on [`Collection::reload`], so `beerscape index` can
+/// rebuild it while the server runs without anyone seeing it half done.
+#[derive(Clone)]
+pub struct Collection {
+    dir: Arc<PathBuf>,
+    catalog: Arc<RwLock<Arc<Catalog>>>,
+}
+
+/// One reading of the index.
+struct Catalog {
+    /// Every entry, ordered by name as the lists show them
+    entries: Vec<IndexEntry>,
+    loaded_at: DateTime<Utc>,
+}
+
+impl Collection {
+    pub fn load(dir: &Path) -> rusqlite::Result<Self> {
+        Ok(Collection {
+            dir: Arc::new(dir.to_path_buf()),
+            catalog: Arc::new(RwLock::new(Arc::new(Catalog::read(dir)?))),
+        })
+    }
+
+    /// Read the index again, returning how many entries it now has. The
+    /// old entries keep being served until the new ones are in.
+    pub fn reload(&self) -> rusqlite::Result<usize> {
+        let catalog = Catalog::read(&self.dir)?;
+        let count = catalog.entries.len();
+        *self.catalog.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(catalog);
+        info!(recipes = count, "reloaded the index");
+        Ok(count)
+    }
+
+    fn catalog(&self) -> Arc<Catalog> {
+        self.catalog
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+}
+
+impl Catalog {
+    fn read(dir: &Path) -> rusqlite::Result<Self> {
+        let mut entries = Index::open(dir)?.entries()?;
+        // The same order the index's own queries give
+        entries.sort_by(|a, b| {
+            a.name
+                .to_ascii_lowercase()
+                .cmp(&b.name.to_ascii_lowercase())
+                .then_with(|| a.filename.cmp(&b.filename))
+        });
+        Ok(Catalog {
+            entries,
+            loaded_at: Utc::now(),
+        })
+    }
+
+    fn search(&self, params: &ListParams) -> RecipeList {
+        let filter = params.filter();
+        let (page, per_page) = (params.page(), params.per_page());
+        let matching: Vec<&IndexEntry> = self
+            .entries
+            .iter()
+            .filter(|entry| filter.matches(entry))
+            .collect();
+        RecipeList {
+            total: matching.len(),
+            page,
+            per_page,
+            recipes: matching
+                .into_iter()
+                .skip((page - 1) * per_page)
+                .take(per_page)
+                .cloned()
+                .map(Listed::new)
+                .collect(),
+        }
+    }
+
+    fn stats(&self) -> Stats {
+        let mut styles = BTreeMap::new();
+        for entry in &self.entries {
+            *styles.entry(entry.style.as_str()).or_insert(0) += 1;
+        }
+        let mut styles: Vec<StyleCount> = styles
+            .into_iter()
+            .map(|(style, recipes)| StyleCount {
+                style: style.to_string(),
+                recipes,
+            })
+            .collect();
+        styles.sort_by_key(|count| Reverse(count.recipes));
+
+        let brewers: HashSet<String> = self
+            .entries
+            .iter()
+            .filter_map(|entry| brewer::key(&entry.brewer))
+            .collect();
+        let mean = |value: fn(&IndexEntry) -> f64| {
+            if self.entries.is_empty() {
+                return 0.0;
+            }
+            self.entries.iter().map(value).sum::<f64>() / self.entries.len() as f64
+        };
+        Stats {
+            recipes: self.entries.len(),
+            brewers: brewers.len(),
+            mean_abv: mean(|entry| entry.abv),
+            mean_ibu: mean(|entry| entry.ibu),
+            mean_og: mean(|entry| entry.og),
+            styles,
+            loaded_at: self.loaded_at,
+        }
+    }
+
+    fn get(&self, id: &str) -> Option<&IndexEntry> {
+        self.entries.iter().find(|entry| entry.filename == id)
+    }
+}
+
+/// Reload the index on every SIGHUP, the usual way to tell a daemon its
+/// files changed.
+#[cfg(unix)]
+fn reload_on_sighup(collection: Collection) -> std::io::Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+    let mut hup = signal(SignalKind::hangup())?;
+    tokio::spawn(async move {
+        while hup.recv().await.is_some() {
+            let collection = collection.clone();
+            let reloaded = tokio::task::spawn_blocking(move || collection.reload()).await;
+            if let Err(e) = reloaded
+                .map_err(|e| e.to_string())
+                .and_then(|r| r.map_err(|e| e.to_string()))
+            {
+                warn!(error = %e, "keeping the old index");
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Query string for the recipe list, shared by the HTML page and the API,
+/// and the body of a search.
+#[derive(Debug, Default, Deserialize)]
+struct ListParams {
+    #[serde(default)]
+    q: String,
+    #[serde(default)]
+    style: String,
+    brewer: Option<String>,
+    min_abv: Option<f64>,
+    max_abv: Option<f64>,
+    page: Option<usize>,
+    per_page: Option<usize>,
+}
+
+impl ListParams {
+    fn filter(&self) -> Filter {
+        Filter {
+            text: self.q.clone(),
+            style: self.style.clone(),
+            brewer: self.brewer.clone().filter(|b| !b.is_empty()),
+            min_abv: self.min_abv,
+            max_abv: self.max_abv,
+            tags: Vec::new(),
+        }
+    }
+
+    fn page(&self) -> usize {
+        self.page.unwrap_or(1).max(1)
+    }
+
+    fn per_page(&self) -> usize {
+        self.per_page
+            .unwrap_or(DEFAULT_PER_PAGE)
+            .clamp(1, MAX_PER_PAGE)
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct RecipeList {
+    total: usize,
+    page: usize,
+    per_page: usize,
+    recipes: Vec<Listed>,
+}
+
+/// An index entry as the API gives it out, with its colour ready for a
+/// swatch.
+#[derive(Debug, Serialize)]
+struct Listed {
+    #[serde(flatten)]
+    entry: IndexEntry,
+    color_hex: String,
+}
+
+impl Listed {
+    fn new(entry: IndexEntry) -> Self {
+        let color_hex = srm_to_hex(entry.color_srm);
+        Listed { entry, color_hex }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct RecipeDetail {
+    #[serde(flatten)]
+    entry: Listed,
+    recipe: Recipe,
+}
+
+#[derive(Debug, Serialize)]
+struct Stats {
+    recipes: usize,
+    /// Distinct brewers, not counting placeholders like "Unknown"
+    brewers: usize,
+    mean_abv: f64,
+    mean_ibu: f64,
+    mean_og: f64,
+    /// Most common first
+    styles: Vec<StyleCount>,
+    /// When the index was last read
+    loaded_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+struct StyleCount {
+    style: String,
+    recipes: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct Reloaded {
+    recipes: usize,
+}
+
+enum ServeError {
+    NotFound,
+    Internal(String),
+}
+
+impl IntoResponse for ServeError {
+    fn into_response(self) -> Response {
+        match self {
+            ServeError::NotFound => (StatusCode::NOT_FOUND, "Recipe not found").into_response(),
+            ServeError::Internal(e) => (StatusCode::INTERNAL_SERVER_ERROR, e).into_response(),
+        }
+    }
+}
+
+/// The index entry for an ID, which is the recipe's file name.
+fn lookup(collection: &Collection, id: &str) -> Result<(IndexEntry, PathBuf), ServeError> {
+    let entry = collection
+        .catalog()
+        .get(id)
+        .cloned()
+        .ok_or(ServeError::NotFound)?;
+    let path = collection.dir.join(&entry.filename);
+    if !path.exists() {
+        return Err(ServeError::NotFound);
+    }
+    Ok((entry, path))
+}
+
+fn load(collection: &Collection, id: &str) -> Result<RecipeDetail, ServeError> {
+    let (entry, path) = lookup(collection, id)?;
+    let recipe = read_recipe(&path).map_err(|e| ServeError::Internal(e.to_string()))?;
+    Ok(RecipeDetail {
+        entry: Listed::new(entry),
+        recipe,
+    })
+}
+
+async fn api_list(
+    State(collection): State<Collection>,
+    Query(params): Query<ListParams>,
+) -> Json<RecipeList> {
+    Json(collection.catalog().search(&params))
+}
+
+async fn api_search(
+    State(collection): State<Collection>,
+    Json(params): Json<ListParams>,
+) -> Json<RecipeList> {
+    Json(collection.catalog().search(&params))
+}
+
+async fn api_detail(
+    State(collection): State<Collection>,
+    UrlPath(id): UrlPath<String>,
+) -> Result<Json<RecipeDetail>, ServeError> {
+    Ok(Json(load(&collection, &id)?))
+}
+
+async fn api_raw(
+    State(collection): State<Collection>,
+    UrlPath(id): UrlPath<String>,
+) -> Result<Response, ServeError> {
+    let (_, path) = lookup(&collection, &id)?;
+    let bytes = read_stored(&path).await?;
+    Ok(([(header::CONTENT_TYPE, "application/xml")], bytes).into_response())
+}
+
+async fn api_stats(State(collection): State<Collection>) -> Json<Stats> {
+    Json(collection.catalog().stats())
+}
+
+async fn api_reload(State(collection): State<Collection>) -> Result<Json<Reloaded>, ServeError> {
+    let recipes = tokio::task::spawn_blocking(move || collection.reload())
+        .await
+        .map_err(|e| ServeError::Internal(e.to_string()))?
+        .map_err(|e| ServeError::Internal(e.to_string()))?;
+    Ok(Json(Reloaded { recipes }))
+}
+
+async fn bsmx_file(
+    State(collection): State<Collection>,
+    UrlPath(id): UrlPath<String>,
+) -> Result<Response, ServeError> {
+    let (entry, path) = lookup(&collection, &id)?;
+    let bytes = read_stored(&path).await?;
+    let filename = entry
+        .filename
+        .strip_suffix(Compression::of(&path).extension())
+        .unwrap_or(&entry.filename);
+    Ok(download(filename, bytes))
+}
+
+/// A recipe file's BSMX, decompressed if it's stored compressed.
+async fn read_stored(path: &Path) -> Result<Vec<u8>, ServeError> {
+    let bytes = tokio::fs::read(path)
+        .await
+        .map_err(|_| ServeError::NotFound)?;
+    Compression::of(path)
+        .decode(&bytes)
+        .map_err(|e| ServeError::Internal(e.to_string()))
+}
+
+async fn beerxml_file(
+    State(collection): State<Collection>,
+    UrlPath(id): UrlPath<String>,
+) -> Result<Response, ServeError> {
+    let detail = load(&collection, &id)?;
+    let stored = Path::new(&detail.entry.entry.filename);
+    let filename = stored
+        .with_file_name(bsmx_stem(stored).unwrap_or_default())
+        .with_extension("xml");
+    Ok(download(
+        &filename.to_string_lossy(),
+        export_beerxml(&detail.recipe).into_bytes(),
+    ))
+}
+
+fn download(filename: &str, bytes: Vec<u8>) -> Response {
+    let disposition = format!(
+        "attachment; filename=\"{}\"",
+        filename.replace(['"', '\\'], "_")
+    );
+    (
+        [
+            (header::CONTENT_TYPE, "application/xml".to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        bytes,
+    )
+        .into_response()
+}
+
+async fn list_page(
+    State(collection): State<Collection>,
+    Query(params): Query<ListParams>,
+) -> Html<String> {
+    let list = collection.catalog().search(&params);
+
+    let mut body = String::new();
+    let _ = write!(
+        body,
+        "<form><input name=\"q\" placeholder=\"Name, style or brewer\" value=\"{}\"> \
+         <input name=\"style\" placeholder=\"Style\" value=\"{}\"> \
+         <button>Search</button></form>",
+        escape(&params.q),
+        escape(&params.style)
+    );
+    let _ = write!(body, "<p>{} recipes</p>", list.total);
+    body.push_str(
+        "<table><tr><th>Name</th><th>Style</th><th>Brewer</th>\
+         <th>ABV</th><th>IBU</th></tr>",
+    );
+    for Listed { entry, .. } in &list.recipes {
+        let _ = write!(
+            body,
+            "<tr><td><a href=\"/recipes/{}\">{}</a></td><td>{}</td><td>{}</td>\
+             <td>{:.1}%</td><td>{:.0}</td></tr>",
+            url_encode(&entry.filename),
+            escape(&entry.name),
+            escape(&entry.style),
+            escape(&entry.brewer),
+            entry.abv,
+            entry.ibu
+        );
+    }
+    body.push_str("</table><p>");
+
+    let pages = list.total.div_ceil(list.per_page).max(1);
+    if list.page > 1 {
+        let _ = write!(
+            body,
+            "<a href=\"{}\">&larr; Previous</a> ",
+            page_link(&params, list.page - 1)
+        );
+    }
+    let _ = write!(body, "Page {} of {}", list.page, pages);
+    if list.page < pages {
+        let _ = write!(
+            body,
+            " <a href=\"{}\">Next &rarr;</a>",
+            page_link(&params, list.page + 1)
+        );
+    }
+    body.push_str("</p>");
+
+    html("Recipes", &body)
+}
+
+async fn detail_page(
+    State(collection): State<Collection>,
+    UrlPath(id): UrlPath<String>,
+) -> Result<Html<String>, ServeError> {
+    let detail = load(&collection, &id)?;
+    let recipe = &detail.recipe;
+    let id = url_encode(&detail.entry.entry.filename);
+
+    let mut body = String::new();
+    if !recipe.brewer.is_empty() {
+        let _ = write!(body, "<p>by {}</p>", escape(&recipe.brewer));
+    }
+    let style = &recipe.style;
+    if !style.name.is_empty() {
+        let _ = write!(
+            body,
+            "<p>{} ({}{})</p>",
+            escape(&style.name),
+            escape(&style.number),
+            escape(&style.letter)
+        );
+    }
+    let _ = write!(
+        body,
+        "<p>Batch {:.1} L &middot; Boil {:.0} min<br>\
+         OG {:.3} &middot; FG {:.3} &middot; ABV {:.1}% &middot; \
+         IBU {:.0} &middot; Color {:.1} SRM</p>",
+        recipe.batch_size_l,
+        recipe.boil_time_min,
+        recipe.og,
+        recipe.fg,
+        recipe.abv,
+        recipe.ibu,
+        recipe.color_srm
+    );
+
+    body.push_str("<h2>Fermentables</h2><table>");
+    for fermentable in &recipe.fermentables {
+        let _ = write!(
+            body,
+            "<tr><td>{:.2} kg</td><td>{}</td><td>{:.0} SRM</td></tr>",
+            fermentable.amount_kg,
+            escape(&fermentable.name),
+            fermentable.color_srm
+        );
+    }
+    body.push_str("</table><h2>Hops</h2><table>");
+    for hop in &recipe.hops {
+        let _ = write!(
+            body,
+            "<tr><td>{:.1} g</td><td>{}</td><td>{:.1}%</td><td>{:.0} min</td></tr>",
+            hop.amount_g,
+            escape(&hop.name),
+            hop.alpha_pct,
+            hop.boil_time_min
+        );
+    }
+    body.push_str("</table><h2>Yeast</h2><ul>");
+    for yeast in &recipe.yeasts {
+        let _ = write!(body, "<li>{}</li>", escape(&yeast.name));
+    }
+    body.push_str("</ul>");
+
+    let _ = write!(
+        body,
+        "<p><a href=\"/recipes/{id}/bsmx\">Download BSMX</a> &middot; \
+         <a href=\"/recipes/{id}/beerxml\">Download BeerXML</a> &middot; \
+         <a href=\"/api/recipes/{id}\">JSON</a> &middot; \
+         <a href=\"/\">All recipes</a></p>",
+        id = id
+    );
+
+    Ok(html(&recipe.name, &body))
+}
+
+fn html(title: &str, body: &str) -> Html<String> {
+    Html(format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{title} - beerscape</title>\
+         <style>body{{font-family:sans-serif;max-width:60em;margin:auto}}\
+         td,th{{padding:0.2em 0.6em;text-align:left}}</style></head>\
+         <body><h1>{title}</h1>{body}</body></html>",
+        title = escape(title),
+        body = body
+    ))
+}
+
+fn page_link(params: &ListParams, page: usize) -> String {
+    let mut link = format!(
+        "/?q={}&style={}&page={}",
+        url_encode(&params.q),
+        url_encode(&params.style),
+        page
+    );
+    if let Some(brewer) = &params.brewer {
+        let _ = write!(link, "&brewer={}", url_encode(brewer));
+    }
+    if let Some(min_abv) = params.min_abv {
+        let _ = write!(link, "&min_abv={}", min_abv);
+    }
+    if let Some(max_abv) = params.max_abv {
+        let _ = write!(link, "&max_abv={}", max_abv);
+    }
+    if let Some(per_page) = params.per_page {
+        let _ = write!(link, "&per_page={}", per_page);
+    }
+    link
+}
+
+/// Percent-encode everything but unreserved characters, which is safe in
+/// both paths and query strings.
+fn url_encode(value: &str) -> String {
+    let mut out = String::new();
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) {
+            out.push(byte as char);
+        } else {
+            let _ = write!(out, "%{:02X}", byte);
+        }
+    }
+    out
+}
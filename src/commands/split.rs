@@ -0,0 +1,76 @@
+use crate::bsmx::{parse_document, split_recipes};
+use crate::cli::SplitArgs;
+use crate::collection::{recipe_files, split_file, ARCHIVE_DIR};
+use crate::compression::read_bsmx;
+use crate::index::Index;
+use crate::RECIPES_DIR;
+use std::error::Error;
+use std::path::Path;
+use tracing::warn;
+
+pub fn run(args: &SplitArgs) -> Result<(), Box<dyn Error>> {
+    let paths = if args.paths.is_empty() {
+        recipe_files()?
+    } else {
+        args.paths.clone()
+    };
+    let index = Index::open(Path::new(RECIPES_DIR))?;
+
+    let mut split = 0;
+    let mut created = 0;
+    let mut skipped = 0;
+    for path in &paths {
+        if args.dry_run {
+            let parts = read_bsmx(path)
+                .map_err(Box::<dyn Error>::from)
+                .and_then(|bytes| Ok(parse_document(&bytes)?))
+                .map(|document| split_recipes(&document).map_or(0, |d| d.len()));
+            match parts {
+                Ok(0) => {}
+                Ok(parts) => {
+                    println!("{} ({} recipes)", path.display(), parts);
+                    split += 1;
+                    created += parts;
+                }
+                Err(e) => {
+                    warn!(file = %path.display(), error = %e, "skipping");
+                    skipped += 1;
+                }
+            }
+            continue;
+        }
+
+        match split_file(&index, path, None) {
+            Ok(parts) if parts.is_empty() => {}
+            Ok(parts) => {
+                println!("Split {} into {} recipes", path.display(), parts.len());
+                split += 1;
+                created += parts.len();
+            }
+            Err(e) => {
+                warn!(file = %path.display(), error = %e, "skipping");
+                skipped += 1;
+            }
+        }
+    }
+
+    if args.dry_run {
+        println!(
+            "{} of {} files would be split into {} recipes",
+            split,
+            paths.len(),
+            created
+        );
+    } else {
+        println!(
+            "Split {} of {} files into {} recipes ({} skipped); originals moved to {}/{}",
+            split,
+            paths.len(),
+            created,
+            skipped,
+            RECIPES_DIR,
+            ARCHIVE_DIR
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,193 @@
+use crate::bsmx::parse_bsmx;
+use crate::cli::{Compression, StorageLayout, SyncArgs};
+use crate::collection::{hash_files, HashedFile};
+use crate::commands::merge;
+use crate::compression::read_bsmx;
+use crate::index::Index;
+use crate::state::State;
+use indicatif::{ProgressBar, ProgressStyle};
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// What copying one way did, or would do.
+#[derive(Default)]
+struct Copied {
+    files: usize,
+    /// Files the other side has a different file under the same name for
+    conflicts: Vec<(PathBuf, PathBuf)>,
+}
+
+/// Copy the recipes in the source that the destination has under neither
+/// their name nor their contents into it, in the destination's layout, and
+/// index them there. With --bidirectional the destination's go the other
+/// way too. A file whose name the other side has with different contents is
+/// a conflict, reported and left alone, and nothing is ever deleted.
+pub fn run(args: &SyncArgs) -> Result<(), Box<dyn Error>> {
+    if !args.source.is_dir() {
+        return Err(format!("no recipes directory at {}", args.source.display()).into());
+    }
+    let source_layout = State::load(&args.source)?.storage_layout;
+    let dest_layout = State::load(&args.dest)?.storage_layout;
+    let sources = scan(&args.source, source_layout)?;
+    let dests = scan(&args.dest, dest_layout)?;
+
+    let to_dest = copy_missing(
+        &args.source,
+        &sources,
+        &args.dest,
+        dest_layout,
+        &dests,
+        args.dry_run,
+    )?;
+    for (source, dest) in &to_dest.conflicts {
+        println!(
+            "Conflict: {} and {} differ; left as they are",
+            source.display(),
+            dest.display()
+        );
+    }
+    // The other way round, the same conflicts turn up swapped
+    let to_source = if args.bidirectional {
+        Some(copy_missing(
+            &args.dest,
+            &dests,
+            &args.source,
+            source_layout,
+            &sources,
+            args.dry_run,
+        )?)
+    } else {
+        None
+    };
+
+    let source_hashes: HashSet<[u8; 32]> = sources.iter().map(|m| m.hash).collect();
+    let source_names: HashSet<&str> = sources.iter().map(|m| m.name.as_str()).collect();
+    let only_in_dest: Vec<&HashedFile> = dests
+        .iter()
+        .filter(|m| !source_hashes.contains(&m.hash) && !source_names.contains(m.name.as_str()))
+        .collect();
+    if to_source.is_none() {
+        for member in &only_in_dest {
+            println!("Only in destination: {}", member.path.display());
+        }
+    }
+
+    let verb = if args.dry_run { "Would Copy" } else { "Copied" };
+    println!();
+    println!("Sync Summary:");
+    println!("-------------");
+    println!("{} to Destination: {}", verb, to_dest.files);
+    if let Some(to_source) = &to_source {
+        println!("{} to Source: {}", verb, to_source.files);
+    }
+    println!(
+        "In Both: {}",
+        dests
+            .iter()
+            .filter(|m| source_hashes.contains(&m.hash))
+            .count()
+    );
+    println!("Conflicts: {}", to_dest.conflicts.len());
+    if to_source.is_none() {
+        println!("Only in Destination: {}", only_in_dest.len());
+    }
+    Ok(())
+}
+
+fn scan(dir: &Path, layout: StorageLayout) -> Result<Vec<HashedFile>, Box<dyn Error>> {
+    let pb = bar(&format!("Hashing {}", dir.display()), 0);
+    let files = hash_files(dir, layout, &pb)?;
+    pb.finish_and_clear();
+    Ok(files)
+}
+
+/// Copy each of `members`, the recipes in `from`, that `existing` has
+/// neither the contents nor the name of into `dir`, kept as `layout` keeps
+/// them.
+fn copy_missing(
+    from: &Path,
+    members: &[HashedFile],
+    dir: &Path,
+    layout: StorageLayout,
+    existing: &[HashedFile],
+    dry_run: bool,
+) -> Result<Copied, Box<dyn Error>> {
+    let mut hashes: HashSet<[u8; 32]> = existing.iter().map(|m| m.hash).collect();
+    let names: HashMap<&str, &HashedFile> = existing.iter().map(|m| (m.name.as_str(), m)).collect();
+
+    let mut copied = Copied::default();
+    let mut missing = Vec::new();
+    for member in members {
+        match names.get(member.name.as_str()) {
+            Some(other) if other.hash != member.hash => copied
+                .conflicts
+                .push((member.path.clone(), other.path.clone())),
+            Some(_) => {}
+            // Unless it's there under another name, or one of several
+            // identical files being copied
+            None if hashes.insert(member.hash) => missing.push(member),
+            None => {}
+        }
+    }
+    if missing.is_empty() {
+        return Ok(copied);
+    }
+
+    let from_index = if dry_run {
+        None
+    } else {
+        merge::open_index(from)?
+    };
+    let index = if dry_run {
+        None
+    } else {
+        fs::create_dir_all(dir)?;
+        Some(Index::open(dir)?)
+    };
+    let verb = if dry_run { "Would copy" } else { "Copied" };
+    let pb = bar(&format!("Copying to {}", dir.display()), missing.len());
+    for member in missing {
+        let contents = read_bsmx(&member.path)?;
+        let recipe = parse_bsmx(&contents).ok();
+        let name = format!(
+            "{}{}",
+            layout.path_for(&member.name, &contents, recipe.as_ref()),
+            Compression::of(&member.path).extension()
+        );
+        let target = dir.join(&name);
+        if target.exists() {
+            // Filed where the layout puts another recipe of the same name
+            copied.conflicts.push((member.path.clone(), target));
+            pb.inc(1);
+            continue;
+        }
+        pb.suspend(|| println!("{} {} -> {}", verb, member.path.display(), target.display()));
+        if let Some(index) = &index {
+            merge::copy(
+                from_index.as_ref(),
+                &member.path,
+                recipe.as_ref(),
+                dir,
+                index,
+                &name,
+            )?;
+        }
+        copied.files += 1;
+        pb.inc(1);
+    }
+    pb.finish_and_clear();
+    Ok(copied)
+}
+
+fn bar(action: &str, len: usize) -> ProgressBar {
+    let pb = ProgressBar::new(len as u64);
+    if let Ok(style) = ProgressStyle::default_bar().template(&format!(
+        "{} [{{bar:50.cyan/blue}}] {{pos}}/{{len}} ({{eta}})",
+        action
+    )) {
+        pb.set_style(style.progress_chars("#>-"));
+    }
+    pb
+}
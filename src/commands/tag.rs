@@ -0,0 +1,68 @@
+use crate::cli::TagCommand;
+use crate::collection::stored_name;
+use crate::index::{Index, INDEX_FILE};
+use crate::tags::Tags;
+use crate::RECIPES_DIR;
+use std::error::Error;
+use std::path::Path;
+
+/// Change or look up the tags in `recipes/tags.json`. Changes are copied
+/// into the index, if there is one, so searches see them straight away.
+pub fn run(command: &TagCommand) -> Result<(), Box<dyn Error>> {
+    let dir = Path::new(RECIPES_DIR);
+    let mut tags = Tags::load(dir)?;
+    match command {
+        TagCommand::Add(args) => {
+            let filename = name_in(dir, &args.filename);
+            if !dir.join(&filename).is_file() {
+                return Err(format!("no recipe file {} in {}", filename, RECIPES_DIR).into());
+            }
+            if tags.add(&filename, &args.tag) {
+                save(dir, &tags, &filename)?;
+                println!("Tagged {} {}", filename, args.tag);
+            } else {
+                println!("{} is already tagged {}", filename, args.tag);
+            }
+        }
+        TagCommand::Remove(args) => {
+            let filename = name_in(dir, &args.filename);
+            if tags.remove(&filename, &args.tag) {
+                save(dir, &tags, &filename)?;
+                println!("Untagged {} {}", filename, args.tag);
+            } else {
+                println!("{} isn't tagged {}", filename, args.tag);
+            }
+        }
+        TagCommand::List(args) => {
+            for tag in tags.of(&name_in(dir, &args.filename)) {
+                println!("{}", tag);
+            }
+        }
+        TagCommand::Search(args) => {
+            let files = tags.files_with(&args.tag);
+            for filename in &files {
+                println!("{}", filename);
+            }
+            println!("{} recipes", files.len());
+        }
+    }
+    Ok(())
+}
+
+/// A recipe file as tags.json and the index name it, whether it was given
+/// relative to the recipes directory or with the directory in front.
+fn name_in(dir: &Path, given: &Path) -> String {
+    if given.starts_with(dir) {
+        stored_name(dir, given)
+    } else {
+        stored_name(Path::new(""), given)
+    }
+}
+
+fn save(dir: &Path, tags: &Tags, filename: &str) -> Result<(), Box<dyn Error>> {
+    tags.save(dir)?;
+    if dir.join(INDEX_FILE).exists() {
+        Index::open(dir)?.set_tags(filename, tags.of(filename))?;
+    }
+    Ok(())
+}
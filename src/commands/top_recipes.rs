@@ -0,0 +1,164 @@
+use crate::bsmx::Recipe;
+use crate::cli::{RankBy, TopRecipesArgs};
+use crate::collection::{load_recipes, stored_name};
+use crate::completeness::completeness_score;
+use crate::compression::bsmx_stem;
+use crate::index::Index;
+use crate::RECIPES_DIR;
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+/// A recipe's place in a ranking.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ranked {
+    pub path: PathBuf,
+    /// Site ID the file was downloaded as, when known
+    pub recipe_id: Option<u32>,
+    pub name: String,
+    pub style: String,
+    pub abv: f64,
+    pub ibu: f64,
+    /// Higher ranks first; see `rank` for what it measures
+    pub score: f64,
+}
+
+pub fn run(args: &TopRecipesArgs) -> Result<(), Box<dyn Error>> {
+    if !(0.0..=1.0).contains(&args.min_completeness) {
+        return Err(format!(
+            "--min-completeness {} is not a score between 0 and 1",
+            args.min_completeness
+        )
+        .into());
+    }
+    let collection = load_recipes()?;
+    // Split files are named after the recipe, so their ID comes from the
+    // index; everything else is named after its ID
+    let indexed: HashMap<String, u32> = if collection.recipes.is_empty() {
+        HashMap::new()
+    } else {
+        Index::open(Path::new(RECIPES_DIR))?
+            .entries()?
+            .into_iter()
+            .filter_map(|entry| Some((entry.filename, entry.recipe_id?)))
+            .collect()
+    };
+    let read = collection.recipes.len();
+    let recipes: Vec<(PathBuf, Option<u32>, Recipe)> = collection
+        .recipes
+        .into_iter()
+        .filter(|(_, recipe)| completeness_score(recipe) >= args.min_completeness)
+        .map(|(path, recipe)| {
+            let filename = stored_name(Path::new(RECIPES_DIR), &path);
+            let recipe_id = indexed
+                .get(&filename)
+                .copied()
+                .or_else(|| bsmx_stem(&path).and_then(|stem| stem.parse().ok()));
+            (path, recipe_id, recipe)
+        })
+        .collect();
+    let ranked = rank(&recipes, args.rank_by);
+
+    println!("Top Recipes:");
+    println!("------------");
+    println!(
+        "{:>4}  {:<36} {:<24} {:>6} {:>6} {:>9}",
+        "#", "Name", "Style", "ABV", "IBU", "Score"
+    );
+    for (n, entry) in ranked.iter().take(args.top).enumerate() {
+        let score = match args.rank_by {
+            RankBy::Id => entry
+                .recipe_id
+                .map_or_else(String::new, |id| id.to_string()),
+            RankBy::Ingredients => entry.score.to_string(),
+            RankBy::Completeness => format!("{:.0}%", entry.score * 100.0),
+            RankBy::Combined => format!("{:.3}", entry.score),
+        };
+        println!(
+            "{:>4}  {:<36} {:<24} {:>5.1}% {:>6.0} {:>9}",
+            n + 1,
+            entry.name,
+            entry.style,
+            entry.abv,
+            entry.ibu,
+            score
+        );
+    }
+    println!();
+    println!("Ranked: {}", ranked.len());
+    if recipes.len() < read {
+        println!("Below --min-completeness: {}", read - recipes.len());
+    }
+    if args.rank_by == RankBy::Id && ranked.len() < recipes.len() {
+        println!("Without an ID: {}", recipes.len() - ranked.len());
+    }
+    println!("Unreadable Files: {}", collection.unreadable);
+    Ok(())
+}
+
+/// Rank `recipes`, given with their site IDs, highest score first. Ties go
+/// to the lower ID, then the path.
+///
+/// - `Id`: lower IDs were posted earlier, so seniority runs from 1.0 for the
+///   lowest ID in the collection to 0.0 for the highest. Recipes without an
+///   ID are left out.
+/// - `Completeness`: the [`completeness_score`], from 0.0 to 1.0.
+/// - `Ingredients`: the number of fermentables, hops and yeasts.
+/// - `Combined`: the mean of seniority, completeness and the ingredient count
+///   as a share of the collection's largest, so each weighs the same.
+///   Recipes without an ID count as least senior.
+pub fn rank(recipes: &[(PathBuf, Option<u32>, Recipe)], rank_by: RankBy) -> Vec<Ranked> {
+    let ids = recipes.iter().filter_map(|(_, id, _)| *id);
+    let (lowest, highest) = (ids.clone().min(), ids.max());
+    let seniority = |id: Option<u32>| match (id, lowest, highest) {
+        (Some(id), Some(lo), Some(hi)) if hi > lo => (hi - id) as f64 / (hi - lo) as f64,
+        (Some(_), _, _) => 1.0,
+        (None, _, _) => 0.0,
+    };
+    let most_ingredients = recipes
+        .iter()
+        .map(|(_, _, recipe)| ingredient_count(recipe))
+        .max()
+        .unwrap_or(0)
+        .max(1);
+
+    let mut ranked: Vec<Ranked> = recipes
+        .iter()
+        .filter(|(_, id, _)| rank_by != RankBy::Id || id.is_some())
+        .map(|(path, id, recipe)| {
+            let score = match rank_by {
+                RankBy::Id => seniority(*id),
+                RankBy::Completeness => completeness_score(recipe),
+                RankBy::Ingredients => ingredient_count(recipe) as f64,
+                RankBy::Combined => {
+                    let ingredients = ingredient_count(recipe) as f64 / most_ingredients as f64;
+                    (seniority(*id) + completeness_score(recipe) + ingredients) / 3.0
+                }
+            };
+            Ranked {
+                path: path.clone(),
+                recipe_id: *id,
+                name: recipe.name.trim().to_string(),
+                style: recipe.style.name.trim().to_string(),
+                abv: recipe.abv,
+                ibu: recipe.ibu,
+                score,
+            }
+        })
+        .collect();
+    ranked.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then_with(|| {
+                a.recipe_id
+                    .unwrap_or(u32::MAX)
+                    .cmp(&b.recipe_id.unwrap_or(u32::MAX))
+            })
+            .then_with(|| a.path.cmp(&b.path))
+    });
+    ranked
+}
+
+fn ingredient_count(recipe: &Recipe) -> usize {
+    recipe.fermentables.len() + recipe.hops.len() + recipe.yeasts.len()
+}
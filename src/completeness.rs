@@ -0,0 +1,33 @@
+use crate::bsmx::Recipe;
+
+/// How much of a recipe is filled in, from 0.0 for an empty stub to 1.0.
+/// The ingredient lists weigh double, since a recipe without them can't be
+/// brewed.
+pub fn completeness_score(recipe: &Recipe) -> f64 {
+    let parts = [
+        (!recipe.name.trim().is_empty(), 1),
+        (!recipe.style.name.trim().is_empty(), 1),
+        (recipe.og > 0.0, 1),
+        (recipe.fg > 0.0, 1),
+        (!recipe.fermentables.is_empty(), 2),
+        (!recipe.hops.is_empty(), 2),
+        (!recipe.mash_steps.is_empty(), 1),
+        (!recipe.yeasts.is_empty(), 1),
+    ];
+    // Summed in tenths so that scores compare exactly
+    let tenths: u32 = parts
+        .iter()
+        .filter(|(present, _)| *present)
+        .map(|(_, weight)| weight)
+        .sum();
+    tenths as f64 / 10.0
+}
+
+/// Number of recipes at each score, from 0.0 at index 0 to 1.0 at index 10.
+pub fn score_distribution<'a>(recipes: impl IntoIterator<Item = &'a Recipe>) -> [usize; 11] {
+    let mut counts = [0; 11];
+    for recipe in recipes {
+        counts[(completeness_score(recipe) * 10.0).round() as usize] += 1;
+    }
+    counts
+}
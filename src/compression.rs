@@ -0,0 +1,192 @@
+use crate::cli::{Compression, StorageLayout};
+use crate::collection::{file_name, recipe_files_in, tmp_path};
+use crate::index::Index;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use sha2::{Digest, Sha256};
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Every ending a recipe file can have, compressed or not
+const RECIPE_ENDINGS: [&str; 3] = [".bsmx", ".bsmx.zst", ".bsmx.gz"];
+
+impl Compression {
+    /// What the compression adds after `.bsmx`.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Compression::None => "",
+            Compression::Gzip => ".gz",
+            Compression::Zstd => ".zst",
+        }
+    }
+
+    /// The compression a recipe file is stored with, by its name.
+    pub fn of(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("zst") => Compression::Zstd,
+            Some("gz") => Compression::Gzip,
+            _ => Compression::None,
+        }
+    }
+
+    /// Write `contents` to `path`, compressing them on the way at `level`,
+    /// or the format's default without one. Gzip levels stop at 9.
+    pub fn write(self, path: &Path, contents: &[u8], level: Option<i32>) -> io::Result<()> {
+        let file = File::create(path)?;
+        match self {
+            Compression::None => {
+                let mut file = file;
+                file.write_all(contents)?;
+            }
+            Compression::Gzip => {
+                let level = level.map_or(flate2::Compression::default(), |level| {
+                    flate2::Compression::new(level.clamp(0, 9) as u32)
+                });
+                let mut encoder = GzEncoder::new(file, level);
+                encoder.write_all(contents)?;
+                encoder.finish()?;
+            }
+            Compression::Zstd => {
+                let level = level.unwrap_or(zstd::DEFAULT_COMPRESSION_LEVEL);
+                let mut encoder = zstd::Encoder::new(file, level)?;
+                encoder.write_all(contents)?;
+                encoder.finish()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Read `path`, decompressing as it goes.
+    pub fn read(self, path: &Path) -> io::Result<Vec<u8>> {
+        decompress(self, File::open(path)?)
+    }
+
+    /// Decompress bytes already read.
+    pub fn decode(self, bytes: &[u8]) -> io::Result<Vec<u8>> {
+        decompress(self, bytes)
+    }
+}
+
+fn decompress(compression: Compression, source: impl Read) -> io::Result<Vec<u8>> {
+    let mut contents = Vec::new();
+    match compression {
+        Compression::None => {
+            let mut source = source;
+            source.read_to_end(&mut contents)?;
+        }
+        Compression::Gzip => {
+            GzDecoder::new(source).read_to_end(&mut contents)?;
+        }
+        Compression::Zstd => {
+            zstd::Decoder::new(source)?.read_to_end(&mut contents)?;
+        }
+    }
+    Ok(contents)
+}
+
+/// The BSMX in a recipe file, decompressed if it's stored compressed.
+pub fn read_bsmx(path: &Path) -> io::Result<Vec<u8>> {
+    Compression::of(path).read(path)
+}
+
+/// Replace a recipe file's contents, compressed as its name says, by
+/// writing beside it and renaming.
+pub fn write_bsmx(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let tmp = tmp_path(path);
+    Compression::of(path).write(&tmp, contents, None)?;
+    fs::rename(&tmp, path)
+}
+
+/// A recipe file's size on disk and the size of the BSMX it holds, which
+/// are the same unless it's stored compressed.
+pub fn file_sizes(path: &Path) -> io::Result<(u64, u64)> {
+    let stored = fs::metadata(path)?.len();
+    let bsmx = match Compression::of(path) {
+        Compression::None => stored,
+        Compression::Gzip => io::copy(&mut GzDecoder::new(File::open(path)?), &mut io::sink())?,
+        Compression::Zstd => {
+            io::copy(&mut zstd::Decoder::new(File::open(path)?)?, &mut io::sink())?
+        }
+    };
+    Ok((stored, bsmx))
+}
+
+/// Whether `path` names a recipe file, compressed or not.
+pub fn is_bsmx(path: &Path) -> bool {
+    bsmx_stem(path).is_some()
+}
+
+/// A recipe file's name without `.bsmx` and any compression extension.
+pub fn bsmx_stem(path: &Path) -> Option<&str> {
+    let name = path.file_name()?.to_str()?;
+    RECIPE_ENDINGS
+        .iter()
+        .find_map(|ending| name.strip_suffix(ending))
+        .filter(|stem| !stem.is_empty())
+}
+
+/// What recompressing a collection did.
+#[derive(Debug, Default)]
+pub struct Recompressed {
+    pub rewritten: usize,
+    /// Files already stored the way asked
+    pub unchanged: usize,
+    pub bytes_before: u64,
+    pub bytes_after: u64,
+    /// Files left as they were, and why
+    pub skipped: Vec<(PathBuf, String)>,
+}
+
+/// Store every recipe under `dir` with `compression`, at `level` if given,
+/// renaming each file and its index entry to match. Nothing is overwritten:
+/// each new file is written under a scratch name, and only renamed into
+/// place, and the original removed, once what it decompresses to hashes
+/// the same as the original's contents.
+pub fn recompress(
+    dir: &Path,
+    layout: StorageLayout,
+    index: &Index,
+    compression: Compression,
+    level: Option<i32>,
+) -> Result<Recompressed, Box<dyn Error>> {
+    let mut result = Recompressed::default();
+    let tx = index.transaction()?;
+    for path in recipe_files_in(dir, layout)? {
+        let from = Compression::of(&path);
+        if from == compression {
+            result.unchanged += 1;
+            continue;
+        }
+        let Some(stem) = bsmx_stem(&path) else {
+            continue;
+        };
+        let target = path.with_file_name(format!("{}.bsmx{}", stem, compression.extension()));
+        if target.exists() {
+            let reason = format!("{} already exists", file_name(&target));
+            result.skipped.push((path, reason));
+            continue;
+        }
+        let contents = from.read(&path)?;
+        let partial = tmp_path(&target);
+        compression.write(&partial, &contents, level)?;
+        if Sha256::digest(compression.read(&partial)?) != Sha256::digest(&contents) {
+            fs::remove_file(&partial)?;
+            let reason = "the rewritten copy didn't read back the same".to_string();
+            result.skipped.push((path, reason));
+            continue;
+        }
+        fs::rename(&partial, &target)?;
+        let stored = fs::metadata(&target)?.len();
+        result.bytes_before += fs::metadata(&path)?.len();
+        result.bytes_after += stored;
+        fs::remove_file(&path)?;
+        let filename = index.name_of(&target);
+        index.rename(&index.name_of(&path), &filename)?;
+        index.set_sizes(&filename, stored, contents.len() as u64)?;
+        result.rewritten += 1;
+    }
+    tx.commit()?;
+    Ok(result)
+}
@@ -0,0 +1,281 @@
+use crate::collection::write_atomic;
+use chrono::DateTime;
+use reqwest::cookie::{CookieStore, Jar};
+use reqwest::header::HeaderValue;
+use reqwest::Url;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::warn;
+
+// Browsers export HttpOnly cookies with this prefix on the domain, which
+// would otherwise make them look like comments
+const HTTP_ONLY_PREFIX: &str = "#HttpOnly_";
+
+/// One line of a `cookies.txt` file.
+#[derive(Debug, Clone, PartialEq)]
+struct SavedCookie {
+    domain: String,
+    include_subdomains: bool,
+    path: String,
+    secure: bool,
+    http_only: bool,
+    /// Seconds since the epoch; zero for a session cookie
+    expires: u64,
+    name: String,
+    value: String,
+}
+
+impl SavedCookie {
+    fn key(&self) -> (String, String, String) {
+        (self.domain.clone(), self.path.clone(), self.name.clone())
+    }
+
+    fn line(&self) -> String {
+        format!(
+            "{}{}{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            if self.http_only { HTTP_ONLY_PREFIX } else { "" },
+            if self.include_subdomains { "." } else { "" },
+            self.domain,
+            bool_field(self.include_subdomains),
+            self.path,
+            bool_field(self.secure),
+            self.expires,
+            self.name,
+            self.value
+        )
+    }
+
+    /// A `Set-Cookie` header the server sent for `url`. `None` for one that
+    /// can't be read, or that deletes the cookie, along with its key.
+    fn from_header(
+        header: &str,
+        url: &Url,
+        now: u64,
+    ) -> Result<Self, Option<(String, String, String)>> {
+        let mut parts = header.split(';');
+        let (name, value) = parts.next().and_then(|p| p.split_once('=')).ok_or(None)?;
+        let mut cookie = SavedCookie {
+            domain: url.host_str().ok_or(None)?.to_string(),
+            include_subdomains: false,
+            // The directory of the request, as browsers default it
+            path: match url.path().rfind('/') {
+                Some(0) | None => "/".to_string(),
+                Some(end) => url.path()[..end].to_string(),
+            },
+            secure: false,
+            http_only: false,
+            expires: 0,
+            name: name.trim().to_string(),
+            value: value.trim().to_string(),
+        };
+        let mut max_age = None;
+        for attribute in parts {
+            let (key, value) = attribute.split_once('=').unwrap_or((attribute, ""));
+            let value = value.trim();
+            match key.trim().to_ascii_lowercase().as_str() {
+                "domain" if !value.is_empty() => {
+                    cookie.domain = value.trim_start_matches('.').to_ascii_lowercase();
+                    cookie.include_subdomains = true;
+                }
+                "path" if value.starts_with('/') => cookie.path = value.to_string(),
+                "secure" => cookie.secure = true,
+                "httponly" => cookie.http_only = true,
+                "max-age" => max_age = value.parse::<i64>().ok(),
+                "expires" => {
+                    if let Ok(at) = DateTime::parse_from_rfc2822(value) {
+                        cookie.expires = at.timestamp().max(1) as u64;
+                    }
+                }
+                _ => {}
+            }
+        }
+        // Max-Age wins over Expires
+        if let Some(seconds) = max_age {
+            cookie.expires = if seconds <= 0 {
+                1
+            } else {
+                now + seconds as u64
+            };
+        }
+        if cookie.expires != 0 && cookie.expires <= now {
+            return Err(Some(cookie.key()));
+        }
+        Ok(cookie)
+    }
+}
+
+fn bool_field(value: bool) -> &'static str {
+    if value {
+        "TRUE"
+    } else {
+        "FALSE"
+    }
+}
+
+/// The cookies a run sends, which also keeps what it needs to write them
+/// back out as a `cookies.txt` file, including ones the server set.
+#[derive(Default)]
+pub struct CookieJar {
+    jar: Jar,
+    saved: Mutex<BTreeMap<(String, String, String), SavedCookie>>,
+}
+
+// Cookie values are login sessions, so only their number is shown
+impl fmt::Debug for CookieJar {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CookieJar")
+            .field("cookies", &self.saved.lock().unwrap().len())
+            .finish()
+    }
+}
+
+impl CookieStore for CookieJar {
+    fn set_cookies(&self, headers: &mut dyn Iterator<Item = &HeaderValue>, url: &Url) {
+        let headers: Vec<&HeaderValue> = headers.collect();
+        let now = unix_now();
+        let mut saved = self.saved.lock().unwrap();
+        for header in &headers {
+            let Ok(text) = header.to_str() else {
+                continue;
+            };
+            match SavedCookie::from_header(text, url, now) {
+                Ok(cookie) => {
+                    saved.insert(cookie.key(), cookie);
+                }
+                Err(Some(key)) => {
+                    saved.remove(&key);
+                }
+                Err(None) => {}
+            }
+        }
+        self.jar.set_cookies(&mut headers.into_iter(), url);
+    }
+
+    fn cookies(&self, url: &Url) -> Option<HeaderValue> {
+        self.jar.cookies(url)
+    }
+}
+
+impl CookieJar {
+    fn add(&self, cookie: SavedCookie) {
+        let url = Url::parse(&format!(
+            "{}://{}{}",
+            if cookie.secure { "https" } else { "http" },
+            cookie.domain,
+            cookie.path
+        ));
+        let Ok(url) = url else {
+            return;
+        };
+        let mut text = format!("{}={}; Path={}", cookie.name, cookie.value, cookie.path);
+        // Without a Domain attribute the cookie only goes to this exact host
+        if cookie.include_subdomains {
+            text.push_str(&format!("; Domain={}", cookie.domain));
+        }
+        if cookie.secure {
+            text.push_str("; Secure");
+        }
+        self.jar.add_cookie_str(&text, &url);
+        self.saved.lock().unwrap().insert(cookie.key(), cookie);
+    }
+
+    /// Write the unexpired cookies to a `cookies.txt` file, session cookies
+    /// included so a login lasts into the next run, returning how many.
+    pub fn save(&self, path: &Path) -> io::Result<usize> {
+        let now = unix_now();
+        let saved = self.saved.lock().unwrap();
+        let mut out = String::from("# Netscape HTTP Cookie File\n");
+        let mut written = 0;
+        for cookie in saved.values() {
+            if cookie.expires != 0 && cookie.expires <= now {
+                continue;
+            }
+            out.push_str(&cookie.line());
+            out.push('\n');
+            written += 1;
+        }
+        write_atomic(path, out.as_bytes())?;
+        Ok(written)
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// Read a Netscape/Mozilla `cookies.txt` file, as exported by browser
+/// extensions, into a cookie jar.
+pub fn parse_netscape_cookies(path: &Path) -> Result<CookieJar, Box<dyn Error>> {
+    let jar = CookieJar::default();
+    load_netscape_cookies(path, &jar)?;
+    Ok(jar)
+}
+
+/// Add the cookies from a `cookies.txt` file to an existing jar, replacing
+/// any with the same name. Expired cookies are skipped with a warning.
+/// Returns the number of cookies added.
+pub fn load_netscape_cookies(path: &Path, jar: &CookieJar) -> Result<usize, Box<dyn Error>> {
+    let now = unix_now();
+    let mut added = 0;
+    let mut expired = 0;
+
+    for (n, line) in fs::read_to_string(path)?.lines().enumerate() {
+        let line = line.trim_end_matches('\r');
+        let http_only = line.starts_with(HTTP_ONLY_PREFIX);
+        let line = line.strip_prefix(HTTP_ONLY_PREFIX).unwrap_or(line);
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let fields: Vec<&str> = line.split('\t').collect();
+        let [domain, include_subdomains, cookie_path, secure, expires, name, value] = fields[..]
+        else {
+            return Err(format!(
+                "{}:{}: expected 7 tab-separated fields, found {}",
+                path.display(),
+                n + 1,
+                fields.len()
+            )
+            .into());
+        };
+        let expires: u64 = expires.parse().map_err(|_| {
+            format!(
+                "{}:{}: invalid expiry time {:?}",
+                path.display(),
+                n + 1,
+                expires
+            )
+        })?;
+
+        // Zero marks a session cookie, which never expires on its own
+        if expires != 0 && expires < now {
+            expired += 1;
+            continue;
+        }
+
+        jar.add(SavedCookie {
+            domain: domain.trim_start_matches('.').to_string(),
+            include_subdomains: include_subdomains.eq_ignore_ascii_case("TRUE"),
+            path: cookie_path.to_string(),
+            secure: secure.eq_ignore_ascii_case("TRUE"),
+            http_only,
+            expires,
+            name: name.to_string(),
+            value: value.to_string(),
+        });
+        added += 1;
+    }
+
+    if expired > 0 {
+        warn!(expired, file = %path.display(), "skipped expired cookies");
+    }
+    Ok(added)
+}
@@ -0,0 +1,360 @@
+use crate::bsmx::{parse_bsmx, Recipe};
+use crate::index::like_pattern;
+use chrono::{DateTime, SecondsFormat, Utc};
+use rusqlite::functions::FunctionFlags;
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, Connection, Row, Transaction};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::path::Path;
+use std::time::Duration;
+
+/// One request for an ID, whatever came of it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Attempt {
+    pub id: u32,
+    pub attempted_at: DateTime<Utc>,
+    /// `None` when the server never answered
+    pub http_status: Option<u16>,
+    /// Failure category, as in the JSON progress stream; `None` for
+    /// downloads, and for failures imported from run reports, which don't
+    /// say why
+    pub error_kind: Option<String>,
+}
+
+/// A recipe found by [`ActivityDb::search`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FoundRecipe {
+    pub filename: String,
+    pub recipe_id: Option<u32>,
+    pub name: String,
+    pub brewer: String,
+    pub style: String,
+    pub abv: f64,
+    pub ibu: f64,
+}
+
+/// How many rows each table holds.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Counts {
+    pub downloads: usize,
+    pub attempts: usize,
+    pub recipes: usize,
+}
+
+/// SQLite log of download activity, kept with --db: every attempt, every
+/// file saved and the parsed contents of each. Rows are only ever added or
+/// refreshed, so the log keeps growing across runs.
+pub struct ActivityDb {
+    conn: Connection,
+}
+
+impl ActivityDb {
+    /// Open the database at `path`, creating it if needed.
+    pub fn open(path: &Path) -> rusqlite::Result<Self> {
+        let conn = Connection::open(path)?;
+        conn.busy_timeout(Duration::from_secs(5))?;
+        // SQLite's LIKE and lower() only fold ASCII, so searches fold case
+        // with this instead
+        conn.create_scalar_function(
+            "casefold",
+            1,
+            FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+            |ctx| {
+                Ok(ctx
+                    .get::<Option<String>>(0)?
+                    .map(|text| text.to_lowercase()))
+            },
+        )?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS downloads (
+                id INTEGER,
+                url TEXT,
+                filename TEXT NOT NULL,
+                downloaded_at TEXT NOT NULL,
+                bytes INTEGER NOT NULL,
+                sha256 TEXT NOT NULL,
+                parse_success INTEGER NOT NULL,
+                UNIQUE (filename, sha256)
+            );
+            CREATE INDEX IF NOT EXISTS downloads_id ON downloads (id);
+            CREATE TABLE IF NOT EXISTS attempts (
+                id INTEGER NOT NULL,
+                attempted_at TEXT NOT NULL,
+                http_status INTEGER,
+                error_kind TEXT,
+                UNIQUE (id, attempted_at)
+            );
+            CREATE TABLE IF NOT EXISTS status_counts (
+                run_started_at TEXT NOT NULL,
+                http_status INTEGER NOT NULL,
+                attempts INTEGER NOT NULL,
+                PRIMARY KEY (run_started_at, http_status)
+            );
+            CREATE TABLE IF NOT EXISTS recipes (
+                filename TEXT PRIMARY KEY,
+                recipe_id INTEGER,
+                name TEXT NOT NULL,
+                brewer TEXT NOT NULL,
+                style_name TEXT NOT NULL,
+                style_category TEXT NOT NULL,
+                style_number TEXT NOT NULL,
+                style_letter TEXT NOT NULL,
+                og REAL NOT NULL,
+                fg REAL NOT NULL,
+                abv REAL NOT NULL,
+                ibu REAL NOT NULL,
+                color_srm REAL NOT NULL,
+                batch_size_l REAL NOT NULL,
+                boil_time_min REAL NOT NULL,
+                bsmx_version TEXT NOT NULL,
+                fermentables TEXT NOT NULL,
+                hops TEXT NOT NULL,
+                yeasts TEXT NOT NULL,
+                mash_steps TEXT NOT NULL,
+                notes TEXT,
+                taste_notes TEXT
+            );",
+        )?;
+        // Databases from before notes were parsed get the columns added
+        // empty; `beerscape db import` fills them in
+        let has_notes = conn
+            .prepare("SELECT 1 FROM pragma_table_info('recipes') WHERE name = 'notes'")?
+            .exists([])?;
+        if !has_notes {
+            conn.execute_batch(
+                "ALTER TABLE recipes ADD COLUMN notes TEXT;
+                ALTER TABLE recipes ADD COLUMN taste_notes TEXT;",
+            )?;
+        }
+        Ok(ActivityDb { conn })
+    }
+
+    /// Group writes so bulk imports don't sync to disk once per row.
+    pub fn transaction(&self) -> rusqlite::Result<Transaction<'_>> {
+        self.conn.unchecked_transaction()
+    }
+
+    /// Log an attempt, returning whether it was new.
+    pub fn record_attempt(&self, attempt: &Attempt) -> rusqlite::Result<bool> {
+        let added = self.conn.execute(
+            "INSERT OR IGNORE INTO attempts (id, attempted_at, http_status, error_kind)
+            VALUES (?1, ?2, ?3, ?4)",
+            params![
+                attempt.id,
+                timestamp(attempt.attempted_at),
+                attempt.http_status,
+                attempt.error_kind,
+            ],
+        )?;
+        Ok(added > 0)
+    }
+
+    /// Log a saved file and, if it parses, its recipe. Returns whether the
+    /// download was new; the same contents under the same name are only
+    /// logged once, however often they're recorded.
+    pub fn record_file(
+        &self,
+        id: Option<u32>,
+        url: Option<&str>,
+        filename: &str,
+        contents: &[u8],
+        downloaded_at: DateTime<Utc>,
+    ) -> Result<bool, Box<dyn Error>> {
+        let recipe = parse_bsmx(contents).ok();
+        let added = self.conn.execute(
+            "INSERT OR IGNORE INTO downloads
+                (id, url, filename, downloaded_at, bytes, sha256, parse_success)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                id,
+                url,
+                filename,
+                timestamp(downloaded_at),
+                contents.len(),
+                format!("{:x}", Sha256::digest(contents)),
+                recipe.is_some(),
+            ],
+        )?;
+        if let Some(recipe) = &recipe {
+            self.record_recipe(filename, id, recipe)?;
+        }
+        Ok(added > 0)
+    }
+
+    /// Log how many of a run's attempts got each HTTP status, 0 standing
+    /// for no answer. Recording the same run again replaces its counts.
+    pub fn record_status_counts(
+        &self,
+        run_started_at: DateTime<Utc>,
+        counts: &HashMap<u16, usize>,
+    ) -> rusqlite::Result<()> {
+        let tx = self.transaction()?;
+        for (status, attempts) in counts {
+            tx.execute(
+                "INSERT OR REPLACE INTO status_counts (run_started_at, http_status, attempts)
+                VALUES (?1, ?2, ?3)",
+                params![timestamp(run_started_at), status, attempts],
+            )?;
+        }
+        tx.commit()
+    }
+
+    /// Attempts by HTTP status across every run logged.
+    pub fn status_counts(&self) -> rusqlite::Result<BTreeMap<u16, usize>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT http_status, SUM(attempts) FROM status_counts GROUP BY http_status")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect()
+    }
+
+    /// Store every parsed field of a recipe, replacing what was stored for
+    /// the file before. Ingredient lists are kept as JSON.
+    pub fn record_recipe(
+        &self,
+        filename: &str,
+        recipe_id: Option<u32>,
+        recipe: &Recipe,
+    ) -> Result<(), Box<dyn Error>> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO recipes
+                (filename, recipe_id, name, brewer, style_name, style_category,
+                 style_number, style_letter, og, fg, abv, ibu, color_srm,
+                 batch_size_l, boil_time_min, bsmx_version, fermentables, hops,
+                 yeasts, mash_steps, notes, taste_notes)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14,
+                    ?15, ?16, ?17, ?18, ?19, ?20, ?21, ?22)",
+            params![
+                filename,
+                recipe_id,
+                recipe.name,
+                recipe.brewer,
+                recipe.style.name,
+                recipe.style.category,
+                recipe.style.number,
+                recipe.style.letter,
+                recipe.og,
+                recipe.fg,
+                recipe.abv,
+                recipe.ibu,
+                recipe.color_srm,
+                recipe.batch_size_l,
+                recipe.boil_time_min,
+                serde_json::to_value(recipe.bsmx_version)?
+                    .as_str()
+                    .unwrap_or_default(),
+                serde_json::to_string(&recipe.fermentables)?,
+                serde_json::to_string(&recipe.hops)?,
+                serde_json::to_string(&recipe.yeasts)?,
+                serde_json::to_string(&recipe.mash_steps)?,
+                recipe.notes,
+                recipe.taste_notes,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Recipes matching `query`, ordered by name.
+    pub fn search(&self, query: &RecipeQuery) -> rusqlite::Result<Vec<FoundRecipe>> {
+        let (clause, values) = query.where_clause();
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT filename, recipe_id, name, brewer, style_name, abv, ibu
+            FROM recipes WHERE {}
+            ORDER BY name COLLATE NOCASE, filename",
+            clause
+        ))?;
+        let rows = stmt.query_map(params_from_iter(values), found_from_row)?;
+        rows.collect()
+    }
+
+    pub fn counts(&self) -> rusqlite::Result<Counts> {
+        let count = |table: &str| {
+            self.conn
+                .query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| {
+                    row.get(0)
+                })
+        };
+        Ok(Counts {
+            downloads: count("downloads")?,
+            attempts: count("attempts")?,
+            recipes: count("recipes")?,
+        })
+    }
+}
+
+/// Which recipes a search returns. Unset fields match everything, and text
+/// matches are substrings ignoring ASCII case.
+#[derive(Debug, Clone, Default)]
+pub struct RecipeQuery {
+    pub name: Option<String>,
+    pub style: Option<String>,
+    pub brewer: Option<String>,
+    pub min_abv: Option<f64>,
+    pub max_abv: Option<f64>,
+    pub min_ibu: Option<f64>,
+    pub max_ibu: Option<f64>,
+    /// In either the notes or the tasting notes
+    pub notes_contains: Option<String>,
+}
+
+impl RecipeQuery {
+    fn where_clause(&self) -> (String, Vec<Value>) {
+        let mut conditions = vec!["1".to_string()];
+        let mut values = Vec::new();
+        let text = [
+            ("name", &self.name),
+            ("style_name", &self.style),
+            ("brewer", &self.brewer),
+        ];
+        for (column, wanted) in text {
+            if let Some(wanted) = wanted {
+                values.push(Value::Text(like_pattern(&wanted.to_lowercase())));
+                conditions.push(format!(
+                    "casefold({}) LIKE ?{} ESCAPE '\\'",
+                    column,
+                    values.len()
+                ));
+            }
+        }
+        if let Some(wanted) = &self.notes_contains {
+            values.push(Value::Text(like_pattern(&wanted.to_lowercase())));
+            conditions.push(format!(
+                "(casefold(notes) LIKE ?{0} ESCAPE '\\' OR casefold(taste_notes) LIKE ?{0} ESCAPE '\\')",
+                values.len()
+            ));
+        }
+        let bounds = [
+            ("abv >=", self.min_abv),
+            ("abv <=", self.max_abv),
+            ("ibu >=", self.min_ibu),
+            ("ibu <=", self.max_ibu),
+        ];
+        for (comparison, bound) in bounds {
+            if let Some(bound) = bound {
+                values.push(Value::Real(bound));
+                conditions.push(format!("{} ?{}", comparison, values.len()));
+            }
+        }
+        (conditions.join(" AND "), values)
+    }
+}
+
+fn found_from_row(row: &Row) -> rusqlite::Result<FoundRecipe> {
+    Ok(FoundRecipe {
+        filename: row.get(0)?,
+        recipe_id: row.get(1)?,
+        name: row.get(2)?,
+        brewer: row.get(3)?,
+        style: row.get(4)?,
+        abv: row.get(5)?,
+        ibu: row.get(6)?,
+    })
+}
+
+// Milliseconds keep a run's attempts at the same ID apart
+fn timestamp(at: DateTime<Utc>) -> String {
+    at.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
@@ -0,0 +1,59 @@
+use crate::cli::IpVersion;
+#[cfg(feature = "dns-control")]
+use hyper::client::connect::dns::Name;
+#[cfg(feature = "dns-control")]
+use reqwest::dns::{Addrs, Resolve, Resolving};
+use std::net::IpAddr;
+#[cfg(feature = "dns-control")]
+use std::net::SocketAddr;
+
+impl IpVersion {
+    /// Whether connections may use `ip`.
+    pub fn allows(self, ip: IpAddr) -> bool {
+        match self {
+            IpVersion::V4 => ip.is_ipv4(),
+            IpVersion::V6 => ip.is_ipv6(),
+            IpVersion::Both => true,
+        }
+    }
+
+    /// The only version a socket bound to `ip` can connect over.
+    pub fn of(ip: IpAddr) -> Self {
+        if ip.is_ipv4() {
+            IpVersion::V4
+        } else {
+            IpVersion::V6
+        }
+    }
+}
+
+/// Looks host names up with the system resolver, like reqwest's default,
+/// but keeps only the addresses of one IP version.
+#[cfg(feature = "dns-control")]
+#[derive(Debug, Clone, Copy)]
+pub struct FamilyResolver {
+    pub version: IpVersion,
+}
+
+#[cfg(feature = "dns-control")]
+impl Resolve for FamilyResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let version = self.version;
+        Box::pin(async move {
+            let host = name.as_str();
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, 0))
+                .await?
+                .filter(|addr| version.allows(addr.ip()))
+                .collect();
+            if addrs.is_empty() {
+                let family = if version == IpVersion::V4 {
+                    "IPv4"
+                } else {
+                    "IPv6"
+                };
+                return Err(format!("{} has no {} address", host, family).into());
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
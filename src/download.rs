@@ -0,0 +1,3342 @@
+use crate::bsmx::{
+    parse_document, parse_timeout, recipe_from_document, within_parse_timeout, ParseTimedOut,
+};
+use crate::bulk::{attempt_group, BulkFetch};
+use crate::cli::{
+    Compression, DownloadArgs, FetchMode, IpVersion, Mode, Precheck, ProgressMode, StorageLayout,
+};
+use crate::collection::{
+    file_name, index_download, scan_stems, write_sidecar, Provenance, PARSE_FAILED_DIR,
+};
+use crate::commands::discover::crawl;
+use crate::completeness::completeness_score;
+use crate::compression::{bsmx_stem, read_bsmx};
+use crate::cookies::{load_netscape_cookies, parse_netscape_cookies, CookieJar};
+use crate::db::{ActivityDb, Attempt};
+#[cfg(feature = "dns-control")]
+use crate::dns::FamilyResolver;
+use crate::fixtures::{Fixtures, MockResponse};
+use crate::ids::{read_id_file, IdTracker};
+use crate::index::Index;
+use crate::limits::{RollingHitRate, TimeLimit};
+use crate::listing::Search;
+use crate::logging;
+use crate::login::{Login, MAX_LOGINS_IN_A_ROW};
+use crate::metrics::{self, Metrics, Remaining};
+use crate::migrate;
+use crate::mirrors::Mirrors;
+use crate::notify::{self, Notification};
+use crate::preflight::{self, Preflight};
+use crate::probe;
+use crate::progress::{
+    format_bytes, BarStyle, Counts, Event, FileBar, FileBars, Output, Reporter, SCHEMA_VERSION,
+};
+use crate::proxy::{self, ProxyConfig, ProxyPool};
+use crate::robots::{self, ROBOTS_AGENT};
+use crate::run_report::{default_report_path, ResponseStats, RunConfig, RunLog, RunReport};
+use crate::sampling::IdPicker;
+use crate::state::{until_midnight, State};
+use crate::stratify::{self, Plan, StyleQuota};
+use crate::throttle::{
+    parse_retry_after, ByteLimiter, RequestGate, TransferMeter, DEFAULT_RETRY_AFTER,
+};
+use crate::user_agents::UserAgents;
+use crate::RECIPES_DIR;
+use chrono::Utc;
+use flate2::read::MultiGzDecoder;
+use indicatif::{ProgressBar, ProgressState, ProgressStyle};
+use reqwest::header::{
+    HeaderMap, HeaderName, HeaderValue, ACCEPT_ENCODING, ACCEPT_RANGES, CONTENT_DISPOSITION,
+    CONTENT_ENCODING, CONTENT_RANGE, CONTENT_TYPE, LOCATION, RANGE, RETRY_AFTER,
+    USER_AGENT as USER_AGENT_HEADER,
+};
+use reqwest::redirect::Policy;
+use reqwest::{Certificate, Client, Request, RequestBuilder, Response, StatusCode, Url};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io::{self, IsTerminal, Read};
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{debug, error, info, info_span, warn, Instrument};
+
+/// Recipes a random run collects unless --target says otherwise
+pub const DEFAULT_TARGET: usize = 10_000;
+/// Added to a recipe ID to name the file an interrupted download is kept
+/// in until it can be resumed
+pub const PARTIAL_SUFFIX: &str = ".bsmx.part";
+pub const MIN_RECIPE_ID: u32 = 1;
+pub const MAX_RECIPE_ID: u32 = 4_000_000;
+pub(crate) const CONCURRENT_REQUESTS: usize = 10;
+pub const DEFAULT_BASE_URL: &str = "https://redacted-recipes.com";
+/// How long connecting may take, unless --connect-timeout-ms says
+/// otherwise. Short, so a dead host is given up on quickly
+pub const DEFAULT_CONNECT_TIMEOUT_MS: u64 = 5_000;
+/// How long a download may go without receiving anything
+pub const DEFAULT_READ_TIMEOUT_MS: u64 = 10_000;
+/// How long a request may take, connecting included. Long enough for a
+/// big bulk export
+pub const DEFAULT_REQUEST_TIMEOUT_MS: u64 = 30_000;
+/// Redirects a request follows before giving up, unless --max-redirects
+/// says otherwise
+pub const DEFAULT_MAX_REDIRECTS: usize = 10;
+/// Exit status for a run that stopped short of its target, e.g. on the
+/// daily quota, so cron jobs can tell it from a finished one
+pub const EXIT_PARTIAL: i32 = 2;
+/// Exit status for a run stopped by SIGINT or SIGTERM
+pub const EXIT_INTERRUPTED: i32 = 3;
+/// Exit status for bad configuration or an I/O error
+pub const EXIT_FATAL: i32 = 4;
+/// Exit status for a run that gave up because the site stopped answering
+pub const EXIT_CIRCUIT_OPEN: i32 = 5;
+/// Requests in a row that can fail to reach the site before the run gives
+/// up on it
+const CIRCUIT_BREAKER_THRESHOLD: usize = 50;
+/// Attempts a run makes before giving up on its target. Enough for 10,000
+/// recipes at a 1% hit rate, but finite so an unattended run always ends
+pub const DEFAULT_MAX_ATTEMPTS: usize = 1_000_000;
+/// Hit rate, in percent, below which a run decides the IDs have dried up
+pub const DEFAULT_MIN_HIT_RATE: f64 = 1.0;
+/// Attempts the hit rate floor is measured over
+pub const DEFAULT_HIT_RATE_WINDOW: usize = 2000;
+/// IDs in a row without a recipe that mark the end of the site's IDs
+pub const DEFAULT_FRONTIER_MISSES: usize = 200;
+/// IDs below the highest downloaded one that newest mode checks again
+pub const DEFAULT_OVERLAP: u32 = 100;
+/// Stands in for an HTTP status in [`DownloadStats::status_counts`] for
+/// attempts the server never answered: timeouts and connection errors
+pub const NO_RESPONSE: u16 = 0;
+/// Pause between batches of requests, to avoid overwhelming the server
+pub const BATCH_DELAY: Duration = Duration::from_millis(100);
+/// Where a run over listed IDs writes the ones that failed or weren't tried
+pub const FAILED_IDS_FILE: &str = "failed_ids.txt";
+pub(crate) const USER_AGENT: &str = "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/605.1.15 (KHTML, like Gecko) Mobile/15E148";
+
+/// Where and how recipes are fetched.
+#[derive(Debug, Clone)]
+pub struct DownloadConfig {
+    /// Site root; recipes are fetched from `{base_url}/download.php?id=N`.
+    /// With mirrors, the first of them
+    pub base_url: String,
+    /// Every --base-url, when there's more than one for recipe downloads
+    /// to fail over between
+    pub mirrors: Option<Arc<Mirrors>>,
+    /// Where big files get bars of their own while they download, when the
+    /// run has a progress bar to put them under
+    pub file_bars: Option<FileBars>,
+    /// Directory downloaded recipes are written to
+    pub output_dir: PathBuf,
+    /// How long connecting may take
+    pub connect_timeout: Duration,
+    /// How long a download may go between chunks of its body
+    pub read_timeout: Duration,
+    /// How long a request may take, connecting included
+    pub request_timeout: Duration,
+    /// Hold recipe downloads to the request timeout only until their
+    /// response starts
+    pub stream_past_request_timeout: bool,
+    /// Redirects a request follows before failing
+    pub max_redirects: usize,
+    /// Refuse redirects to another host
+    pub same_host_redirects_only: bool,
+    /// Ask for recipes compressed in transit
+    pub transfer_compression: bool,
+    /// Also write each recipe's parsed metadata to `<stem>.json`
+    pub sidecar: bool,
+    /// Fetch and check recipes but don't write them anywhere
+    pub dry_run: bool,
+    /// Cookies sent with every request, for sites that need a login
+    pub cookies: Option<Arc<CookieJar>>,
+    /// Extra trusted root certificates
+    pub root_certificates: Vec<Certificate>,
+    /// Skip TLS certificate validation entirely
+    pub accept_invalid_certs: bool,
+    /// Discard recipes with a lower completeness score
+    pub skip_incomplete: Option<f64>,
+    /// Local address outbound connections are made from
+    pub local_address: Option<IpAddr>,
+    /// IP versions host names may resolve to; only enforced when built
+    /// with the `dns-control` feature
+    pub ip_version: IpVersion,
+    /// How saved files are arranged in the output directory
+    pub storage_layout: StorageLayout,
+    /// Check IDs with HEAD before downloading them
+    pub head_precheck: Option<Arc<HeadPrecheck>>,
+    /// Shared cap on the bytes per second downloaded
+    pub bandwidth: Option<Arc<ByteLimiter>>,
+    /// What every request waits on before it's sent
+    pub requests: Arc<RequestGate>,
+    /// Counts the bytes every transfer receives
+    pub transfers: Arc<TransferMeter>,
+    /// Statuses and timings of the recipe GETs
+    pub responses: Arc<ResponseStats>,
+    /// Styles a --stratified run still has room for
+    pub style_quota: Option<Arc<StyleQuota>>,
+    /// How saved recipes are compressed
+    pub compression: Compression,
+    /// Level to compress them at, or the format's default
+    pub compression_level: Option<i32>,
+    /// Fetch several IDs per request through the bulk export
+    pub bulk: Option<Arc<BulkFetch>>,
+    /// Proxy every request goes through; with a pool, the first of them,
+    /// for the requests outside the rotation
+    pub proxy: Option<ProxyConfig>,
+    /// --proxy-list, which the downloads are spread over
+    pub proxy_pool: Option<Arc<ProxyPool>>,
+    /// Sent with every request, after the built-in ones
+    pub headers: Vec<RequestHeader>,
+    /// The User-Agent, or the pool recipe requests pick one from
+    pub user_agents: UserAgents,
+    /// The account to log in with, which also makes the site's login page
+    /// mean the session has run out
+    pub login: Option<Arc<Login>>,
+    /// --test-mode's fixtures, which answer recipe requests instead of the
+    /// site
+    pub test_mode: Option<Arc<Fixtures>>,
+}
+
+impl Default for DownloadConfig {
+    fn default() -> Self {
+        DownloadConfig {
+            base_url: DEFAULT_BASE_URL.to_string(),
+            mirrors: None,
+            file_bars: None,
+            output_dir: PathBuf::from(RECIPES_DIR),
+            connect_timeout: Duration::from_millis(DEFAULT_CONNECT_TIMEOUT_MS),
+            read_timeout: Duration::from_millis(DEFAULT_READ_TIMEOUT_MS),
+            request_timeout: Duration::from_millis(DEFAULT_REQUEST_TIMEOUT_MS),
+            stream_past_request_timeout: false,
+            max_redirects: DEFAULT_MAX_REDIRECTS,
+            same_host_redirects_only: false,
+            transfer_compression: true,
+            sidecar: false,
+            dry_run: false,
+            cookies: None,
+            root_certificates: Vec::new(),
+            accept_invalid_certs: false,
+            skip_incomplete: None,
+            local_address: None,
+            ip_version: IpVersion::Both,
+            storage_layout: StorageLayout::Flat,
+            head_precheck: None,
+            bandwidth: None,
+            requests: Arc::new(RequestGate::new(None)),
+            transfers: Arc::default(),
+            responses: Arc::default(),
+            style_quota: None,
+            compression: Compression::None,
+            compression_level: None,
+            bulk: None,
+            proxy: None,
+            proxy_pool: None,
+            headers: Vec::new(),
+            user_agents: UserAgents::default(),
+            login: None,
+            test_mode: None,
+        }
+    }
+}
+
+/// IDs each request asks for: a bulk group, or just one.
+fn bulk_group_size(config: &DownloadConfig) -> usize {
+    config.bulk.as_ref().map_or(1, |bulk| bulk.group_size())
+}
+
+/// The pause between batches: [`BATCH_DELAY`], unless --max-rps is
+/// spacing the requests out instead.
+pub(crate) fn batch_delay(requests: &RequestGate) -> Duration {
+    match requests.max_rps() {
+        Some(_) => Duration::ZERO,
+        None => BATCH_DELAY,
+    }
+}
+
+/// A header from --header, given as `Name: value`. Only the first colon
+/// splits, so the value may hold more.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RequestHeader {
+    pub name: HeaderName,
+    pub value: HeaderValue,
+}
+
+impl FromStr for RequestHeader {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, value) = s
+            .split_once(':')
+            .ok_or_else(|| format!("expected NAME:VALUE, got {:?}", s))?;
+        let name = HeaderName::from_bytes(name.trim().as_bytes())
+            .map_err(|_| format!("{:?} is not a valid header name", name.trim()))?;
+        let mut value = HeaderValue::from_str(value.trim())
+            .map_err(|_| format!("the value for {} has characters a header can't hold", name))?;
+        // Often a token, so kept out of debug output
+        value.set_sensitive(true);
+        Ok(RequestHeader { name, value })
+    }
+}
+
+/// What a run's HEAD prechecks have learned, shared by its requests.
+#[derive(Debug, Default)]
+pub struct HeadPrecheck {
+    /// Set once the server shows it doesn't answer HEAD properly
+    unsupported: AtomicBool,
+    avoided: AtomicUsize,
+}
+
+impl HeadPrecheck {
+    /// GETs skipped because the HEAD answer ruled the ID out.
+    pub fn gets_avoided(&self) -> usize {
+        self.avoided.load(Ordering::Relaxed)
+    }
+
+    /// Whether HEAD requests are still worth sending.
+    pub fn is_supported(&self) -> bool {
+        !self.unsupported.load(Ordering::Relaxed)
+    }
+}
+
+pub fn build_client(config: &DownloadConfig) -> reqwest::Result<Client> {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        USER_AGENT_HEADER,
+        config.user_agents.default_value().clone(),
+    );
+    // A --header for one of these replaces it
+    for header in &config.headers {
+        headers.insert(header.name.clone(), header.value.clone());
+    }
+    // The environment's proxy is only used if download_config picked it
+    let mut builder = Client::builder()
+        .default_headers(headers)
+        .timeout(config.request_timeout)
+        .connect_timeout(config.connect_timeout)
+        .danger_accept_invalid_certs(config.accept_invalid_certs)
+        .redirect(redirect_policy(config))
+        .no_proxy();
+    if let Some(proxy) = &config.proxy {
+        builder = builder.proxy(proxy.to_reqwest()?);
+    }
+    if let Some(jar) = &config.cookies {
+        builder = builder.cookie_provider(jar.clone());
+    }
+    for cert in &config.root_certificates {
+        builder = builder.add_root_certificate(cert.clone());
+    }
+    if config.local_address.is_some() {
+        builder = builder.local_address(config.local_address);
+    }
+    #[cfg(feature = "dns-control")]
+    if config.ip_version != IpVersion::Both {
+        builder = builder.dns_resolver(Arc::new(FamilyResolver {
+            version: config.ip_version,
+        }));
+    }
+    builder.build()
+}
+
+/// Follow up to --max-redirects redirects. One to another host is refused
+/// with --same-host-redirects-only. With --header values it's handed back
+/// instead, for [`send_download`] to follow without them: reqwest keeps
+/// cookies and credentials to their own host, but would send those along.
+fn redirect_policy(config: &DownloadConfig) -> Policy {
+    let max = config.max_redirects;
+    let same_host_only = config.same_host_redirects_only;
+    let custom_headers = !config.headers.is_empty();
+    Policy::custom(move |attempt| {
+        // The original request is the first of these
+        if attempt.previous().len() > max {
+            return attempt.error(RedirectRefused::TooMany(max));
+        }
+        let host = attempt.url().host_str().unwrap_or_default().to_string();
+        let cross_host = attempt
+            .previous()
+            .first()
+            .is_some_and(|origin| origin.host_str() != Some(host.as_str()));
+        if cross_host && same_host_only {
+            return attempt.error(RedirectRefused::CrossHost(host));
+        }
+        if cross_host && custom_headers {
+            return attempt.stop();
+        }
+        attempt.follow()
+    })
+}
+
+/// Why a redirect wasn't followed.
+#[derive(Debug)]
+pub enum RedirectRefused {
+    /// More than this many in a row
+    TooMany(usize),
+    /// To another host, with --same-host-redirects-only
+    CrossHost(String),
+}
+
+impl fmt::Display for RedirectRefused {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RedirectRefused::TooMany(max) => write!(f, "more than {} redirects", max),
+            RedirectRefused::CrossHost(host) => write!(f, "redirected to another host, {}", host),
+        }
+    }
+}
+
+impl Error for RedirectRefused {}
+
+/// The server turned a request away with 401 Unauthorized, which on a
+/// login-gated site means the session has expired.
+#[derive(Debug)]
+pub struct SessionExpired;
+
+impl fmt::Display for SessionExpired {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "server answered 401 Unauthorized")
+    }
+}
+
+impl Error for SessionExpired {}
+
+/// The server turned a request away with 429 Too Many Requests, asking for
+/// this long before the next.
+#[derive(Debug)]
+pub struct RateLimited(pub Duration);
+
+impl fmt::Display for RateLimited {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "server answered 429 Too Many Requests; retry in {}s",
+            self.0.as_secs()
+        )
+    }
+}
+
+impl Error for RateLimited {}
+
+/// A download stopped by one of the timeouts reqwest doesn't keep itself.
+#[derive(Debug)]
+pub enum TimedOut {
+    /// Nothing arrived for this long partway through the body
+    Read(Duration),
+    /// The response hadn't started after this long
+    Request(Duration),
+}
+
+impl fmt::Display for TimedOut {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TimedOut::Read(after) => write!(f, "nothing received for {}", format_duration(*after)),
+            TimedOut::Request(after) => {
+                write!(f, "no response within {}", format_duration(*after))
+            }
+        }
+    }
+}
+
+impl Error for TimedOut {}
+
+/// Why a download run ended without finishing, which decides its exit
+/// status.
+#[derive(Debug)]
+pub enum RunError {
+    /// Stopped by a limit, such as the daily quota, before the target
+    Partial {
+        reason: &'static str,
+        summary: String,
+    },
+    /// Stopped by SIGINT or SIGTERM after finishing the batch in flight
+    Interrupted { summary: String },
+    /// Gave up after this many requests in a row couldn't reach the site
+    CircuitOpen { failures: usize },
+    /// Bad configuration, or an I/O error the run can't carry on from
+    Fatal(Box<dyn Error>),
+}
+
+impl RunError {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            RunError::Partial { .. } => EXIT_PARTIAL,
+            RunError::Interrupted { .. } => EXIT_INTERRUPTED,
+            RunError::CircuitOpen { .. } => EXIT_CIRCUIT_OPEN,
+            RunError::Fatal(_) => EXIT_FATAL,
+        }
+    }
+}
+
+/// The status line's keyword comes first so scripts can match on it.
+impl fmt::Display for RunError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RunError::Partial { reason, summary } => write!(f, "partial: {}, {}", reason, summary),
+            RunError::Interrupted { summary } => write!(f, "interrupted: {}", summary),
+            RunError::CircuitOpen { failures } => write!(
+                f,
+                "circuit-open: {} requests in a row could not reach the site",
+                failures
+            ),
+            RunError::Fatal(e) => write!(f, "fatal: {}", e),
+        }
+    }
+}
+
+// Not an `Error` itself, so that any error can be turned into a fatal one
+// with `?`
+impl<E: Into<Box<dyn Error>>> From<E> for RunError {
+    fn from(e: E) -> Self {
+        RunError::Fatal(e.into())
+    }
+}
+
+/// Print a download run's one-line status to stderr and exit with its
+/// status code.
+pub fn exit_with(result: Result<String, RunError>) -> ! {
+    match result {
+        Ok(summary) => {
+            eprintln!("beerscape: complete: {}", summary);
+            process::exit(0);
+        }
+        Err(e) => {
+            eprintln!("beerscape: {}", e);
+            process::exit(e.exit_code());
+        }
+    }
+}
+
+pub(crate) enum Outcome {
+    Saved(RecipeInfo),
+    Failed {
+        category: &'static str,
+        status: Option<u16>,
+    },
+    SessionExpired,
+    /// Answered 429, to be tried again once the wait is over
+    RateLimited {
+        retry_after: Duration,
+    },
+}
+
+/// What the server gave back for an ID, short of a transport failure.
+/// The rejections carry the status the content came with.
+pub(crate) enum Fetched {
+    Saved(RecipeInfo),
+    Status(StatusCode),
+    InvalidContent(StatusCode),
+    /// Below the --skip-incomplete score
+    Incomplete(StatusCode),
+    /// Of a style with its share of a --stratified sample already
+    StyleFull(StatusCode),
+    /// The site's login form, for a run that logs in
+    LoginPage,
+    /// Took longer than the parse timeout to check
+    ParseTimeout(StatusCode),
+    /// Answered 429, with the wait the server asked for
+    RateLimited(Duration),
+}
+
+/// Tallies for a download run. Recipes already on disk are counted apart
+/// from the ones this run downloads, so neither can be mistaken for the
+/// other.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DownloadStats {
+    /// Recipes on disk before the run started
+    pub existing: usize,
+    /// Recipes this run saved
+    pub downloaded: usize,
+    pub failed: usize,
+    /// IDs this run tried, whatever came of them
+    pub attempted: usize,
+    /// Bytes this run downloaded
+    pub bytes: usize,
+    /// Bytes this run received, including transfers that broke off or
+    /// were turned away
+    pub transferred: u64,
+    /// Bytes of the complete transfers that arrived compressed, as
+    /// received and as decompressed
+    pub compressed: u64,
+    pub decompressed: u64,
+    /// GETs the --precheck requests made unnecessary, if there were
+    /// prechecks
+    pub gets_avoided: Option<usize>,
+    /// Write byte counts as plain bytes rather than KiB or MiB
+    pub raw_bytes: bool,
+    /// Attempts by the HTTP status they were answered with, or
+    /// [`NO_RESPONSE`] for those the server never answered
+    pub status_counts: HashMap<u16, usize>,
+    /// Recipes set aside for taking longer than the parse timeout
+    pub parse_timeouts: usize,
+}
+
+impl DownloadStats {
+    /// Recipes on disk now.
+    pub fn on_disk(&self) -> usize {
+        self.existing + self.downloaded
+    }
+
+    /// This run's downloads as a share of its attempts, or `None` if it
+    /// made none.
+    pub fn success_rate(&self) -> Option<f64> {
+        (self.attempted > 0).then(|| self.downloaded as f64 / self.attempted as f64)
+    }
+
+    /// Count an attempt answered with `status`, or not answered at all.
+    pub fn count_status(&mut self, status: Option<u16>) {
+        *self
+            .status_counts
+            .entry(status.unwrap_or(NO_RESPONSE))
+            .or_default() += 1;
+    }
+
+    /// Mean size of the recipes this run saved, or `None` if it saved none.
+    pub fn average_recipe_size(&self) -> Option<u64> {
+        (self.downloaded > 0).then(|| self.bytes as u64 / self.downloaded as u64)
+    }
+
+    /// The summary printed at the end of a run.
+    pub fn summary(&self) -> String {
+        let rate = match self.success_rate() {
+            Some(rate) => format!("{:.1}%", rate * 100.0),
+            None => "n/a, nothing attempted".to_string(),
+        };
+        let mut summary = format!(
+            "Download Summary:\n\
+             ----------------\n\
+             Previously Existing: {}\n\
+             Newly Downloaded: {}\n\
+             Failed Attempts: {}\n\
+             Total Attempts: {}\n\
+             Final Success Rate: {}",
+            self.existing, self.downloaded, self.failed, self.attempted, rate
+        );
+        if self.transferred > 0 {
+            summary.push_str(&format!(
+                "\nData Downloaded: {}\nData Transferred: {}",
+                format_bytes(self.bytes as u64, self.raw_bytes),
+                format_bytes(self.transferred, self.raw_bytes)
+            ));
+            if self.compressed > 0 {
+                summary.push_str(&format!(
+                    "\nCompressed in Transit: {} for {} ({:.1}% saved)",
+                    format_bytes(self.compressed, self.raw_bytes),
+                    format_bytes(self.decompressed, self.raw_bytes),
+                    100.0 - self.compressed as f64 * 100.0 / self.decompressed.max(1) as f64
+                ));
+            }
+            if let Some(average) = self.average_recipe_size() {
+                summary.push_str(&format!(
+                    "\nAverage Recipe Size: {}",
+                    format_bytes(average, self.raw_bytes)
+                ));
+            }
+        }
+        if let Some(avoided) = self.gets_avoided {
+            summary.push_str(&format!("\nGETs Avoided by Precheck: {}", avoided));
+        }
+        if self.parse_timeouts > 0 {
+            summary.push_str(&format!("\nParse Timeouts: {}", self.parse_timeouts));
+        }
+        if !self.status_counts.is_empty() {
+            summary.push_str("\nResponses by Status:");
+            let mut counts: Vec<_> = self.status_counts.iter().collect();
+            counts.sort();
+            for (&status, count) in counts {
+                if status == NO_RESPONSE {
+                    summary.push_str(&format!("\n  no response: {}", count));
+                } else {
+                    summary.push_str(&format!("\n  {}: {}", status, count));
+                }
+            }
+        }
+        summary
+    }
+}
+
+#[derive(Debug)]
+pub struct RecipeInfo {
+    pub filename: String,
+    /// Size of the downloaded file
+    pub bytes: usize,
+    /// What the server answered with: 200, or 206 for a resumed transfer
+    pub status: u16,
+}
+
+/// Where and how to fetch recipes, from a download run's arguments.
+pub fn download_config(args: &DownloadArgs) -> Result<DownloadConfig, Box<dyn Error>> {
+    let login = Login::from_args(args)?;
+    // A login needs somewhere to keep the session cookie
+    let cookies = match &args.cookies {
+        Some(path) => Some(Arc::new(parse_netscape_cookies(path)?)),
+        None if login.is_some() => Some(Arc::default()),
+        None => None,
+    };
+    let root_certificates = match &args.tls_ca_bundle {
+        Some(path) => load_ca_bundle(path)?,
+        None => Vec::new(),
+    };
+    if args.no_verify_ssl {
+        // Printed directly rather than logged so that -q can't hide it
+        eprintln!(
+            "WARNING: --no-verify-ssl disables ALL TLS certificate validation. \
+             Any server, including an attacker intercepting the connection, \
+             will be trusted. This is a security risk; use --tls-ca-bundle \
+             for servers with self-signed certificates instead."
+        );
+    }
+    // A socket bound to one version can't connect over the other, so
+    // resolving to it would only produce connection errors
+    let ip_version = match (args.ip_version, args.bind_address) {
+        (Some(version), Some(ip)) if !version.allows(ip) => {
+            return Err(format!(
+                "--bind-address {} can't be used with --ip-version {:?}",
+                ip, version
+            )
+            .into())
+        }
+        (Some(version), _) => version,
+        (None, Some(ip)) => IpVersion::of(ip),
+        (None, None) => IpVersion::Both,
+    };
+    if !cfg!(feature = "dns-control") && args.ip_version.is_some_and(|v| v != IpVersion::Both) {
+        return Err("--ip-version needs beerscape built with `--features dns-control`".into());
+    }
+    if let Some(rps) = args.max_rps.filter(|r| !(r.is_finite() && *r > 0.0)) {
+        return Err(format!("--max-rps {} is not a positive number of requests", rps).into());
+    }
+    let connect_timeout = timeout(
+        args.connect_timeout_ms,
+        args.connect_timeout,
+        "--connect-timeout",
+    )?;
+    let read_timeout = timeout(args.read_timeout_ms, args.read_timeout, "--read-timeout")?;
+    let request_timeout = timeout(
+        args.request_timeout_ms,
+        args.request_timeout,
+        "--request-timeout",
+    )?;
+    let base_urls: Vec<String> = args
+        .base_url
+        .iter()
+        .map(|url| url.trim_end_matches('/').to_string())
+        .collect();
+    for (n, url) in base_urls.iter().enumerate() {
+        if base_urls[..n].contains(url) {
+            return Err(format!("--base-url {} is given twice", url).into());
+        }
+    }
+    let mut config = DownloadConfig {
+        base_url: base_urls[0].clone(),
+        connect_timeout,
+        read_timeout,
+        request_timeout,
+        stream_past_request_timeout: args.no_total_timeout_when_streaming,
+        max_redirects: args.max_redirects,
+        same_host_redirects_only: args.same_host_redirects_only,
+        transfer_compression: !args.no_compression,
+        // A proxy list stands in for the environment's proxy too, and test
+        // mode has no use for either
+        proxy: match &args.proxy_list {
+            Some(_) => None,
+            None if args.test_mode.is_some() => None,
+            None => ProxyConfig::resolve(
+                args.proxy.as_deref(),
+                args.proxy_user.as_deref(),
+                &base_urls[0],
+            )?,
+        },
+        headers: args.header.clone(),
+        user_agents: user_agents(args)?,
+        login: login.map(Arc::new),
+        sidecar: args.sidecar,
+        dry_run: args.dry_run,
+        cookies,
+        root_certificates,
+        accept_invalid_certs: args.no_verify_ssl,
+        skip_incomplete: args.skip_incomplete,
+        local_address: args.bind_address,
+        ip_version,
+        head_precheck: args
+            .precheck
+            .map(|Precheck::Head| Arc::new(HeadPrecheck::default())),
+        bandwidth: args
+            .max_bandwidth
+            .map(|rate| Arc::new(ByteLimiter::new(rate, args.bandwidth_burst.unwrap_or(rate)))),
+        requests: Arc::new(RequestGate::new(args.max_rps)),
+        compression: args.output_compression,
+        compression_level: args.compression_level,
+        test_mode: args
+            .test_mode
+            .as_deref()
+            .map(|dir| Fixtures::load(dir, Duration::from_millis(args.mock_latency_ms)))
+            .transpose()?
+            .map(Arc::new),
+        bulk: match args.fetch_mode {
+            FetchMode::Single => None,
+            FetchMode::Bulk => Some(Arc::new(BulkFetch::new(
+                args.bulk_size.into(),
+                &args.bulk_url,
+            )?)),
+        },
+        ..Default::default()
+    };
+    if base_urls.len() > 1 {
+        config.mirrors = Some(Arc::new(Mirrors::new(
+            &base_urls,
+            config.requests.clone(),
+            args.max_rps,
+        )));
+    }
+    config.storage_layout = match args.storage_layout {
+        Some(layout) => layout,
+        None => State::load(&config.output_dir)?.storage_layout,
+    };
+    if let Some(path) = &args.proxy_list {
+        let pool = ProxyPool::load(
+            path,
+            args.proxy_user.as_deref(),
+            args.proxy_rotation,
+            args.per_proxy_concurrency.map(usize::from),
+            &config,
+        )?;
+        config.proxy = Some(pool.first());
+        config.proxy_pool = Some(Arc::new(pool));
+    }
+    Ok(config)
+}
+
+/// A timeout from its `-ms` option or, if given instead, the option in
+/// seconds.
+fn timeout(ms: u64, secs: Option<f64>, option: &str) -> Result<Duration, String> {
+    let Some(secs) = secs else {
+        return Ok(Duration::from_millis(ms));
+    };
+    let ms = secs * 1000.0;
+    if !(ms.is_finite() && ms >= 1.0) {
+        return Err(format!(
+            "{} {} is not a positive number of seconds",
+            option, secs
+        ));
+    }
+    Ok(Duration::from_millis(ms.round() as u64))
+}
+
+/// The timeouts in force, for the progress bar.
+fn timeouts(config: &DownloadConfig) -> String {
+    format!(
+        "Timeouts: connect {}, read {}, request {}{}",
+        format_duration(config.connect_timeout),
+        format_duration(config.read_timeout),
+        format_duration(config.request_timeout),
+        if config.stream_past_request_timeout {
+            " to start"
+        } else {
+            ""
+        }
+    )
+}
+
+/// Milliseconds under a second, otherwise seconds, e.g. `150ms` or `2.5s`.
+fn format_duration(duration: Duration) -> String {
+    let ms = duration.as_millis();
+    if ms < 1000 {
+        format!("{}ms", ms)
+    } else {
+        format!("{}s", duration.as_secs_f64())
+    }
+}
+
+/// The User-Agents from --user-agent, --user-agents and --ua-strategy,
+/// none of which go with a User-Agent set by --header.
+fn user_agents(args: &DownloadArgs) -> Result<UserAgents, String> {
+    let header = args
+        .header
+        .iter()
+        .any(|header| header.name == USER_AGENT_HEADER);
+    if header
+        && (args.user_agent.is_some() || args.user_agents.is_some() || args.ua_strategy.is_some())
+    {
+        return Err("a User-Agent --header can't be combined with --user-agent, --user-agents or --ua-strategy".into());
+    }
+    UserAgents::from_args(
+        args.user_agent.as_deref(),
+        args.user_agents.as_deref(),
+        args.ua_strategy,
+    )
+}
+
+/// The User-Agent for a recipe request: one set by --header, or the next
+/// from the pool.
+pub(crate) fn request_user_agent(config: &DownloadConfig) -> &HeaderValue {
+    config
+        .headers
+        .iter()
+        .rev()
+        .find(|header| header.name == USER_AGENT_HEADER)
+        .map_or_else(|| config.user_agents.pick(), |header| &header.value)
+}
+
+/// Run downloads until the target is reached or the listed IDs are done,
+/// returning the final summary.
+pub async fn run(args: &DownloadArgs, quiet: bool) -> Result<String, RunError> {
+    run_with_interrupt(args, quiet, None).await
+}
+
+/// [`run`], stopping after the batch in flight once `interrupted` is set.
+/// A caller that runs downloads again and again passes its own flag and
+/// watches for signals itself; without one the run installs its handlers
+/// for SIGINT, SIGTERM and SIGUSR1.
+pub async fn run_with_interrupt(
+    args: &DownloadArgs,
+    quiet: bool,
+    interrupted: Option<Arc<AtomicBool>>,
+) -> Result<String, RunError> {
+    let mut config = download_config(args)?;
+    if args.min_id > args.max_id {
+        return Err(format!("--min-id {} is above --max-id {}", args.min_id, args.max_id).into());
+    }
+    if !(0.0..=100.0).contains(&args.min_hit_rate) {
+        return Err(format!(
+            "--min-hit-rate {} is not a percentage between 0 and 100",
+            args.min_hit_rate
+        )
+        .into());
+    }
+    if let Some(score) = args.skip_incomplete.filter(|s| !(0.0..=1.0).contains(s)) {
+        return Err(format!("--skip-incomplete {} is not a score between 0 and 1", score).into());
+    }
+    if !(0.0..=1.0).contains(&args.exploration) {
+        return Err(format!(
+            "--exploration {} is not a fraction between 0 and 1",
+            args.exploration
+        )
+        .into());
+    }
+    if let Some(since) = args
+        .since_id
+        .filter(|id| !(args.min_id..=args.max_id).contains(id))
+    {
+        return Err(format!(
+            "--since-id {} is outside --min-id {} to --max-id {}",
+            since, args.min_id, args.max_id
+        )
+        .into());
+    }
+    let sequential = args.mode == Mode::Sequential;
+    let newest = args.mode == Mode::Newest;
+    if args.mode != Mode::Random
+        && (args.dry_run
+            || args.id_file.is_some()
+            || !args.ids.is_empty()
+            || !args.id_range.is_empty()
+            || !args.filter_style.is_empty()
+            || args.stratified)
+    {
+        return Err(format!(
+            "--mode {:?} chooses its own IDs, so it can't be combined with --dry-run, --id-file, --ids, --id-range, --filter-style or --stratified",
+            args.mode
+        )
+        .to_lowercase()
+        .into());
+    }
+    if args.stratified && !(args.oversample.is_finite() && args.oversample > 0.0) {
+        return Err(format!("--oversample {} is not a positive factor", args.oversample).into());
+    }
+    if newest && args.frontier_misses == 0 {
+        return Err("--frontier-misses must be at least 1".into());
+    }
+    if let Some(start) = args.start_at_id {
+        if !sequential && args.id_file.is_none() && args.ids.is_empty() && args.id_range.is_empty()
+        {
+            return Err(format!(
+                "--start-at-id only applies to sequential walks, with --mode sequential, --id-file, --ids or --id-range, not to --mode {:?}",
+                args.mode
+            )
+            .to_lowercase()
+            .into());
+        }
+        if !(args.min_id..=args.max_id).contains(&start) {
+            return Err(format!(
+                "--start-at-id {} is outside --min-id {} to --max-id {}",
+                start, args.min_id, args.max_id
+            )
+            .into());
+        }
+    }
+    if let Some(secs) = args.rate_limit.filter(|s| !(s.is_finite() && *s >= 0.0)) {
+        return Err(format!("--rate-limit {} is not a number of seconds", secs).into());
+    }
+    if let Some(pool) = &config.proxy_pool {
+        info!(
+            "Checking the proxies in {}",
+            args.proxy_list.as_ref().unwrap().display()
+        );
+        pool.check(&config.base_url).await?;
+        #[cfg(unix)]
+        pool.reload_on_sighup()?;
+    } else if let Some(proxy) = &config.proxy {
+        info!(%proxy, source = %proxy.source, "Checking the proxy");
+        proxy::check(proxy, &build_client(&config)?, &config.base_url).await?;
+    }
+    let mut rate_limit = args.rate_limit;
+    if args.respect_robots_txt {
+        let crawl_delay = check_robots(args, &config).await?;
+        if let (None, Some(delay)) = (rate_limit, crawl_delay) {
+            info!("Leaving {}s between requests, as robots.txt asks", delay);
+            rate_limit = Some(delay);
+        }
+    }
+    if let Some(login) = &config.login {
+        info!("Logging in at {}", login.url);
+        login.log_in(&build_client(&config)?, &config).await?;
+    }
+    if args.dry_run {
+        return probe::run(args, config, quiet).await;
+    }
+    // A rate limit sends requests one at a time, spaced out
+    let (concurrency, batch_delay) = match rate_limit {
+        Some(secs) => (
+            1,
+            batch_delay(&config.requests).max(Duration::from_secs_f64(secs)),
+        ),
+        None => (CONCURRENT_REQUESTS, batch_delay(&config.requests)),
+    };
+    let time_limit = TimeLimit::new(
+        Instant::now(),
+        args.max_duration.map(|d| d.0),
+        args.deadline.map(|t| t.0),
+    );
+    if let Some(limit) = &time_limit {
+        let left = limit.remaining().as_secs();
+        info!(
+            "Will stop starting downloads in {}h {:02}m",
+            left / 3600,
+            left % 3600 / 60
+        );
+    }
+    let output = if args.progress == ProgressMode::Json {
+        Output::Json
+    } else if args.progress_style == Some(BarStyle::Json) {
+        Output::JsonStatus
+    } else if quiet || args.progress_style == Some(BarStyle::None) {
+        Output::Summary
+    } else if args.no_progress || !io::stdout().is_terminal() {
+        Output::Lines
+    } else {
+        Output::Bar
+    };
+    let mut reporter = Reporter::new(output, args.target);
+    reporter.watch_transfers(config.transfers.clone(), args.raw_bytes);
+    if args.stratified {
+        let probed = stratify::survey(args, &config, &build_client(&config)?).await?;
+        let plan = Plan::new(args.sample, &probed, args.oversample);
+        reporter.log(format!("{}\n", plan));
+        config.style_quota = Some(Arc::new(StyleQuota::capped(&plan)));
+    }
+    let requested = requested_ids(args)?;
+    let listed = requested.is_some() || !args.filter_style.is_empty();
+    // The metrics read the run's totals from here
+    let shared_stats = Arc::new(Mutex::new(DownloadStats::default()));
+    let metrics = Arc::new(Metrics::new(shared_stats.clone()));
+    metrics.watch_requests(config.requests.clone());
+    let metrics_addr = args.metrics_addr.or(args
+        .metrics_port
+        .map(|port| SocketAddr::from(([0, 0, 0, 0], port))));
+    if let Some(addr) = metrics_addr {
+        metrics::serve(addr, metrics.clone()).await?;
+        info!("Serving metrics at http://{}/metrics", addr);
+    }
+
+    // Create recipes directory if it doesn't exist
+    fs::create_dir_all(&config.output_dir)?;
+
+    for upgrade in migrate::outdated(&config.output_dir)? {
+        warn!(
+            "{} is at schema version {} but this beerscape uses {}; run `beerscape migrate`",
+            upgrade.file, upgrade.from, upgrade.to
+        );
+    }
+    let mut state = State::load(&config.output_dir)?;
+    state.daily_quota.roll_over();
+    if let Some(max_per_day) = args.daily_quota {
+        state.daily_quota.max_per_day = max_per_day;
+    }
+    state.save(&config.output_dir)?;
+    if state.daily_quota.is_exhausted() {
+        print_quota_reached(&reporter, state.daily_quota.max_per_day);
+        return Err(RunError::Partial {
+            reason: "quota-reached",
+            summary: summary(0, 0),
+        });
+    }
+    let interrupted = match interrupted {
+        Some(flag) => flag,
+        None => {
+            #[cfg(unix)]
+            dump_stats_on_sigusr1(metrics.clone())?;
+            watch_for_interrupt()?
+        }
+    };
+
+    // Scan existing recipes
+    info!("Scanning existing recipes...");
+    let scan_pb = if reporter.shows_bar() {
+        ProgressBar::new_spinner()
+    } else {
+        ProgressBar::hidden()
+    };
+    scan_pb.set_style(
+        ProgressStyle::default_spinner()
+            .template("{spinner:.green} Scanned {pos} files ({per_sec})")?,
+    );
+    let mut existing_recipes = scan_stems(&config.output_dir, state.storage_layout, &scan_pb)?;
+    scan_pb.finish_and_clear();
+    // Saving in a second layout would hide one half of the collection from
+    // the other
+    if config.storage_layout != state.storage_layout {
+        if !existing_recipes.is_empty() {
+            return Err(format!(
+                "{} is in the {} layout; run `beerscape migrate-layout --to {}` to switch",
+                config.output_dir.display(),
+                format!("{:?}", state.storage_layout).to_lowercase(),
+                format!("{:?}", config.storage_layout).to_lowercase()
+            )
+            .into());
+        }
+        state.storage_layout = config.storage_layout;
+        state.save(&config.output_dir)?;
+    }
+
+    info!("Found {} existing recipes", existing_recipes.len());
+    let index = Index::open(&config.output_dir)?;
+    let activity = args.db.as_deref().map(ActivityDb::open).transpose()?;
+    let client = build_client(&config)?;
+
+    let (requested, discovered_styles) = if args.filter_style.is_empty() {
+        (requested, HashMap::new())
+    } else {
+        add_style_listings(args, &config, &client, requested, || {
+            if interrupted.load(Ordering::Relaxed) {
+                return Some("interrupted");
+            }
+            time_limit.filter(TimeLimit::is_reached).map(|l| l.reason)
+        })
+        .await?
+    };
+
+    let indexed = index.recipe_ids()?;
+    // Parsed once, so that checking an ID doesn't allocate
+    let downloaded: HashSet<u32> = indexed
+        .iter()
+        .copied()
+        .chain(existing_recipes.iter().filter_map(|stem| stem.parse().ok()))
+        .collect();
+    // Enough IDs ahead for the biggest batch the run takes
+    let lookahead = match (&config.proxy_pool, rate_limit) {
+        (Some(pool), None) => pool.concurrency().unwrap_or(concurrency),
+        _ => concurrency,
+    }
+    .max(CONCURRENT_REQUESTS)
+        * bulk_group_size(&config);
+    let QueuePlan {
+        mut queue,
+        skipped,
+        mut next_id,
+        last_id,
+        mut unqueued,
+        target,
+    } = plan_queue(
+        args,
+        &mut state,
+        requested,
+        &downloaded,
+        existing_recipes.len(),
+        lookahead,
+    )?;
+    reporter.set_target(target);
+    let remaining_needed = target.saturating_sub(existing_recipes.len());
+    info!("Need to download {} more recipes", remaining_needed);
+
+    // Rather than finding out an hour in that every request failed
+    let preflight = if args.skip_healthcheck || config.test_mode.is_some() || remaining_needed == 0
+    {
+        None
+    } else {
+        let id = args.healthcheck_id.or(indexed.iter().copied().min());
+        let preflight = preflight::check(&client, &config, id).await;
+        if !preflight.passed() {
+            return Err(format!(
+                "pre-flight check failed: {}; pass --skip-healthcheck to start anyway",
+                preflight
+            )
+            .into());
+        }
+        info!("Pre-flight check passed: {}", preflight);
+        Some(preflight)
+    };
+
+    let done_reason = if sequential {
+        "range-exhausted"
+    } else if newest {
+        "frontier-reached"
+    } else if queue.is_some() && !args.fallback_random {
+        "ids-exhausted"
+    } else {
+        "target-reached"
+    };
+
+    reporter.event(&Event::RunStarted {
+        schema: SCHEMA_VERSION,
+        base_url: &config.base_url,
+        output_dir: config.output_dir.display().to_string(),
+        target,
+        concurrency,
+        timeout_secs: config.request_timeout.as_secs(),
+        connect_timeout_ms: config.connect_timeout.as_millis() as u64,
+        read_timeout_ms: config.read_timeout.as_millis() as u64,
+        request_timeout_ms: config.request_timeout.as_millis() as u64,
+        daily_quota: state.daily_quota.max_per_day,
+        existing: existing_recipes.len(),
+    });
+
+    let mut stats = DownloadStats {
+        existing: existing_recipes.len(),
+        gets_avoided: config.head_precheck.as_ref().map(|_| 0),
+        raw_bytes: args.raw_bytes,
+        ..Default::default()
+    };
+    shared_stats.lock().unwrap().clone_from(&stats);
+    if remaining_needed == 0 {
+        if sequential {
+            state.cursor(args.min_id, args.max_id).next_id = args.max_id as u64 + 1;
+            state.save(&config.output_dir)?;
+            info!(
+                "Every ID from {} to {} has been tried or downloaded",
+                args.min_id, args.max_id
+            );
+        } else {
+            info!("Target already reached! No more downloads needed.");
+        }
+        reporter.log(format!("\n{}", stats.summary()));
+        if listed {
+            report_listed(&reporter, &[], skipped, &[])?;
+        }
+        finish(&reporter, done_reason, &stats);
+        return Ok(format!("{}, {}", done_reason, summary(0, 0)));
+    }
+
+    // Setup progress bar; a sequential run shows its place in the range
+    // rather than its progress toward the target
+    let scan_position = |queue: &Option<VecDeque<u32>>, next_id: u64| {
+        let next_id = queue
+            .as_ref()
+            .and_then(|queue| queue.front())
+            .map_or(next_id, |&id| id as u64);
+        next_id - args.min_id as u64
+    };
+    let pb = if !reporter.shows_bar() {
+        ProgressBar::hidden()
+    } else if newest {
+        ProgressBar::new_spinner()
+    } else if sequential {
+        ProgressBar::new(args.max_id as u64 - args.min_id as u64 + 1)
+    } else {
+        ProgressBar::new(target as u64)
+    };
+    let pb = if reporter.shows_bar() {
+        let (bars, pb) = FileBars::new(pb, args.file_bar_threshold.0);
+        config.file_bars = Some(bars);
+        pb
+    } else {
+        pb
+    };
+    pb.set_position(if sequential {
+        scan_position(&queue, next_id)
+    } else if newest {
+        queue
+            .as_ref()
+            .and_then(|queue| queue.front())
+            .map_or(0, |&id| id.into())
+    } else {
+        existing_recipes.len() as u64
+    });
+    // With a time limit the countdown to it matters more than the ETA
+    let countdown = if time_limit.is_some() {
+        "Stops in: {stops_in}"
+    } else {
+        "ETA: {eta_precise}"
+    };
+    let counter = if sequential {
+        "ID {scan_id} of {scan_range}"
+    } else {
+        "{pos}/{len}"
+    };
+    // Newest mode has no end to measure against, just the current ID
+    let template = if newest {
+        let countdown = if time_limit.is_some() {
+            " - Stops in: {stops_in}"
+        } else {
+            ""
+        };
+        format!(
+            "{{spinner:.green}} [{{elapsed_precise}}] Scanning ID {{pos}}{} - Success: {{msg}}",
+            countdown
+        )
+    } else {
+        format!("{{spinner:.green}} [{{elapsed_precise}}] [{{bar:50.cyan/blue}}] {} ({{percent}}%) - {} - Success: {{msg}}", counter, countdown)
+    };
+    let template = format!("{} - {{transfer}} - {}", template, timeouts(&config));
+    let request_rate = if config.requests.max_rps().is_some() {
+        " - {request_rate}"
+    } else {
+        ""
+    };
+    let template = match &args.progress_style {
+        Some(BarStyle::Compact) if newest => "{spinner:.green} ID {pos} {msg}".to_string(),
+        Some(BarStyle::Compact) => format!("[{{bar:20.cyan/blue}}] {} {{msg}}", counter),
+        Some(BarStyle::Verbose) => {
+            let head = if newest && time_limit.is_some() {
+                "{spinner:.green} [{elapsed_precise}] Scanning ID {pos} - Stops in: {stops_in}"
+                    .to_string()
+            } else if newest {
+                "{spinner:.green} [{elapsed_precise}] Scanning ID {pos}".to_string()
+            } else {
+                format!(
+                    "{{spinner:.green}} [{{elapsed_precise}}] [{{bar:50.cyan/blue}}] {} ({{percent}}%) - {}",
+                    counter, countdown
+                )
+            };
+            format!(
+                "{}\n  Success: {{msg}}\n  Saving {{per_sec}} - {{transfer}}{}\n  {}",
+                head,
+                request_rate,
+                timeouts(&config)
+            )
+        }
+        Some(BarStyle::Template(custom)) => custom.clone(),
+        _ => format!("{}{}", template, request_rate),
+    };
+    let min_id = args.min_id as u64;
+    let scan_range = format!("{}-{}", args.min_id, args.max_id);
+    let mut bar_style = ProgressStyle::default_bar()
+        .template(&template)?
+        .progress_chars("#>-")
+        .with_key(
+            "scan_id",
+            move |state: &ProgressState, w: &mut dyn fmt::Write| {
+                let _ = write!(w, "{}", min_id + state.pos());
+            },
+        )
+        .with_key(
+            "scan_range",
+            move |_: &ProgressState, w: &mut dyn fmt::Write| {
+                let _ = w.write_str(&scan_range);
+            },
+        );
+    if let Some(limit) = time_limit {
+        bar_style = bar_style.with_key(
+            "stops_in",
+            move |_: &ProgressState, w: &mut dyn fmt::Write| {
+                let left = limit.remaining().as_secs();
+                let _ = write!(
+                    w,
+                    "{:02}:{:02}:{:02}",
+                    left / 3600,
+                    left % 3600 / 60,
+                    left % 60
+                );
+            },
+        );
+    }
+    let (transfers, raw_bytes) = (config.transfers.clone(), args.raw_bytes);
+    bar_style = bar_style.with_key(
+        "transfer",
+        move |_: &ProgressState, w: &mut dyn fmt::Write| {
+            let _ = write!(
+                w,
+                "{}/s, {}",
+                format_bytes(transfers.rate() as u64, raw_bytes),
+                format_bytes(transfers.received(), raw_bytes)
+            );
+            if let Some(average) = transfers.average_saved() {
+                let _ = write!(w, ", avg {}", format_bytes(average, raw_bytes));
+            }
+        },
+    );
+    let requests = config.requests.clone();
+    bar_style = bar_style.with_key(
+        "request_rate",
+        move |_: &ProgressState, w: &mut dyn fmt::Write| {
+            let _ = write!(w, "{:.1} req/s", requests.recent_rate());
+        },
+    );
+    pb.set_style(bar_style);
+    logging::set_progress_bar(Some(pb.clone()));
+    pb.set_message(format!(
+        "{}/{} (Failed: {})",
+        stats.on_disk(),
+        stats.attempted,
+        stats.failed
+    ));
+
+    let mut rng = rand::thread_rng();
+    let mut attempted_ids = IdTracker::new(args.min_id..=args.max_id);
+    let mut picker = IdPicker::new(args.min_id..=args.max_id, args.strategy, args.exploration)
+        .with_since(args.since_id);
+    if (queue.is_none() || args.fallback_random) && !sequential && !newest {
+        debug!("Picking IDs {}", picker.distribution());
+    }
+    let ticker = reporter.start_ticker(
+        Duration::from_secs(args.heartbeat),
+        Duration::from_secs(args.status_interval),
+    );
+    let mut finish_reason = done_reason;
+    let mut log = RunLog::default();
+    let mut unreachable_streak = 0;
+    // Logins since a batch last got through without the session running out
+    let mut logins_in_a_row = 0;
+    // IDs in a row with no recipe, which in newest mode mark the frontier
+    let mut miss_streak = 0;
+    // How each listed ID went, in the order they finished
+    let mut listed_outcomes = Vec::new();
+    let mut hit_rate = RollingHitRate::new(args.hit_rate_window);
+    let remaining = |queue: &Option<VecDeque<u32>>, unqueued: usize, successful: usize| match queue
+    {
+        Some(queue) => Remaining::Ids(queue.len() + unqueued),
+        None => Remaining::Saves(target.saturating_sub(successful)),
+    };
+    metrics.set_remaining(remaining(&queue, unqueued, stats.on_disk()));
+
+    loop {
+        // With --fallback-random, random picks take over once the
+        // discovered IDs are done, passing over any already downloaded
+        if args.fallback_random && queue.as_ref().is_some_and(VecDeque::is_empty) {
+            info!("The --filter-style recipes are done; carrying on with random IDs");
+            let downloaded = existing_recipes
+                .iter()
+                .filter_map(|stem| stem.parse().ok())
+                .chain(indexed.iter().copied());
+            for id in downloaded {
+                attempted_ids.insert(id);
+            }
+            queue = None;
+        }
+        let more = match &queue {
+            Some(queue) => !queue.is_empty(),
+            None => stats.on_disk() < target,
+        };
+        if !more {
+            break;
+        }
+        state.daily_quota.roll_over();
+        if state.daily_quota.is_exhausted() {
+            pb.abandon();
+            metrics.record_throttle();
+            print_quota_reached(&reporter, state.daily_quota.max_per_day);
+            finish_reason = "quota-reached";
+            break;
+        }
+        if interrupted.load(Ordering::Relaxed) {
+            pb.abandon();
+            finish_reason = "interrupted";
+            break;
+        }
+        if let Some(limit) = time_limit.filter(TimeLimit::is_reached) {
+            pb.abandon();
+            info!("Time limit reached; not starting any more downloads");
+            finish_reason = limit.reason;
+            break;
+        }
+        if args.max_attempts > 0 && stats.attempted >= args.max_attempts {
+            pb.abandon();
+            info!(
+                "Stopping: made the maximum of {} attempts",
+                args.max_attempts
+            );
+            finish_reason = "max-attempts-reached";
+            break;
+        }
+        if let Some(rate) = hit_rate
+            .rate()
+            .filter(|rate| rate * 100.0 < args.min_hit_rate)
+        {
+            pb.abandon();
+            warn!(
+                "Stopping: only {:.1}% of the last {} attempts found a recipe, below --min-hit-rate {}%",
+                rate * 100.0,
+                args.hit_rate_window,
+                args.min_hit_rate
+            );
+            finish_reason = "hit-rate-too-low";
+            break;
+        }
+        // Bulk requests each take a group of IDs
+        let group_size = bulk_group_size(&config);
+        // --per-proxy-concurrency for each proxy, unless a rate limit
+        // keeps to one
+        let concurrency = match (&config.proxy_pool, rate_limit) {
+            (Some(pool), None) => pool.concurrency().unwrap_or(concurrency),
+            _ => concurrency,
+        };
+        // Never start more downloads than the quota or attempt limit
+        // has left
+        let mut batch_size = state
+            .daily_quota
+            .remaining()
+            .map_or(concurrency * group_size, |left| {
+                left.min(concurrency * group_size)
+            });
+        if args.max_attempts > 0 {
+            batch_size = batch_size.min(args.max_attempts - stats.attempted);
+        }
+        // A sample is meant to come out at its size, not a batch over
+        if args.stratified {
+            batch_size = batch_size.min(target - stats.on_disk());
+        }
+
+        let mut current_batch = vec![];
+
+        // Take the next listed IDs, or generate a batch of new ones
+        if let Some(queue) = &mut queue {
+            let take = batch_size.min(queue.len());
+            current_batch.extend(queue.drain(..take));
+            for &id in &current_batch {
+                attempted_ids.insert(id);
+            }
+        }
+        while queue.is_none() && current_batch.len() < batch_size {
+            // IDs waiting for a retry go first
+            if !state.retry_ids.is_empty() {
+                let id = state.retry_ids.remove(0);
+                attempted_ids.insert(id);
+                current_batch.push(id);
+                continue;
+            }
+            match picker.next(&mut attempted_ids, &mut rng) {
+                Some(id) => current_batch.push(id),
+                None => break,
+            }
+        }
+        if current_batch.is_empty() {
+            pb.abandon();
+            info!(
+                "ID space exhausted: every ID from {} to {} has been tried",
+                args.min_id, args.max_id
+            );
+            finish_reason = "id-space-exhausted";
+            break;
+        }
+
+        let mut tasks = vec![];
+        metrics.set_queue_depth(current_batch.len());
+
+        for group in current_batch.chunks(group_size) {
+            let group = group.to_vec();
+            let mut client = client.clone();
+            let mut config = config.clone();
+            let metrics = metrics.clone();
+            let member = config.proxy_pool.as_ref().map(|pool| pool.pick());
+            if let Some(member) = &member {
+                client = member.client.clone();
+                config.proxy = Some(member.proxy.clone());
+            }
+
+            let span = match group[..] {
+                [id] => info_span!("download", id),
+                _ => info_span!("download", ids = ?group),
+            };
+            let task = async move {
+                let _slot = match &member {
+                    Some(member) => member.slot().await,
+                    None => None,
+                };
+                let started = Instant::now();
+                metrics.request_started();
+                let outcomes = match (&config.bulk, &group[..]) {
+                    (Some(bulk), ids) if ids.len() > 1 => {
+                        attempt_group(&client, &config, bulk, ids).await
+                    }
+                    _ => vec![(group[0], attempt(&client, &config, group[0]).await)],
+                };
+                metrics.request_finished();
+                let elapsed = started.elapsed();
+                for (_, outcome) in &outcomes {
+                    if let Some(member) = &member {
+                        member.record(outcome);
+                    }
+                    match outcome {
+                        Outcome::Saved(info) => {
+                            metrics.record_download("saved", elapsed);
+                            metrics.add_bytes(info.bytes);
+                        }
+                        Outcome::Failed { category, .. } => {
+                            metrics.record_download(category, elapsed)
+                        }
+                        Outcome::SessionExpired => {
+                            metrics.record_download("session-expired", elapsed);
+                            metrics.record_retry();
+                        }
+                        Outcome::RateLimited { .. } => {
+                            metrics.record_download("rate-limited", elapsed);
+                            metrics.record_retry();
+                        }
+                    }
+                }
+                outcomes
+                    .into_iter()
+                    .map(|(id, outcome)| (id, elapsed, outcome))
+                    .collect::<Vec<_>>()
+            };
+            tasks.push(tokio::spawn(task.instrument(span)));
+        }
+
+        // Wait for all tasks in batch to complete
+        let mut expired_ids = vec![];
+        let mut limited_ids = vec![];
+        let mut pending = tasks.len();
+        for task in tasks {
+            let results = match task.await {
+                Ok(outcomes) => outcomes.into_iter().map(Ok).collect(),
+                Err(e) => vec![Err(e)],
+            };
+            pending -= 1;
+            metrics.set_queue_depth(pending);
+            for result in results {
+                match result {
+                    Ok((id, elapsed, Outcome::Saved(info))) => {
+                        stats.downloaded += 1;
+                        stats.attempted += 1;
+                        stats.count_status(Some(info.status));
+                        stats.bytes += info.bytes;
+                        config.transfers.saved(info.bytes);
+                        log.saved(info.bytes, elapsed);
+                        state.daily_quota.count_today += 1;
+                        unreachable_streak = 0;
+                        miss_streak = 0;
+                        hit_rate.record(true);
+                        // Random picks after --fallback-random aren't listed
+                        if listed && queue.is_some() {
+                            listed_outcomes.push((id, "saved"));
+                        }
+                        picker.record(id, true);
+                        reporter.event(&Event::DownloadSucceeded {
+                            id,
+                            filename: &info.filename,
+                            bytes: info.bytes,
+                            elapsed_ms: elapsed.as_millis() as u64,
+                        });
+                        // Multi-recipe exports are split into one file per
+                        // recipe here
+                        let path = config.output_dir.join(&info.filename);
+                        if let Err(e) = index_download(&index, &path, id) {
+                            error!(id, error = %e, "could not index recipe");
+                        }
+                        if let Some(db) = &activity {
+                            if let Err(e) = log_download(db, &config, id, &info) {
+                                error!(id, error = %e, "could not log download to the database");
+                            }
+                        }
+                        if let Some(style) = discovered_styles.get(&id) {
+                            if let Err(e) = index.set_discovered_style(id, style) {
+                                error!(id, error = %e, "could not tag recipe with its style");
+                            }
+                        }
+                        if let Some(stem) = bsmx_stem(Path::new(&info.filename)) {
+                            existing_recipes.insert(stem.to_string());
+                        }
+                    }
+                    Ok((id, elapsed, Outcome::Failed { category, status })) => {
+                        log.failed(id, category, elapsed);
+                        let unreachable = is_unreachable(category, status);
+                        if unreachable {
+                            unreachable_streak += 1;
+                        } else {
+                            unreachable_streak = 0;
+                        }
+                        if listed && queue.is_some() {
+                            listed_outcomes.push((id, category));
+                        }
+                        // Any other answer means the ID is in use
+                        if category == "not-found" {
+                            miss_streak += 1;
+                        } else if !unreachable {
+                            miss_streak = 0;
+                        }
+                        stats.failed += 1;
+                        stats.attempted += 1;
+                        stats.count_status(status);
+                        if category == "parse-timeout" {
+                            stats.parse_timeouts += 1;
+                        }
+                        hit_rate.record(false);
+                        // The site never answered for this ID, so it may yet
+                        // have a recipe
+                        if unreachable && queue.is_none() {
+                            attempted_ids.remove(id);
+                        } else {
+                            picker.record(id, false);
+                        }
+                        reporter.event(&Event::DownloadFailed {
+                            id,
+                            category,
+                            status,
+                        });
+                        if let Some(db) = &activity {
+                            let attempt = Attempt {
+                                id,
+                                attempted_at: Utc::now(),
+                                http_status: status,
+                                error_kind: Some(category.to_string()),
+                            };
+                            if let Err(e) = db.record_attempt(&attempt) {
+                                error!(id, error = %e, "could not log attempt to the database");
+                            }
+                        }
+                    }
+                    // Not the recipe's fault, so it isn't counted and gets
+                    // another try
+                    Ok((id, _, Outcome::SessionExpired)) => {
+                        debug!(id, "will retry after the session is renewed");
+                        stats.count_status(Some(StatusCode::UNAUTHORIZED.as_u16()));
+                        log.retried();
+                        expired_ids.push(id);
+                    }
+                    // Nor is being asked to slow down; the gate is already
+                    // shut for the wait
+                    Ok((id, _, Outcome::RateLimited { retry_after })) => {
+                        debug!(id, "will retry once the rate limit's wait is over");
+                        stats.count_status(Some(StatusCode::TOO_MANY_REQUESTS.as_u16()));
+                        log.retried();
+                        reporter.event(&Event::RateLimited {
+                            id,
+                            retry_after_secs: retry_after.as_secs(),
+                        });
+                        limited_ids.push(id);
+                    }
+                    Err(e) => {
+                        error!(error = %e, "download task failed");
+                        stats.failed += 1;
+                        stats.attempted += 1;
+                    }
+                }
+                // A listed ID is done once tried, whether or not it existed
+                if !sequential && !newest {
+                    pb.set_position(if queue.is_some() {
+                        (stats.existing + stats.attempted) as u64
+                    } else {
+                        stats.on_disk() as u64
+                    });
+                }
+                pb.set_message(format!(
+                    "{}/{} (Failed: {})",
+                    stats.on_disk(),
+                    stats.attempted,
+                    stats.failed
+                ));
+                metrics.set_remaining(remaining(&queue, unqueued, stats.on_disk()));
+                shared_stats.lock().unwrap().clone_from(&stats);
+                reporter.set_counts(Counts {
+                    successful: stats.on_disk(),
+                    failed: stats.failed,
+                    attempted: stats.attempted,
+                    transferred: config.transfers.received(),
+                    bytes_per_sec: config.transfers.rate(),
+                });
+            }
+        }
+
+        // Expired and rate-limited IDs go back in front, in their
+        // original order
+        for &id in expired_ids.iter().chain(&limited_ids).rev() {
+            match &mut queue {
+                Some(queue) => queue.push_front(id),
+                None => state.retry_ids.insert(0, id),
+            }
+        }
+        if sequential {
+            if let Some(queue) = &mut queue {
+                let queued = top_up(queue, lookahead, &mut next_id, last_id, |id| {
+                    downloaded.contains(&id)
+                });
+                unqueued = unqueued.saturating_sub(queued);
+            }
+            let position = scan_position(&queue, next_id);
+            state.cursor(args.min_id, args.max_id).next_id = args.min_id as u64 + position;
+            pb.set_position(position);
+        }
+
+        if let Some(left) = config
+            .requests
+            .paused_for()
+            .filter(|_| !limited_ids.is_empty())
+        {
+            state.save(&config.output_dir)?;
+            metrics.record_throttle();
+            reporter.event(&Event::Throttled {
+                reason: "rate-limited",
+                resume_in_secs: Some(left.as_secs()),
+            });
+        }
+        if !expired_ids.is_empty() {
+            state.save(&config.output_dir)?;
+            metrics.record_throttle();
+            reporter.event(&Event::Throttled {
+                reason: "session-expired",
+                resume_in_secs: None,
+            });
+        }
+        if expired_ids.is_empty() {
+            logins_in_a_row = 0;
+        } else if let Some(reason) = renew_session(
+            args,
+            &config,
+            &client,
+            &pb,
+            &mut state,
+            &mut log,
+            time_limit,
+            &interrupted,
+            &mut logins_in_a_row,
+            expired_ids.len(),
+        )
+        .await?
+        {
+            finish_reason = reason;
+            break;
+        }
+
+        if unreachable_streak >= CIRCUIT_BREAKER_THRESHOLD {
+            state.save(&config.output_dir)?;
+            pb.abandon();
+            error!(
+                "Stopping: the last {} requests could not reach the site",
+                unreachable_streak
+            );
+            finish_reason = "circuit-open";
+            break;
+        }
+
+        state.save(&config.output_dir)?;
+        reporter.event(&Event::Checkpoint {
+            successful: stats.on_disk(),
+            failed: stats.failed,
+            attempted: stats.attempted,
+        });
+
+        if newest {
+            if miss_streak >= args.frontier_misses {
+                info!(
+                    "Reached the newest recipes: the last {} IDs had none",
+                    miss_streak
+                );
+                if let Some(queue) = &mut queue {
+                    queue.clear();
+                }
+                pb.finish_with_message(format!("Reached the frontier: {} new", stats.downloaded));
+                break;
+            }
+            if let Some(queue) = &mut queue {
+                top_up(queue, lookahead, &mut next_id, last_id, |id| {
+                    downloaded.contains(&id)
+                });
+                if let Some(&id) = queue.front() {
+                    pb.set_position(id.into());
+                }
+            }
+        }
+
+        tokio::time::sleep(batch_delay).await;
+    }
+
+    if !pb.is_finished() {
+        pb.finish_with_message(format!(
+            "Completed: {}/{} successful",
+            stats.on_disk(),
+            target
+        ));
+    }
+
+    logging::set_progress_bar(None);
+    if let Some(ticker) = ticker {
+        ticker.abort();
+    }
+
+    stats.gets_avoided = config.head_precheck.as_ref().map(|p| p.gets_avoided());
+    stats.transferred = config.transfers.received();
+    (stats.compressed, stats.decompressed) = config.transfers.compression();
+    if queue.is_none() {
+        log.set_buckets(picker.buckets());
+    }
+    let run_config = RunConfig {
+        base_url: config.base_url.clone(),
+        output_dir: config.output_dir.clone(),
+        target,
+        concurrency,
+        timeout_secs: config.request_timeout.as_secs(),
+        daily_quota: state.daily_quota.max_per_day,
+    };
+    let report = run_report(&config, log, run_config, finish_reason, &stats, preflight);
+    if let Some(db) = &activity {
+        if let Err(e) = db.record_status_counts(report.started_at, &stats.status_counts) {
+            error!(error = %e, "could not log status counts to the database");
+        }
+    }
+    if let (true, Some(path), Some(jar)) = (args.save_cookies, &args.cookies, &config.cookies) {
+        match jar.save(path) {
+            Ok(saved) => info!(saved, "saved cookies to {}", path.display()),
+            Err(e) => error!(error = %e, "could not save cookies to {}", path.display()),
+        }
+    }
+    let listed = listed.then(|| ListedRun {
+        outcomes: &listed_outcomes,
+        skipped,
+        untried: queue.iter().flatten().copied().collect(),
+    });
+    report_run(args, &config, &client, &reporter, &report, &stats, listed).await?;
+
+    let stopped_short = match &queue {
+        Some(queue) => !queue.is_empty() || unqueued > 0,
+        None => stats.on_disk() < target,
+    };
+    run_result(finish_reason, stopped_short, &stats, unreachable_streak)
+}
+
+/// The run's report, from what `log` gathered and the final `stats`.
+fn run_report(
+    config: &DownloadConfig,
+    log: RunLog,
+    run_config: RunConfig,
+    finish_reason: &str,
+    stats: &DownloadStats,
+    preflight: Option<Preflight>,
+) -> RunReport {
+    let mut report = log.finish(
+        run_config,
+        finish_reason,
+        stats.existing,
+        stats.downloaded,
+        stats.attempted,
+    );
+    report.gets_avoided = stats.gets_avoided;
+    report.transferred_bytes = stats.transferred;
+    report.mirrors = config.mirrors.iter().flat_map(|m| m.stats()).collect();
+    report.preflight = preflight;
+    report.compressed_bytes = stats.compressed;
+    report.decompressed_bytes = stats.decompressed;
+    report.status_codes = stats.status_counts.iter().map(|(&s, &n)| (s, n)).collect();
+    config.responses.add_to(&mut report);
+    report
+}
+
+/// How a run over listed IDs went, for [`report_listed`].
+struct ListedRun<'a> {
+    outcomes: &'a [(u32, &'static str)],
+    skipped: usize,
+    untried: Vec<u32>,
+}
+
+/// Tell everyone who asked how the run went: the summaries, the listed
+/// IDs' outcomes, the finish event, the webhook and the report file.
+async fn report_run(
+    args: &DownloadArgs,
+    config: &DownloadConfig,
+    client: &Client,
+    reporter: &Reporter,
+    report: &RunReport,
+    stats: &DownloadStats,
+    listed: Option<ListedRun<'_>>,
+) -> Result<(), Box<dyn Error>> {
+    reporter.log(format!("\n{}", stats.summary()));
+    if let Some(pool) = &config.proxy_pool {
+        reporter.log(format!("\n{}", pool.summary()));
+    }
+    if let Some(mirrors) = &config.mirrors {
+        reporter.log(format!("\n{}", mirrors.summary()));
+    }
+    if stats.attempted > 0 {
+        reporter.log(format!("\n{}", report.latency_summary()));
+    }
+    if let Some(quota) = &config.style_quota {
+        reporter.log(format!("\n{}", quota.summary()));
+    }
+    if let Some(listed) = listed {
+        report_listed(reporter, listed.outcomes, listed.skipped, &listed.untried)?;
+    }
+    finish(reporter, &report.finish_reason, stats);
+    if let Some(url) = &args.notify_webhook {
+        if !args.notify_on_error_only || stats.failed > 0 {
+            let notification = Notification::new(stats, reporter.elapsed_secs());
+            // The run itself went however it went; a dead hook can't change
+            // that
+            if let Err(e) = notify::send(client, url, &notification).await {
+                warn!(error = %e, "could not notify {}", url);
+            }
+        }
+    }
+
+    let report_file = match (&args.report_file, args.report) {
+        (Some(path), _) => Some(path.clone()),
+        (None, true) => Some(default_report_path(report.started_at)),
+        (None, false) => None,
+    };
+    if let Some(path) = report_file {
+        report.save(&path)?;
+        info!("Run report written to {}", path.display());
+    }
+    Ok(())
+}
+
+/// What the run comes to: its summary, or the error that sets the exit
+/// code when it was interrupted, tripped the circuit breaker or stopped
+/// short of its target.
+fn run_result(
+    finish_reason: &'static str,
+    stopped_short: bool,
+    stats: &DownloadStats,
+    unreachable_streak: usize,
+) -> Result<String, RunError> {
+    let summary = summary(stats.downloaded, stats.failed);
+    match finish_reason {
+        "interrupted" => Err(RunError::Interrupted { summary }),
+        "circuit-open" => Err(RunError::CircuitOpen {
+            failures: unreachable_streak,
+        }),
+        reason if stopped_short => Err(RunError::Partial { reason, summary }),
+        reason => Ok(format!("{}, {}", reason, summary)),
+    }
+}
+
+fn summary(downloaded: usize, failed: usize) -> String {
+    format!("{} downloaded, {} failed", downloaded, failed)
+}
+
+/// Print a statistics snapshot on every SIGUSR1 without holding up the
+/// downloads. Without SIGUSR1 the same snapshot is served at /stats with
+/// --metrics-addr.
+#[cfg(unix)]
+fn dump_stats_on_sigusr1(metrics: Arc<Metrics>) -> io::Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+    let mut usr1 = signal(SignalKind::user_defined1())?;
+    tokio::spawn(async move {
+        // Signals arriving while a snapshot is printed are merged into one
+        while usr1.recv().await.is_some() {
+            let snapshot = metrics.snapshot();
+            logging::write_above_bar(format!("\n{}", snapshot).as_bytes());
+            debug!(%snapshot, "statistics snapshot");
+        }
+    });
+    Ok(())
+}
+
+/// Watch for SIGINT and SIGTERM (Ctrl-C elsewhere). The first sets the
+/// returned flag so the run can stop after the batch in flight; a second
+/// exits straight away.
+pub(crate) fn watch_for_interrupt() -> io::Result<Arc<AtomicBool>> {
+    let interrupted = Arc::new(AtomicBool::new(false));
+    let flag = interrupted.clone();
+    // Handlers are installed here rather than in the task so that a signal
+    // arriving before it first runs isn't missed
+    #[cfg(unix)]
+    let (mut sigint, mut sigterm) = {
+        use tokio::signal::unix::{signal, SignalKind};
+        (
+            signal(SignalKind::interrupt())?,
+            signal(SignalKind::terminate())?,
+        )
+    };
+    tokio::spawn(async move {
+        loop {
+            #[cfg(unix)]
+            tokio::select! {
+                _ = sigint.recv() => {}
+                _ = sigterm.recv() => {}
+            }
+            #[cfg(not(unix))]
+            if tokio::signal::ctrl_c().await.is_err() {
+                return;
+            }
+            if flag.swap(true, Ordering::Relaxed) {
+                eprintln!("beerscape: interrupted: stopped immediately");
+                process::exit(EXIT_INTERRUPTED);
+            }
+            warn!("Interrupted; stopping after the current batch. Interrupt again to stop now");
+        }
+    });
+    Ok(interrupted)
+}
+
+/// Add the recipes the site's style search files under each
+/// --filter-style to the IDs given directly, returning them with the style
+/// each was found under. `stop` cuts the crawl short, as in [`crawl`].
+async fn add_style_listings<'a>(
+    args: &'a DownloadArgs,
+    config: &DownloadConfig,
+    client: &Client,
+    requested: Option<Vec<u32>>,
+    stop: impl Fn() -> Option<&'static str>,
+) -> Result<(Option<Vec<u32>>, HashMap<u32, &'a str>), Box<dyn Error>> {
+    let mut ids = requested.unwrap_or_default();
+    let mut seen: HashSet<u32> = ids.iter().copied().collect();
+    let mut styles = HashMap::new();
+    for style in &args.filter_style {
+        let found = crawl(
+            client,
+            &config.base_url,
+            &config.requests,
+            Search::Style(style),
+            args.filter_max_pages,
+            &stop,
+        )
+        .await?;
+        info!(
+            "Found {} recipes filed under {:?} in {} pages",
+            found.recipes.len(),
+            style,
+            found.pages
+        );
+        // A recipe filed under several of the styles is tagged with the
+        // first
+        for recipe in found.recipes {
+            if seen.insert(recipe.id) {
+                styles.insert(recipe.id, style.as_str());
+                ids.push(recipe.id);
+            }
+        }
+    }
+    Ok((Some(ids), styles))
+}
+
+/// The IDs a run walks in order, and what sequential and newest mode need
+/// to queue more of them as the run goes.
+struct QueuePlan {
+    /// `None` when IDs are picked at random
+    queue: Option<VecDeque<u32>>,
+    /// Requested IDs passed over as already downloaded
+    skipped: usize,
+    /// The next ID to queue, and the last
+    next_id: u64,
+    last_id: u32,
+    /// IDs not queued yet that are still to try
+    unqueued: usize,
+    /// Recipes on disk the run means to finish with
+    target: usize,
+}
+
+/// Plan the run's queue: the `requested` IDs, the rest of a sequential
+/// range or the IDs past the newest one downloaded, each minus the ones
+/// already `downloaded`. Random runs, stratified ones included, have none
+/// and only a target.
+fn plan_queue(
+    args: &DownloadArgs,
+    state: &mut State,
+    requested: Option<Vec<u32>>,
+    downloaded: &HashSet<u32>,
+    existing: usize,
+    lookahead: usize,
+) -> Result<QueuePlan, Box<dyn Error>> {
+    let sequential = args.mode == Mode::Sequential;
+    let newest = args.mode == Mode::Newest;
+    let mut queue = None;
+    let mut skipped = 0;
+    // With --id-file, --ids or --id-range the run walks the listed IDs,
+    // minus any already downloaded unless --force says otherwise, instead
+    // of picking at random up to the target
+    if let Some(ids) = requested {
+        let requested = ids.len();
+        let listed: VecDeque<u32> = ids
+            .into_iter()
+            .filter(|id| args.force || !downloaded.contains(id))
+            .collect();
+        skipped = requested - listed.len();
+        if skipped > 0 {
+            info!(
+                "Skipping {} of {} requested IDs that are already downloaded; --force fetches them again",
+                skipped, requested
+            );
+        } else {
+            info!(
+                "{} of {} requested IDs are already downloaded",
+                skipped, requested
+            );
+        }
+        queue = Some(listed);
+    }
+
+    // In sequential and newest mode the queue is topped up batch by batch
+    // as the run goes, from `next_id` up to `last_id`
+    let mut next_id: u64 = 0;
+    let mut last_id = u32::MAX;
+    let mut unqueued = 0;
+    if sequential {
+        // A sequential run carries on from the range's saved position
+        let cursor = state.cursor(args.min_id, args.max_id);
+        // Skips straight there, without checking the IDs before it
+        if let Some(start) = args.start_at_id {
+            cursor.next_id = start.into();
+        }
+        if cursor.next_id > args.min_id.into() {
+            info!(
+                "Carrying on from ID {} of {}-{}",
+                cursor.next_id, args.min_id, args.max_id
+            );
+        }
+        next_id = cursor.next_id;
+        last_id = args.max_id;
+        let rest = next_id..=u64::from(last_id);
+        let requested = (last_id as u64 + 1).saturating_sub(next_id) as usize;
+        skipped = downloaded
+            .iter()
+            .filter(|&&id| rest.contains(&id.into()))
+            .count();
+        unqueued = requested - skipped;
+        info!(
+            "{} of {} requested IDs are already downloaded",
+            skipped, requested
+        );
+    }
+    if newest {
+        // A newest run starts from a little below the highest ID
+        // downloaded so far
+        let highest = downloaded.iter().copied().max().ok_or(
+            "--mode newest carries on from the highest ID downloaded so far, and nothing has been downloaded yet",
+        )?;
+        next_id = highest.saturating_sub(args.overlap).max(1).into();
+        info!(
+            "Highest downloaded ID is {}; scanning from {}",
+            highest, next_id
+        );
+    }
+    if sequential || newest {
+        let mut ids = VecDeque::new();
+        let queued = top_up(&mut ids, lookahead, &mut next_id, last_id, |id| {
+            downloaded.contains(&id)
+        });
+        unqueued = unqueued.saturating_sub(queued);
+        queue = Some(ids);
+    }
+
+    let target = match &queue {
+        Some(_) if args.fallback_random => args.target,
+        Some(queue) => existing + queue.len() + unqueued,
+        None if args.stratified => existing + args.sample,
+        None => args.target,
+    };
+    Ok(QueuePlan {
+        queue,
+        skipped,
+        next_id,
+        last_id,
+        unqueued,
+        target,
+    })
+}
+
+/// Queue IDs from `next_id` up to `last_id` until `batch` are waiting,
+/// skipping the ones `downloaded` says are already on disk. Returns how
+/// many were queued.
+fn top_up(
+    queue: &mut VecDeque<u32>,
+    batch: usize,
+    next_id: &mut u64,
+    last_id: u32,
+    downloaded: impl Fn(u32) -> bool,
+) -> usize {
+    let mut queued = 0;
+    while queue.len() < batch && *next_id <= last_id.into() {
+        let id = *next_id as u32;
+        if !downloaded(id) {
+            queue.push_back(id);
+            queued += 1;
+        }
+        *next_id += 1;
+    }
+    queued
+}
+
+/// The IDs from --id-file, --ids and then --id-range, in order and without
+/// repeats, or `None` if none of them was given.
+fn requested_ids(args: &DownloadArgs) -> Result<Option<Vec<u32>>, Box<dyn Error>> {
+    if args.id_file.is_none() && args.ids.is_empty() && args.id_range.is_empty() {
+        return Ok(None);
+    }
+    let mut ids = match &args.id_file {
+        Some(path) => read_id_file(path)?,
+        None => Vec::new(),
+    };
+    ids.extend(&args.ids);
+    for range in &args.id_range {
+        ids.extend(range.0.clone());
+    }
+    let mut seen = HashSet::new();
+    ids.retain(|id| seen.insert(*id));
+    // Everything below it was tried by an earlier run, so it's dropped
+    // without being looked up
+    if let Some(start) = args.start_at_id {
+        ids.retain(|&id| id >= start);
+    }
+
+    // The site may have grown past the range random picks come from, so
+    // these are still tried
+    let outside = ids
+        .iter()
+        .filter(|id| !(MIN_RECIPE_ID..=MAX_RECIPE_ID).contains(*id))
+        .count();
+    if outside > 0 {
+        warn!(
+            "{} requested IDs are outside the usual range {}-{}",
+            outside, MIN_RECIPE_ID, MAX_RECIPE_ID
+        );
+    }
+    Ok(Some(ids))
+}
+
+/// Print how the listed IDs went, and write the ones that failed or weren't
+/// tried to [`FAILED_IDS_FILE`] for another run's --id-file.
+fn report_listed(
+    reporter: &Reporter,
+    outcomes: &[(u32, &'static str)],
+    skipped: usize,
+    untried: &[u32],
+) -> io::Result<()> {
+    let failed: Vec<(u32, &str)> = outcomes
+        .iter()
+        .copied()
+        .filter(|(_, outcome)| *outcome != "saved")
+        .collect();
+    let mut lines = vec![
+        String::new(),
+        "Listed IDs:".to_string(),
+        "-----------".to_string(),
+        format!("Downloaded: {}", outcomes.len() - failed.len()),
+        format!("Already Downloaded: {}", skipped),
+        format!("Failed: {}", failed.len()),
+    ];
+    for (id, outcome) in &failed {
+        lines.push(format!("  {:>10}  {}", id, outcome));
+    }
+    lines.push(format!("Not Tried: {}", untried.len()));
+
+    if !failed.is_empty() || !untried.is_empty() {
+        // Grouped by reason under comment lines, which --id-file skips
+        let mut by_reason: BTreeMap<&str, Vec<u32>> = BTreeMap::new();
+        for &(id, outcome) in &failed {
+            by_reason.entry(outcome).or_default().push(id);
+        }
+        let mut contents = String::new();
+        for (reason, ids) in by_reason
+            .iter()
+            .map(|(reason, ids)| (*reason, ids.as_slice()))
+            .chain((!untried.is_empty()).then_some(("not tried", untried)))
+        {
+            contents.push_str(&format!("# {}\n", reason));
+            for id in ids {
+                contents.push_str(&format!("{}\n", id));
+            }
+        }
+        fs::write(FAILED_IDS_FILE, contents)?;
+        lines.push(format!(
+            "Failed and untried IDs written to {}; retry them with --id-file {}",
+            FAILED_IDS_FILE, FAILED_IDS_FILE
+        ));
+    } else {
+        // A list from an earlier run would be out of date now
+        match fs::remove_file(FAILED_IDS_FILE) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+    }
+    reporter.log(lines.join("\n"));
+    Ok(())
+}
+
+fn finish(reporter: &Reporter, reason: &str, stats: &DownloadStats) {
+    reporter.event(&Event::RunFinished {
+        reason,
+        existing: stats.existing,
+        downloaded: stats.downloaded,
+        failed: stats.failed,
+        attempted: stats.attempted,
+        elapsed_secs: reporter.elapsed_secs(),
+    });
+}
+
+/// Whether `response` carries the rest of a file from byte `offset` on.
+fn resumes_at(response: &Response, offset: usize) -> bool {
+    response.status() == StatusCode::PARTIAL_CONTENT
+        && response
+            .headers()
+            .get(CONTENT_RANGE)
+            .and_then(|h| h.to_str().ok())
+            .is_some_and(|range| range.starts_with(&format!("bytes {}-", offset)))
+}
+
+/// Read the rest of `response` onto `body`, which holds what an earlier
+/// transfer already got, returning `None` if the result isn't the size the
+/// server announced or can't be decompressed. Each chunk is counted by
+/// `transfers` as it arrives and waits its turn with `bandwidth`, if given.
+/// A gzip or brotli body is decompressed once it's all arrived, so what's
+/// returned is always the file itself. `file_bar`, if given, shows the
+/// file's progress as it arrives.
+///
+/// If an uncompressed transfer breaks off and the server takes range
+/// requests, what arrived is written to `keep_partial` for the next attempt
+/// at the ID to resume from; the file is removed once a transfer completes.
+pub(crate) async fn read_body(
+    mut response: Response,
+    mut body: Vec<u8>,
+    keep_partial: Option<&Path>,
+    transfers: &TransferMeter,
+    bandwidth: Option<&ByteLimiter>,
+    read_timeout: Duration,
+    file_bar: Option<&FileBar>,
+) -> Result<Option<Vec<u8>>, Box<dyn Error + Send + Sync>> {
+    let resumed = body.len();
+    let expected = match response.headers().get(CONTENT_RANGE) {
+        // bytes <first>-<last>/<total>
+        Some(range) => range
+            .to_str()
+            .ok()
+            .and_then(|r| r.rsplit('/').next()?.parse().ok()),
+        None => response.content_length().map(|len| len as usize + resumed),
+    };
+    let encoding = response
+        .headers()
+        .get(CONTENT_ENCODING)
+        .and_then(|h| h.to_str().ok())
+        .map(|e| e.trim().to_ascii_lowercase())
+        .filter(|e| e != "identity");
+    let resumable = encoding.is_none()
+        && (response.status() == StatusCode::PARTIAL_CONTENT
+            || response
+                .headers()
+                .get(ACCEPT_RANGES)
+                .and_then(|h| h.to_str().ok())
+                .is_some_and(|units| units.split(',').any(|u| u.trim() == "bytes")));
+
+    loop {
+        let next = match tokio::time::timeout(read_timeout, response.chunk()).await {
+            Ok(next) => next.map_err(Into::into),
+            Err(_) => Err(Box::new(TimedOut::Read(read_timeout)) as Box<dyn Error + Send + Sync>),
+        };
+        match next {
+            Ok(Some(chunk)) => {
+                transfers.record(chunk.len());
+                if let Some(bar) = file_bar {
+                    bar.inc(chunk.len());
+                }
+                if let Some(limiter) = bandwidth {
+                    limiter.take(chunk.len()).await;
+                }
+                body.extend_from_slice(&chunk);
+            }
+            Ok(None) => break,
+            Err(e) => {
+                if let Some(path) = keep_partial.filter(|_| resumable && !body.is_empty()) {
+                    match fs::write(path, &body) {
+                        Ok(()) => debug!(bytes = body.len(), "kept the partial download"),
+                        Err(e) => warn!(error = %e, "could not keep the partial download"),
+                    }
+                }
+                return Err(e);
+            }
+        }
+    }
+    if let Some(path) = keep_partial.filter(|_| resumed > 0) {
+        let _ = fs::remove_file(path);
+    }
+    if expected.is_some_and(|len| len != body.len()) {
+        debug!(
+            bytes = body.len(),
+            expected, "download doesn't match its announced size"
+        );
+        return Ok(None);
+    }
+    let Some(encoding) = encoding else {
+        return Ok(Some(body));
+    };
+    match decompress(&encoding, &body) {
+        Ok(decompressed) => {
+            transfers.decompressed(body.len(), decompressed.len());
+            Ok(Some(decompressed))
+        }
+        Err(e) => {
+            warn!(
+                encoding,
+                error = %e,
+                "could not decompress a download; --no-compression asks for them plain"
+            );
+            Ok(None)
+        }
+    }
+}
+
+/// Undo a response's Content-Encoding.
+fn decompress(encoding: &str, body: &[u8]) -> io::Result<Vec<u8>> {
+    let mut decompressed = Vec::new();
+    match encoding {
+        "gzip" | "x-gzip" => {
+            MultiGzDecoder::new(body).read_to_end(&mut decompressed)?;
+        }
+        "br" => {
+            brotli::Decompressor::new(body, 4096).read_to_end(&mut decompressed)?;
+        }
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("unknown encoding {}", encoding),
+            ))
+        }
+    }
+    Ok(decompressed)
+}
+
+/// Ask for the headers of `url` first, returning what to count the ID as
+/// when they rule out a recipe and `None` when the GET should go ahead.
+///
+/// Only a 404 or headers describing something other than a file (an HTML
+/// error page, say) skip the GET; other error statuses get the GET anyway,
+/// in case the server treats HEAD differently. A server that refuses HEAD
+/// (405 or 501) or answers it without content headers is sent plain GETs
+/// for the rest of the run.
+async fn head_precheck(
+    client: &Client,
+    url: &str,
+    precheck: &HeadPrecheck,
+    requests: &RequestGate,
+) -> Result<Option<Fetched>, Box<dyn Error + Send + Sync>> {
+    if !precheck.is_supported() {
+        return Ok(None);
+    }
+    requests.pass().await;
+    let response = client.head(url).send().await?;
+    let status = response.status();
+    let headers = response.headers();
+    let unsupported = matches!(
+        status,
+        StatusCode::METHOD_NOT_ALLOWED | StatusCode::NOT_IMPLEMENTED
+    ) || (status.is_success()
+        && !headers.contains_key(CONTENT_TYPE)
+        && !headers.contains_key(CONTENT_DISPOSITION));
+    if unsupported {
+        if !precheck.unsupported.swap(true, Ordering::Relaxed) {
+            info!(
+                status = status.as_u16(),
+                "server doesn't answer HEAD usefully; sending GETs only"
+            );
+        }
+        return Ok(None);
+    }
+
+    let skipped = if status == StatusCode::NOT_FOUND {
+        Fetched::Status(status)
+    } else if status.is_success() && !looks_like_attachment(headers) {
+        Fetched::InvalidContent(status)
+    } else {
+        return Ok(None);
+    };
+    debug!(status = status.as_u16(), "precheck ruled the ID out");
+    precheck.avoided.fetch_add(1, Ordering::Relaxed);
+    Ok(Some(skipped))
+}
+
+/// Whether response headers describe a downloadable file rather than a
+/// page: named as an attachment, or typed as XML or raw bytes.
+fn looks_like_attachment(headers: &HeaderMap) -> bool {
+    let header = |name| {
+        headers
+            .get(name)
+            .and_then(|h| h.to_str().ok())
+            .unwrap_or_default()
+            .to_ascii_lowercase()
+    };
+    let disposition = header(CONTENT_DISPOSITION);
+    if disposition.contains("attachment") || disposition.contains("filename") {
+        return true;
+    }
+    let content_type = header(CONTENT_TYPE);
+    let mime = content_type.split(';').next().unwrap_or_default().trim();
+    matches!(
+        mime,
+        "application/octet-stream" | "application/xml" | "text/xml" | "application/x-bsmx"
+    ) || mime.ends_with("+xml")
+}
+
+/// Whether response headers describe a web page.
+pub(crate) fn is_web_page(headers: &HeaderMap) -> bool {
+    let content_type = headers
+        .get(CONTENT_TYPE)
+        .and_then(|h| h.to_str().ok())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    let mime = content_type.split(';').next().unwrap_or_default().trim();
+    matches!(mime, "text/html" | "application/xhtml+xml")
+}
+
+/// Read the site's robots.txt, failing if it keeps beerscape away from the
+/// downloads unless --force says to go ahead, and return the crawl delay it
+/// asks for.
+async fn check_robots(
+    args: &DownloadArgs,
+    config: &DownloadConfig,
+) -> Result<Option<f64>, Box<dyn Error>> {
+    config.requests.pass().await;
+    let robots = robots::fetch(&build_client(config)?, &config.base_url).await?;
+    let path = Url::parse(&recipe_url(&config.base_url, 0))?
+        .path()
+        .to_string();
+    if let Some(rule) = robots.disallowing(ROBOTS_AGENT, &path) {
+        if !args.force {
+            return Err(format!(
+                "robots.txt disallows {} with \"{}\"; pass --force to download anyway",
+                path, rule
+            )
+            .into());
+        }
+        warn!(%rule, "robots.txt disallows {}; going ahead because of --force", path);
+    }
+    Ok(robots.crawl_delay(ROBOTS_AGENT))
+}
+
+/// Log a saved recipe to the --db database, before anything splits it.
+fn log_download(
+    db: &ActivityDb,
+    config: &DownloadConfig,
+    id: u32,
+    info: &RecipeInfo,
+) -> Result<(), Box<dyn Error>> {
+    let now = Utc::now();
+    db.record_attempt(&Attempt {
+        id,
+        attempted_at: now,
+        http_status: Some(info.status),
+        error_kind: None,
+    })?;
+    let contents = read_bsmx(&config.output_dir.join(&info.filename))?;
+    let url = recipe_url(&config.base_url, id);
+    db.record_file(Some(id), Some(&url), &info.filename, &contents, now)?;
+    Ok(())
+}
+
+/// Where the site serves the recipe with ID `id`.
+pub fn recipe_url(base_url: &str, id: u32) -> String {
+    format!("{}/download.php?id={}", base_url, id)
+}
+
+/// Shut the request gate for as long as a 429's `Retry-After` asks, or
+/// [`DEFAULT_RETRY_AFTER`] if it doesn't say, and return that wait. While
+/// several 429s are in flight, the longest wait wins.
+pub(crate) fn rate_limited(config: &DownloadConfig, headers: &HeaderMap) -> Duration {
+    let wait = headers
+        .get(RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| parse_retry_after(value, Utc::now()))
+        .unwrap_or(DEFAULT_RETRY_AFTER);
+    if config.requests.pause_for(wait) {
+        warn!(
+            retry_after_secs = wait.as_secs(),
+            "rate limited (HTTP 429); pausing every request"
+        );
+    }
+    wait
+}
+
+/// Whether a failure means the site is in trouble (a timeout, connection
+/// failure or server error) rather than an answer about the ID.
+pub(crate) fn is_unreachable(category: &str, status: Option<u16>) -> bool {
+    matches!(
+        category,
+        "connect-timeout" | "read-timeout" | "timeout" | "network" | "proxy"
+    ) || status.is_some_and(|code| code >= 500)
+}
+
+/// Fetch one recipe and classify how it went.
+pub(crate) async fn attempt(client: &Client, config: &DownloadConfig, id: u32) -> Outcome {
+    outcome_of(config, fetch_recipe(client, config, id).await)
+}
+
+/// Classify how fetching one recipe went.
+pub(crate) fn outcome_of(
+    config: &DownloadConfig,
+    fetched: Result<Fetched, Box<dyn Error + Send + Sync>>,
+) -> Outcome {
+    match fetched {
+        Ok(Fetched::Saved(info)) => Outcome::Saved(info),
+        Ok(Fetched::Status(StatusCode::UNAUTHORIZED) | Fetched::LoginPage) => {
+            Outcome::SessionExpired
+        }
+        Ok(Fetched::RateLimited(retry_after)) => Outcome::RateLimited { retry_after },
+        Ok(Fetched::Status(status)) => Outcome::Failed {
+            category: if status == StatusCode::NOT_FOUND {
+                "not-found"
+            } else {
+                "http-error"
+            },
+            status: Some(status.as_u16()),
+        },
+        Ok(Fetched::InvalidContent(status)) => Outcome::Failed {
+            category: "invalid-content",
+            status: Some(status.as_u16()),
+        },
+        Ok(Fetched::Incomplete(status)) => Outcome::Failed {
+            category: "incomplete",
+            status: Some(status.as_u16()),
+        },
+        Ok(Fetched::StyleFull(status)) => Outcome::Failed {
+            category: "style-full",
+            status: Some(status.as_u16()),
+        },
+        Ok(Fetched::ParseTimeout(status)) => Outcome::Failed {
+            category: "parse-timeout",
+            status: Some(status.as_u16()),
+        },
+        Err(e) => {
+            let category = error_category(e.as_ref(), config.proxy.is_some());
+            match &config.proxy {
+                Some(proxy) if category == "proxy" => {
+                    warn!(error = %e, %proxy, "could not connect through the proxy")
+                }
+                _ => warn!(error = %e, "download failed"),
+            }
+            Outcome::Failed {
+                category,
+                status: None,
+            }
+        }
+    }
+}
+
+/// A short, stable name for why a request failed, for the JSON stream.
+/// Behind a proxy every connection is to the proxy, so failing to connect
+/// is put down to it.
+pub(crate) fn error_category(
+    e: &(dyn Error + Send + Sync + 'static),
+    proxied: bool,
+) -> &'static str {
+    if let Some(e) = e.downcast_ref::<reqwest::Error>() {
+        if e.is_timeout() && e.is_connect() {
+            "connect-timeout"
+        } else if e.is_timeout() {
+            "timeout"
+        } else if e.is_redirect() {
+            "redirect"
+        } else if proxied && e.is_connect() {
+            "proxy"
+        } else {
+            "network"
+        }
+    } else if let Some(e) = e.downcast_ref::<TimedOut>() {
+        match e {
+            TimedOut::Read(_) => "read-timeout",
+            TimedOut::Request(_) => "timeout",
+        }
+    } else if e.is::<io::Error>() {
+        "io"
+    } else {
+        "other"
+    }
+}
+
+/// The certificates in a PEM file, which must hold at least one.
+fn load_ca_bundle(path: &Path) -> Result<Vec<Certificate>, Box<dyn Error>> {
+    let certs = Certificate::from_pem_bundle(&fs::read(path)?)
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+    if certs.is_empty() {
+        return Err(format!("{}: no PEM certificates found", path.display()).into());
+    }
+    Ok(certs)
+}
+
+/// Get a fresh session after the server turned `expired` IDs away with
+/// 401: log in again when the run has an account, or else wait for the
+/// user to renew the cookie file. Returns why the run has to stop instead,
+/// with the progress bar already abandoned.
+#[allow(clippy::too_many_arguments)]
+async fn renew_session(
+    args: &DownloadArgs,
+    config: &DownloadConfig,
+    client: &Client,
+    pb: &ProgressBar,
+    state: &mut State,
+    log: &mut RunLog,
+    time_limit: Option<TimeLimit>,
+    interrupted: &AtomicBool,
+    logins_in_a_row: &mut u32,
+    expired: usize,
+) -> Result<Option<&'static str>, Box<dyn Error>> {
+    if let Some(login) = &config.login {
+        *logins_in_a_row += 1;
+        if *logins_in_a_row > MAX_LOGINS_IN_A_ROW {
+            pb.abandon();
+            warn!("Stopping: the site keeps ending the session as soon as it's logged in");
+            return Ok(Some("session-expired"));
+        }
+        warn!("the login session has expired; logging in again");
+        let paused = Instant::now();
+        login.log_in(client, config).await?;
+        log.throttled(paused.elapsed());
+        return Ok(None);
+    }
+
+    warn!("the server answered 401 Unauthorized; the login session has expired");
+    if !io::stdin().is_terminal() {
+        // Nobody can press Enter, so waiting would only hang the run
+        state.save(&config.output_dir)?;
+        pb.abandon();
+        warn!("stdin is not a terminal, so the run can't wait for fresh cookies");
+        return Err(
+            "the server requires a login session; renew the cookies or pass --login".into(),
+        );
+    }
+    let paused = Instant::now();
+    let renewal = if time_limit.is_some_and(|limit| limit.is_reached()) {
+        Renewal::TimeUp
+    } else {
+        wait_for_renewal(args.cookies.as_deref(), pb, time_limit, interrupted).await
+    };
+    log.throttled(paused.elapsed());
+    if renewal == Renewal::Interrupted {
+        state.save(&config.output_dir)?;
+        pb.abandon();
+        return Ok(Some("interrupted"));
+    }
+    if let Some(limit) = time_limit.filter(|_| renewal == Renewal::TimeUp) {
+        // The retries would start past the limit, so they're left for the
+        // next run: random IDs in the state file, and listed ones for the
+        // next run over the same list
+        state.save(&config.output_dir)?;
+        pb.abandon();
+        warn!(
+            "Time limit reached before the session was renewed; leaving {} IDs for the next run",
+            expired
+        );
+        return Ok(Some(limit.reason));
+    }
+    if renewal == Renewal::Closed {
+        pb.abandon();
+        warn!("Stopping: the server requires a login session");
+        return Ok(Some("session-expired"));
+    }
+    if let (Some(path), Some(jar)) = (&args.cookies, &config.cookies) {
+        let loaded = load_netscape_cookies(path, jar)?;
+        info!(loaded, "reloaded cookies");
+    }
+    Ok(None)
+}
+
+/// How a pause for fresh cookies ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Renewal {
+    /// Enter was pressed
+    Resumed,
+    /// stdin closed, or couldn't be read
+    Closed,
+    TimeUp,
+    Interrupted,
+}
+
+/// Ask the user to refresh the cookie file and wait for them to press Enter.
+/// The read happens on a blocking thread so the time limit and Ctrl-C still
+/// end the pause.
+async fn wait_for_renewal(
+    cookie_file: Option<&Path>,
+    pb: &ProgressBar,
+    time_limit: Option<TimeLimit>,
+    interrupted: &AtomicBool,
+) -> Renewal {
+    pb.suspend(|| match cookie_file {
+        Some(path) => eprintln!(
+            "Export fresh cookies to {} and press Enter to continue",
+            path.display()
+        ),
+        None => eprintln!("Restart with --cookies FILE, or press Enter to retry"),
+    });
+    // A read left behind when the pause ends some other way goes with the
+    // process
+    let enter = tokio::task::spawn_blocking(|| io::stdin().read_line(&mut String::new()));
+    let deadline = async {
+        match time_limit {
+            Some(limit) => tokio::time::sleep(limit.remaining()).await,
+            None => std::future::pending().await,
+        }
+    };
+    let interrupt = async {
+        while !interrupted.load(Ordering::Relaxed) {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    };
+    tokio::select! {
+        read = enter => match read {
+            Ok(Ok(n)) if n > 0 => Renewal::Resumed,
+            _ => Renewal::Closed,
+        },
+        _ = deadline => Renewal::TimeUp,
+        _ = interrupt => Renewal::Interrupted,
+    }
+}
+
+fn print_quota_reached(reporter: &Reporter, max_per_day: usize) {
+    let left = until_midnight().as_secs();
+    info!(
+        "Daily quota of {} downloads reached; it resets in {}h {:02}m",
+        max_per_day,
+        left / 3600,
+        left % 3600 / 60
+    );
+    reporter.event(&Event::Throttled {
+        reason: "daily-quota",
+        resume_in_secs: Some(left),
+    });
+}
+
+/// Fetch one recipe and save it to the output directory.
+///
+/// Returns `Ok(None)` when the server has no usable recipe for the ID (an
+/// error status, or a body that isn't well-formed XML) and `Err` for
+/// transport failures such as timeouts.
+pub async fn download_recipe(
+    client: &Client,
+    config: &DownloadConfig,
+    recipe_id: u32,
+) -> Result<Option<RecipeInfo>, Box<dyn Error>> {
+    match fetch_recipe(client, config, recipe_id).await {
+        Ok(Fetched::Saved(info)) => Ok(Some(info)),
+        Ok(Fetched::Status(StatusCode::UNAUTHORIZED) | Fetched::LoginPage) => {
+            Err(Box::new(SessionExpired))
+        }
+        Ok(Fetched::RateLimited(wait)) => Err(Box::new(RateLimited(wait))),
+        Ok(
+            Fetched::Status(_)
+            | Fetched::InvalidContent(_)
+            | Fetched::Incomplete(_)
+            | Fetched::StyleFull(_)
+            | Fetched::ParseTimeout(_),
+        ) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Fetch one recipe. With mirrors it's asked of the healthiest first, and
+/// of the next while they fail in ways that are the mirror's fault rather
+/// than an answer about the ID; what the last one said stands.
+async fn fetch_recipe(
+    client: &Client,
+    config: &DownloadConfig,
+    recipe_id: u32,
+) -> Result<Fetched, Box<dyn Error + Send + Sync>> {
+    let Some(mirrors) = &config.mirrors else {
+        return fetch_recipe_from(client, config, recipe_id).await;
+    };
+    let mut last = None;
+    for mirror in mirrors.in_order() {
+        let config = DownloadConfig {
+            base_url: mirror.base_url.clone(),
+            requests: mirror.requests.clone(),
+            ..config.clone()
+        };
+        let fetched = fetch_recipe_from(client, &config, recipe_id).await;
+        let error = match &fetched {
+            Ok(Fetched::Status(status)) => is_unreachable("", Some(status.as_u16())),
+            Ok(_) => false,
+            Err(e) => is_unreachable(error_category(e.as_ref(), config.proxy.is_some()), None),
+        };
+        mirror.record(matches!(fetched, Ok(Fetched::Saved(_))), error);
+        if !error {
+            return fetched;
+        }
+        match &fetched {
+            Err(e) => {
+                warn!(mirror = %mirror.base_url, error = %e, "mirror failed; trying the next")
+            }
+            _ => {
+                warn!(mirror = %mirror.base_url, "mirror answered with a server error; trying the next")
+            }
+        }
+        last = Some(fetched);
+    }
+    last.expect("there's always a mirror")
+}
+
+/// Fetch one recipe from `config.base_url`.
+async fn fetch_recipe_from(
+    client: &Client,
+    config: &DownloadConfig,
+    recipe_id: u32,
+) -> Result<Fetched, Box<dyn Error + Send + Sync>> {
+    if let Some(fixtures) = &config.test_mode {
+        return fetch_fixture(config, fixtures, recipe_id).await;
+    }
+    let url = recipe_url(&config.base_url, recipe_id);
+
+    if let Some(precheck) = config.head_precheck.as_deref() {
+        if let Some(skipped) = head_precheck(client, &url, precheck, &config.requests).await? {
+            return Ok(skipped);
+        }
+    }
+
+    // What arrived of an earlier transfer that broke off, if the server
+    // said it could be resumed
+    let partial_path = config
+        .output_dir
+        .join(format!("{}{}", recipe_id, PARTIAL_SUFFIX));
+    let mut partial = if config.dry_run {
+        Vec::new()
+    } else {
+        fs::read(&partial_path).unwrap_or_default()
+    };
+
+    let user_agent = request_user_agent(config);
+    let mut request = client.get(&url).header(USER_AGENT_HEADER, user_agent);
+    // A range of a compressed transfer wouldn't follow on from the partial
+    // one's bytes, so those are only kept from plain transfers and asked
+    // for plain
+    if partial.is_empty() {
+        request = accept_compressed(request, config);
+    } else {
+        request = request.header(RANGE, format!("bytes={}-", partial.len()));
+    }
+    config.requests.pass().await;
+    let sent = Instant::now();
+    let mut response = send_download(request, config).await?;
+    config.responses.headers(sent.elapsed());
+    if !partial.is_empty() && !resumes_at(&response, partial.len()) {
+        debug!(
+            status = response.status().as_u16(),
+            "server didn't resume the transfer; starting over"
+        );
+        partial.clear();
+        let _ = fs::remove_file(&partial_path);
+        // A 200 is the whole file anyway
+        if matches!(
+            response.status(),
+            StatusCode::PARTIAL_CONTENT | StatusCode::RANGE_NOT_SATISFIABLE
+        ) {
+            config.requests.pass().await;
+            let sent = Instant::now();
+            response = send_download(
+                accept_compressed(
+                    client.get(&url).header(USER_AGENT_HEADER, user_agent),
+                    config,
+                ),
+                config,
+            )
+            .await?;
+            config.responses.headers(sent.elapsed());
+        }
+    }
+
+    if response.status() == StatusCode::TOO_MANY_REQUESTS {
+        return Ok(Fetched::RateLimited(rate_limited(
+            config,
+            response.headers(),
+        )));
+    }
+    if !response.status().is_success() {
+        debug!(status = response.status().as_u16(), "no recipe");
+        return Ok(Fetched::Status(response.status()));
+    }
+    // Redirected somewhere else, which is worth keeping unless it was to
+    // a page (a login form or an error) rather than the recipe
+    let final_url = (Url::parse(&url).ok().as_ref() != Some(response.url()))
+        .then(|| response.url().to_string());
+    if final_url.is_some() && is_web_page(response.headers()) {
+        debug!(url = %response.url(), "redirected to a page, not a recipe");
+        return Ok(Fetched::InvalidContent(response.status()));
+    }
+
+    // Get the filename from Content-Disposition header or use default
+    let filename = disposition_filename(response.headers(), recipe_id, &config.output_dir);
+
+    let status = response.status();
+    let keep_partial = (!config.dry_run).then_some(partial_path.as_path());
+    let bandwidth = config.bandwidth.as_deref();
+    let file_bar = config.file_bars.as_ref().and_then(|bars| {
+        let received = partial.len() as u64;
+        let total = response.content_length().map(|len| len + received);
+        bars.start(&filename, received, total)
+    });
+    let reading = Instant::now();
+    let body = read_body(
+        response,
+        partial,
+        keep_partial,
+        &config.transfers,
+        bandwidth,
+        config.read_timeout,
+        file_bar.as_ref(),
+    )
+    .await;
+    config.responses.body(reading.elapsed());
+    let Some(content) = body? else {
+        return Ok(Fetched::InvalidContent(status));
+    };
+    let provenance = Provenance {
+        user_agent: Some(String::from_utf8_lossy(user_agent.as_bytes()).into_owned()),
+        final_url,
+    };
+    store_recipe(config, filename, content, status, &provenance).await
+}
+
+/// Answer a recipe request from the fixtures instead of the site, after
+/// --mock-latency-ms, with some 404s and timeouts among them.
+async fn fetch_fixture(
+    config: &DownloadConfig,
+    fixtures: &Fixtures,
+    recipe_id: u32,
+) -> Result<Fetched, Box<dyn Error + Send + Sync>> {
+    config.requests.pass().await;
+    let sent = Instant::now();
+    tokio::time::sleep(fixtures.latency).await;
+    config.responses.headers(sent.elapsed());
+    match fixtures.answer()? {
+        MockResponse::NotFound => {
+            debug!(status = 404, "no recipe");
+            Ok(Fetched::Status(StatusCode::NOT_FOUND))
+        }
+        MockResponse::TimedOut => Err(Box::new(TimedOut::Request(config.request_timeout))),
+        MockResponse::Recipe(content) => {
+            store_recipe(
+                config,
+                format!("{}.bsmx", recipe_id),
+                content,
+                StatusCode::OK,
+                &Provenance::default(),
+            )
+            .await
+        }
+    }
+}
+
+/// Ask for `request`'s response compressed in transit, unless
+/// --no-compression says not to. [`read_body`] decompresses it, rather than
+/// reqwest, so the bytes on the wire can be counted.
+pub(crate) fn accept_compressed(
+    request: RequestBuilder,
+    config: &DownloadConfig,
+) -> RequestBuilder {
+    if config.transfer_compression {
+        request.header(ACCEPT_ENCODING, "gzip, br")
+    } else {
+        request
+    }
+}
+
+/// Send a request for recipes. With --no-total-timeout-when-streaming the
+/// request timeout only covers waiting for the response to start, and the
+/// body has for as long as it keeps arriving within the read timeout.
+///
+/// A redirect to another host that the client handed back because of the
+/// --header values is followed here, from a client without them. Any
+/// redirects after that are counted afresh against --max-redirects.
+pub(crate) async fn send_download(
+    request: RequestBuilder,
+    config: &DownloadConfig,
+) -> Result<Response, Box<dyn Error + Send + Sync>> {
+    let (client, request) = request.build_split();
+    let request = request?;
+    // Only the request's own headers; the client adds the --header values
+    let headers = request.headers().clone();
+    let response = execute(&client, request, config).await?;
+
+    let elsewhere = response
+        .headers()
+        .get(LOCATION)
+        .and_then(|location| location.to_str().ok())
+        .and_then(|location| response.url().join(location).ok())
+        .filter(|location| location.host_str() != response.url().host_str());
+    let Some(location) = elsewhere.filter(|_| {
+        response.status().is_redirection()
+            && !config.headers.is_empty()
+            && !config.same_host_redirects_only
+    }) else {
+        return Ok(response);
+    };
+    debug!(url = %location, "redirected to another host; following without the --header values");
+    let plain = build_client(&DownloadConfig {
+        headers: Vec::new(),
+        ..config.clone()
+    })?;
+    let mut request = plain.get(location).build()?;
+    request.headers_mut().extend(headers);
+    execute(&plain, request, config).await
+}
+
+async fn execute(
+    client: &Client,
+    mut request: Request,
+    config: &DownloadConfig,
+) -> Result<Response, Box<dyn Error + Send + Sync>> {
+    if !config.stream_past_request_timeout {
+        return Ok(client.execute(request).await?);
+    }
+    // tokio puts a deadline this far off at its far future
+    *request.timeout_mut() = Some(Duration::MAX);
+    match tokio::time::timeout(config.request_timeout, client.execute(request)).await {
+        Ok(response) => Ok(response?),
+        Err(_) => Err(Box::new(TimedOut::Request(config.request_timeout))),
+    }
+}
+
+/// The filename a response's Content-Disposition gives, cut down to its
+/// last component so the server can't name a file outside the output
+/// folder. Only a `.bsmx` name that can't be mistaken for another
+/// recipe's, and that nothing in `output_dir` has yet, is taken; otherwise
+/// `{id}.bsmx`.
+fn disposition_filename(headers: &HeaderMap, recipe_id: u32, output_dir: &Path) -> String {
+    let own = format!("{}.bsmx", recipe_id);
+    headers
+        .get(CONTENT_DISPOSITION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|s| s.split("filename=").nth(1))
+        .and_then(|f| {
+            let f = f
+                .split(';')
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .trim_matches('"');
+            Path::new(f).file_name()
+        })
+        .map(|f| f.to_string_lossy().into_owned())
+        .filter(|f| {
+            match f.strip_suffix(".bsmx") {
+                None | Some("") => false,
+                // A numbered name is some recipe's, so it has to be this one's
+                Some(stem) if stem.parse::<u32>().is_ok() => *f == own,
+                Some(_) => !output_dir.join(f).exists(),
+            }
+        })
+        .unwrap_or(own)
+}
+
+/// Check a recipe's content and, unless it's turned away or this is a dry
+/// run, save it under `filename` as the layout and compression say.
+/// `provenance` is how it was fetched, for the sidecar. Parsing runs on
+/// tokio's blocking threads, so a slow file doesn't hold up the downloads
+/// around it.
+pub(crate) async fn store_recipe(
+    config: &DownloadConfig,
+    filename: String,
+    content: Vec<u8>,
+    status: StatusCode,
+    provenance: &Provenance,
+) -> Result<Fetched, Box<dyn Error + Send + Sync>> {
+    // Check if content seems valid (a complete XML document, not an
+    // error page or a transfer cut short), and parse it if the recipe is
+    // wanted; a recipe that won't parse is still kept, just without
+    // metadata, unless it has to prove it's complete enough
+    let wants_recipe = config.sidecar
+        || config.skip_incomplete.is_some()
+        || config.style_quota.is_some()
+        || config.storage_layout == StorageLayout::Style;
+    let content: Arc<[u8]> = content.into();
+    let parsing = content.clone();
+    let timeout = parse_timeout();
+    let checking = tokio::task::spawn_blocking(move || {
+        within_parse_timeout(|| {
+            let document = parsing
+                .starts_with(b"<")
+                .then(|| parse_document(&parsing).ok())
+                .flatten();
+            let recipe = document
+                .as_ref()
+                .filter(|_| wants_recipe)
+                .and_then(|document| recipe_from_document(document).ok());
+            (document.is_some(), recipe)
+        })
+    });
+    // The parse gives itself up at the deadline too; this is in case it
+    // doesn't get back to the clock in time
+    let checked = match tokio::time::timeout(timeout, checking).await {
+        Ok(Ok(checked)) => checked,
+        Ok(Err(e)) => {
+            warn!("the parser failed: {}", e);
+            Ok((false, None))
+        }
+        Err(_) => Err(ParseTimedOut(timeout)),
+    };
+    let (valid, recipe) = match checked {
+        Ok(checked) => checked,
+        Err(e) => {
+            let kept = config
+                .output_dir
+                .join(PARSE_FAILED_DIR)
+                .join(file_name(Path::new(&filename)));
+            if !config.dry_run {
+                fs::create_dir_all(kept.parent().unwrap_or(&config.output_dir))?;
+                fs::write(&kept, &content)?;
+            }
+            warn!(file = %kept.display(), "{}", e);
+            return Ok(Fetched::ParseTimeout(status));
+        }
+    };
+    if !valid {
+        if let Some(login) = config
+            .login
+            .as_deref()
+            .filter(|l| l.is_login_page(&content))
+        {
+            debug!(url = %login.url, "got the login page instead of a recipe");
+            return Ok(Fetched::LoginPage);
+        }
+        debug!(
+            bytes = content.len(),
+            "response is not a well-formed recipe"
+        );
+        return Ok(Fetched::InvalidContent(status));
+    }
+
+    if let Some(min_score) = config.skip_incomplete {
+        let score = recipe.as_ref().map_or(0.0, completeness_score);
+        if score < min_score {
+            debug!(score, "recipe is too incomplete to keep");
+            return Ok(Fetched::Incomplete(status));
+        }
+    }
+    if let Some(quota) = &config.style_quota {
+        let style = recipe.as_ref().map_or("", |r| r.style.name.as_str());
+        if !quota.admit(style) {
+            debug!(style, "style already has its share of the sample");
+            return Ok(Fetched::StyleFull(status));
+        }
+    }
+
+    if config.dry_run {
+        debug!(%filename, bytes = content.len(), "found");
+        return Ok(Fetched::Saved(RecipeInfo {
+            filename,
+            bytes: content.len(),
+            status: status.as_u16(),
+        }));
+    }
+
+    let filename = format!(
+        "{}{}",
+        config
+            .storage_layout
+            .path_for(&filename, &content, recipe.as_ref()),
+        config.compression.extension()
+    );
+    let file_path = config.output_dir.join(&filename);
+    if let Some(parent) = file_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    config
+        .compression
+        .write(&file_path, &content, config.compression_level)?;
+
+    if config.sidecar {
+        if let Some(recipe) = &recipe {
+            write_sidecar(&file_path, recipe, provenance).map_err(|e| e.to_string())?;
+        }
+    }
+
+    debug!(%filename, bytes = content.len(), "saved");
+    Ok(Fetched::Saved(RecipeInfo {
+        filename,
+        bytes: content.len(),
+        status: status.as_u16(),
+    }))
+}
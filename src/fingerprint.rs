@@ -0,0 +1,72 @@
+use crate::bsmx::Recipe;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+/// A recipe's ingredients scaled to one liter of batch, which is what the
+/// fingerprint hashes.
+#[derive(Debug, Serialize)]
+struct Canonical {
+    fermentables: Vec<Amount>,
+    hops: Vec<Amount>,
+    yeasts: Vec<String>,
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+struct Amount {
+    name: String,
+    /// Per liter of batch, to the nearest gram
+    grams: i64,
+}
+
+/// SHA-256 of a recipe's ingredients per liter of batch, rounded to the
+/// nearest gram and sorted by name within each kind, so the same recipe
+/// scaled to another batch size has the same fingerprint. Names are
+/// compared ignoring case and surrounding space. A recipe without a batch
+/// size is hashed as if it made one liter.
+pub fn ingredient_fingerprint(recipe: &Recipe) -> [u8; 32] {
+    let liters = if recipe.batch_size_l > 0.0 {
+        recipe.batch_size_l
+    } else {
+        1.0
+    };
+    let per_liter = |name: &str, grams: f64| Amount {
+        name: name_key(name),
+        grams: (grams / liters).round() as i64,
+    };
+    let mut canonical = Canonical {
+        fermentables: recipe
+            .fermentables
+            .iter()
+            .map(|f| per_liter(&f.name, f.amount_kg * 1000.0))
+            .collect(),
+        hops: recipe
+            .hops
+            .iter()
+            .map(|h| per_liter(&h.name, h.amount_g))
+            .collect(),
+        yeasts: recipe.yeasts.iter().map(|y| name_key(&y.name)).collect(),
+    };
+    canonical.fermentables.sort();
+    canonical.hops.sort();
+    canonical.yeasts.sort();
+    let json = serde_json::to_vec(&canonical).expect("plain structs always serialize");
+    Sha256::digest(json).into()
+}
+
+/// The fingerprint as lowercase hex, as sidecars and reports show it.
+pub fn fingerprint_hex(recipe: &Recipe) -> String {
+    ingredient_fingerprint(recipe)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Whether a recipe lists any ingredients; ones that don't all share a
+/// fingerprint, so it says nothing about them.
+pub fn has_ingredients(recipe: &Recipe) -> bool {
+    !(recipe.fermentables.is_empty() && recipe.hops.is_empty() && recipe.yeasts.is_empty())
+}
+
+fn name_key(name: &str) -> String {
+    name.trim().to_lowercase()
+}
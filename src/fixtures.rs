@@ -0,0 +1,71 @@
+use crate::cli::StorageLayout;
+use crate::collection::recipe_files_in;
+use crate::compression::read_bsmx;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use std::error::Error;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Where `beerscape fixtures` puts the recipes it picks unless told
+/// otherwise
+pub const FIXTURES_DIR: &str = "fixtures";
+/// Share of test-mode requests answered with a 404, as for an ID with no
+/// recipe
+pub const NOT_FOUND_RATE: f64 = 0.10;
+/// Share of test-mode requests that time out, to be retried
+pub const TIMEOUT_RATE: f64 = 0.05;
+
+/// What a test-mode request gets instead of the site's answer.
+#[derive(Debug, PartialEq)]
+pub enum MockResponse {
+    NotFound,
+    TimedOut,
+    /// One of the fixtures, uncompressed
+    Recipe(Vec<u8>),
+}
+
+/// Recipe files standing in for the site with --test-mode, so the whole
+/// download pipeline runs without a network.
+#[derive(Debug)]
+pub struct Fixtures {
+    files: Vec<PathBuf>,
+    /// How long each request takes to answer
+    pub latency: Duration,
+}
+
+impl Fixtures {
+    /// The recipe files in `dir`, compressed or not.
+    pub fn load(dir: &Path, latency: Duration) -> Result<Fixtures, Box<dyn Error>> {
+        let files = recipe_files_in(dir, StorageLayout::Flat)?;
+        if files.is_empty() {
+            return Err(format!(
+                "no fixtures in {}; copy some from the collection with `beerscape fixtures`",
+                dir.display()
+            )
+            .into());
+        }
+        Ok(Fixtures { files, latency })
+    }
+
+    /// Answer a request: a 404 or a timeout at their rates, otherwise a
+    /// fixture picked at random.
+    pub fn answer(&self) -> io::Result<MockResponse> {
+        self.answer_with(&mut rand::thread_rng())
+    }
+
+    pub fn answer_with(&self, rng: &mut impl Rng) -> io::Result<MockResponse> {
+        let roll: f64 = rng.gen();
+        if roll < NOT_FOUND_RATE {
+            return Ok(MockResponse::NotFound);
+        }
+        if roll < NOT_FOUND_RATE + TIMEOUT_RATE {
+            return Ok(MockResponse::TimedOut);
+        }
+        let Some(path) = self.files.choose(rng) else {
+            return Ok(MockResponse::NotFound);
+        };
+        Ok(MockResponse::Recipe(read_bsmx(path)?))
+    }
+}
@@ -0,0 +1,200 @@
+use rand::Rng;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::ops::RangeInclusive;
+use std::path::Path;
+use std::str::FromStr;
+
+/// An inclusive range of recipe IDs, written `A-B`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdRange(pub RangeInclusive<u32>);
+
+impl FromStr for IdRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start, end) = s
+            .split_once('-')
+            .ok_or_else(|| format!("expected START-END, got {:?}", s))?;
+        let parse = |n: &str| {
+            n.trim()
+                .parse::<u32>()
+                .map_err(|_| format!("invalid recipe ID {:?}", n.trim()))
+        };
+        let (start, end) = (parse(start)?, parse(end)?);
+        if start > end {
+            return Err(format!("range {}-{} is backwards", start, end));
+        }
+        Ok(IdRange(start..=end))
+    }
+}
+
+impl fmt::Display for IdRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.0.start(), self.0.end())
+    }
+}
+
+/// Read a file of recipe IDs, one per line. Anything after a `#` is a
+/// comment, and blank lines are ignored.
+pub fn read_id_file(path: &Path) -> Result<Vec<u32>, Box<dyn Error>> {
+    let mut ids = Vec::new();
+    for (n, line) in fs::read_to_string(path)?.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let id = line
+            .parse()
+            .map_err(|_| format!("{}:{}: invalid recipe ID {:?}", path.display(), n + 1, line))?;
+        ids.push(id);
+    }
+    Ok(ids)
+}
+
+/// Which IDs in an inclusive range have been attempted, one bit each, so
+/// the full 1..=4,000,000 range costs about 500 KB however many are tried.
+/// IDs outside the range are never tracked.
+#[derive(Debug, Clone)]
+pub struct IdTracker {
+    start: u32,
+    len: u64,
+    /// Bit `i` is set once `start + i` has been attempted. Bits past the
+    /// end of the range are set from the start, so they never look free
+    bits: Vec<u64>,
+    attempted: u64,
+}
+
+/// Random picks tried before searching the bitmap for a free ID; with
+/// most of the range still free one of them almost always lands.
+const RANDOM_PICKS: usize = 8;
+
+impl IdTracker {
+    pub fn new(range: RangeInclusive<u32>) -> Self {
+        let (start, end) = range.into_inner();
+        let len = (end as u64 + 1).saturating_sub(start as u64);
+        let mut bits = vec![0; len.div_ceil(64) as usize];
+        let tail = len % 64;
+        if let Some(last) = bits.last_mut().filter(|_| tail > 0) {
+            *last = !0 << tail;
+        }
+        IdTracker {
+            start,
+            len,
+            bits,
+            attempted: 0,
+        }
+    }
+
+    pub fn contains(&self, id: u32) -> bool {
+        self.position(id)
+            .is_some_and(|i| self.bits[i / 64] & (1 << (i % 64)) != 0)
+    }
+
+    /// Mark an ID attempted, returning whether it wasn't already.
+    pub fn insert(&mut self, id: u32) -> bool {
+        let Some(i) = self.position(id) else {
+            return false;
+        };
+        let word = &mut self.bits[i / 64];
+        let newly = *word & (1 << (i % 64)) == 0;
+        *word |= 1 << (i % 64);
+        self.attempted += newly as u64;
+        newly
+    }
+
+    /// Make an ID available again, e.g. to retry after a timeout,
+    /// returning whether it had been attempted.
+    pub fn remove(&mut self, id: u32) -> bool {
+        let Some(i) = self.position(id) else {
+            return false;
+        };
+        let word = &mut self.bits[i / 64];
+        let was = *word & (1 << (i % 64)) != 0;
+        *word &= !(1 << (i % 64));
+        self.attempted -= was as u64;
+        was
+    }
+
+    /// IDs in the range not yet attempted.
+    pub fn remaining_count(&self) -> u64 {
+        self.len - self.attempted
+    }
+
+    /// Pick an unattempted ID at random and mark it attempted, or `None`
+    /// once the whole range has been.
+    pub fn next(&mut self, rng: &mut impl Rng) -> Option<u32> {
+        if self.remaining_count() == 0 {
+            return None;
+        }
+        self.next_in(self.start..=self.start + (self.len - 1) as u32, rng)
+    }
+
+    /// Like [`next`](Self::next), but only from the part of `range` the
+    /// tracker covers.
+    pub fn next_in(&mut self, range: RangeInclusive<u32>, rng: &mut impl Rng) -> Option<u32> {
+        let (lo, hi) = self.span(&range)?;
+        for _ in 0..RANDOM_PICKS {
+            let id = self.start + rng.gen_range(lo..hi) as u32;
+            if self.insert(id) {
+                return Some(id);
+            }
+        }
+        // Late in a run most picks are taken, so choose among the free IDs
+        // directly rather than rolling until one lands
+        let free = self.free_in(lo, hi);
+        if free == 0 {
+            return None;
+        }
+        let mut k = rng.gen_range(0..free);
+        for w in lo / 64..=(hi - 1) / 64 {
+            let mut free = !self.bits[w] & word_mask(w, lo, hi);
+            let count = free.count_ones() as u64;
+            if k >= count {
+                k -= count;
+                continue;
+            }
+            for _ in 0..k {
+                free &= free - 1;
+            }
+            let id = self.start + (w * 64) as u32 + free.trailing_zeros();
+            self.insert(id);
+            return Some(id);
+        }
+        None
+    }
+
+    /// IDs in `range` not yet attempted.
+    pub fn remaining_in(&self, range: RangeInclusive<u32>) -> u64 {
+        self.span(&range).map_or(0, |(lo, hi)| self.free_in(lo, hi))
+    }
+
+    /// Positions `lo..hi` of the IDs in `range` that are tracked.
+    fn span(&self, range: &RangeInclusive<u32>) -> Option<(usize, usize)> {
+        let lo = (*range.start() as u64).max(self.start as u64) - self.start as u64;
+        let hi = (*range.end() as u64 + 1).min(self.start as u64 + self.len);
+        let hi = hi.checked_sub(self.start as u64)?;
+        (lo < hi).then_some((lo as usize, hi as usize))
+    }
+
+    fn free_in(&self, lo: usize, hi: usize) -> u64 {
+        (lo / 64..=(hi - 1) / 64)
+            .map(|w| (!self.bits[w] & word_mask(w, lo, hi)).count_ones() as u64)
+            .sum()
+    }
+
+    fn position(&self, id: u32) -> Option<usize> {
+        let offset = (id as u64).checked_sub(self.start as u64)?;
+        (offset < self.len).then_some(offset as usize)
+    }
+}
+
+/// The bits of word `w` that hold positions `lo..hi`.
+fn word_mask(w: usize, lo: usize, hi: usize) -> u64 {
+    let first = w * 64;
+    let from = lo.saturating_sub(first);
+    let to = (hi - first).min(64);
+    let below_to = if to == 64 { !0 } else { (1 << to) - 1 };
+    below_to & (!0 << from)
+}
@@ -0,0 +1,505 @@
+use crate::brewer;
+use crate::bsmx::Recipe;
+use crate::cli::FilterArgs;
+use crate::collection::stored_name;
+use crate::compression::file_sizes;
+use crate::tags::{matches_tags, tag_filter, Tags};
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension, Row, Transaction};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Name of the index database inside the recipes directory.
+pub const INDEX_FILE: &str = "index.db";
+
+/// One indexed recipe file.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct IndexEntry {
+    /// File name relative to the recipes directory
+    pub filename: String,
+    /// Site ID the file was downloaded as, when known
+    pub recipe_id: Option<u32>,
+    /// Position within a split multi-recipe download, starting at 1
+    pub sub_id: Option<u32>,
+    pub name: String,
+    /// Brewer with whitespace tidied, as it should be displayed
+    pub brewer: String,
+    pub style: String,
+    pub og: f64,
+    pub fg: f64,
+    pub abv: f64,
+    pub ibu: f64,
+    pub color_srm: f64,
+    /// Style search the recipe was downloaded through, with --filter-style
+    pub discovered_style: Option<String>,
+    /// Size of the file on disk, when known
+    pub stored_bytes: Option<u64>,
+    /// Size of the BSMX in it, which is bigger when the file is compressed
+    pub bsmx_bytes: Option<u64>,
+    /// The user's tags, from `tags.json`
+    pub tags: Vec<String>,
+}
+
+impl IndexEntry {
+    pub fn new(filename: &str, recipe: &Recipe) -> Self {
+        IndexEntry {
+            filename: filename.to_string(),
+            recipe_id: None,
+            sub_id: None,
+            name: recipe.name.clone(),
+            brewer: brewer::display_name(&recipe.brewer),
+            style: recipe.style.name.clone(),
+            og: recipe.og,
+            fg: recipe.fg,
+            abv: recipe.abv,
+            ibu: recipe.ibu,
+            color_srm: recipe.color_srm,
+            discovered_style: None,
+            stored_bytes: None,
+            bsmx_bytes: None,
+            tags: Vec::new(),
+        }
+    }
+
+    /// Record the sizes of the file at `path`, as [`file_sizes`] finds them.
+    pub fn with_sizes(mut self, path: &Path) -> Self {
+        if let Ok((stored, bsmx)) = file_sizes(path) {
+            self.stored_bytes = Some(stored);
+            self.bsmx_bytes = Some(bsmx);
+        }
+        self
+    }
+}
+
+/// SQLite index of the recipes directory, so queries don't have to parse
+/// every file.
+pub struct Index {
+    conn: Connection,
+    dir: PathBuf,
+}
+
+impl Index {
+    /// Open the index in `dir`, creating it if needed.
+    pub fn open(dir: &Path) -> rusqlite::Result<Self> {
+        let conn = Connection::open(dir.join(INDEX_FILE))?;
+        // Wait out a rebuild in another process rather than failing
+        conn.busy_timeout(Duration::from_secs(5))?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS recipes (
+                filename TEXT PRIMARY KEY,
+                recipe_id INTEGER,
+                sub_id INTEGER,
+                name TEXT NOT NULL,
+                brewer TEXT NOT NULL DEFAULT '',
+                brewer_key TEXT NOT NULL DEFAULT '',
+                style TEXT NOT NULL,
+                og REAL NOT NULL,
+                fg REAL NOT NULL,
+                abv REAL NOT NULL,
+                ibu REAL NOT NULL,
+                color_srm REAL NOT NULL,
+                discovered_style TEXT,
+                stored_bytes INTEGER,
+                bsmx_bytes INTEGER
+            );
+            CREATE INDEX IF NOT EXISTS recipes_recipe_id ON recipes (recipe_id);
+            CREATE TABLE IF NOT EXISTS tags (
+                filename TEXT NOT NULL,
+                tag TEXT NOT NULL,
+                PRIMARY KEY (filename, tag)
+            );
+            CREATE INDEX IF NOT EXISTS tags_tag ON tags (tag);",
+        )?;
+
+        // Indexes built before brewers were tracked get the columns added
+        // empty; `beerscape index` fills them in
+        let has_brewer = conn
+            .prepare("SELECT 1 FROM pragma_table_info('recipes') WHERE name = 'brewer'")?
+            .exists([])?;
+        if !has_brewer {
+            conn.execute_batch(
+                "ALTER TABLE recipes ADD COLUMN brewer TEXT NOT NULL DEFAULT '';
+                ALTER TABLE recipes ADD COLUMN brewer_key TEXT NOT NULL DEFAULT '';",
+            )?;
+        }
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS recipes_brewer_key ON recipes (brewer_key)",
+            [],
+        )?;
+        let has_discovered_style = conn
+            .prepare("SELECT 1 FROM pragma_table_info('recipes') WHERE name = 'discovered_style'")?
+            .exists([])?;
+        if !has_discovered_style {
+            conn.execute_batch("ALTER TABLE recipes ADD COLUMN discovered_style TEXT;")?;
+        }
+        // Sizes stay unknown until the files are indexed again
+        let has_sizes = conn
+            .prepare("SELECT 1 FROM pragma_table_info('recipes') WHERE name = 'stored_bytes'")?
+            .exists([])?;
+        if !has_sizes {
+            conn.execute_batch(
+                "ALTER TABLE recipes ADD COLUMN stored_bytes INTEGER;
+                ALTER TABLE recipes ADD COLUMN bsmx_bytes INTEGER;",
+            )?;
+        }
+
+        Ok(Index {
+            conn,
+            dir: dir.to_path_buf(),
+        })
+    }
+
+    /// Group writes so bulk updates don't sync to disk once per row.
+    /// Changes are rolled back unless the transaction is committed.
+    pub fn transaction(&self) -> rusqlite::Result<Transaction<'_>> {
+        self.conn.unchecked_transaction()
+    }
+
+    /// Insert or update an entry. IDs already on record are kept when the
+    /// new entry doesn't know them, so reindexing a file loses nothing.
+    pub fn upsert(&self, entry: &IndexEntry) -> rusqlite::Result<()> {
+        self.conn.execute(
+            "INSERT INTO recipes
+                (filename, recipe_id, sub_id, name, brewer, brewer_key, style,
+                 og, fg, abv, ibu, color_srm, discovered_style, stored_bytes, bsmx_bytes)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)
+            ON CONFLICT (filename) DO UPDATE SET
+                recipe_id = COALESCE(excluded.recipe_id, recipe_id),
+                sub_id = COALESCE(excluded.sub_id, sub_id),
+                name = excluded.name,
+                brewer = excluded.brewer,
+                brewer_key = excluded.brewer_key,
+                style = excluded.style,
+                og = excluded.og,
+                fg = excluded.fg,
+                abv = excluded.abv,
+                ibu = excluded.ibu,
+                color_srm = excluded.color_srm,
+                discovered_style = COALESCE(excluded.discovered_style, discovered_style),
+                stored_bytes = COALESCE(excluded.stored_bytes, stored_bytes),
+                bsmx_bytes = COALESCE(excluded.bsmx_bytes, bsmx_bytes)",
+            params![
+                entry.filename,
+                entry.recipe_id,
+                entry.sub_id,
+                entry.name,
+                entry.brewer,
+                brewer::key(&entry.brewer).unwrap_or_default(),
+                entry.style,
+                entry.og,
+                entry.fg,
+                entry.abv,
+                entry.ibu,
+                entry.color_srm,
+                entry.discovered_style,
+                entry.stored_bytes,
+                entry.bsmx_bytes,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// The name the index gives the file at `path`.
+    pub fn name_of(&self, path: &Path) -> String {
+        stored_name(&self.dir, path)
+    }
+
+    /// Re-key an entry after its file has moved, replacing any stale entry
+    /// already under the new name.
+    pub fn rename(&self, from: &str, to: &str) -> rusqlite::Result<()> {
+        self.conn.execute(
+            "UPDATE OR REPLACE recipes SET filename = ?2 WHERE filename = ?1",
+            [from, to],
+        )?;
+        self.conn.execute(
+            "UPDATE OR REPLACE tags SET filename = ?2 WHERE filename = ?1",
+            [from, to],
+        )?;
+        Ok(())
+    }
+
+    /// Replace one file's tags.
+    pub fn set_tags(&self, filename: &str, tags: &[String]) -> rusqlite::Result<()> {
+        self.conn
+            .execute("DELETE FROM tags WHERE filename = ?1", [filename])?;
+        for tag in tags {
+            self.conn.execute(
+                "INSERT OR IGNORE INTO tags (filename, tag) VALUES (?1, ?2)",
+                [filename, tag],
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Replace every file's tags with those in `tags`.
+    pub fn sync_tags(&self, tags: &Tags) -> rusqlite::Result<()> {
+        self.conn.execute("DELETE FROM tags", [])?;
+        for (filename, tags) in tags.iter() {
+            self.set_tags(filename, tags)?;
+        }
+        Ok(())
+    }
+
+    /// Record a file's size on disk and the size of the BSMX it holds.
+    pub fn set_sizes(&self, filename: &str, stored: u64, bsmx: u64) -> rusqlite::Result<()> {
+        self.conn.execute(
+            "UPDATE recipes SET stored_bytes = ?2, bsmx_bytes = ?3 WHERE filename = ?1",
+            params![filename, stored, bsmx],
+        )?;
+        Ok(())
+    }
+
+    /// Tag every file downloaded as `recipe_id` with the style search it
+    /// was found through.
+    pub fn set_discovered_style(&self, recipe_id: u32, style: &str) -> rusqlite::Result<()> {
+        self.conn.execute(
+            "UPDATE recipes SET discovered_style = ?2 WHERE recipe_id = ?1",
+            params![recipe_id, style],
+        )?;
+        Ok(())
+    }
+
+    pub fn remove(&self, filename: &str) -> rusqlite::Result<()> {
+        self.conn
+            .execute("DELETE FROM recipes WHERE filename = ?1", [filename])?;
+        self.conn
+            .execute("DELETE FROM tags WHERE filename = ?1", [filename])?;
+        Ok(())
+    }
+
+    /// The site ID a file was downloaded as, if the index knows it.
+    pub fn recipe_id(&self, filename: &str) -> rusqlite::Result<Option<u32>> {
+        self.conn
+            .query_row(
+                "SELECT recipe_id FROM recipes WHERE filename = ?1",
+                [filename],
+                |row| row.get(0),
+            )
+            .optional()
+            .map(Option::flatten)
+    }
+
+    /// Every site ID in the index.
+    pub fn recipe_ids(&self) -> rusqlite::Result<HashSet<u32>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT DISTINCT recipe_id FROM recipes WHERE recipe_id IS NOT NULL")?;
+        let rows = stmt.query_map([], |row| row.get(0))?;
+        rows.collect()
+    }
+
+    pub fn count(&self) -> rusqlite::Result<usize> {
+        self.conn
+            .query_row("SELECT COUNT(*) FROM recipes", [], |row| row.get(0))
+    }
+
+    pub fn filenames(&self) -> rusqlite::Result<Vec<String>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT filename FROM recipes ORDER BY filename")?;
+        let rows = stmt.query_map([], |row| row.get(0))?;
+        rows.collect()
+    }
+
+    /// Every entry, in file name order.
+    pub fn entries(&self) -> rusqlite::Result<Vec<IndexEntry>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM recipes ORDER BY filename",
+            ENTRY_COLUMNS
+        ))?;
+        let rows = stmt.query_map([], entry_from_row)?;
+        rows.collect()
+    }
+
+    /// Entries by one brewer, matched the way [`brewer::key`] groups them.
+    /// Asking for a placeholder such as "Unknown" gives the recipes with no
+    /// brewer.
+    pub fn entries_by_brewer(&self, name: &str) -> rusqlite::Result<Vec<IndexEntry>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM recipes WHERE brewer_key = ?1 ORDER BY filename",
+            ENTRY_COLUMNS
+        ))?;
+        let rows = stmt.query_map([brewer::key(name).unwrap_or_default()], entry_from_row)?;
+        rows.collect()
+    }
+
+    pub fn get(&self, filename: &str) -> rusqlite::Result<Option<IndexEntry>> {
+        self.conn
+            .query_row(
+                &format!("SELECT {} FROM recipes WHERE filename = ?1", ENTRY_COLUMNS),
+                [filename],
+                entry_from_row,
+            )
+            .optional()
+    }
+
+    pub fn count_matching(&self, filter: &Filter) -> rusqlite::Result<usize> {
+        let (clause, values) = filter.where_clause();
+        self.conn.query_row(
+            &format!("SELECT COUNT(*) FROM recipes WHERE {}", clause),
+            params_from_iter(values),
+            |row| row.get(0),
+        )
+    }
+
+    /// One page of the entries matching `filter`, ordered by name, for
+    /// callers that only need what's on screen.
+    pub fn matching(
+        &self,
+        filter: &Filter,
+        offset: usize,
+        limit: usize,
+    ) -> rusqlite::Result<Vec<IndexEntry>> {
+        let (clause, values) = filter.where_clause();
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM recipes WHERE {}
+            ORDER BY name COLLATE NOCASE, filename LIMIT {} OFFSET {}",
+            ENTRY_COLUMNS, clause, limit, offset
+        ))?;
+        let rows = stmt.query_map(params_from_iter(values), entry_from_row)?;
+        rows.collect()
+    }
+}
+
+/// Which entries a search returns. Empty fields match everything, and
+/// text matches ignore ASCII case.
+#[derive(Debug, Clone, Default)]
+pub struct Filter {
+    /// Substring of the name, style or brewer
+    pub text: String,
+    /// Substring of the style name
+    pub style: String,
+    /// Brewer, matched the way [`brewer::key`] groups them
+    pub brewer: Option<String>,
+    pub min_abv: Option<f64>,
+    pub max_abv: Option<f64>,
+    /// Tags, as a [`tag_filter`](crate::tags::tag_filter): every group
+    /// must have one of its tags on the recipe
+    pub tags: Vec<Vec<String>>,
+}
+
+impl FilterArgs {
+    pub fn to_filter(&self) -> Filter {
+        Filter {
+            text: self.query.clone().unwrap_or_default(),
+            style: self.style.clone().unwrap_or_default(),
+            brewer: self.brewer.clone(),
+            min_abv: self.min_abv,
+            max_abv: self.max_abv,
+            tags: tag_filter(&self.tag),
+        }
+    }
+}
+
+impl Filter {
+    /// Whether the filter lets every entry through.
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+            && self.style.is_empty()
+            && self.brewer.is_none()
+            && self.min_abv.is_none()
+            && self.max_abv.is_none()
+            && self.tags.is_empty()
+    }
+
+    /// Whether `entry` matches, for callers holding entries in memory. Gives
+    /// the same answers as the index's own queries.
+    pub fn matches(&self, entry: &IndexEntry) -> bool {
+        let contains = |field: &str, wanted: &str| {
+            field
+                .to_ascii_lowercase()
+                .contains(&wanted.to_ascii_lowercase())
+        };
+        (self.text.is_empty()
+            || [&entry.name, &entry.style, &entry.brewer]
+                .iter()
+                .any(|field| contains(field, &self.text)))
+            && (self.style.is_empty() || contains(&entry.style, &self.style))
+            && self.brewer.as_ref().is_none_or(|name| {
+                brewer::key(&entry.brewer).unwrap_or_default()
+                    == brewer::key(name).unwrap_or_default()
+            })
+            && self.min_abv.is_none_or(|min| entry.abv >= min)
+            && self.max_abv.is_none_or(|max| entry.abv <= max)
+            && matches_tags(&entry.tags, &self.tags)
+    }
+
+    fn where_clause(&self) -> (String, Vec<Value>) {
+        let mut conditions = vec!["1".to_string()];
+        let mut values = Vec::new();
+        if !self.text.is_empty() {
+            values.push(Value::Text(like_pattern(&self.text)));
+            let n = values.len();
+            conditions.push(format!(
+                "(name LIKE ?{n} ESCAPE '\\' OR style LIKE ?{n} ESCAPE '\\' \
+                 OR brewer LIKE ?{n} ESCAPE '\\')",
+                n = n
+            ));
+        }
+        if !self.style.is_empty() {
+            values.push(Value::Text(like_pattern(&self.style)));
+            conditions.push(format!("style LIKE ?{} ESCAPE '\\'", values.len()));
+        }
+        if let Some(name) = &self.brewer {
+            values.push(Value::Text(brewer::key(name).unwrap_or_default()));
+            conditions.push(format!("brewer_key = ?{}", values.len()));
+        }
+        for (comparison, bound) in [("abv >=", self.min_abv), ("abv <=", self.max_abv)] {
+            if let Some(bound) = bound {
+                values.push(Value::Real(bound));
+                conditions.push(format!("{} ?{}", comparison, values.len()));
+            }
+        }
+        for any in &self.tags {
+            let mut placeholders = Vec::new();
+            for tag in any {
+                values.push(Value::Text(tag.clone()));
+                placeholders.push(format!("?{}", values.len()));
+            }
+            conditions.push(format!(
+                "filename IN (SELECT filename FROM tags WHERE tag IN ({}))",
+                placeholders.join(", ")
+            ));
+        }
+        (conditions.join(" AND "), values)
+    }
+}
+
+pub(crate) fn like_pattern(query: &str) -> String {
+    let escaped = query
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    format!("%{}%", escaped)
+}
+
+// Tags can't have commas, so they're joined with them
+const ENTRY_COLUMNS: &str =
+    "filename, recipe_id, sub_id, name, brewer, style, og, fg, abv, ibu, color_srm, discovered_style,
+    stored_bytes, bsmx_bytes,
+    (SELECT group_concat(tag, ',' ORDER BY tag) FROM tags WHERE tags.filename = recipes.filename)";
+
+fn entry_from_row(row: &Row) -> rusqlite::Result<IndexEntry> {
+    Ok(IndexEntry {
+        filename: row.get(0)?,
+        recipe_id: row.get(1)?,
+        sub_id: row.get(2)?,
+        name: row.get(3)?,
+        brewer: row.get(4)?,
+        style: row.get(5)?,
+        og: row.get(6)?,
+        fg: row.get(7)?,
+        abv: row.get(8)?,
+        ibu: row.get(9)?,
+        color_srm: row.get(10)?,
+        discovered_style: row.get(11)?,
+        stored_bytes: row.get(12)?,
+        bsmx_bytes: row.get(13)?,
+        tags: row
+            .get::<_, Option<String>>(14)?
+            .map(|tags| tags.split(',').map(str::to_string).collect())
+            .unwrap_or_default(),
+    })
+}
@@ -0,0 +1,169 @@
+use crate::bsmx::Recipe;
+use crate::cli::Compression;
+use crate::cli::StorageLayout;
+use crate::collection::{file_name, read_recipe, recipe_files_in, sidecar_path, ARCHIVE_DIR};
+use crate::compression::{bsmx_stem, read_bsmx};
+use crate::index::Index;
+use crate::state::State;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Style layout folder for recipes with no style recorded
+pub const UNKNOWN_STYLE: &str = "unknown-style";
+
+impl StorageLayout {
+    /// Where a recipe downloaded as `filename` is kept, relative to the
+    /// recipes directory and with `/` between the parts. The style layout
+    /// files recipes that didn't parse under the unknown style.
+    pub fn path_for(self, filename: &str, contents: &[u8], recipe: Option<&Recipe>) -> String {
+        match self {
+            StorageLayout::Flat => filename.to_string(),
+            StorageLayout::Cas => {
+                let hash = format!("{:x}", Sha256::digest(contents));
+                format!("{}/{}.bsmx", &hash[..2], hash)
+            }
+            StorageLayout::Style => format!(
+                "{}/{}",
+                style_slug(recipe.map_or("", |r| &r.style.name)),
+                filename
+            ),
+        }
+    }
+
+    /// Glob pattern for the layout's recipe files, relative to the recipes
+    /// directory. It takes in compressed files, and anything else that
+    /// starts the same, which [`is_bsmx`] sorts out.
+    pub fn pattern(self) -> &'static str {
+        match self {
+            StorageLayout::Flat => "*.bsmx*",
+            StorageLayout::Cas | StorageLayout::Style => "*/*.bsmx*",
+        }
+    }
+}
+
+/// A style name as a folder name: lowercase letters and digits, with one
+/// hyphen for each run of anything else.
+pub fn style_slug(style: &str) -> String {
+    let mut slug = String::new();
+    for c in style.chars() {
+        if c.is_alphanumeric() {
+            slug.extend(c.to_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    match slug.trim_end_matches('-') {
+        "" => UNKNOWN_STYLE.to_string(),
+        // Scans skip the archive folder, which would hide the style
+        ARCHIVE_DIR => format!("{}-style", ARCHIVE_DIR),
+        slug => slug.to_string(),
+    }
+}
+
+/// What moving a collection into another layout did.
+#[derive(Debug, Default)]
+pub struct Relayout {
+    pub moved: usize,
+    /// Files that were already where the layout keeps them
+    pub in_place: usize,
+    /// Files left where they were, and why
+    pub skipped: Vec<(PathBuf, String)>,
+}
+
+/// Move every recipe under `dir` to where `layout` keeps it, along with its
+/// sidecar and index entry, and record `layout` as the collection's.
+///
+/// Files are picked up wherever any layout would keep them, so an
+/// interrupted migration can simply be run again. Nothing is overwritten: a
+/// file whose new name is already taken stays put. Files leaving the CAS
+/// layout are named after their site ID when the index knows it, since
+/// their original names weren't kept.
+pub fn relayout(
+    dir: &Path,
+    index: &Index,
+    layout: StorageLayout,
+) -> Result<Relayout, Box<dyn Error>> {
+    let mut paths = recipe_files_in(dir, StorageLayout::Flat)?;
+    paths.extend(recipe_files_in(dir, StorageLayout::Cas)?);
+
+    let mut result = Relayout::default();
+    let mut left = BTreeSet::new();
+    let tx = index.transaction()?;
+    for path in paths {
+        let old_name = index.name_of(&path);
+        // Hashed and named as the BSMX, keeping the file's compression
+        let contents = read_bsmx(&path)?;
+        let compression = Compression::of(&path);
+        let recipe = match layout {
+            StorageLayout::Style => read_recipe(&path).ok(),
+            _ => None,
+        };
+        let filename = match index.get(&old_name)? {
+            Some(entry) if is_content_hash(&path) => match (entry.recipe_id, entry.sub_id) {
+                (Some(id), Some(n)) => format!("{}-{}.bsmx", id, n),
+                (Some(id), None) => format!("{}.bsmx", id),
+                (None, _) => uncompressed_name(&path),
+            },
+            _ => uncompressed_name(&path),
+        };
+        let new_name = format!(
+            "{}{}",
+            layout.path_for(&filename, &contents, recipe.as_ref()),
+            compression.extension()
+        );
+        if new_name == old_name {
+            result.in_place += 1;
+            continue;
+        }
+
+        let target = dir.join(&new_name);
+        if target.exists() {
+            result
+                .skipped
+                .push((path, format!("{} already exists", new_name)));
+            continue;
+        }
+        let moved = target
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::rename(&path, &target));
+        if let Err(e) = moved {
+            result.skipped.push((path, e.to_string()));
+            continue;
+        }
+        let sidecar = sidecar_path(&path);
+        if sidecar.exists() {
+            fs::rename(&sidecar, sidecar_path(&target))?;
+        }
+        index.rename(&old_name, &new_name)?;
+        if let Some(parent) = path.parent().filter(|parent| *parent != dir) {
+            left.insert(parent.to_path_buf());
+        }
+        result.moved += 1;
+    }
+    tx.commit()?;
+
+    // Only succeeds for folders the move emptied
+    for folder in left {
+        let _ = fs::remove_dir(folder);
+    }
+    let mut state = State::load(dir)?;
+    state.storage_layout = layout;
+    state.save(dir)?;
+    Ok(result)
+}
+
+pub(crate) fn uncompressed_name(path: &Path) -> String {
+    match bsmx_stem(path) {
+        Some(stem) => format!("{}.bsmx", stem),
+        None => file_name(path),
+    }
+}
+
+fn is_content_hash(path: &Path) -> bool {
+    bsmx_stem(path)
+        .is_some_and(|stem| stem.len() == 64 && stem.bytes().all(|b| b.is_ascii_hexdigit()))
+}
@@ -0,0 +1,45 @@
+pub mod archive;
+pub mod beerxml;
+pub mod brewer;
+pub mod bsmx;
+pub mod bulk;
+pub mod cli;
+pub mod collection;
+pub mod color;
+pub mod commands;
+pub mod completeness;
+pub mod compression;
+pub mod cookies;
+pub mod db;
+pub mod dns;
+pub mod download;
+pub mod fingerprint;
+pub mod fixtures;
+pub mod ids;
+pub mod index;
+pub mod layout;
+pub mod limits;
+pub mod listing;
+pub mod logging;
+pub mod login;
+pub mod metrics;
+pub mod migrate;
+pub mod mirrors;
+pub mod normalize;
+pub mod notify;
+pub mod preflight;
+pub mod probe;
+pub mod progress;
+pub mod proxy;
+pub mod remote;
+pub mod robots;
+pub mod run_report;
+pub mod sampling;
+pub mod state;
+pub mod stratify;
+pub mod styles;
+pub mod tags;
+pub mod throttle;
+pub mod user_agents;
+
+pub const RECIPES_DIR: &str = "recipes";
@@ -0,0 +1,170 @@
+use chrono::{Local, NaiveDateTime, NaiveTime};
+use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// A length of time written as hours, minutes and seconds, e.g. `5h`,
+/// `90m` or `1h30m`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RunDuration(pub Duration);
+
+impl FromStr for RunDuration {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut secs = 0u64;
+        let mut digits = String::new();
+        for c in s.trim().chars() {
+            if c.is_ascii_digit() {
+                digits.push(c);
+                continue;
+            }
+            let unit = match c {
+                'h' => 3600,
+                'm' => 60,
+                's' => 1,
+                _ => return Err(format!("unknown unit {:?} in {:?}; use h, m or s", c, s)),
+            };
+            let n: u64 = digits
+                .parse()
+                .map_err(|_| format!("expected a number before {:?} in {:?}", c, s))?;
+            secs = n
+                .checked_mul(unit)
+                .and_then(|n| secs.checked_add(n))
+                .ok_or_else(|| format!("{:?} is too long", s))?;
+            digits.clear();
+        }
+        if !digits.is_empty() {
+            return Err(format!("{:?} needs a unit, e.g. {}m", s, digits));
+        }
+        if secs == 0 {
+            return Err(format!(
+                "expected a duration such as 5h or 90m, got {:?}",
+                s
+            ));
+        }
+        Ok(RunDuration(Duration::from_secs(secs)))
+    }
+}
+
+/// A local time of day, written `HH:MM`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClockTime(pub NaiveTime);
+
+impl FromStr for ClockTime {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        NaiveTime::parse_from_str(s.trim(), "%H:%M")
+            .map(ClockTime)
+            .map_err(|_| format!("expected a time of day as HH:MM, got {:?}", s))
+    }
+}
+
+impl fmt::Display for ClockTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0.format("%H:%M"))
+    }
+}
+
+/// The first time after `now` that the clock reads `time`: later today, or
+/// tomorrow if that has already passed.
+pub fn next_occurrence(time: NaiveTime, now: NaiveDateTime) -> NaiveDateTime {
+    let today = now.date().and_time(time);
+    if today > now {
+        today
+    } else {
+        today + chrono::Duration::days(1)
+    }
+}
+
+/// When a run has to stop issuing new downloads, from --max-duration or
+/// --deadline, whichever comes first.
+#[derive(Debug, Clone, Copy)]
+pub struct TimeLimit {
+    pub stop_at: Instant,
+    /// Finish reason reported when the limit is reached
+    pub reason: &'static str,
+}
+
+impl TimeLimit {
+    /// The earlier of the two limits counted from `started`, or `None`
+    /// when neither is set.
+    pub fn new(
+        started: Instant,
+        max_duration: Option<Duration>,
+        deadline: Option<NaiveTime>,
+    ) -> Option<Self> {
+        let by_duration = max_duration.map(|d| TimeLimit {
+            stop_at: started + d,
+            reason: "max-duration-reached",
+        });
+        let by_deadline = deadline.map(|time| {
+            let now = Local::now().naive_local();
+            let left = (next_occurrence(time, now) - now)
+                .to_std()
+                .unwrap_or_default();
+            TimeLimit {
+                stop_at: Instant::now() + left,
+                reason: "deadline-reached",
+            }
+        });
+        match (by_duration, by_deadline) {
+            (Some(a), Some(b)) => Some(if b.stop_at < a.stop_at { b } else { a }),
+            (a, b) => a.or(b),
+        }
+    }
+
+    pub fn is_reached(&self) -> bool {
+        Instant::now() >= self.stop_at
+    }
+
+    /// Time left before the limit, zero once it has passed.
+    pub fn remaining(&self) -> Duration {
+        self.stop_at.saturating_duration_since(Instant::now())
+    }
+}
+
+/// Hit rate over the most recent attempts, for noticing when the IDs being
+/// tried have stopped turning up recipes.
+#[derive(Debug, Clone)]
+pub struct RollingHitRate {
+    window: usize,
+    recent: VecDeque<bool>,
+    hits: usize,
+}
+
+impl RollingHitRate {
+    /// Measure over the last `window` attempts; 0 never reports a rate.
+    pub fn new(window: usize) -> Self {
+        RollingHitRate {
+            window,
+            recent: VecDeque::with_capacity(window),
+            hits: 0,
+        }
+    }
+
+    pub fn record(&mut self, hit: bool) {
+        if self.window == 0 {
+            return;
+        }
+        if self.recent.len() == self.window && self.recent.pop_front() == Some(true) {
+            self.hits -= 1;
+        }
+        self.recent.push_back(hit);
+        if hit {
+            self.hits += 1;
+        }
+    }
+
+    /// The fraction of the window that were hits, or `None` until the
+    /// window has filled, so a run isn't judged on its first few attempts.
+    pub fn rate(&self) -> Option<f64> {
+        if self.window == 0 || self.recent.len() < self.window {
+            None
+        } else {
+            Some(self.hits as f64 / self.window as f64)
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use reqwest::Url;
+use scraper::{ElementRef, Html, Selector};
+use std::collections::HashMap;
+use std::error::Error;
+
+/// Path of the site's paginated recipe search; without a query it lists
+/// every recipe
+pub const SEARCH_PATH: &str = "/search.php";
+
+/// A recipe linked from a listing page.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListedRecipe {
+    pub id: u32,
+    /// Link text, with runs of whitespace collapsed; empty if no link to
+    /// the recipe had any
+    pub name: String,
+}
+
+/// What one search or listing page holds.
+#[derive(Debug, Default, PartialEq)]
+pub struct ListingPage {
+    /// Recipes in the order they appear, each once
+    pub recipes: Vec<ListedRecipe>,
+    /// Whether the page links to a next page
+    pub has_next: bool,
+}
+
+/// Which of the site's listings to read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Search<'a> {
+    /// Every recipe
+    All,
+    /// A free-text search
+    Query(&'a str),
+    /// The recipes filed under a style or category
+    Style(&'a str),
+}
+
+/// URL of page `page` of `search`, counting from 1.
+pub fn listing_url(base_url: &str, search: Search, page: u32) -> Result<Url, Box<dyn Error>> {
+    let page = page.to_string();
+    let mut params = vec![("page", page.as_str())];
+    match search {
+        Search::All => {}
+        Search::Query(query) => params.insert(0, ("q", query)),
+        Search::Style(style) => params.insert(0, ("style", style)),
+    }
+    Ok(Url::parse_with_params(
+        &format!("{}{}", base_url, SEARCH_PATH),
+        params,
+    )?)
+}
+
+/// Pick the recipe links and the next-page link out of a listing page.
+/// Anything that isn't recognised is ignored rather than treated as an
+/// error, since the rest of the page may still be usable. This and
+/// [`listing_url`] are all that know how the site's pages look, so a
+/// redesign only needs fixing here.
+pub fn parse_listing(html: &str) -> ListingPage {
+    let document = Html::parse_document(html);
+    let links = Selector::parse("a[href]").unwrap();
+    let mut page = ListingPage::default();
+    let mut positions: HashMap<u32, usize> = HashMap::new();
+
+    for link in document.select(&links) {
+        if is_next_link(&link) {
+            page.has_next = true;
+        }
+        let Some(id) = link.value().attr("href").and_then(recipe_id) else {
+            continue;
+        };
+        let name = link_text(&link);
+        match positions.get(&id) {
+            // A thumbnail link often comes before the one with the name
+            Some(&at) if page.recipes[at].name.is_empty() => page.recipes[at].name = name,
+            Some(_) => {}
+            None => {
+                positions.insert(id, page.recipes.len());
+                page.recipes.push(ListedRecipe { id, name });
+            }
+        }
+    }
+    page
+}
+
+/// The recipe a link points at: the `id` parameter of a recipe or download
+/// page, or the number a `/recipe/` path ends in, as in
+/// `/recipe/1234-hoppy-ipa`. Links to anything else give `None`.
+fn recipe_id(href: &str) -> Option<u32> {
+    let url = Url::parse("http://site/").ok()?.join(href).ok()?;
+    let path = url.path().to_ascii_lowercase();
+    if !path.contains("recipe") && !path.contains("download") {
+        return None;
+    }
+    if let Some((_, id)) = url.query_pairs().find(|(key, _)| key == "id") {
+        return id.parse().ok();
+    }
+    let last = url.path_segments()?.rfind(|segment| !segment.is_empty())?;
+    let digits: String = last.chars().take_while(char::is_ascii_digit).collect();
+    digits.parse().ok()
+}
+
+fn is_next_link(link: &ElementRef) -> bool {
+    let rel = link.value().attr("rel").unwrap_or_default();
+    if rel
+        .split_whitespace()
+        .any(|r| r.eq_ignore_ascii_case("next"))
+    {
+        return true;
+    }
+    let text = link_text(link).to_lowercase();
+    let text = text.trim_matches(|c: char| "»›>".contains(c) || c.is_whitespace());
+    text == "next" || text == "next page" || (text.is_empty() && !link_text(link).is_empty())
+}
+
+fn link_text(link: &ElementRef) -> String {
+    link.text()
+        .flat_map(str::split_whitespace)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
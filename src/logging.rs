@@ -0,0 +1,105 @@
+use indicatif::ProgressBar;
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, IsTerminal, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::Layer;
+
+// The bar currently on screen, hidden while a log line is written so the
+// two don't tear each other
+static ACTIVE_BAR: Mutex<Option<ProgressBar>> = Mutex::new(None);
+
+/// Set up console logging to stderr at a level picked by `-v`/`-q`, and
+/// JSON logging of everything at debug level and up to `log_file`.
+pub fn init(verbose: u8, quiet: bool, log_file: Option<&Path>) -> Result<(), Box<dyn Error>> {
+    let level = match (quiet, verbose) {
+        (true, _) => LevelFilter::ERROR,
+        (false, 0) => LevelFilter::INFO,
+        (false, 1) => LevelFilter::DEBUG,
+        (false, _) => LevelFilter::TRACE,
+    };
+    let console = tracing_subscriber::fmt::layer()
+        .with_writer(BarWriter)
+        .with_ansi(io::stderr().is_terminal())
+        .with_target(false)
+        .without_time()
+        .with_filter(crate_filter(level));
+
+    let file = match log_file {
+        Some(path) => Some(
+            tracing_subscriber::fmt::layer()
+                .json()
+                .with_current_span(true)
+                .with_span_list(false)
+                .with_writer(Mutex::new(File::create(path)?))
+                .with_filter(crate_filter(level.max(LevelFilter::DEBUG))),
+        ),
+        None => None,
+    };
+
+    tracing_subscriber::registry()
+        .with(console)
+        .with(file)
+        .try_init()?;
+    Ok(())
+}
+
+// Dependencies only get a say when something goes wrong
+fn crate_filter(level: LevelFilter) -> Targets {
+    Targets::new()
+        .with_target(env!("CARGO_CRATE_NAME"), level)
+        .with_default(LevelFilter::WARN.min(level))
+}
+
+/// Route console log lines around `bar` until it is cleared with `None`.
+pub fn set_progress_bar(bar: Option<ProgressBar>) {
+    *ACTIVE_BAR.lock().unwrap() = bar;
+}
+
+/// Write `text` to stderr as is, with the active bar suspended.
+pub fn write_above_bar(text: &[u8]) {
+    let write = || {
+        let _ = io::stderr().write_all(text);
+    };
+    match ACTIVE_BAR.lock().unwrap().as_ref() {
+        Some(bar) => bar.suspend(write),
+        None => write(),
+    }
+}
+
+struct BarWriter;
+
+impl<'a> MakeWriter<'a> for BarWriter {
+    type Writer = LineWriter;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        LineWriter(Vec::new())
+    }
+}
+
+/// Buffers one formatted event and writes it to stderr in one go when
+/// dropped, with the active bar suspended.
+struct LineWriter(Vec<u8>);
+
+impl Write for LineWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for LineWriter {
+    fn drop(&mut self) {
+        write_above_bar(&self.0);
+    }
+}
@@ -0,0 +1,185 @@
+use crate::cli::DownloadArgs;
+use crate::download::{request_user_agent, DownloadConfig};
+use reqwest::header::USER_AGENT;
+use reqwest::Client;
+use std::{env, fmt};
+
+/// Where --username is taken from when it isn't given
+pub const USERNAME_VAR: &str = "BEERSCAPE_USERNAME";
+/// Where --password is taken from when it isn't given
+pub const PASSWORD_VAR: &str = "BEERSCAPE_PASSWORD";
+/// The site's login form, under --base-url
+pub const DEFAULT_LOGIN_PATH: &str = "/login.php";
+pub const DEFAULT_USERNAME_FIELD: &str = "username";
+pub const DEFAULT_PASSWORD_FIELD: &str = "password";
+/// Times in a row a run logs in again without a batch getting through
+/// before it decides the site won't keep it logged in
+pub const MAX_LOGINS_IN_A_ROW: u32 = 3;
+
+/// A site account. The password never appears in debug output.
+#[derive(Clone)]
+pub struct Credentials {
+    pub username: String,
+    password: String,
+}
+
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Credentials")
+            .field("username", &self.username)
+            .field("password", &"***")
+            .finish()
+    }
+}
+
+impl Credentials {
+    pub fn new(username: &str, password: &str) -> Self {
+        Credentials {
+            username: username.to_string(),
+            password: password.to_string(),
+        }
+    }
+
+    /// The account from --username and --password, each falling back to
+    /// its environment variable. Neither means no login; one without the
+    /// other is an error.
+    pub fn from_args(
+        username: Option<&str>,
+        password: Option<&str>,
+    ) -> Result<Option<Self>, String> {
+        let username = username.map(str::to_string).or_else(|| var(USERNAME_VAR));
+        let password = password.map(str::to_string).or_else(|| var(PASSWORD_VAR));
+        match (username, password) {
+            (Some(username), Some(password)) => Ok(Some(Credentials { username, password })),
+            (None, None) => Ok(None),
+            (Some(_), None) => Err(format!(
+                "--username needs a password too: pass --password or set {}",
+                PASSWORD_VAR
+            )),
+            (None, Some(_)) => Err(format!(
+                "a password needs --username too, or {} set",
+                USERNAME_VAR
+            )),
+        }
+    }
+}
+
+fn var(name: &str) -> Option<String> {
+    env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+/// How a run logs in to the site, and as whom.
+#[derive(Debug, Clone)]
+pub struct Login {
+    /// The login form's address, which the credentials are posted to
+    pub url: String,
+    pub username_field: String,
+    pub password_field: String,
+    pub credentials: Credentials,
+}
+
+impl Login {
+    /// The login a run's options ask for, if any. A --login-url starting
+    /// with `/` is under --base-url.
+    pub fn from_args(args: &DownloadArgs) -> Result<Option<Self>, String> {
+        let Some(credentials) =
+            Credentials::from_args(args.username.as_deref(), args.password.as_deref())?
+        else {
+            return Ok(None);
+        };
+        let url = if args.login_url.starts_with('/') {
+            format!(
+                "{}{}",
+                args.base_url[0].trim_end_matches('/'),
+                args.login_url
+            )
+        } else {
+            args.login_url.clone()
+        };
+        Ok(Some(Login {
+            url,
+            username_field: args.username_field.clone(),
+            password_field: args.password_field.clone(),
+            credentials,
+        }))
+    }
+
+    /// Whether a response is the site asking for a login: an HTML form
+    /// with a password in it, which it sends instead of a recipe to
+    /// visitors who aren't logged in.
+    pub fn is_login_page(&self, body: &[u8]) -> bool {
+        let text = String::from_utf8_lossy(body).to_lowercase();
+        let field = self.password_field.to_lowercase();
+        text.contains("<form")
+            && (text.contains("type=\"password\"")
+                || text.contains("type='password'")
+                || text.contains("type=password")
+                || text.contains(&format!("name=\"{}\"", field))
+                || text.contains(&format!("name='{}'", field)))
+    }
+
+    /// Post the credentials to the login form, leaving the session cookie
+    /// the site answers with in the client's cookie store, then ask for
+    /// the form again to make sure the site now knows who we are.
+    /// Messages never include the credentials.
+    pub async fn log_in(&self, client: &Client, config: &DownloadConfig) -> Result<(), String> {
+        let form = [
+            (
+                self.username_field.as_str(),
+                self.credentials.username.as_str(),
+            ),
+            (
+                self.password_field.as_str(),
+                self.credentials.password.as_str(),
+            ),
+        ];
+        config.requests.pass().await;
+        let response = client
+            .post(&self.url)
+            .header(USER_AGENT, request_user_agent(config))
+            .form(&form)
+            .send()
+            .await
+            .map_err(|e| format!("could not log in at {}: {}", self.url, e.without_url()))?;
+        let status = response.status();
+        let body = response
+            .bytes()
+            .await
+            .map_err(|e| format!("could not log in at {}: {}", self.url, e.without_url()))?;
+        if !status.is_success() || self.is_login_page(&body) {
+            return Err(format!(
+                "{} turned the login down (HTTP {}); check the username and password",
+                self.url,
+                status.as_u16()
+            ));
+        }
+
+        config.requests.pass().await;
+        let check = client
+            .get(&self.url)
+            .header(USER_AGENT, request_user_agent(config))
+            .send()
+            .await
+            .map_err(|e| {
+                format!(
+                    "could not check the login at {}: {}",
+                    self.url,
+                    e.without_url()
+                )
+            })?;
+        let body = check.bytes().await.map_err(|e| {
+            format!(
+                "could not check the login at {}: {}",
+                self.url,
+                e.without_url()
+            )
+        })?;
+        if self.is_login_page(&body) {
+            return Err(format!(
+                "{} accepted the login but still asks for one; the site may not be keeping the session cookie",
+                self.url
+            ));
+        }
+        Ok(())
+    }
+}
@@ -1,199 +1,87 @@
-use glob::glob;
-use indicatif::{ProgressBar, ProgressStyle};
-use rand::Rng;
-use reqwest::Client;
-use std::collections::HashSet;
+use beer_scape::cli::{Cli, Command, DbCommand, ReportCommand};
+use beer_scape::{bsmx, commands, download, logging};
+use clap::Parser;
 use std::error::Error;
-use std::fs::{self, File};
-use std::io::Write;
-use std::path::Path;
+use std::process;
 use std::time::Duration;
 
-const TOTAL_RECIPES_TARGET: usize = 10_000;
-const MIN_RECIPE_ID: u32 = 1;
-const MAX_RECIPE_ID: u32 = 4_000_000;
-const CONCURRENT_REQUESTS: usize = 10;
-
-#[derive(Debug)]
-struct DownloadStats {
-    successful: usize,
-    failed: usize,sd
-    total_attempted: usize,
-    existing: usize,
-}
-
-
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
-    // Create recipes directory if it doesn't exist
-    fs::create_dir_all("recipes")?;
-
-    // Scan existing recipes
-    let mut existing_recipes = HashSet::new();
-    println!("Scanning existing recipes...");
-    for entry in glob("recipes/*.bsmx")? {
-        if let Ok(path) = entry {
-            if let Some(file_stem) = path.file_stem() {
-                // Store the full filename to track duplicates
-                if let Some(name) = file_stem.to_str() {
-                    existing_recipes.insert(name.to_string());
-                }
-            }
+    // Bad arguments are a fatal configuration error; clap's own status for
+    // them would read as a partial run
+    let cli = Cli::try_parse().unwrap_or_else(|e| {
+        if e.use_stderr() {
+            let _ = e.print();
+            process::exit(download::EXIT_FATAL);
         }
+        e.exit()
+    });
+    if let Err(e) = logging::init(cli.verbose, cli.quiet, cli.log_file.as_deref()) {
+        eprintln!("beerscape: fatal: {}", e);
+        process::exit(download::EXIT_FATAL);
     }
 
-    println!("Found {} existing recipes", existing_recipes.len());
-    let remaining_needed = TOTAL_RECIPES_TARGET.saturating_sub(existing_recipes.len());
-    println!("Need to download {} more recipes", remaining_needed);
-
-    if remaining_needed == 0 {
-        println!("Target already reached! No more downloads needed.");
-        return Ok(());
-    }
-
-    // Create a new HTTP client with timeout
-    let client = Client::builder().timeout(Duration::from_secs(10)).build()?;
-
-    let mut stats = DownloadStats {
-        successful: existing_recipes.len(),
-        failed: 0,
-        total_attempted: 0,
-        existing: existing_recipes.len(),
-    };
-
-    // Setup progress bar
-    let pb = ProgressBar::new(TOTAL_RECIPES_TARGET as u64);
-    pb.set_position(existing_recipes.len() as u64);
-    pb.set_style(ProgressStyle::default_bar()
-        .template("{spinner:.green} [{elapsed_precise}] [{bar:50.cyan/blue}] {pos}/{len} ({percent}%) - ETA: {eta_precise} - Success: {msg}")?
-        .progress_chars("#>-"));
-    pb.set_message(format!(
-        "{}/{} (Failed: {})",
-        stats.successful, stats.total_attempted, stats.failed
-    ));
-
-    let mut rng = rand::thread_rng();
-    let mut attempted_ids = HashSet::new();
-
-    while stats.successful < TOTAL_RECIPES_TARGET {
-        let mut current_batch = vec![];
-
-        // Generate batch of new IDs
-        while current_batch.len() < CONCURRENT_REQUESTS {
-            let id = rng.gen_range(MIN_RECIPE_ID..=MAX_RECIPE_ID);
-            if !attempted_ids.contains(&id) {
-                current_batch.push(id);
-                attempted_ids.insert(id);
-            }
+    bsmx::set_abv_formula(cli.abv_formula);
+    bsmx::set_parse_timeout(Duration::from_millis(cli.parse_timeout_ms));
+    if let Some(threads) = cli.parse_threads {
+        if let Err(e) = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads.into())
+            .build_global()
+        {
+            eprintln!("beerscape: fatal: {}", e);
+            process::exit(download::EXIT_FATAL);
         }
-
-        let mut tasks = vec![];
-
-        for id in current_batch {
-            let client = client.clone();
-            let pb = pb.clone();
-
-            tasks.push(tokio::spawn(async move {
-                match download_recipe(&client, id).await {
-                    Ok(Some(info)) => (id, true, Some(info)),
-                    Ok(None) => (id, false, None),
-                    Err(e) => {
-                        eprintln!("Error downloading recipe {}: {}", id, e);
-                        (id, false, None)
-                    }
-                }
-            }));
-        }
-
-        // Wait for all tasks in batch to complete
-        for task in tasks {
-            match task.await {
-                Ok((id, success, info)) => {
-                    if success && info.is_some() {
-                        stats.successful += 1;
-                        pb.set_position(stats.successful as u64);
-                    } else {
-                        stats.failed += 1;
-                        attempted_ids.remove(&id);
-                    }
-                    stats.total_attempted += 1;
-                    pb.set_message(format!(
-                        "{}/{} (Failed: {})",
-                        stats.successful, stats.total_attempted, stats.failed
-                    ));
-                }
-                Err(e) => {
-                    eprintln!("Task error: {}", e);
-                    stats.failed += 1;
-                }
-            }
-        }
-
-        // Small delay between chunks to avoid overwhelming the server
-        tokio::time::sleep(Duration::from_millis(100)).await;
     }
 
-    pb.finish_with_message(format!(
-        "Completed: {}/{} successful",
-        stats.successful, TOTAL_RECIPES_TARGET
-    ));
-
-    println!("\nDownload Summary:");
-    println!("----------------");
-    println!("Previously Existing: {}", stats.existing);
-    println!("Newly Downloaded: {}", stats.successful - stats.existing);
-    println!("Failed Attempts: {}", stats.failed);
-    println!("Total Attempts: {}", stats.total_attempted);
-    println!(
-        "Final Success Rate: {:.1}%",
-        ((stats.successful - stats.existing) as f64 / stats.total_attempted as f64) * 100.0
-    );
-
-    Ok(())
-}
-
-async fn download_recipe(
-    client: &Client,
-    recipe_id: u32,
-) -> Result<Option<RecipeInfo>, Box<dyn Error>> {
-    // Direct download URL
-    let url = format!("https://redacted-recipes.com/download.php?id={}", recipe_id);
-
-    let response = client
-        .get(&url)
-        .header("User-Agent", "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/605.1.15 (KHTML, like Gecko) Mobile/15E148")
-        .send()
-        .await?;
-
-    if response.status().is_success() {
-        // Get the filename from Content-Disposition header or use default
-        let filename = response
-            .headers()
-            .get("content-disposition")
-            .and_then(|h| h.to_str().ok())
-            .and_then(|s| {
-                s.split("filename=")
-                    .nth(1)
-                    .map(|f| f.trim_matches('"').to_string())
-            })
-            .unwrap_or_else(|| format!("{}.bsmx", recipe_id));
-
-        let content = response.bytes().await?;
-
-        // Check if content seems valid (contains XML or BSMX data)
-        if content.starts_with(b"<") {
-            let file_path = Path::new("recipes").join(&filename);
-            let mut file = File::create(file_path)?;
-            file.write_all(&content)?;
-
-            Ok(Some(RecipeInfo {
-                id: recipe_id,
-                filename,
-            }))
-        } else {
-            Ok(None)
+    match cli.command {
+        None => download::exit_with(download::run(&cli.download, cli.quiet).await),
+        Some(Command::Archive(args)) => commands::archive::run(&args),
+        Some(Command::Backup(args)) => commands::backup::run(&args),
+        Some(Command::Benchmark(args)) => commands::benchmark::run(&args),
+        Some(Command::Browse) => commands::browse::run(),
+        Some(Command::Cluster(args)) => commands::cluster::run(&args),
+        Some(Command::Compare(args)) => commands::compare::run(&args),
+        Some(Command::Daemon(args)) => commands::daemon::run(&args, &cli.download, cli.quiet).await,
+        Some(Command::Db { db }) => match db {
+            DbCommand::Import => commands::db::import(&cli.download),
+            DbCommand::Search(args) => commands::db::search(&cli.download, &args),
+        },
+        Some(Command::Deduplicate(args)) => commands::deduplicate::run(&args),
+        Some(Command::Discover(args)) => {
+            commands::discover::run(&args, &cli.download, cli.quiet).await
         }
-    } else {
-        Ok(None)
+        Some(Command::Export(args)) => commands::export::run(&args),
+        Some(Command::Fixtures(args)) => commands::fixtures::run(&args),
+        Some(Command::Fmt(args)) => commands::fmt::run(&args),
+        Some(Command::HealthCheck(args)) => commands::health_check::run(&args, &cli.download).await,
+        Some(Command::HopInventory(args)) => commands::hop_inventory::run(&args),
+        Some(Command::Index) => commands::index::run(),
+        Some(Command::List(args)) => commands::list::run(&args),
+        Some(Command::Merge(args)) => commands::merge::run(&args),
+        Some(Command::Migrate) => commands::migrate::run(),
+        Some(Command::MigrateLayout(args)) => commands::migrate_layout::run(&args),
+        Some(Command::Normalize(args)) => commands::normalize::run(&args),
+        Some(Command::RandomRecipe(args)) => commands::random_recipe::run(&args),
+        Some(Command::Recompress(args)) => commands::recompress::run(&args),
+        Some(Command::Compress(args)) => commands::recompress::compress(&args),
+        Some(Command::Decompress) => commands::recompress::decompress(),
+        Some(Command::Prune(args)) => commands::prune::run(&args),
+        Some(Command::Push(args)) => commands::remote::push(&args).await,
+        Some(Command::Pull(args)) => commands::remote::pull(&args).await,
+        Some(Command::Report { report }) => match report {
+            ReportCommand::Authors => commands::report::authors(),
+            ReportCommand::Color => commands::report::color(),
+            ReportCommand::Completeness => commands::report::completeness(),
+            ReportCommand::Notes => commands::report::notes(),
+            ReportCommand::Versions => commands::report::versions(),
+            ReportCommand::StyleFit(args) => commands::report::style_fit(&args),
+            ReportCommand::Yeast(args) => commands::report::yeast(&args),
+        },
+        Some(Command::Reports(args)) => commands::reports::run(&args),
+        Some(Command::Serve(args)) => commands::serve::run(&args).await,
+        Some(Command::Split(args)) => commands::split::run(&args),
+        Some(Command::Sync(args)) => commands::sync::run(&args),
+        Some(Command::Tag { tag }) => commands::tag::run(&tag),
+        Some(Command::TopRecipes(args)) => commands::top_recipes::run(&args),
     }
 }
@@ -0,0 +1,417 @@
+use crate::download::DownloadStats;
+use crate::progress::thousands;
+use crate::throttle::RequestGate;
+use axum::http::header;
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::Router;
+use prometheus::core::Collector;
+use prometheus::{
+    Encoder, Gauge, Histogram, HistogramOpts, IntCounter, IntCounterVec, IntGauge, Opts, Registry,
+    TextEncoder,
+};
+use std::collections::VecDeque;
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Outcomes a download is counted under: `saved`, or why it failed.
+pub const OUTCOMES: [&str; 16] = [
+    "saved",
+    "not-found",
+    "http-error",
+    "invalid-content",
+    "incomplete",
+    "style-full",
+    "parse-timeout",
+    "connect-timeout",
+    "read-timeout",
+    "timeout",
+    "network",
+    "proxy",
+    "redirect",
+    "io",
+    "session-expired",
+    "other",
+];
+
+/// Upper bounds of the request latency histogram buckets, in seconds
+const LATENCY_BUCKETS: [f64; 10] = [0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0];
+
+/// Upper bounds of the recipe size histogram buckets, in bytes
+const SIZE_BUCKETS: [f64; 8] = [
+    1_000.0,
+    4_000.0,
+    16_000.0,
+    64_000.0,
+    256_000.0,
+    1_000_000.0,
+    4_000_000.0,
+    16_000_000.0,
+];
+
+/// Attempts the recent hit rate is measured over
+const RECENT_WINDOW: usize = 1_000;
+
+/// What a run still has to do, for estimating when it will finish.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Remaining {
+    /// Recipes still to save before the target is reached
+    Saves(usize),
+    /// Listed IDs still to try
+    Ids(usize),
+}
+
+/// A download run's Prometheus metrics, and the recent attempts behind the
+/// readable snapshot. The run's totals are read from the [`DownloadStats`]
+/// it shares with the download loop, the rest is recorded as it happens.
+pub struct Metrics {
+    registry: Registry,
+    downloads: IntCounterVec,
+    recipes_downloaded: IntCounter,
+    recipes_failed: IntCounter,
+    bytes: IntCounter,
+    retries: IntCounter,
+    throttles: IntCounter,
+    existing: IntGauge,
+    in_flight: IntGauge,
+    queue_depth: IntGauge,
+    success_rate: Gauge,
+    request_rate: Gauge,
+    request_duration: Histogram,
+    response_bytes: Histogram,
+    stats: Arc<Mutex<DownloadStats>>,
+    /// When each recent attempt finished and whether it saved a recipe
+    recent: Mutex<VecDeque<(Instant, bool)>>,
+    remaining: Mutex<Option<Remaining>>,
+    requests: Mutex<Option<Arc<RequestGate>>>,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Metrics::new(Arc::default())
+    }
+}
+
+impl Metrics {
+    /// Metrics for a run whose download loop keeps `stats` up to date.
+    pub fn new(stats: Arc<Mutex<DownloadStats>>) -> Self {
+        let registry = Registry::new();
+        let downloads = IntCounterVec::new(
+            Opts::new(
+                "beerscape_downloads_total",
+                "Downloads attempted, by outcome.",
+            ),
+            &["outcome"],
+        )
+        .unwrap();
+        // Every outcome is listed from the start, at zero
+        for outcome in OUTCOMES {
+            downloads.with_label_values(&[outcome]);
+        }
+        registry.register(Box::new(downloads.clone())).unwrap();
+        let histogram = |name: &str, help: &str, buckets: &[f64]| {
+            let histogram =
+                Histogram::with_opts(HistogramOpts::new(name, help).buckets(buckets.to_vec()))
+                    .unwrap();
+            registry.register(Box::new(histogram.clone())).unwrap();
+            histogram
+        };
+        let request_duration = histogram(
+            "beerscape_request_duration_seconds",
+            "Time to fetch and save a recipe.",
+            &LATENCY_BUCKETS,
+        );
+        let response_bytes = histogram(
+            "beerscape_response_bytes",
+            "Size of each saved recipe.",
+            &SIZE_BUCKETS,
+        );
+        Metrics {
+            recipes_downloaded: register(
+                &registry,
+                IntCounter::new(
+                    "beerscape_recipes_downloaded_total",
+                    "Recipes saved by this run.",
+                ),
+            ),
+            recipes_failed: register(
+                &registry,
+                IntCounter::new(
+                    "beerscape_recipes_failed_total",
+                    "Downloads that failed for good.",
+                ),
+            ),
+            bytes: register(
+                &registry,
+                IntCounter::new(
+                    "beerscape_downloaded_bytes_total",
+                    "Bytes of recipes downloaded.",
+                ),
+            ),
+            retries: register(
+                &registry,
+                IntCounter::new(
+                    "beerscape_retries_total",
+                    "Downloads retried after the login session was renewed.",
+                ),
+            ),
+            throttles: register(
+                &registry,
+                IntCounter::new(
+                    "beerscape_throttle_events_total",
+                    "Times the run paused for the daily quota or an expired session.",
+                ),
+            ),
+            existing: register(
+                &registry,
+                IntGauge::new(
+                    "beerscape_recipes_existing",
+                    "Recipes already on disk when the run started.",
+                ),
+            ),
+            in_flight: register(
+                &registry,
+                IntGauge::new(
+                    "beerscape_active_requests",
+                    "Requests currently being made.",
+                ),
+            ),
+            queue_depth: register(
+                &registry,
+                IntGauge::new(
+                    "beerscape_queue_depth",
+                    "Downloads in the current batch not yet finished.",
+                ),
+            ),
+            success_rate: register(
+                &registry,
+                Gauge::new(
+                    "beerscape_success_rate",
+                    "Fraction of this run's attempts that saved a recipe.",
+                ),
+            ),
+            // Registered by watch_requests, as there's no rate to give
+            // until then
+            request_rate: Gauge::new(
+                "beerscape_request_rate",
+                "Requests sent per second over the last minute.",
+            )
+            .unwrap(),
+            registry,
+            downloads,
+            request_duration,
+            response_bytes,
+            stats,
+            recent: Mutex::default(),
+            remaining: Mutex::default(),
+            requests: Mutex::default(),
+        }
+    }
+
+    /// Count a finished download under `outcome`, one of [`OUTCOMES`].
+    pub fn record_download(&self, outcome: &str, latency: Duration) {
+        let outcome = OUTCOMES
+            .iter()
+            .find(|o| **o == outcome)
+            .unwrap_or(&OUTCOMES[OUTCOMES.len() - 1]);
+        self.downloads.with_label_values(&[outcome]).inc();
+        if *outcome != "session-expired" {
+            let mut recent = self.recent.lock().unwrap();
+            if recent.len() == RECENT_WINDOW {
+                recent.pop_front();
+            }
+            recent.push_back((Instant::now(), *outcome == "saved"));
+        }
+        self.request_duration.observe(latency.as_secs_f64());
+    }
+
+    /// Count the size of one saved recipe.
+    pub fn add_bytes(&self, bytes: usize) {
+        self.bytes.inc_by(bytes as u64);
+        self.response_bytes.observe(bytes as f64);
+    }
+
+    pub fn record_retry(&self) {
+        self.retries.inc();
+    }
+
+    pub fn record_throttle(&self) {
+        self.throttles.inc();
+    }
+
+    pub fn request_started(&self) {
+        self.in_flight.inc();
+    }
+
+    pub fn request_finished(&self) {
+        self.in_flight.dec();
+    }
+
+    /// Downloads handed out in the current batch whose results haven't been
+    /// collected yet.
+    pub fn set_queue_depth(&self, depth: usize) {
+        self.queue_depth.set(depth as i64);
+    }
+
+    pub fn set_remaining(&self, remaining: Remaining) {
+        *self.remaining.lock().unwrap() = Some(remaining);
+    }
+
+    /// Report the rate `requests` lets requests through.
+    pub fn watch_requests(&self, requests: Arc<RequestGate>) {
+        let mut watched = self.requests.lock().unwrap();
+        if watched.is_none() {
+            let _ = self.registry.register(Box::new(self.request_rate.clone()));
+        }
+        *watched = Some(requests);
+    }
+
+    fn request_rate(&self) -> Option<f64> {
+        self.requests
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|gate| gate.recent_rate())
+    }
+
+    /// A readable report of the run so far, with the hit rate over the
+    /// last attempts and when the run should finish at that rate.
+    pub fn snapshot(&self) -> String {
+        let counts: Vec<u64> = OUTCOMES
+            .iter()
+            .map(|outcome| self.downloads.with_label_values(&[outcome]).get())
+            .collect();
+        let attempted: u64 = OUTCOMES
+            .iter()
+            .zip(&counts)
+            .filter(|(outcome, _)| **outcome != "session-expired")
+            .map(|(_, count)| count)
+            .sum();
+
+        let mut out = String::new();
+        let _ = writeln!(out, "Run Statistics:");
+        let _ = writeln!(out, "---------------");
+        let _ = writeln!(out, "IDs attempted: {}", thousands(attempted as usize));
+        for (outcome, count) in OUTCOMES.iter().zip(&counts) {
+            if *count > 0 {
+                let _ = writeln!(out, "  {:<16} {:>10}", outcome, thousands(*count as usize));
+            }
+        }
+        let _ = writeln!(
+            out,
+            "In flight: {} (queue depth {})",
+            self.in_flight.get(),
+            self.queue_depth.get()
+        );
+        let _ = writeln!(
+            out,
+            "Downloaded: {:.1} MB",
+            self.bytes.get() as f64 / 1_000_000.0
+        );
+        if let Some(rate) = self.request_rate() {
+            let _ = writeln!(out, "Request rate (last minute): {:.1}/s", rate);
+        }
+
+        let (window, hits, span) = {
+            let recent = self.recent.lock().unwrap();
+            let hits = recent.iter().filter(|(_, saved)| *saved).count();
+            let span = match recent.front() {
+                Some((first, _)) => first.elapsed().as_secs_f64(),
+                None => 0.0,
+            };
+            (recent.len(), hits, span)
+        };
+        if window == 0 {
+            let _ = writeln!(out, "Hit rate: no attempts yet");
+        } else {
+            let _ = writeln!(
+                out,
+                "Hit rate (last {}): {:.1}%",
+                thousands(window),
+                hits as f64 / window as f64 * 100.0
+            );
+        }
+
+        // Listed IDs finish at the attempt rate, a target at the save rate
+        let eta = match *self.remaining.lock().unwrap() {
+            Some(Remaining::Saves(left)) if hits > 0 && span > 0.0 => {
+                Some(left as f64 * span / hits as f64)
+            }
+            Some(Remaining::Ids(left)) if window > 0 && span > 0.0 => {
+                Some(left as f64 * span / window as f64)
+            }
+            _ => None,
+        };
+        match eta {
+            Some(secs) => {
+                let secs = secs.round() as u64;
+                let _ = writeln!(
+                    out,
+                    "Estimated time to finish: {}h {:02}m",
+                    secs / 3600,
+                    secs % 3600 / 60
+                );
+            }
+            None => {
+                let _ = writeln!(out, "Estimated time to finish: unknown");
+            }
+        }
+        out
+    }
+
+    /// Everything in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        {
+            let stats = self.stats.lock().unwrap();
+            // The loop's tallies only grow, so the counters catch up to them
+            catch_up(&self.recipes_downloaded, stats.downloaded);
+            catch_up(&self.recipes_failed, stats.failed);
+            self.existing.set(stats.existing as i64);
+            self.success_rate.set(stats.success_rate().unwrap_or(0.0));
+        }
+        if let Some(rate) = self.request_rate() {
+            self.request_rate.set(rate);
+        }
+        TextEncoder::new()
+            .encode_to_string(&self.registry.gather())
+            .unwrap_or_default()
+    }
+}
+
+fn register<M: Collector + Clone + 'static>(
+    registry: &Registry,
+    metric: prometheus::Result<M>,
+) -> M {
+    let metric = metric.unwrap();
+    registry.register(Box::new(metric.clone())).unwrap();
+    metric
+}
+
+fn catch_up(counter: &IntCounter, total: usize) {
+    counter.inc_by((total as u64).saturating_sub(counter.get()));
+}
+
+/// Serve `metrics` at `/metrics`, and the readable snapshot at `/stats`,
+/// on `addr` in the background.
+pub async fn serve(addr: SocketAddr, metrics: Arc<Metrics>) -> std::io::Result<()> {
+    let stats = metrics.clone();
+    let app = Router::new()
+        .route(
+            "/metrics",
+            get(move || async move {
+                (
+                    [(header::CONTENT_TYPE, TextEncoder::new().format_type())],
+                    metrics.render(),
+                )
+                    .into_response()
+            }),
+        )
+        .route("/stats", get(move || async move { stats.snapshot() }));
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    tokio::spawn(async move {
+        let _ = axum::serve(listener, app).await;
+    });
+    Ok(())
+}
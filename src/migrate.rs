@@ -0,0 +1,143 @@
+use crate::collection::write_atomic;
+use crate::state::{STATE_FILE, STATE_SCHEMA_VERSION};
+use serde_json::Value;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// One step in bringing a state file up to date.
+pub trait Migration {
+    /// Name of the file it applies to, inside the recipes directory
+    fn file(&self) -> &'static str;
+    // Named for what it is rather than as a conversion
+    #[allow(clippy::wrong_self_convention)]
+    fn from_version(&self) -> u32;
+    fn to_version(&self) -> u32;
+    /// Rewrite the file at `path` from `from_version` to `to_version`.
+    fn apply(&self, path: &Path) -> Result<(), Box<dyn Error>>;
+}
+
+/// A state file's version before and after an upgrade.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Upgrade {
+    pub file: &'static str,
+    pub from: u32,
+    pub to: u32,
+}
+
+/// Every known migration, in the order they apply.
+pub fn migrations() -> Vec<Box<dyn Migration>> {
+    vec![Box::new(VersionState)]
+}
+
+/// The version each state file should be at, by file name.
+pub const CURRENT_VERSIONS: [(&str, u32); 1] = [(STATE_FILE, STATE_SCHEMA_VERSION)];
+
+/// The `schema_version` recorded in a state file, 0 if it predates
+/// versioning, or `None` if there is no such file.
+pub fn schema_version(path: &Path) -> Result<Option<u32>, Box<dyn Error>> {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let value: Value = serde_json::from_slice(&bytes)?;
+    let version = value
+        .get("schema_version")
+        .and_then(Value::as_u64)
+        .unwrap_or(0);
+    Ok(Some(version as u32))
+}
+
+/// State files in `dir` older than this build writes, with the upgrade each
+/// needs.
+pub fn outdated(dir: &Path) -> Result<Vec<Upgrade>, Box<dyn Error>> {
+    let mut outdated = Vec::new();
+    for (file, current) in CURRENT_VERSIONS {
+        if let Some(version) = schema_version(&dir.join(file))? {
+            if version < current {
+                outdated.push(Upgrade {
+                    file,
+                    from: version,
+                    to: current,
+                });
+            }
+        }
+    }
+    Ok(outdated)
+}
+
+/// Upgrade every state file in `dir` to the current version in place,
+/// returning each step applied.
+pub fn migrate(dir: &Path) -> Result<Vec<Upgrade>, Box<dyn Error>> {
+    let migrations = migrations();
+    let mut applied = Vec::new();
+    for (file, current) in CURRENT_VERSIONS {
+        let path = dir.join(file);
+        let Some(mut version) = schema_version(&path)? else {
+            continue;
+        };
+        if version > current {
+            return Err(format!(
+                "{} is at version {}, newer than this beerscape understands ({}); upgrade beerscape",
+                path.display(),
+                version,
+                current
+            )
+            .into());
+        }
+        while version < current {
+            let step = migrations
+                .iter()
+                .find(|m| m.file() == file && m.from_version() == version)
+                .ok_or_else(|| format!("no migration for {} from version {}", file, version))?;
+            step.apply(&path)?;
+            applied.push(Upgrade {
+                file,
+                from: version,
+                to: step.to_version(),
+            });
+            version = step.to_version();
+        }
+    }
+    Ok(applied)
+}
+
+/// Rewrite a JSON file with `change` applied and `schema_version` set.
+fn rewrite_json(
+    path: &Path,
+    version: u32,
+    change: impl FnOnce(&mut serde_json::Map<String, Value>),
+) -> Result<(), Box<dyn Error>> {
+    let mut value: Value = serde_json::from_slice(&fs::read(path)?)?;
+    let object = value
+        .as_object_mut()
+        .ok_or_else(|| format!("{} is not a JSON object", path.display()))?;
+    change(object);
+    object.insert("schema_version".to_string(), version.into());
+    write_atomic(path, &serde_json::to_vec_pretty(&value)?)?;
+    Ok(())
+}
+
+/// State files before version 1 had no `schema_version`; the layout is
+/// otherwise unchanged.
+struct VersionState;
+
+impl Migration for VersionState {
+    fn file(&self) -> &'static str {
+        STATE_FILE
+    }
+
+    fn from_version(&self) -> u32 {
+        0
+    }
+
+    fn to_version(&self) -> u32 {
+        1
+    }
+
+    fn apply(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        rewrite_json(path, self.to_version(), |_| {})
+    }
+}
@@ -0,0 +1,194 @@
+use crate::throttle::RequestGate;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How long it takes a mirror's error rate to forget half of what it has
+/// seen, so one that was failing is tried again once it's had time to
+/// recover.
+pub const ERROR_RATE_HALF_LIFE: Duration = Duration::from_secs(60);
+
+/// How much worse a mirror's error rate may be than the best one's before
+/// requests go to the better mirror instead.
+pub const ERROR_RATE_MARGIN: f64 = 0.1;
+
+/// The mirrors of the site, from repeated --base-url, in the order given.
+/// Each recipe request goes to the healthiest of them, preferring the
+/// earlier ones when they're about as healthy.
+#[derive(Debug)]
+pub struct Mirrors {
+    members: Vec<Mirror>,
+}
+
+/// One of the --base-url mirrors and how it's been doing.
+#[derive(Debug)]
+pub struct Mirror {
+    pub base_url: String,
+    /// --max-rps, for this mirror alone
+    pub requests: Arc<RequestGate>,
+    health: Mutex<ErrorRate>,
+    attempts: AtomicUsize,
+    saved: AtomicUsize,
+    failed: AtomicUsize,
+    /// Of `failed`, those put down to the mirror rather than the ID
+    errors: AtomicUsize,
+}
+
+/// Requests and the errors among them, each weighed down by half every
+/// [`ERROR_RATE_HALF_LIFE`] since.
+#[derive(Debug)]
+struct ErrorRate {
+    requests: f64,
+    errors: f64,
+    updated: Instant,
+}
+
+impl ErrorRate {
+    fn decay(&mut self, now: Instant) {
+        let halvings =
+            now.duration_since(self.updated).as_secs_f64() / ERROR_RATE_HALF_LIFE.as_secs_f64();
+        let factor = 0.5f64.powf(halvings);
+        self.requests *= factor;
+        self.errors *= factor;
+        self.updated = now;
+    }
+
+    /// The share of recent requests that failed. One imaginary success
+    /// keeps a mirror with little history from looking all good or all
+    /// bad, and lets the rate of one left alone fall back toward zero.
+    fn rate(&self) -> f64 {
+        self.errors / (self.requests + 1.0)
+    }
+}
+
+/// How one mirror did over a run, for the run report.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MirrorStats {
+    pub base_url: String,
+    pub attempts: usize,
+    pub saved: usize,
+    pub failed: usize,
+    /// Failures put down to the mirror: server errors, timeouts and
+    /// connections that failed
+    pub errors: usize,
+    /// The decayed error rate at the end of the run
+    pub error_rate: f64,
+}
+
+impl Mirrors {
+    /// Mirrors at `base_urls`, the first of which uses `primary`, the
+    /// gate the rest of the run's requests to it pass through; the others
+    /// get gates of their own, capped at `max_rps` too.
+    pub fn new(base_urls: &[String], primary: Arc<RequestGate>, max_rps: Option<f64>) -> Self {
+        let now = Instant::now();
+        let members = base_urls
+            .iter()
+            .enumerate()
+            .map(|(n, base_url)| Mirror {
+                base_url: base_url.clone(),
+                requests: if n == 0 {
+                    primary.clone()
+                } else {
+                    Arc::new(RequestGate::new(max_rps))
+                },
+                health: Mutex::new(ErrorRate {
+                    requests: 0.0,
+                    errors: 0.0,
+                    updated: now,
+                }),
+                attempts: AtomicUsize::new(0),
+                saved: AtomicUsize::new(0),
+                failed: AtomicUsize::new(0),
+                errors: AtomicUsize::new(0),
+            })
+            .collect();
+        Mirrors { members }
+    }
+
+    /// Every mirror, in the order to try them for the next request: the
+    /// first one given whose error rate is within [`ERROR_RATE_MARGIN`] of
+    /// the best, then the rest from healthiest to least.
+    pub fn in_order(&self) -> Vec<&Mirror> {
+        let now = Instant::now();
+        let mut rated: Vec<(f64, &Mirror)> = self
+            .members
+            .iter()
+            .map(|mirror| (mirror.error_rate(now), mirror))
+            .collect();
+        let best = rated
+            .iter()
+            .map(|&(rate, _)| rate)
+            .fold(f64::INFINITY, f64::min);
+        let first = rated
+            .iter()
+            .position(|&(rate, _)| rate <= best + ERROR_RATE_MARGIN)
+            .unwrap_or(0);
+        let first = rated.remove(first);
+        // Stable, so equally healthy mirrors keep their order
+        rated.sort_by(|a, b| a.0.total_cmp(&b.0));
+        std::iter::once(first)
+            .chain(rated)
+            .map(|(_, mirror)| mirror)
+            .collect()
+    }
+
+    /// How each mirror did, for the run report.
+    pub fn stats(&self) -> Vec<MirrorStats> {
+        let now = Instant::now();
+        self.members
+            .iter()
+            .map(|mirror| MirrorStats {
+                base_url: mirror.base_url.clone(),
+                attempts: mirror.attempts.load(Ordering::Relaxed),
+                saved: mirror.saved.load(Ordering::Relaxed),
+                failed: mirror.failed.load(Ordering::Relaxed),
+                errors: mirror.errors.load(Ordering::Relaxed),
+                error_rate: mirror.error_rate(now),
+            })
+            .collect()
+    }
+
+    /// How each mirror did, for the end of a run.
+    pub fn summary(&self) -> String {
+        let mut out = String::from("Mirrors:\n--------\n");
+        for mirror in self.stats() {
+            out.push_str(&format!(
+                "  {:<36} {:>6} saved {:>6} failed {:>6} errors, error rate {:.1}%\n",
+                mirror.base_url,
+                mirror.saved,
+                mirror.failed,
+                mirror.errors,
+                mirror.error_rate * 100.0
+            ));
+        }
+        out.pop();
+        out
+    }
+}
+
+impl Mirror {
+    fn error_rate(&self, now: Instant) -> f64 {
+        let mut health = self.health.lock().unwrap();
+        health.decay(now);
+        health.rate()
+    }
+
+    /// Count an attempt at an ID sent to the mirror: whether it saved a
+    /// recipe, and whether it failed in a way that's the mirror's fault
+    /// rather than an answer about the ID.
+    pub fn record(&self, saved: bool, error: bool) {
+        self.attempts.fetch_add(1, Ordering::Relaxed);
+        let counter = if saved { &self.saved } else { &self.failed };
+        counter.fetch_add(1, Ordering::Relaxed);
+        if error {
+            self.errors.fetch_add(1, Ordering::Relaxed);
+        }
+        let mut health = self.health.lock().unwrap();
+        health.decay(Instant::now());
+        health.requests += 1.0;
+        if error {
+            health.errors += 1.0;
+        }
+    }
+}
@@ -0,0 +1,194 @@
+use crate::bsmx::{Element, Recipe};
+use crate::cli::Units;
+use serde_json::Value;
+
+/// Liters in a US fluid ounce, the unit BeerSmith records volumes in
+pub const FL_OZ_TO_L: f64 = 0.029_573_53;
+/// Liters in a US gallon
+pub const GAL_TO_L: f64 = 3.785_411_784;
+/// Grams in an avoirdupois ounce, the unit BeerSmith records weights in
+pub const OZ_TO_G: f64 = 28.349_52;
+/// Grams in a pound
+pub const LB_TO_G: f64 = 453.592_37;
+
+/// A unit a recipe file can give a quantity in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unit {
+    Gram,
+    Kilogram,
+    Ounce,
+    Pound,
+    Liter,
+    FluidOunce,
+    Gallon,
+    Celsius,
+    Fahrenheit,
+}
+
+impl Unit {
+    /// A `<DISPLAY_UNIT>` value: a unit's name or its usual abbreviation,
+    /// ignoring case.
+    pub fn from_display(text: &str) -> Option<Self> {
+        let unit = match text.trim().to_lowercase().trim_end_matches('.') {
+            "g" | "gram" | "grams" => Unit::Gram,
+            "kg" | "kilogram" | "kilograms" => Unit::Kilogram,
+            "oz" | "ounce" | "ounces" => Unit::Ounce,
+            "lb" | "lbs" | "pound" | "pounds" => Unit::Pound,
+            "l" | "liter" | "liters" | "litre" | "litres" => Unit::Liter,
+            "fl oz" | "floz" | "fluid ounce" | "fluid ounces" => Unit::FluidOunce,
+            "gal" | "gallon" | "gallons" => Unit::Gallon,
+            "c" | "°c" | "celsius" => Unit::Celsius,
+            "f" | "°f" | "fahrenheit" => Unit::Fahrenheit,
+            _ => return None,
+        };
+        Some(unit)
+    }
+
+    fn is_weight(self) -> bool {
+        matches!(
+            self,
+            Unit::Gram | Unit::Kilogram | Unit::Ounce | Unit::Pound
+        )
+    }
+
+    /// `value` in this unit as grams, liters or degrees Celsius, whichever
+    /// it measures. Zero temperatures mean not recorded and stay zero.
+    pub fn to_metric(self, value: f64) -> f64 {
+        match self {
+            Unit::Gram | Unit::Liter | Unit::Celsius => value,
+            Unit::Kilogram => value * 1000.0,
+            Unit::Ounce => value * OZ_TO_G,
+            Unit::Pound => value * LB_TO_G,
+            Unit::FluidOunce => value * FL_OZ_TO_L,
+            Unit::Gallon => value * GAL_TO_L,
+            Unit::Fahrenheit if value == 0.0 => 0.0,
+            Unit::Fahrenheit => (value - 32.0) * 5.0 / 9.0,
+        }
+    }
+}
+
+/// The units a parsed recipe's quantities are in until [`normalize`]
+/// converts them, read off the file's display units.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordedUnits {
+    pub batch: Unit,
+    pub temperature: Unit,
+    /// One per fermentable, in order
+    pub fermentables: Vec<Unit>,
+    /// One per hop, in order
+    pub hops: Vec<Unit>,
+}
+
+impl RecordedUnits {
+    /// BeerSmith records everything in ounces, fluid ounces and degrees
+    /// Fahrenheit. A `<DISPLAY_UNIT>` of `metric` under the recipe means
+    /// kilograms of fermentables, grams of hops, liters and degrees Celsius
+    /// instead, and an ingredient's own `<F_G_DISPLAY_UNIT>` or
+    /// `<F_H_DISPLAY_UNIT>` naming a weight unit wins over either.
+    pub fn of(recipe: &Element) -> Self {
+        let metric = recipe
+            .child_text("DISPLAY_UNIT")
+            .is_some_and(|system| matches!(system.trim().to_lowercase().as_str(), "metric" | "si"));
+        let (batch, temperature, grain, hop) = if metric {
+            (Unit::Liter, Unit::Celsius, Unit::Kilogram, Unit::Gram)
+        } else {
+            (Unit::FluidOunce, Unit::Fahrenheit, Unit::Ounce, Unit::Ounce)
+        };
+        RecordedUnits {
+            batch,
+            temperature,
+            fermentables: ingredient_units(recipe, "Grain", "F_G_DISPLAY_UNIT", grain),
+            hops: ingredient_units(recipe, "Hops", "F_H_DISPLAY_UNIT", hop),
+        }
+    }
+}
+
+fn ingredient_units(recipe: &Element, name: &str, field: &str, default: Unit) -> Vec<Unit> {
+    let mut elements = Vec::new();
+    recipe.find_all(name, &mut elements);
+    elements
+        .iter()
+        .map(|element| {
+            element
+                .child_text(field)
+                .and_then(Unit::from_display)
+                .filter(|unit| unit.is_weight())
+                .unwrap_or(default)
+        })
+        .collect()
+}
+
+/// Convert a recipe's quantities from the units its file recorded them in
+/// to liters, kilograms (grams for hops) and degrees Celsius. A recipe
+/// that's already metric comes back as it was.
+pub fn normalize(mut recipe: Recipe) -> Recipe {
+    let Some(units) = recipe.recorded.take() else {
+        return recipe;
+    };
+    recipe.batch_size_l = units.batch.to_metric(recipe.batch_size_l);
+    for (fermentable, unit) in recipe.fermentables.iter_mut().zip(&units.fermentables) {
+        fermentable.amount_kg = unit.to_metric(fermentable.amount_kg) / 1000.0;
+    }
+    for (hop, unit) in recipe.hops.iter_mut().zip(&units.hops) {
+        hop.amount_g = unit.to_metric(hop.amount_g);
+    }
+    for yeast in &mut recipe.yeasts {
+        yeast.min_temp_c = units.temperature.to_metric(yeast.min_temp_c);
+        yeast.max_temp_c = units.temperature.to_metric(yeast.max_temp_c);
+    }
+    for step in &mut recipe.mash_steps {
+        step.temp_c = units.temperature.to_metric(step.temp_c);
+    }
+    recipe
+}
+
+type Conversion = fn(f64) -> f64;
+
+/// Metric fields of a serialized recipe, what they're called in imperial
+/// units and how to get there.
+const IMPERIAL_FIELDS: [(&str, &str, Conversion); 6] = [
+    ("batch_size_l", "batch_size_gal", |l| l / GAL_TO_L),
+    ("amount_kg", "amount_lb", |kg| kg * 1000.0 / LB_TO_G),
+    ("amount_g", "amount_oz", |g| g / OZ_TO_G),
+    ("min_temp_c", "min_temp_f", celsius_to_fahrenheit),
+    ("max_temp_c", "max_temp_f", celsius_to_fahrenheit),
+    ("temp_c", "temp_f", celsius_to_fahrenheit),
+];
+
+// Zero means not recorded, as it does for Fahrenheit
+fn celsius_to_fahrenheit(c: f64) -> f64 {
+    if c == 0.0 {
+        0.0
+    } else {
+        c * 9.0 / 5.0 + 32.0
+    }
+}
+
+/// Rewrite a serialized recipe, or anything holding recipes, in `units`:
+/// in imperial, gallons, pounds of fermentables, ounces of hops and
+/// degrees Fahrenheit, with the fields renamed to match. Recipes are kept
+/// metric everywhere else; this is only for output.
+pub fn convert_json(value: &mut Value, units: Units) {
+    if units == Units::Metric {
+        return;
+    }
+    match value {
+        Value::Object(fields) => {
+            for (metric, imperial, convert) in IMPERIAL_FIELDS {
+                if let Some(amount) = fields.remove(metric) {
+                    let converted = amount.as_f64().map_or(amount, |n| convert(n).into());
+                    fields.insert(imperial.to_string(), converted);
+                }
+            }
+            for field in fields.values_mut() {
+                convert_json(field, units);
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                convert_json(item, units);
+            }
+        }
+        _ => {}
+    }
+}
@@ -0,0 +1,40 @@
+use crate::download::DownloadStats;
+use reqwest::Client;
+use serde::Serialize;
+
+/// The JSON body `--notify-webhook` posts when a run ends.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Notification {
+    /// Recipes on disk, including those there before the run
+    pub successful: usize,
+    pub failed: usize,
+    pub total_attempted: usize,
+    pub newly_downloaded: usize,
+    pub duration_secs: u64,
+    /// New downloads per attempt, or null if nothing was attempted
+    pub success_rate: Option<f64>,
+}
+
+impl Notification {
+    pub fn new(stats: &DownloadStats, duration_secs: u64) -> Self {
+        Notification {
+            successful: stats.on_disk(),
+            failed: stats.failed,
+            total_attempted: stats.attempted,
+            newly_downloaded: stats.downloaded,
+            duration_secs,
+            success_rate: stats.success_rate(),
+        }
+    }
+}
+
+/// POST `notification` to `url`, treating any non-2xx answer as a failure.
+pub async fn send(client: &Client, url: &str, notification: &Notification) -> reqwest::Result<()> {
+    client
+        .post(url)
+        .json(notification)
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
@@ -0,0 +1,124 @@
+use crate::bsmx::parse_bsmx;
+use crate::download::{is_web_page, recipe_url, request_user_agent, DownloadConfig};
+use crate::proxy::error_chain;
+use reqwest::header::USER_AGENT;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::time::Instant;
+
+/// What a run checked before it started downloading, and what it found.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Preflight {
+    pub url: String,
+    /// The recipe probed, or none if it was the site root
+    pub id: Option<u32>,
+    /// `ok`, or what went wrong: `dns`, `tls`, `connect`, `timeout`,
+    /// `network`, `http`, `html` or `not-a-recipe`
+    pub outcome: String,
+    /// The error, status or recipe name behind the outcome
+    pub detail: String,
+    pub latency_ms: u64,
+}
+
+impl Preflight {
+    pub fn passed(&self) -> bool {
+        self.outcome == "ok"
+    }
+
+    fn new(url: &str, id: Option<u32>, outcome: &str, detail: String) -> Self {
+        Preflight {
+            url: url.to_string(),
+            id,
+            outcome: outcome.to_string(),
+            detail,
+            latency_ms: 0,
+        }
+    }
+}
+
+impl fmt::Display for Preflight {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let what = match self.outcome.as_str() {
+            "ok" => "answered",
+            "dns" => "couldn't be looked up in DNS",
+            "tls" => "failed the TLS handshake",
+            "connect" => "refused the connection",
+            "timeout" => "timed out",
+            "network" => "couldn't be reached",
+            "http" => "answered with an HTTP error",
+            "html" => "answered with a web page instead of a recipe",
+            _ => "answered with something that isn't a recipe",
+        };
+        write!(f, "{} {} ({})", self.url, what, self.detail)
+    }
+}
+
+/// Fetch recipe `id`, or the site root without one, and check the answer
+/// is what a run would need: a recipe, or for the root any response short
+/// of a server error. Failures say whether DNS, TLS, the connection or the
+/// server is to blame.
+pub async fn check(client: &Client, config: &DownloadConfig, id: Option<u32>) -> Preflight {
+    let url = match id {
+        Some(id) => recipe_url(&config.base_url, id),
+        None => config.base_url.clone(),
+    };
+    let started = Instant::now();
+    config.requests.pass().await;
+    let mut preflight = probe(client, config, &url, id).await;
+    preflight.latency_ms = started.elapsed().as_millis() as u64;
+    preflight
+}
+
+async fn probe(client: &Client, config: &DownloadConfig, url: &str, id: Option<u32>) -> Preflight {
+    let sent = client
+        .get(url)
+        .header(USER_AGENT, request_user_agent(config))
+        .send()
+        .await;
+    let response = match sent {
+        Ok(response) => response,
+        Err(e) => return Preflight::new(url, id, diagnose(&e), error_chain(&e)),
+    };
+    let status = response.status();
+    let failed = match id {
+        Some(_) => !status.is_success(),
+        None => status.is_server_error(),
+    };
+    if failed {
+        return Preflight::new(url, id, "http", format!("HTTP {}", status.as_u16()));
+    }
+    if id.is_none() {
+        return Preflight::new(url, id, "ok", format!("HTTP {}", status.as_u16()));
+    }
+    if is_web_page(response.headers()) {
+        return Preflight::new(url, id, "html", format!("HTTP {}", status.as_u16()));
+    }
+    match response.bytes().await {
+        Err(e) => Preflight::new(url, id, diagnose(&e), error_chain(&e)),
+        Ok(body) => match parse_bsmx(&body) {
+            Ok(recipe) => Preflight::new(url, id, "ok", recipe.name),
+            Err(e) => Preflight::new(url, id, "not-a-recipe", e.to_string()),
+        },
+    }
+}
+
+/// Which layer a request failed at, going by reqwest's flags and, since
+/// it has none for them, the wording of the DNS and TLS errors underneath.
+fn diagnose(e: &reqwest::Error) -> &'static str {
+    let chain = error_chain(e).to_ascii_lowercase();
+    if chain.contains("dns error") || chain.contains("failed to lookup address") {
+        "dns"
+    } else if ["tls", "ssl", "certificate", "handshake"]
+        .iter()
+        .any(|word| chain.contains(word))
+    {
+        "tls"
+    } else if e.is_timeout() {
+        "timeout"
+    } else if e.is_connect() {
+        "connect"
+    } else {
+        "network"
+    }
+}
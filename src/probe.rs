@@ -0,0 +1,293 @@
+use crate::cli::{DownloadArgs, Strategy};
+use crate::collection::scan_stems;
+use crate::download::{
+    attempt, batch_delay, build_client, is_unreachable, watch_for_interrupt, DownloadConfig,
+    Outcome, RunError, CONCURRENT_REQUESTS, NO_RESPONSE,
+};
+use crate::ids::IdTracker;
+use crate::logging;
+use crate::run_report::{default_probe_path, RunConfig, RunLog};
+use crate::sampling::{BucketStats, IdPicker};
+use indicatif::{ProgressBar, ProgressStyle};
+use std::collections::BTreeMap;
+use std::io::{self, IsTerminal};
+use std::sync::atomic::Ordering;
+use std::time::Instant;
+use tracing::{debug, info, info_span, warn, Instrument};
+
+/// Recipes a full run collects, for the projection
+const FULL_RUN_TARGET: usize = 10_000;
+/// z for a 95% confidence interval
+const Z_95: f64 = 1.96;
+
+/// Fetch a random sample of IDs the way a download run would, without
+/// writing anything, and report how many are recipes. With
+/// `--compare-strategies` the batches alternate between the uniform and
+/// adaptive strategies, which never pick the same ID twice, and each
+/// strategy's hit rate is reported.
+pub async fn run(
+    args: &DownloadArgs,
+    config: DownloadConfig,
+    quiet: bool,
+) -> Result<String, RunError> {
+    let span = (args.max_id - args.min_id) as usize + 1;
+    let sample = args.sample.min(span);
+    let mut rng = rand::thread_rng();
+    let mut tracker = IdTracker::new(args.min_id..=args.max_id);
+    let strategies = if args.compare_strategies {
+        vec![Strategy::Uniform, Strategy::Adaptive]
+    } else {
+        vec![args.strategy]
+    };
+    let mut pickers: Vec<IdPicker> = strategies
+        .into_iter()
+        .map(|strategy| {
+            IdPicker::new(args.min_id..=args.max_id, strategy, args.exploration)
+                .with_since(args.since_id)
+        })
+        .collect();
+    for picker in &pickers {
+        debug!("Picking IDs {}", picker.distribution());
+    }
+    // (hits, probed) for each picker
+    let mut tallies = vec![(0, 0); pickers.len()];
+    info!(
+        "Probing {} IDs between {} and {}; nothing will be written",
+        sample, args.min_id, args.max_id
+    );
+
+    let client = build_client(&config)?;
+    let interrupted = watch_for_interrupt()?;
+    let pb = if quiet || !io::stdout().is_terminal() {
+        ProgressBar::hidden()
+    } else {
+        ProgressBar::new(sample as u64)
+    };
+    pb.set_style(
+        ProgressStyle::default_bar()
+            .template("{spinner:.green} [{elapsed_precise}] [{bar:50.cyan/blue}] {pos}/{len} - Hits: {msg}")?
+            .progress_chars("#>-"),
+    );
+    logging::set_progress_bar(Some(pb.clone()));
+
+    let mut log = RunLog::default();
+    let mut outcomes: BTreeMap<&str, usize> = BTreeMap::new();
+    let mut status_codes: BTreeMap<u16, usize> = BTreeMap::new();
+    let (mut hits, mut probed) = (0, 0);
+    let mut reason = "dry-run";
+
+    // The same batches and pauses as a real run, so the probe is no harder
+    // on the site
+    let mut issued = 0;
+    let mut turn = 0;
+    while issued < sample {
+        if interrupted.load(Ordering::Relaxed) {
+            reason = "interrupted";
+            break;
+        }
+        let p = turn % pickers.len();
+        turn += 1;
+        let mut batch = vec![];
+        while batch.len() < CONCURRENT_REQUESTS.min(sample - issued) {
+            match pickers[p].next(&mut tracker, &mut rng) {
+                Some(id) => batch.push(id),
+                None => break,
+            }
+        }
+        if batch.is_empty() {
+            break;
+        }
+        issued += batch.len();
+
+        let mut tasks = vec![];
+        for id in batch {
+            let client = client.clone();
+            let config = config.clone();
+            let task = async move {
+                let started = Instant::now();
+                let outcome = attempt(&client, &config, id).await;
+                (id, started.elapsed(), outcome)
+            };
+            tasks.push(tokio::spawn(task.instrument(info_span!("probe", id))));
+        }
+
+        let mut session_expired = false;
+        for task in tasks {
+            let (id, elapsed, outcome) = task.await?;
+            match outcome {
+                Outcome::Saved(info) => {
+                    hits += 1;
+                    probed += 1;
+                    tallies[p].0 += 1;
+                    tallies[p].1 += 1;
+                    pickers[p].record(id, true);
+                    *outcomes.entry("saved").or_default() += 1;
+                    *status_codes.entry(info.status).or_default() += 1;
+                    log.saved(info.bytes, elapsed);
+                }
+                Outcome::Failed { category, status } => {
+                    probed += 1;
+                    tallies[p].1 += 1;
+                    if !is_unreachable(category, status) {
+                        pickers[p].record(id, false);
+                    }
+                    *outcomes.entry(category).or_default() += 1;
+                    *status_codes
+                        .entry(status.unwrap_or(NO_RESPONSE))
+                        .or_default() += 1;
+                    log.failed(id, category, elapsed);
+                }
+                Outcome::SessionExpired => session_expired = true,
+                // The gate holds the next batch back until the wait is over
+                Outcome::RateLimited { .. } => {}
+            }
+            pb.set_position(probed as u64);
+            pb.set_message(hits.to_string());
+        }
+        // Every later answer would be a 401 too, which says nothing about
+        // which IDs exist
+        if session_expired {
+            warn!("Stopping: the server requires a login session; pass --cookies");
+            reason = "session-expired";
+            break;
+        }
+
+        tokio::time::sleep(batch_delay(&config.requests)).await;
+    }
+    pb.finish_and_clear();
+    logging::set_progress_bar(None);
+
+    println!("\nDry Run:");
+    println!("--------");
+    println!(
+        "IDs probed: {} of {} between {} and {}",
+        probed, span, args.min_id, args.max_id
+    );
+    for (outcome, count) in &outcomes {
+        println!("  {:<16} {:>8}", outcome, count);
+    }
+    match wilson_interval(hits, probed) {
+        Some((low, high)) => {
+            println!(
+                "Hit rate: {:.2}% (95% CI {:.2}%-{:.2}%)",
+                hits as f64 / probed as f64 * 100.0,
+                low * 100.0,
+                high * 100.0
+            );
+            println!(
+                "Expected recipes in range: about {:.0}",
+                hits as f64 / probed as f64 * span as f64
+            );
+            let existing = scan_stems(
+                &config.output_dir,
+                config.storage_layout,
+                &ProgressBar::hidden(),
+            )
+            .map_or(0, |stems| stems.len());
+            let needed = FULL_RUN_TARGET.saturating_sub(existing);
+            match projected_attempts(needed, hits, probed) {
+                Some((likely, best, worst)) => println!(
+                    "Attempts for {} more recipes: about {} ({}-{})",
+                    needed, likely, best, worst
+                ),
+                None => println!("Attempts for {} more recipes: unknown, no hits", needed),
+            }
+        }
+        None => println!("Hit rate: nothing probed"),
+    }
+    if pickers.len() > 1 {
+        println!("Hit rate by strategy:");
+        for (picker, &(hits, probed)) in pickers.iter().zip(&tallies) {
+            let rate = if probed == 0 {
+                "nothing probed".to_string()
+            } else {
+                format!("{:.2}% of {}", hits as f64 / probed as f64 * 100.0, probed)
+            };
+            println!(
+                "  {:<16} {}",
+                format!("{:?}", picker.strategy()).to_lowercase(),
+                rate
+            );
+        }
+    }
+
+    let report_file = match (&args.report_file, args.report) {
+        (Some(path), _) => Some(path.clone()),
+        (None, true) => Some(default_probe_path(log.started_at())),
+        (None, false) => None,
+    };
+    if let Some(path) = report_file {
+        let run_config = RunConfig {
+            base_url: config.base_url.clone(),
+            output_dir: config.output_dir.clone(),
+            target: sample,
+            concurrency: CONCURRENT_REQUESTS,
+            timeout_secs: config.request_timeout.as_secs(),
+            daily_quota: 0,
+        };
+        log.set_buckets(&combined_buckets(&pickers));
+        let mut report = log.finish(run_config, reason, 0, hits, probed);
+        report.transferred_bytes = config.transfers.received();
+        (report.compressed_bytes, report.decompressed_bytes) = config.transfers.compression();
+        report.status_codes = status_codes;
+        config.responses.add_to(&mut report);
+        report.save(&path)?;
+        info!("Dry run report written to {}", path.display());
+    }
+
+    let summary = format!("{}, {} hits of {} probed", reason, hits, probed);
+    match reason {
+        "interrupted" => Err(RunError::Interrupted { summary }),
+        "session-expired" => Err(RunError::Partial { reason, summary }),
+        _ => Ok(summary),
+    }
+}
+
+/// The 95% Wilson score interval for a hit rate of `hits` in `trials`,
+/// which stays sensible for the small rates and samples a probe sees.
+pub fn wilson_interval(hits: usize, trials: usize) -> Option<(f64, f64)> {
+    if trials == 0 {
+        return None;
+    }
+    let n = trials as f64;
+    let p = hits as f64 / n;
+    let z2 = Z_95 * Z_95;
+    let denominator = 1.0 + z2 / n;
+    let center = (p + z2 / (2.0 * n)) / denominator;
+    let half = Z_95 * (p * (1.0 - p) / n + z2 / (4.0 * n * n)).sqrt() / denominator;
+    Some(((center - half).max(0.0), (center + half).min(1.0)))
+}
+
+/// Attempts needed to save `needed` more recipes at the observed hit rate,
+/// as (likely, best case, worst case) from the ends of the 95% interval.
+pub fn projected_attempts(
+    needed: usize,
+    hits: usize,
+    trials: usize,
+) -> Option<(usize, usize, usize)> {
+    if hits == 0 {
+        return None;
+    }
+    let (low, high) = wilson_interval(hits, trials)?;
+    let rate = hits as f64 / trials as f64;
+    let attempts = |rate: f64| (needed as f64 / rate).ceil() as usize;
+    Some((attempts(rate), attempts(high), attempts(low)))
+}
+
+/// Every picker's bucket counts added together; they all split the range
+/// the same way.
+fn combined_buckets(pickers: &[IdPicker]) -> Vec<BucketStats> {
+    let mut combined = pickers[0].buckets().to_vec();
+    for picker in &pickers[1..] {
+        for (total, bucket) in combined.iter_mut().zip(picker.buckets()) {
+            total.attempts += bucket.attempts;
+            total.hits += bucket.hits;
+        }
+    }
+    for bucket in &mut combined {
+        if bucket.attempts > 0 {
+            bucket.hit_rate = bucket.hits as f64 / bucket.attempts as f64;
+        }
+    }
+    combined
+}
@@ -0,0 +1,410 @@
+use crate::throttle::TransferMeter;
+use chrono::{SecondsFormat, Utc};
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use serde::Serialize;
+use std::fmt::Display;
+use std::io::{self, Write};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
+
+/// Version of the `--progress json` event schema, bumped on any change that
+/// could break a consumer.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// One line of the `--progress json` stream. See the README for the schema.
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum Event<'a> {
+    #[serde(rename_all = "snake_case")]
+    RunStarted {
+        schema: u32,
+        base_url: &'a str,
+        output_dir: String,
+        target: usize,
+        concurrency: usize,
+        timeout_secs: u64,
+        connect_timeout_ms: u64,
+        read_timeout_ms: u64,
+        request_timeout_ms: u64,
+        daily_quota: usize,
+        existing: usize,
+    },
+    DownloadSucceeded {
+        id: u32,
+        filename: &'a str,
+        bytes: usize,
+        elapsed_ms: u64,
+    },
+    DownloadFailed {
+        id: u32,
+        category: &'a str,
+        status: Option<u16>,
+    },
+    /// An ID answered 429 Too Many Requests, and will be tried again
+    #[serde(rename_all = "snake_case")]
+    RateLimited { id: u32, retry_after_secs: u64 },
+    Checkpoint {
+        successful: usize,
+        failed: usize,
+        attempted: usize,
+    },
+    #[serde(rename_all = "snake_case")]
+    Throttled {
+        reason: &'a str,
+        resume_in_secs: Option<u64>,
+    },
+    #[serde(rename_all = "snake_case")]
+    Heartbeat {
+        successful: usize,
+        failed: usize,
+        attempted: usize,
+        elapsed_secs: u64,
+        transferred_bytes: u64,
+        bytes_per_sec: u64,
+    },
+    #[serde(rename_all = "snake_case")]
+    RunFinished {
+        reason: &'a str,
+        existing: usize,
+        downloaded: usize,
+        failed: usize,
+        attempted: usize,
+        elapsed_secs: u64,
+    },
+}
+
+#[derive(Serialize)]
+struct Line<'a> {
+    ts: String,
+    #[serde(flatten)]
+    event: &'a Event<'a>,
+}
+
+/// One line of `--progress-style json`.
+#[derive(Serialize)]
+struct StatusObject {
+    ts: String,
+    successful: usize,
+    failed: usize,
+    /// IDs tried per second since the run started
+    rate_per_sec: f64,
+}
+
+/// What `--progress-style` asks for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BarStyle {
+    /// The bar and counts on one short line
+    Compact,
+    /// The bar, ETA, rates and counts over several lines
+    Verbose,
+    /// A JSON status object a second in place of the bar
+    Json,
+    /// No bar, just the final summary
+    None,
+    /// An indicatif template
+    Template(String),
+}
+
+impl FromStr for BarStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "compact" => BarStyle::Compact,
+            "verbose" => BarStyle::Verbose,
+            "json" => BarStyle::Json,
+            "none" => BarStyle::None,
+            _ => {
+                ProgressStyle::with_template(s).map_err(|e| {
+                    format!(
+                        "expected compact, verbose, json, none or a progress bar template: {}",
+                        e
+                    )
+                })?;
+                BarStyle::Template(s.to_string())
+            }
+        })
+    }
+}
+
+/// Running totals for a download run.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Counts {
+    /// Recipes on disk, including those there before the run
+    pub successful: usize,
+    pub failed: usize,
+    pub attempted: usize,
+    /// Bytes received this run, including transfers that broke off
+    pub transferred: u64,
+    /// Bytes received per second lately
+    pub bytes_per_sec: f64,
+}
+
+/// How a download run shows its progress.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Output {
+    /// An animated progress bar, for a terminal
+    Bar,
+    /// A plain status line every so often, for logs and cron mail
+    Lines,
+    /// JSON events on stdout, for a supervising program
+    Json,
+    /// A JSON status object on stdout every second, for log parsers
+    JsonStatus,
+    /// Nothing but the final summary
+    Summary,
+}
+
+/// Where a download run reports to: people (a progress bar or status lines
+/// plus messages on stdout) or a supervising program (JSON events on
+/// stdout, messages on stderr).
+pub struct Reporter {
+    output: Output,
+    target: usize,
+    started: Instant,
+    counts: Arc<Mutex<Counts>>,
+    transfers: Option<Arc<TransferMeter>>,
+    raw_bytes: bool,
+}
+
+impl Reporter {
+    pub fn new(output: Output, target: usize) -> Self {
+        Reporter {
+            output,
+            target,
+            started: Instant::now(),
+            counts: Arc::default(),
+            transfers: None,
+            raw_bytes: false,
+        }
+    }
+
+    /// Take the transfer totals in heartbeats and status lines from
+    /// `meter`, written as plain bytes if `raw_bytes`. Takes effect for
+    /// tickers started afterwards.
+    pub fn watch_transfers(&mut self, meter: Arc<TransferMeter>, raw_bytes: bool) {
+        self.transfers = Some(meter);
+        self.raw_bytes = raw_bytes;
+    }
+
+    /// Change the count status lines measure against. Takes effect for
+    /// tickers started afterwards.
+    pub fn set_target(&mut self, target: usize) {
+        self.target = target;
+    }
+
+    pub fn is_json(&self) -> bool {
+        self.output == Output::Json
+    }
+
+    pub fn shows_bar(&self) -> bool {
+        self.output == Output::Bar
+    }
+
+    /// A message for whoever is watching, kept off stdout when it carries
+    /// JSON.
+    pub fn log(&self, message: impl Display) {
+        if matches!(self.output, Output::Json | Output::JsonStatus) {
+            eprintln!("{}", message);
+        } else {
+            println!("{}", message);
+        }
+    }
+
+    pub fn event(&self, event: &Event) {
+        if self.is_json() {
+            emit(event);
+        }
+    }
+
+    /// Record the run's totals for the next heartbeat or status line.
+    pub fn set_counts(&self, counts: Counts) {
+        *self.counts.lock().unwrap() = counts;
+    }
+
+    pub fn elapsed_secs(&self) -> u64 {
+        self.started.elapsed().as_secs()
+    }
+
+    /// Emit a heartbeat every `heartbeat` in JSON mode, so a supervisor can
+    /// tell a slow run from a hung one, a status line every
+    /// `status_interval` in plain mode, or a status object every second
+    /// with `--progress-style json`. Stops when the handle is aborted.
+    pub fn start_ticker(
+        &self,
+        heartbeat: Duration,
+        status_interval: Duration,
+    ) -> Option<JoinHandle<()>> {
+        let every = match self.output {
+            Output::Json => heartbeat,
+            Output::Lines => status_interval,
+            Output::JsonStatus => Duration::from_secs(1),
+            Output::Bar | Output::Summary => return None,
+        };
+        if every.is_zero() {
+            return None;
+        }
+        let (output, target, raw_bytes) = (self.output, self.target, self.raw_bytes);
+        let counts = self.counts.clone();
+        let transfers = self.transfers.clone();
+        let started = self.started;
+        Some(tokio::spawn(async move {
+            let mut interval = tokio::time::interval(every);
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                let mut c = *counts.lock().unwrap();
+                if let Some(meter) = &transfers {
+                    c.transferred = meter.received();
+                    c.bytes_per_sec = meter.rate();
+                }
+                let elapsed = started.elapsed();
+                match output {
+                    Output::Json => emit(&Event::Heartbeat {
+                        successful: c.successful,
+                        failed: c.failed,
+                        attempted: c.attempted,
+                        elapsed_secs: elapsed.as_secs(),
+                        transferred_bytes: c.transferred,
+                        bytes_per_sec: c.bytes_per_sec as u64,
+                    }),
+                    Output::JsonStatus => write_line(&StatusObject {
+                        ts: timestamp(),
+                        successful: c.successful,
+                        failed: c.failed,
+                        rate_per_sec: (c.attempted as f64 / elapsed.as_secs_f64() * 100.0).round()
+                            / 100.0,
+                    }),
+                    _ => println!("{}", status_line(&c, target, raw_bytes)),
+                }
+            }
+        }))
+    }
+}
+
+/// One line of plain progress, e.g.
+/// "5,230/10,000 downloaded, 312 failed, 1.1 MiB/s, 61.2 MiB received".
+pub fn status_line(counts: &Counts, target: usize, raw_bytes: bool) -> String {
+    format!(
+        "{}/{} downloaded, {} failed, {}/s, {} received",
+        thousands(counts.successful),
+        thousands(target),
+        thousands(counts.failed),
+        format_bytes(counts.bytes_per_sec as u64, raw_bytes),
+        format_bytes(counts.transferred, raw_bytes)
+    )
+}
+
+/// A byte count in binary units, e.g. "1.5 MiB", or as a plain number of
+/// bytes if `raw`.
+pub fn format_bytes(bytes: u64, raw: bool) -> String {
+    const UNITS: [&str; 3] = ["KiB", "MiB", "GiB"];
+    if raw || bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+pub(crate) fn thousands(n: usize) -> String {
+    let digits = n.to_string();
+    let mut out = String::new();
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.push(',');
+        }
+        out.push(c);
+    }
+    out
+}
+
+fn emit(event: &Event) {
+    write_line(&Line {
+        ts: timestamp(),
+        event,
+    });
+}
+
+fn timestamp() -> String {
+    Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+fn write_line(line: &impl Serialize) {
+    if let Ok(json) = serde_json::to_string(line) {
+        // Hold the lock so heartbeat lines never interleave with others
+        let mut stdout = io::stdout().lock();
+        let _ = writeln!(stdout, "{}", json);
+        let _ = stdout.flush();
+    }
+}
+
+/// Bars of their own, under the run's bar, for recipe files big enough to
+/// take a while, showing each file's name and how much of it has arrived.
+#[derive(Debug, Clone)]
+pub struct FileBars {
+    multi: MultiProgress,
+    /// Files announced as bigger than this many bytes get a bar
+    threshold: u64,
+}
+
+impl FileBars {
+    /// Bars for files over `threshold` bytes, drawn below `main`, which is
+    /// returned to be used in its place.
+    pub fn new(main: ProgressBar, threshold: u64) -> (Self, ProgressBar) {
+        let multi = MultiProgress::new();
+        let main = multi.add(main);
+        (FileBars { multi, threshold }, main)
+    }
+
+    /// A bar for `filename` if its `total` size is known and over the
+    /// threshold. `received` is what an earlier transfer already got.
+    pub fn start(&self, filename: &str, received: u64, total: Option<u64>) -> Option<FileBar> {
+        let total = total.filter(|&total| total > self.threshold)?;
+        let bar = self.multi.add(ProgressBar::new(total));
+        bar.set_style(
+            ProgressStyle::default_bar()
+                .template(
+                    "  {msg:30!} [{bar:30.green/blue}] {bytes}/{total_bytes} ({bytes_per_sec})",
+                )
+                .ok()?
+                .progress_chars("#>-"),
+        );
+        bar.set_message(filename.to_string());
+        bar.set_position(received);
+        Some(FileBar {
+            bar,
+            multi: self.multi.clone(),
+        })
+    }
+}
+
+/// One file's bar, taken off the screen when the transfer ends, however it
+/// ends.
+pub struct FileBar {
+    bar: ProgressBar,
+    multi: MultiProgress,
+}
+
+impl FileBar {
+    pub fn inc(&self, bytes: usize) {
+        self.bar.inc(bytes as u64);
+    }
+
+    pub fn position(&self) -> u64 {
+        self.bar.position()
+    }
+}
+
+impl Drop for FileBar {
+    fn drop(&mut self) {
+        self.bar.finish_and_clear();
+        self.multi.remove(&self.bar);
+    }
+}
@@ -0,0 +1,544 @@
+use crate::cli::ProxyRotation;
+use crate::download::{build_client, is_unreachable, DownloadConfig, Outcome};
+use rand::Rng;
+use reqwest::{Client, NoProxy, Proxy, Url};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use std::{env, fmt, fs};
+use tokio::net::TcpStream;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tracing::{info, warn};
+
+/// Proxy schemes --proxy takes
+const PROXY_SCHEMES: [&str; 4] = ["http", "https", "socks5", "socks5h"];
+/// How long the startup check waits for the proxy to accept a connection
+const PROXY_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Requests in a row a pooled proxy can fail to get through before it's
+/// set aside
+pub const QUARANTINE_AFTER: u32 = 3;
+/// How long a proxy is first set aside; each time again without a request
+/// getting through doubles it, up to [`MAX_QUARANTINE`]
+pub const QUARANTINE: Duration = Duration::from_secs(30);
+pub const MAX_QUARANTINE: Duration = Duration::from_secs(600);
+
+/// The proxy every request goes through.
+#[derive(Clone)]
+pub struct ProxyConfig {
+    pub url: Url,
+    /// From --proxy-user, overriding any user in the URL
+    pub auth: Option<(String, String)>,
+    /// `--proxy`, the environment variable it was taken from, or the
+    /// --proxy-list line
+    pub source: String,
+    /// Whether NO_PROXY applies
+    from_env: bool,
+}
+
+impl fmt::Debug for ProxyConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProxyConfig")
+            .field("url", &self.to_string())
+            .field("source", &self.source)
+            .finish()
+    }
+}
+
+/// The URL with any password left out, for messages.
+impl fmt::Display for ProxyConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut url = self.url.clone();
+        if url.password().is_some() {
+            let _ = url.set_password(Some("***"));
+        }
+        write!(f, "{}", url.as_str().trim_end_matches('/'))
+    }
+}
+
+impl ProxyConfig {
+    /// The proxy for requests to `base_url`: --proxy if given, otherwise
+    /// the first of the usual environment variables that's set. `user` is
+    /// `USER[:PASSWORD]`, as curl's --proxy-user takes it.
+    pub fn resolve(
+        flag: Option<&str>,
+        user: Option<&str>,
+        base_url: &str,
+    ) -> Result<Option<Self>, String> {
+        let (text, source) = match flag {
+            Some(text) => (text.to_string(), "--proxy".to_string()),
+            None => match from_env(base_url) {
+                Some(found) => found,
+                None if user.is_some() => {
+                    return Err("--proxy-user needs --proxy or a proxy in the environment".into())
+                }
+                None => return Ok(None),
+            },
+        };
+        let from_env = flag.is_none();
+        let mut proxy = ProxyConfig::parse(&text, user, source)?;
+        proxy.from_env = from_env;
+        Ok(Some(proxy))
+    }
+
+    /// A proxy URL, with `source` saying where it came from for messages.
+    pub fn parse(text: &str, user: Option<&str>, source: String) -> Result<Self, String> {
+        // A bare host:port means an HTTP proxy, as it does to curl
+        let text = if text.contains("://") {
+            text.to_string()
+        } else {
+            format!("http://{}", text)
+        };
+        let url = Url::parse(&text).map_err(|e| format!("{} {:?}: {}", source, text, e))?;
+        if !PROXY_SCHEMES.contains(&url.scheme()) {
+            return Err(format!(
+                "{} {:?}: unsupported scheme {:?}; use http, https or socks5",
+                source,
+                text,
+                url.scheme()
+            ));
+        }
+        if url.host_str().is_none() {
+            return Err(format!("{} {:?} has no host", source, text));
+        }
+        let auth = user.map(|user| match user.split_once(':') {
+            Some((name, password)) => (name.to_string(), password.to_string()),
+            None => (user.to_string(), String::new()),
+        });
+        Ok(ProxyConfig {
+            url,
+            auth,
+            source,
+            from_env: false,
+        })
+    }
+
+    /// The proxy as reqwest takes it. One from the environment leaves out
+    /// the hosts NO_PROXY names.
+    pub fn to_reqwest(&self) -> reqwest::Result<Proxy> {
+        let mut proxy = Proxy::all(self.url.clone())?;
+        if let Some((user, password)) = &self.auth {
+            proxy = proxy.basic_auth(user, password);
+        }
+        if self.from_env {
+            proxy = proxy.no_proxy(NoProxy::from_env());
+        }
+        Ok(proxy)
+    }
+
+    /// `host:port` the proxy listens on, with the scheme's usual port if
+    /// the URL doesn't give one.
+    pub fn address(&self) -> String {
+        let port = self.url.port().unwrap_or(match self.url.scheme() {
+            "https" => 443,
+            "socks5" | "socks5h" => 1080,
+            _ => 80,
+        });
+        let host = self.url.host_str().unwrap_or_default();
+        format!("{}:{}", host, port)
+    }
+}
+
+/// The environment variable curl and reqwest would take a proxy for
+/// `base_url` from, and its value.
+fn from_env(base_url: &str) -> Option<(String, String)> {
+    let names: &[&str] = if base_url.starts_with("http://") {
+        &["HTTP_PROXY", "http_proxy", "ALL_PROXY", "all_proxy"]
+    } else {
+        &["HTTPS_PROXY", "https_proxy", "ALL_PROXY", "all_proxy"]
+    };
+    names.iter().find_map(|name| {
+        env::var(name)
+            .ok()
+            .filter(|value| !value.trim().is_empty())
+            .map(|value| (value.trim().to_string(), name.to_string()))
+    })
+}
+
+/// Make sure the proxy is there and passes requests on before a run
+/// starts, telling apart a proxy that can't be reached from a site that
+/// can't be reached through it.
+pub async fn check(proxy: &ProxyConfig, client: &Client, base_url: &str) -> Result<(), String> {
+    let address = proxy.address();
+    match tokio::time::timeout(PROXY_CONNECT_TIMEOUT, TcpStream::connect(&address)).await {
+        Ok(Ok(_)) => {}
+        Ok(Err(e)) => {
+            return Err(format!(
+                "can't reach the proxy {} (from {}): {}",
+                proxy, proxy.source, e
+            ))
+        }
+        Err(_) => {
+            return Err(format!(
+                "can't reach the proxy {} (from {}): no answer within {}s",
+                proxy,
+                proxy.source,
+                PROXY_CONNECT_TIMEOUT.as_secs()
+            ))
+        }
+    }
+    // Any answer at all means the proxy got the request through
+    if let Err(e) = client.head(base_url).send().await {
+        return Err(format!(
+            "the proxy {} is up, but {} can't be reached through it: {}",
+            proxy,
+            base_url,
+            error_chain(&e)
+        ));
+    }
+    Ok(())
+}
+
+/// An error with its causes, which is where reqwest says what went wrong.
+pub(crate) fn error_chain(e: &dyn Error) -> String {
+    let mut text = e.to_string();
+    let mut source = e.source();
+    while let Some(cause) = source {
+        text.push_str(": ");
+        text.push_str(&cause.to_string());
+        source = cause.source();
+    }
+    text
+}
+
+/// The proxies in a --proxy-list file: one URL per line, as --proxy takes
+/// them, skipping blank lines and `#` comments. `user` logs in to all of
+/// them.
+pub fn read_proxy_list(path: &Path, user: Option<&str>) -> Result<Vec<ProxyConfig>, String> {
+    let text =
+        fs::read_to_string(path).map_err(|e| format!("can't read {}: {}", path.display(), e))?;
+    let mut proxies = vec![];
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let source = format!("{} line {}", path.display(), n + 1);
+        proxies.push(ProxyConfig::parse(line, user, source)?);
+    }
+    if proxies.is_empty() {
+        return Err(format!("{} lists no proxies", path.display()));
+    }
+    Ok(proxies)
+}
+
+/// One proxy of a --proxy-list, with the client that goes through it and
+/// how it's been doing.
+#[derive(Debug)]
+pub struct PooledProxy {
+    pub proxy: ProxyConfig,
+    pub client: Client,
+    /// --per-proxy-concurrency
+    slots: Option<Arc<Semaphore>>,
+    health: Mutex<Health>,
+    saved: AtomicUsize,
+    failed: AtomicUsize,
+    /// Of `failed`, the requests that didn't get through at all
+    unreachable: AtomicUsize,
+    quarantines: AtomicUsize,
+}
+
+#[derive(Debug, Default)]
+struct Health {
+    /// Requests that didn't get through since the last one that did
+    failures: u32,
+    /// Times set aside since a request last got through
+    quarantines: u32,
+    /// Left out of the rotation until then
+    until: Option<Instant>,
+}
+
+impl PooledProxy {
+    fn new(
+        proxy: ProxyConfig,
+        template: &DownloadConfig,
+        per_proxy: Option<usize>,
+    ) -> reqwest::Result<Self> {
+        let client = build_client(&DownloadConfig {
+            proxy: Some(proxy.clone()),
+            ..template.clone()
+        })?;
+        Ok(PooledProxy {
+            proxy,
+            client,
+            slots: per_proxy.map(|n| Arc::new(Semaphore::new(n))),
+            health: Mutex::default(),
+            saved: AtomicUsize::new(0),
+            failed: AtomicUsize::new(0),
+            unreachable: AtomicUsize::new(0),
+            quarantines: AtomicUsize::new(0),
+        })
+    }
+
+    /// When the proxy comes back into the rotation, if it's set aside.
+    fn quarantined_until(&self, now: Instant) -> Option<Instant> {
+        self.health
+            .lock()
+            .unwrap()
+            .until
+            .filter(|&until| until > now)
+    }
+
+    /// Wait for one of the proxy's --per-proxy-concurrency slots; the
+    /// request holds it until it's done.
+    pub async fn slot(&self) -> Option<OwnedSemaphorePermit> {
+        match &self.slots {
+            Some(slots) => slots.clone().acquire_owned().await.ok(),
+            None => None,
+        }
+    }
+
+    /// Count a request sent through the proxy. Timeouts and failed
+    /// connections are put down to the proxy; once there have been
+    /// [`QUARANTINE_AFTER`] in a row it's set aside, and when it comes
+    /// back a single failure sets it aside again, for twice as long.
+    pub(crate) fn record(&self, outcome: &Outcome) {
+        let got_through = match outcome {
+            Outcome::Saved(_) => {
+                self.saved.fetch_add(1, Ordering::Relaxed);
+                true
+            }
+            Outcome::Failed { category, .. } => {
+                self.failed.fetch_add(1, Ordering::Relaxed);
+                let unreachable = is_unreachable(category, None);
+                if unreachable {
+                    self.unreachable.fetch_add(1, Ordering::Relaxed);
+                }
+                !unreachable
+            }
+            Outcome::SessionExpired | Outcome::RateLimited { .. } => true,
+        };
+        let mut health = self.health.lock().unwrap();
+        if got_through {
+            *health = Health::default();
+            return;
+        }
+        health.failures += 1;
+        let now = Instant::now();
+        // Requests already in flight when it was set aside don't count again
+        if health.failures >= QUARANTINE_AFTER && health.until.is_none_or(|until| until <= now) {
+            self.quarantine(&mut health, now);
+        }
+    }
+
+    fn quarantine(&self, health: &mut Health, now: Instant) {
+        let backoff = QUARANTINE
+            .saturating_mul(1 << health.quarantines.min(16))
+            .min(MAX_QUARANTINE);
+        health.quarantines += 1;
+        health.until = Some(now + backoff);
+        self.quarantines.fetch_add(1, Ordering::Relaxed);
+        warn!(
+            proxy = %self.proxy,
+            "setting the proxy aside for {}s after {} failed requests in a row",
+            backoff.as_secs(),
+            health.failures
+        );
+    }
+}
+
+/// The proxies of a --proxy-list, which the requests of a run are spread
+/// over.
+#[derive(Debug)]
+pub struct ProxyPool {
+    path: PathBuf,
+    user: Option<String>,
+    rotation: ProxyRotation,
+    per_proxy: Option<usize>,
+    /// What each proxy's client is built from
+    template: DownloadConfig,
+    members: RwLock<Vec<Arc<PooledProxy>>>,
+    /// Dropped from the file since the run started, kept for the summary
+    removed: Mutex<Vec<Arc<PooledProxy>>>,
+    next: AtomicUsize,
+}
+
+impl ProxyPool {
+    /// Read the proxies in `path`, building their clients with the rest
+    /// of `template`.
+    pub fn load(
+        path: &Path,
+        user: Option<&str>,
+        rotation: ProxyRotation,
+        per_proxy: Option<usize>,
+        template: &DownloadConfig,
+    ) -> Result<Self, Box<dyn Error>> {
+        let template = DownloadConfig {
+            proxy: None,
+            proxy_pool: None,
+            ..template.clone()
+        };
+        let members = read_proxy_list(path, user)?
+            .into_iter()
+            .map(|proxy| PooledProxy::new(proxy, &template, per_proxy).map(Arc::new))
+            .collect::<reqwest::Result<_>>()?;
+        Ok(ProxyPool {
+            path: path.to_path_buf(),
+            user: user.map(str::to_string),
+            rotation,
+            per_proxy,
+            template,
+            members: RwLock::new(members),
+            removed: Mutex::default(),
+            next: AtomicUsize::new(0),
+        })
+    }
+
+    /// The proxy for the odd request outside the rotation, like robots.txt.
+    pub fn first(&self) -> ProxyConfig {
+        self.members.read().unwrap()[0].proxy.clone()
+    }
+
+    /// The proxy for the next request. When they're all set aside, the
+    /// one coming back soonest.
+    pub fn pick(&self) -> Arc<PooledProxy> {
+        let members = self.members.read().unwrap();
+        let now = Instant::now();
+        let ready: Vec<&Arc<PooledProxy>> = members
+            .iter()
+            .filter(|member| member.quarantined_until(now).is_none())
+            .collect();
+        if ready.is_empty() {
+            return members
+                .iter()
+                .min_by_key(|member| member.quarantined_until(now))
+                .expect("a pool always has a proxy")
+                .clone();
+        }
+        let n = match self.rotation {
+            ProxyRotation::RoundRobin => self.next.fetch_add(1, Ordering::Relaxed),
+            ProxyRotation::Random => rand::thread_rng().gen_range(0..ready.len()),
+        };
+        ready[n % ready.len()].clone()
+    }
+
+    /// Requests worth having in flight at once, with
+    /// --per-proxy-concurrency.
+    pub fn concurrency(&self) -> Option<usize> {
+        self.per_proxy
+            .map(|n| n * self.members.read().unwrap().len())
+    }
+
+    /// [`check`] each proxy before a run starts, setting aside the ones
+    /// that fail. Only if none pass is that an error.
+    pub async fn check(&self, base_url: &str) -> Result<(), String> {
+        let members = self.members.read().unwrap().clone();
+        let checks = members.iter().map(|member| {
+            let member = member.clone();
+            let base_url = base_url.to_string();
+            tokio::spawn(async move { check(&member.proxy, &member.client, &base_url).await })
+        });
+        let mut last_error = String::new();
+        let mut working = 0;
+        for (member, check) in members.iter().zip(checks.collect::<Vec<_>>()) {
+            match check.await.map_err(|e| e.to_string()).and_then(|r| r) {
+                Ok(()) => working += 1,
+                Err(e) => {
+                    warn!(error = %e, "proxy check failed");
+                    let mut health = member.health.lock().unwrap();
+                    health.failures = QUARANTINE_AFTER;
+                    member.quarantine(&mut health, Instant::now());
+                    last_error = e;
+                }
+            }
+        }
+        if working == 0 {
+            return Err(format!(
+                "none of the proxies in {} work; the last: {}",
+                self.path.display(),
+                last_error
+            ));
+        }
+        info!("{} of {} proxies work", working, members.len());
+        Ok(())
+    }
+
+    /// Read the list again. Proxies still in it keep their counts and
+    /// health; new ones start fresh. A list that can't be read, or is
+    /// empty, leaves the pool as it was.
+    pub fn reload(&self) -> Result<(), Box<dyn Error>> {
+        let proxies = read_proxy_list(&self.path, self.user.as_deref())?;
+        let mut members = self.members.write().unwrap();
+        let mut kept = Vec::with_capacity(proxies.len());
+        for proxy in proxies {
+            match members.iter().find(|member| member.proxy.url == proxy.url) {
+                Some(member) => kept.push(member.clone()),
+                None => kept.push(Arc::new(PooledProxy::new(
+                    proxy,
+                    &self.template,
+                    self.per_proxy,
+                )?)),
+            }
+        }
+        let (added, gone) = (
+            kept.iter()
+                .filter(|m| !members.iter().any(|old| Arc::ptr_eq(old, m)))
+                .count(),
+            members
+                .iter()
+                .filter(|m| !kept.iter().any(|new| Arc::ptr_eq(new, m))),
+        );
+        let gone: Vec<_> = gone.cloned().collect();
+        info!(
+            "Reloaded {}: {} proxies, {} new, {} dropped",
+            self.path.display(),
+            kept.len(),
+            added,
+            gone.len()
+        );
+        self.removed.lock().unwrap().extend(gone);
+        *members = kept;
+        Ok(())
+    }
+
+    /// Read the list again on every SIGHUP.
+    #[cfg(unix)]
+    pub fn reload_on_sighup(self: &Arc<Self>) -> std::io::Result<()> {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut hup = signal(SignalKind::hangup())?;
+        let pool = self.clone();
+        tokio::spawn(async move {
+            while hup.recv().await.is_some() {
+                if let Err(e) = pool.reload() {
+                    warn!(error = %e, "keeping the old proxy list");
+                }
+            }
+        });
+        Ok(())
+    }
+
+    /// How each proxy did, for the end of a run.
+    pub fn summary(&self) -> String {
+        let mut out = String::from("Proxies:\n--------\n");
+        let members = self.members.read().unwrap();
+        let removed = self.removed.lock().unwrap();
+        let now = Instant::now();
+        let all = members
+            .iter()
+            .map(|member| (member, false))
+            .chain(removed.iter().map(|member| (member, true)));
+        for (member, dropped) in all {
+            let mut line = format!(
+                "  {:<36} {:>6} saved {:>6} failed {:>6} unreachable",
+                member.proxy.to_string(),
+                member.saved.load(Ordering::Relaxed),
+                member.failed.load(Ordering::Relaxed),
+                member.unreachable.load(Ordering::Relaxed),
+            );
+            let quarantines = member.quarantines.load(Ordering::Relaxed);
+            if quarantines > 0 {
+                line.push_str(&format!(", set aside {}x", quarantines));
+            }
+            if dropped {
+                line.push_str(" (dropped from the list)");
+            } else if member.quarantined_until(now).is_some() {
+                line.push_str(" (set aside now)");
+            }
+            out.push_str(&line);
+            out.push('\n');
+        }
+        out.pop();
+        out
+    }
+}
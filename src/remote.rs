@@ -0,0 +1,465 @@
+use crate::cli::RemoteArgs;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use reqwest::{Client, Method, StatusCode, Url};
+use sha2::{Digest, Sha256};
+use std::error::Error;
+use std::path::PathBuf;
+use std::time::Duration;
+use std::{env, fmt, fs};
+use tracing::{debug, warn};
+
+/// Region assumed when neither the flags, the environment nor the AWS
+/// config file give one
+pub const DEFAULT_REGION: &str = "us-east-1";
+
+/// SHA-256 of nothing, which every request without a body is signed with
+const EMPTY_SHA256: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+
+/// A place in an S3 bucket, written `s3://bucket/prefix`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteLocation {
+    pub bucket: String,
+    /// Without a trailing slash; empty for the top of the bucket
+    pub prefix: String,
+}
+
+impl RemoteLocation {
+    pub fn parse(url: &str) -> Result<Self, String> {
+        let rest = url
+            .strip_prefix("s3://")
+            .ok_or_else(|| format!("expected a remote like s3://bucket/prefix, got {:?}", url))?;
+        let (bucket, prefix) = rest.split_once('/').unwrap_or((rest, ""));
+        if bucket.is_empty() {
+            return Err(format!("{:?} has no bucket name", url));
+        }
+        Ok(RemoteLocation {
+            bucket: bucket.to_string(),
+            prefix: prefix.trim_matches('/').to_string(),
+        })
+    }
+
+    /// The object key for `name` under the prefix.
+    pub fn key(&self, name: &str) -> String {
+        if self.prefix.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}/{}", self.prefix, name)
+        }
+    }
+}
+
+impl fmt::Display for RemoteLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "s3://{}/{}", self.bucket, self.prefix)
+    }
+}
+
+/// An access key. The secret never appears in debug output.
+#[derive(Clone)]
+pub struct AwsCredentials {
+    pub access_key_id: String,
+    secret_access_key: String,
+    pub session_token: Option<String>,
+}
+
+impl fmt::Debug for AwsCredentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AwsCredentials")
+            .field("access_key_id", &self.access_key_id)
+            .field("secret_access_key", &"***")
+            .field("session_token", &self.session_token.as_ref().map(|_| "***"))
+            .finish()
+    }
+}
+
+impl AwsCredentials {
+    pub fn new(access_key_id: &str, secret_access_key: &str, session_token: Option<&str>) -> Self {
+        AwsCredentials {
+            access_key_id: access_key_id.to_string(),
+            secret_access_key: secret_access_key.to_string(),
+            session_token: session_token.map(str::to_string),
+        }
+    }
+
+    /// The key from --access-key-id and --secret-access-key, or failing
+    /// them the standard AWS chain: `AWS_ACCESS_KEY_ID`,
+    /// `AWS_SECRET_ACCESS_KEY` and `AWS_SESSION_TOKEN`, then the profile's
+    /// section of the shared credentials file.
+    pub fn resolve(args: &RemoteArgs) -> Result<Self, String> {
+        match (&args.access_key_id, &args.secret_access_key) {
+            (Some(id), Some(secret)) => return Ok(AwsCredentials::new(id, secret, None)),
+            (None, None) => {}
+            _ => {
+                return Err(
+                    "--access-key-id and --secret-access-key have to be given together".into(),
+                )
+            }
+        }
+        if let (Some(id), Some(secret)) = (var("AWS_ACCESS_KEY_ID"), var("AWS_SECRET_ACCESS_KEY")) {
+            return Ok(AwsCredentials::new(
+                &id,
+                &secret,
+                var("AWS_SESSION_TOKEN").as_deref(),
+            ));
+        }
+        let profile = profile(args);
+        let path = aws_file("AWS_SHARED_CREDENTIALS_FILE", "credentials");
+        let section = path
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|text| ini_section(&text, &profile));
+        let value = |name: &str| {
+            section
+                .as_ref()
+                .and_then(|section| section.iter().find(|(key, _)| key == name))
+                .map(|(_, value)| value.clone())
+        };
+        match (value("aws_access_key_id"), value("aws_secret_access_key")) {
+            (Some(id), Some(secret)) => Ok(AwsCredentials::new(
+                &id,
+                &secret,
+                value("aws_session_token").as_deref(),
+            )),
+            _ => Err(format!(
+                "no AWS credentials: pass --access-key-id and --secret-access-key, set \
+                 AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY, or add a [{}] profile to \
+                 ~/.aws/credentials",
+                profile
+            )),
+        }
+    }
+}
+
+/// The region from --region, `AWS_REGION`, `AWS_DEFAULT_REGION` or the
+/// profile in the AWS config file, in that order.
+pub fn resolve_region(args: &RemoteArgs) -> String {
+    if let Some(region) = args
+        .region
+        .clone()
+        .or_else(|| var("AWS_REGION"))
+        .or_else(|| var("AWS_DEFAULT_REGION"))
+    {
+        return region;
+    }
+    let profile = profile(args);
+    // The config file names every profile but the default one that way
+    let section = if profile == "default" {
+        profile
+    } else {
+        format!("profile {}", profile)
+    };
+    aws_file("AWS_CONFIG_FILE", "config")
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|text| ini_section(&text, &section))
+        .and_then(|section| {
+            section
+                .into_iter()
+                .find(|(key, _)| key == "region")
+                .map(|(_, value)| value)
+        })
+        .unwrap_or_else(|| DEFAULT_REGION.to_string())
+}
+
+fn profile(args: &RemoteArgs) -> String {
+    args.profile
+        .clone()
+        .or_else(|| var("AWS_PROFILE"))
+        .unwrap_or_else(|| "default".to_string())
+}
+
+fn var(name: &str) -> Option<String> {
+    env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+/// The file an AWS variable points at, or `~/.aws/<name>`.
+fn aws_file(variable: &str, name: &str) -> Option<PathBuf> {
+    if let Some(path) = env::var_os(variable) {
+        return Some(PathBuf::from(path));
+    }
+    let home = env::var_os("HOME").or_else(|| env::var_os("USERPROFILE"))?;
+    Some(PathBuf::from(home).join(".aws").join(name))
+}
+
+/// The keys and values in one `[section]` of an INI file.
+fn ini_section(text: &str, name: &str) -> Option<Vec<(String, String)>> {
+    let mut found = None;
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if let Some(header) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            if found.is_some() {
+                break;
+            }
+            if header.trim() == name {
+                found = Some(Vec::new());
+            }
+            continue;
+        }
+        if let (Some(section), Some((key, value))) = (&mut found, line.split_once('=')) {
+            section.push((key.trim().to_string(), value.trim().to_string()));
+        }
+    }
+    found
+}
+
+/// Just enough of an S3 client to read and write whole objects, signing
+/// each request with AWS Signature Version 4. Requests that fail in a way
+/// worth trying again (no answer, a 429 or a server error) are retried
+/// with backoff.
+#[derive(Debug, Clone)]
+pub struct S3Client {
+    http: Client,
+    location: RemoteLocation,
+    /// `--endpoint`, for S3-compatible services; buckets are then
+    /// addressed by path rather than by host name
+    endpoint: Option<Url>,
+    region: String,
+    credentials: AwsCredentials,
+    retries: u32,
+}
+
+impl S3Client {
+    pub fn new(args: &RemoteArgs) -> Result<Self, Box<dyn Error>> {
+        let endpoint = match &args.endpoint {
+            Some(endpoint) => Some(Url::parse(endpoint.trim_end_matches('/'))?),
+            None => None,
+        };
+        Ok(S3Client {
+            http: Client::builder()
+                .connect_timeout(Duration::from_secs(10))
+                .timeout(Duration::from_secs(300))
+                .build()?,
+            location: RemoteLocation::parse(&args.remote)?,
+            endpoint,
+            region: resolve_region(args),
+            credentials: AwsCredentials::resolve(args)?,
+            retries: args.retries,
+        })
+    }
+
+    pub fn location(&self) -> &RemoteLocation {
+        &self.location
+    }
+
+    /// The object `name` under the prefix, or `None` if there isn't one.
+    pub async fn get(&self, name: &str) -> Result<Option<Vec<u8>>, Box<dyn Error + Send + Sync>> {
+        let response = self.send(Method::GET, name, Vec::new()).await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let response = fail_unless_success(response).await?;
+        Ok(Some(response.bytes().await?.to_vec()))
+    }
+
+    /// Store `body` as the object `name` under the prefix.
+    pub async fn put(&self, name: &str, body: Vec<u8>) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let response = self.send(Method::PUT, name, body).await?;
+        fail_unless_success(response).await?;
+        Ok(())
+    }
+
+    async fn send(
+        &self,
+        method: Method,
+        name: &str,
+        body: Vec<u8>,
+    ) -> Result<reqwest::Response, Box<dyn Error + Send + Sync>> {
+        let url = self.url(name)?;
+        let payload_hash = if body.is_empty() {
+            EMPTY_SHA256.to_string()
+        } else {
+            format!("{:x}", Sha256::digest(&body))
+        };
+        let mut attempt = 0;
+        loop {
+            let now = Utc::now();
+            let mut headers = vec![
+                ("x-amz-content-sha256".to_string(), payload_hash.clone()),
+                ("x-amz-date".to_string(), amz_date(now)),
+            ];
+            if let Some(token) = &self.credentials.session_token {
+                headers.push(("x-amz-security-token".to_string(), token.clone()));
+            }
+            let authorization = sign(
+                method.as_str(),
+                &url,
+                &headers,
+                &payload_hash,
+                &self.region,
+                &self.credentials,
+                now,
+            );
+            let mut request = self
+                .http
+                .request(method.clone(), url.clone())
+                .header("authorization", authorization)
+                .body(body.clone());
+            for (name, value) in &headers {
+                request = request.header(name.as_str(), value.as_str());
+            }
+            let result = request.send().await;
+            let transient = match &result {
+                Ok(response) => {
+                    response.status() == StatusCode::TOO_MANY_REQUESTS
+                        || response.status().is_server_error()
+                }
+                Err(_) => true,
+            };
+            if !transient || attempt >= self.retries {
+                return Ok(result?);
+            }
+            attempt += 1;
+            let wait = Duration::from_millis(500 << attempt.min(6));
+            match &result {
+                Ok(response) => {
+                    warn!(status = response.status().as_u16(), %url, "retrying in {:?}", wait)
+                }
+                Err(e) => warn!(error = %e, %url, "retrying in {:?}", wait),
+            }
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Path-style with an endpoint, as S3-compatible services expect, and
+    /// virtual-hosted on AWS itself.
+    fn url(&self, name: &str) -> Result<Url, Box<dyn Error + Send + Sync>> {
+        let key = uri_encode(&self.location.key(name), false);
+        let url = match &self.endpoint {
+            Some(endpoint) => format!(
+                "{}/{}/{}",
+                endpoint.as_str().trim_end_matches('/'),
+                uri_encode(&self.location.bucket, true),
+                key
+            ),
+            None => format!(
+                "https://{}.s3.{}.amazonaws.com/{}",
+                self.location.bucket, self.region, key
+            ),
+        };
+        Ok(Url::parse(&url)?)
+    }
+}
+
+async fn fail_unless_success(
+    response: reqwest::Response,
+) -> Result<reqwest::Response, Box<dyn Error + Send + Sync>> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let url = response.url().clone();
+    let body = response.text().await.unwrap_or_default();
+    debug!(%url, %body, "S3 error");
+    // S3 explains itself in a <Code> element
+    let code = body
+        .split_once("<Code>")
+        .and_then(|(_, rest)| rest.split_once("</Code>"))
+        .map_or("", |(code, _)| code);
+    Err(
+        format!("{} answered HTTP {} {}", url, status.as_u16(), code)
+            .trim_end()
+            .into(),
+    )
+}
+
+fn amz_date(now: DateTime<Utc>) -> String {
+    now.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+/// The `Authorization` header for a request, by AWS Signature Version 4.
+/// `headers` are signed along with `host`, which is taken from `url`.
+pub fn sign(
+    method: &str,
+    url: &Url,
+    headers: &[(String, String)],
+    payload_hash: &str,
+    region: &str,
+    credentials: &AwsCredentials,
+    now: DateTime<Utc>,
+) -> String {
+    let host = match url.port() {
+        Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+        None => url.host_str().unwrap_or_default().to_string(),
+    };
+    let mut signed: Vec<(String, String)> = headers
+        .iter()
+        .map(|(name, value)| (name.to_lowercase(), value.trim().to_string()))
+        .chain([("host".to_string(), host)])
+        .collect();
+    signed.sort();
+    let canonical_headers: String = signed
+        .iter()
+        .map(|(name, value)| format!("{}:{}\n", name, value))
+        .collect();
+    let signed_headers = signed
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect::<Vec<_>>()
+        .join(";");
+
+    let mut query: Vec<(String, String)> = url
+        .query_pairs()
+        .map(|(key, value)| (uri_encode(&key, true), uri_encode(&value, true)))
+        .collect();
+    query.sort();
+    let canonical_query = query
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect::<Vec<_>>()
+        .join("&");
+
+    let canonical_request = format!(
+        "{}\n{}\n{}\n{}\n{}\n{}",
+        method,
+        url.path(),
+        canonical_query,
+        canonical_headers,
+        signed_headers,
+        payload_hash
+    );
+    let date = now.format("%Y%m%d").to_string();
+    let scope = format!("{}/{}/s3/aws4_request", date, region);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{:x}",
+        amz_date(now),
+        scope,
+        Sha256::digest(canonical_request.as_bytes())
+    );
+
+    let key = [date.as_str(), region, "s3", "aws4_request"].iter().fold(
+        format!("AWS4{}", credentials.secret_access_key).into_bytes(),
+        |key, part| hmac(&key, part.as_bytes()),
+    );
+    let signature: String = hmac(&key, string_to_sign.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    format!(
+        "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+        credentials.access_key_id, scope, signed_headers, signature
+    )
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes any key length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Percent-encode everything but the characters S3 leaves alone, and `/`
+/// too unless `slash` says otherwise.
+fn uri_encode(text: &str, slash: bool) -> String {
+    let mut encoded = String::new();
+    for byte in text.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(byte as char)
+            }
+            b'/' if !slash => encoded.push('/'),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
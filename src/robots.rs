@@ -0,0 +1,168 @@
+use reqwest::{Client, StatusCode};
+use std::error::Error;
+use std::fmt;
+
+/// Product token robots.txt groups can name to address beerscape
+pub const ROBOTS_AGENT: &str = "beerscape";
+
+/// One `Allow` or `Disallow` line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rule {
+    pub allow: bool,
+    /// Path prefix, where `*` matches any run of characters and a final `$`
+    /// anchors the end
+    pub path: String,
+}
+
+impl Rule {
+    fn matches(&self, path: &str) -> bool {
+        let (pattern, anchored) = match self.path.strip_suffix('$') {
+            Some(pattern) => (pattern, true),
+            None => (self.path.as_str(), false),
+        };
+        let mut parts = pattern.split('*');
+        let Some(mut rest) = parts.next().and_then(|first| path.strip_prefix(first)) else {
+            return false;
+        };
+        let parts: Vec<&str> = parts.collect();
+        for (i, part) in parts.iter().enumerate() {
+            // The last part of an anchored pattern has to end the path
+            if anchored && i == parts.len() - 1 {
+                return rest.ends_with(part);
+            }
+            match rest.find(part) {
+                Some(at) => rest = &rest[at + part.len()..],
+                None => return false,
+            }
+        }
+        !anchored || rest.is_empty()
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let field = if self.allow { "Allow" } else { "Disallow" };
+        write!(f, "{}: {}", field, self.path)
+    }
+}
+
+/// The rules a robots.txt gives one set of user agents.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Group {
+    /// User-agent values, lowercased
+    pub agents: Vec<String>,
+    pub rules: Vec<Rule>,
+    /// Seconds to leave between requests
+    pub crawl_delay: Option<f64>,
+}
+
+/// A parsed robots.txt.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Robots {
+    pub groups: Vec<Group>,
+}
+
+impl Robots {
+    /// Read a robots.txt, ignoring lines it can't make sense of. Consecutive
+    /// `User-agent` lines share the rules that follow them.
+    pub fn parse(text: &str) -> Self {
+        let mut robots = Robots::default();
+        let mut in_agents = false;
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or_default();
+            let Some((field, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+            match field.trim().to_ascii_lowercase().as_str() {
+                "user-agent" => {
+                    if !in_agents {
+                        robots.groups.push(Group::default());
+                        in_agents = true;
+                    }
+                    let group = robots.groups.last_mut().expect("just pushed");
+                    group.agents.push(value.to_ascii_lowercase());
+                }
+                field => {
+                    in_agents = false;
+                    // Rules before any User-agent line belong to nobody
+                    let Some(group) = robots.groups.last_mut() else {
+                        continue;
+                    };
+                    match field {
+                        // An empty Disallow allows everything
+                        "allow" | "disallow" if !value.is_empty() => group.rules.push(Rule {
+                            allow: field == "allow",
+                            path: value.to_string(),
+                        }),
+                        "crawl-delay" => {
+                            group.crawl_delay = value
+                                .parse::<f64>()
+                                .ok()
+                                .filter(|d| d.is_finite() && *d >= 0.0)
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+        robots
+    }
+
+    /// The groups that apply to `agent`: the ones naming it if there are
+    /// any, otherwise the `*` ones.
+    pub fn groups_for(&self, agent: &str) -> Vec<&Group> {
+        let agent = agent.to_ascii_lowercase();
+        let named: Vec<&Group> = self
+            .groups
+            .iter()
+            .filter(|g| g.agents.contains(&agent))
+            .collect();
+        if !named.is_empty() {
+            return named;
+        }
+        self.groups
+            .iter()
+            .filter(|g| g.agents.iter().any(|a| a == "*"))
+            .collect()
+    }
+
+    /// The rule that keeps `agent` away from `path`, if one does. The
+    /// longest matching rule wins, and `Allow` wins a tie.
+    pub fn disallowing(&self, agent: &str, path: &str) -> Option<&Rule> {
+        self.groups_for(agent)
+            .into_iter()
+            .flat_map(|g| &g.rules)
+            .filter(|rule| rule.matches(path))
+            .max_by_key(|rule| (rule.path.len(), rule.allow))
+            .filter(|rule| !rule.allow)
+    }
+
+    /// The longest crawl delay the groups for `agent` ask for.
+    pub fn crawl_delay(&self, agent: &str) -> Option<f64> {
+        self.groups_for(agent)
+            .into_iter()
+            .filter_map(|g| g.crawl_delay)
+            .reduce(f64::max)
+    }
+}
+
+/// Fetch and parse `<base_url>/robots.txt`. A missing file (any 4xx) allows
+/// everything; a server error is an error, since the site's wishes can't be
+/// known.
+pub async fn fetch(client: &Client, base_url: &str) -> Result<Robots, Box<dyn Error>> {
+    let url = format!("{}/robots.txt", base_url);
+    let response = client
+        .get(&url)
+        .send()
+        .await
+        .map_err(|e| format!("{}: {}", url, e))?;
+    let status = response.status();
+    if status.is_client_error() && status != StatusCode::TOO_MANY_REQUESTS {
+        return Ok(Robots::default());
+    }
+    if !status.is_success() {
+        return Err(format!("{}: HTTP {}", url, status.as_u16()).into());
+    }
+    Ok(Robots::parse(&response.text().await?))
+}
@@ -0,0 +1,375 @@
+use crate::mirrors::MirrorStats;
+use crate::preflight::Preflight;
+use crate::sampling::BucketStats;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Where `--report` writes run reports and `beerscape reports` reads them.
+pub const REPORTS_DIR: &str = ".beerscape/reports";
+
+/// Where `--report` writes reports of dry runs, kept apart so they don't
+/// skew the history of real ones.
+pub const PROBES_DIR: &str = ".beerscape/probes";
+
+/// Settings a download run was started with.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunConfig {
+    pub base_url: String,
+    pub output_dir: PathBuf,
+    pub target: usize,
+    pub concurrency: usize,
+    pub timeout_secs: u64,
+    /// 0 when there was no limit
+    pub daily_quota: usize,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LatencyPercentiles {
+    pub p50_ms: u64,
+    pub p90_ms: u64,
+    pub p99_ms: u64,
+    pub max_ms: u64,
+}
+
+impl fmt::Display for LatencyPercentiles {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "p50 {} ms, p90 {} ms, p99 {} ms",
+            self.p50_ms, self.p90_ms, self.p99_ms
+        )
+    }
+}
+
+// Buckets per doubling of latency in a LatencyHistogram, a power of two
+const SUB_BUCKETS: u64 = 32;
+
+/// Latencies counted into buckets by the millisecond: exact under 64 ms
+/// and to within about 3% above, so recording one is an increment and a
+/// run of any length takes the same few kilobytes.
+#[derive(Debug, Clone, Default)]
+pub struct LatencyHistogram {
+    counts: Vec<u64>,
+    total: u64,
+    max_ms: u64,
+}
+
+impl LatencyHistogram {
+    pub fn record(&mut self, latency: Duration) {
+        let ms = latency.as_millis() as u64;
+        let bucket = bucket_of(ms);
+        if self.counts.len() <= bucket {
+            self.counts.resize(bucket + 1, 0);
+        }
+        self.counts[bucket] += 1;
+        self.total += 1;
+        self.max_ms = self.max_ms.max(ms);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.total
+    }
+
+    /// The latency `p` percent of those recorded came in at or under,
+    /// taken as the top of its bucket; 0 if none were recorded.
+    pub fn percentile_ms(&self, p: f64) -> u64 {
+        if self.total == 0 {
+            return 0;
+        }
+        let rank = ((p / 100.0) * (self.total - 1) as f64).round() as u64;
+        let mut seen = 0;
+        for (bucket, &count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen > rank {
+                return bucket_top(bucket).min(self.max_ms);
+            }
+        }
+        self.max_ms
+    }
+
+    pub fn percentiles(&self) -> LatencyPercentiles {
+        LatencyPercentiles {
+            p50_ms: self.percentile_ms(50.0),
+            p90_ms: self.percentile_ms(90.0),
+            p99_ms: self.percentile_ms(99.0),
+            max_ms: self.max_ms,
+        }
+    }
+}
+
+fn bucket_of(ms: u64) -> usize {
+    if ms < 2 * SUB_BUCKETS {
+        return ms as usize;
+    }
+    // Keeps the top six bits, so each doubling gets SUB_BUCKETS buckets
+    let shift = (63 - ms.leading_zeros() as u64) - SUB_BUCKETS.trailing_zeros() as u64;
+    (shift * SUB_BUCKETS + (ms >> shift)) as usize
+}
+
+/// The largest latency counted in `bucket`.
+fn bucket_top(bucket: usize) -> u64 {
+    let bucket = bucket as u64;
+    if bucket < 2 * SUB_BUCKETS {
+        return bucket;
+    }
+    let shift = bucket / SUB_BUCKETS - 1;
+    ((bucket - shift * SUB_BUCKETS + 1) << shift) - 1
+}
+
+/// How long the recipe GETs of a run took, shared by its downloads: from
+/// sending each to its headers, and from then to the end of its body.
+#[derive(Debug, Default)]
+pub struct ResponseStats {
+    tally: Mutex<ResponseTally>,
+}
+
+#[derive(Debug, Default)]
+struct ResponseTally {
+    headers: LatencyHistogram,
+    body: LatencyHistogram,
+}
+
+impl ResponseStats {
+    /// Count a response whose headers arrived `elapsed` after the request
+    /// was sent.
+    pub fn headers(&self, elapsed: Duration) {
+        self.tally.lock().unwrap().headers.record(elapsed);
+    }
+
+    /// Count a body that took `elapsed` to read, or to break off.
+    pub fn body(&self, elapsed: Duration) {
+        self.tally.lock().unwrap().body.record(elapsed);
+    }
+
+    /// Fill in the report's phase latencies.
+    pub fn add_to(&self, report: &mut RunReport) {
+        let tally = self.tally.lock().unwrap();
+        report.header_latency = tally.headers.percentiles();
+        report.body_latency = tally.body.percentiles();
+    }
+}
+
+/// Everything about one download run, written as JSON at its end.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunReport {
+    pub config: RunConfig,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    pub finish_reason: String,
+    /// Recipes already on disk when the run started
+    pub existing: usize,
+    pub downloaded: usize,
+    pub attempted: usize,
+    /// Attempts by outcome: `saved` or a failure category
+    pub outcomes: BTreeMap<String, usize>,
+    pub bytes: u64,
+    /// Of every attempt, whatever came of it
+    pub latency: LatencyPercentiles,
+    /// Of the attempts that saved a recipe
+    #[serde(default)]
+    pub saved_latency: LatencyPercentiles,
+    /// Of the attempts that didn't
+    #[serde(default)]
+    pub failed_latency: LatencyPercentiles,
+    /// Attempts by the HTTP status they were answered with; 0 for those
+    /// never answered
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub status_codes: BTreeMap<u16, usize>,
+    /// From sending a GET to its headers arriving
+    #[serde(default)]
+    pub header_latency: LatencyPercentiles,
+    /// From a GET's headers arriving to the end of its body
+    #[serde(default)]
+    pub body_latency: LatencyPercentiles,
+    /// Downloads tried again after the login session was renewed
+    pub retries: usize,
+    /// Time spent paused, waiting for a new login session
+    pub throttle_secs: f64,
+    /// IDs that failed for good in this run
+    pub failed_ids: Vec<u32>,
+    /// Hit rates across the ID range, for runs that picked IDs at random
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub buckets: Vec<BucketStats>,
+    /// GETs skipped on the strength of a --precheck request, for runs that
+    /// made them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gets_avoided: Option<usize>,
+    /// Bytes received, including transfers that broke off
+    #[serde(default)]
+    pub transferred_bytes: u64,
+    /// Bytes of the recipes that arrived compressed, as received
+    #[serde(default)]
+    pub compressed_bytes: u64,
+    /// The same recipes' bytes decompressed
+    #[serde(default)]
+    pub decompressed_bytes: u64,
+    /// Mean size of the recipes saved, if any were
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub average_recipe_bytes: Option<u64>,
+    /// How each --base-url mirror did, for runs given more than one
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mirrors: Vec<MirrorStats>,
+    /// The check made before the run started, unless --skip-healthcheck
+    /// said not to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preflight: Option<Preflight>,
+}
+
+impl RunReport {
+    /// New downloads per attempt, or `None` if nothing was attempted.
+    pub fn success_rate(&self) -> Option<f64> {
+        (self.attempted > 0).then(|| self.downloaded as f64 / self.attempted as f64)
+    }
+
+    /// Latencies, for the end of a run.
+    pub fn latency_summary(&self) -> String {
+        let mut out = String::from("Latency Summary:\n----------------\n");
+        let lines = [
+            ("Saved Latency", &self.saved_latency),
+            ("Failed Latency", &self.failed_latency),
+            ("Time to Headers", &self.header_latency),
+            ("Time to Read Body", &self.body_latency),
+        ];
+        for (label, latency) in lines {
+            out.push_str(&format!("{}: {}\n", label, latency));
+        }
+        out.pop();
+        out
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        Ok(serde_json::from_slice(&fs::read(path)?)?)
+    }
+}
+
+/// The file `--report` writes to for a run started at `started_at`.
+pub fn default_report_path(started_at: DateTime<Utc>) -> PathBuf {
+    Path::new(REPORTS_DIR).join(format!("run-{}.json", started_at.format("%Y%m%d-%H%M%S")))
+}
+
+/// The file `--report` writes to for a dry run started at `started_at`.
+pub fn default_probe_path(started_at: DateTime<Utc>) -> PathBuf {
+    Path::new(PROBES_DIR).join(format!("probe-{}.json", started_at.format("%Y%m%d-%H%M%S")))
+}
+
+/// Collects the details of a download run as it goes, for its report.
+#[derive(Debug)]
+pub struct RunLog {
+    started_at: DateTime<Utc>,
+    outcomes: BTreeMap<String, usize>,
+    latency: LatencyHistogram,
+    saved_latency: LatencyHistogram,
+    failed_latency: LatencyHistogram,
+    bytes: u64,
+    retries: usize,
+    throttled: Duration,
+    failed_ids: Vec<u32>,
+    buckets: Vec<BucketStats>,
+}
+
+impl Default for RunLog {
+    fn default() -> Self {
+        RunLog {
+            started_at: Utc::now(),
+            outcomes: BTreeMap::new(),
+            latency: LatencyHistogram::default(),
+            saved_latency: LatencyHistogram::default(),
+            failed_latency: LatencyHistogram::default(),
+            bytes: 0,
+            retries: 0,
+            throttled: Duration::ZERO,
+            failed_ids: Vec::new(),
+            buckets: Vec::new(),
+        }
+    }
+}
+
+impl RunLog {
+    pub fn started_at(&self) -> DateTime<Utc> {
+        self.started_at
+    }
+
+    pub fn saved(&mut self, bytes: usize, latency: Duration) {
+        self.record("saved", latency);
+        self.saved_latency.record(latency);
+        self.bytes += bytes as u64;
+    }
+
+    pub fn failed(&mut self, id: u32, category: &str, latency: Duration) {
+        self.record(category, latency);
+        self.failed_latency.record(latency);
+        self.failed_ids.push(id);
+    }
+
+    pub fn retried(&mut self) {
+        self.retries += 1;
+    }
+
+    pub fn throttled(&mut self, paused: Duration) {
+        self.throttled += paused;
+    }
+
+    /// Record hit rates across the ID range for the report.
+    pub fn set_buckets(&mut self, buckets: &[BucketStats]) {
+        self.buckets = buckets.to_vec();
+    }
+
+    fn record(&mut self, outcome: &str, latency: Duration) {
+        *self.outcomes.entry(outcome.to_string()).or_default() += 1;
+        self.latency.record(latency);
+    }
+
+    pub fn finish(
+        mut self,
+        config: RunConfig,
+        finish_reason: &str,
+        existing: usize,
+        downloaded: usize,
+        attempted: usize,
+    ) -> RunReport {
+        self.failed_ids.sort_unstable();
+        RunReport {
+            config,
+            started_at: self.started_at,
+            finished_at: Utc::now(),
+            finish_reason: finish_reason.to_string(),
+            existing,
+            downloaded,
+            attempted,
+            outcomes: self.outcomes,
+            bytes: self.bytes,
+            latency: self.latency.percentiles(),
+            saved_latency: self.saved_latency.percentiles(),
+            failed_latency: self.failed_latency.percentiles(),
+            status_codes: BTreeMap::new(),
+            header_latency: LatencyPercentiles::default(),
+            body_latency: LatencyPercentiles::default(),
+            retries: self.retries,
+            throttle_secs: self.throttled.as_secs_f64(),
+            failed_ids: self.failed_ids,
+            buckets: self.buckets,
+            gets_avoided: None,
+            transferred_bytes: 0,
+            compressed_bytes: 0,
+            decompressed_bytes: 0,
+            average_recipe_bytes: (downloaded > 0).then(|| self.bytes / downloaded as u64),
+            mirrors: Vec::new(),
+            preflight: None,
+        }
+    }
+}
@@ -0,0 +1,204 @@
+use crate::cli::Strategy;
+use crate::ids::IdTracker;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::ops::RangeInclusive;
+
+/// Buckets the ID range is split into for hit rate tracking
+pub const BUCKETS: usize = 100;
+/// Share of adaptive picks made from a bucket chosen uniformly at random,
+/// so sparse buckets still get probed now and then, and of --since-id picks
+/// made over the whole range
+pub const DEFAULT_EXPLORATION: f64 = 0.1;
+/// Triangular picks that land on an attempted ID before one is taken
+/// from whatever is left past --since-id
+const SINCE_PICKS: usize = 8;
+/// Attempts' worth of weight the run's overall hit rate carries in each
+/// bucket's estimate, so a few lucky hits don't dominate
+const PRIOR_WEIGHT: f64 = 10.0;
+
+/// Attempts and hits in one slice of the ID range.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BucketStats {
+    pub start: u32,
+    pub end: u32,
+    pub attempts: usize,
+    pub hits: usize,
+    /// hits / attempts, or 0 before any attempt
+    pub hit_rate: f64,
+}
+
+/// Picks random IDs for a run, either uniformly over the range or weighted
+/// toward the buckets that have been turning up recipes, or toward the
+/// newest IDs past --since-id. Hit rates are tracked per bucket either way,
+/// for the run report.
+#[derive(Debug, Clone)]
+pub struct IdPicker {
+    strategy: Strategy,
+    exploration: f64,
+    since: Option<u32>,
+    buckets: Vec<BucketStats>,
+}
+
+impl IdPicker {
+    pub fn new(range: RangeInclusive<u32>, strategy: Strategy, exploration: f64) -> Self {
+        let (start, end) = range.into_inner();
+        let len = (end as u64 + 1).saturating_sub(start as u64);
+        let count = (BUCKETS as u64).min(len).max(1);
+        let buckets = (0..count)
+            .map(|b| BucketStats {
+                start: start + (len * b / count) as u32,
+                end: start + (len * (b + 1) / count).saturating_sub(1) as u32,
+                attempts: 0,
+                hits: 0,
+                hit_rate: 0.0,
+            })
+            .collect();
+        IdPicker {
+            strategy,
+            exploration: exploration.clamp(0.0, 1.0),
+            since: None,
+            buckets,
+        }
+    }
+
+    /// Favour IDs from `since` up, where newer recipes are.
+    pub fn with_since(mut self, since: Option<u32>) -> Self {
+        self.since = since;
+        self
+    }
+
+    pub fn strategy(&self) -> Strategy {
+        self.strategy
+    }
+
+    /// Pick an ID `tracker` hasn't seen and mark it attempted, or `None`
+    /// once its whole range has been.
+    pub fn next(&self, tracker: &mut IdTracker, rng: &mut impl Rng) -> Option<u32> {
+        if let Some(since) = self.since {
+            return self.next_since(since, tracker, rng);
+        }
+        if self.strategy == Strategy::Uniform {
+            return tracker.next(rng);
+        }
+        let (hits, attempts) = self
+            .buckets
+            .iter()
+            .fold((0, 0), |(h, a), b| (h + b.hits, a + b.attempts));
+        // Laplace smoothing keeps the overall rate above zero, so no
+        // bucket is ruled out before it's tried
+        let overall = (hits as f64 + 1.0) / (attempts as f64 + 2.0);
+        let open: Vec<(usize, f64)> = self
+            .buckets
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| tracker.remaining_in(b.start..=b.end) > 0)
+            .map(|(i, b)| (i, estimate(b, overall)))
+            .collect();
+        if open.is_empty() {
+            return None;
+        }
+
+        let total: f64 = open.iter().map(|(_, weight)| weight).sum();
+        let chosen = if rng.gen_bool(self.exploration) || total <= 0.0 {
+            open[rng.gen_range(0..open.len())].0
+        } else {
+            let mut point = rng.gen_range(0.0..total);
+            open.iter()
+                .find(|(_, weight)| {
+                    point -= weight;
+                    point < 0.0
+                })
+                .unwrap_or(&open[open.len() - 1])
+                .0
+        };
+        let bucket = &self.buckets[chosen];
+        tracker.next_in(bucket.start..=bucket.end, rng)
+    }
+
+    /// Most picks from a triangular distribution over `since` to the end of
+    /// the range, rising to its peak at the end; the exploration share, and
+    /// every pick once nothing past `since` is left, uniform over the whole
+    /// range.
+    fn next_since(&self, since: u32, tracker: &mut IdTracker, rng: &mut impl Rng) -> Option<u32> {
+        let (_, end) = self.range();
+        if since <= end && !rng.gen_bool(self.exploration) {
+            for _ in 0..SINCE_PICKS {
+                let id = triangular(since, end, rng);
+                if tracker.insert(id) {
+                    return Some(id);
+                }
+            }
+            if let Some(id) = tracker.next_in(since..=end, rng) {
+                return Some(id);
+            }
+        }
+        tracker.next(rng)
+    }
+
+    /// How IDs are picked, for --verbose.
+    pub fn distribution(&self) -> String {
+        let (start, end) = self.range();
+        let exploration = self.exploration * 100.0;
+        match (self.since, self.strategy) {
+            (Some(since), _) => format!(
+                "{:.0}% from a triangular distribution over {}-{} peaking at {}, {:.0}% uniformly over {}-{}",
+                100.0 - exploration,
+                since,
+                end,
+                end,
+                exploration,
+                start,
+                end
+            ),
+            (None, Strategy::Uniform) => format!("uniformly over {}-{}", start, end),
+            (None, Strategy::Adaptive) => format!(
+                "{:.0}% weighted by the hit rates of {} buckets, {:.0}% from a bucket chosen uniformly, over {}-{}",
+                100.0 - exploration,
+                self.buckets.len(),
+                exploration,
+                start,
+                end
+            ),
+        }
+    }
+
+    fn range(&self) -> (u32, u32) {
+        (
+            self.buckets.first().map_or(0, |b| b.start),
+            self.buckets.last().map_or(0, |b| b.end),
+        )
+    }
+
+    /// Count an attempt at `id` toward its bucket.
+    pub fn record(&mut self, id: u32, hit: bool) {
+        let Some(bucket) = self
+            .buckets
+            .iter_mut()
+            .find(|b| (b.start..=b.end).contains(&id))
+        else {
+            return;
+        };
+        bucket.attempts += 1;
+        bucket.hits += hit as usize;
+        bucket.hit_rate = bucket.hits as f64 / bucket.attempts as f64;
+    }
+
+    pub fn buckets(&self) -> &[BucketStats] {
+        &self.buckets
+    }
+}
+
+/// An ID from `lo` to `hi` inclusive, more likely the nearer it is to `hi`:
+/// the density rises in a straight line from nothing at `lo`.
+pub fn triangular(lo: u32, hi: u32, rng: &mut impl Rng) -> u32 {
+    let span = (hi - lo) as f64 + 1.0;
+    let offset = (span * rng.gen::<f64>().sqrt()).floor() as u32;
+    lo.saturating_add(offset).min(hi)
+}
+
+/// A bucket's hit rate, pulled toward the `overall` rate until it has
+/// attempts of its own.
+fn estimate(bucket: &BucketStats, overall: f64) -> f64 {
+    (bucket.hits as f64 + PRIOR_WEIGHT * overall) / (bucket.attempts as f64 + PRIOR_WEIGHT)
+}
@@ -0,0 +1,148 @@
+use crate::cli::StorageLayout;
+use crate::collection::write_atomic;
+use chrono::{DateTime, Local, NaiveDate, NaiveTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+/// Name of the state file inside the recipes directory.
+pub const STATE_FILE: &str = ".beerscape_state.json";
+
+/// Version of the state file layout this build writes. Older files are
+/// brought up to date by `beerscape migrate`.
+pub const STATE_SCHEMA_VERSION: u32 = 1;
+
+/// Download progress that has to survive between runs.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct State {
+    /// Files from before versioning have none, and read as version 0
+    #[serde(default)]
+    pub schema_version: u32,
+    pub daily_quota: DailyQuota,
+    /// Random IDs to try again after the login session expired, before any
+    /// new ones; kept here so a run that stops first leaves them for the
+    /// next
+    pub retry_ids: Vec<u32>,
+    /// How far sequential runs have got through each range they walk
+    pub cursors: Vec<ScanCursor>,
+    /// How the recipes directory is arranged, so every command looks for
+    /// files in the right places
+    pub storage_layout: StorageLayout,
+    /// When the last archive of the whole collection started, which
+    /// `archive --incremental` carries on from
+    pub last_archive: Option<DateTime<Utc>>,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        State {
+            schema_version: STATE_SCHEMA_VERSION,
+            daily_quota: DailyQuota::default(),
+            retry_ids: Vec::new(),
+            cursors: Vec::new(),
+            storage_layout: StorageLayout::Flat,
+            last_archive: None,
+        }
+    }
+}
+
+impl State {
+    /// Read the state from `dir`, starting fresh if there isn't one yet.
+    pub fn load(dir: &Path) -> Result<Self, Box<dyn Error>> {
+        match fs::read(dir.join(STATE_FILE)) {
+            Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(State::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Write the state back to `dir`, replacing the old file in one step.
+    pub fn save(&self, dir: &Path) -> Result<(), Box<dyn Error>> {
+        write_atomic(&dir.join(STATE_FILE), &serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// The cursor for a sequential run over `min_id..=max_id`, starting one
+    /// at `min_id` if no run has walked that range before.
+    pub fn cursor(&mut self, min_id: u32, max_id: u32) -> &mut ScanCursor {
+        let at = match self
+            .cursors
+            .iter()
+            .position(|c| c.min_id == min_id && c.max_id == max_id)
+        {
+            Some(at) => at,
+            None => {
+                self.cursors.push(ScanCursor {
+                    min_id,
+                    max_id,
+                    next_id: min_id.into(),
+                });
+                self.cursors.len() - 1
+            }
+        };
+        &mut self.cursors[at]
+    }
+}
+
+/// Position of a sequential run within its range.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScanCursor {
+    pub min_id: u32,
+    pub max_id: u32,
+    /// First ID not yet tried; past `max_id` once the range is done
+    pub next_id: u64,
+}
+
+impl ScanCursor {
+    pub fn is_finished(&self) -> bool {
+        self.next_id > self.max_id.into()
+    }
+}
+
+/// Cap on the number of recipes downloaded per calendar day (local time).
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct DailyQuota {
+    /// Zero means no limit
+    pub max_per_day: usize,
+    pub count_today: usize,
+    pub quota_date: NaiveDate,
+}
+
+impl DailyQuota {
+    /// Start a new count if the last one was for an earlier day.
+    pub fn roll_over(&mut self) {
+        let today = Local::now().date_naive();
+        if self.quota_date != today {
+            self.quota_date = today;
+            self.count_today = 0;
+        }
+    }
+
+    /// Downloads still allowed today, or `None` when there is no limit.
+    pub fn remaining(&self) -> Option<usize> {
+        if self.max_per_day == 0 {
+            None
+        } else {
+            Some(self.max_per_day.saturating_sub(self.count_today))
+        }
+    }
+
+    pub fn is_exhausted(&self) -> bool {
+        self.remaining() == Some(0)
+    }
+}
+
+/// Time left until the quota resets at local midnight.
+pub fn until_midnight() -> Duration {
+    let now = Local::now().naive_local();
+    let midnight = now
+        .date()
+        .succ_opt()
+        .unwrap_or(now.date())
+        .and_time(NaiveTime::MIN);
+    (midnight - now).to_std().unwrap_or_default()
+}
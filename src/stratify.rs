@@ -0,0 +1,246 @@
+use crate::cli::DownloadArgs;
+use crate::download::{attempt, batch_delay, DownloadConfig, Outcome, CONCURRENT_REQUESTS};
+use crate::ids::IdTracker;
+use crate::sampling::IdPicker;
+use reqwest::Client;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use tracing::{info, warn};
+
+/// Recipes the reconnaissance pass parses unless --sample-probe says otherwise
+pub const DEFAULT_SAMPLE_PROBE: usize = 1000;
+/// Headroom each style's cap gets over its share of the probe
+pub const DEFAULT_OVERSAMPLE: f64 = 1.2;
+/// Recipes in a row turned away for a full style after which the styles
+/// still short are taken to have run out
+pub const STRATIFY_PATIENCE: usize = 200;
+
+/// How many recipes of each style a stratified sample may take, from the
+/// style mix a reconnaissance pass found.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Plan {
+    pub sample: usize,
+    /// Recipes the pass found
+    pub probed: usize,
+    /// Most common first
+    pub styles: Vec<StyleCap>,
+    /// Cap for a style the pass didn't see, as if it had turned up once
+    pub unseen_cap: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StyleCap {
+    /// Style name; empty for recipes without one
+    pub style: String,
+    /// Recipes of the style the pass found
+    pub probed: usize,
+    pub cap: usize,
+}
+
+impl Plan {
+    /// Cap each style at `ceil(sample * share * oversample)`, where share
+    /// is the style's fraction of the recipes counted in `probed`.
+    pub fn new(sample: usize, probed: &HashMap<String, usize>, oversample: f64) -> Self {
+        let total: usize = probed.values().sum();
+        let cap = |count: usize| {
+            if total == 0 {
+                return sample;
+            }
+            (sample as f64 * count as f64 / total as f64 * oversample).ceil() as usize
+        };
+        let mut styles: Vec<StyleCap> = probed
+            .iter()
+            .map(|(style, &count)| StyleCap {
+                style: style.clone(),
+                probed: count,
+                cap: cap(count),
+            })
+            .collect();
+        styles.sort_by(|a, b| b.probed.cmp(&a.probed).then_with(|| a.style.cmp(&b.style)));
+        Plan {
+            sample,
+            probed: total,
+            styles,
+            unseen_cap: cap(1),
+        }
+    }
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Stratification Plan:")?;
+        writeln!(f, "--------------------")?;
+        writeln!(f, "Sample Size: {}", self.sample)?;
+        writeln!(f, "Recipes Probed: {}", self.probed)?;
+        for style in &self.styles {
+            let name = if style.style.is_empty() {
+                "(no style)"
+            } else {
+                &style.style
+            };
+            writeln!(
+                f,
+                "  {:<36} {:>5.1}%  up to {}",
+                name,
+                style.probed as f64 / self.probed as f64 * 100.0,
+                style.cap
+            )?;
+        }
+        write!(f, "Styles Not Probed: up to {} each", self.unseen_cap)
+    }
+}
+
+/// Which recipes a stratified run keeps, shared by its downloads. Each
+/// style is kept until it reaches its cap; once [`STRATIFY_PATIENCE`]
+/// recipes in a row are turned away, the styles still short are taken to
+/// have run out and the caps are lifted, so the sample still fills up.
+#[derive(Debug)]
+pub struct StyleQuota {
+    /// `None` counts every style without turning any away
+    caps: Option<(HashMap<String, usize>, usize)>,
+    state: Mutex<QuotaState>,
+}
+
+#[derive(Debug, Default)]
+struct QuotaState {
+    kept: HashMap<String, usize>,
+    turned_away: usize,
+    lifted: bool,
+}
+
+impl StyleQuota {
+    /// A quota that keeps everything, for counting styles.
+    pub fn survey() -> Self {
+        StyleQuota {
+            caps: None,
+            state: Mutex::default(),
+        }
+    }
+
+    pub fn capped(plan: &Plan) -> Self {
+        let caps = plan
+            .styles
+            .iter()
+            .map(|style| (style.style.clone(), style.cap))
+            .collect();
+        StyleQuota {
+            caps: Some((caps, plan.unseen_cap)),
+            state: Mutex::default(),
+        }
+    }
+
+    /// Whether a recipe of `style` may be kept, counting it if so.
+    pub fn admit(&self, style: &str) -> bool {
+        let style = style.trim();
+        let mut state = self.state.lock().unwrap();
+        if let Some((caps, unseen_cap)) = &self.caps {
+            let cap = caps.get(style).copied().unwrap_or(*unseen_cap);
+            let kept = state.kept.get(style).copied().unwrap_or(0);
+            if kept >= cap && !state.lifted {
+                state.turned_away += 1;
+                if state.turned_away < STRATIFY_PATIENCE {
+                    return false;
+                }
+                warn!(
+                    "The last {} recipes were all of styles at their cap; lifting the caps to fill the sample",
+                    STRATIFY_PATIENCE
+                );
+                state.lifted = true;
+            } else {
+                state.turned_away = 0;
+            }
+        }
+        *state.kept.entry(style.to_string()).or_default() += 1;
+        true
+    }
+
+    /// Recipes kept so far, by style.
+    pub fn kept(&self) -> HashMap<String, usize> {
+        self.state.lock().unwrap().kept.clone()
+    }
+
+    /// Whether the caps had to be lifted.
+    pub fn lifted(&self) -> bool {
+        self.state.lock().unwrap().lifted
+    }
+
+    /// What the run kept of each style, most first.
+    pub fn summary(&self) -> String {
+        let mut kept: Vec<(String, usize)> = self.kept().into_iter().collect();
+        kept.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        let mut out = String::from("Sample by Style:\n----------------\n");
+        for (style, count) in kept {
+            let name = if style.is_empty() {
+                "(no style)"
+            } else {
+                &style
+            };
+            out.push_str(&format!("  {:<36} {:>6}\n", name, count));
+        }
+        let lifted = if self.lifted() { "yes" } else { "no" };
+        out.push_str(&format!("Caps Lifted: {}", lifted));
+        out
+    }
+}
+
+/// The reconnaissance pass: fetch random IDs the way a run would, keeping
+/// nothing, until `--sample-probe` recipes have turned up or the range runs
+/// out, and count the styles among them.
+pub async fn survey(
+    args: &DownloadArgs,
+    config: &DownloadConfig,
+    client: &Client,
+) -> Result<HashMap<String, usize>, Box<dyn Error>> {
+    let quota = Arc::new(StyleQuota::survey());
+    let config = DownloadConfig {
+        dry_run: true,
+        style_quota: Some(quota.clone()),
+        ..config.clone()
+    };
+    info!(
+        "Probing for up to {} recipes to plan the sample; none of them are kept",
+        args.sample_probe
+    );
+    let mut tracker = IdTracker::new(args.min_id..=args.max_id);
+    let mut picker = IdPicker::new(args.min_id..=args.max_id, args.strategy, args.exploration);
+    let mut rng = rand::thread_rng();
+    let mut found = 0;
+    while found < args.sample_probe {
+        let mut batch = vec![];
+        while batch.len() < CONCURRENT_REQUESTS {
+            match picker.next(&mut tracker, &mut rng) {
+                Some(id) => batch.push(id),
+                None => break,
+            }
+        }
+        if batch.is_empty() {
+            break;
+        }
+        let tasks: Vec<_> = batch
+            .into_iter()
+            .map(|id| {
+                let client = client.clone();
+                let config = config.clone();
+                tokio::spawn(async move { (id, attempt(&client, &config, id).await) })
+            })
+            .collect();
+        for task in tasks {
+            match task.await? {
+                (id, Outcome::Saved(_)) => {
+                    found += 1;
+                    picker.record(id, true);
+                }
+                (id, Outcome::Failed { .. }) => picker.record(id, false),
+                (_, Outcome::SessionExpired) => {
+                    return Err("the server requires a login session; pass --cookies".into())
+                }
+                // Left unrecorded, so it can come up again
+                (_, Outcome::RateLimited { .. }) => {}
+            }
+        }
+        tokio::time::sleep(batch_delay(&config.requests)).await;
+    }
+    Ok(quota.kept())
+}
@@ -0,0 +1,297 @@
+use crate::bsmx::Style;
+use strsim::jaro_winkler;
+
+// Minimum name similarity for a fuzzy style match
+const FUZZY_MATCH_THRESHOLD: f64 = 0.9;
+// Looser similarity required when the category number also agrees
+const CODE_MATCH_THRESHOLD: f64 = 0.75;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Range {
+    pub min: f64,
+    pub max: f64,
+}
+
+impl Range {
+    pub fn contains(&self, value: f64) -> bool {
+        // Allow for rounding in the third decimal of gravities
+        value >= self.min - 1e-6 && value <= self.max + 1e-6
+    }
+}
+
+/// Vital statistics for one BJCP 2021 style.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StyleGuideline {
+    pub code: &'static str,
+    pub name: &'static str,
+    pub category: &'static str,
+    pub og: Range,
+    pub fg: Range,
+    pub ibu: Range,
+    pub srm: Range,
+    pub abv: Range,
+}
+
+const fn r(min: f64, max: f64) -> Range {
+    Range { min, max }
+}
+
+#[rustfmt::skip]
+#[allow(clippy::too_many_arguments)]
+const fn style(
+    code: &'static str, name: &'static str, category: &'static str,
+    og: Range, fg: Range, ibu: Range, srm: Range, abv: Range,
+) -> StyleGuideline {
+    StyleGuideline { code, name, category, og, fg, ibu, srm, abv }
+}
+
+/// BJCP 2021 beer styles that publish vital statistics. Specialty
+/// categories (28-34) define their ranges by base style and are left out.
+#[rustfmt::skip]
+pub static BJCP_2021: &[StyleGuideline] = &[
+    style("1A", "American Light Lager", "Standard American Beer", r(1.028, 1.040), r(0.998, 1.008), r(8.0, 12.0), r(2.0, 3.0), r(2.8, 4.2)),
+    style("1B", "American Lager", "Standard American Beer", r(1.040, 1.050), r(1.004, 1.010), r(8.0, 18.0), r(2.0, 3.5), r(4.2, 5.3)),
+    style("1C", "Cream Ale", "Standard American Beer", r(1.042, 1.055), r(1.006, 1.012), r(8.0, 20.0), r(2.0, 5.0), r(4.2, 5.6)),
+    style("1D", "American Wheat Beer", "Standard American Beer", r(1.040, 1.055), r(1.008, 1.013), r(15.0, 30.0), r(3.0, 6.0), r(4.0, 5.5)),
+    style("2A", "International Pale Lager", "International Lager", r(1.042, 1.050), r(1.008, 1.012), r(18.0, 25.0), r(2.0, 6.0), r(4.6, 6.0)),
+    style("2B", "International Amber Lager", "International Lager", r(1.042, 1.055), r(1.008, 1.014), r(8.0, 25.0), r(7.0, 14.0), r(4.6, 6.0)),
+    style("2C", "International Dark Lager", "International Lager", r(1.044, 1.056), r(1.008, 1.012), r(8.0, 20.0), r(14.0, 30.0), r(4.2, 6.0)),
+    style("3A", "Czech Pale Lager", "Czech Lager", r(1.028, 1.044), r(1.008, 1.014), r(20.0, 35.0), r(3.0, 6.0), r(3.0, 4.1)),
+    style("3B", "Czech Premium Pale Lager", "Czech Lager", r(1.044, 1.060), r(1.013, 1.017), r(30.0, 45.0), r(3.5, 6.0), r(4.2, 5.8)),
+    style("3C", "Czech Amber Lager", "Czech Lager", r(1.044, 1.060), r(1.013, 1.017), r(20.0, 35.0), r(10.0, 16.0), r(4.4, 5.8)),
+    style("3D", "Czech Dark Lager", "Czech Lager", r(1.044, 1.060), r(1.013, 1.017), r(18.0, 34.0), r(17.0, 35.0), r(4.4, 5.8)),
+    style("4A", "Munich Helles", "Pale Malty European Lager", r(1.044, 1.048), r(1.006, 1.012), r(16.0, 22.0), r(3.0, 5.0), r(4.7, 5.4)),
+    style("4B", "Festbier", "Pale Malty European Lager", r(1.054, 1.057), r(1.010, 1.012), r(18.0, 25.0), r(4.0, 6.0), r(5.8, 6.3)),
+    style("4C", "Helles Bock", "Pale Malty European Lager", r(1.064, 1.072), r(1.011, 1.018), r(23.0, 35.0), r(6.0, 9.0), r(6.3, 7.4)),
+    style("5A", "German Leichtbier", "Pale Bitter European Beer", r(1.026, 1.034), r(1.006, 1.010), r(15.0, 28.0), r(1.5, 4.0), r(2.4, 3.6)),
+    style("5B", "Kölsch", "Pale Bitter European Beer", r(1.044, 1.050), r(1.007, 1.011), r(18.0, 30.0), r(3.5, 5.0), r(4.4, 5.2)),
+    style("5C", "German Helles Exportbier", "Pale Bitter European Beer", r(1.050, 1.058), r(1.008, 1.015), r(20.0, 30.0), r(4.0, 7.0), r(4.8, 6.0)),
+    style("5D", "German Pils", "Pale Bitter European Beer", r(1.044, 1.050), r(1.008, 1.013), r(22.0, 40.0), r(2.0, 4.0), r(4.4, 5.2)),
+    style("6A", "Märzen", "Amber Malty European Lager", r(1.054, 1.060), r(1.010, 1.014), r(18.0, 24.0), r(8.0, 17.0), r(5.6, 6.3)),
+    style("6B", "Rauchbier", "Amber Malty European Lager", r(1.050, 1.057), r(1.012, 1.016), r(20.0, 30.0), r(12.0, 22.0), r(4.8, 6.0)),
+    style("6C", "Dunkles Bock", "Amber Malty European Lager", r(1.064, 1.072), r(1.013, 1.019), r(20.0, 27.0), r(14.0, 22.0), r(6.3, 7.2)),
+    style("7A", "Vienna Lager", "Amber Bitter European Beer", r(1.048, 1.055), r(1.010, 1.014), r(18.0, 30.0), r(9.0, 15.0), r(4.7, 5.5)),
+    style("7B", "Altbier", "Amber Bitter European Beer", r(1.044, 1.052), r(1.008, 1.014), r(25.0, 50.0), r(9.0, 17.0), r(4.3, 5.5)),
+    style("8A", "Munich Dunkel", "Dark European Lager", r(1.048, 1.056), r(1.010, 1.016), r(18.0, 28.0), r(17.0, 28.0), r(4.5, 5.6)),
+    style("8B", "Schwarzbier", "Dark European Lager", r(1.046, 1.052), r(1.010, 1.016), r(20.0, 35.0), r(19.0, 30.0), r(4.4, 5.4)),
+    style("9A", "Doppelbock", "Strong European Beer", r(1.072, 1.112), r(1.016, 1.024), r(16.0, 26.0), r(6.0, 25.0), r(7.0, 10.0)),
+    style("9B", "Eisbock", "Strong European Beer", r(1.078, 1.120), r(1.020, 1.035), r(25.0, 35.0), r(18.0, 30.0), r(9.0, 14.0)),
+    style("9C", "Baltic Porter", "Strong European Beer", r(1.060, 1.090), r(1.016, 1.024), r(20.0, 40.0), r(17.0, 30.0), r(6.5, 9.5)),
+    style("10A", "Weissbier", "German Wheat Beer", r(1.044, 1.053), r(1.008, 1.014), r(8.0, 15.0), r(2.0, 6.0), r(4.3, 5.6)),
+    style("10B", "Dunkles Weissbier", "German Wheat Beer", r(1.044, 1.057), r(1.008, 1.014), r(10.0, 18.0), r(14.0, 23.0), r(4.3, 5.6)),
+    style("10C", "Weizenbock", "German Wheat Beer", r(1.064, 1.090), r(1.015, 1.022), r(15.0, 30.0), r(6.0, 25.0), r(6.5, 9.0)),
+    style("11A", "Ordinary Bitter", "British Bitter", r(1.030, 1.039), r(1.007, 1.011), r(25.0, 35.0), r(8.0, 14.0), r(3.2, 3.8)),
+    style("11B", "Best Bitter", "British Bitter", r(1.040, 1.048), r(1.008, 1.012), r(25.0, 40.0), r(8.0, 16.0), r(3.8, 4.6)),
+    style("11C", "Strong Bitter", "British Bitter", r(1.048, 1.060), r(1.010, 1.016), r(30.0, 50.0), r(8.0, 18.0), r(4.6, 6.2)),
+    style("12A", "British Golden Ale", "Pale Commonwealth Beer", r(1.038, 1.053), r(1.006, 1.012), r(20.0, 45.0), r(2.0, 6.0), r(3.8, 5.0)),
+    style("12B", "Australian Sparkling Ale", "Pale Commonwealth Beer", r(1.038, 1.050), r(1.004, 1.006), r(20.0, 35.0), r(4.0, 7.0), r(4.5, 6.0)),
+    style("12C", "English IPA", "Pale Commonwealth Beer", r(1.050, 1.070), r(1.010, 1.015), r(40.0, 60.0), r(6.0, 14.0), r(5.0, 7.5)),
+    style("13A", "Dark Mild", "Brown British Beer", r(1.030, 1.038), r(1.008, 1.013), r(10.0, 25.0), r(14.0, 25.0), r(3.0, 3.8)),
+    style("13B", "British Brown Ale", "Brown British Beer", r(1.040, 1.052), r(1.008, 1.013), r(20.0, 30.0), r(12.0, 22.0), r(4.2, 5.4)),
+    style("13C", "English Porter", "Brown British Beer", r(1.040, 1.052), r(1.008, 1.014), r(18.0, 35.0), r(20.0, 30.0), r(4.0, 5.4)),
+    style("14A", "Scottish Light", "Scottish Ale", r(1.030, 1.035), r(1.010, 1.013), r(10.0, 20.0), r(17.0, 25.0), r(2.5, 3.3)),
+    style("14B", "Scottish Heavy", "Scottish Ale", r(1.035, 1.040), r(1.010, 1.015), r(10.0, 20.0), r(12.0, 20.0), r(3.3, 3.9)),
+    style("14C", "Scottish Export", "Scottish Ale", r(1.040, 1.060), r(1.010, 1.016), r(15.0, 30.0), r(12.0, 20.0), r(3.9, 6.0)),
+    style("15A", "Irish Red Ale", "Irish Beer", r(1.036, 1.046), r(1.010, 1.014), r(18.0, 28.0), r(9.0, 14.0), r(3.8, 5.0)),
+    style("15B", "Irish Stout", "Irish Beer", r(1.036, 1.044), r(1.007, 1.011), r(25.0, 45.0), r(25.0, 40.0), r(3.8, 5.0)),
+    style("15C", "Irish Extra Stout", "Irish Beer", r(1.052, 1.062), r(1.010, 1.014), r(35.0, 50.0), r(30.0, 40.0), r(5.5, 6.5)),
+    style("16A", "Sweet Stout", "Dark British Beer", r(1.044, 1.060), r(1.012, 1.024), r(20.0, 40.0), r(30.0, 40.0), r(4.0, 6.0)),
+    style("16B", "Oatmeal Stout", "Dark British Beer", r(1.045, 1.065), r(1.010, 1.018), r(25.0, 40.0), r(22.0, 40.0), r(4.2, 5.9)),
+    style("16C", "Tropical Stout", "Dark British Beer", r(1.056, 1.075), r(1.010, 1.018), r(30.0, 50.0), r(30.0, 40.0), r(5.5, 8.0)),
+    style("16D", "Foreign Extra Stout", "Dark British Beer", r(1.056, 1.075), r(1.010, 1.018), r(50.0, 70.0), r(30.0, 40.0), r(6.3, 8.0)),
+    style("17A", "British Strong Ale", "Strong British Ale", r(1.055, 1.080), r(1.015, 1.022), r(30.0, 60.0), r(8.0, 22.0), r(5.5, 8.0)),
+    style("17B", "Old Ale", "Strong British Ale", r(1.055, 1.088), r(1.015, 1.022), r(30.0, 60.0), r(10.0, 22.0), r(5.5, 9.0)),
+    style("17C", "Wee Heavy", "Strong British Ale", r(1.070, 1.130), r(1.018, 1.040), r(17.0, 35.0), r(14.0, 25.0), r(6.5, 10.0)),
+    style("17D", "English Barley Wine", "Strong British Ale", r(1.080, 1.120), r(1.018, 1.030), r(35.0, 70.0), r(8.0, 22.0), r(8.0, 12.0)),
+    style("18A", "Blonde Ale", "Pale American Ale", r(1.038, 1.054), r(1.008, 1.013), r(15.0, 28.0), r(3.0, 6.0), r(3.8, 5.5)),
+    style("18B", "American Pale Ale", "Pale American Ale", r(1.045, 1.060), r(1.010, 1.015), r(30.0, 50.0), r(5.0, 10.0), r(4.5, 6.2)),
+    style("19A", "American Amber Ale", "Amber and Brown American Beer", r(1.045, 1.060), r(1.010, 1.015), r(25.0, 40.0), r(10.0, 17.0), r(4.5, 6.2)),
+    style("19B", "California Common", "Amber and Brown American Beer", r(1.048, 1.054), r(1.011, 1.014), r(30.0, 45.0), r(9.0, 14.0), r(4.5, 5.5)),
+    style("19C", "American Brown Ale", "Amber and Brown American Beer", r(1.045, 1.060), r(1.010, 1.016), r(20.0, 30.0), r(18.0, 35.0), r(4.3, 6.2)),
+    style("20A", "American Porter", "American Porter and Stout", r(1.050, 1.070), r(1.012, 1.018), r(25.0, 50.0), r(22.0, 40.0), r(4.8, 6.5)),
+    style("20B", "American Stout", "American Porter and Stout", r(1.050, 1.075), r(1.010, 1.022), r(35.0, 75.0), r(30.0, 40.0), r(5.0, 7.0)),
+    style("20C", "Imperial Stout", "American Porter and Stout", r(1.075, 1.115), r(1.018, 1.030), r(50.0, 90.0), r(30.0, 40.0), r(8.0, 12.0)),
+    style("21A", "American IPA", "IPA", r(1.056, 1.070), r(1.008, 1.014), r(40.0, 70.0), r(6.0, 14.0), r(5.5, 7.5)),
+    style("21B", "Belgian IPA", "IPA", r(1.058, 1.080), r(1.008, 1.016), r(50.0, 100.0), r(5.0, 15.0), r(6.2, 9.5)),
+    style("21B", "Black IPA", "IPA", r(1.050, 1.085), r(1.010, 1.018), r(50.0, 90.0), r(25.0, 40.0), r(5.5, 9.0)),
+    style("21B", "Brown IPA", "IPA", r(1.056, 1.070), r(1.008, 1.016), r(40.0, 70.0), r(11.0, 19.0), r(5.5, 7.5)),
+    style("21B", "Red IPA", "IPA", r(1.056, 1.070), r(1.008, 1.016), r(40.0, 70.0), r(11.0, 19.0), r(5.5, 7.5)),
+    style("21B", "Rye IPA", "IPA", r(1.056, 1.075), r(1.008, 1.014), r(50.0, 75.0), r(6.0, 14.0), r(5.5, 8.0)),
+    style("21B", "White IPA", "IPA", r(1.056, 1.065), r(1.010, 1.016), r(40.0, 70.0), r(5.0, 6.0), r(5.5, 7.0)),
+    style("21B", "Brut IPA", "IPA", r(1.046, 1.066), r(0.990, 1.004), r(20.0, 30.0), r(2.0, 4.0), r(6.0, 7.5)),
+    style("21C", "Hazy IPA", "IPA", r(1.060, 1.085), r(1.010, 1.015), r(25.0, 60.0), r(3.0, 7.0), r(6.0, 9.0)),
+    style("22A", "Double IPA", "Strong American Ale", r(1.065, 1.085), r(1.008, 1.018), r(60.0, 100.0), r(6.0, 14.0), r(7.5, 10.0)),
+    style("22B", "American Strong Ale", "Strong American Ale", r(1.062, 1.090), r(1.014, 1.024), r(50.0, 100.0), r(7.0, 18.0), r(6.3, 10.0)),
+    style("22C", "American Barleywine", "Strong American Ale", r(1.080, 1.120), r(1.016, 1.030), r(50.0, 100.0), r(9.0, 18.0), r(8.0, 12.0)),
+    style("22D", "Wheatwine", "Strong American Ale", r(1.080, 1.120), r(1.016, 1.030), r(30.0, 60.0), r(6.0, 14.0), r(8.0, 12.0)),
+    style("23A", "Berliner Weisse", "European Sour Ale", r(1.028, 1.032), r(1.003, 1.006), r(3.0, 8.0), r(2.0, 3.0), r(2.8, 3.8)),
+    style("23B", "Flanders Red Ale", "European Sour Ale", r(1.048, 1.057), r(1.002, 1.012), r(10.0, 25.0), r(10.0, 17.0), r(4.6, 6.5)),
+    style("23C", "Oud Bruin", "European Sour Ale", r(1.040, 1.074), r(1.008, 1.012), r(20.0, 25.0), r(17.0, 35.0), r(4.0, 8.0)),
+    style("23D", "Lambic", "European Sour Ale", r(1.040, 1.054), r(1.001, 1.010), r(0.0, 10.0), r(3.0, 6.0), r(5.0, 6.5)),
+    style("23E", "Gueuze", "European Sour Ale", r(1.040, 1.060), r(1.000, 1.006), r(0.0, 10.0), r(5.0, 6.0), r(5.0, 8.0)),
+    style("23F", "Fruit Lambic", "European Sour Ale", r(1.040, 1.060), r(1.000, 1.010), r(0.0, 10.0), r(3.0, 7.0), r(5.0, 7.0)),
+    style("23G", "Gose", "European Sour Ale", r(1.036, 1.056), r(1.006, 1.010), r(5.0, 12.0), r(3.0, 4.0), r(4.2, 4.8)),
+    style("24A", "Witbier", "Belgian Ale", r(1.044, 1.052), r(1.008, 1.012), r(8.0, 20.0), r(2.0, 4.0), r(4.5, 5.5)),
+    style("24B", "Belgian Pale Ale", "Belgian Ale", r(1.048, 1.054), r(1.010, 1.014), r(20.0, 30.0), r(8.0, 14.0), r(4.8, 5.5)),
+    style("24C", "Bière de Garde", "Belgian Ale", r(1.060, 1.080), r(1.008, 1.016), r(18.0, 28.0), r(6.0, 19.0), r(6.0, 8.5)),
+    style("25A", "Belgian Blond Ale", "Strong Belgian Ale", r(1.062, 1.075), r(1.008, 1.018), r(15.0, 30.0), r(4.0, 6.0), r(6.0, 7.5)),
+    style("25B", "Saison", "Strong Belgian Ale", r(1.048, 1.065), r(1.002, 1.008), r(20.0, 35.0), r(5.0, 22.0), r(3.5, 9.5)),
+    style("25C", "Belgian Golden Strong Ale", "Strong Belgian Ale", r(1.070, 1.095), r(1.005, 1.016), r(22.0, 35.0), r(3.0, 6.0), r(7.5, 10.5)),
+    style("26A", "Belgian Single", "Monastic Ale", r(1.044, 1.054), r(1.004, 1.010), r(25.0, 45.0), r(3.0, 5.0), r(4.8, 6.0)),
+    style("26B", "Belgian Dubbel", "Monastic Ale", r(1.062, 1.075), r(1.008, 1.018), r(15.0, 25.0), r(10.0, 17.0), r(6.0, 7.6)),
+    style("26C", "Belgian Tripel", "Monastic Ale", r(1.075, 1.085), r(1.008, 1.014), r(20.0, 40.0), r(4.5, 7.0), r(7.5, 9.5)),
+    style("26D", "Belgian Dark Strong Ale", "Monastic Ale", r(1.075, 1.110), r(1.010, 1.024), r(20.0, 35.0), r(12.0, 22.0), r(8.0, 12.0)),
+    style("27", "Kentucky Common", "Historical Beer", r(1.044, 1.055), r(1.010, 1.018), r(15.0, 30.0), r(11.0, 20.0), r(4.0, 5.5)),
+    style("27", "Lichtenhainer", "Historical Beer", r(1.032, 1.040), r(1.004, 1.008), r(5.0, 12.0), r(3.0, 6.0), r(3.5, 4.7)),
+    style("27", "London Brown Ale", "Historical Beer", r(1.033, 1.038), r(1.012, 1.015), r(15.0, 20.0), r(22.0, 35.0), r(2.8, 3.6)),
+    style("27", "Piwo Grodziskie", "Historical Beer", r(1.028, 1.032), r(1.006, 1.012), r(20.0, 35.0), r(3.0, 6.0), r(2.5, 3.3)),
+    style("27", "Pre-Prohibition Lager", "Historical Beer", r(1.044, 1.060), r(1.010, 1.015), r(25.0, 40.0), r(3.0, 6.0), r(4.5, 6.0)),
+    style("27", "Pre-Prohibition Porter", "Historical Beer", r(1.046, 1.060), r(1.010, 1.016), r(20.0, 30.0), r(18.0, 30.0), r(4.5, 6.0)),
+    style("27", "Roggenbier", "Historical Beer", r(1.046, 1.056), r(1.010, 1.014), r(10.0, 20.0), r(14.0, 19.0), r(4.5, 6.0)),
+    style("27", "Sahti", "Historical Beer", r(1.076, 1.120), r(1.016, 1.038), r(0.0, 15.0), r(4.0, 22.0), r(7.0, 11.0)),
+];
+
+// Names BeerSmith (and older BJCP editions) use for styles that were
+// renamed or merged in 2021
+const ALIASES: &[(&str, &str)] = &[
+    ("lite american lager", "American Light Lager"),
+    ("light american lager", "American Light Lager"),
+    ("standard american lager", "American Lager"),
+    ("premium american lager", "International Pale Lager"),
+    ("american wheat or rye beer", "American Wheat Beer"),
+    ("dark american lager", "International Dark Lager"),
+    ("bohemian pilsener", "Czech Premium Pale Lager"),
+    ("bohemian pilsner", "Czech Premium Pale Lager"),
+    ("czech pilsner", "Czech Premium Pale Lager"),
+    ("german pilsner", "German Pils"),
+    ("german pilsener", "German Pils"),
+    ("northern german pilsner", "German Pils"),
+    ("dortmunder export", "German Helles Exportbier"),
+    ("maibock", "Helles Bock"),
+    ("traditional bock", "Dunkles Bock"),
+    ("oktoberfest", "Märzen"),
+    ("oktoberfest marzen", "Märzen"),
+    ("dusseldorf altbier", "Altbier"),
+    ("northern german altbier", "Altbier"),
+    ("weizen", "Weissbier"),
+    ("weizen weissbier", "Weissbier"),
+    ("hefeweizen", "Weissbier"),
+    ("dunkelweizen", "Dunkles Weissbier"),
+    ("standard ordinary bitter", "Ordinary Bitter"),
+    ("special best premium bitter", "Best Bitter"),
+    ("extra special strong bitter", "Strong Bitter"),
+    ("extra special bitter", "Strong Bitter"),
+    ("esb", "Strong Bitter"),
+    ("mild", "Dark Mild"),
+    ("northern english brown ale", "British Brown Ale"),
+    ("southern english brown", "London Brown Ale"),
+    ("english brown ale", "British Brown Ale"),
+    ("brown porter", "English Porter"),
+    ("robust porter", "American Porter"),
+    ("scottish light 60", "Scottish Light"),
+    ("scottish heavy 70", "Scottish Heavy"),
+    ("scottish export 80", "Scottish Export"),
+    ("strong scotch ale", "Wee Heavy"),
+    ("scotch ale", "Wee Heavy"),
+    ("dry stout", "Irish Stout"),
+    ("milk stout", "Sweet Stout"),
+    ("russian imperial stout", "Imperial Stout"),
+    ("english barleywine", "English Barley Wine"),
+    ("american barley wine", "American Barleywine"),
+    ("imperial ipa", "Double IPA"),
+    ("new england ipa", "Hazy IPA"),
+    ("neipa", "Hazy IPA"),
+    ("cascadian dark ale", "Black IPA"),
+    ("california common beer", "California Common"),
+    ("classic american pilsner", "Pre-Prohibition Lager"),
+    ("roggenbier german rye beer", "Roggenbier"),
+    ("flanders brown ale oud bruin", "Oud Bruin"),
+    ("straight unblended lambic", "Lambic"),
+    ("trappist single", "Belgian Single"),
+    ("belgian blonde ale", "Belgian Blond Ale"),
+    ("biere de garde", "Bière de Garde"),
+];
+
+/// Find the BJCP 2021 guideline for a recipe's style.
+///
+/// Tries, in order: an exact or aliased name match, a fuzzy name match, and
+/// finally the style's category number and letter.
+pub fn find_guideline(style: &Style) -> Option<&'static StyleGuideline> {
+    let key = normalize(&style.name);
+    if !key.is_empty() {
+        if let Some(found) = by_name(&key) {
+            return Some(found);
+        }
+        // BeerSmith sometimes joins alternative names with a slash
+        for part in style.name.split('/') {
+            if let Some(found) = by_name(&normalize(part)) {
+                return Some(found);
+            }
+        }
+
+        let best = BJCP_2021
+            .iter()
+            .map(|g| (jaro_winkler(&key, &normalize(g.name)), g))
+            .max_by(|a, b| a.0.total_cmp(&b.0));
+        if let Some((score, guideline)) = best {
+            if score >= FUZZY_MATCH_THRESHOLD {
+                return Some(guideline);
+            }
+        }
+    }
+
+    // Older BJCP editions numbered styles differently, so only trust the
+    // number when it's unambiguous and the names are at least related
+    let code = format!(
+        "{}{}",
+        style.number.trim(),
+        style.letter.trim().to_uppercase()
+    );
+    let mut candidates = BJCP_2021.iter().filter(|g| g.code == code);
+    match (candidates.next(), candidates.next()) {
+        (Some(only), None)
+            if key.is_empty()
+                || jaro_winkler(&key, &normalize(only.name)) >= CODE_MATCH_THRESHOLD =>
+        {
+            Some(only)
+        }
+        _ => None,
+    }
+}
+
+fn by_name(key: &str) -> Option<&'static StyleGuideline> {
+    if key.is_empty() {
+        return None;
+    }
+    let name = ALIASES
+        .iter()
+        .find(|(alias, _)| *alias == key)
+        .map(|(_, name)| *name);
+    BJCP_2021.iter().find(|g| match name {
+        Some(name) => g.name == name,
+        None => normalize(g.name) == key,
+    })
+}
+
+/// Lowercase, fold accents and reduce punctuation to single spaces so
+/// "Oktoberfest/Märzen" and "oktoberfest marzen" compare equal.
+pub fn normalize(name: &str) -> String {
+    let mut out = String::with_capacity(name.len());
+    for c in name.chars().flat_map(char::to_lowercase) {
+        let c = match c {
+            'ä' | 'à' | 'á' | 'â' => 'a',
+            'è' | 'é' | 'ê' | 'ë' => 'e',
+            'ï' | 'î' => 'i',
+            'ö' | 'ô' => 'o',
+            'ü' | 'û' => 'u',
+            c => c,
+        };
+        if c.is_alphanumeric() {
+            out.push(c);
+        } else if !out.is_empty() && !out.ends_with(' ') {
+            out.push(' ');
+        }
+    }
+    out.truncate(out.trim_end().len());
+    out
+}
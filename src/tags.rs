@@ -0,0 +1,116 @@
+use crate::collection::write_atomic;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Name of the file in the recipes directory holding the user's tags.
+pub const TAGS_FILE: &str = "tags.json";
+
+/// Tags the user has given recipe files, by file name relative to the
+/// recipes directory. Each file's tags are kept sorted, without repeats.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Tags(BTreeMap<String, Vec<String>>);
+
+impl Tags {
+    /// The tags in `dir`, or none if nothing has been tagged there yet.
+    pub fn load(dir: &Path) -> Result<Self, Box<dyn Error>> {
+        match fs::read(dir.join(TAGS_FILE)) {
+            Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Tags::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Write the tags back to `dir`, replacing the old file in one step.
+    pub fn save(&self, dir: &Path) -> Result<(), Box<dyn Error>> {
+        write_atomic(&dir.join(TAGS_FILE), &serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Tag `filename`, returning false if it already had the tag.
+    pub fn add(&mut self, filename: &str, tag: &str) -> bool {
+        let tags = self.0.entry(filename.to_string()).or_default();
+        match tags.binary_search_by(|t| t.as_str().cmp(tag)) {
+            Ok(_) => false,
+            Err(at) => {
+                tags.insert(at, tag.to_string());
+                true
+            }
+        }
+    }
+
+    /// Take a tag off `filename`, returning false if it didn't have it.
+    pub fn remove(&mut self, filename: &str, tag: &str) -> bool {
+        let Some(tags) = self.0.get_mut(filename) else {
+            return false;
+        };
+        let before = tags.len();
+        tags.retain(|t| t != tag);
+        let removed = tags.len() < before;
+        if tags.is_empty() {
+            self.0.remove(filename);
+        }
+        removed
+    }
+
+    pub fn of(&self, filename: &str) -> &[String] {
+        self.0.get(filename).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// Files with `tag`, in name order.
+    pub fn files_with(&self, tag: &str) -> Vec<&str> {
+        self.0
+            .iter()
+            .filter(|(_, tags)| tags.iter().any(|t| t == tag))
+            .map(|(filename, _)| filename.as_str())
+            .collect()
+    }
+
+    /// Every tagged file and its tags, in file name order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &[String])> {
+        self.0
+            .iter()
+            .map(|(filename, tags)| (filename.as_str(), tags.as_slice()))
+    }
+}
+
+/// A tag as given on the command line, trimmed. Commas separate the
+/// alternatives of a --tag filter, so a tag can't have one.
+pub fn parse_tag(tag: &str) -> Result<String, String> {
+    let tag = tag.trim();
+    if tag.is_empty() {
+        return Err("a tag can't be empty".to_string());
+    }
+    if tag.contains(',') {
+        return Err(format!("a tag can't have a comma in it: {}", tag));
+    }
+    Ok(tag.to_string())
+}
+
+/// Each --tag value is a comma-separated list of tags of which a recipe
+/// needs any one; a recipe has to satisfy every --tag.
+pub fn tag_filter(values: &[String]) -> Vec<Vec<String>> {
+    values
+        .iter()
+        .map(|value| {
+            value
+                .split(',')
+                .map(str::trim)
+                .filter(|tag| !tag.is_empty())
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        })
+        .filter(|any| !any.is_empty())
+        .collect()
+}
+
+/// Whether a recipe with `tags` satisfies a [`tag_filter`].
+pub fn matches_tags(tags: &[String], filter: &[Vec<String>]) -> bool {
+    filter
+        .iter()
+        .all(|any| any.iter().any(|wanted| tags.contains(wanted)))
+}
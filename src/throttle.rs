@@ -0,0 +1,364 @@
+use chrono::{DateTime, Utc};
+use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex, Notify};
+
+/// A number of bytes, written with an optional decimal (`KB`, `MB`, `GB`) or
+/// binary (`KiB`, `MiB`, `GiB`) unit, e.g. `512KB` or `1.5MiB`. A trailing
+/// `/s` is allowed, so rates read naturally: `2MB/s`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteSize(pub u64);
+
+impl FromStr for ByteSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let text = s.trim();
+        let text = text.strip_suffix("/s").unwrap_or(text);
+        let split = text
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(text.len());
+        let (number, unit) = text.split_at(split);
+        let number: f64 = number
+            .parse()
+            .map_err(|_| format!("expected a size such as 2MB or 512KiB, got {:?}", s))?;
+        let multiplier = match unit.trim().to_ascii_lowercase().as_str() {
+            "" | "b" => 1.0,
+            "k" | "kb" => 1e3,
+            "m" | "mb" => 1e6,
+            "g" | "gb" => 1e9,
+            "kib" => 1024.0,
+            "mib" => 1024.0 * 1024.0,
+            "gib" => 1024.0 * 1024.0 * 1024.0,
+            _ => {
+                return Err(format!(
+                    "unknown unit {:?} in {:?}; use B, KB, MB, GB, KiB, MiB or GiB",
+                    unit.trim(),
+                    s
+                ))
+            }
+        };
+        let bytes = (number * multiplier).round();
+        if bytes < 1.0 || bytes > u64::MAX as f64 {
+            return Err(format!("{:?} is not a usable size", s));
+        }
+        Ok(ByteSize(bytes as u64))
+    }
+}
+
+impl fmt::Display for ByteSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bytes = self.0 as f64;
+        if bytes >= 1e9 {
+            write!(f, "{:.1} GB", bytes / 1e9)
+        } else if bytes >= 1e6 {
+            write!(f, "{:.1} MB", bytes / 1e6)
+        } else if bytes >= 1e3 {
+            write!(f, "{:.1} KB", bytes / 1e3)
+        } else {
+            write!(f, "{} B", self.0)
+        }
+    }
+}
+
+/// Caps the bytes per second read across every download sharing it, as a
+/// token bucket that can hold up to `burst` bytes.
+///
+/// Transfers queue for the bucket in turn, one chunk at a time, so a large
+/// file can't starve the others. A chunk larger than the bucket holds puts
+/// it into debt, which the next in line waits out by sleeping rather than
+/// polling.
+#[derive(Debug)]
+pub struct ByteLimiter {
+    rate: f64,
+    burst: f64,
+    bucket: Mutex<Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl ByteLimiter {
+    /// A limiter allowing `rate` bytes per second, starting with a full
+    /// bucket of `burst` bytes.
+    pub fn new(rate: ByteSize, burst: ByteSize) -> Self {
+        ByteLimiter {
+            rate: rate.0 as f64,
+            burst: burst.0 as f64,
+            bucket: Mutex::new(Bucket {
+                tokens: burst.0 as f64,
+                refilled: Instant::now(),
+            }),
+        }
+    }
+
+    /// Account for `bytes` just read, waiting until the rate allows them.
+    pub async fn take(&self, bytes: usize) {
+        // Held while waiting, which keeps the other transfers queued behind
+        // this one in the order they asked
+        let mut bucket = self.bucket.lock().await;
+        let now = Instant::now();
+        let refill = now.duration_since(bucket.refilled).as_secs_f64() * self.rate;
+        bucket.tokens = (bucket.tokens + refill).min(self.burst) - bytes as f64;
+        bucket.refilled = now;
+        if bucket.tokens < 0.0 {
+            tokio::time::sleep(Duration::from_secs_f64(-bucket.tokens / self.rate)).await;
+        }
+    }
+}
+
+// How long the transfer rate is smoothed over, in whole seconds
+const TRANSFER_RATE_WINDOW: u64 = 10;
+
+/// Counts the bytes a run receives as they arrive, so transfers that break
+/// off count too, and the recipes it saves, for the rate, total and
+/// average size shown while it runs.
+#[derive(Debug)]
+pub struct TransferMeter {
+    started: Instant,
+    received: AtomicU64,
+    /// Bytes received in each of the last few seconds, by whole seconds
+    /// since `started`
+    recent: std::sync::Mutex<VecDeque<(u64, u64)>>,
+    saved: AtomicU64,
+    saved_bytes: AtomicU64,
+    compressed: AtomicU64,
+    decompressed: AtomicU64,
+}
+
+impl Default for TransferMeter {
+    fn default() -> Self {
+        TransferMeter {
+            started: Instant::now(),
+            received: AtomicU64::new(0),
+            recent: std::sync::Mutex::new(VecDeque::new()),
+            saved: AtomicU64::new(0),
+            saved_bytes: AtomicU64::new(0),
+            compressed: AtomicU64::new(0),
+            decompressed: AtomicU64::new(0),
+        }
+    }
+}
+
+impl TransferMeter {
+    /// Count a chunk of `bytes` just read off the wire.
+    pub fn record(&self, bytes: usize) {
+        self.received.fetch_add(bytes as u64, Ordering::Relaxed);
+        let second = self.started.elapsed().as_secs();
+        let mut recent = self.recent.lock().unwrap();
+        match recent.back_mut() {
+            Some((at, total)) if *at == second => *total += bytes as u64,
+            _ => recent.push_back((second, bytes as u64)),
+        }
+        prune_seconds(&mut recent, second);
+    }
+
+    /// Count a recipe of `bytes` saved.
+    pub fn saved(&self, bytes: usize) {
+        self.saved.fetch_add(1, Ordering::Relaxed);
+        self.saved_bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    /// Count a transfer that arrived as `compressed` bytes and came to
+    /// `decompressed` once decompressed.
+    pub fn decompressed(&self, compressed: usize, decompressed: usize) {
+        self.compressed
+            .fetch_add(compressed as u64, Ordering::Relaxed);
+        self.decompressed
+            .fetch_add(decompressed as u64, Ordering::Relaxed);
+    }
+
+    /// Bytes of the transfers that arrived compressed, as received and as
+    /// decompressed.
+    pub fn compression(&self) -> (u64, u64) {
+        (
+            self.compressed.load(Ordering::Relaxed),
+            self.decompressed.load(Ordering::Relaxed),
+        )
+    }
+
+    /// Bytes received so far.
+    pub fn received(&self) -> u64 {
+        self.received.load(Ordering::Relaxed)
+    }
+
+    /// Bytes received per second over the last ten seconds, or over the
+    /// time since the meter was made if that's shorter.
+    pub fn rate(&self) -> f64 {
+        let elapsed = self.started.elapsed();
+        let mut recent = self.recent.lock().unwrap();
+        prune_seconds(&mut recent, elapsed.as_secs());
+        let bytes: u64 = recent.iter().map(|(_, bytes)| bytes).sum();
+        let window = elapsed
+            .as_secs_f64()
+            .clamp(1.0, TRANSFER_RATE_WINDOW as f64);
+        bytes as f64 / window
+    }
+
+    /// Mean size of the recipes saved, or `None` before the first.
+    pub fn average_saved(&self) -> Option<u64> {
+        let saved = self.saved.load(Ordering::Relaxed);
+        (saved > 0).then(|| self.saved_bytes.load(Ordering::Relaxed) / saved)
+    }
+}
+
+fn prune_seconds(recent: &mut VecDeque<(u64, u64)>, now: u64) {
+    while recent
+        .front()
+        .is_some_and(|(at, _)| at + TRANSFER_RATE_WINDOW <= now)
+    {
+        recent.pop_front();
+    }
+}
+
+// How long the request rate is measured over
+const REQUEST_RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// How long to hold off after a 429 that doesn't say, or says in a way
+/// that can't be read.
+pub const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+/// The wait a `Retry-After` header asks for, given as a number of seconds
+/// or as an HTTP date. A date already past means no wait at all.
+pub fn parse_retry_after(value: &str, now: DateTime<Utc>) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let at = DateTime::parse_from_rfc2822(value).ok()?;
+    Some(
+        (at.with_timezone(&Utc) - now)
+            .to_std()
+            .unwrap_or(Duration::ZERO),
+    )
+}
+
+/// Every request a run sends passes through here first. With a cap it's a
+/// token bucket refilling at `max_rps` and holding a second's worth, so
+/// requests are spread out however many are in flight; either way it
+/// counts them, to report the rate they actually went out at. A 429 shuts
+/// it for every request until the wait the server asked for is over.
+#[derive(Debug)]
+pub struct RequestGate {
+    max_rps: Option<f64>,
+    bucket: Mutex<RequestBucket>,
+    started: Instant,
+    /// When each request in the last minute was let through
+    sent: std::sync::Mutex<VecDeque<Instant>>,
+    /// When the last 429's wait is over
+    paused_until: std::sync::Mutex<Option<Instant>>,
+    /// Wakes the requests waiting out a pause when it's made longer
+    pause_extended: Arc<Notify>,
+}
+
+#[derive(Debug)]
+struct RequestBucket {
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl RequestGate {
+    /// A gate letting through at most `max_rps` requests a second, or any
+    /// number with `None`.
+    pub fn new(max_rps: Option<f64>) -> Self {
+        let now = Instant::now();
+        RequestGate {
+            max_rps,
+            // One to start with, so the first second isn't a burst
+            bucket: Mutex::new(RequestBucket {
+                tokens: 1.0,
+                refilled: now,
+            }),
+            started: now,
+            sent: std::sync::Mutex::new(VecDeque::new()),
+            paused_until: std::sync::Mutex::new(None),
+            pause_extended: Arc::new(Notify::new()),
+        }
+    }
+
+    pub fn max_rps(&self) -> Option<f64> {
+        self.max_rps
+    }
+
+    /// Hold every request back for `wait` from now, unless an earlier pause
+    /// already runs longer. Returns whether the pause was extended.
+    pub fn pause_for(&self, wait: Duration) -> bool {
+        let until = Instant::now() + wait;
+        let mut paused_until = self.paused_until.lock().unwrap();
+        if paused_until.is_some_and(|current| current >= until) {
+            return false;
+        }
+        *paused_until = Some(until);
+        self.pause_extended.notify_waiters();
+        true
+    }
+
+    /// How much of a pause is left, if one is on.
+    pub fn paused_for(&self) -> Option<Duration> {
+        let until = (*self.paused_until.lock().unwrap())?;
+        let left = until.saturating_duration_since(Instant::now());
+        (!left.is_zero()).then_some(left)
+    }
+
+    /// Wait until any pause is over and the cap allows another request.
+    pub async fn pass(&self) {
+        loop {
+            // Registered before the deadline is read, so an extension in
+            // between still wakes this request
+            let extended = self.pause_extended.notified();
+            let Some(left) = self.paused_for() else {
+                break;
+            };
+            tokio::select! {
+                _ = tokio::time::sleep(left) => {}
+                _ = extended => {}
+            }
+        }
+        if let Some(rate) = self.max_rps {
+            // Held while waiting, so requests go out in the order they came
+            let mut bucket = self.bucket.lock().await;
+            let now = Instant::now();
+            let refill = now.duration_since(bucket.refilled).as_secs_f64() * rate;
+            bucket.tokens = (bucket.tokens + refill).min(rate.max(1.0));
+            bucket.refilled = now;
+            if bucket.tokens < 1.0 {
+                tokio::time::sleep(Duration::from_secs_f64((1.0 - bucket.tokens) / rate)).await;
+                bucket.tokens = 1.0;
+                bucket.refilled = Instant::now();
+            }
+            bucket.tokens -= 1.0;
+        }
+        let now = Instant::now();
+        let mut sent = self.sent.lock().unwrap();
+        sent.push_back(now);
+        prune(&mut sent, now);
+    }
+
+    /// Requests let through per second over the last minute, or over the
+    /// time since the gate was made if that's shorter.
+    pub fn recent_rate(&self) -> f64 {
+        let now = Instant::now();
+        let mut sent = self.sent.lock().unwrap();
+        prune(&mut sent, now);
+        let window = now
+            .duration_since(self.started)
+            .clamp(Duration::from_secs(1), REQUEST_RATE_WINDOW);
+        sent.len() as f64 / window.as_secs_f64()
+    }
+}
+
+fn prune(sent: &mut VecDeque<Instant>, now: Instant) {
+    while sent
+        .front()
+        .is_some_and(|at| now.duration_since(*at) > REQUEST_RATE_WINDOW)
+    {
+        sent.pop_front();
+    }
+}
@@ -0,0 +1,116 @@
+use crate::cli::UaStrategy;
+use crate::download::USER_AGENT;
+use rand::seq::SliceRandom;
+use reqwest::header::HeaderValue;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Current browsers on the common platforms, rotated through with
+/// --ua-strategy when no --user-agents file is given
+pub const BUILT_IN_USER_AGENTS: [&str; 8] = [
+    USER_AGENT,
+    "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/124.0.0.0 Safari/537.36",
+    "Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:125.0) Gecko/20100101 Firefox/125.0",
+    "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/124.0.0.0 Safari/537.36 Edg/124.0.0.0",
+    "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/124.0.0.0 Safari/537.36",
+    "Mozilla/5.0 (Macintosh; Intel Mac OS X 14.4; rv:125.0) Gecko/20100101 Firefox/125.0",
+    "Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/124.0.0.0 Safari/537.36",
+    "Mozilla/5.0 (iPhone; CPU iPhone OS 17_4 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.4 Mobile/15E148 Safari/604.1",
+];
+
+/// The User-Agent requests are sent with.
+#[derive(Debug, Clone)]
+pub enum UserAgents {
+    /// The same one on every request
+    Fixed(HeaderValue),
+    /// One picked from these for each recipe request
+    Rotating(Arc<Vec<HeaderValue>>),
+}
+
+impl Default for UserAgents {
+    fn default() -> Self {
+        UserAgents::Fixed(HeaderValue::from_static(USER_AGENT))
+    }
+}
+
+impl UserAgents {
+    /// The User-Agent a run's options ask for. Without --user-agents or
+    /// --ua-strategy every request gets the built-in one; with either, the
+    /// pool is the file or the built-in list, and a run keeps to one picked
+    /// at random unless --ua-strategy per-request is given.
+    pub fn from_args(
+        user_agent: Option<&str>,
+        pool: Option<&Path>,
+        strategy: Option<UaStrategy>,
+    ) -> Result<Self, String> {
+        if let Some(user_agent) = user_agent {
+            return Ok(UserAgents::Fixed(header_value(user_agent, "--user-agent")?));
+        }
+        let pool = match (pool, strategy) {
+            (Some(path), _) => read_user_agents(path)?,
+            (None, Some(_)) => BUILT_IN_USER_AGENTS
+                .iter()
+                .map(|ua| HeaderValue::from_static(ua))
+                .collect(),
+            (None, None) => return Ok(UserAgents::default()),
+        };
+        Ok(match strategy.unwrap_or(UaStrategy::PerRun) {
+            UaStrategy::PerRun => UserAgents::Fixed(
+                pool.choose(&mut rand::thread_rng())
+                    .expect("a pool is never empty")
+                    .clone(),
+            ),
+            UaStrategy::PerRequest => UserAgents::Rotating(Arc::new(pool)),
+        })
+    }
+
+    /// The one a client sends unless a request says otherwise.
+    pub fn default_value(&self) -> &HeaderValue {
+        match self {
+            UserAgents::Fixed(ua) => ua,
+            UserAgents::Rotating(pool) => &pool[0],
+        }
+    }
+
+    /// The one for the next recipe request.
+    pub fn pick(&self) -> &HeaderValue {
+        match self {
+            UserAgents::Fixed(ua) => ua,
+            UserAgents::Rotating(pool) => pool
+                .choose(&mut rand::thread_rng())
+                .expect("a pool is never empty"),
+        }
+    }
+}
+
+/// The User-Agents in a --user-agents file, one per line, skipping blank
+/// lines and `#` comments.
+pub fn read_user_agents(path: &Path) -> Result<Vec<HeaderValue>, String> {
+    let text =
+        fs::read_to_string(path).map_err(|e| format!("can't read {}: {}", path.display(), e))?;
+    let mut pool = vec![];
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        pool.push(header_value(
+            line,
+            &format!("{} line {}", path.display(), n + 1),
+        )?);
+    }
+    if pool.is_empty() {
+        return Err(format!("{} lists no User-Agents", path.display()));
+    }
+    Ok(pool)
+}
+
+fn header_value(user_agent: &str, source: &str) -> Result<HeaderValue, String> {
+    HeaderValue::from_str(user_agent).map_err(|_| {
+        format!(
+            "{}: the User-Agent has characters a header can't hold",
+            source
+        )
+    })
+}
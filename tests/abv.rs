@@ -0,0 +1,70 @@
+use beer_scape::beerxml::{export_beerxml, parse_beerxml};
+use beer_scape::bsmx::{calculate_abv, parse_bsmx, set_abv_formula, AbvSource, Recipe};
+use beer_scape::cli::AbvFormula;
+use std::fs;
+
+fn recipe(abv: &str) -> Recipe {
+    let xml = fs::read_to_string("tests/fixtures/utf8.bsmx")
+        .unwrap()
+        .replace("<F_R_ABV>7.4</F_R_ABV>", abv);
+    parse_bsmx(xml.as_bytes()).unwrap()
+}
+
+fn close(a: f64, b: f64) -> bool {
+    (a - b).abs() < 1e-9
+}
+
+#[test]
+fn formulas() {
+    assert!(close(
+        calculate_abv(1.050, 1.010, AbvFormula::Standard),
+        5.25
+    ));
+    // The two agree on ordinary beers and part ways on strong ones
+    let accurate = calculate_abv(1.050, 1.010, AbvFormula::Accurate);
+    assert!((accurate - 5.25).abs() < 0.1, "{}", accurate);
+    let strong = calculate_abv(1.120, 1.020, AbvFormula::Accurate);
+    assert!(strong > calculate_abv(1.120, 1.020, AbvFormula::Standard) + 0.5);
+}
+
+#[test]
+fn keeps_a_recorded_abv_and_says_when_there_is_none() {
+    let parsed = recipe("<F_R_ABV>7.4</F_R_ABV>");
+    assert_eq!(parsed.abv, 7.4);
+    assert_eq!(parsed.abv_source, AbvSource::Explicit);
+
+    let xml = fs::read_to_string("tests/fixtures/utf8.bsmx")
+        .unwrap()
+        .replace("<F_R_ABV>7.4</F_R_ABV>", "")
+        .replace("<F_R_FG>1.012</F_R_FG>", "<F_R_FG>0</F_R_FG>");
+    let parsed = parse_bsmx(xml.as_bytes()).unwrap();
+    assert_eq!(parsed.abv, 0.0);
+    assert_eq!(parsed.abv_source, AbvSource::Missing);
+}
+
+// The formula is process-wide, so everything that depends on it is in one
+// test
+#[test]
+fn works_out_a_missing_abv_with_the_chosen_formula() {
+    let parsed = recipe("<F_R_ABV>0</F_R_ABV>");
+    assert!(close(parsed.abv, (1.068 - 1.012) * 131.25));
+    assert_eq!(parsed.abv_source, AbvSource::CalculatedStandard);
+    let parsed = recipe("");
+    assert_eq!(parsed.abv_source, AbvSource::CalculatedStandard);
+
+    set_abv_formula(AbvFormula::Accurate);
+    let parsed = recipe("<F_R_ABV>0</F_R_ABV>");
+    assert!(close(
+        parsed.abv,
+        calculate_abv(1.068, 1.012, AbvFormula::Accurate)
+    ));
+    assert_eq!(parsed.abv_source, AbvSource::CalculatedAccurate);
+    set_abv_formula(AbvFormula::Standard);
+
+    // BeerXML without an ABV gets one the same way
+    let mut exported = recipe("<F_R_ABV>7.4</F_R_ABV>");
+    exported.abv = 0.0;
+    let parsed = parse_beerxml(export_beerxml(&exported).as_bytes()).unwrap();
+    assert!(close(parsed.abv, (1.068 - 1.012) * 131.25));
+    assert_eq!(parsed.abv_source, AbvSource::CalculatedStandard);
+}
@@ -0,0 +1,162 @@
+use beer_scape::archive::{Manifest, MANIFEST_FILE};
+use beer_scape::state::State;
+use flate2::read::GzDecoder;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::Read;
+use std::process::{Command, Output};
+use std::time::{Duration, SystemTime};
+use tempfile::TempDir;
+
+fn beerscape(dir: &TempDir, args: &[&str]) -> Output {
+    let output = Command::new(env!("CARGO_BIN_EXE_beerscape"))
+        .current_dir(dir.path())
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    output
+}
+
+/// A collection of three recipes, one with a sidecar, indexed and tagged.
+fn collection() -> TempDir {
+    let dir = TempDir::new().unwrap();
+    let recipes = dir.path().join("recipes");
+    fs::create_dir(&recipes).unwrap();
+    for name in ["1.bsmx", "2.bsmx", "3.bsmx"] {
+        fs::copy("tests/fixtures/utf8.bsmx", recipes.join(name)).unwrap();
+    }
+    fs::write(recipes.join("1.json"), "{}").unwrap();
+    State::default().save(&recipes).unwrap();
+    beerscape(&dir, &["index"]);
+    beerscape(&dir, &["tag", "add", "2.bsmx", "keeper"]);
+    dir
+}
+
+fn zip_contents(path: &std::path::Path) -> BTreeMap<String, Vec<u8>> {
+    let mut zip = zip::ZipArchive::new(File::open(path).unwrap()).unwrap();
+    let mut files = BTreeMap::new();
+    for i in 0..zip.len() {
+        let mut file = zip.by_index(i).unwrap();
+        let mut contents = vec![];
+        file.read_to_end(&mut contents).unwrap();
+        files.insert(file.name().to_string(), contents);
+    }
+    files
+}
+
+fn tar_gz_contents(path: &std::path::Path) -> BTreeMap<String, Vec<u8>> {
+    let mut tar = tar::Archive::new(GzDecoder::new(File::open(path).unwrap()));
+    let mut files = BTreeMap::new();
+    for entry in tar.entries().unwrap() {
+        let mut entry = entry.unwrap();
+        let mut contents = vec![];
+        entry.read_to_end(&mut contents).unwrap();
+        let name = entry.path().unwrap().to_string_lossy().into_owned();
+        files.insert(name, contents);
+    }
+    files
+}
+
+fn manifest(files: &BTreeMap<String, Vec<u8>>) -> Manifest {
+    serde_json::from_slice(&files[MANIFEST_FILE]).unwrap()
+}
+
+#[test]
+fn packs_the_collection_with_a_manifest_in_either_format() {
+    let dir = collection();
+    beerscape(&dir, &["archive", "-o", "backup.zip"]);
+    let output = beerscape(&dir, &["archive", "-o", "backup.tgz"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.starts_with("Archive:\n--------\n"), "{}", stdout);
+    assert!(stdout.contains("\nRecipes: 3\n"), "{}", stdout);
+
+    for files in [
+        zip_contents(&dir.path().join("backup.zip")),
+        tar_gz_contents(&dir.path().join("backup.tgz")),
+    ] {
+        let names: Vec<&str> = files.keys().map(String::as_str).collect();
+        assert_eq!(
+            names,
+            [
+                "manifest.json",
+                "recipes/.beerscape_state.json",
+                "recipes/1.bsmx",
+                "recipes/1.json",
+                "recipes/2.bsmx",
+                "recipes/3.bsmx",
+                "recipes/index.db",
+                "recipes/tags.json",
+            ]
+        );
+        assert_eq!(
+            files["recipes/2.bsmx"],
+            fs::read("tests/fixtures/utf8.bsmx").unwrap()
+        );
+        let manifest = manifest(&files);
+        assert_eq!(manifest.files.len(), 7);
+        for entry in &manifest.files {
+            let contents = &files[&entry.path];
+            assert_eq!(entry.bytes, contents.len() as u64);
+            assert_eq!(entry.sha256, format!("{:x}", Sha256::digest(contents)));
+        }
+    }
+}
+
+#[test]
+fn filters_and_flattens() {
+    let dir = collection();
+    beerscape(&dir, &["migrate-layout", "--to", "style"]);
+    beerscape(
+        &dir,
+        &["archive", "--tag", "keeper", "--flat", "-o", "keepers.zip"],
+    );
+
+    let files = zip_contents(&dir.path().join("keepers.zip"));
+    let names: Vec<&str> = files.keys().map(String::as_str).collect();
+    assert_eq!(names, ["manifest.json", "recipes/2.bsmx"]);
+    // Only archives of the whole collection count for --incremental
+    let state = State::load(&dir.path().join("recipes")).unwrap();
+    assert_eq!(state.last_archive, None);
+
+    beerscape(&dir, &["archive", "--format", "zip", "-o", "all.tar.gz"]);
+    let files = zip_contents(&dir.path().join("all.tar.gz"));
+    assert!(
+        files
+            .keys()
+            .any(|name| name.ends_with("/2.bsmx") && name != "recipes/2.bsmx"),
+        "{:?}",
+        files.keys()
+    );
+}
+
+#[test]
+fn incremental_archives_take_only_what_changed() {
+    let dir = collection();
+    let output = beerscape(&dir, &["archive", "--incremental", "-o", "full.zip"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("\nRecipes: 3\n"), "{}", stdout);
+
+    let recipes = dir.path().join("recipes");
+    fs::copy("tests/fixtures/utf8.bsmx", recipes.join("4.bsmx")).unwrap();
+    File::options()
+        .write(true)
+        .open(recipes.join("4.bsmx"))
+        .unwrap()
+        .set_modified(SystemTime::now() + Duration::from_secs(5))
+        .unwrap();
+    let output = beerscape(&dir, &["archive", "--incremental", "-o", "week.tar.gz"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("\nChanged Since: "), "{}", stdout);
+    assert!(stdout.contains("\nRecipes: 1\n"), "{}", stdout);
+
+    let files = tar_gz_contents(&dir.path().join("week.tar.gz"));
+    assert!(files.contains_key("recipes/4.bsmx"));
+    assert!(!files.contains_key("recipes/1.bsmx"));
+    assert!(manifest(&files).since.is_some());
+}
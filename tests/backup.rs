@@ -0,0 +1,87 @@
+use beer_scape::commands::backup::{snapshots, SNAPSHOT_MANIFEST};
+use std::fs;
+use std::process::Command;
+use tempfile::TempDir;
+
+fn backup(dir: &TempDir) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_beerscape"))
+        .current_dir(dir.path())
+        .args(["backup", "--dest", "backups", "--keep", "2"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+    assert!(
+        output.status.success(),
+        "{}{}",
+        stdout,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    stdout
+}
+
+#[test]
+fn snapshots_share_unchanged_files_and_rotate() {
+    let dir = TempDir::new().unwrap();
+    let recipes = dir.path().join("recipes");
+    fs::create_dir(&recipes).unwrap();
+    fs::copy("tests/fixtures/utf8.bsmx", recipes.join("1.bsmx")).unwrap();
+    fs::write(recipes.join("2.bsmx"), "<Recipe/>").unwrap();
+
+    let out = backup(&dir);
+    assert!(out.contains("Files: 2"), "{}", out);
+    assert!(out.contains("Hard-Linked: 0\n"), "{}", out);
+    assert!(out.contains("Verified: yes"), "{}", out);
+
+    fs::write(
+        recipes.join("2.bsmx"),
+        "<Recipe><F_R_NAME>Mild</F_R_NAME></Recipe>",
+    )
+    .unwrap();
+    let out = backup(&dir);
+    assert!(out.contains("Hard-Linked: 1\n"), "{}", out);
+    assert!(out.contains("Copied: 1 "), "{}", out);
+
+    let taken = snapshots(&dir.path().join("backups")).unwrap();
+    assert_eq!(taken.len(), 2);
+    let (first, second) = (&taken[0].0, &taken[1].0);
+    assert_eq!(
+        fs::read_to_string(first.join("recipes/2.bsmx")).unwrap(),
+        "<Recipe/>"
+    );
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let inode = |path: &std::path::Path| fs::metadata(path).unwrap().ino();
+        assert_eq!(
+            inode(&first.join("recipes/1.bsmx")),
+            inode(&second.join("recipes/1.bsmx"))
+        );
+    }
+
+    // The third snapshot pushes the first out
+    let out = backup(&dir);
+    assert!(out.contains("Pruned: 1\n"), "{}", out);
+    assert!(!first.exists());
+    assert!(second.join("recipes/1.bsmx").exists());
+}
+
+#[test]
+fn a_snapshot_that_failed_verification_is_not_linked_to() {
+    let dir = TempDir::new().unwrap();
+    let recipes = dir.path().join("recipes");
+    fs::create_dir(&recipes).unwrap();
+    fs::copy("tests/fixtures/utf8.bsmx", recipes.join("1.bsmx")).unwrap();
+    backup(&dir);
+
+    let (path, _) = snapshots(&dir.path().join("backups")).unwrap().remove(0);
+    let manifest = fs::read_to_string(path.join(SNAPSHOT_MANIFEST)).unwrap();
+    fs::write(
+        path.join(SNAPSHOT_MANIFEST),
+        manifest.replace("\"verified\": true", "\"verified\": false"),
+    )
+    .unwrap();
+
+    let out = backup(&dir);
+    assert!(out.contains("Hard-Linked: 0\n"), "{}", out);
+    assert!(out.contains("Copied: 1 "), "{}", out);
+}
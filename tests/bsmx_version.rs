@@ -0,0 +1,112 @@
+mod common;
+
+use beer_scape::bsmx::{
+    detect_bsmx_version, parse_bsmx, parse_document, recipe_from_document, BsmxVersion,
+};
+use common::{fixture, fixture_text};
+use std::fs;
+use std::process::Command;
+use tempfile::TempDir;
+
+fn with_root(attributes: &str) -> Vec<u8> {
+    fixture_text()
+        .replacen("<Selections>", &format!("<Selections {}>", attributes), 1)
+        .into_bytes()
+}
+
+#[test]
+fn reads_the_major_version_from_the_root() {
+    assert_eq!(
+        detect_bsmx_version(&with_root("Version=\"2.3.12\"")),
+        BsmxVersion::V2
+    );
+    assert_eq!(
+        detect_bsmx_version(&with_root("BSMX_version='3'")),
+        BsmxVersion::V3
+    );
+    assert_eq!(
+        detect_bsmx_version(&with_root("version=\"v3.0.8\"")),
+        BsmxVersion::V3
+    );
+    assert_eq!(
+        detect_bsmx_version(&with_root("Version=\"4\"")),
+        BsmxVersion::Unknown
+    );
+    assert_eq!(
+        detect_bsmx_version(&with_root("Name=\"2\"")),
+        BsmxVersion::Unknown
+    );
+    assert_eq!(detect_bsmx_version(b"not xml"), BsmxVersion::Unknown);
+}
+
+#[test]
+fn parsed_recipes_record_their_version() {
+    let recipe = parse_bsmx(&with_root("Version=\"3.1\"")).unwrap();
+    assert_eq!(recipe.bsmx_version, BsmxVersion::V3);
+    assert_eq!(recipe.style.name, "Bière de Garde");
+
+    let undeclared = parse_bsmx(&fixture()).unwrap();
+    assert_eq!(undeclared.bsmx_version, BsmxVersion::Unknown);
+    assert_eq!(recipe.name, undeclared.name);
+    assert_eq!(recipe.hops, undeclared.hops);
+}
+
+#[test]
+fn reads_a_parsed_document_like_the_bytes() {
+    let xml = with_root("Version=\"3.1\"");
+    let document = parse_document(&xml).unwrap();
+    let recipe = recipe_from_document(&document).unwrap();
+    assert_eq!(recipe, parse_bsmx(&xml).unwrap());
+    assert_eq!(recipe.bsmx_version, BsmxVersion::V3);
+}
+
+#[test]
+fn reads_every_version_by_the_same_names() {
+    let notes = |attributes: &str| {
+        let xml = String::from_utf8(with_root(attributes))
+            .unwrap()
+            .replace("<F_R_BREWER>", "<NOTES>Carried over</NOTES><F_R_BREWER>");
+        parse_bsmx(xml.as_bytes()).unwrap().notes
+    };
+    assert_eq!(notes("Version=\"2\"").as_deref(), Some("Carried over"));
+    assert_eq!(notes("Version=\"3\"").as_deref(), Some("Carried over"));
+    assert_eq!(notes("").as_deref(), Some("Carried over"));
+}
+
+#[test]
+fn reports_recipes_by_version() {
+    let dir = TempDir::new().unwrap();
+    let recipes = dir.path().join("recipes");
+    fs::create_dir(&recipes).unwrap();
+    fs::write(recipes.join("1.bsmx"), with_root("Version=\"2.3\"")).unwrap();
+    fs::write(recipes.join("2.bsmx"), with_root("Version=\"3\"")).unwrap();
+    fs::write(recipes.join("3.bsmx"), with_root("Version=\"3.1\"")).unwrap();
+    fs::write(recipes.join("4.bsmx"), with_root("")).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_beerscape"))
+        .current_dir(dir.path())
+        .args(["report", "versions"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    for line in [
+        "BeerSmith 2         1   25.0%",
+        "BeerSmith 3         2   50.0%",
+        "unknown             1   25.0%",
+        "Recipes: 4",
+    ] {
+        assert!(stdout.contains(line), "{}", stdout);
+    }
+}
+
+#[test]
+fn sidecars_without_a_version_still_load() {
+    let recipe = parse_bsmx(&with_root("Version=\"2\"")).unwrap();
+    let json = serde_json::to_string(&recipe).unwrap();
+    assert!(json.contains("\"bsmx_version\":\"v2\""), "{}", json);
+
+    let old = json.replace(",\"bsmx_version\":\"v2\"", "");
+    let loaded: beer_scape::bsmx::Recipe = serde_json::from_str(&old).unwrap();
+    assert_eq!(loaded.bsmx_version, BsmxVersion::Unknown);
+}
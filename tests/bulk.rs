@@ -0,0 +1,146 @@
+use beer_scape::bsmx::{parse_bsmx, parse_document, write_document};
+use beer_scape::bulk::{recipes_by_id, BulkFetch};
+use std::fs;
+use std::process::Stdio;
+use tempfile::TempDir;
+use tokio::process::Command;
+use wiremock::matchers::{method, path, query_param};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+fn recipe(name: &str, id: Option<u32>) -> String {
+    let id = id.map_or(String::new(), |id| format!("<F_R_ID>{}</F_R_ID>", id));
+    format!(
+        "<Recipe>{}<F_R_NAME>{}</F_R_NAME><F_R_OG>1.050</F_R_OG></Recipe>",
+        id, name
+    )
+}
+
+fn export(recipes: &[String]) -> String {
+    format!("<Selections><Data>{}</Data></Selections>", recipes.concat())
+}
+
+fn names(export: &str, ids: &[u32]) -> Vec<(u32, String)> {
+    let root = parse_document(export.as_bytes()).unwrap();
+    let mut found: Vec<(u32, String)> = recipes_by_id(&root, ids)
+        .into_iter()
+        .map(|(id, document)| {
+            let xml = write_document(&document);
+            (id, parse_bsmx(xml.as_bytes()).unwrap().name)
+        })
+        .collect();
+    found.sort();
+    found
+}
+
+#[test]
+fn fills_in_the_url_template() {
+    let bulk = BulkFetch::new(20, "{base_url}/export.php?ids={ids}").unwrap();
+    assert_eq!(
+        bulk.url("https://example.com", &[3, 1, 4]),
+        "https://example.com/export.php?ids=3,1,4"
+    );
+    assert!(BulkFetch::new(20, "{base_url}/export.php").is_err());
+}
+
+#[test]
+fn attributes_recipes_by_the_id_they_carry() {
+    let doc = export(&[recipe("Stout", Some(3)), recipe("Porter", Some(1))]);
+    assert_eq!(
+        names(&doc, &[1, 2, 3]),
+        vec![(1, "Porter".to_string()), (3, "Stout".to_string())]
+    );
+    // One recipe isn't split, but still counts
+    let doc = export(&[recipe("Stout", Some(3))]);
+    assert_eq!(names(&doc, &[3, 4]), vec![(3, "Stout".to_string())]);
+    // IDs not asked for are dropped
+    assert!(names(&doc, &[4]).is_empty());
+}
+
+#[test]
+fn falls_back_to_order_without_ids() {
+    let doc = export(&[recipe("Stout", None), recipe("Porter", None)]);
+    assert_eq!(
+        names(&doc, &[7, 8]),
+        vec![(7, "Stout".to_string()), (8, "Porter".to_string())]
+    );
+    // With a recipe missing, there's no telling which one it was
+    assert!(names(&doc, &[7, 8, 9]).is_empty());
+    assert!(names(&export(&[]), &[7]).is_empty());
+}
+
+fn beerscape(dir: &TempDir, base_url: &str, args: &[&str]) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_beerscape"));
+    command
+        .current_dir(dir.path())
+        .args(["--base-url", base_url, "--fetch-mode", "bulk"])
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    command
+}
+
+#[tokio::test]
+async fn bulk_run_saves_each_recipe_and_counts_missing_ids() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/export.php"))
+        .and(query_param("ids", "1,2,3"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(export(&[
+            recipe("Porter", Some(1)),
+            recipe("Stout", Some(3)),
+        ])))
+        .expect(1)
+        .mount(&server)
+        .await;
+    let dir = TempDir::new().unwrap();
+    let output = beerscape(&dir, &server.uri(), &["--id-range", "1-3"])
+        .output()
+        .await
+        .unwrap();
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.ends_with("complete: ids-exhausted, 2 downloaded, 1 failed\n"),
+        "{}",
+        stderr
+    );
+    let saved = fs::read(dir.path().join("recipes/3.bsmx")).unwrap();
+    assert_eq!(parse_bsmx(&saved).unwrap().name, "Stout");
+    assert!(!dir.path().join("recipes/2.bsmx").exists());
+}
+
+#[tokio::test]
+async fn falls_back_to_single_requests_when_the_export_fails() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/export.php"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/download.php"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_bytes(fs::read("tests/fixtures/utf8.bsmx").unwrap()),
+        )
+        .mount(&server)
+        .await;
+    let dir = TempDir::new().unwrap();
+    let output = beerscape(
+        &dir,
+        &server.uri(),
+        &["--id-range", "1-4", "--bulk-size", "2"],
+    )
+    .output()
+    .await
+    .unwrap();
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.ends_with("complete: ids-exhausted, 4 downloaded, 0 failed\n"),
+        "{}",
+        stderr
+    );
+    assert!(dir.path().join("recipes/4.bsmx").exists());
+}
@@ -0,0 +1,79 @@
+use beer_scape::bsmx::{Fermentable, Recipe};
+use beer_scape::color::{morey_srm, paint, recipe_srm, srm_to_hex, srm_to_rgb};
+use std::fs;
+use std::process::Command;
+use tempfile::TempDir;
+
+#[test]
+fn matches_the_reference_chart_at_whole_srm() {
+    for (srm, hex) in [
+        (1.0, "#FFE699"),
+        (2.0, "#FFD878"),
+        (4.0, "#FFBF42"),
+        (6.0, "#F8A600"),
+        (10.0, "#DE7C00"),
+        (17.0, "#B04500"),
+        (20.0, "#9B3200"),
+        (29.0, "#660D00"),
+        (35.0, "#470606"),
+        (40.0, "#36080A"),
+    ] {
+        assert_eq!(srm_to_hex(srm), hex, "{} SRM", srm);
+    }
+    assert_eq!(srm_to_rgb(6.0), (0xF8, 0xA6, 0x00));
+}
+
+#[test]
+fn interpolates_between_and_clamps_outside_the_chart() {
+    // Halfway between #FFE699 and #FFD878
+    assert_eq!(srm_to_rgb(1.5), (0xFF, 0xDF, 0x89));
+    assert_eq!(srm_to_hex(0.0), "#FFE699");
+    assert_eq!(srm_to_hex(f64::NAN), "#FFE699");
+    assert_eq!(srm_to_hex(80.0), "#36080A");
+    assert_eq!(paint("█", 1.0), "\x1b[38;2;255;230;153m█\x1b[0m");
+}
+
+#[test]
+fn estimates_unrecorded_color_with_morey() {
+    assert_eq!(morey_srm(0.0), 0.0);
+    assert!((morey_srm(10.0) - 7.24).abs() < 0.01, "{}", morey_srm(10.0));
+
+    // 5 lb of 10 °L malt in 5 gallons is 10 MCU
+    let recipe = Recipe {
+        batch_size_l: 5.0 * 3.785_41,
+        fermentables: vec![Fermentable {
+            name: "Crystal 10".to_string(),
+            amount_kg: 5.0 / 2.204_62,
+            color_srm: 10.0,
+        }],
+        ..Recipe::default()
+    };
+    assert!((recipe_srm(&recipe) - morey_srm(10.0)).abs() < 1e-9);
+    let recorded = Recipe {
+        color_srm: 3.5,
+        ..recipe
+    };
+    assert_eq!(recipe_srm(&recorded), 3.5);
+    assert_eq!(recipe_srm(&Recipe::default()), 0.0);
+}
+
+#[test]
+fn color_report_is_plain_off_a_terminal() {
+    let dir = TempDir::new().unwrap();
+    let recipes = dir.path().join("recipes");
+    fs::create_dir(&recipes).unwrap();
+    fs::copy("tests/fixtures/utf8.bsmx", recipes.join("1.bsmx")).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_beerscape"))
+        .current_dir(dir.path())
+        .args(["report", "color"])
+        .output()
+        .unwrap();
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", stdout);
+    assert!(stdout.starts_with("Color:\n------\n"), "{}", stdout);
+    assert!(stdout.contains("█"), "{}", stdout);
+    assert!(!stdout.contains('\x1b'), "{}", stdout);
+    assert!(stdout.contains("\nRecipes: 1\n"), "{}", stdout);
+}
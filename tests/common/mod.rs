@@ -0,0 +1,65 @@
+//! Helpers shared by the integration tests. Each test crate uses its own
+//! share of them.
+#![allow(dead_code)]
+
+use std::fs;
+use std::process::{Output, Stdio};
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::process::Command;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+/// The UTF-8 recipe fixture, "Bière de Garde à 12°".
+pub fn fixture() -> Vec<u8> {
+    fs::read("tests/fixtures/utf8.bsmx").unwrap()
+}
+
+/// [`fixture`], as text to edit.
+pub fn fixture_text() -> String {
+    fs::read_to_string("tests/fixtures/utf8.bsmx").unwrap()
+}
+
+/// A site where every ID has the [`fixture`] recipe, sent after `delay`.
+pub async fn serve_recipes(delay: Duration) -> MockServer {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/download.php"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_bytes(fixture())
+                .set_delay(delay),
+        )
+        .mount(&server)
+        .await;
+    server
+}
+
+/// A site where no ID has a recipe.
+pub async fn serve_nothing() -> MockServer {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(404))
+        .mount(&server)
+        .await;
+    server
+}
+
+/// A download run in a fresh directory, with output captured.
+pub fn beerscape(dir: &TempDir, base_url: &str, args: &[&str]) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_beerscape"));
+    command
+        .current_dir(dir.path())
+        .args(["--base-url", base_url])
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    command
+}
+
+/// The one-line status a run ends with.
+pub fn status_line(output: &Output) -> String {
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    stderr.lines().last().unwrap_or_default().to_string()
+}
@@ -0,0 +1,133 @@
+use beer_scape::bsmx::{Fermentable, Hop, Recipe, Style};
+use beer_scape::commands::compare::similarity;
+use std::fs;
+use std::process::Command;
+use tempfile::TempDir;
+
+fn recipe(style: &str, malts: &[&str], hops: &[&str], abv: f64, ibu: f64) -> Recipe {
+    Recipe {
+        style: Style {
+            name: style.to_string(),
+            ..Default::default()
+        },
+        fermentables: malts
+            .iter()
+            .map(|name| Fermentable {
+                name: name.to_string(),
+                ..Default::default()
+            })
+            .collect(),
+        hops: hops
+            .iter()
+            .map(|name| Hop {
+                name: name.to_string(),
+                ..Default::default()
+            })
+            .collect(),
+        abv,
+        ibu,
+        og: 1.050,
+        color_srm: 6.0,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn identical_recipes_score_one() {
+    let ipa = recipe("American IPA", &["Pale Malt"], &["Cascade"], 6.5, 60.0);
+    let score = similarity(&ipa, &ipa);
+    assert_eq!(score.overall, 1.0);
+}
+
+#[test]
+fn components_are_weighted() {
+    let ipa = recipe(
+        "American IPA",
+        &["Pale Malt", "Crystal 40"],
+        &["Cascade"],
+        6.5,
+        60.0,
+    );
+    let hazy = recipe(
+        "Hazy IPA",
+        &["pale malt"],
+        &["Cascade", "Simcoe"],
+        6.5,
+        60.0,
+    );
+    let score = similarity(&ipa, &hazy);
+
+    // pale malt and cascade are shared out of four names
+    assert_eq!(score.ingredients, 0.5);
+    // Both are in the IPA category
+    assert_eq!(score.style, 0.5);
+    assert_eq!(score.numeric, 1.0);
+    assert!((score.overall - (0.4 * 0.5 + 0.3 * 0.5 + 0.3)).abs() < 1e-9);
+}
+
+#[test]
+fn unrelated_styles_score_zero() {
+    let stout = recipe("Irish Stout", &[], &[], 4.2, 35.0);
+    let pils = recipe("German Pils", &[], &[], 4.8, 35.0);
+    assert_eq!(similarity(&stout, &pils).style, 0.0);
+}
+
+#[test]
+fn compares_directories() {
+    let fixture = fs::read_to_string("tests/fixtures/utf8.bsmx").unwrap();
+    let with_malt = |malt: &str| fixture.replace("Pilsner (2 Row) Bel", malt);
+    let dir = TempDir::new().unwrap();
+    for (name, contents) in [
+        ("first/1.bsmx", fixture.clone()),
+        ("first/2.bsmx", with_malt("Munich Malt")),
+        ("first/3.bsmx", with_malt("Wheat Malt")),
+        (
+            "first/5.bsmx",
+            with_malt("Vienna Malt").replace("Bière de Garde à 12°", "Farmhouse Garde"),
+        ),
+        ("second/1.bsmx", fixture.clone()),
+        ("second/7.bsmx", with_malt("Munich Malt")),
+        ("second/3.bsmx", with_malt("Rye Malt")),
+        ("second/9.bsmx", "<Recipe/>".to_string()),
+    ] {
+        let path = dir.path().join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    let output = Command::new(env!("CARGO_BIN_EXE_beerscape"))
+        .current_dir(dir.path())
+        .args(["compare", "first", "second", "--csv", "compare.csv"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", stdout);
+    for line in [
+        "Identical: 1\n",
+        "Only in First: 1\n",
+        "Only in Second: 1\n",
+        "Same Name, Different Content: 1\n",
+        "Same Content, Different Name: 1\n",
+        // 3.bsmx is the same recipe with another malt on each side
+        "Same Recipe by Name and Brewer: 1\n",
+    ] {
+        assert!(stdout.contains(line), "{}", stdout);
+    }
+    let csv = fs::read_to_string(dir.path().join("compare.csv")).unwrap();
+    assert!(
+        csv.starts_with("status,first,second,same_recipe_as\n"),
+        "{}",
+        csv
+    );
+    assert!(
+        csv.contains("different-content,first/3.bsmx,second/3.bsmx,second/3.bsmx\n"),
+        "{}",
+        csv
+    );
+    assert!(
+        csv.contains("renamed,first/2.bsmx,second/7.bsmx,\n"),
+        "{}",
+        csv
+    );
+    assert!(csv.contains("only-in-second,,second/9.bsmx,\n"), "{}", csv);
+}
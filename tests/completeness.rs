@@ -0,0 +1,77 @@
+use beer_scape::bsmx::{parse_bsmx, Fermentable, Hop, MashStep, Recipe, Yeast};
+use beer_scape::completeness::{completeness_score, score_distribution};
+use std::fs;
+
+fn full_recipe() -> Recipe {
+    let mut recipe = Recipe {
+        name: "Best Bitter".to_string(),
+        og: 1.040,
+        fg: 1.010,
+        fermentables: vec![Fermentable::default()],
+        hops: vec![Hop::default()],
+        yeasts: vec![Yeast::default()],
+        mash_steps: vec![MashStep::default()],
+        ..Recipe::default()
+    };
+    recipe.style.name = "Ordinary Bitter".to_string();
+    recipe
+}
+
+#[test]
+fn scores_each_part() {
+    assert_eq!(completeness_score(&Recipe::default()), 0.0);
+    assert_eq!(completeness_score(&full_recipe()), 1.0);
+
+    let mut stub = Recipe {
+        name: "Stub".to_string(),
+        ..Recipe::default()
+    };
+    stub.style.name = "IPA".to_string();
+    assert_eq!(completeness_score(&stub), 0.2);
+
+    let no_hops = Recipe {
+        hops: Vec::new(),
+        ..full_recipe()
+    };
+    assert_eq!(completeness_score(&no_hops), 0.8);
+    let no_mash = Recipe {
+        mash_steps: Vec::new(),
+        ..full_recipe()
+    };
+    assert_eq!(completeness_score(&no_mash), 0.9);
+
+    // Blank names don't count
+    let blank = Recipe {
+        name: "  ".to_string(),
+        ..Recipe::default()
+    };
+    assert_eq!(completeness_score(&blank), 0.0);
+}
+
+#[test]
+fn counts_recipes_per_score() {
+    let recipes = [Recipe::default(), full_recipe(), full_recipe()];
+    let counts = score_distribution(&recipes);
+    assert_eq!(counts[0], 1);
+    assert_eq!(counts[10], 2);
+    assert_eq!(counts.iter().sum::<usize>(), 3);
+}
+
+#[test]
+fn reads_mash_steps() {
+    let xml = r#"<Recipe><F_R_NAME>Mashed</F_R_NAME><F_R_MASH><F_MH_NAME>Single Infusion</F_MH_NAME>
+        <steps><Data><MashStep><F_MS_NAME>Saccharification</F_MS_NAME><F_MS_STEP_TEMP>152</F_MS_STEP_TEMP>
+        <F_MS_STEP_TIME>60</F_MS_STEP_TIME></MashStep><MashStep><F_MS_NAME>Mash Out</F_MS_NAME>
+        <F_MS_STEP_TEMP>168</F_MS_STEP_TEMP><F_MS_STEP_TIME>10</F_MS_STEP_TIME></MashStep></Data></steps>
+        </F_R_MASH></Recipe>"#;
+    let recipe = parse_bsmx(xml.as_bytes()).unwrap();
+    assert_eq!(recipe.mash_steps.len(), 2);
+    assert_eq!(recipe.mash_steps[0].name, "Saccharification");
+    assert!((recipe.mash_steps[0].temp_c - 66.67).abs() < 0.01);
+    assert_eq!(recipe.mash_steps[1].time_min, 10.0);
+
+    // The fixture is an extract-style recipe with no mash
+    let fixture = parse_bsmx(&fs::read("tests/fixtures/utf8.bsmx").unwrap()).unwrap();
+    assert!(fixture.mash_steps.is_empty());
+    assert_eq!(completeness_score(&fixture), 0.9);
+}
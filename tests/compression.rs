@@ -0,0 +1,197 @@
+mod common;
+
+use beer_scape::bsmx::parse_bsmx;
+use beer_scape::cli::{Compression, StorageLayout};
+use beer_scape::collection::{read_recipe, recipe_files_in, scan_stems, sidecar_path};
+use beer_scape::compression::{bsmx_stem, is_bsmx, read_bsmx, recompress};
+use beer_scape::download::{build_client, download_recipe, DownloadConfig};
+use beer_scape::index::{Index, IndexEntry};
+use common::fixture;
+use indicatif::ProgressBar;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+use tempfile::TempDir;
+use wiremock::matchers::{method, path, query_param};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+#[test]
+fn round_trips_each_compression() {
+    let dir = TempDir::new().unwrap();
+    for compression in [Compression::None, Compression::Gzip, Compression::Zstd] {
+        let path = dir
+            .path()
+            .join(format!("1.bsmx{}", compression.extension()));
+        compression.write(&path, &fixture(), None).unwrap();
+        assert_eq!(Compression::of(&path), compression);
+        assert_eq!(read_bsmx(&path).unwrap(), fixture(), "{:?}", compression);
+    }
+    let zstd = fs::metadata(dir.path().join("1.bsmx.zst")).unwrap().len();
+    assert!(zstd < fixture().len() as u64);
+}
+
+#[test]
+fn recognises_recipe_files_by_name() {
+    assert_eq!(bsmx_stem(Path::new("42.bsmx")), Some("42"));
+    assert_eq!(bsmx_stem(Path::new("porter/42.bsmx.zst")), Some("42"));
+    assert_eq!(bsmx_stem(Path::new("42.bsmx.gz")), Some("42"));
+    assert!(!is_bsmx(Path::new("42.bsmx.part")));
+    assert!(!is_bsmx(Path::new("42.json")));
+    assert!(!is_bsmx(Path::new(".bsmx")));
+    assert_eq!(sidecar_path(Path::new("42.bsmx.zst")), Path::new("42.json"));
+}
+
+#[test]
+fn scans_compressed_files_alongside_plain_ones() {
+    let dir = TempDir::new().unwrap();
+    fs::write(dir.path().join("1.bsmx"), fixture()).unwrap();
+    Compression::Zstd
+        .write(&dir.path().join("2.bsmx.zst"), &fixture(), None)
+        .unwrap();
+    fs::write(dir.path().join("3.bsmx.part"), "<Rec").unwrap();
+
+    let mut files = recipe_files_in(dir.path(), StorageLayout::Flat).unwrap();
+    files.sort();
+    assert_eq!(
+        files,
+        vec![dir.path().join("1.bsmx"), dir.path().join("2.bsmx.zst")]
+    );
+    let stems = scan_stems(dir.path(), StorageLayout::Flat, &ProgressBar::hidden()).unwrap();
+    assert_eq!(stems.len(), 2);
+    assert!(stems.contains("2"));
+    assert_eq!(
+        read_recipe(&dir.path().join("2.bsmx.zst")).unwrap(),
+        parse_bsmx(&fixture()).unwrap()
+    );
+}
+
+#[test]
+fn recompress_renames_files_and_index_entries() {
+    let dir = TempDir::new().unwrap();
+    let index = Index::open(dir.path()).unwrap();
+    let recipe = parse_bsmx(&fixture()).unwrap();
+    for name in ["1.bsmx", "2.bsmx"] {
+        fs::write(dir.path().join(name), fixture()).unwrap();
+        index.upsert(&IndexEntry::new(name, &recipe)).unwrap();
+    }
+    Compression::Zstd
+        .write(&dir.path().join("3.bsmx.zst"), &fixture(), None)
+        .unwrap();
+
+    let result = recompress(
+        dir.path(),
+        StorageLayout::Flat,
+        &index,
+        Compression::Zstd,
+        None,
+    )
+    .unwrap();
+    assert_eq!(result.rewritten, 2);
+    assert_eq!(result.unchanged, 1);
+    assert!(result.skipped.is_empty());
+    assert!(result.bytes_after < result.bytes_before);
+    assert!(!dir.path().join("1.bsmx").exists());
+    assert_eq!(
+        read_bsmx(&dir.path().join("1.bsmx.zst")).unwrap(),
+        fixture()
+    );
+    let mut names: Vec<String> = index
+        .entries()
+        .unwrap()
+        .into_iter()
+        .map(|e| e.filename)
+        .collect();
+    names.sort();
+    assert_eq!(names, vec!["1.bsmx.zst", "2.bsmx.zst"]);
+
+    let back = recompress(
+        dir.path(),
+        StorageLayout::Flat,
+        &index,
+        Compression::None,
+        None,
+    )
+    .unwrap();
+    assert_eq!(back.rewritten, 3);
+    assert_eq!(fs::read(dir.path().join("3.bsmx")).unwrap(), fixture());
+}
+
+#[test]
+fn recompress_leaves_files_whose_target_exists() {
+    let dir = TempDir::new().unwrap();
+    let index = Index::open(dir.path()).unwrap();
+    fs::write(dir.path().join("1.bsmx"), fixture()).unwrap();
+    fs::write(dir.path().join("1.bsmx.gz"), "not gzip").unwrap();
+
+    let result = recompress(
+        dir.path(),
+        StorageLayout::Flat,
+        &index,
+        Compression::Gzip,
+        None,
+    )
+    .unwrap();
+    assert_eq!(result.rewritten, 0);
+    assert_eq!(result.skipped.len(), 1);
+    assert_eq!(fs::read(dir.path().join("1.bsmx")).unwrap(), fixture());
+}
+
+#[test]
+fn recompress_records_both_sizes_in_the_index() {
+    let dir = TempDir::new().unwrap();
+    let index = Index::open(dir.path()).unwrap();
+    let path = dir.path().join("1.bsmx");
+    fs::write(&path, fixture()).unwrap();
+    let entry = IndexEntry::new("1.bsmx", &parse_bsmx(&fixture()).unwrap()).with_sizes(&path);
+    assert_eq!(entry.stored_bytes, Some(fixture().len() as u64));
+    index.upsert(&entry).unwrap();
+
+    let result = recompress(
+        dir.path(),
+        StorageLayout::Flat,
+        &index,
+        Compression::Zstd,
+        Some(19),
+    )
+    .unwrap();
+    assert_eq!(result.rewritten, 1);
+    assert!(!dir.path().join("1.bsmx.zst.tmp").exists());
+    let entry = index.get("1.bsmx.zst").unwrap().unwrap();
+    let stored = fs::metadata(dir.path().join("1.bsmx.zst")).unwrap().len();
+    assert_eq!(entry.stored_bytes, Some(stored));
+    assert_eq!(entry.bsmx_bytes, Some(fixture().len() as u64));
+    assert!(stored < fixture().len() as u64);
+}
+
+#[tokio::test]
+async fn downloads_are_written_compressed() {
+    let server = MockServer::start().await;
+    let dir = TempDir::new().unwrap();
+    Mock::given(method("GET"))
+        .and(path("/download.php"))
+        .and(query_param("id", "42"))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(fixture()))
+        .mount(&server)
+        .await;
+    let config = DownloadConfig {
+        base_url: server.uri(),
+        output_dir: dir.path().to_path_buf(),
+        request_timeout: Duration::from_millis(500),
+        sidecar: true,
+        compression: Compression::Zstd,
+        ..Default::default()
+    };
+
+    let client = build_client(&config).unwrap();
+    let info = download_recipe(&client, &config, 42)
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(info.filename, "42.bsmx.zst");
+    assert_eq!(
+        read_bsmx(&dir.path().join("42.bsmx.zst")).unwrap(),
+        fixture()
+    );
+    assert!(dir.path().join("42.json").exists());
+}
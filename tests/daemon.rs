@@ -0,0 +1,154 @@
+use beer_scape::commands::daemon::{backoff, jittered, load_status, LOCK_FILE, STATUS_FILE};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::fs;
+use std::path::Path;
+use std::process::{self, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+
+fn with_fixtures() -> TempDir {
+    let dir = TempDir::new().unwrap();
+    fs::create_dir(dir.path().join("fx")).unwrap();
+    fs::copy("tests/fixtures/utf8.bsmx", dir.path().join("fx/1.bsmx")).unwrap();
+    dir
+}
+
+fn daemon(dir: &TempDir, args: &[&str]) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_beerscape"));
+    command
+        .current_dir(dir.path())
+        .args([
+            "--test-mode",
+            "fx",
+            "--mock-latency-ms",
+            "0",
+            "--no-progress",
+        ])
+        .args(["--max-attempts", "20", "daemon", "--target", "5"])
+        .args(args)
+        .stdin(Stdio::null());
+    command
+}
+
+#[test]
+fn backs_off_and_jitters_the_interval() {
+    let hour = Duration::from_secs(3600);
+    let max = 24 * hour;
+    assert_eq!(backoff(6 * hour, 0, max), 6 * hour);
+    assert_eq!(backoff(6 * hour, 1, max), 12 * hour);
+    assert_eq!(backoff(6 * hour, 3, max), max);
+    assert_eq!(backoff(6 * hour, 100, max), max);
+    // A cap below the interval never shortens it
+    assert_eq!(backoff(48 * hour, 2, max), 48 * hour);
+
+    let mut rng = StdRng::seed_from_u64(3);
+    let waits: Vec<Duration> = (0..200)
+        .map(|_| jittered(6 * hour, 0.1, &mut rng))
+        .collect();
+    assert!(waits
+        .iter()
+        .all(|wait| (5.4 * 3600.0..=6.6 * 3600.0).contains(&wait.as_secs_f64())));
+    assert!(waits.iter().any(|wait| *wait < 6 * hour));
+    assert!(waits.iter().any(|wait| *wait > 6 * hour));
+    assert_eq!(jittered(6 * hour, 0.0, &mut rng), 6 * hour);
+}
+
+#[test]
+fn runs_cycles_and_records_the_last() {
+    let dir = with_fixtures();
+    let output = daemon(
+        &dir,
+        &["--interval", "1s", "--jitter", "0", "--max-cycles", "2"],
+    )
+    .output()
+    .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let status = load_status(&dir.path().join(STATUS_FILE)).unwrap().unwrap();
+    assert_eq!(status.state, "stopped");
+    assert_eq!(status.cycles, 2);
+    let last = status.last_cycle.unwrap();
+    assert_eq!(last.number, 2);
+    assert!(last.healthy);
+    // The first cycle reached the target, so the second only fetched the
+    // newest IDs
+    assert!(last
+        .newest
+        .unwrap()
+        .starts_with("partial: max-attempts-reached"));
+    assert!(last.crawl.is_none());
+    assert!(last.recipes >= 5);
+    assert!(!dir.path().join(LOCK_FILE).exists());
+
+    let output = Command::new(env!("CARGO_BIN_EXE_beerscape"))
+        .current_dir(dir.path())
+        .args(["daemon", "--status"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("State: stopped"), "{}", stdout);
+    assert!(stdout.contains("Last Cycle: 2"), "{}", stdout);
+    assert!(stdout.contains("Random Crawl: skipped"), "{}", stdout);
+}
+
+#[test]
+fn skips_a_cycle_while_another_process_holds_the_lock() {
+    let dir = with_fixtures();
+    fs::create_dir(dir.path().join(".beerscape")).unwrap();
+    // This test's own process, which is certainly running
+    fs::write(dir.path().join(LOCK_FILE), process::id().to_string()).unwrap();
+
+    let output = daemon(&dir, &["--max-cycles", "1"]).output().unwrap();
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Another process is running a cycle"),
+        "{}",
+        stderr
+    );
+    assert!(!dir.path().join("recipes").exists());
+    assert!(dir.path().join(LOCK_FILE).exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn stops_cleanly_on_sigterm_while_asleep() {
+    let dir = with_fixtures();
+    let mut child = daemon(&dir, &["--interval", "1h"])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let status_file = dir.path().join(STATUS_FILE);
+    let started = Instant::now();
+    while !is_sleeping(&status_file) {
+        assert!(started.elapsed() < Duration::from_secs(60));
+        thread::sleep(Duration::from_millis(50));
+    }
+
+    let killed = Command::new("kill")
+        .args(["-TERM", &child.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(killed.success());
+    assert!(child.wait().unwrap().success());
+
+    let status = load_status(&status_file).unwrap().unwrap();
+    assert_eq!(status.state, "stopped");
+    assert_eq!(status.cycles, 1);
+    assert!(status.next_wake.is_none());
+}
+
+fn is_sleeping(path: &Path) -> bool {
+    // Not there at all until the daemon gets going
+    load_status(path)
+        .ok()
+        .flatten()
+        .is_some_and(|status| status.state == "sleeping")
+}
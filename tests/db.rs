@@ -0,0 +1,241 @@
+mod common;
+
+use beer_scape::bsmx::BsmxVersion;
+use beer_scape::db::{ActivityDb, Attempt, Counts, RecipeQuery};
+use chrono::{Duration, Utc};
+use common::{beerscape, fixture, serve_recipes};
+use tempfile::TempDir;
+
+#[test]
+fn logs_each_download_once() {
+    let dir = TempDir::new().unwrap();
+    let db = ActivityDb::open(&dir.path().join("activity.db")).unwrap();
+    let url = "https://example.com/download.php?id=42";
+
+    assert!(db
+        .record_file(Some(42), Some(url), "42.bsmx", &fixture(), Utc::now())
+        .unwrap());
+    assert!(!db
+        .record_file(Some(42), Some(url), "42.bsmx", &fixture(), Utc::now())
+        .unwrap());
+    // Not a recipe, so it's logged as a download that didn't parse
+    assert!(db
+        .record_file(Some(43), None, "43.bsmx", b"<html/>", Utc::now())
+        .unwrap());
+    assert_eq!(
+        db.counts().unwrap(),
+        Counts {
+            downloads: 2,
+            attempts: 0,
+            recipes: 1,
+        }
+    );
+
+    let attempt = Attempt {
+        id: 7,
+        attempted_at: Utc::now(),
+        http_status: Some(404),
+        error_kind: Some("not-found".to_string()),
+    };
+    assert!(db.record_attempt(&attempt).unwrap());
+    assert!(!db.record_attempt(&attempt).unwrap());
+}
+
+#[test]
+fn searches_with_where_clauses() {
+    let dir = TempDir::new().unwrap();
+    let db = ActivityDb::open(&dir.path().join("activity.db")).unwrap();
+    db.record_file(Some(42), None, "42.bsmx", &fixture(), Utc::now())
+        .unwrap();
+
+    let everything = db.search(&RecipeQuery::default()).unwrap();
+    assert_eq!(everything.len(), 1);
+    assert_eq!(everything[0].name, "Bière de Garde à 12°");
+    assert_eq!(everything[0].recipe_id, Some(42));
+
+    let matches = |query: RecipeQuery| db.search(&query).unwrap().len();
+    assert_eq!(
+        matches(RecipeQuery {
+            style: Some("de garde".to_string()),
+            min_abv: Some(7.0),
+            ..Default::default()
+        }),
+        1
+    );
+    assert_eq!(
+        matches(RecipeQuery {
+            max_abv: Some(5.0),
+            ..Default::default()
+        }),
+        0
+    );
+    // LIKE wildcards in the query are taken literally
+    assert_eq!(
+        matches(RecipeQuery {
+            brewer: Some("%".to_string()),
+            ..Default::default()
+        }),
+        0
+    );
+}
+
+#[test]
+fn searches_notes_ignoring_case() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("activity.db");
+    // A database from before notes were stored
+    let conn = rusqlite::Connection::open(&path).unwrap();
+    conn.execute_batch(
+        "CREATE TABLE recipes (
+            filename TEXT PRIMARY KEY, recipe_id INTEGER, name TEXT NOT NULL,
+            brewer TEXT NOT NULL, style_name TEXT NOT NULL,
+            style_category TEXT NOT NULL, style_number TEXT NOT NULL,
+            style_letter TEXT NOT NULL, og REAL NOT NULL, fg REAL NOT NULL,
+            abv REAL NOT NULL, ibu REAL NOT NULL, color_srm REAL NOT NULL,
+            batch_size_l REAL NOT NULL, boil_time_min REAL NOT NULL,
+            bsmx_version TEXT NOT NULL, fermentables TEXT NOT NULL,
+            hops TEXT NOT NULL, yeasts TEXT NOT NULL, mash_steps TEXT NOT NULL
+        );",
+    )
+    .unwrap();
+    drop(conn);
+
+    let db = ActivityDb::open(&path).unwrap();
+    let plain = String::from_utf8(fixture()).unwrap();
+    let with_notes = |notes: &str| {
+        plain
+            .replace("<F_R_BREWER>", &format!("{}<F_R_BREWER>", notes))
+            .into_bytes()
+    };
+    db.record_file(
+        Some(1),
+        None,
+        "1.bsmx",
+        &with_notes("<F_R_NOTES>Add the Brett at bottling</F_R_NOTES>"),
+        Utc::now(),
+    )
+    .unwrap();
+    db.record_file(
+        Some(2),
+        None,
+        "2.bsmx",
+        &with_notes("<F_R_TASTE_NOTES>Funky, BRETT forward</F_R_TASTE_NOTES>"),
+        Utc::now(),
+    )
+    .unwrap();
+    db.record_file(Some(3), None, "3.bsmx", &fixture(), Utc::now())
+        .unwrap();
+    db.record_file(
+        Some(4),
+        None,
+        "4.bsmx",
+        &with_notes("<F_R_NOTES>Une BIÈRE de garde</F_R_NOTES>"),
+        Utc::now(),
+    )
+    .unwrap();
+
+    let found = |text: &str| -> Vec<String> {
+        db.search(&RecipeQuery {
+            notes_contains: Some(text.to_string()),
+            ..Default::default()
+        })
+        .unwrap()
+        .into_iter()
+        .map(|recipe| recipe.filename)
+        .collect()
+    };
+    assert_eq!(found("brett"), ["1.bsmx", "2.bsmx"]);
+    assert_eq!(found("at bottling"), ["1.bsmx"]);
+    assert!(found("lager").is_empty());
+    // Beyond ASCII too
+    assert_eq!(found("bière"), ["4.bsmx"]);
+    assert_eq!(found("BIÈRE"), ["4.bsmx"]);
+}
+
+#[test]
+fn stores_every_parsed_field() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("activity.db");
+    let db = ActivityDb::open(&path).unwrap();
+    db.record_file(Some(42), None, "42.bsmx", &fixture(), Utc::now())
+        .unwrap();
+    drop(db);
+
+    let conn = rusqlite::Connection::open(&path).unwrap();
+    let (style_category, boil_time, version, hops): (String, f64, String, String) = conn
+        .query_row(
+            "SELECT style_category, boil_time_min, bsmx_version, hops FROM recipes",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        )
+        .unwrap();
+    assert_eq!(style_category, "Belgian Ale");
+    assert_eq!(boil_time, 90.0);
+    assert_eq!(version, serde_json::to_value(BsmxVersion::Unknown).unwrap());
+    assert!(hops.contains("Strisselspalt"), "{}", hops);
+}
+
+#[test]
+fn logs_status_counts_per_run() {
+    let dir = TempDir::new().unwrap();
+    let db = ActivityDb::open(&dir.path().join("activity.db")).unwrap();
+    let first = Utc::now() - Duration::hours(1);
+    db.record_status_counts(first, &[(200, 3), (404, 5)].into_iter().collect())
+        .unwrap();
+    // The same run recorded again replaces its counts
+    db.record_status_counts(first, &[(200, 4), (404, 5)].into_iter().collect())
+        .unwrap();
+    db.record_status_counts(Utc::now(), &[(0, 2), (404, 1)].into_iter().collect())
+        .unwrap();
+    assert_eq!(
+        db.status_counts().unwrap().into_iter().collect::<Vec<_>>(),
+        vec![(0, 2), (200, 4), (404, 6)]
+    );
+}
+
+#[tokio::test]
+async fn logs_runs_to_a_database_and_imports_old_ones() {
+    let server = serve_recipes(std::time::Duration::ZERO).await;
+    let dir = TempDir::new().unwrap();
+    let output = beerscape(
+        &dir,
+        &server.uri(),
+        &["--db", "activity.db", "--ids", "3,5"],
+    )
+    .output()
+    .await
+    .unwrap();
+    assert!(output.status.success());
+
+    let output = beerscape(
+        &dir,
+        &server.uri(),
+        &["--db", "activity.db", "db", "search", "--style", "garde"],
+    )
+    .output()
+    .await
+    .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.ends_with("2 recipes\n"), "{}", stdout);
+
+    // A database started later picks up what's already on disk
+    let output = beerscape(&dir, &server.uri(), &["--db", "later.db", "db", "import"])
+        .output()
+        .await
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("New Downloads: 2\n"), "{}", stdout);
+    let output = beerscape(&dir, &server.uri(), &["--db", "later.db", "db", "import"])
+        .output()
+        .await
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("New Downloads: 0\n"), "{}", stdout);
+
+    let output = beerscape(&dir, &server.uri(), &["db", "search"])
+        .output()
+        .await
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--db PATH"));
+}
@@ -0,0 +1,74 @@
+mod common;
+
+use beer_scape::cli::IpVersion;
+use common::{beerscape, serve_nothing, serve_recipes, status_line};
+use std::net::IpAddr;
+use std::time::Duration;
+use tempfile::TempDir;
+
+#[test]
+fn filters_by_version() {
+    let v4: IpAddr = "192.0.2.1".parse().unwrap();
+    let v6: IpAddr = "2001:db8::1".parse().unwrap();
+    assert!(IpVersion::V4.allows(v4) && !IpVersion::V4.allows(v6));
+    assert!(IpVersion::V6.allows(v6) && !IpVersion::V6.allows(v4));
+    assert!(IpVersion::Both.allows(v4) && IpVersion::Both.allows(v6));
+
+    assert_eq!(IpVersion::of(v4), IpVersion::V4);
+    assert_eq!(IpVersion::of(v6), IpVersion::V6);
+}
+
+#[tokio::test]
+async fn binds_to_a_local_address() {
+    let server = serve_recipes(Duration::ZERO).await;
+    let dir = TempDir::new().unwrap();
+    let output = beerscape(
+        &dir,
+        &server.uri(),
+        &["--id-range", "1-2", "--bind-address", "127.0.0.1"],
+    )
+    .output()
+    .await
+    .unwrap();
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        status_line(&output),
+        "beerscape: complete: ids-exhausted, 2 downloaded, 0 failed"
+    );
+}
+
+#[tokio::test]
+async fn bind_address_of_the_wrong_version_is_fatal() {
+    let server = serve_nothing().await;
+    let dir = TempDir::new().unwrap();
+    let output = beerscape(
+        &dir,
+        &server.uri(),
+        &["--bind-address", "127.0.0.1", "--ip-version", "6"],
+    )
+    .output()
+    .await
+    .unwrap();
+
+    assert_eq!(output.status.code(), Some(4));
+    assert!(server.received_requests().await.unwrap().is_empty());
+}
+
+#[cfg(feature = "dns-control")]
+#[tokio::test]
+async fn resolves_one_version_only() {
+    use beer_scape::dns::FamilyResolver;
+    use reqwest::dns::Resolve;
+
+    let resolver = FamilyResolver {
+        version: IpVersion::V4,
+    };
+    let addrs: Vec<_> = resolver
+        .resolve("localhost".parse().unwrap())
+        .await
+        .unwrap()
+        .collect();
+    assert!(!addrs.is_empty());
+    assert!(addrs.iter().all(|addr| addr.is_ipv4()), "{:?}", addrs);
+}
@@ -0,0 +1,128 @@
+use beer_scape::download::{DownloadStats, NO_RESPONSE};
+
+#[test]
+fn zero_attempts_has_no_success_rate() {
+    let stats = DownloadStats {
+        existing: 40,
+        ..Default::default()
+    };
+    assert_eq!(stats.success_rate(), None);
+
+    let summary = stats.summary();
+    assert!(summary.contains("Newly Downloaded: 0\n"), "{}", summary);
+    assert!(summary.ends_with("Final Success Rate: n/a, nothing attempted"));
+    assert!(!summary.contains("NaN"));
+}
+
+#[test]
+fn zero_new_downloads_is_zero_percent() {
+    let stats = DownloadStats {
+        existing: 40,
+        failed: 25,
+        attempted: 25,
+        ..Default::default()
+    };
+    assert_eq!(stats.success_rate(), Some(0.0));
+    assert!(stats.summary().ends_with("Final Success Rate: 0.0%"));
+}
+
+#[test]
+fn rate_counts_only_this_runs_downloads() {
+    let stats = DownloadStats {
+        existing: 9_000,
+        downloaded: 30,
+        failed: 90,
+        attempted: 120,
+        bytes: 0,
+        gets_avoided: None,
+        ..Default::default()
+    };
+    assert_eq!(stats.on_disk(), 9_030);
+    assert!(stats.summary().ends_with("Final Success Rate: 25.0%"));
+}
+
+#[test]
+fn existing_beyond_the_target_is_reported_as_is() {
+    let stats = DownloadStats {
+        existing: 12_000,
+        ..Default::default()
+    };
+    assert_eq!(
+        stats.summary(),
+        "Download Summary:\n\
+         ----------------\n\
+         Previously Existing: 12000\n\
+         Newly Downloaded: 0\n\
+         Failed Attempts: 0\n\
+         Total Attempts: 0\n\
+         Final Success Rate: n/a, nothing attempted"
+    );
+}
+
+#[test]
+fn prechecked_runs_report_the_gets_they_avoided() {
+    let stats = DownloadStats {
+        attempted: 10,
+        failed: 10,
+        gets_avoided: Some(8),
+        ..Default::default()
+    };
+    assert!(stats
+        .summary()
+        .ends_with("Final Success Rate: 0.0%\nGETs Avoided by Precheck: 8"));
+}
+
+#[test]
+fn reports_data_in_binary_units_or_raw_bytes() {
+    let mut stats = DownloadStats {
+        downloaded: 4,
+        attempted: 5,
+        failed: 1,
+        bytes: 40_960,
+        transferred: 45_000,
+        ..Default::default()
+    };
+    assert!(
+        stats.summary().ends_with(
+            "Final Success Rate: 80.0%\n\
+             Data Downloaded: 40.0 KiB\n\
+             Data Transferred: 43.9 KiB\n\
+             Average Recipe Size: 10.0 KiB"
+        ),
+        "{}",
+        stats.summary()
+    );
+
+    stats.raw_bytes = true;
+    assert!(stats.summary().ends_with(
+        "Data Downloaded: 40960 B\n\
+         Data Transferred: 45000 B\n\
+         Average Recipe Size: 10240 B"
+    ));
+}
+
+#[test]
+fn lists_attempts_by_status() {
+    let mut stats = DownloadStats {
+        attempted: 6,
+        downloaded: 2,
+        failed: 4,
+        ..Default::default()
+    };
+    for status in [Some(404), Some(200), None, Some(404), Some(200), Some(503)] {
+        stats.count_status(status);
+    }
+    assert_eq!(stats.status_counts[&NO_RESPONSE], 1);
+    assert!(
+        stats.summary().ends_with(
+            "Final Success Rate: 33.3%\n\
+             Responses by Status:\n  \
+             no response: 1\n  \
+             200: 2\n  \
+             404: 2\n  \
+             503: 1"
+        ),
+        "{}",
+        stats.summary()
+    );
+}
@@ -0,0 +1,55 @@
+use beer_scape::bsmx::{decode_bsmx, parse_bsmx, to_utf8_document};
+use std::fs;
+
+fn fixture(name: &str) -> Vec<u8> {
+    fs::read(format!("tests/fixtures/{}", name)).unwrap()
+}
+
+#[test]
+fn parses_utf8_and_latin1_alike() {
+    let utf8 = parse_bsmx(&fixture("utf8.bsmx")).unwrap();
+    let latin1 = parse_bsmx(&fixture("latin1.bsmx")).unwrap();
+
+    assert_eq!(utf8.name, "Bière de Garde à 12°");
+    assert_eq!(utf8.style.name, "Bière de Garde");
+    assert_eq!(latin1, utf8);
+}
+
+#[test]
+fn ignores_declaration_that_lies() {
+    let expected = parse_bsmx(&fixture("utf8.bsmx")).unwrap();
+
+    let utf8_as_latin1 = parse_bsmx(&fixture("utf8_declared_latin1.bsmx")).unwrap();
+    assert_eq!(utf8_as_latin1, expected);
+
+    let cp1252_as_utf8 = parse_bsmx(&fixture("cp1252_declared_utf8.bsmx")).unwrap();
+    assert_eq!(cp1252_as_utf8.name, "“Crème” Bière 12°");
+}
+
+#[test]
+fn normalizes_to_utf8_with_updated_declaration() {
+    let latin1 = fixture("latin1.bsmx");
+    let normalized = to_utf8_document(&latin1).unwrap();
+
+    let text = String::from_utf8(normalized.clone()).unwrap();
+    assert!(text.starts_with(r#"<?xml version="1.0" encoding="UTF-8"?>"#));
+    assert_eq!(normalized, fixture("utf8.bsmx"));
+    assert_eq!(
+        parse_bsmx(&normalized).unwrap(),
+        parse_bsmx(&latin1).unwrap()
+    );
+}
+
+#[test]
+fn leaves_clean_utf8_alone() {
+    let utf8 = fixture("utf8.bsmx");
+    assert!(to_utf8_document(&utf8).is_none());
+    assert_eq!(decode_bsmx(&utf8).as_bytes(), utf8.as_slice());
+}
+
+#[test]
+fn strips_byte_order_mark() {
+    let mut with_bom = b"\xEF\xBB\xBF".to_vec();
+    with_bom.extend(fixture("utf8.bsmx"));
+    assert_eq!(to_utf8_document(&with_bom).unwrap(), fixture("utf8.bsmx"));
+}
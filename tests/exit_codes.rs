@@ -0,0 +1,609 @@
+mod common;
+
+use common::{beerscape, serve_nothing, serve_recipes, status_line};
+use std::fs;
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::process::Command;
+use wiremock::matchers::{method, path, query_param};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+#[tokio::test]
+async fn finished_run_exits_zero() {
+    let server = serve_recipes(Duration::ZERO).await;
+    let dir = TempDir::new().unwrap();
+    let output = beerscape(&dir, &server.uri(), &["--id-range", "1-3"])
+        .output()
+        .await
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        status_line(&output),
+        "beerscape: complete: ids-exhausted, 3 downloaded, 0 failed"
+    );
+}
+
+#[tokio::test]
+async fn quota_exits_two() {
+    let server = serve_recipes(Duration::ZERO).await;
+    let dir = TempDir::new().unwrap();
+    let output = beerscape(
+        &dir,
+        &server.uri(),
+        &["--id-range", "1-3", "--daily-quota", "1"],
+    )
+    .output()
+    .await
+    .unwrap();
+
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(
+        status_line(&output),
+        "beerscape: partial: quota-reached, 1 downloaded, 0 failed"
+    );
+}
+
+#[tokio::test]
+async fn time_limit_exits_two() {
+    let server = serve_nothing().await;
+    let dir = TempDir::new().unwrap();
+    let output = beerscape(&dir, &server.uri(), &["--max-duration", "1s"])
+        .output()
+        .await
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(2));
+    assert!(
+        status_line(&output)
+            .starts_with("beerscape: partial: max-duration-reached, 0 downloaded, "),
+        "{}",
+        status_line(&output)
+    );
+}
+
+#[tokio::test]
+async fn max_attempts_exits_two() {
+    let server = serve_nothing().await;
+    let dir = TempDir::new().unwrap();
+    let output = beerscape(&dir, &server.uri(), &["--max-attempts", "25"])
+        .output()
+        .await
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(
+        status_line(&output),
+        "beerscape: partial: max-attempts-reached, 0 downloaded, 25 failed"
+    );
+}
+
+#[tokio::test]
+async fn collapsed_hit_rate_exits_two() {
+    let server = serve_nothing().await;
+    let dir = TempDir::new().unwrap();
+    let output = beerscape(&dir, &server.uri(), &["--hit-rate-window", "40"])
+        .output()
+        .await
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(
+        status_line(&output),
+        "beerscape: partial: hit-rate-too-low, 0 downloaded, 40 failed"
+    );
+}
+
+#[tokio::test]
+async fn exhausted_id_space_exits_two() {
+    let server = MockServer::start().await;
+    for id in ["7", "42", "99"] {
+        Mock::given(method("GET"))
+            .and(query_param("id", id))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_bytes(fs::read("tests/fixtures/utf8.bsmx").unwrap()),
+            )
+            .mount(&server)
+            .await;
+    }
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(404))
+        .mount(&server)
+        .await;
+    let dir = TempDir::new().unwrap();
+    // A target of 10,000 can't be met from 100 IDs
+    let output = beerscape(&dir, &server.uri(), &["--min-id", "1", "--max-id", "100"])
+        .output()
+        .await
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(
+        status_line(&output),
+        "beerscape: partial: id-space-exhausted, 3 downloaded, 97 failed"
+    );
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn interrupt_exits_three() {
+    let server = serve_recipes(Duration::from_millis(200)).await;
+    let dir = TempDir::new().unwrap();
+    let child = beerscape(&dir, &server.uri(), &["--id-range", "1-1000"])
+        .spawn()
+        .unwrap();
+
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    let pid = child.id().unwrap().to_string();
+    let kill = Command::new("kill")
+        .args(["-INT", &pid])
+        .status()
+        .await
+        .unwrap();
+    assert!(kill.success());
+    let output = child.wait_with_output().await.unwrap();
+
+    assert_eq!(output.status.code(), Some(3));
+    assert!(status_line(&output).starts_with("beerscape: interrupted: "));
+}
+
+#[tokio::test]
+async fn bad_configuration_exits_four() {
+    let dir = TempDir::new().unwrap();
+    let output = beerscape(&dir, "http://127.0.0.1:1", &["--id-file", "missing.txt"])
+        .output()
+        .await
+        .unwrap();
+    assert_eq!(output.status.code(), Some(4));
+    assert!(status_line(&output).starts_with("beerscape: fatal: "));
+
+    let output = beerscape(&dir, "http://127.0.0.1:1", &["--id-range", "9-1"])
+        .output()
+        .await
+        .unwrap();
+    assert_eq!(output.status.code(), Some(4));
+}
+
+#[tokio::test]
+async fn expired_session_without_a_terminal_exits_four() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(401))
+        .mount(&server)
+        .await;
+    let dir = TempDir::new().unwrap();
+    let run = beerscape(&dir, &server.uri(), &["--ids", "1"]).output();
+    let output = tokio::time::timeout(Duration::from_secs(30), run)
+        .await
+        .expect("the run waited on stdin")
+        .unwrap();
+    assert_eq!(output.status.code(), Some(4));
+    assert!(status_line(&output).contains("requires a login session"));
+}
+
+#[tokio::test]
+async fn start_at_id_skips_ahead_in_a_listed_walk() {
+    let server = serve_recipes(Duration::ZERO).await;
+    let dir = TempDir::new().unwrap();
+    let output = beerscape(
+        &dir,
+        &server.uri(),
+        &["--id-range", "1-10", "--start-at-id", "8"],
+    )
+    .output()
+    .await
+    .unwrap();
+    assert_eq!(
+        status_line(&output),
+        "beerscape: complete: ids-exhausted, 3 downloaded, 0 failed"
+    );
+    let mut ids: Vec<u32> = server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .filter_map(|request| {
+            request
+                .url
+                .query_pairs()
+                .find(|(key, _)| key == "id")
+                .map(|(_, id)| id.parse().unwrap())
+        })
+        .collect();
+    ids.sort();
+    assert_eq!(ids, [8, 9, 10]);
+
+    for (args, error) in [
+        (
+            &[
+                "--id-range",
+                "1-10",
+                "--max-id",
+                "100",
+                "--start-at-id",
+                "101",
+            ][..],
+            "--start-at-id 101 is outside --min-id 1 to --max-id 100",
+        ),
+        (
+            &["--start-at-id", "5"][..],
+            "only applies to sequential walks",
+        ),
+    ] {
+        let output = beerscape(&dir, &server.uri(), args)
+            .args(["--min-id", "1"])
+            .output()
+            .await
+            .unwrap();
+        assert_eq!(output.status.code(), Some(4));
+        assert!(
+            status_line(&output).contains(error),
+            "{}",
+            status_line(&output)
+        );
+    }
+}
+
+#[tokio::test]
+async fn timeouts_take_milliseconds_or_seconds() {
+    let server = serve_recipes(Duration::from_secs(2)).await;
+    for timeout in [
+        ["--request-timeout-ms", "150"],
+        ["--request-timeout", "0.15"],
+    ] {
+        let dir = TempDir::new().unwrap();
+        let output = beerscape(
+            &dir,
+            &server.uri(),
+            &["--id-range", "1-3", "--progress", "json"],
+        )
+        .args(timeout)
+        .output()
+        .await
+        .unwrap();
+
+        assert_eq!(
+            status_line(&output),
+            "beerscape: complete: ids-exhausted, 0 downloaded, 3 failed"
+        );
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let started: serde_json::Value =
+            serde_json::from_str(stdout.lines().next().unwrap()).unwrap();
+        assert_eq!(started["request_timeout_ms"], 150);
+        assert_eq!(started["connect_timeout_ms"], 5000);
+        assert_eq!(started["read_timeout_ms"], 10000);
+        assert!(stdout.contains(r#""category":"timeout""#), "{}", stdout);
+    }
+
+    let dir = TempDir::new().unwrap();
+    let both = [
+        "--read-timeout",
+        "1",
+        "--read-timeout-ms",
+        "150",
+        "--ids",
+        "1",
+    ];
+    let output = beerscape(&dir, &server.uri(), &both)
+        .output()
+        .await
+        .unwrap();
+    assert_eq!(output.status.code(), Some(4));
+}
+
+#[tokio::test]
+async fn unreachable_site_exits_five() {
+    let dir = TempDir::new().unwrap();
+    // Nothing listens on port 1, so every connection is refused
+    let args = ["--id-range", "1-200", "--skip-healthcheck"];
+    let output = beerscape(&dir, "http://127.0.0.1:1", &args)
+        .output()
+        .await
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(5));
+    assert_eq!(
+        status_line(&output),
+        "beerscape: circuit-open: 50 requests in a row could not reach the site"
+    );
+}
+
+#[tokio::test]
+async fn sequential_run_carries_on_from_its_cursor() {
+    let server = serve_nothing().await;
+    let dir = TempDir::new().unwrap();
+    let range = ["--mode", "sequential", "--min-id", "1", "--max-id", "30"];
+    let output = beerscape(&dir, &server.uri(), &range)
+        .args(["--max-attempts", "10"])
+        .output()
+        .await
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(
+        status_line(&output),
+        "beerscape: partial: max-attempts-reached, 0 downloaded, 10 failed"
+    );
+    let state = fs::read_to_string(dir.path().join("recipes/.beerscape_state.json")).unwrap();
+    assert!(state.contains("\"next_id\": 11"), "{}", state);
+
+    let output = beerscape(&dir, &server.uri(), &range)
+        .output()
+        .await
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        status_line(&output),
+        "beerscape: complete: range-exhausted, 0 downloaded, 20 failed"
+    );
+
+    // Every ID was asked for once; a batch's requests run concurrently, so
+    // they arrive in any order
+    let mut ids: Vec<u32> = server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .filter_map(|request| {
+            request
+                .url
+                .query_pairs()
+                .find(|(key, _)| key == "id")
+                .map(|(_, id)| id.parse().unwrap())
+        })
+        .collect();
+    ids.sort();
+    assert_eq!(ids, (1..=30).collect::<Vec<_>>());
+
+    // A finished range stays finished
+    let output = beerscape(&dir, &server.uri(), &range)
+        .output()
+        .await
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        status_line(&output),
+        "beerscape: complete: range-exhausted, 0 downloaded, 0 failed"
+    );
+}
+
+#[tokio::test]
+async fn sequential_run_queues_a_huge_range_as_it_goes() {
+    let server = serve_nothing().await;
+    let dir = TempDir::new().unwrap();
+    let range = [
+        "--mode",
+        "sequential",
+        "--min-id",
+        "1",
+        "--max-id",
+        "4000000000",
+    ];
+    let output = beerscape(&dir, &server.uri(), &range)
+        .args(["--max-attempts", "25"])
+        .output()
+        .await
+        .unwrap();
+    assert_eq!(
+        status_line(&output),
+        "beerscape: partial: max-attempts-reached, 0 downloaded, 25 failed"
+    );
+    let state = fs::read_to_string(dir.path().join("recipes/.beerscape_state.json")).unwrap();
+    assert!(state.contains("\"next_id\": 26"), "{}", state);
+}
+
+#[tokio::test]
+async fn sequential_run_starts_at_the_given_id() {
+    let server = serve_nothing().await;
+    let dir = TempDir::new().unwrap();
+    let range = ["--mode", "sequential", "--min-id", "1", "--max-id", "30"];
+    let output = beerscape(&dir, &server.uri(), &range)
+        .args(["--start-at-id", "21"])
+        .output()
+        .await
+        .unwrap();
+    assert_eq!(
+        status_line(&output),
+        "beerscape: complete: range-exhausted, 0 downloaded, 10 failed"
+    );
+    let mut ids: Vec<u32> = server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .filter_map(|request| {
+            request
+                .url
+                .query_pairs()
+                .find(|(key, _)| key == "id")
+                .map(|(_, id)| id.parse().unwrap())
+        })
+        .collect();
+    ids.sort();
+    assert_eq!(ids, (21..=30).collect::<Vec<_>>());
+    let state = fs::read_to_string(dir.path().join("recipes/.beerscape_state.json")).unwrap();
+    assert!(state.contains("\"next_id\": 31"), "{}", state);
+}
+
+#[tokio::test]
+async fn newest_mode_stops_at_the_frontier() {
+    let server = MockServer::start().await;
+    for id in 101..=105 {
+        Mock::given(method("GET"))
+            .and(path("/download.php"))
+            .and(query_param("id", id.to_string()))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_bytes(fs::read("tests/fixtures/utf8.bsmx").unwrap()),
+            )
+            .mount(&server)
+            .await;
+    }
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(404))
+        .mount(&server)
+        .await;
+    let dir = TempDir::new().unwrap();
+    fs::create_dir(dir.path().join("recipes")).unwrap();
+    fs::copy(
+        "tests/fixtures/utf8.bsmx",
+        dir.path().join("recipes/100.bsmx"),
+    )
+    .unwrap();
+
+    let output = beerscape(
+        &dir,
+        &server.uri(),
+        &[
+            "--mode",
+            "newest",
+            "--overlap",
+            "5",
+            "--frontier-misses",
+            "20",
+        ],
+    )
+    .output()
+    .await
+    .unwrap();
+
+    // 95-99 are rechecked, then the scan runs on until two batches in a
+    // row come up empty
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        status_line(&output),
+        "beerscape: complete: frontier-reached, 5 downloaded, 25 failed"
+    );
+    assert!(dir.path().join("recipes/105.bsmx").exists());
+}
+
+#[tokio::test]
+async fn newest_mode_needs_a_downloaded_recipe() {
+    let server = serve_nothing().await;
+    let dir = TempDir::new().unwrap();
+    let output = beerscape(&dir, &server.uri(), &["--mode", "newest"])
+        .output()
+        .await
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(4));
+    assert!(server.received_requests().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn listed_ids_report_their_outcomes() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(query_param("id", "2"))
+        .respond_with(ResponseTemplate::new(404))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/download.php"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_bytes(fs::read("tests/fixtures/utf8.bsmx").unwrap()),
+        )
+        .mount(&server)
+        .await;
+    let dir = TempDir::new().unwrap();
+
+    let output = beerscape(&dir, &server.uri(), &["--ids", "1,2,3"])
+        .output()
+        .await
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        status_line(&output),
+        "beerscape: complete: ids-exhausted, 2 downloaded, 1 failed"
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("Failed: 1\n           2  not-found"),
+        "{}",
+        stdout
+    );
+    assert_eq!(
+        fs::read_to_string(dir.path().join("failed_ids.txt")).unwrap(),
+        "# not-found\n2\n"
+    );
+
+    // Downloaded IDs are skipped, unless forced
+    let output = beerscape(&dir, &server.uri(), &["--ids", "1,3"])
+        .output()
+        .await
+        .unwrap();
+    assert_eq!(
+        status_line(&output),
+        "beerscape: complete: ids-exhausted, 0 downloaded, 0 failed"
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Already Downloaded: 2"), "{}", stdout);
+    assert!(!dir.path().join("failed_ids.txt").exists());
+
+    let output = beerscape(&dir, &server.uri(), &["--ids", "1,3", "--force"])
+        .output()
+        .await
+        .unwrap();
+    assert_eq!(
+        status_line(&output),
+        "beerscape: complete: ids-exhausted, 2 downloaded, 0 failed"
+    );
+}
+
+#[tokio::test]
+async fn style_filter_downloads_the_styles_listing() {
+    let server = serve_recipes(Duration::ZERO).await;
+    Mock::given(method("GET"))
+        .and(path("/search.php"))
+        .and(query_param("style", "Pilsner"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_string(fs::read_to_string("tests/fixtures/listing_page2.html").unwrap()),
+        )
+        .mount(&server)
+        .await;
+    let dir = TempDir::new().unwrap();
+
+    let output = beerscape(&dir, &server.uri(), &["--filter-style", "Pilsner"])
+        .output()
+        .await
+        .unwrap();
+    assert_eq!(
+        status_line(&output),
+        "beerscape: complete: ids-exhausted, 2 downloaded, 0 failed"
+    );
+    let index = beer_scape::index::Index::open(&dir.path().join("recipes")).unwrap();
+    let styles: Vec<_> = index
+        .entries()
+        .unwrap()
+        .into_iter()
+        .map(|entry| (entry.recipe_id, entry.discovered_style))
+        .collect();
+    assert_eq!(styles.len(), 2);
+    assert!(styles
+        .iter()
+        .all(|(_, style)| style.as_deref() == Some("Pilsner")));
+
+    // Once the listing is used up, random IDs make up the rest
+    let output = beerscape(
+        &dir,
+        &server.uri(),
+        &[
+            "--filter-style",
+            "Pilsner",
+            "--fallback-random",
+            "--min-id",
+            "1",
+            "--max-id",
+            "5",
+        ],
+    )
+    .output()
+    .await
+    .unwrap();
+    assert_eq!(
+        status_line(&output),
+        "beerscape: partial: id-space-exhausted, 5 downloaded, 0 failed"
+    );
+}
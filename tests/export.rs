@@ -0,0 +1,130 @@
+use beer_scape::cli::{ExportFormat, Units};
+use beer_scape::commands::export::{delta_path, export, last_export, modified_since};
+use chrono::{TimeZone, Utc};
+use serde_json::Value;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tempfile::TempDir;
+
+fn fixtures() -> Vec<PathBuf> {
+    vec![
+        PathBuf::from("tests/fixtures/latin1.bsmx"),
+        PathBuf::from("tests/fixtures/missing.bsmx"),
+        PathBuf::from("tests/fixtures/utf8.bsmx"),
+    ]
+}
+
+#[test]
+fn ndjson_writes_one_recipe_per_line() {
+    let mut out = Vec::new();
+    let exported = export(&fixtures(), ExportFormat::Ndjson, Units::Metric, &mut out).unwrap();
+    assert_eq!(exported, 2);
+
+    let text = String::from_utf8(out).unwrap();
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines.len(), 2);
+    let first: Value = serde_json::from_str(lines[0]).unwrap();
+    assert_eq!(first["filename"], "latin1.bsmx");
+    assert!(first["name"].is_string());
+    assert!(first["abv"].is_number());
+    let second: Value = serde_json::from_str(lines[1]).unwrap();
+    assert_eq!(second["filename"], "utf8.bsmx");
+}
+
+#[test]
+fn json_writes_one_array() {
+    let mut out = Vec::new();
+    let exported = export(&fixtures(), ExportFormat::Json, Units::Metric, &mut out).unwrap();
+    assert_eq!(exported, 2);
+
+    let all: Vec<Value> = serde_json::from_slice(&out).unwrap();
+    assert_eq!(all.len(), 2);
+    assert_eq!(all[1]["filename"], "utf8.bsmx");
+}
+
+#[test]
+fn imperial_export_converts_and_renames_quantities() {
+    let mut out = Vec::new();
+    let paths = [PathBuf::from("tests/fixtures/utf8.bsmx")];
+    export(&paths, ExportFormat::Json, Units::Metric, &mut out).unwrap();
+    let metric: Vec<Value> = serde_json::from_slice(&out).unwrap();
+    out.clear();
+    export(&paths, ExportFormat::Json, Units::Imperial, &mut out).unwrap();
+    let imperial: Vec<Value> = serde_json::from_slice(&out).unwrap();
+
+    let (metric, imperial) = (&metric[0], &imperial[0]);
+    // The fixture's 640 fl oz batch
+    let gallons = imperial["batch_size_gal"].as_f64().unwrap();
+    assert!((gallons - 5.0).abs() < 1e-6, "{}", gallons);
+    assert!(imperial.get("batch_size_l").is_none());
+    let ounces = imperial["hops"][0]["amount_oz"].as_f64().unwrap();
+    assert!((ounces - 2.0).abs() < 1e-9);
+    let pounds = imperial["fermentables"][0]["amount_lb"].as_f64().unwrap();
+    assert!((pounds - 11.0).abs() < 1e-5, "{}", pounds);
+    assert_eq!(imperial["name"], metric["name"]);
+    assert_eq!(imperial["og"], metric["og"]);
+}
+
+fn touch(path: &Path, at: SystemTime) {
+    fs::write(path, "").unwrap();
+    File::options()
+        .write(true)
+        .open(path)
+        .unwrap()
+        .set_modified(at)
+        .unwrap();
+}
+
+#[test]
+fn picks_files_modified_after_a_time() {
+    let dir = TempDir::new().unwrap();
+    let now = SystemTime::now();
+    let old = dir.path().join("1.bsmx");
+    let new = dir.path().join("2.bsmx");
+    touch(&old, now - Duration::from_secs(3600));
+    touch(&new, now);
+
+    let since = now - Duration::from_secs(60);
+    assert_eq!(
+        modified_since(vec![old.clone(), new.clone()], since).unwrap(),
+        vec![new]
+    );
+    assert!(modified_since(vec![old], now).unwrap().is_empty());
+}
+
+#[test]
+fn finds_the_newest_delta_export() {
+    let dir = TempDir::new().unwrap();
+    assert_eq!(last_export(dir.path()).unwrap(), None);
+
+    let now = SystemTime::now();
+    let earlier = now - Duration::from_secs(86_400);
+    touch(
+        &dir.path().join("recipes_delta_20240501-030000.json"),
+        earlier,
+    );
+    touch(
+        &dir.path().join("recipes_delta_20240502-030000.ndjson"),
+        now,
+    );
+    // A full export doesn't count
+    touch(
+        &dir.path().join("recipes.json"),
+        now + Duration::from_secs(60),
+    );
+    assert_eq!(last_export(dir.path()).unwrap(), Some(now));
+}
+
+#[test]
+fn names_delta_files_by_time() {
+    let at = Utc.with_ymd_and_hms(2024, 5, 2, 3, 4, 5).unwrap();
+    assert_eq!(
+        delta_path(at, ExportFormat::Json),
+        PathBuf::from("recipes_delta_20240502-030405.json")
+    );
+    assert_eq!(
+        delta_path(at, ExportFormat::Ndjson),
+        PathBuf::from("recipes_delta_20240502-030405.ndjson")
+    );
+}
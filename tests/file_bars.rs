@@ -0,0 +1,43 @@
+use beer_scape::download::{build_client, download_recipe, DownloadConfig};
+use beer_scape::progress::FileBars;
+use indicatif::ProgressBar;
+use std::fs;
+use tempfile::TempDir;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+#[test]
+fn only_files_announced_over_the_threshold_get_a_bar() {
+    let (bars, _main) = FileBars::new(ProgressBar::hidden(), 1024);
+    assert!(bars.start("small.bsmx", 0, Some(1024)).is_none());
+    assert!(bars.start("unknown.bsmx", 0, None).is_none());
+
+    let bar = bars.start("big.bsmx", 512, Some(4096)).unwrap();
+    assert_eq!(bar.position(), 512);
+    bar.inc(1000);
+    assert_eq!(bar.position(), 1512);
+}
+
+#[tokio::test]
+async fn big_files_download_the_same_with_a_bar() {
+    let server = MockServer::start().await;
+    let fixture = fs::read("tests/fixtures/utf8.bsmx").unwrap();
+    Mock::given(method("GET"))
+        .and(path("/download.php"))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(fixture.clone()))
+        .mount(&server)
+        .await;
+    let dir = TempDir::new().unwrap();
+    let (bars, _main) = FileBars::new(ProgressBar::hidden(), 100);
+    let config = DownloadConfig {
+        base_url: server.uri(),
+        output_dir: dir.path().to_path_buf(),
+        file_bars: Some(bars),
+        ..Default::default()
+    };
+
+    let client = build_client(&config).unwrap();
+    download_recipe(&client, &config, 3).await.unwrap().unwrap();
+
+    assert_eq!(fs::read(dir.path().join("3.bsmx")).unwrap(), fixture);
+}
@@ -0,0 +1,102 @@
+mod common;
+
+use beer_scape::bsmx::{parse_bsmx, Recipe};
+use beer_scape::collection::{read_recipe, sidecar_path, write_sidecar, Provenance};
+use beer_scape::fingerprint::{fingerprint_hex, has_ingredients, ingredient_fingerprint};
+use common::fixture_text;
+use serde_json::Value;
+use std::fs;
+use std::process::Command;
+use tempfile::TempDir;
+
+/// The fixture at twice its batch size.
+fn doubled() -> String {
+    fixture_text()
+        .replace("<F_E_BATCH_VOL>640.0<", "<F_E_BATCH_VOL>1280.0<")
+        .replace("<F_G_AMOUNT>176<", "<F_G_AMOUNT>352<")
+        .replace("<F_H_AMOUNT>2<", "<F_H_AMOUNT>4<")
+}
+
+fn recipe(xml: &str) -> Recipe {
+    parse_bsmx(xml.as_bytes()).unwrap()
+}
+
+#[test]
+fn scaled_recipes_share_a_fingerprint() {
+    let original = recipe(&fixture_text());
+    let scaled = recipe(&doubled());
+    assert_ne!(original.batch_size_l, scaled.batch_size_l);
+    assert_eq!(
+        ingredient_fingerprint(&original),
+        ingredient_fingerprint(&scaled)
+    );
+
+    // More grain for the same batch is another recipe
+    let stronger = recipe(&fixture_text().replace("<F_G_AMOUNT>176<", "<F_G_AMOUNT>264<"));
+    assert_ne!(
+        ingredient_fingerprint(&original),
+        ingredient_fingerprint(&stronger)
+    );
+}
+
+#[test]
+fn ignores_ingredient_order_and_name_case() {
+    let mut a = recipe(&fixture_text());
+    let mut extra = a.fermentables[0].clone();
+    extra.name = "Munich Malt".into();
+    a.fermentables.push(extra);
+    let mut b = a.clone();
+    b.fermentables.reverse();
+    b.fermentables[0].name = "  munich MALT ".into();
+    assert_eq!(ingredient_fingerprint(&a), ingredient_fingerprint(&b));
+    assert_eq!(fingerprint_hex(&a).len(), 64);
+
+    assert!(has_ingredients(&a));
+    assert!(!has_ingredients(&Recipe::default()));
+}
+
+#[test]
+fn sidecars_carry_the_fingerprint() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("1.bsmx");
+    fs::write(&path, fixture_text()).unwrap();
+    let parsed = recipe(&fixture_text());
+    write_sidecar(&path, &parsed, &Provenance::default()).unwrap();
+
+    let sidecar: Value = serde_json::from_slice(&fs::read(sidecar_path(&path)).unwrap()).unwrap();
+    assert_eq!(sidecar["fingerprint"], fingerprint_hex(&parsed));
+    assert_eq!(sidecar["name"], parsed.name.as_str());
+    assert_eq!(read_recipe(&path).unwrap(), parsed);
+}
+
+#[test]
+fn deduplicates_by_fingerprint() {
+    let dir = TempDir::new().unwrap();
+    let recipes = dir.path().join("recipes");
+    fs::create_dir(&recipes).unwrap();
+    fs::write(recipes.join("1.bsmx"), fixture_text()).unwrap();
+    fs::write(recipes.join("2.bsmx"), doubled()).unwrap();
+    fs::write(
+        recipes.join("3.bsmx"),
+        fixture_text().replace("Strisselspalt", "Saaz"),
+    )
+    .unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_beerscape"))
+        .current_dir(dir.path())
+        .args(["deduplicate", "--by-fingerprint", "--auto-remove"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+
+    let report: Value =
+        serde_json::from_slice(&fs::read(dir.path().join("duplicates_report.json")).unwrap())
+            .unwrap();
+    assert_eq!(report["mode"], "fingerprint");
+    let pairs = report["duplicates"].as_array().unwrap();
+    assert_eq!(pairs.len(), 1);
+    assert!(pairs[0]["second"].as_str().unwrap().ends_with("2.bsmx"));
+    assert!(recipes.join("1.bsmx").exists());
+    assert!(!recipes.join("2.bsmx").exists());
+    assert!(recipes.join("3.bsmx").exists());
+}
@@ -0,0 +1,89 @@
+use std::fs;
+use std::process::{Output, Stdio};
+use tempfile::TempDir;
+use tokio::process::Command;
+use wiremock::matchers::{method, path, query_param};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+async fn serve(server: &MockServer, id: &str, response: ResponseTemplate) {
+    Mock::given(method("GET"))
+        .and(path("/download.php"))
+        .and(query_param("id", id))
+        .respond_with(response)
+        .mount(server)
+        .await;
+}
+
+fn recipe() -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_bytes(fs::read("tests/fixtures/utf8.bsmx").unwrap())
+}
+
+async fn health_check(server: &MockServer, ids: &str) -> Output {
+    let dir = TempDir::new().unwrap();
+    Command::new(env!("CARGO_BIN_EXE_beerscape"))
+        .current_dir(dir.path())
+        .args([
+            "--base-url",
+            &server.uri(),
+            "health-check",
+            "--test-ids",
+            ids,
+        ])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn passes_when_every_probe_finds_a_recipe() {
+    let server = MockServer::start().await;
+    serve(&server, "1", recipe()).await;
+    serve(&server, "2", recipe()).await;
+    let output = health_check(&server, "1,2").await;
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(0), "{}", stdout);
+    assert_eq!(stdout.matches("  OK    ").count(), 2, "{}", stdout);
+    assert!(stdout.contains("Bière de Garde"), "{}", stdout);
+    assert!(stdout.contains("Passed: 2 of 2"), "{}", stdout);
+    assert!(stdout.contains("Average Latency: "), "{}", stdout);
+    assert!(stdout.contains("Expected Success Rate: 100%"), "{}", stdout);
+}
+
+#[tokio::test]
+async fn some_failures_exit_1() {
+    let server = MockServer::start().await;
+    serve(&server, "1", recipe()).await;
+    serve(&server, "2", ResponseTemplate::new(404)).await;
+    serve(
+        &server,
+        "3",
+        ResponseTemplate::new(200).set_body_string("<html>maintenance</html"),
+    )
+    .await;
+    let output = health_check(&server, "1,2,3").await;
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(1), "{}", stdout);
+    assert!(stdout.contains("HTTP 404"), "{}", stdout);
+    assert!(stdout.contains("not a recipe"), "{}", stdout);
+    assert!(stdout.contains("Passed: 1 of 3"), "{}", stdout);
+}
+
+#[tokio::test]
+async fn all_failures_exit_2() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(503))
+        .mount(&server)
+        .await;
+    let output = health_check(&server, "1,2,3").await;
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(2), "{}", stdout);
+    assert_eq!(stdout.matches("  FAIL  ").count(), 3, "{}", stdout);
+    assert!(!stdout.contains("Average Latency"), "{}", stdout);
+}
@@ -0,0 +1,62 @@
+use beer_scape::bsmx::{Hop, Recipe};
+use beer_scape::commands::hop_inventory::{hop_totals, read_on_hand, shopping_list};
+use beer_scape::normalize::OZ_TO_G;
+use std::fs;
+use tempfile::TempDir;
+
+fn recipe(hops: &[(&str, f64, f64)]) -> Recipe {
+    Recipe {
+        hops: hops
+            .iter()
+            .map(|&(name, amount_g, alpha_pct)| Hop {
+                name: name.to_string(),
+                amount_g,
+                alpha_pct,
+                ..Default::default()
+            })
+            .collect(),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn totals_hops_across_recipes() {
+    let recipes = [
+        recipe(&[
+            ("Cascade", 20.0, 5.5),
+            ("cascade ", 30.0, 6.5),
+            ("Magnum", 15.0, 0.0),
+        ]),
+        recipe(&[("Citra", 40.0, 12.0), ("Cascade", 10.0, 0.0)]),
+    ];
+    let totals = hop_totals(&recipes, 2);
+
+    let names: Vec<&str> = totals.iter().map(|t| t.name.as_str()).collect();
+    assert_eq!(names, ["Cascade", "Citra", "Magnum"]);
+    assert_eq!(totals[0].amount_g, 120.0);
+    assert_eq!(totals[0].recipes, 2);
+    assert_eq!(totals[0].alpha_pct, 6.0);
+    assert_eq!(totals[2].alpha_pct, 0.0);
+}
+
+#[test]
+fn shopping_list_shows_the_deficit() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("on_hand.csv");
+    fs::write(
+        &path,
+        "variety,amount_oz,recipes\n\"Cascade\",2,1\n\nMagnum,1,1\n",
+    )
+    .unwrap();
+    let on_hand = read_on_hand(&path).unwrap();
+    assert!((on_hand["cascade"] - 2.0 * OZ_TO_G).abs() < 1e-9);
+
+    let recipes = [recipe(&[("Cascade", 100.0, 5.5), ("Magnum", 10.0, 12.0)])];
+    let list = shopping_list(&hop_totals(&recipes, 1), &on_hand);
+    assert_eq!(list.len(), 1);
+    assert_eq!(list[0].name, "Cascade");
+    assert!((list[0].to_buy_g() - (100.0 - 2.0 * OZ_TO_G)).abs() < 1e-9);
+
+    fs::write(&path, "name,grams\nCascade,5\n").unwrap();
+    assert!(read_on_hand(&path).is_err());
+}
@@ -0,0 +1,71 @@
+use beer_scape::ids::{read_id_file, IdRange, IdTracker};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::collections::HashSet;
+use std::fs;
+use tempfile::TempDir;
+
+#[test]
+fn parses_ranges() {
+    let range: IdRange = "10-20".parse().unwrap();
+    assert_eq!(range.0, 10..=20);
+    assert_eq!(range.to_string(), "10-20");
+    assert_eq!("7-7".parse::<IdRange>().unwrap().0, 7..=7);
+
+    assert!("20-10".parse::<IdRange>().is_err());
+    assert!("10".parse::<IdRange>().is_err());
+    assert!("a-b".parse::<IdRange>().is_err());
+}
+
+#[test]
+fn reads_id_files() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("ids.txt");
+    fs::write(&path, "# wanted\n12\n\n  345 # Pale Ale #2\n12\n").unwrap();
+    // Repeats are kept here; the download run drops them
+    assert_eq!(read_id_file(&path).unwrap(), vec![12, 345, 12]);
+
+    fs::write(&path, "12\nthirteen\n").unwrap();
+    let error = read_id_file(&path).unwrap_err().to_string();
+    assert!(error.contains(":2:"), "{}", error);
+}
+
+#[test]
+fn tracker_draws_every_id_once() {
+    let mut rng = StdRng::seed_from_u64(7);
+    let mut tracker = IdTracker::new(100..=199);
+    assert_eq!(tracker.remaining_count(), 100);
+
+    let mut drawn = HashSet::new();
+    while let Some(id) = tracker.next(&mut rng) {
+        assert!((100..=199).contains(&id));
+        assert!(drawn.insert(id), "{} drawn twice", id);
+    }
+    assert_eq!(drawn.len(), 100);
+    assert_eq!(tracker.remaining_count(), 0);
+
+    assert!(tracker.remove(150));
+    assert_eq!(tracker.remaining_count(), 1);
+    assert_eq!(tracker.next(&mut rng), Some(150));
+    assert_eq!(tracker.next(&mut rng), None);
+}
+
+#[test]
+fn tracker_marks_ids_in_its_range() {
+    let mut tracker = IdTracker::new(1..=130);
+    assert!(!tracker.contains(64));
+    assert!(tracker.insert(64));
+    assert!(!tracker.insert(64));
+    assert!(tracker.contains(64));
+    assert_eq!(tracker.remaining_count(), 129);
+
+    // Outside the range nothing is tracked
+    assert!(!tracker.insert(0));
+    assert!(!tracker.insert(131));
+    assert!(!tracker.contains(131));
+    assert!(!tracker.remove(131));
+
+    assert!(tracker.remove(64));
+    assert!(!tracker.remove(64));
+    assert_eq!(tracker.remaining_count(), 130);
+}
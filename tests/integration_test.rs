@@ -0,0 +1,810 @@
+mod common;
+
+use beer_scape::bsmx::{parse_bsmx, Recipe};
+use beer_scape::cookies::parse_netscape_cookies;
+use beer_scape::download::{
+    build_client, download_recipe, DownloadConfig, HeadPrecheck, RequestHeader, SessionExpired,
+    TimedOut,
+};
+use beer_scape::throttle::{ByteLimiter, ByteSize, RequestGate};
+use common::fixture;
+use std::fs;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use wiremock::matchers::{header, header_regex, method, path, query_param};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+async fn setup() -> (MockServer, TempDir, DownloadConfig) {
+    let server = MockServer::start().await;
+    let dir = TempDir::new().unwrap();
+    let config = DownloadConfig {
+        base_url: server.uri(),
+        output_dir: dir.path().to_path_buf(),
+        request_timeout: Duration::from_millis(500),
+        ..Default::default()
+    };
+    (server, dir, config)
+}
+
+async fn stub(server: &MockServer, id: u32, response: ResponseTemplate) {
+    Mock::given(method("GET"))
+        .and(path("/download.php"))
+        .and(query_param("id", id.to_string()))
+        .respond_with(response)
+        .mount(server)
+        .await;
+}
+
+fn saved_files(dir: &TempDir) -> usize {
+    fs::read_dir(dir.path()).unwrap().count()
+}
+
+#[tokio::test]
+async fn saves_valid_recipe() {
+    let (server, dir, config) = setup().await;
+    stub(
+        &server,
+        42,
+        ResponseTemplate::new(200)
+            .insert_header("content-disposition", "attachment; filename=\"Biere.bsmx\"")
+            .set_body_bytes(fixture()),
+    )
+    .await;
+
+    let client = build_client(&config).unwrap();
+    let info = download_recipe(&client, &config, 42)
+        .await
+        .unwrap()
+        .expect("recipe should be saved");
+
+    assert_eq!(info.filename, "Biere.bsmx");
+    assert_eq!(fs::read(dir.path().join("Biere.bsmx")).unwrap(), fixture());
+}
+
+#[tokio::test]
+async fn keeps_content_disposition_names_inside_the_output_folder() {
+    let (server, dir, mut config) = setup().await;
+    config.output_dir = dir.path().join("recipes");
+    fs::create_dir(&config.output_dir).unwrap();
+    for (id, name) in [(1, "../../escaped.bsmx"), (2, "..")] {
+        stub(
+            &server,
+            id,
+            ResponseTemplate::new(200)
+                .insert_header(
+                    "content-disposition",
+                    format!("attachment; filename=\"{}\"", name),
+                )
+                .set_body_bytes(fixture()),
+        )
+        .await;
+    }
+
+    let client = build_client(&config).unwrap();
+    let info = download_recipe(&client, &config, 1).await.unwrap().unwrap();
+    assert_eq!(info.filename, "escaped.bsmx");
+    let info = download_recipe(&client, &config, 2).await.unwrap().unwrap();
+    assert_eq!(info.filename, "2.bsmx");
+
+    let mut saved: Vec<_> = fs::read_dir(&config.output_dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect();
+    saved.sort();
+    assert_eq!(saved, ["2.bsmx", "escaped.bsmx"]);
+    assert_eq!(saved_files(&dir), 1);
+}
+
+#[tokio::test]
+async fn content_disposition_cannot_name_a_reserved_file() {
+    let (server, dir, config) = setup().await;
+    fs::write(dir.path().join("index.db"), "kept").unwrap();
+    for (id, name) in [(1, "index.db"), (2, ".beerscape_state.json"), (3, ".bsmx")] {
+        stub(
+            &server,
+            id,
+            ResponseTemplate::new(200)
+                .insert_header(
+                    "content-disposition",
+                    format!("attachment; filename=\"{}\"", name),
+                )
+                .set_body_bytes(fixture()),
+        )
+        .await;
+    }
+
+    let client = build_client(&config).unwrap();
+    for id in [1, 2, 3] {
+        let info = download_recipe(&client, &config, id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(info.filename, format!("{}.bsmx", id));
+    }
+    assert_eq!(
+        fs::read_to_string(dir.path().join("index.db")).unwrap(),
+        "kept"
+    );
+    assert!(!dir.path().join(".beerscape_state.json").exists());
+}
+
+#[tokio::test]
+async fn content_disposition_cannot_overwrite_another_recipe() {
+    let (server, dir, config) = setup().await;
+    fs::write(dir.path().join("Biere.bsmx"), "kept").unwrap();
+    for (id, name) in [(4, "Biere.bsmx"), (5, "9.bsmx"), (6, "6.bsmx")] {
+        stub(
+            &server,
+            id,
+            ResponseTemplate::new(200)
+                .insert_header(
+                    "content-disposition",
+                    format!("attachment; filename=\"{}\"", name),
+                )
+                .set_body_bytes(fixture()),
+        )
+        .await;
+    }
+
+    let client = build_client(&config).unwrap();
+    let info = download_recipe(&client, &config, 4).await.unwrap().unwrap();
+    assert_eq!(info.filename, "4.bsmx");
+    let info = download_recipe(&client, &config, 5).await.unwrap().unwrap();
+    assert_eq!(info.filename, "5.bsmx");
+    // Its own number is its own name
+    let info = download_recipe(&client, &config, 6).await.unwrap().unwrap();
+    assert_eq!(info.filename, "6.bsmx");
+    assert_eq!(
+        fs::read_to_string(dir.path().join("Biere.bsmx")).unwrap(),
+        "kept"
+    );
+    assert!(!dir.path().join("9.bsmx").exists());
+}
+
+#[tokio::test]
+async fn names_file_after_id_without_content_disposition() {
+    let (server, dir, config) = setup().await;
+    stub(
+        &server,
+        7,
+        ResponseTemplate::new(200).set_body_bytes(fixture()),
+    )
+    .await;
+
+    let client = build_client(&config).unwrap();
+    let info = download_recipe(&client, &config, 7).await.unwrap().unwrap();
+
+    assert_eq!(info.filename, "7.bsmx");
+    assert!(dir.path().join("7.bsmx").exists());
+}
+
+#[tokio::test]
+async fn writes_sidecar_when_enabled() {
+    let (server, dir, mut config) = setup().await;
+    config.sidecar = true;
+    stub(
+        &server,
+        8,
+        ResponseTemplate::new(200).set_body_bytes(fixture()),
+    )
+    .await;
+
+    let client = build_client(&config).unwrap();
+    download_recipe(&client, &config, 8).await.unwrap().unwrap();
+
+    let sidecar: Recipe =
+        serde_json::from_slice(&fs::read(dir.path().join("8.json")).unwrap()).unwrap();
+    assert_eq!(sidecar, parse_bsmx(&fixture()).unwrap());
+}
+
+#[tokio::test]
+async fn skips_sidecar_for_unparseable_recipe() {
+    let (server, dir, mut config) = setup().await;
+    config.sidecar = true;
+    stub(
+        &server,
+        9,
+        ResponseTemplate::new(200).set_body_string("<Recipe><F_R_OG>strong</F_R_OG></Recipe>"),
+    )
+    .await;
+
+    let client = build_client(&config).unwrap();
+    download_recipe(&client, &config, 9).await.unwrap().unwrap();
+
+    assert!(dir.path().join("9.bsmx").exists());
+    assert!(!dir.path().join("9.json").exists());
+}
+
+#[tokio::test]
+async fn not_found_is_none() {
+    let (server, dir, config) = setup().await;
+    stub(&server, 1, ResponseTemplate::new(404)).await;
+
+    let client = build_client(&config).unwrap();
+    assert!(download_recipe(&client, &config, 1)
+        .await
+        .unwrap()
+        .is_none());
+    assert_eq!(saved_files(&dir), 0);
+}
+
+#[tokio::test]
+async fn non_xml_body_is_none() {
+    let (server, dir, config) = setup().await;
+    stub(
+        &server,
+        2,
+        ResponseTemplate::new(200).set_body_string("Recipe not available"),
+    )
+    .await;
+
+    let client = build_client(&config).unwrap();
+    assert!(download_recipe(&client, &config, 2)
+        .await
+        .unwrap()
+        .is_none());
+    assert_eq!(saved_files(&dir), 0);
+}
+
+#[tokio::test]
+async fn truncated_xml_is_none() {
+    let (server, dir, config) = setup().await;
+    let mut body = fixture();
+    body.truncate(body.len() / 2);
+    stub(&server, 3, ResponseTemplate::new(200).set_body_bytes(body)).await;
+
+    let client = build_client(&config).unwrap();
+    assert!(download_recipe(&client, &config, 3)
+        .await
+        .unwrap()
+        .is_none());
+    assert_eq!(saved_files(&dir), 0);
+}
+
+#[tokio::test]
+async fn unauthorized_is_session_expired() {
+    let (server, dir, config) = setup().await;
+    stub(&server, 5, ResponseTemplate::new(401)).await;
+
+    let client = build_client(&config).unwrap();
+    let err = download_recipe(&client, &config, 5).await.unwrap_err();
+
+    assert!(err.is::<SessionExpired>());
+    assert_eq!(saved_files(&dir), 0);
+}
+
+#[tokio::test]
+async fn sends_unexpired_cookies() {
+    let (server, dir, mut config) = setup().await;
+    let cookies = dir.path().join("cookies.txt");
+    fs::write(
+        &cookies,
+        "# Netscape HTTP Cookie File\n\
+         127.0.0.1\tFALSE\t/\tFALSE\t0\tsession\tabc\n\
+         127.0.0.1\tFALSE\t/\tFALSE\t1000000000\tstale\txyz\n",
+    )
+    .unwrap();
+    config.cookies = Some(Arc::new(parse_netscape_cookies(&cookies).unwrap()));
+    Mock::given(method("GET"))
+        .and(path("/download.php"))
+        .and(header("cookie", "session=abc"))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(fixture()))
+        .mount(&server)
+        .await;
+
+    let client = build_client(&config).unwrap();
+    assert!(download_recipe(&client, &config, 6)
+        .await
+        .unwrap()
+        .is_some());
+}
+
+#[tokio::test]
+async fn saves_the_cookies_the_server_sets() {
+    let (server, dir, mut config) = setup().await;
+    let cookies = dir.path().join("cookies.txt");
+    fs::write(
+        &cookies,
+        "#HttpOnly_.127.0.0.1\tTRUE\t/\tTRUE\t4102444800\tremember\tme\n\
+         127.0.0.1\tFALSE\t/\tFALSE\t0\tsession\told\n\
+         127.0.0.1\tFALSE\t/\tFALSE\t0\tlogout\tsoon\n",
+    )
+    .unwrap();
+    let jar = Arc::new(parse_netscape_cookies(&cookies).unwrap());
+    config.cookies = Some(jar.clone());
+    Mock::given(method("GET"))
+        .and(path("/download.php"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_bytes(fixture())
+                .append_header("set-cookie", "session=new; Path=/; HttpOnly")
+                .append_header("set-cookie", "logout=; Max-Age=0")
+                .append_header("set-cookie", "pref=dark; Path=/; Max-Age=3600"),
+        )
+        .mount(&server)
+        .await;
+
+    let client = build_client(&config).unwrap();
+    download_recipe(&client, &config, 6).await.unwrap();
+    assert_eq!(jar.save(&cookies).unwrap(), 3);
+
+    let saved = fs::read_to_string(&cookies).unwrap();
+    let lines: Vec<&str> = saved.lines().collect();
+    assert_eq!(lines[0], "# Netscape HTTP Cookie File");
+    assert!(lines.contains(&"#HttpOnly_.127.0.0.1\tTRUE\t/\tTRUE\t4102444800\tremember\tme"));
+    assert!(lines.contains(&"#HttpOnly_127.0.0.1\tFALSE\t/\tFALSE\t0\tsession\tnew"));
+    assert!(lines.iter().any(|l| l.ends_with("\tpref\tdark")));
+    assert!(!saved.contains("logout"));
+    // What was saved reads back in
+    assert!(format!("{:?}", parse_netscape_cookies(&cookies).unwrap()).contains("cookies: 3"));
+}
+
+#[test]
+fn parses_custom_headers() {
+    let auth: RequestHeader = "Authorization: Bearer token:with:colons".parse().unwrap();
+    assert_eq!(auth.name, "authorization");
+    assert_eq!(auth.value, "Bearer token:with:colons");
+    let empty: RequestHeader = "X-Empty:".parse().unwrap();
+    assert_eq!(empty.value, "");
+
+    assert!("X-Api-Key".parse::<RequestHeader>().is_err());
+    assert!("X Api: 1".parse::<RequestHeader>().is_err());
+    assert!("Schlüssel: 1".parse::<RequestHeader>().is_err());
+    assert!("X-Api-Key: a\nb".parse::<RequestHeader>().is_err());
+}
+
+#[tokio::test]
+async fn sends_custom_headers_alongside_the_built_in_ones() {
+    let (server, _dir, mut config) = setup().await;
+    config.headers = vec![
+        "X-Api-Key: abc123".parse().unwrap(),
+        "Authorization: Bearer t:1".parse().unwrap(),
+    ];
+    Mock::given(method("GET"))
+        .and(path("/download.php"))
+        .and(header("x-api-key", "abc123"))
+        .and(header("authorization", "Bearer t:1"))
+        .and(header_regex("user-agent", "^Mozilla/5.0 "))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(fixture()))
+        .mount(&server)
+        .await;
+
+    let client = build_client(&config).unwrap();
+    assert!(download_recipe(&client, &config, 6)
+        .await
+        .unwrap()
+        .is_some());
+}
+
+#[tokio::test]
+async fn custom_user_agent_replaces_the_built_in_one() {
+    let (server, _dir, mut config) = setup().await;
+    config.headers = vec!["User-Agent: archive-bot/1.0".parse().unwrap()];
+    stub(
+        &server,
+        6,
+        ResponseTemplate::new(200).set_body_bytes(fixture()),
+    )
+    .await;
+
+    let client = build_client(&config).unwrap();
+    download_recipe(&client, &config, 6).await.unwrap().unwrap();
+    let requests = server.received_requests().await.unwrap();
+    let agents: Vec<_> = requests[0].headers.get_all("user-agent").iter().collect();
+    assert_eq!(agents, vec!["archive-bot/1.0"]);
+}
+
+#[tokio::test]
+async fn slow_response_times_out() {
+    let (server, dir, config) = setup().await;
+    stub(
+        &server,
+        4,
+        ResponseTemplate::new(200)
+            .set_body_bytes(fixture())
+            .set_delay(Duration::from_secs(5)),
+    )
+    .await;
+
+    let client = build_client(&config).unwrap();
+    let err = download_recipe(&client, &config, 4).await.unwrap_err();
+
+    let err = err
+        .downcast_ref::<reqwest::Error>()
+        .expect("timeout should surface as a reqwest error");
+    assert!(err.is_timeout());
+    assert_eq!(saved_files(&dir), 0);
+}
+
+#[tokio::test]
+async fn skips_incomplete_recipe() {
+    let (server, dir, mut config) = setup().await;
+    config.skip_incomplete = Some(1.0);
+    stub(
+        &server,
+        42,
+        ResponseTemplate::new(200).set_body_bytes(fixture()),
+    )
+    .await;
+
+    let client = build_client(&config).unwrap();
+    let info = download_recipe(&client, &config, 42).await.unwrap();
+
+    // The fixture has no mash profile, so scores 0.9
+    assert!(info.is_none());
+    assert_eq!(saved_files(&dir), 0);
+
+    config.skip_incomplete = Some(0.9);
+    let info = download_recipe(&client, &config, 42).await.unwrap();
+    assert!(info.is_some());
+}
+
+async fn stub_head(server: &MockServer, response: ResponseTemplate, expected: u64) {
+    Mock::given(method("HEAD"))
+        .and(path("/download.php"))
+        .respond_with(response)
+        .expect(expected)
+        .mount(server)
+        .await;
+}
+
+#[tokio::test]
+async fn head_precheck_skips_the_get_for_missing_ids() {
+    let (server, dir, mut config) = setup().await;
+    let precheck = Arc::new(HeadPrecheck::default());
+    config.head_precheck = Some(precheck.clone());
+    stub_head(&server, ResponseTemplate::new(404), 1).await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(404))
+        .expect(0)
+        .mount(&server)
+        .await;
+
+    let client = build_client(&config).unwrap();
+    assert!(download_recipe(&client, &config, 1)
+        .await
+        .unwrap()
+        .is_none());
+    assert_eq!(precheck.gets_avoided(), 1);
+    assert_eq!(saved_files(&dir), 0);
+}
+
+#[tokio::test]
+async fn head_precheck_skips_error_pages() {
+    let (server, _dir, mut config) = setup().await;
+    let precheck = Arc::new(HeadPrecheck::default());
+    config.head_precheck = Some(precheck.clone());
+    stub_head(
+        &server,
+        ResponseTemplate::new(200).insert_header("content-type", "text/html; charset=utf-8"),
+        1,
+    )
+    .await;
+
+    let client = build_client(&config).unwrap();
+    assert!(download_recipe(&client, &config, 3)
+        .await
+        .unwrap()
+        .is_none());
+    assert_eq!(precheck.gets_avoided(), 1);
+}
+
+#[tokio::test]
+async fn head_precheck_lets_attachments_through() {
+    let (server, dir, mut config) = setup().await;
+    let precheck = Arc::new(HeadPrecheck::default());
+    config.head_precheck = Some(precheck.clone());
+    stub_head(
+        &server,
+        ResponseTemplate::new(200)
+            .insert_header("content-disposition", "attachment; filename=\"Biere.bsmx\""),
+        1,
+    )
+    .await;
+    stub(
+        &server,
+        42,
+        ResponseTemplate::new(200).set_body_bytes(fixture()),
+    )
+    .await;
+
+    let client = build_client(&config).unwrap();
+    download_recipe(&client, &config, 42)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(dir.path().join("42.bsmx").exists());
+    assert_eq!(precheck.gets_avoided(), 0);
+}
+
+#[tokio::test]
+async fn refused_head_falls_back_to_get_for_the_rest_of_the_run() {
+    let (server, dir, mut config) = setup().await;
+    let precheck = Arc::new(HeadPrecheck::default());
+    config.head_precheck = Some(precheck.clone());
+    stub_head(&server, ResponseTemplate::new(405), 1).await;
+    for id in [5, 6] {
+        stub(
+            &server,
+            id,
+            ResponseTemplate::new(200).set_body_bytes(fixture()),
+        )
+        .await;
+    }
+
+    let client = build_client(&config).unwrap();
+    for id in [5, 6] {
+        download_recipe(&client, &config, id)
+            .await
+            .unwrap()
+            .unwrap();
+    }
+    assert!(!precheck.is_supported());
+    assert_eq!(saved_files(&dir), 2);
+}
+
+/// A server that answers successive connections with `responses` in turn,
+/// written as raw bytes so one can end partway through its body. Returns
+/// its URL and the request heads it received.
+async fn serve_raw(responses: Vec<Vec<u8>>) -> (String, Arc<Mutex<Vec<String>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let seen = requests.clone();
+    tokio::spawn(async move {
+        for response in responses {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut head = Vec::new();
+            while !head.ends_with(b"\r\n\r\n") {
+                let mut byte = [0; 1];
+                if socket.read(&mut byte).await.unwrap() == 0 {
+                    break;
+                }
+                head.push(byte[0]);
+            }
+            seen.lock()
+                .unwrap()
+                .push(String::from_utf8_lossy(&head).to_lowercase());
+            socket.write_all(&response).await.unwrap();
+            // Dropping the socket cuts off a response shorter than its
+            // Content-Length
+        }
+    });
+    (url, requests)
+}
+
+fn raw_response(status: &str, headers: &[String], body: &[u8]) -> Vec<u8> {
+    let mut response = format!("HTTP/1.1 {}\r\nConnection: close\r\n", status);
+    for header in headers {
+        response.push_str(header);
+        response.push_str("\r\n");
+    }
+    response.push_str("\r\n");
+    let mut response = response.into_bytes();
+    response.extend_from_slice(body);
+    response
+}
+
+/// A 200 for the whole fixture that breaks off halfway.
+fn broken_transfer(accept_ranges: bool) -> Vec<u8> {
+    let body = fixture();
+    let mut headers = vec![format!("Content-Length: {}", body.len())];
+    if accept_ranges {
+        headers.push("Accept-Ranges: bytes".to_string());
+    }
+    raw_response("200 OK", &headers, &body[..body.len() / 2])
+}
+
+#[tokio::test]
+async fn resumes_a_broken_transfer_with_a_range_request() {
+    let body = fixture();
+    let half = body.len() / 2;
+    let rest = raw_response(
+        "206 Partial Content",
+        &[
+            format!(
+                "Content-Range: bytes {}-{}/{}",
+                half,
+                body.len() - 1,
+                body.len()
+            ),
+            format!("Content-Length: {}", body.len() - half),
+        ],
+        &body[half..],
+    );
+    let (url, requests) = serve_raw(vec![broken_transfer(true), rest]).await;
+    let (_server, dir, mut config) = setup().await;
+    config.base_url = url;
+    let client = build_client(&config).unwrap();
+
+    assert!(download_recipe(&client, &config, 7).await.is_err());
+    let partial = dir.path().join("7.bsmx.part");
+    assert_eq!(fs::read(&partial).unwrap(), &body[..half]);
+    // What arrived before the break counts toward the bytes received
+    assert_eq!(config.transfers.received(), half as u64);
+
+    download_recipe(&client, &config, 7).await.unwrap().unwrap();
+    assert_eq!(fs::read(dir.path().join("7.bsmx")).unwrap(), body);
+    assert_eq!(config.transfers.received(), body.len() as u64);
+    assert!(!partial.exists());
+    let requests = requests.lock().unwrap();
+    assert!(!requests[0].contains("range:"));
+    assert!(
+        requests[1].contains(&format!("range: bytes={}-\r\n", half)),
+        "{}",
+        requests[1]
+    );
+}
+
+/// Serves one response, a piece at a time with a pause before each.
+async fn serve_slowly(pieces: Vec<(Duration, Vec<u8>)>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let mut head = [0; 1024];
+        let _ = socket.read(&mut head).await;
+        for (pause, piece) in pieces {
+            tokio::time::sleep(pause).await;
+            if socket.write_all(&piece).await.is_err() {
+                return;
+            }
+        }
+    });
+    url
+}
+
+/// The fixture in `n` pieces, `pause` apart, after the headers.
+fn trickle(n: usize, pause: Duration) -> Vec<(Duration, Vec<u8>)> {
+    let body = fixture();
+    let head = raw_response(
+        "200 OK",
+        &[
+            format!("Content-Length: {}", body.len()),
+            "Accept-Ranges: bytes".to_string(),
+        ],
+        &[],
+    );
+    let mut pieces = vec![(Duration::ZERO, head)];
+    for chunk in body.chunks(body.len().div_ceil(n)) {
+        pieces.push((pause, chunk.to_vec()));
+    }
+    pieces
+}
+
+#[tokio::test]
+async fn read_timeout_gives_up_on_a_stalled_body() {
+    let mut pieces = trickle(2, Duration::ZERO);
+    pieces[2].0 = Duration::from_secs(5);
+    let (_server, dir, mut config) = setup().await;
+    config.base_url = serve_slowly(pieces).await;
+    config.read_timeout = Duration::from_millis(200);
+    config.request_timeout = Duration::from_secs(10);
+    let client = build_client(&config).unwrap();
+
+    let started = Instant::now();
+    let err = download_recipe(&client, &config, 7).await.unwrap_err();
+    assert!(started.elapsed() < Duration::from_secs(2));
+    assert!(
+        matches!(err.downcast_ref(), Some(TimedOut::Read(_))),
+        "{}",
+        err
+    );
+    // What arrived is kept for the next attempt to resume
+    assert!(dir.path().join("7.bsmx.part").exists());
+}
+
+#[tokio::test]
+async fn steady_downloads_can_outlast_the_request_timeout() {
+    for streaming in [false, true] {
+        let (_server, _dir, mut config) = setup().await;
+        config.base_url = serve_slowly(trickle(6, Duration::from_millis(100))).await;
+        config.read_timeout = Duration::from_millis(300);
+        config.request_timeout = Duration::from_millis(350);
+        config.stream_past_request_timeout = streaming;
+        let client = build_client(&config).unwrap();
+
+        let result = download_recipe(&client, &config, 7).await;
+        if streaming {
+            assert!(result.unwrap().is_some());
+        } else {
+            let err = result.unwrap_err();
+            let err = err.downcast_ref::<reqwest::Error>().unwrap();
+            assert!(err.is_timeout(), "{}", err);
+        }
+    }
+}
+
+#[tokio::test]
+async fn server_ignoring_the_range_sends_the_whole_file_again() {
+    let body = fixture();
+    let whole = raw_response(
+        "200 OK",
+        &[format!("Content-Length: {}", body.len())],
+        &body,
+    );
+    let (url, _) = serve_raw(vec![broken_transfer(true), whole]).await;
+    let (_server, dir, mut config) = setup().await;
+    config.base_url = url;
+    let client = build_client(&config).unwrap();
+
+    assert!(download_recipe(&client, &config, 7).await.is_err());
+    download_recipe(&client, &config, 7).await.unwrap().unwrap();
+    assert_eq!(fs::read(dir.path().join("7.bsmx")).unwrap(), body);
+    assert!(!dir.path().join("7.bsmx.part").exists());
+}
+
+#[tokio::test]
+async fn broken_transfer_without_range_support_is_discarded() {
+    let (url, _) = serve_raw(vec![broken_transfer(false)]).await;
+    let (_server, dir, mut config) = setup().await;
+    config.base_url = url;
+    let client = build_client(&config).unwrap();
+
+    assert!(download_recipe(&client, &config, 7).await.is_err());
+    assert_eq!(saved_files(&dir), 0);
+}
+
+#[tokio::test]
+async fn max_rps_gates_prechecks_and_downloads() {
+    let (server, dir, mut config) = setup().await;
+    stub_head(
+        &server,
+        ResponseTemplate::new(200).insert_header("content-disposition", "attachment"),
+        3,
+    )
+    .await;
+    for id in [1, 2, 3] {
+        stub(
+            &server,
+            id,
+            ResponseTemplate::new(200).set_body_bytes(fixture()),
+        )
+        .await;
+    }
+    config.head_precheck = Some(Arc::new(HeadPrecheck::default()));
+    let client = build_client(&config).unwrap();
+
+    // Six requests, a HEAD and a GET for each, a tenth of a second apart.
+    // The gate is made last, so its bucket hasn't filled in the meantime
+    config.requests = Arc::new(RequestGate::new(Some(10.0)));
+    let started = Instant::now();
+    for id in [1, 2, 3] {
+        download_recipe(&client, &config, id)
+            .await
+            .unwrap()
+            .unwrap();
+    }
+    assert!(started.elapsed() >= Duration::from_millis(480));
+    assert_eq!(saved_files(&dir), 3);
+    assert_eq!(server.received_requests().await.unwrap().len(), 6);
+}
+
+#[tokio::test]
+async fn bandwidth_cap_slows_downloads() {
+    let (server, dir, mut config) = setup().await;
+    for id in [1, 2, 3] {
+        stub(
+            &server,
+            id,
+            ResponseTemplate::new(200).set_body_bytes(fixture()),
+        )
+        .await;
+    }
+    // 3 files of 887 bytes, the first 500 bytes free
+    config.bandwidth = Some(Arc::new(ByteLimiter::new(ByteSize(2_000), ByteSize(500))));
+    let client = build_client(&config).unwrap();
+
+    let started = Instant::now();
+    for id in [1, 2, 3] {
+        download_recipe(&client, &config, id)
+            .await
+            .unwrap()
+            .unwrap();
+    }
+    assert!(started.elapsed() >= Duration::from_millis(900));
+    assert_eq!(saved_files(&dir), 3);
+}
@@ -0,0 +1,41 @@
+use beer_scape::commands::cluster::kmeans;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+#[test]
+fn separates_distinct_groups() {
+    let points = [
+        [0.0, 0.0],
+        [0.1, 0.0],
+        [0.0, 0.1],
+        [1.0, 1.0],
+        [0.9, 1.0],
+        [1.0, 0.9],
+    ];
+    let result = kmeans(&points, 2, 10, &mut StdRng::seed_from_u64(1));
+
+    assert_eq!(result.centroids.len(), 2);
+    assert_eq!(result.assignments[0], result.assignments[1]);
+    assert_eq!(result.assignments[0], result.assignments[2]);
+    assert_eq!(result.assignments[3], result.assignments[4]);
+    assert_eq!(result.assignments[3], result.assignments[5]);
+    assert_ne!(result.assignments[0], result.assignments[3]);
+    assert!(result.inertia < 0.05);
+}
+
+#[test]
+fn same_seed_gives_same_clusters() {
+    let points: Vec<[f64; 1]> = (0..50).map(|i| [(i * 7 % 13) as f64]).collect();
+    let a = kmeans(&points, 4, 10, &mut StdRng::seed_from_u64(42));
+    let b = kmeans(&points, 4, 10, &mut StdRng::seed_from_u64(42));
+    assert_eq!(a.assignments, b.assignments);
+    assert_eq!(a.centroids, b.centroids);
+}
+
+#[test]
+fn never_makes_more_clusters_than_points() {
+    let points = [[0.5], [0.7]];
+    let result = kmeans(&points, 20, 10, &mut StdRng::seed_from_u64(0));
+    assert_eq!(result.centroids.len(), 2);
+    assert_eq!(result.inertia, 0.0);
+}
@@ -0,0 +1,191 @@
+mod common;
+
+use beer_scape::bsmx::parse_bsmx;
+use beer_scape::cli::StorageLayout;
+use beer_scape::collection::{recipe_files_in, scan_stems, write_sidecar, Provenance, ARCHIVE_DIR};
+use beer_scape::index::{Index, IndexEntry};
+use beer_scape::layout::{relayout, style_slug, UNKNOWN_STYLE};
+use beer_scape::state::State;
+use common::{beerscape, fixture, serve_recipes, status_line};
+use indicatif::ProgressBar;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::process::Command;
+
+#[test]
+fn slugs_style_names() {
+    assert_eq!(style_slug("Bière de Garde"), "bière-de-garde");
+    assert_eq!(style_slug("  India Pale Ale (IPA) "), "india-pale-ale-ipa");
+    assert_eq!(style_slug("../.."), UNKNOWN_STYLE);
+    assert_eq!(style_slug("Archive"), "archive-style");
+}
+
+#[test]
+fn places_files_by_layout() {
+    let contents = fixture();
+    let recipe = parse_bsmx(&contents).unwrap();
+    let hash = format!("{:x}", Sha256::digest(&contents));
+
+    assert_eq!(
+        StorageLayout::Flat.path_for("42.bsmx", &contents, Some(&recipe)),
+        "42.bsmx"
+    );
+    assert_eq!(
+        StorageLayout::Cas.path_for("42.bsmx", &contents, None),
+        format!("{}/{}.bsmx", &hash[..2], hash)
+    );
+    assert_eq!(
+        StorageLayout::Style.path_for("42.bsmx", &contents, Some(&recipe)),
+        "bière-de-garde/42.bsmx"
+    );
+    assert_eq!(
+        StorageLayout::Style.path_for("42.bsmx", &contents, None),
+        format!("{}/42.bsmx", UNKNOWN_STYLE)
+    );
+}
+
+#[test]
+fn nested_layouts_scan_subfolders_but_not_the_archive() {
+    let dir = TempDir::new().unwrap();
+    fs::write(dir.path().join("1.bsmx"), "<Recipe/>").unwrap();
+    for folder in ["porter", ARCHIVE_DIR] {
+        fs::create_dir(dir.path().join(folder)).unwrap();
+    }
+    fs::write(dir.path().join("porter/2.bsmx"), "<Recipe/>").unwrap();
+    fs::write(dir.path().join(ARCHIVE_DIR).join("3.bsmx"), "<Recipe/>").unwrap();
+
+    let files = recipe_files_in(dir.path(), StorageLayout::Style).unwrap();
+    assert_eq!(files, vec![dir.path().join("porter/2.bsmx")]);
+    let stems = scan_stems(dir.path(), StorageLayout::Style, &ProgressBar::hidden()).unwrap();
+    assert_eq!(stems.into_iter().collect::<Vec<_>>(), vec!["2"]);
+
+    let files = recipe_files_in(dir.path(), StorageLayout::Flat).unwrap();
+    assert_eq!(files, vec![dir.path().join("1.bsmx")]);
+}
+
+#[test]
+fn migrates_there_and_back_keeping_index_and_sidecars() {
+    let dir = TempDir::new().unwrap();
+    let contents = fixture();
+    let recipe = parse_bsmx(&contents).unwrap();
+    let path = dir.path().join("42.bsmx");
+    fs::write(&path, &contents).unwrap();
+    write_sidecar(&path, &recipe, &Provenance::default()).unwrap();
+    let index = Index::open(dir.path()).unwrap();
+    let mut entry = IndexEntry::new("42.bsmx", &recipe);
+    entry.recipe_id = Some(42);
+    index.upsert(&entry).unwrap();
+
+    let result = relayout(dir.path(), &index, StorageLayout::Cas).unwrap();
+    assert_eq!(result.moved, 1);
+    let hash = format!("{:x}", Sha256::digest(&contents));
+    let stored = format!("{}/{}.bsmx", &hash[..2], hash);
+    assert_eq!(fs::read(dir.path().join(&stored)).unwrap(), contents);
+    assert!(dir
+        .path()
+        .join(format!("{}/{}.json", &hash[..2], hash))
+        .exists());
+    assert!(!path.exists());
+    assert_eq!(index.recipe_id(&stored).unwrap(), Some(42));
+    assert_eq!(
+        State::load(dir.path()).unwrap().storage_layout,
+        StorageLayout::Cas
+    );
+
+    // Back out of CAS, the ID in the index names the file again
+    let result = relayout(dir.path(), &index, StorageLayout::Style).unwrap();
+    assert_eq!(result.moved, 1);
+    assert!(dir.path().join("bière-de-garde/42.bsmx").exists());
+    assert!(dir.path().join("bière-de-garde/42.json").exists());
+    assert!(!dir.path().join(&hash[..2]).exists());
+    assert_eq!(
+        index.filenames().unwrap(),
+        vec!["bière-de-garde/42.bsmx".to_string()]
+    );
+
+    let result = relayout(dir.path(), &index, StorageLayout::Flat).unwrap();
+    assert_eq!(result.moved, 1);
+    assert_eq!(fs::read(&path).unwrap(), contents);
+    assert_eq!(index.recipe_id("42.bsmx").unwrap(), Some(42));
+}
+
+#[test]
+fn never_overwrites_a_taken_name() {
+    let dir = TempDir::new().unwrap();
+    let contents = fixture();
+    fs::write(dir.path().join("1.bsmx"), &contents).unwrap();
+    fs::write(dir.path().join("2.bsmx"), &contents).unwrap();
+    let index = Index::open(dir.path()).unwrap();
+
+    // The copies hash the same, so only the first can move
+    let result = relayout(dir.path(), &index, StorageLayout::Cas).unwrap();
+    assert_eq!(result.moved, 1);
+    assert_eq!(result.skipped.len(), 1);
+    assert_eq!(result.skipped[0].0, dir.path().join("2.bsmx"));
+    assert_eq!(fs::read(dir.path().join("2.bsmx")).unwrap(), contents);
+
+    // Running it again only finds the leftover
+    let result = relayout(dir.path(), &index, StorageLayout::Cas).unwrap();
+    assert_eq!((result.moved, result.in_place), (0, 1));
+    assert_eq!(result.skipped.len(), 1);
+}
+
+#[tokio::test]
+async fn storage_layout_is_kept_until_migrated() {
+    let server = serve_recipes(Duration::ZERO).await;
+    let dir = TempDir::new().unwrap();
+    let recipes = dir.path().join("recipes");
+
+    let output = beerscape(
+        &dir,
+        &server.uri(),
+        &["--ids", "1,2", "--storage-layout", "style"],
+    )
+    .output()
+    .await
+    .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    assert!(recipes.join("bière-de-garde/1.bsmx").exists());
+    assert!(recipes.join("bière-de-garde/2.bsmx").exists());
+
+    // Later runs find the files, and keep to the layout, without the flag
+    let output = beerscape(&dir, &server.uri(), &["--ids", "1,3"])
+        .output()
+        .await
+        .unwrap();
+    assert_eq!(
+        status_line(&output),
+        "beerscape: complete: ids-exhausted, 1 downloaded, 0 failed"
+    );
+    assert!(recipes.join("bière-de-garde/3.bsmx").exists());
+
+    let output = beerscape(
+        &dir,
+        &server.uri(),
+        &["--ids", "4", "--storage-layout", "flat"],
+    )
+    .output()
+    .await
+    .unwrap();
+    assert_eq!(output.status.code(), Some(4));
+    assert!(
+        status_line(&output).contains("run `beerscape migrate-layout --to flat`"),
+        "{}",
+        status_line(&output)
+    );
+
+    let output = Command::new(env!("CARGO_BIN_EXE_beerscape"))
+        .current_dir(dir.path())
+        .args(["migrate-layout", "--to", "flat"])
+        .output()
+        .await
+        .unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Moved: 3\n"));
+    for id in 1..=3 {
+        assert!(recipes.join(format!("{}.bsmx", id)).exists());
+    }
+    assert!(!recipes.join("bière-de-garde").exists());
+}
@@ -0,0 +1,61 @@
+use beer_scape::limits::{next_occurrence, ClockTime, RollingHitRate, RunDuration};
+use chrono::{NaiveDate, NaiveTime};
+use std::time::Duration;
+
+#[test]
+fn parses_durations() {
+    let parse = |s: &str| s.parse::<RunDuration>().map(|d| d.0);
+    assert_eq!(parse("5h"), Ok(Duration::from_secs(5 * 3600)));
+    assert_eq!(parse("90m"), Ok(Duration::from_secs(90 * 60)));
+    assert_eq!(parse("1h30m"), Ok(Duration::from_secs(5400)));
+    assert_eq!(parse("45s"), Ok(Duration::from_secs(45)));
+    assert!(parse("5").is_err());
+    assert!(parse("5d").is_err());
+    assert!(parse("h").is_err());
+    assert!(parse("0m").is_err());
+}
+
+#[test]
+fn parses_clock_times() {
+    let time: ClockTime = "07:00".parse().unwrap();
+    assert_eq!(time.0, NaiveTime::from_hms_opt(7, 0, 0).unwrap());
+    assert_eq!(time.to_string(), "07:00");
+    assert!("7am".parse::<ClockTime>().is_err());
+    assert!("25:00".parse::<ClockTime>().is_err());
+}
+
+#[test]
+fn deadline_is_the_next_time_the_clock_reads_it() {
+    let day = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+    let seven = NaiveTime::from_hms_opt(7, 0, 0).unwrap();
+
+    let at_two = day.and_hms_opt(2, 0, 0).unwrap();
+    assert_eq!(next_occurrence(seven, at_two), day.and_time(seven));
+
+    let at_eight = day.and_hms_opt(8, 0, 0).unwrap();
+    let tomorrow = day.succ_opt().unwrap();
+    assert_eq!(next_occurrence(seven, at_eight), tomorrow.and_time(seven));
+    assert_eq!(
+        next_occurrence(seven, day.and_time(seven)),
+        tomorrow.and_time(seven)
+    );
+}
+
+#[test]
+fn hit_rate_covers_only_the_window() {
+    let mut rate = RollingHitRate::new(4);
+    rate.record(true);
+    rate.record(true);
+    rate.record(false);
+    assert_eq!(rate.rate(), None);
+
+    rate.record(false);
+    assert_eq!(rate.rate(), Some(0.5));
+    rate.record(false);
+    rate.record(false);
+    assert_eq!(rate.rate(), Some(0.0));
+
+    let mut disabled = RollingHitRate::new(0);
+    disabled.record(false);
+    assert_eq!(disabled.rate(), None);
+}
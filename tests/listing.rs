@@ -0,0 +1,140 @@
+mod common;
+
+use beer_scape::listing::{listing_url, parse_listing, ListedRecipe, Search};
+use common::{beerscape, serve_recipes, status_line};
+use std::fs;
+use std::time::Duration;
+use tempfile::TempDir;
+use wiremock::matchers::{method, path, query_param};
+use wiremock::{Mock, ResponseTemplate};
+
+fn page(name: &str) -> String {
+    fs::read_to_string(format!("tests/fixtures/{}", name)).unwrap()
+}
+
+fn recipe(id: u32, name: &str) -> ListedRecipe {
+    ListedRecipe {
+        id,
+        name: name.to_string(),
+    }
+}
+
+#[test]
+fn reads_recipe_links_from_a_results_page() {
+    let listing = parse_listing(&page("listing_page1.html"));
+    assert_eq!(
+        listing.recipes,
+        vec![
+            recipe(1201, "West Coast IPA"),
+            recipe(1187, "Hazy Days NEIPA"),
+            recipe(1160, "Black IPA & Friends"),
+        ]
+    );
+    assert!(listing.has_next);
+}
+
+#[test]
+fn last_page_has_no_next() {
+    let listing = parse_listing(&page("listing_page2.html"));
+    assert_eq!(
+        listing.recipes,
+        vec![recipe(1099, "Session IPA"), recipe(1201, "West Coast IPA")]
+    );
+    assert!(!listing.has_next);
+}
+
+#[test]
+fn copes_with_pages_that_are_not_listings() {
+    let listing = parse_listing("<p>Too many requests</p><a href=next>");
+    assert!(listing.recipes.is_empty());
+    assert!(!listing.has_next);
+    assert!(parse_listing("").recipes.is_empty());
+}
+
+#[test]
+fn builds_search_urls() {
+    assert_eq!(
+        listing_url("https://site.example", Search::Query("Pale & Hoppy"), 3)
+            .unwrap()
+            .as_str(),
+        "https://site.example/search.php?q=Pale+%26+Hoppy&page=3"
+    );
+    assert_eq!(
+        listing_url("https://site.example", Search::All, 1)
+            .unwrap()
+            .as_str(),
+        "https://site.example/search.php?page=1"
+    );
+    assert_eq!(
+        listing_url("https://site.example", Search::Style("Pilsner"), 2)
+            .unwrap()
+            .as_str(),
+        "https://site.example/search.php?style=Pilsner&page=2"
+    );
+}
+
+#[tokio::test]
+async fn discovers_ids_and_downloads_them() {
+    let server = serve_recipes(Duration::ZERO).await;
+    for (page, body) in [("1", "listing_page1.html"), ("2", "listing_page2.html")] {
+        Mock::given(method("GET"))
+            .and(path("/search.php"))
+            .and(query_param("q", "IPA"))
+            .and(query_param("page", page))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_string(
+                    fs::read_to_string(format!("tests/fixtures/{}", body)).unwrap(),
+                ),
+            )
+            .mount(&server)
+            .await;
+    }
+    let dir = TempDir::new().unwrap();
+
+    let output = beerscape(&dir, &server.uri(), &["discover", "--query", "IPA"])
+        .output()
+        .await
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Pages Read: 2\n"), "{}", stdout);
+    assert!(stdout.contains("Recipe IDs Found: 4\n"), "{}", stdout);
+    assert_eq!(
+        fs::read_to_string(dir.path().join("discovered_ids.txt")).unwrap(),
+        "# beerscape discover --query \"IPA\"\n\
+         1201 # West Coast IPA\n\
+         1187 # Hazy Days NEIPA\n\
+         1160 # Black IPA & Friends\n\
+         1099 # Session IPA\n"
+    );
+
+    let output = beerscape(&dir, &server.uri(), &["--ids-file", "discovered_ids.txt"])
+        .output()
+        .await
+        .unwrap();
+    assert_eq!(
+        status_line(&output),
+        "beerscape: complete: ids-exhausted, 4 downloaded, 0 failed"
+    );
+
+    let output = beerscape(
+        &dir,
+        &server.uri(),
+        &[
+            "--force",
+            "discover",
+            "--query",
+            "IPA",
+            "--max-pages",
+            "1",
+            "--download",
+        ],
+    )
+    .output()
+    .await
+    .unwrap();
+    assert_eq!(
+        status_line(&output),
+        "beerscape: complete: ids-exhausted, 3 downloaded, 0 failed"
+    );
+}
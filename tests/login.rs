@@ -0,0 +1,178 @@
+use beer_scape::login::{Credentials, Login};
+use std::fs;
+use std::path::Path;
+use std::process::Stdio;
+use tempfile::TempDir;
+use tokio::process::Command;
+use wiremock::matchers::{body_string_contains, header, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const LOGIN_FORM: &str = r#"<html><body><form method="post" action="/login.php">
+<input name="username"><input type="password" name="password">
+</form></body></html>"#;
+
+fn login(password_field: &str) -> Login {
+    Login {
+        url: "https://example.com/login.php".to_string(),
+        username_field: "username".to_string(),
+        password_field: password_field.to_string(),
+        credentials: Credentials::new("brewer", "s3cret"),
+    }
+}
+
+#[test]
+fn keeps_the_password_out_of_debug_output() {
+    let shown = format!("{:?}", login("password"));
+    assert!(shown.contains("brewer"), "{}", shown);
+    assert!(!shown.contains("s3cret"), "{}", shown);
+}
+
+#[test]
+fn recognizes_login_pages() {
+    assert!(login("password").is_login_page(LOGIN_FORM.as_bytes()));
+    // A field named as configured, whatever its type
+    let custom = "<FORM><input type=text name=\"pw\"></FORM>";
+    assert!(login("pw").is_login_page(custom.as_bytes()));
+    assert!(!login("password").is_login_page(custom.as_bytes()));
+
+    assert!(!login("password").is_login_page(b"<html>Not found</html>"));
+    assert!(!login("password").is_login_page(&fs::read("tests/fixtures/utf8.bsmx").unwrap()));
+}
+
+fn beerscape(dir: &TempDir, args: &[&str]) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_beerscape"));
+    command
+        .current_dir(dir.path())
+        .args(args)
+        .env_remove("BEERSCAPE_USERNAME")
+        .env("BEERSCAPE_PASSWORD", "s3cret")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    command
+}
+
+/// Every file under `dir`, read as text.
+fn contents(dir: &Path) -> String {
+    let mut all = String::new();
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            all.push_str(&contents(&path));
+        } else {
+            all.push_str(&String::from_utf8_lossy(&fs::read(&path).unwrap()));
+        }
+    }
+    all
+}
+
+async fn login_form(server: &MockServer) {
+    for route in ["/login.php", "/download.php"] {
+        Mock::given(method("GET"))
+            .and(path(route))
+            .respond_with(ResponseTemplate::new(200).set_body_string(LOGIN_FORM))
+            .with_priority(10)
+            .mount(server)
+            .await;
+    }
+}
+
+#[tokio::test]
+async fn logs_in_and_again_when_the_session_runs_out() {
+    let server = MockServer::start().await;
+    login_form(&server).await;
+    Mock::given(method("POST"))
+        .and(path("/login.php"))
+        .and(body_string_contains("username=brewer"))
+        .and(body_string_contains("password=s3cret"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_string("<p>Welcome back</p>")
+                .append_header("set-cookie", "session=abc; Path=/"),
+        )
+        .expect(2)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/login.php"))
+        .and(header("cookie", "session=abc"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("<p>Logged in</p>"))
+        .mount(&server)
+        .await;
+    // The session runs out after the first login
+    Mock::given(method("GET"))
+        .and(path("/download.php"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(LOGIN_FORM))
+        .up_to_n_times(1)
+        .with_priority(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/download.php"))
+        .and(header("cookie", "session=abc"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_bytes(fs::read("tests/fixtures/utf8.bsmx").unwrap()),
+        )
+        .mount(&server)
+        .await;
+
+    let dir = TempDir::new().unwrap();
+    let output = beerscape(
+        &dir,
+        &[
+            "--base-url",
+            &server.uri(),
+            "--username",
+            "brewer",
+            "--ids",
+            "7",
+        ],
+    )
+    .output()
+    .await
+    .unwrap();
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr);
+    assert!(dir.path().join("recipes/7.bsmx").exists());
+    assert!(stderr.contains("logging in again"), "{}", stderr);
+    assert!(!stderr.contains("s3cret"));
+    assert!(!String::from_utf8_lossy(&output.stdout).contains("s3cret"));
+    assert!(!contents(dir.path()).contains("s3cret"));
+    server.verify().await;
+}
+
+#[tokio::test]
+async fn a_rejected_login_is_fatal() {
+    let server = MockServer::start().await;
+    login_form(&server).await;
+    Mock::given(method("POST"))
+        .and(path("/login.php"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_string(format!("Wrong password{}", LOGIN_FORM)),
+        )
+        .mount(&server)
+        .await;
+
+    let dir = TempDir::new().unwrap();
+    let output = beerscape(
+        &dir,
+        &[
+            "--base-url",
+            &server.uri(),
+            "--username",
+            "brewer",
+            "--ids",
+            "7",
+        ],
+    )
+    .output()
+    .await
+    .unwrap();
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(4), "{}", stderr);
+    assert!(stderr.contains("turned the login down"), "{}", stderr);
+    assert!(!stderr.contains("s3cret"));
+}
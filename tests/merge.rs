@@ -0,0 +1,141 @@
+mod common;
+
+use beer_scape::state::{State, STATE_FILE};
+use common::fixture_text;
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+use tempfile::TempDir;
+
+/// The fixture with other ingredients, so it's a different recipe.
+fn other_recipe() -> String {
+    fixture_text().replace("Pilsner (2 Row) Bel", "Munich Malt")
+}
+
+/// The fixture under another name: the same ingredients, different bytes.
+fn renamed(name: &str) -> String {
+    fixture_text().replace("Bière de Garde à 12°", name)
+}
+
+fn merge(dir: &TempDir, args: &[&str]) -> Output {
+    let output = Command::new(env!("CARGO_BIN_EXE_beerscape"))
+        .current_dir(dir.path())
+        .args(["merge", "--source", "source", "--dest", "dest"])
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    output
+}
+
+fn write(dir: &Path, files: &[(&str, &str)]) {
+    fs::create_dir_all(dir).unwrap();
+    for (name, contents) in files {
+        fs::write(dir.join(name), contents).unwrap();
+    }
+}
+
+/// Two collections with a recipe each only has, one they share byte for
+/// byte, and one they each have a copy of under different names.
+fn collections() -> TempDir {
+    let dir = TempDir::new().unwrap();
+    write(
+        &dir.path().join("source"),
+        &[
+            ("1.bsmx", &fixture_text()),
+            ("2.bsmx", &other_recipe()),
+            ("3.bsmx", &renamed("Farmhouse Garde")),
+        ],
+    );
+    write(
+        &dir.path().join("dest"),
+        &[
+            ("1.bsmx", &fixture_text()),
+            ("4.bsmx", &renamed("Garde Ale")),
+        ],
+    );
+    dir
+}
+
+#[test]
+fn copies_what_the_destination_lacks_and_leaves_near_duplicates() {
+    let dir = collections();
+    for (side, retry_ids) in [("source", vec![7, 9]), ("dest", vec![8, 9])] {
+        let state = State {
+            retry_ids,
+            ..Default::default()
+        };
+        state.save(&dir.path().join(side)).unwrap();
+    }
+
+    let output = merge(&dir, &[]);
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Copied: 1\n"), "{}", stdout);
+    assert!(stdout.contains("Skipped as Duplicates: 1\n"), "{}", stdout);
+    assert!(stdout.contains("Unresolved: 1\n"), "{}", stdout);
+    assert!(stdout.contains("State File: the source's"), "{}", stdout);
+    let dest = dir.path().join("dest");
+    assert_eq!(
+        fs::read_to_string(dest.join("2.bsmx")).unwrap(),
+        other_recipe()
+    );
+    assert!(!dest.join("3.bsmx").exists());
+    assert_eq!(State::load(&dest).unwrap().retry_ids, vec![7, 8, 9]);
+    assert!(dest.join(STATE_FILE).exists());
+}
+
+#[test]
+fn settles_near_duplicates_with_keep() {
+    let dir = collections();
+    merge(&dir, &["--keep", "source"]);
+    let dest = dir.path().join("dest");
+    assert!(!dest.join("4.bsmx").exists());
+    assert_eq!(
+        fs::read_to_string(dest.join("3.bsmx")).unwrap(),
+        renamed("Farmhouse Garde")
+    );
+
+    // A name both sides use keeps the destination's under it
+    let dir = collections();
+    write(
+        &dir.path().join("source"),
+        &[("4.bsmx", &renamed("Farmhouse Garde"))],
+    );
+    fs::remove_file(dir.path().join("source/3.bsmx")).unwrap();
+    merge(&dir, &["--keep", "both"]);
+    let dest = dir.path().join("dest");
+    assert_eq!(
+        fs::read_to_string(dest.join("4.bsmx")).unwrap(),
+        renamed("Garde Ale")
+    );
+    assert_eq!(
+        fs::read_to_string(dest.join("4 (source).bsmx")).unwrap(),
+        renamed("Farmhouse Garde")
+    );
+}
+
+#[test]
+fn dry_run_only_reports_the_plan() {
+    let dir = collections();
+    let output = merge(&dir, &["--dry-run", "--keep", "source"]);
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("Would copy source/2.bsmx -> 2.bsmx"),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("Would Copy: 2\n"), "{}", stdout);
+    let mut left: Vec<_> = fs::read_dir(dir.path().join("dest"))
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect();
+    left.sort();
+    assert_eq!(left, ["1.bsmx", "4.bsmx"]);
+}
@@ -0,0 +1,103 @@
+use beer_scape::download::DownloadStats;
+use beer_scape::metrics::{Metrics, Remaining};
+use beer_scape::throttle::RequestGate;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[test]
+fn renders_prometheus_text() {
+    let stats = Arc::new(Mutex::new(DownloadStats {
+        existing: 7,
+        downloaded: 1,
+        failed: 2,
+        attempted: 3,
+        ..Default::default()
+    }));
+    let metrics = Metrics::new(stats.clone());
+    metrics.record_download("saved", Duration::from_millis(200));
+    metrics.add_bytes(1500);
+    metrics.record_download("not-found", Duration::from_millis(40));
+    metrics.record_download("something-new", Duration::from_secs(3));
+
+    let text = metrics.render();
+    assert!(text.contains("# TYPE beerscape_downloads_total counter\n"));
+    assert!(text.contains("beerscape_downloads_total{outcome=\"saved\"} 1\n"));
+    assert!(text.contains("beerscape_downloads_total{outcome=\"not-found\"} 1\n"));
+    assert!(text.contains("beerscape_downloads_total{outcome=\"other\"} 1\n"));
+    assert!(text.contains("beerscape_downloaded_bytes_total 1500\n"));
+    assert!(text.contains("beerscape_request_duration_seconds_bucket{le=\"0.05\"} 1\n"));
+    assert!(text.contains("beerscape_request_duration_seconds_bucket{le=\"0.25\"} 2\n"));
+    assert!(text.contains("beerscape_request_duration_seconds_bucket{le=\"+Inf\"} 3\n"));
+    assert!(text.contains("beerscape_request_duration_seconds_count 3\n"));
+    assert!(text.contains("beerscape_recipes_downloaded_total 1\n"));
+    assert!(text.contains("beerscape_recipes_failed_total 2\n"));
+    assert!(text.contains("beerscape_recipes_existing 7\n"));
+    assert!(text.contains("# TYPE beerscape_response_bytes histogram\n"));
+    assert!(text.contains("beerscape_response_bytes_bucket{le=\"1000\"} 0\n"));
+    assert!(text.contains("beerscape_response_bytes_bucket{le=\"4000\"} 1\n"));
+    assert!(text.contains("beerscape_response_bytes_sum 1500\n"));
+    assert!(text.contains("beerscape_response_bytes_count 1\n"));
+
+    // The totals follow the download loop's
+    stats.lock().unwrap().downloaded = 4;
+    assert!(metrics
+        .render()
+        .contains("beerscape_recipes_downloaded_total 4\n"));
+}
+
+#[test]
+fn success_rate_comes_from_the_run_stats() {
+    let stats = Arc::new(Mutex::new(DownloadStats::default()));
+    let metrics = Metrics::new(stats.clone());
+    assert!(metrics.render().contains("beerscape_success_rate 0\n"));
+
+    *stats.lock().unwrap() = DownloadStats {
+        downloaded: 1,
+        failed: 1,
+        attempted: 2,
+        ..Default::default()
+    };
+    assert!(metrics.render().contains("beerscape_success_rate 0.5\n"));
+}
+
+#[test]
+fn snapshot_reports_recent_hit_rate() {
+    let metrics = Metrics::default();
+    let snapshot = metrics.snapshot();
+    assert!(snapshot.contains("Hit rate: no attempts yet\n"));
+    assert!(snapshot.contains("Estimated time to finish: unknown\n"));
+
+    metrics.record_download("saved", Duration::ZERO);
+    metrics.add_bytes(2_500_000);
+    metrics.record_download("not-found", Duration::ZERO);
+    metrics.record_download("session-expired", Duration::ZERO);
+    std::thread::sleep(Duration::from_millis(10));
+    metrics.set_remaining(Remaining::Saves(10));
+
+    let snapshot = metrics.snapshot();
+    assert!(snapshot.contains("IDs attempted: 2\n"), "{}", snapshot);
+    assert!(
+        snapshot.contains("  not-found                 1\n"),
+        "{}",
+        snapshot
+    );
+    assert!(snapshot.contains("Downloaded: 2.5 MB\n"));
+    assert!(snapshot.contains("Hit rate (last 2): 50.0%\n"));
+    assert!(snapshot.contains("Estimated time to finish: 0h 00m\n"));
+}
+
+#[tokio::test]
+async fn reports_the_request_rate_once_watched() {
+    let metrics = Metrics::default();
+    assert!(!metrics.render().contains("beerscape_request_rate"));
+
+    let gate = Arc::new(RequestGate::new(None));
+    metrics.watch_requests(gate.clone());
+    for _ in 0..3 {
+        gate.pass().await;
+    }
+    assert!(metrics.render().contains("beerscape_request_rate 3\n"));
+    assert!(metrics
+        .snapshot()
+        .contains("Request rate (last minute): 3.0/s\n"));
+}
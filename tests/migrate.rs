@@ -0,0 +1,61 @@
+use beer_scape::migrate::{migrate, outdated, schema_version, Upgrade};
+use beer_scape::state::{State, STATE_FILE, STATE_SCHEMA_VERSION};
+use std::fs;
+use tempfile::TempDir;
+
+const OLD_STATE: &str =
+    r#"{"daily_quota":{"max_per_day":500,"count_today":12,"quota_date":"2024-05-01"}}"#;
+
+#[test]
+fn upgrades_unversioned_state() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join(STATE_FILE);
+    fs::write(&path, OLD_STATE).unwrap();
+
+    assert_eq!(State::load(dir.path()).unwrap().schema_version, 0);
+    assert_eq!(
+        outdated(dir.path()).unwrap(),
+        vec![Upgrade {
+            file: STATE_FILE,
+            from: 0,
+            to: STATE_SCHEMA_VERSION
+        }]
+    );
+
+    let applied = migrate(dir.path()).unwrap();
+    assert_eq!(
+        applied,
+        vec![Upgrade {
+            file: STATE_FILE,
+            from: 0,
+            to: 1
+        }]
+    );
+    assert_eq!(schema_version(&path).unwrap(), Some(STATE_SCHEMA_VERSION));
+    let state = State::load(dir.path()).unwrap();
+    assert_eq!(state.daily_quota.max_per_day, 500);
+    assert_eq!(state.daily_quota.count_today, 12);
+
+    // Running again has nothing to do
+    assert!(migrate(dir.path()).unwrap().is_empty());
+    assert!(outdated(dir.path()).unwrap().is_empty());
+}
+
+#[test]
+fn new_state_is_current() {
+    let dir = TempDir::new().unwrap();
+    assert!(migrate(dir.path()).unwrap().is_empty());
+
+    State::default().save(dir.path()).unwrap();
+    assert_eq!(
+        schema_version(&dir.path().join(STATE_FILE)).unwrap(),
+        Some(STATE_SCHEMA_VERSION)
+    );
+}
+
+#[test]
+fn refuses_newer_files() {
+    let dir = TempDir::new().unwrap();
+    fs::write(dir.path().join(STATE_FILE), r#"{"schema_version":99}"#).unwrap();
+    assert!(migrate(dir.path()).is_err());
+}
@@ -0,0 +1,158 @@
+use beer_scape::download::{build_client, download_recipe, DownloadConfig};
+use beer_scape::mirrors::Mirrors;
+use beer_scape::run_report::RunReport;
+use std::fs;
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+use tempfile::TempDir;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+async fn answering(status: u16) -> MockServer {
+    let server = MockServer::start().await;
+    let response = match status {
+        200 => {
+            ResponseTemplate::new(200).set_body_bytes(fs::read("tests/fixtures/utf8.bsmx").unwrap())
+        }
+        _ => ResponseTemplate::new(status),
+    };
+    Mock::given(method("GET"))
+        .and(path("/download.php"))
+        .respond_with(response)
+        .mount(&server)
+        .await;
+    server
+}
+
+fn mirrored(dir: &TempDir, servers: &[&MockServer]) -> DownloadConfig {
+    let base_urls: Vec<String> = servers.iter().map(|s| s.uri()).collect();
+    let config = DownloadConfig {
+        base_url: base_urls[0].clone(),
+        output_dir: dir.path().to_path_buf(),
+        ..Default::default()
+    };
+    DownloadConfig {
+        mirrors: Some(Arc::new(Mirrors::new(
+            &base_urls,
+            config.requests.clone(),
+            None,
+        ))),
+        ..config
+    }
+}
+
+async fn requests(server: &MockServer) -> usize {
+    server.received_requests().await.unwrap().len()
+}
+
+#[tokio::test]
+async fn fails_over_to_a_mirror_and_then_prefers_it() {
+    let primary = answering(503).await;
+    let mirror = answering(200).await;
+    let dir = TempDir::new().unwrap();
+    let config = mirrored(&dir, &[&primary, &mirror]);
+
+    let client = build_client(&config).unwrap();
+    download_recipe(&client, &config, 1).await.unwrap().unwrap();
+    download_recipe(&client, &config, 2).await.unwrap().unwrap();
+
+    assert!(dir.path().join("1.bsmx").exists());
+    assert!(dir.path().join("2.bsmx").exists());
+    // The second went straight to the mirror
+    assert_eq!(requests(&primary).await, 1);
+    assert_eq!(requests(&mirror).await, 2);
+    let stats = config.mirrors.as_ref().unwrap().stats();
+    assert_eq!((stats[0].attempts, stats[0].errors), (1, 1));
+    assert!(stats[0].error_rate > 0.4, "{:?}", stats[0]);
+    assert_eq!(
+        (stats[1].attempts, stats[1].saved, stats[1].errors),
+        (2, 2, 0)
+    );
+    assert_eq!(stats[1].error_rate, 0.0);
+}
+
+#[tokio::test]
+async fn stays_on_the_first_mirror_while_it_answers() {
+    let primary = answering(404).await;
+    let mirror = answering(200).await;
+    let dir = TempDir::new().unwrap();
+    let config = mirrored(&dir, &[&primary, &mirror]);
+
+    let client = build_client(&config).unwrap();
+    // Not having the recipe is an answer about the ID, not the mirror
+    assert!(download_recipe(&client, &config, 1)
+        .await
+        .unwrap()
+        .is_none());
+    assert!(download_recipe(&client, &config, 2)
+        .await
+        .unwrap()
+        .is_none());
+
+    assert_eq!(requests(&primary).await, 2);
+    assert_eq!(requests(&mirror).await, 0);
+    let stats = config.mirrors.as_ref().unwrap().stats();
+    assert_eq!((stats[0].failed, stats[0].errors), (2, 0));
+}
+
+#[tokio::test]
+async fn fails_only_once_every_mirror_has_been_tried() {
+    let primary = answering(500).await;
+    let mirror = answering(502).await;
+    let dir = TempDir::new().unwrap();
+    let config = mirrored(&dir, &[&primary, &mirror]);
+
+    let client = build_client(&config).unwrap();
+    assert!(download_recipe(&client, &config, 1)
+        .await
+        .unwrap()
+        .is_none());
+
+    assert_eq!(requests(&primary).await, 1);
+    assert_eq!(requests(&mirror).await, 1);
+}
+
+#[tokio::test]
+async fn reports_how_each_mirror_did() {
+    let primary = answering(503).await;
+    let mirror = answering(200).await;
+    let dir = TempDir::new().unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_beerscape"))
+        .current_dir(dir.path())
+        .args(["--base-url", &primary.uri(), "--base-url", &mirror.uri()])
+        .args(["--id-range", "1-3", "--report-file", "report.json"])
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", stdout);
+    assert!(stdout.contains("Mirrors:\n--------\n"), "{}", stdout);
+    let report = RunReport::load(&dir.path().join("report.json")).unwrap();
+    assert_eq!(report.downloaded, 3);
+    assert_eq!(report.mirrors.len(), 2);
+    assert_eq!(report.mirrors[0].base_url, primary.uri());
+    assert!(report.mirrors[0].errors >= 1);
+    assert_eq!(report.mirrors[1].saved, 3);
+}
+
+#[test]
+fn turns_down_a_mirror_given_twice() {
+    let dir = TempDir::new().unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_beerscape"))
+        .current_dir(dir.path())
+        .args([
+            "--base-url",
+            "http://a.test",
+            "--base-url",
+            "http://a.test/",
+        ])
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("given twice"), "{}", stderr);
+}
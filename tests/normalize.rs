@@ -0,0 +1,82 @@
+mod common;
+
+use beer_scape::bsmx::{parse_bsmx, Recipe};
+use beer_scape::normalize::{normalize, Unit, OZ_TO_G};
+use common::fixture_text;
+
+fn recipe(xml: &str) -> Recipe {
+    parse_bsmx(xml.as_bytes()).unwrap()
+}
+
+fn close(a: f64, b: f64) -> bool {
+    (a - b).abs() < 1e-6
+}
+
+#[test]
+fn reads_display_units() {
+    assert_eq!(Unit::from_display(" LBS "), Some(Unit::Pound));
+    assert_eq!(Unit::from_display("g"), Some(Unit::Gram));
+    assert_eq!(Unit::from_display("°F"), Some(Unit::Fahrenheit));
+    assert_eq!(Unit::from_display("fl oz"), Some(Unit::FluidOunce));
+    assert_eq!(Unit::from_display("furlongs"), None);
+
+    assert!(close(Unit::Pound.to_metric(1.0), 453.59237));
+    assert!(close(Unit::Fahrenheit.to_metric(212.0), 100.0));
+    // Zero means not recorded
+    assert_eq!(Unit::Fahrenheit.to_metric(0.0), 0.0);
+}
+
+#[test]
+fn beersmith_units_are_converted_to_metric() {
+    let parsed = recipe(&fixture_text());
+    assert!(parsed.recorded.is_none());
+    assert!(close(parsed.batch_size_l, 640.0 * 0.029_573_53));
+    assert!(close(
+        parsed.fermentables[0].amount_kg,
+        176.0 * OZ_TO_G / 1000.0
+    ));
+    assert!(close(parsed.hops[0].amount_g, 2.0 * OZ_TO_G));
+    // Already metric, so nothing changes
+    assert_eq!(normalize(parsed.clone()), parsed);
+}
+
+#[test]
+fn display_units_say_what_the_amounts_are_in() {
+    let xml = fixture_text()
+        .replace(
+            "<F_G_AMOUNT>176</F_G_AMOUNT>",
+            "<F_G_AMOUNT>11</F_G_AMOUNT><F_G_DISPLAY_UNIT>lb</F_G_DISPLAY_UNIT>",
+        )
+        .replace(
+            "<F_H_AMOUNT>2</F_H_AMOUNT>",
+            "<F_H_AMOUNT>56.7</F_H_AMOUNT><F_H_DISPLAY_UNIT>g</F_H_DISPLAY_UNIT>",
+        );
+    let parsed = recipe(&xml);
+    assert!(close(parsed.fermentables[0].amount_kg, 11.0 * 0.453_592_37));
+    assert!(close(parsed.hops[0].amount_g, 56.7));
+    // Volumes stay in BeerSmith's fluid ounces
+    assert!(close(parsed.batch_size_l, 640.0 * 0.029_573_53));
+
+    // A unit that isn't a weight is no help with an amount
+    let xml = fixture_text().replace(
+        "<F_H_AMOUNT>2</F_H_AMOUNT>",
+        "<F_H_AMOUNT>2</F_H_AMOUNT><F_H_DISPLAY_UNIT>gal</F_H_DISPLAY_UNIT>",
+    );
+    assert!(close(recipe(&xml).hops[0].amount_g, 2.0 * OZ_TO_G));
+}
+
+#[test]
+fn a_metric_recipe_is_read_as_metric() {
+    let xml = fixture_text()
+        .replace(
+            "<F_R_BREWER>",
+            "<DISPLAY_UNIT>Metric</DISPLAY_UNIT><F_R_BREWER>",
+        )
+        .replace("<F_E_BATCH_VOL>640.0<", "<F_E_BATCH_VOL>19<")
+        .replace("<F_G_AMOUNT>176<", "<F_G_AMOUNT>5<")
+        .replace("<F_H_AMOUNT>2<", "<F_H_AMOUNT>57<");
+    let parsed = recipe(&xml);
+    assert!(close(parsed.batch_size_l, 19.0));
+    assert!(close(parsed.fermentables[0].amount_kg, 5.0));
+    assert!(close(parsed.hops[0].amount_g, 57.0));
+}
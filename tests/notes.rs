@@ -0,0 +1,100 @@
+mod common;
+
+use beer_scape::beerxml::{export_beerxml, parse_beerxml};
+use beer_scape::bsmx::{parse_bsmx, Recipe, NOTES_KEPT, TRUNCATED};
+use beer_scape::cli::{ExportFormat, Units};
+use beer_scape::commands::export::export;
+use common::fixture_text;
+use serde_json::Value;
+use std::fs;
+use std::process::Command;
+use tempfile::TempDir;
+
+/// The fixture with these elements added to its recipe.
+fn with(elements: &str) -> String {
+    fixture_text().replace("<F_R_BREWER>", &format!("{}<F_R_BREWER>", elements))
+}
+
+fn recipe(xml: &str) -> Recipe {
+    parse_bsmx(xml.as_bytes()).unwrap()
+}
+
+#[test]
+fn reads_notes_and_tasting_notes() {
+    let parsed = recipe(&with(
+        "<F_R_NOTES>  Mash thick; from Zymurgy, 1998.\n</F_R_NOTES><TASTE_NOTES>Earthy &amp; dry</TASTE_NOTES>",
+    ));
+    assert_eq!(
+        parsed.notes.as_deref(),
+        Some("Mash thick; from Zymurgy, 1998.")
+    );
+    // The bare BeerXML-style name serves when the F_R_ one is missing
+    assert_eq!(parsed.taste_notes.as_deref(), Some("Earthy & dry"));
+
+    let blank = recipe(&with("<F_R_NOTES>  </F_R_NOTES>"));
+    assert_eq!(blank.notes, None);
+    assert_eq!(blank.taste_notes, None);
+
+    // And both survive a trip through BeerXML
+    let round_trip = parse_beerxml(export_beerxml(&parsed).as_bytes()).unwrap();
+    assert_eq!(round_trip.notes, parsed.notes);
+    assert_eq!(round_trip.taste_notes, parsed.taste_notes);
+}
+
+#[test]
+fn truncates_very_long_notes() {
+    // Two bytes a character, so the cut has to find a character boundary
+    let long = "é".repeat(6 * 1024);
+    let parsed = recipe(&with(&format!("<F_R_NOTES>{}</F_R_NOTES>", long)));
+    let notes = parsed.notes.unwrap();
+    assert!(notes.ends_with(&format!(" {}", TRUNCATED)), "{}", notes);
+    let kept = notes.trim_end_matches(TRUNCATED).trim_end();
+    assert!(kept.len() <= NOTES_KEPT && kept.len() > NOTES_KEPT - 2);
+    assert!(long.starts_with(kept));
+
+    // Up to the limit, notes are kept whole
+    let short = "a".repeat(10 * 1024);
+    let parsed = recipe(&with(&format!("<F_R_NOTES>{}</F_R_NOTES>", short)));
+    assert_eq!(parsed.notes, Some(short));
+}
+
+#[test]
+fn exports_and_reports_notes() {
+    let dir = TempDir::new().unwrap();
+    let recipes = dir.path().join("recipes");
+    fs::create_dir(&recipes).unwrap();
+    fs::write(
+        recipes.join("1.bsmx"),
+        with("<F_R_NOTES>Brewed for a wedding</F_R_NOTES><F_R_TASTE_NOTES>Malty</F_R_TASTE_NOTES>"),
+    )
+    .unwrap();
+    fs::write(recipes.join("2.bsmx"), with("")).unwrap();
+
+    let mut out = Vec::new();
+    let paths = [recipes.join("1.bsmx"), recipes.join("2.bsmx")];
+    export(&paths, ExportFormat::Json, Units::Metric, &mut out).unwrap();
+    let all: Vec<Value> = serde_json::from_slice(&out).unwrap();
+    assert_eq!(all[0]["notes"], "Brewed for a wedding");
+    assert_eq!(all[0]["taste_notes"], "Malty");
+    assert!(all[1]["notes"].is_null());
+
+    let output = Command::new(env!("CARGO_BIN_EXE_beerscape"))
+        .current_dir(dir.path())
+        .args(["report", "notes"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("With Any Notes: 1 (50.0%)"), "{}", stdout);
+    assert!(
+        stdout.contains("With Tasting Notes: 1 (50.0%)"),
+        "{}",
+        stdout
+    );
+    // "Brewed for a wedding" and "Malty"
+    assert!(
+        stdout.contains("Average Notes Length: 25 characters"),
+        "{}",
+        stdout
+    );
+}
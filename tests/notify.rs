@@ -0,0 +1,88 @@
+mod common;
+
+use common::{beerscape, serve_nothing, serve_recipes};
+use std::time::Duration;
+use tempfile::TempDir;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+async fn serve_hook(status: u16) -> MockServer {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/hook"))
+        .respond_with(ResponseTemplate::new(status))
+        .mount(&server)
+        .await;
+    server
+}
+
+#[tokio::test]
+async fn posts_the_summary_to_a_webhook() {
+    let server = serve_recipes(Duration::ZERO).await;
+    let hook = serve_hook(200).await;
+    let url = format!("{}/hook", hook.uri());
+    let dir = TempDir::new().unwrap();
+
+    let output = beerscape(
+        &dir,
+        &server.uri(),
+        &["--ids", "1,2", "--notify-webhook", &url],
+    )
+    .output()
+    .await
+    .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let requests = hook.received_requests().await.unwrap();
+    assert_eq!(requests.len(), 1);
+    let body: serde_json::Value = serde_json::from_slice(&requests[0].body).unwrap();
+    assert_eq!(body["successful"], 2);
+    assert_eq!(body["failed"], 0);
+    assert_eq!(body["total_attempted"], 2);
+    assert_eq!(body["newly_downloaded"], 2);
+    assert_eq!(body["success_rate"], 1.0);
+    assert!(body["duration_secs"].is_u64());
+}
+
+#[tokio::test]
+async fn error_only_webhook_waits_for_a_failure() {
+    let hook = serve_hook(200).await;
+    let url = format!("{}/hook", hook.uri());
+    let args = ["--notify-webhook", &url, "--notify-on-error-only"];
+
+    let server = serve_recipes(Duration::ZERO).await;
+    let dir = TempDir::new().unwrap();
+    let mut command = beerscape(&dir, &server.uri(), &args);
+    let output = command.args(["--ids", "1"]).output().await.unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    assert!(hook.received_requests().await.unwrap().is_empty());
+
+    // Recipe 1 is gone too, which would fail the pre-flight check
+    let server = serve_nothing().await;
+    let mut command = beerscape(&dir, &server.uri(), &args);
+    let command = command.args(["--ids", "2", "--skip-healthcheck"]);
+    command.output().await.unwrap();
+    let requests = hook.received_requests().await.unwrap();
+    assert_eq!(requests.len(), 1);
+    let body: serde_json::Value = serde_json::from_slice(&requests[0].body).unwrap();
+    assert_eq!(body["failed"], 1);
+}
+
+#[tokio::test]
+async fn failing_webhook_leaves_the_exit_code_alone() {
+    let server = serve_recipes(Duration::ZERO).await;
+    let hook = serve_hook(500).await;
+    let url = format!("{}/hook", hook.uri());
+    let dir = TempDir::new().unwrap();
+
+    let output = beerscape(
+        &dir,
+        &server.uri(),
+        &["--ids", "1", "--notify-webhook", &url],
+    )
+    .output()
+    .await
+    .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(hook.received_requests().await.unwrap().len(), 1);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("could not notify"));
+}
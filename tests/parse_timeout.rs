@@ -0,0 +1,113 @@
+use beer_scape::bsmx::{
+    parse_document, set_parse_timeout, within_parse_timeout, ParseError, ParseTimedOut,
+    DEFAULT_PARSE_TIMEOUT_MS, MAX_DEPTH,
+};
+use beer_scape::collection::{read_or_set_aside, read_recipe, PARSE_FAILED_DIR};
+use beer_scape::download::{build_client, download_recipe, DownloadConfig};
+use std::fs;
+use std::process::Command;
+use std::time::Duration;
+use tempfile::TempDir;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+/// Well-formed, but big enough to take a while to parse.
+fn pathological() -> String {
+    format!(
+        "<Recipe>{}</Recipe>",
+        "<F_R_NOTES>hop</F_R_NOTES>".repeat(300_000)
+    )
+}
+
+#[test]
+fn turns_down_documents_nested_too_deep() {
+    let depth = 100_000;
+    let xml = format!(
+        "<Recipe>{}{}</Recipe>",
+        "<F_R_NOTES>".repeat(depth),
+        "</F_R_NOTES>".repeat(depth)
+    );
+    assert!(matches!(
+        parse_document(xml.as_bytes()),
+        Err(ParseError::TooDeep)
+    ));
+    let xml = format!(
+        "<Recipe>{}{}</Recipe>",
+        "<F_R_NOTES>".repeat(MAX_DEPTH - 1),
+        "</F_R_NOTES>".repeat(MAX_DEPTH - 1)
+    );
+    assert!(parse_document(xml.as_bytes()).is_ok());
+}
+
+// The timeout is process-wide, so everything that shortens it is in one
+// test
+#[tokio::test]
+async fn sets_aside_recipes_that_take_too_long_to_parse() {
+    let dir = TempDir::new().unwrap();
+    let slow = dir.path().join("1.bsmx");
+    fs::write(&slow, pathological()).unwrap();
+    set_parse_timeout(Duration::from_millis(1));
+
+    let e = read_recipe(&slow).unwrap_err();
+    assert!(e.is::<ParseTimedOut>(), "{}", e);
+    assert!(read_or_set_aside(dir.path(), &slow).is_none());
+    assert!(!slow.exists());
+    assert!(dir.path().join(PARSE_FAILED_DIR).join("1.bsmx").exists());
+
+    // The parse itself stops at the deadline, rather than running on
+    let mut parsed = None;
+    let xml = pathological();
+    assert!(within_parse_timeout(|| parsed = Some(parse_document(xml.as_bytes()))).is_err());
+    assert!(matches!(parsed, Some(Err(ParseError::TimedOut))));
+
+    // A download is checked under the same timeout
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/download.php"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(pathological()))
+        .mount(&server)
+        .await;
+    let config = DownloadConfig {
+        base_url: server.uri(),
+        output_dir: dir.path().to_path_buf(),
+        ..Default::default()
+    };
+    let client = build_client(&config).unwrap();
+    assert!(download_recipe(&client, &config, 2)
+        .await
+        .unwrap()
+        .is_none());
+    assert!(!dir.path().join("2.bsmx").exists());
+    assert!(dir.path().join(PARSE_FAILED_DIR).join("2.bsmx").exists());
+
+    set_parse_timeout(Duration::from_millis(DEFAULT_PARSE_TIMEOUT_MS));
+    fs::copy("tests/fixtures/utf8.bsmx", dir.path().join("3.bsmx")).unwrap();
+    assert!(read_recipe(&dir.path().join("3.bsmx")).is_ok());
+}
+
+#[test]
+fn indexing_moves_slow_files_out_of_the_way() {
+    let dir = TempDir::new().unwrap();
+    let recipes = dir.path().join("recipes");
+    fs::create_dir(&recipes).unwrap();
+    fs::write(recipes.join("1.bsmx"), pathological()).unwrap();
+    fs::copy("tests/fixtures/utf8.bsmx", recipes.join("2.bsmx")).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_beerscape"))
+        .current_dir(dir.path())
+        .args(["--parse-timeout-ms", "50", "index"])
+        .output()
+        .unwrap();
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", stdout);
+    assert!(
+        stdout.contains("Indexed 1 recipes (1 unreadable"),
+        "{}",
+        stdout
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("1.bsmx"), "{}", stderr);
+    assert!(recipes.join(PARSE_FAILED_DIR).join("1.bsmx").exists());
+    assert!(!recipes.join("1.bsmx").exists());
+}
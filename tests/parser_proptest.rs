@@ -0,0 +1,215 @@
+use beer_scape::beerxml::{export_beerxml, parse_beerxml};
+use beer_scape::bsmx::{parse_bsmx, ParseError, Recipe};
+use proptest::prelude::*;
+use quick_xml::escape::escape;
+
+// BSMX stores ounces and fluid ounces, so values pass through a unit
+// conversion on the way in; compare with a relative tolerance
+const EPSILON: f64 = 1e-9;
+
+struct Params {
+    name: String,
+    style: String,
+    og: f64,
+    fg: f64,
+    ibu: f64,
+    batch_floz: f64,
+    boil_time: f64,
+    hops: Vec<(String, f64, f64, f64)>,
+    grains: Vec<(String, f64, f64)>,
+}
+
+fn bsmx(p: &Params) -> String {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<Recipe>");
+    xml += &format!("<F_R_NAME>{}</F_R_NAME>", escape(&p.name));
+    xml += &format!(
+        "<F_R_STYLE><F_S_NAME>{}</F_S_NAME></F_R_STYLE>",
+        escape(&p.style)
+    );
+    xml += &format!(
+        "<F_R_EQUIPMENT><F_E_BATCH_VOL>{}</F_E_BATCH_VOL><F_E_BOIL_TIME>{}</F_E_BOIL_TIME></F_R_EQUIPMENT>",
+        p.batch_floz, p.boil_time
+    );
+    xml += &format!(
+        "<F_R_OG>{}</F_R_OG><F_R_FG>{}</F_R_FG><F_R_IBU>{}</F_R_IBU>",
+        p.og, p.fg, p.ibu
+    );
+    xml += "<Ingredients><Data>";
+    for (name, amount, color) in &p.grains {
+        xml += &format!(
+            "<Grain><F_G_NAME>{}</F_G_NAME><F_G_AMOUNT>{}</F_G_AMOUNT><F_G_COLOR>{}</F_G_COLOR></Grain>",
+            escape(name),
+            amount,
+            color
+        );
+    }
+    for (name, amount, alpha, time) in &p.hops {
+        xml += &format!(
+            "<Hops><F_H_NAME>{}</F_H_NAME><F_H_AMOUNT>{}</F_H_AMOUNT><F_H_ALPHA>{}</F_H_ALPHA><F_H_BOIL_TIME>{}</F_H_BOIL_TIME></Hops>",
+            escape(name),
+            amount,
+            alpha,
+            time
+        );
+    }
+    xml += "</Data></Ingredients></Recipe>";
+    xml
+}
+
+fn base() -> Params {
+    Params {
+        name: "Test".into(),
+        style: "American IPA".into(),
+        og: 1.060,
+        fg: 1.012,
+        ibu: 50.0,
+        batch_floz: 640.0,
+        boil_time: 60.0,
+        hops: vec![("Cascade".into(), 1.0, 5.5, 60.0)],
+        grains: vec![("Pale Malt".into(), 160.0, 2.0)],
+    }
+}
+
+fn close(a: f64, b: f64) -> bool {
+    (a - b).abs() <= EPSILON * a.abs().max(b.abs()).max(1.0)
+}
+
+fn assert_round_trip(original: &Recipe, back: &Recipe) {
+    assert_eq!(back.name, original.name);
+    assert_eq!(back.brewer, original.brewer);
+    assert_eq!(back.style, original.style);
+    for (a, b) in [
+        (original.og, back.og),
+        (original.fg, back.fg),
+        (original.ibu, back.ibu),
+        (original.abv, back.abv),
+        (original.color_srm, back.color_srm),
+        (original.batch_size_l, back.batch_size_l),
+        (original.boil_time_min, back.boil_time_min),
+    ] {
+        assert!(close(a, b), "{} != {}", a, b);
+    }
+
+    assert_eq!(back.hops.len(), original.hops.len());
+    for (a, b) in original.hops.iter().zip(&back.hops) {
+        assert_eq!(a.name, b.name);
+        assert!(
+            close(a.amount_g, b.amount_g),
+            "{} != {}",
+            a.amount_g,
+            b.amount_g
+        );
+        assert!(close(a.alpha_pct, b.alpha_pct));
+        assert!(close(a.boil_time_min, b.boil_time_min));
+    }
+
+    assert_eq!(back.fermentables.len(), original.fermentables.len());
+    for (a, b) in original.fermentables.iter().zip(&back.fermentables) {
+        assert_eq!(a.name, b.name);
+        assert!(close(a.amount_kg, b.amount_kg));
+        assert!(close(a.color_srm, b.color_srm));
+    }
+}
+
+// Names without leading/trailing whitespace, since the parser trims text
+fn name() -> impl Strategy<Value = String> {
+    "[A-Za-z0-9&<>\"'éè°][A-Za-z0-9 &<>\"'éè°#.-]{0,28}[A-Za-z0-9]"
+}
+
+fn xmlish() -> impl Strategy<Value = Vec<u8>> {
+    let token = prop_oneof![
+        Just("<".to_string()),
+        Just(">".to_string()),
+        Just("</".to_string()),
+        Just("/>".to_string()),
+        Just("<?xml version=\"1.0\"?>".to_string()),
+        Just("Recipe".to_string()),
+        Just("F_R_OG".to_string()),
+        Just("F_R_STYLE".to_string()),
+        Just("Hops".to_string()),
+        Just("F_H_AMOUNT".to_string()),
+        Just("&amp;".to_string()),
+        Just("&bogus;".to_string()),
+        Just("<![CDATA[".to_string()),
+        Just("]]>".to_string()),
+        "[0-9.\\-]{1,6}",
+        "[ -~]{1,4}",
+    ];
+    prop::collection::vec(token, 0..64).prop_map(|t| t.concat().into_bytes())
+}
+
+proptest! {
+    #[test]
+    fn arbitrary_bytes_never_panic(bytes in prop::collection::vec(any::<u8>(), 0..2048)) {
+        let _ = parse_bsmx(&bytes);
+    }
+
+    #[test]
+    fn xml_like_noise_never_panics(bytes in xmlish()) {
+        let _ = parse_bsmx(&bytes);
+    }
+
+    #[test]
+    fn non_numeric_og_is_invalid_number(og in "[A-Za-z_][A-Za-z0-9._]{0,10}") {
+        let mut p = base();
+        p.og = 0.0;
+        let xml = bsmx(&p).replace("<F_R_OG>0</F_R_OG>", &format!("<F_R_OG>{}</F_R_OG>", og));
+        match parse_bsmx(xml.as_bytes()) {
+            Err(ParseError::InvalidNumber { field, value }) => {
+                prop_assert_eq!(field, "F_R_OG");
+                prop_assert_eq!(value, og);
+            }
+            other => prop_assert!(false, "expected InvalidNumber, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn negative_boil_time_is_rejected(minutes in -10_000.0f64..-0.001) {
+        let mut p = base();
+        p.boil_time = minutes;
+        match parse_bsmx(bsmx(&p).as_bytes()) {
+            Err(ParseError::NegativeValue { field, .. }) => prop_assert_eq!(field, "F_E_BOIL_TIME"),
+            other => prop_assert!(false, "expected NegativeValue, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn negative_hop_time_is_rejected(minutes in -10_000.0f64..-0.001) {
+        let mut p = base();
+        p.hops[0].3 = minutes;
+        match parse_bsmx(bsmx(&p).as_bytes()) {
+            Err(ParseError::NegativeValue { field, .. }) => prop_assert_eq!(field, "F_H_BOIL_TIME"),
+            other => prop_assert!(false, "expected NegativeValue, got {:?}", other),
+        }
+    }
+
+    // Stub recipes without a style are common, so an empty style name is
+    // not an error; it just parses as no style
+    #[test]
+    fn empty_style_name_parses_as_no_style(ws in "[ \t\r\n]{0,4}") {
+        let mut p = base();
+        p.style = ws;
+        let recipe = parse_bsmx(bsmx(&p).as_bytes()).unwrap();
+        prop_assert_eq!(recipe.style.name, "");
+    }
+
+    #[test]
+    fn round_trips_through_beerxml(
+        name in name(),
+        style in name(),
+        og in 1.0f64..1.2,
+        fg in 0.99f64..1.05,
+        ibu in 0.0f64..150.0,
+        batch_floz in 0.0f64..20_000.0,
+        boil_time in 0.0f64..240.0,
+        hops in prop::collection::vec((name(), 0.0f64..50.0, 0.0f64..25.0, 0.0f64..120.0), 0..8),
+        grains in prop::collection::vec((name(), 0.0f64..800.0, 0.0f64..600.0), 0..8),
+    ) {
+        let p = Params { name, style, og, fg, ibu, batch_floz, boil_time, hops, grains };
+        let original = parse_bsmx(bsmx(&p).as_bytes()).unwrap();
+        prop_assert_eq!(&original.name, &p.name);
+
+        let back = parse_beerxml(export_beerxml(&original).as_bytes()).unwrap();
+        assert_round_trip(&original, &back);
+    }
+}
@@ -0,0 +1,132 @@
+use beer_scape::download::{build_client, DownloadConfig};
+use beer_scape::preflight::check;
+use beer_scape::run_report::RunReport;
+use std::fs;
+use std::process::{Command, Stdio};
+use tempfile::TempDir;
+use wiremock::matchers::{method, path, query_param};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+fn recipe() -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_bytes(fs::read("tests/fixtures/utf8.bsmx").unwrap())
+}
+
+async fn serve(server: &MockServer, id: &str, response: ResponseTemplate) {
+    Mock::given(method("GET"))
+        .and(path("/download.php"))
+        .and(query_param("id", id))
+        .respond_with(response)
+        .mount(server)
+        .await;
+}
+
+fn config(base_url: &str) -> DownloadConfig {
+    DownloadConfig {
+        base_url: base_url.to_string(),
+        ..Default::default()
+    }
+}
+
+async fn outcome(base_url: &str, id: Option<u32>) -> String {
+    let config = config(base_url);
+    let client = build_client(&config).unwrap();
+    check(&client, &config, id).await.outcome
+}
+
+#[tokio::test]
+async fn passes_on_a_recipe_or_a_working_site_root() {
+    let server = MockServer::start().await;
+    serve(&server, "7", recipe()).await;
+    let config = config(&server.uri());
+    let client = build_client(&config).unwrap();
+
+    let preflight = check(&client, &config, Some(7)).await;
+    assert!(preflight.passed(), "{}", preflight);
+    assert_eq!(preflight.detail, "Bière de Garde à 12°");
+    // Wiremock answers the unmocked root with a 404, which is still the
+    // site answering
+    assert_eq!(outcome(&server.uri(), None).await, "ok");
+}
+
+#[tokio::test]
+async fn tells_http_errors_pages_and_junk_apart() {
+    let server = MockServer::start().await;
+    serve(&server, "1", ResponseTemplate::new(503)).await;
+    serve(
+        &server,
+        "2",
+        ResponseTemplate::new(200).set_body_raw("<html>Log in</html>", "text/html"),
+    )
+    .await;
+    serve(
+        &server,
+        "3",
+        ResponseTemplate::new(200).set_body_bytes("junk"),
+    )
+    .await;
+    serve(&server, "4", ResponseTemplate::new(404)).await;
+
+    assert_eq!(outcome(&server.uri(), Some(1)).await, "http");
+    assert_eq!(outcome(&server.uri(), Some(2)).await, "html");
+    assert_eq!(outcome(&server.uri(), Some(3)).await, "not-a-recipe");
+    // The ID is meant to be known good, so a 404 means something's wrong
+    assert_eq!(outcome(&server.uri(), Some(4)).await, "http");
+}
+
+#[tokio::test]
+async fn tells_dns_from_refused_connections() {
+    assert_eq!(outcome("http://127.0.0.1:1", None).await, "connect");
+    assert_eq!(outcome("http://beerscape.invalid", None).await, "dns");
+}
+
+#[tokio::test]
+async fn a_failed_check_stops_the_run_before_it_starts() {
+    let server = MockServer::start().await;
+    serve(&server, "5", ResponseTemplate::new(500)).await;
+    let dir = TempDir::new().unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_beerscape"))
+        .current_dir(dir.path())
+        .args(["--base-url", &server.uri(), "--id-range", "1-3"])
+        .args(["--healthcheck-id", "5"])
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(4));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("pre-flight check failed") && stderr.contains("HTTP 500"),
+        "{}",
+        stderr
+    );
+    assert_eq!(server.received_requests().await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn probes_an_indexed_recipe_and_reports_how_it_went() {
+    let server = MockServer::start().await;
+    serve(&server, "1", recipe()).await;
+    serve(&server, "2", recipe()).await;
+    serve(&server, "3", recipe()).await;
+    let dir = TempDir::new().unwrap();
+    let run = |ids: &str| {
+        Command::new(env!("CARGO_BIN_EXE_beerscape"))
+            .current_dir(dir.path())
+            .args(["--base-url", &server.uri(), "--ids", ids])
+            .args(["--report-file", "report.json"])
+            .stdin(Stdio::null())
+            .output()
+            .unwrap()
+    };
+
+    assert!(run("2").status.success());
+    let report = RunReport::load(&dir.path().join("report.json")).unwrap();
+    let preflight = report.preflight.unwrap();
+    assert_eq!((preflight.id, preflight.outcome.as_str()), (None, "ok"));
+
+    assert!(run("1,3").status.success());
+    let report = RunReport::load(&dir.path().join("report.json")).unwrap();
+    let preflight = report.preflight.unwrap();
+    assert_eq!((preflight.id, preflight.outcome.as_str()), (Some(2), "ok"));
+}
@@ -0,0 +1,115 @@
+use beer_scape::probe::{projected_attempts, wilson_interval};
+use std::fs;
+use std::process::Stdio;
+use tempfile::TempDir;
+use tokio::process::Command;
+use wiremock::matchers::{method, path, query_param};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+#[test]
+fn interval_brackets_the_rate() {
+    assert_eq!(wilson_interval(0, 0), None);
+
+    let (low, high) = wilson_interval(50, 1000).unwrap();
+    assert!((low - 0.0381).abs() < 0.001, "{}", low);
+    assert!((high - 0.0653).abs() < 0.001, "{}", high);
+
+    // No hits still leaves room for a small rate
+    let (low, high) = wilson_interval(0, 100).unwrap();
+    assert_eq!(low, 0.0);
+    assert!(high > 0.0 && high < 0.05);
+}
+
+#[test]
+fn projects_attempts_from_the_interval() {
+    assert_eq!(projected_attempts(100, 0, 1000), None);
+
+    let (likely, best, worst) = projected_attempts(100, 50, 1000).unwrap();
+    assert_eq!(likely, 2000);
+    assert!(best < likely && likely < worst);
+}
+
+#[tokio::test]
+async fn dry_run_writes_no_recipes() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/download.php"))
+        .and(query_param("id", "2"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_bytes(fs::read("tests/fixtures/utf8.bsmx").unwrap()),
+        )
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(404))
+        .mount(&server)
+        .await;
+
+    let dir = TempDir::new().unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_beerscape"))
+        .current_dir(dir.path())
+        .args(["--base-url", &server.uri(), "--dry-run", "--report"])
+        .args(["--min-id", "1", "--max-id", "4", "--sample", "10"])
+        .stdin(Stdio::null())
+        .output()
+        .await
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("IDs probed: 4 of 4 between 1 and 4"),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("Hit rate: 25.00%"), "{}", stdout);
+    assert!(!dir.path().join("recipes").exists());
+    assert!(!dir.path().join(".beerscape/reports").exists());
+    assert_eq!(
+        fs::read_dir(dir.path().join(".beerscape/probes"))
+            .unwrap()
+            .count(),
+        1
+    );
+}
+
+#[tokio::test]
+async fn compares_strategies() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(404))
+        .mount(&server)
+        .await;
+
+    let dir = TempDir::new().unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_beerscape"))
+        .current_dir(dir.path())
+        .args([
+            "--base-url",
+            &server.uri(),
+            "--dry-run",
+            "--compare-strategies",
+        ])
+        .args(["--min-id", "1", "--max-id", "100", "--sample", "20"])
+        .stdin(Stdio::null())
+        .output()
+        .await
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("IDs probed: 20 of 100"), "{}", stdout);
+    assert!(stdout.contains("Hit rate by strategy:"), "{}", stdout);
+    // Batches alternate, so each strategy gets half the sample
+    assert!(
+        stdout.contains("uniform          0.00% of 10"),
+        "{}",
+        stdout
+    );
+    assert!(
+        stdout.contains("adaptive         0.00% of 10"),
+        "{}",
+        stdout
+    );
+}
@@ -0,0 +1,159 @@
+use beer_scape::progress::{format_bytes, status_line, BarStyle, Counts, Event};
+use serde_json::json;
+use std::fs;
+use std::process::Stdio;
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::process::Command;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+// The JSON stream is consumed by other programs, so its field names are
+// pinned here
+
+#[test]
+fn download_events_keep_their_schema() {
+    let succeeded = Event::DownloadSucceeded {
+        id: 42,
+        filename: "42.bsmx",
+        bytes: 1234,
+        elapsed_ms: 80,
+    };
+    assert_eq!(
+        serde_json::to_value(&succeeded).unwrap(),
+        json!({
+            "event": "download-succeeded",
+            "id": 42,
+            "filename": "42.bsmx",
+            "bytes": 1234,
+            "elapsed_ms": 80
+        })
+    );
+
+    let failed = Event::DownloadFailed {
+        id: 7,
+        category: "not-found",
+        status: Some(404),
+    };
+    assert_eq!(
+        serde_json::to_value(&failed).unwrap(),
+        json!({"event": "download-failed", "id": 7, "category": "not-found", "status": 404})
+    );
+}
+
+#[test]
+fn run_finished_keeps_its_schema() {
+    let finished = Event::RunFinished {
+        reason: "quota-reached",
+        existing: 10,
+        downloaded: 5,
+        failed: 2,
+        attempted: 7,
+        elapsed_secs: 3,
+    };
+    assert_eq!(
+        serde_json::to_value(&finished).unwrap(),
+        json!({
+            "event": "run-finished",
+            "reason": "quota-reached",
+            "existing": 10,
+            "downloaded": 5,
+            "failed": 2,
+            "attempted": 7,
+            "elapsed_secs": 3
+        })
+    );
+}
+
+#[test]
+fn status_line_is_plain_text() {
+    let counts = Counts {
+        successful: 5230,
+        failed: 312,
+        attempted: 5542,
+        transferred: 64_200_000,
+        bytes_per_sec: 1_200_000.0,
+    };
+    assert_eq!(
+        status_line(&counts, 10_000, false),
+        "5,230/10,000 downloaded, 312 failed, 1.1 MiB/s, 61.2 MiB received"
+    );
+    assert_eq!(
+        status_line(&counts, 10_000, true),
+        "5,230/10,000 downloaded, 312 failed, 1200000 B/s, 64200000 B received"
+    );
+    assert_eq!(
+        status_line(&Counts::default(), 10_000, false),
+        "0/10,000 downloaded, 0 failed, 0 B/s, 0 B received"
+    );
+}
+
+#[test]
+fn formats_bytes_in_binary_units() {
+    assert_eq!(format_bytes(1023, false), "1023 B");
+    assert_eq!(format_bytes(1024, false), "1.0 KiB");
+    assert_eq!(format_bytes(8_400, false), "8.2 KiB");
+    assert_eq!(format_bytes(3 * 1024 * 1024 * 1024, false), "3.0 GiB");
+    assert_eq!(format_bytes(5 * 1024u64.pow(4), false), "5120.0 GiB");
+    assert_eq!(format_bytes(8_400, true), "8400 B");
+}
+
+#[test]
+fn parses_progress_styles() {
+    assert_eq!("compact".parse(), Ok(BarStyle::Compact));
+    assert_eq!("none".parse(), Ok(BarStyle::None));
+    assert_eq!(
+        "{bar:40} {pos}/{len}".parse(),
+        Ok(BarStyle::Template("{bar:40} {pos}/{len}".to_string()))
+    );
+    assert!("{pos:abc}".parse::<BarStyle>().is_err());
+}
+
+#[tokio::test]
+async fn json_style_prints_a_status_object_a_second() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/download.php"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_bytes(fs::read("tests/fixtures/utf8.bsmx").unwrap())
+                .set_delay(Duration::from_millis(2500)),
+        )
+        .mount(&server)
+        .await;
+
+    let dir = TempDir::new().unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_beerscape"))
+        .current_dir(dir.path())
+        .args([
+            "--base-url",
+            &server.uri(),
+            "--ids",
+            "7",
+            "--skip-healthcheck",
+            "--progress-style",
+            "json",
+        ])
+        .stdin(Stdio::null())
+        .output()
+        .await
+        .unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines: Vec<serde_json::Value> = stdout
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert!(lines.len() >= 2, "{}", stdout);
+    for line in &lines {
+        let mut keys: Vec<&str> = line
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(|k| k.as_str())
+            .collect();
+        keys.sort();
+        assert_eq!(keys, ["failed", "rate_per_sec", "successful", "ts"]);
+    }
+}
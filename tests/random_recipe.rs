@@ -0,0 +1,89 @@
+use std::fs;
+use std::process::Command;
+use tempfile::TempDir;
+
+fn beerscape(dir: &TempDir, args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_beerscape"))
+        .current_dir(dir.path())
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+fn collection(size: usize) -> TempDir {
+    let dir = TempDir::new().unwrap();
+    let recipes = dir.path().join("recipes");
+    fs::create_dir(&recipes).unwrap();
+    for n in 1..=size {
+        fs::copy(
+            "tests/fixtures/utf8.bsmx",
+            recipes.join(format!("{}.bsmx", n)),
+        )
+        .unwrap();
+    }
+    dir
+}
+
+/// The files named in the summaries' titles, in order.
+fn picked(stdout: &str) -> Vec<String> {
+    stdout
+        .lines()
+        .filter_map(|line| line.strip_prefix("Bière de Garde à 12° (recipes/"))
+        .map(|rest| rest.trim_end_matches(')').to_string())
+        .collect()
+}
+
+#[test]
+fn prints_a_summary_of_a_recipe() {
+    let dir = collection(1);
+    let stdout = beerscape(&dir, &["random-recipe"]);
+    assert!(
+        stdout.starts_with("Bière de Garde à 12° (recipes/1.bsmx)\n---"),
+        "{}",
+        stdout
+    );
+    for part in ["\nOG ", " IBU ", " SRM ", "\nFermentables:\n", "\nHops:\n"] {
+        assert!(stdout.contains(part), "{}", stdout);
+    }
+}
+
+#[test]
+fn the_same_seed_picks_the_same_recipes() {
+    let dir = collection(20);
+    let args = ["random-recipe", "--count", "3", "--seed", "7"];
+    let first = picked(&beerscape(&dir, &args));
+    assert_eq!(first.len(), 3);
+    assert_eq!(picked(&beerscape(&dir, &args)), first);
+    let mut distinct = first.clone();
+    distinct.sort();
+    distinct.dedup();
+    assert_eq!(distinct.len(), 3);
+
+    let all = picked(&beerscape(&dir, &["random-recipe", "--count", "50"]));
+    assert_eq!(all.len(), 20);
+}
+
+#[test]
+fn filters_pick_only_from_matching_recipes() {
+    let dir = collection(10);
+    beerscape(&dir, &["index"]);
+    beerscape(&dir, &["tag", "add", "4.bsmx", "inspiring"]);
+    beerscape(&dir, &["tag", "add", "9.bsmx", "inspiring"]);
+
+    let stdout = beerscape(
+        &dir,
+        &["random-recipe", "--count", "5", "--tag", "inspiring"],
+    );
+    let mut picked = picked(&stdout);
+    picked.sort();
+    assert_eq!(picked, ["4.bsmx", "9.bsmx"]);
+
+    let stdout = beerscape(&dir, &["random-recipe", "--tag", "nothing"]);
+    assert_eq!(stdout, "No recipes to pick from\n");
+}
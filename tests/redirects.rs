@@ -0,0 +1,208 @@
+mod common;
+
+use beer_scape::cookies::parse_netscape_cookies;
+use beer_scape::download::{build_client, download_recipe, DownloadConfig, RedirectRefused};
+use common::fixture;
+use std::error::Error;
+use std::fs;
+use std::sync::Arc;
+use std::time::Duration;
+use tempfile::TempDir;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+async fn setup() -> (MockServer, TempDir, DownloadConfig) {
+    let server = MockServer::start().await;
+    let dir = TempDir::new().unwrap();
+    let config = DownloadConfig {
+        base_url: server.uri(),
+        output_dir: dir.path().to_path_buf(),
+        request_timeout: Duration::from_millis(500),
+        ..Default::default()
+    };
+    (server, dir, config)
+}
+
+async fn redirect(server: &MockServer, from: &str, to: &str) {
+    Mock::given(method("GET"))
+        .and(path(from))
+        .respond_with(ResponseTemplate::new(302).insert_header("location", to))
+        .mount(server)
+        .await;
+}
+
+async fn recipe_at(server: &MockServer, at: &str) {
+    Mock::given(method("GET"))
+        .and(path(at))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("content-type", "application/octet-stream")
+                .set_body_bytes(fixture()),
+        )
+        .mount(server)
+        .await;
+}
+
+/// The same server under a name that isn't the same host to reqwest.
+fn other_host(server: &MockServer) -> String {
+    format!("http://localhost:{}", server.address().port())
+}
+
+fn refusal<'a>(e: &'a (dyn Error + 'static)) -> Option<&'a RedirectRefused> {
+    let mut source = e.source();
+    while let Some(e) = source {
+        if let Some(refused) = e.downcast_ref() {
+            return Some(refused);
+        }
+        source = e.source();
+    }
+    None
+}
+
+#[tokio::test]
+async fn follows_redirects_on_the_same_host_and_records_where_they_went() {
+    let (server, dir, mut config) = setup().await;
+    config.sidecar = true;
+    config.same_host_redirects_only = true;
+    redirect(&server, "/download.php", "/mirror/7.bsmx").await;
+    recipe_at(&server, "/mirror/7.bsmx").await;
+
+    let client = build_client(&config).unwrap();
+    download_recipe(&client, &config, 7).await.unwrap().unwrap();
+
+    let sidecar: serde_json::Value =
+        serde_json::from_slice(&fs::read(dir.path().join("7.json")).unwrap()).unwrap();
+    assert_eq!(
+        sidecar["final_url"],
+        format!("{}/mirror/7.bsmx", server.uri())
+    );
+    assert!(sidecar["user_agent"].is_string());
+}
+
+#[tokio::test]
+async fn leaves_the_final_url_out_without_a_redirect() {
+    let (server, dir, mut config) = setup().await;
+    config.sidecar = true;
+    recipe_at(&server, "/download.php").await;
+
+    let client = build_client(&config).unwrap();
+    download_recipe(&client, &config, 7).await.unwrap().unwrap();
+
+    let sidecar: serde_json::Value =
+        serde_json::from_slice(&fs::read(dir.path().join("7.json")).unwrap()).unwrap();
+    assert!(sidecar.get("final_url").is_none(), "{}", sidecar);
+}
+
+#[tokio::test]
+async fn follows_redirects_to_another_host_without_the_session_cookie() {
+    let (server, dir, mut config) = setup().await;
+    let mirror = MockServer::start().await;
+    let cookies = dir.path().join("cookies.txt");
+    fs::write(&cookies, "127.0.0.1\tFALSE\t/\tFALSE\t0\tsession\tabc\n").unwrap();
+    config.cookies = Some(Arc::new(parse_netscape_cookies(&cookies).unwrap()));
+    redirect(
+        &server,
+        "/download.php",
+        &format!("{}/mirror/7.bsmx", other_host(&mirror)),
+    )
+    .await;
+    recipe_at(&mirror, "/mirror/7.bsmx").await;
+
+    let client = build_client(&config).unwrap();
+    download_recipe(&client, &config, 7).await.unwrap().unwrap();
+
+    let sent = server.received_requests().await.unwrap();
+    assert_eq!(sent[0].headers.get("cookie").unwrap(), "session=abc");
+    let followed = mirror.received_requests().await.unwrap();
+    assert_eq!(followed.len(), 1);
+    assert!(followed[0].headers.get("cookie").is_none());
+}
+
+#[tokio::test]
+async fn refuses_redirects_to_another_host_when_asked() {
+    let (server, dir, mut config) = setup().await;
+    let mirror = MockServer::start().await;
+    config.same_host_redirects_only = true;
+    redirect(
+        &server,
+        "/download.php",
+        &format!("{}/mirror/7.bsmx", other_host(&mirror)),
+    )
+    .await;
+    recipe_at(&mirror, "/mirror/7.bsmx").await;
+
+    let client = build_client(&config).unwrap();
+    let e = download_recipe(&client, &config, 7).await.unwrap_err();
+    assert!(
+        matches!(refusal(e.as_ref()), Some(RedirectRefused::CrossHost(_))),
+        "{}",
+        e
+    );
+    assert!(mirror.received_requests().await.unwrap().is_empty());
+    assert!(!dir.path().join("7.bsmx").exists());
+}
+
+#[tokio::test]
+async fn keeps_custom_headers_from_another_host() {
+    let (server, dir, mut config) = setup().await;
+    let mirror = MockServer::start().await;
+    config.headers = vec!["X-Api-Key: abc123".parse().unwrap()];
+    redirect(
+        &server,
+        "/download.php",
+        &format!("{}/mirror/7.bsmx", other_host(&mirror)),
+    )
+    .await;
+    recipe_at(&mirror, "/mirror/7.bsmx").await;
+
+    let client = build_client(&config).unwrap();
+    download_recipe(&client, &config, 7).await.unwrap().unwrap();
+    assert!(dir.path().join("7.bsmx").exists());
+
+    let sent = server.received_requests().await.unwrap();
+    assert_eq!(sent[0].headers.get("x-api-key").unwrap(), "abc123");
+    let followed = mirror.received_requests().await.unwrap();
+    assert_eq!(followed.len(), 1);
+    assert!(followed[0].headers.get("x-api-key").is_none());
+    // The request's own headers still go
+    assert!(followed[0].headers.get("user-agent").is_some());
+}
+
+#[tokio::test]
+async fn gives_up_after_max_redirects() {
+    let (server, _dir, mut config) = setup().await;
+    config.max_redirects = 3;
+    redirect(&server, "/download.php", "/download.php").await;
+
+    let client = build_client(&config).unwrap();
+    let e = download_recipe(&client, &config, 7).await.unwrap_err();
+    assert!(
+        matches!(refusal(e.as_ref()), Some(RedirectRefused::TooMany(3))),
+        "{}",
+        e
+    );
+    // The original request and three redirects
+    assert_eq!(server.received_requests().await.unwrap().len(), 4);
+}
+
+#[tokio::test]
+async fn a_redirect_to_a_page_is_not_a_recipe() {
+    let (server, dir, config) = setup().await;
+    redirect(&server, "/download.php", "/error.html").await;
+    // Well-formed enough to pass for XML
+    Mock::given(method("GET"))
+        .and(path("/error.html"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(
+            "<html><body>Recipe not available</body></html>",
+            "text/html; charset=utf-8",
+        ))
+        .mount(&server)
+        .await;
+
+    let client = build_client(&config).unwrap();
+    assert!(download_recipe(&client, &config, 7)
+        .await
+        .unwrap()
+        .is_none());
+    assert!(!dir.path().join("7.bsmx").exists());
+}
@@ -0,0 +1,102 @@
+use beer_scape::throttle::{parse_retry_after, RequestGate};
+use chrono::{TimeZone, Utc};
+use std::fs;
+use std::process::Stdio;
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+use tokio::process::Command;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+#[test]
+fn parses_seconds_and_http_dates() {
+    let now = Utc.with_ymd_and_hms(2015, 10, 21, 7, 28, 0).unwrap();
+    assert_eq!(
+        parse_retry_after("120", now),
+        Some(Duration::from_secs(120))
+    );
+    assert_eq!(parse_retry_after(" 0 ", now), Some(Duration::ZERO));
+    assert_eq!(
+        parse_retry_after("Wed, 21 Oct 2015 07:28:30 GMT", now),
+        Some(Duration::from_secs(30))
+    );
+    // Already past
+    assert_eq!(
+        parse_retry_after("Wed, 21 Oct 2015 07:27:00 GMT", now),
+        Some(Duration::ZERO)
+    );
+    assert_eq!(parse_retry_after("soon", now), None);
+    assert_eq!(parse_retry_after("-5", now), None);
+}
+
+#[tokio::test]
+async fn the_longest_pause_holds_every_request() {
+    let gate = RequestGate::new(None);
+    assert!(gate.pause_for(Duration::from_millis(300)));
+    // Shorter than the pause already on, so it changes nothing
+    assert!(!gate.pause_for(Duration::from_millis(100)));
+    let started = Instant::now();
+    let waiting = async {
+        gate.pass().await;
+        started.elapsed()
+    };
+    let extending = async {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(gate.pause_for(Duration::from_millis(500)));
+    };
+    let (waited, ()) = tokio::join!(waiting, extending);
+    assert!(waited >= Duration::from_millis(500), "{:?}", waited);
+    assert!(waited < Duration::from_millis(1000), "{:?}", waited);
+    assert_eq!(gate.paused_for(), None);
+}
+
+#[tokio::test]
+async fn retries_a_rate_limited_id_after_the_wait() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/download.php"))
+        .respond_with(ResponseTemplate::new(429).append_header("retry-after", "1"))
+        .up_to_n_times(1)
+        .with_priority(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/download.php"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_bytes(fs::read("tests/fixtures/utf8.bsmx").unwrap()),
+        )
+        .mount(&server)
+        .await;
+
+    let dir = TempDir::new().unwrap();
+    let started = Instant::now();
+    let output = Command::new(env!("CARGO_BIN_EXE_beerscape"))
+        .current_dir(dir.path())
+        .args([
+            "--base-url",
+            &server.uri(),
+            "--ids",
+            "7",
+            "--skip-healthcheck",
+            "--progress",
+            "json",
+        ])
+        .stdin(Stdio::null())
+        .output()
+        .await
+        .unwrap();
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr);
+    assert!(started.elapsed() >= Duration::from_secs(1));
+    assert!(dir.path().join("recipes/7.bsmx").exists());
+    assert!(
+        stdout.contains(r#""event":"rate-limited","id":7,"retry_after_secs":1"#),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains(r#""failed":0"#), "{}", stdout);
+    assert!(!dir.path().join("failed_ids.txt").exists());
+}
@@ -0,0 +1,124 @@
+mod common;
+
+use beer_scape::robots::{Robots, ROBOTS_AGENT};
+use common::{beerscape, serve_recipes, status_line};
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const ROBOTS: &str = "\
+# Keep the crawlers off the heavy pages
+User-agent: *
+Disallow: /download
+Allow: /download.php$
+Crawl-delay: 2
+
+User-agent: BadBot
+User-agent: beerscape
+Disallow: /*.php$
+Allow: /search.php
+Crawl-delay: 5
+";
+
+#[test]
+fn our_own_group_replaces_the_wildcard_one() {
+    let robots = Robots::parse(ROBOTS);
+    let rule = robots.disallowing(ROBOTS_AGENT, "/download.php").unwrap();
+    assert_eq!(rule.to_string(), "Disallow: /*.php$");
+    assert!(robots.disallowing(ROBOTS_AGENT, "/search.php").is_none());
+    assert!(robots
+        .disallowing(ROBOTS_AGENT, "/download.php.bak")
+        .is_none());
+    assert_eq!(robots.crawl_delay(ROBOTS_AGENT), Some(5.0));
+}
+
+#[test]
+fn longest_match_wins_and_allow_wins_ties() {
+    let robots = Robots::parse(ROBOTS);
+    // Only the wildcard group applies to anyone else
+    assert!(robots.disallowing("otherbot", "/download.php").is_none());
+    assert_eq!(
+        robots
+            .disallowing("otherbot", "/downloads/all.zip")
+            .unwrap()
+            .to_string(),
+        "Disallow: /download"
+    );
+    assert_eq!(robots.crawl_delay("otherbot"), Some(2.0));
+
+    let tie = Robots::parse("User-agent: *\nDisallow: /a\nAllow: /a\n");
+    assert!(tie.disallowing(ROBOTS_AGENT, "/a").is_none());
+}
+
+#[test]
+fn empty_or_missing_rules_allow_everything() {
+    let robots = Robots::parse("User-agent: *\nDisallow:\n\nnonsense\nDisallow: /orphan\n");
+    assert!(robots.disallowing(ROBOTS_AGENT, "/download.php").is_none());
+    assert!(Robots::default()
+        .disallowing(ROBOTS_AGENT, "/download.php")
+        .is_none());
+    // Rules before any User-agent line belong to nobody
+    let orphans = Robots::parse("Disallow: /\nUser-agent: *\nAllow: /\n");
+    assert!(orphans.disallowing(ROBOTS_AGENT, "/download.php").is_none());
+    assert_eq!(orphans.crawl_delay(ROBOTS_AGENT), None);
+}
+
+async fn serve_robots(server: &MockServer, robots: &str) {
+    Mock::given(method("GET"))
+        .and(path("/robots.txt"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(robots))
+        .mount(server)
+        .await;
+}
+
+#[tokio::test]
+async fn robots_txt_can_stop_a_run() {
+    let server = serve_recipes(Duration::ZERO).await;
+    serve_robots(&server, "User-agent: *\nDisallow: /download\n").await;
+    let dir = TempDir::new().unwrap();
+
+    let output = beerscape(&dir, &server.uri(), &["--respect-robots-txt", "--ids", "1"])
+        .output()
+        .await
+        .unwrap();
+    assert_eq!(output.status.code(), Some(4));
+    assert!(
+        status_line(&output)
+            .contains("robots.txt disallows /download.php with \"Disallow: /download\""),
+        "{}",
+        status_line(&output)
+    );
+
+    let output = beerscape(
+        &dir,
+        &server.uri(),
+        &["--respect-robots-txt", "--force", "--ids", "1"],
+    )
+    .output()
+    .await
+    .unwrap();
+    assert!(output.status.success());
+}
+
+#[tokio::test]
+async fn crawl_delay_spaces_out_requests() {
+    let server = serve_recipes(Duration::ZERO).await;
+    serve_robots(&server, "User-agent: beerscape\nCrawl-delay: 0.3\n").await;
+    let dir = TempDir::new().unwrap();
+
+    let started = Instant::now();
+    let output = beerscape(
+        &dir,
+        &server.uri(),
+        &["--respect-robots-txt", "--ids", "1,2,3"],
+    )
+    .output()
+    .await
+    .unwrap();
+    assert_eq!(
+        status_line(&output),
+        "beerscape: complete: ids-exhausted, 3 downloaded, 0 failed"
+    );
+    assert!(started.elapsed() >= Duration::from_millis(600));
+}
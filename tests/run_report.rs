@@ -0,0 +1,112 @@
+use beer_scape::run_report::{LatencyHistogram, ResponseStats, RunConfig, RunLog, RunReport};
+use std::path::PathBuf;
+use std::time::Duration;
+use tempfile::TempDir;
+
+fn config() -> RunConfig {
+    RunConfig {
+        base_url: "http://localhost".to_string(),
+        output_dir: PathBuf::from("recipes"),
+        target: 100,
+        concurrency: 10,
+        timeout_secs: 10,
+        daily_quota: 0,
+    }
+}
+
+#[test]
+fn summarizes_the_run() {
+    let mut log = RunLog::default();
+    for ms in 1..=100 {
+        log.saved(1000, Duration::from_millis(ms));
+    }
+    log.failed(7, "not-found", Duration::from_millis(5));
+    log.failed(3, "timeout", Duration::from_secs(10));
+    log.retried();
+    log.throttled(Duration::from_secs(30));
+
+    let report = log.finish(config(), "target-reached", 10, 100, 102);
+    assert_eq!(report.outcomes["saved"], 100);
+    assert_eq!(report.outcomes["not-found"], 1);
+    assert_eq!(report.bytes, 100_000);
+    assert_eq!(report.average_recipe_bytes, Some(1000));
+    assert_eq!(report.failed_ids, vec![3, 7]);
+    assert_eq!(report.retries, 1);
+    assert_eq!(report.throttle_secs, 30.0);
+    assert_eq!(report.latency.p50_ms, 51);
+    assert_eq!(report.latency.max_ms, 10_000);
+    assert_eq!(report.saved_latency.p50_ms, 51);
+    assert_eq!(report.saved_latency.max_ms, 100);
+    assert_eq!(report.failed_latency.p50_ms, 10_000);
+    assert_eq!(report.failed_latency.p90_ms, 10_000);
+    assert_eq!(report.success_rate(), Some(100.0 / 102.0));
+}
+
+#[test]
+fn round_trips_through_a_file() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("reports").join("run.json");
+    let report = RunLog::default().finish(config(), "quota-reached", 5, 0, 0);
+    report.save(&path).unwrap();
+
+    let loaded = RunReport::load(&path).unwrap();
+    assert_eq!(loaded.finish_reason, "quota-reached");
+    assert_eq!(loaded.started_at, report.started_at);
+    assert_eq!(loaded.success_rate(), None);
+}
+
+#[test]
+fn histogram_percentiles_stay_close_to_the_latencies() {
+    let mut histogram = LatencyHistogram::default();
+    assert_eq!(histogram.percentile_ms(50.0), 0);
+    for ms in 1..=10_000 {
+        histogram.record(Duration::from_millis(ms));
+    }
+    assert_eq!(histogram.count(), 10_000);
+    for (p, exact) in [(50.0, 5_000.0), (90.0, 9_000.0), (99.0, 9_900.0)] {
+        let ms = histogram.percentile_ms(p) as f64;
+        assert!(ms >= exact && ms <= exact * 1.04, "p{} was {}", p, ms);
+    }
+    assert_eq!(histogram.percentile_ms(100.0), 10_000);
+
+    let mut small = LatencyHistogram::default();
+    for ms in [3, 3, 7, 40] {
+        small.record(Duration::from_millis(ms));
+    }
+    assert_eq!(small.percentiles().p50_ms, 7);
+    assert_eq!(small.percentiles().max_ms, 40);
+}
+
+#[test]
+fn reports_latency_by_outcome_and_phase() {
+    let responses = ResponseStats::default();
+    responses.headers(Duration::from_millis(30));
+    responses.headers(Duration::from_millis(10));
+    responses.headers(Duration::from_millis(12));
+    responses.body(Duration::from_millis(5));
+
+    let mut log = RunLog::default();
+    log.saved(1000, Duration::from_millis(35));
+    log.failed(2, "not-found", Duration::from_millis(10));
+    log.failed(3, "not-found", Duration::from_millis(12));
+    let mut report = log.finish(config(), "target-reached", 0, 1, 3);
+    responses.add_to(&mut report);
+    assert_eq!(report.header_latency.max_ms, 30);
+    assert_eq!(
+        report.latency_summary(),
+        "Latency Summary:\n\
+         ----------------\n\
+         Saved Latency: p50 35 ms, p90 35 ms, p99 35 ms\n\
+         Failed Latency: p50 12 ms, p90 12 ms, p99 12 ms\n\
+         Time to Headers: p50 12 ms, p90 30 ms, p99 30 ms\n\
+         Time to Read Body: p50 5 ms, p90 5 ms, p99 5 ms"
+    );
+
+    report.status_codes = [(0, 1), (200, 1), (404, 2)].into_iter().collect();
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("run.json");
+    report.save(&path).unwrap();
+    let loaded = RunReport::load(&path).unwrap();
+    assert_eq!(loaded.status_codes, report.status_codes);
+    assert_eq!(loaded.body_latency.p50_ms, 5);
+}
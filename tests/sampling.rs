@@ -0,0 +1,125 @@
+use beer_scape::cli::Strategy;
+use beer_scape::ids::IdTracker;
+use beer_scape::sampling::{IdPicker, BUCKETS};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+#[test]
+fn buckets_cover_the_range() {
+    let picker = IdPicker::new(1..=1000, Strategy::Uniform, 0.1);
+    let buckets = picker.buckets();
+    assert_eq!(buckets.len(), BUCKETS);
+    assert_eq!(buckets[0].start, 1);
+    assert_eq!(buckets[0].end, 10);
+    assert_eq!(buckets[BUCKETS - 1].end, 1000);
+    for pair in buckets.windows(2) {
+        assert_eq!(pair[0].end + 1, pair[1].start);
+    }
+
+    // Fewer IDs than buckets gives one bucket per ID
+    assert_eq!(
+        IdPicker::new(5..=7, Strategy::Uniform, 0.1).buckets().len(),
+        3
+    );
+}
+
+#[test]
+fn records_hit_rates_per_bucket() {
+    let mut picker = IdPicker::new(1..=1000, Strategy::Uniform, 0.1);
+    picker.record(3, true);
+    picker.record(4, false);
+    picker.record(995, false);
+    picker.record(5000, true);
+
+    let buckets = picker.buckets();
+    assert_eq!((buckets[0].attempts, buckets[0].hits), (2, 1));
+    assert_eq!(buckets[0].hit_rate, 0.5);
+    assert_eq!((buckets[99].attempts, buckets[99].hits), (1, 0));
+    let attempts: usize = buckets.iter().map(|b| b.attempts).sum();
+    assert_eq!(attempts, 3);
+}
+
+#[test]
+fn adaptive_favours_dense_buckets() {
+    let mut rng = StdRng::seed_from_u64(11);
+    let mut tracker = IdTracker::new(1..=100_000);
+    let mut picker = IdPicker::new(1..=100_000, Strategy::Adaptive, 0.1);
+    // Only the first bucket holds recipes
+    let is_hit = |id: u32| id <= 1000;
+
+    let mut hits = 0;
+    for _ in 0..2000 {
+        let id = picker.next(&mut tracker, &mut rng).unwrap();
+        picker.record(id, is_hit(id));
+        hits += is_hit(id) as usize;
+    }
+    // Uniform picks would find about 20
+    assert!(hits > 200, "{} hits", hits);
+    assert_eq!(tracker.remaining_count(), 98_000);
+}
+
+#[test]
+fn adaptive_draws_every_id_once() {
+    let mut rng = StdRng::seed_from_u64(3);
+    let mut tracker = IdTracker::new(1..=500);
+    let mut picker = IdPicker::new(1..=500, Strategy::Adaptive, 0.1);
+    let mut seen = vec![false; 501];
+    while let Some(id) = picker.next(&mut tracker, &mut rng) {
+        assert!(!seen[id as usize], "{} drawn twice", id);
+        seen[id as usize] = true;
+        picker.record(id, id % 7 == 0);
+    }
+    assert!(seen[1..].iter().all(|&s| s));
+}
+
+#[test]
+fn tracker_draws_within_a_subrange() {
+    let mut rng = StdRng::seed_from_u64(5);
+    let mut tracker = IdTracker::new(1..=200);
+    assert_eq!(tracker.remaining_in(50..=59), 10);
+    tracker.insert(55);
+    assert_eq!(tracker.remaining_in(50..=59), 9);
+
+    for _ in 0..9 {
+        let id = tracker.next_in(50..=59, &mut rng).unwrap();
+        assert!((50..=59).contains(&id) && id != 55);
+    }
+    assert_eq!(tracker.next_in(50..=59, &mut rng), None);
+    assert_eq!(tracker.remaining_in(50..=59), 0);
+    assert_eq!(tracker.remaining_count(), 190);
+}
+
+#[test]
+fn since_id_skews_picks_toward_the_newest_ids() {
+    let picker = IdPicker::new(1..=1_000_000, Strategy::Uniform, 0.1).with_since(Some(900_001));
+    let mut tracker = IdTracker::new(1..=1_000_000);
+    let mut rng = StdRng::seed_from_u64(3);
+    let picks: Vec<u32> = (0..10_000)
+        .map(|_| picker.next(&mut tracker, &mut rng).unwrap())
+        .collect();
+    let share = |f: &dyn Fn(u32) -> bool| {
+        picks.iter().filter(|&&id| f(id)).count() as f64 / picks.len() as f64
+    };
+    // 90% triangular past 900,000, plus a tenth of the 10% explored
+    assert!((share(&|id| id > 900_000) - 0.91).abs() < 0.01);
+    // The triangle puts three quarters of its picks in its upper half
+    let upper = share(&|id| id > 950_000) / share(&|id| id > 900_000);
+    assert!((upper - 0.75).abs() < 0.02, "{}", upper);
+    assert!(picker
+        .distribution()
+        .contains("triangular distribution over 900001-1000000"));
+}
+
+#[test]
+fn since_id_falls_back_to_the_whole_range_once_it_is_used_up() {
+    let picker = IdPicker::new(1..=100, Strategy::Uniform, 0.0).with_since(Some(91));
+    let mut tracker = IdTracker::new(1..=100);
+    let mut rng = StdRng::seed_from_u64(5);
+    let mut picks: Vec<u32> = (0..100)
+        .map(|_| picker.next(&mut tracker, &mut rng).unwrap())
+        .collect();
+    assert!(picks[..10].iter().all(|&id| id > 90), "{:?}", picks);
+    picks.sort_unstable();
+    assert_eq!(picks, (1..=100).collect::<Vec<_>>());
+    assert_eq!(picker.next(&mut tracker, &mut rng), None);
+}
@@ -0,0 +1,23 @@
+use beer_scape::cli::StorageLayout;
+use beer_scape::collection::scan_stems;
+use indicatif::ProgressBar;
+use std::fs;
+use tempfile::TempDir;
+
+#[test]
+fn scans_the_recipe_files_and_counts_every_entry() {
+    let dir = TempDir::new().unwrap();
+    for id in 0..10_000 {
+        fs::write(dir.path().join(format!("{}.bsmx", id)), "<Recipe/>").unwrap();
+    }
+    fs::write(dir.path().join("index.db"), "").unwrap();
+    fs::write(dir.path().join("1.json"), "{}").unwrap();
+
+    let pb = ProgressBar::hidden();
+    let stems = scan_stems(dir.path(), StorageLayout::Flat, &pb).unwrap();
+
+    assert_eq!(stems.len(), 10_000);
+    assert!(stems.contains("9999"));
+    assert!(!stems.contains("index"));
+    assert_eq!(pb.position(), 10_002);
+}
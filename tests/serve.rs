@@ -0,0 +1,204 @@
+mod common;
+
+use beer_scape::bsmx::parse_bsmx;
+use beer_scape::color::srm_to_hex;
+use beer_scape::commands::serve::{router, Collection};
+use beer_scape::index::{Filter, Index, IndexEntry};
+use common::fixture;
+use serde_json::{json, Value};
+use std::fs;
+use tempfile::TempDir;
+
+/// Index the fixture under each name, with the given style and ABV.
+fn add_recipes(dir: &TempDir, recipes: &[(&str, &str, f64)]) {
+    let contents = fixture();
+    let recipe = parse_bsmx(&contents).unwrap();
+    let index = Index::open(dir.path()).unwrap();
+    for &(filename, style, abv) in recipes {
+        fs::write(dir.path().join(filename), &contents).unwrap();
+        let mut entry = IndexEntry::new(filename, &recipe);
+        entry.name = filename.trim_end_matches(".bsmx").to_string();
+        entry.style = style.to_string();
+        entry.abv = abv;
+        index.upsert(&entry).unwrap();
+    }
+}
+
+async fn start(collection: Collection) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, router(collection)).await });
+    format!("http://{}", addr)
+}
+
+async fn get_json(url: &str) -> Value {
+    reqwest::get(url).await.unwrap().json().await.unwrap()
+}
+
+fn names(list: &Value) -> Vec<&str> {
+    list["recipes"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|r| r["name"].as_str().unwrap())
+        .collect()
+}
+
+#[test]
+fn filter_matches_like_the_index() {
+    let dir = TempDir::new().unwrap();
+    add_recipes(
+        &dir,
+        &[
+            ("amber.bsmx", "American Amber Ale", 5.2),
+            ("stout.bsmx", "Dry Stout", 4.1),
+            ("tripel.bsmx", "Belgian Tripel", 9.0),
+        ],
+    );
+    let index = Index::open(dir.path()).unwrap();
+    let filters = [
+        Filter {
+            text: "AMBER".into(),
+            ..Default::default()
+        },
+        Filter {
+            style: "stout".into(),
+            ..Default::default()
+        },
+        Filter {
+            min_abv: Some(5.0),
+            ..Default::default()
+        },
+        Filter {
+            min_abv: Some(4.0),
+            max_abv: Some(5.2),
+            ..Default::default()
+        },
+    ];
+    let entries = index.entries().unwrap();
+    for filter in &filters {
+        let queried = index.matching(filter, 0, 10).unwrap();
+        let mut in_memory: Vec<&IndexEntry> =
+            entries.iter().filter(|e| filter.matches(e)).collect();
+        in_memory.sort_by(|a, b| a.name.cmp(&b.name));
+        assert_eq!(
+            queried.iter().collect::<Vec<_>>(),
+            in_memory,
+            "{:?}",
+            filter
+        );
+    }
+}
+
+#[tokio::test]
+async fn lists_and_searches_with_pages_and_abv_bounds() {
+    let dir = TempDir::new().unwrap();
+    add_recipes(
+        &dir,
+        &[
+            ("amber.bsmx", "American Amber Ale", 5.2),
+            ("stout.bsmx", "Dry Stout", 4.1),
+            ("tripel.bsmx", "Belgian Tripel", 9.0),
+            ("brown.bsmx", "American Brown Ale", 5.5),
+        ],
+    );
+    let base = start(Collection::load(dir.path()).unwrap()).await;
+
+    let page = get_json(&format!("{}/api/recipes?per_page=3&page=2", base)).await;
+    assert_eq!(page["total"], 4);
+    assert_eq!(names(&page), vec!["tripel"]);
+
+    let list = get_json(&format!("{}/api/recipes?min_abv=5&max_abv=6", base)).await;
+    assert_eq!(names(&list), vec!["amber", "brown"]);
+
+    let found: Value = reqwest::Client::new()
+        .post(format!("{}/api/search", base))
+        .json(&json!({"style": "american", "max_abv": 5.3}))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(found["total"], 1);
+    assert_eq!(names(&found), vec!["amber"]);
+}
+
+#[tokio::test]
+async fn serves_recipes_raw_files_and_stats() {
+    let dir = TempDir::new().unwrap();
+    add_recipes(
+        &dir,
+        &[
+            ("amber.bsmx", "American Amber Ale", 5.0),
+            ("amber2.bsmx", "American Amber Ale", 6.0),
+            ("stout.bsmx", "Dry Stout", 4.0),
+        ],
+    );
+    let base = start(Collection::load(dir.path()).unwrap()).await;
+
+    let detail = get_json(&format!("{}/api/recipes/stout.bsmx", base)).await;
+    assert_eq!(detail["filename"], "stout.bsmx");
+    assert!(detail["recipe"]["hops"].is_array());
+    let srm = detail["color_srm"].as_f64().unwrap();
+    assert_eq!(detail["color_hex"], srm_to_hex(srm));
+    let list = get_json(&format!("{}/api/recipes", base)).await;
+    assert_eq!(list["recipes"][0]["color_hex"], srm_to_hex(srm));
+
+    let raw = reqwest::get(format!("{}/api/recipes/stout.bsmx/raw", base))
+        .await
+        .unwrap();
+    assert_eq!(raw.headers()["content-type"], "application/xml");
+    assert_eq!(raw.bytes().await.unwrap().to_vec(), fixture());
+
+    let missing = reqwest::get(format!("{}/api/recipes/nope.bsmx/raw", base))
+        .await
+        .unwrap();
+    assert_eq!(missing.status(), 404);
+
+    let stats = get_json(&format!("{}/api/stats", base)).await;
+    assert_eq!(stats["recipes"], 3);
+    assert_eq!(stats["mean_abv"], 5.0);
+    assert_eq!(
+        stats["styles"][0],
+        json!({"style": "American Amber Ale", "recipes": 2})
+    );
+}
+
+#[tokio::test]
+async fn keeps_serving_the_loaded_index_until_reloaded() {
+    let dir = TempDir::new().unwrap();
+    add_recipes(&dir, &[("amber.bsmx", "American Amber Ale", 5.0)]);
+    let collection = Collection::load(dir.path()).unwrap();
+    let base = start(collection.clone()).await;
+
+    add_recipes(&dir, &[("stout.bsmx", "Dry Stout", 4.0)]);
+    let list = get_json(&format!("{}/api/recipes", base)).await;
+    assert_eq!(list["total"], 1);
+    let unseen = reqwest::get(format!("{}/api/recipes/stout.bsmx", base))
+        .await
+        .unwrap();
+    assert_eq!(unseen.status(), 404);
+
+    let reloaded: Value = reqwest::Client::new()
+        .post(format!("{}/api/reload", base))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(reloaded, json!({"recipes": 2}));
+    let list = get_json(&format!("{}/api/recipes", base)).await;
+    assert_eq!(names(&list), vec!["amber", "stout"]);
+
+    // As SIGHUP does
+    fs::remove_file(dir.path().join("amber.bsmx")).unwrap();
+    Index::open(dir.path())
+        .unwrap()
+        .remove("amber.bsmx")
+        .unwrap();
+    assert_eq!(collection.reload().unwrap(), 1);
+    let list = get_json(&format!("{}/api/recipes", base)).await;
+    assert_eq!(names(&list), vec!["stout"]);
+}
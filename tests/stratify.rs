@@ -0,0 +1,134 @@
+mod common;
+
+use beer_scape::stratify::{Plan, StyleQuota, STRATIFY_PATIENCE};
+use common::{beerscape, serve_recipes};
+use std::collections::HashMap;
+use std::fs;
+use std::time::Duration;
+use tempfile::TempDir;
+
+fn probed(styles: &[(&str, usize)]) -> HashMap<String, usize> {
+    styles
+        .iter()
+        .map(|&(style, count)| (style.to_string(), count))
+        .collect()
+}
+
+#[test]
+fn caps_styles_by_their_share_of_the_probe() {
+    let plan = Plan::new(
+        100,
+        &probed(&[("American IPA", 20), ("Dry Stout", 5), ("", 75)]),
+        1.2,
+    );
+    assert_eq!(plan.probed, 100);
+    let caps: Vec<(&str, usize)> = plan
+        .styles
+        .iter()
+        .map(|s| (s.style.as_str(), s.cap))
+        .collect();
+    // ceil(100 * 0.2 * 1.2) and so on, most common first
+    assert_eq!(caps, vec![("", 90), ("American IPA", 24), ("Dry Stout", 6)]);
+    assert_eq!(plan.unseen_cap, 2);
+
+    let text = plan.to_string();
+    assert!(text.starts_with("Stratification Plan:\n"));
+    assert!(text.contains("  American IPA                          20.0%  up to 24\n"));
+    assert!(text.contains("  (no style)"));
+    assert!(text.ends_with("Styles Not Probed: up to 2 each"));
+}
+
+#[test]
+fn an_empty_probe_caps_nothing() {
+    let plan = Plan::new(50, &HashMap::new(), 1.2);
+    assert!(plan.styles.is_empty());
+    assert_eq!(plan.unseen_cap, 50);
+}
+
+#[test]
+fn turns_away_full_styles() {
+    let plan = Plan::new(10, &probed(&[("Pilsner", 1), ("Porter", 1)]), 1.0);
+    let quota = StyleQuota::capped(&plan);
+    for _ in 0..5 {
+        assert!(quota.admit("Pilsner"));
+    }
+    assert!(!quota.admit("Pilsner"));
+    assert!(quota.admit(" Porter "));
+    // Unseen styles get the share of a style seen once
+    for _ in 0..5 {
+        assert!(quota.admit("Gose"));
+    }
+    assert!(!quota.admit("Gose"));
+    assert_eq!(quota.kept()["Porter"], 1);
+    assert!(!quota.lifted());
+}
+
+#[test]
+fn lifts_the_caps_once_the_short_styles_run_out() {
+    let plan = Plan::new(2, &probed(&[("Pilsner", 1), ("Porter", 1)]), 1.0);
+    let quota = StyleQuota::capped(&plan);
+    assert!(quota.admit("Pilsner"));
+    for _ in 1..STRATIFY_PATIENCE {
+        assert!(!quota.admit("Pilsner"));
+    }
+    assert!(quota.admit("Pilsner"));
+    assert!(quota.lifted());
+    assert!(quota.admit("Pilsner"));
+    assert_eq!(quota.kept()["Pilsner"], 3);
+    assert!(quota.summary().ends_with("Caps Lifted: yes"));
+}
+
+#[test]
+fn a_survey_keeps_everything() {
+    let quota = StyleQuota::survey();
+    for style in ["Pilsner", "Pilsner", "Porter"] {
+        assert!(quota.admit(style));
+    }
+    assert_eq!(quota.kept(), probed(&[("Pilsner", 2), ("Porter", 1)]));
+}
+
+#[tokio::test]
+async fn stratified_sample_prints_its_plan() {
+    let server = serve_recipes(Duration::ZERO).await;
+    let dir = TempDir::new().unwrap();
+    let output = beerscape(
+        &dir,
+        &server.uri(),
+        &[
+            "--stratified",
+            "--sample",
+            "3",
+            "--sample-probe",
+            "4",
+            "--min-id",
+            "1",
+            "--max-id",
+            "40",
+        ],
+    )
+    .output()
+    .await
+    .unwrap();
+
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Stratification Plan:"), "{}", stdout);
+    // Every recipe in the probe was the same style, so it gets the lot
+    assert!(stdout.contains("100.0%  up to 4\n"), "{}", stdout);
+    assert!(stdout.contains("Sample by Style:"), "{}", stdout);
+    assert_eq!(
+        fs::read_dir(dir.path().join("recipes"))
+            .unwrap()
+            .flatten()
+            .filter(|e| e.path().extension().is_some_and(|x| x == "bsmx"))
+            .count(),
+        3
+    );
+
+    // --sample on its own still needs a dry run or --stratified
+    let output = beerscape(&dir, &server.uri(), &["--sample", "3"])
+        .output()
+        .await
+        .unwrap();
+    assert_eq!(output.status.code(), Some(4));
+}
@@ -0,0 +1,130 @@
+mod common;
+
+use beer_scape::cli::StorageLayout;
+use beer_scape::collection::recipe_files_in;
+use beer_scape::index::Index;
+use beer_scape::state::State;
+use common::fixture_text;
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use tempfile::TempDir;
+
+/// The fixture with other ingredients, so it's a different recipe.
+fn other_recipe() -> String {
+    fixture_text().replace("Pilsner (2 Row) Bel", "Munich Malt")
+}
+
+fn sync(dir: &TempDir, args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_beerscape"))
+        .current_dir(dir.path())
+        .args(["sync", "source", "dest"])
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+fn write(dir: &Path, files: &[(&str, &str)]) {
+    fs::create_dir_all(dir).unwrap();
+    for (name, contents) in files {
+        fs::write(dir.join(name), contents).unwrap();
+    }
+}
+
+fn names(dir: &Path) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .filter(|name| name.ends_with(".bsmx"))
+        .collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn copies_missing_files_and_reports_conflicts() {
+    let dir = TempDir::new().unwrap();
+    write(
+        &dir.path().join("source"),
+        &[
+            ("1.bsmx", &fixture_text()),
+            ("2.bsmx", &other_recipe()),
+            ("3.bsmx", &fixture_text()),
+        ],
+    );
+    write(
+        &dir.path().join("dest"),
+        &[("3.bsmx", &other_recipe()), ("4.bsmx", "<Recipe/>")],
+    );
+
+    let stdout = sync(&dir, &[]);
+
+    assert!(stdout.contains("Copied to Destination: 1\n"), "{}", stdout);
+    let dest = dir.path().join("dest");
+    // 2 is in the destination as 3, whose name the source uses for
+    // something else, and 4 stays though the source lacks it
+    assert_eq!(names(&dest), ["1.bsmx", "3.bsmx", "4.bsmx"]);
+    assert_eq!(
+        fs::read_to_string(dest.join("3.bsmx")).unwrap(),
+        other_recipe()
+    );
+    assert!(
+        stdout.contains("Conflict: source/3.bsmx and dest/3.bsmx differ"),
+        "{}",
+        stdout
+    );
+    assert!(
+        stdout.contains("Only in destination: dest/4.bsmx"),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("Only in Destination: 1\n"), "{}", stdout);
+    assert!(Index::open(&dest).unwrap().get("1.bsmx").unwrap().is_some());
+}
+
+#[test]
+fn syncs_both_ways_across_layouts() {
+    let dir = TempDir::new().unwrap();
+    let source = dir.path().join("source");
+    let dest = dir.path().join("dest");
+    write(&source, &[("1.bsmx", &fixture_text())]);
+    let cas_name = StorageLayout::Cas.path_for("2.bsmx", other_recipe().as_bytes(), None);
+    fs::create_dir_all(dest.join(&cas_name).parent().unwrap()).unwrap();
+    fs::write(dest.join(&cas_name), other_recipe()).unwrap();
+    let state = State {
+        storage_layout: StorageLayout::Cas,
+        ..Default::default()
+    };
+    state.save(&dest).unwrap();
+
+    let stdout = sync(&dir, &["--bidirectional", "--dry-run"]);
+    assert!(
+        stdout.contains("Would Copy to Destination: 1\n"),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("Would Copy to Source: 1\n"), "{}", stdout);
+    assert_eq!(names(&source), ["1.bsmx"]);
+    assert_eq!(recipe_files_in(&dest, StorageLayout::Cas).unwrap().len(), 1);
+
+    let stdout = sync(&dir, &["--bidirectional"]);
+    assert!(stdout.contains("Copied to Source: 1\n"), "{}", stdout);
+    // The content-addressed name is all the destination knew it by
+    assert!(source
+        .join(Path::new(&cas_name).file_name().unwrap())
+        .exists());
+    assert_eq!(recipe_files_in(&dest, StorageLayout::Cas).unwrap().len(), 2);
+
+    // Already in step, nothing more to do either way
+    let stdout = sync(&dir, &["--bidirectional"]);
+    assert!(stdout.contains("Copied to Destination: 0\n"), "{}", stdout);
+    assert!(stdout.contains("Copied to Source: 0\n"), "{}", stdout);
+    assert!(stdout.contains("In Both: 2\n"), "{}", stdout);
+}
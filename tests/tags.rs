@@ -0,0 +1,181 @@
+use beer_scape::bsmx::parse_bsmx;
+use beer_scape::index::{Filter, Index, IndexEntry};
+use beer_scape::tags::{matches_tags, parse_tag, tag_filter, Tags, TAGS_FILE};
+use std::fs;
+use std::process::{Command, Output};
+use tempfile::TempDir;
+
+fn tags(list: &[&str]) -> Vec<String> {
+    list.iter().map(|t| t.to_string()).collect()
+}
+
+fn beerscape(dir: &TempDir, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_beerscape"))
+        .current_dir(dir.path())
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+#[test]
+fn keeps_each_files_tags_sorted_and_unique() {
+    let dir = TempDir::new().unwrap();
+    let mut saved = Tags::default();
+    assert!(saved.add("1.bsmx", "want-to-brew"));
+    assert!(saved.add("1.bsmx", "tried"));
+    assert!(!saved.add("1.bsmx", "tried"));
+    assert!(saved.add("2.bsmx", "tried"));
+    saved.save(dir.path()).unwrap();
+
+    let mut loaded = Tags::load(dir.path()).unwrap();
+    assert_eq!(loaded, saved);
+    assert_eq!(loaded.of("1.bsmx"), tags(&["tried", "want-to-brew"]));
+    assert_eq!(loaded.files_with("tried"), ["1.bsmx", "2.bsmx"]);
+    assert!(loaded.remove("2.bsmx", "tried"));
+    assert!(!loaded.remove("2.bsmx", "tried"));
+    assert!(loaded.iter().all(|(filename, _)| filename != "2.bsmx"));
+
+    assert_eq!(
+        Tags::load(&dir.path().join("missing")).unwrap(),
+        Tags::default()
+    );
+    assert!(parse_tag(" ").is_err());
+    assert!(parse_tag("a,b").is_err());
+    assert_eq!(parse_tag(" tried ").unwrap(), "tried");
+}
+
+#[test]
+fn commas_are_or_and_repeats_are_and() {
+    let filter = tag_filter(&tags(&["tried,award-winner", "want-to-brew"]));
+    assert_eq!(
+        filter,
+        [tags(&["tried", "award-winner"]), tags(&["want-to-brew"])]
+    );
+    assert!(matches_tags(
+        &tags(&["award-winner", "want-to-brew"]),
+        &filter
+    ));
+    assert!(!matches_tags(&tags(&["tried", "award-winner"]), &filter));
+    assert!(matches_tags(&[], &[]));
+}
+
+#[test]
+fn the_index_filters_on_tags_like_it_does_in_memory() {
+    let dir = TempDir::new().unwrap();
+    let recipe = parse_bsmx(&fs::read("tests/fixtures/utf8.bsmx").unwrap()).unwrap();
+    let index = Index::open(dir.path()).unwrap();
+    for filename in ["1.bsmx", "2.bsmx", "3.bsmx"] {
+        index.upsert(&IndexEntry::new(filename, &recipe)).unwrap();
+    }
+    let mut saved = Tags::default();
+    saved.add("1.bsmx", "tried");
+    saved.add("1.bsmx", "award-winner");
+    saved.add("2.bsmx", "want-to-brew");
+    saved.add("gone.bsmx", "tried");
+    index.sync_tags(&saved).unwrap();
+
+    let entries = index.entries().unwrap();
+    assert_eq!(entries[0].tags, tags(&["award-winner", "tried"]));
+    assert!(entries[2].tags.is_empty());
+    for (wanted, expected) in [
+        (vec!["tried"], vec!["1.bsmx"]),
+        (vec!["tried,want-to-brew"], vec!["1.bsmx", "2.bsmx"]),
+        (vec!["tried", "award-winner"], vec!["1.bsmx"]),
+        (vec!["tried", "want-to-brew"], vec![]),
+    ] {
+        let filter = Filter {
+            tags: tag_filter(&tags(&wanted)),
+            ..Default::default()
+        };
+        let queried: Vec<_> = index
+            .matching(&filter, 0, 10)
+            .unwrap()
+            .into_iter()
+            .map(|e| e.filename)
+            .collect();
+        let in_memory: Vec<_> = entries
+            .iter()
+            .filter(|e| filter.matches(e))
+            .map(|e| e.filename.clone())
+            .collect();
+        assert_eq!(queried, expected, "{:?}", wanted);
+        assert_eq!(in_memory, expected, "{:?}", wanted);
+        assert_eq!(index.count_matching(&filter).unwrap(), expected.len());
+    }
+
+    index.remove("1.bsmx").unwrap();
+    index.rename("2.bsmx", "4.bsmx").unwrap();
+    let entries = index.entries().unwrap();
+    assert_eq!(entries[1].filename, "4.bsmx");
+    assert_eq!(entries[1].tags, tags(&["want-to-brew"]));
+}
+
+#[test]
+fn tag_subcommand_keeps_tags_json_and_the_index_in_step() {
+    let dir = TempDir::new().unwrap();
+    let recipes = dir.path().join("recipes");
+    fs::create_dir(&recipes).unwrap();
+    for name in ["1.bsmx", "2.bsmx"] {
+        fs::copy("tests/fixtures/utf8.bsmx", recipes.join(name)).unwrap();
+    }
+    stdout(&beerscape(&dir, &["index"]));
+
+    stdout(&beerscape(&dir, &["tag", "add", "1.bsmx", "tried"]));
+    stdout(&beerscape(
+        &dir,
+        &["tag", "add", "recipes/1.bsmx", "award-winner"],
+    ));
+    stdout(&beerscape(&dir, &["tag", "add", "2.bsmx", "want-to-brew"]));
+    assert!(!beerscape(&dir, &["tag", "add", "3.bsmx", "tried"])
+        .status
+        .success());
+    assert!(!beerscape(&dir, &["tag", "add", "1.bsmx", "a,b"])
+        .status
+        .success());
+
+    assert_eq!(
+        stdout(&beerscape(&dir, &["tag", "list", "1.bsmx"])),
+        "award-winner\ntried\n"
+    );
+    assert_eq!(
+        stdout(&beerscape(&dir, &["tag", "search", "want-to-brew"])),
+        "2.bsmx\n1 recipes\n"
+    );
+    let listed = stdout(&beerscape(
+        &dir,
+        &[
+            "list",
+            "--tag",
+            "tried,want-to-brew",
+            "--tag",
+            "award-winner",
+        ],
+    ));
+    assert!(listed.contains("1.bsmx"), "{}", listed);
+    assert!(!listed.contains("2.bsmx"), "{}", listed);
+
+    stdout(&beerscape(&dir, &["tag", "remove", "1.bsmx", "tried"]));
+    let listed = stdout(&beerscape(&dir, &["list", "--tag", "tried"]));
+    assert!(listed.ends_with("0 recipes\n"), "{}", listed);
+    let saved: serde_json::Value =
+        serde_json::from_slice(&fs::read(recipes.join(TAGS_FILE)).unwrap()).unwrap();
+    assert_eq!(
+        saved,
+        serde_json::json!({"1.bsmx": ["award-winner"], "2.bsmx": ["want-to-brew"]})
+    );
+
+    // A rebuilt index picks the tags back up
+    fs::remove_file(recipes.join("index.db")).unwrap();
+    stdout(&beerscape(&dir, &["index"]));
+    let listed = stdout(&beerscape(&dir, &["list", "--tag", "want-to-brew"]));
+    assert!(listed.ends_with("2.bsmx\n1 recipes\n"), "{}", listed);
+}
@@ -0,0 +1,91 @@
+use beer_scape::fixtures::{Fixtures, MockResponse, NOT_FOUND_RATE, TIMEOUT_RATE};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::fs;
+use std::process::Command;
+use std::time::Duration;
+use tempfile::TempDir;
+
+fn beerscape(dir: &TempDir, args: &[&str]) -> (bool, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_beerscape"))
+        .current_dir(dir.path())
+        .args(args)
+        .output()
+        .unwrap();
+    let text = format!(
+        "{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    (output.status.success(), text)
+}
+
+#[test]
+fn answers_fail_at_their_rates() {
+    let dir = TempDir::new().unwrap();
+    fs::copy("tests/fixtures/utf8.bsmx", dir.path().join("1.bsmx")).unwrap();
+    let fixtures = Fixtures::load(dir.path(), Duration::ZERO).unwrap();
+    let mut rng = StdRng::seed_from_u64(7);
+    let (mut not_found, mut timed_out) = (0, 0);
+    let draws = 10_000;
+    for _ in 0..draws {
+        match fixtures.answer_with(&mut rng).unwrap() {
+            MockResponse::NotFound => not_found += 1,
+            MockResponse::TimedOut => timed_out += 1,
+            MockResponse::Recipe(content) => assert!(content.starts_with(b"<")),
+        }
+    }
+    let share = |n: i32| n as f64 / draws as f64;
+    assert!((share(not_found) - NOT_FOUND_RATE).abs() < 0.015);
+    assert!((share(timed_out) - TIMEOUT_RATE).abs() < 0.01);
+
+    let empty = TempDir::new().unwrap();
+    let e = Fixtures::load(empty.path(), Duration::ZERO).unwrap_err();
+    assert!(e.to_string().contains("beerscape fixtures"), "{}", e);
+}
+
+#[test]
+fn downloads_from_fixtures_copied_from_the_collection() {
+    let dir = TempDir::new().unwrap();
+    let recipes = dir.path().join("recipes");
+    fs::create_dir(&recipes).unwrap();
+    for id in 1..=3 {
+        fs::copy(
+            "tests/fixtures/utf8.bsmx",
+            recipes.join(format!("{}.bsmx", id)),
+        )
+        .unwrap();
+    }
+    let (ok, out) = beerscape(&dir, &["fixtures", "--count", "2", "--output", "fx"]);
+    assert!(ok, "{}", out);
+    assert!(out.contains("Copied 2 recipes to fx"), "{}", out);
+    assert_eq!(fs::read_dir(dir.path().join("fx")).unwrap().count(), 2);
+
+    let run = TempDir::new().unwrap();
+    let fixtures = dir.path().join("fx");
+    let (ok, out) = beerscape(
+        &run,
+        &[
+            "--test-mode",
+            fixtures.to_str().unwrap(),
+            "--mock-latency-ms",
+            "0",
+            "--id-range",
+            "100-139",
+            "--no-progress",
+        ],
+    );
+    assert!(ok, "{}", out);
+    assert!(out.contains("Total Attempts: 40"), "{}", out);
+    let saved = fs::read_dir(run.path().join("recipes"))
+        .unwrap()
+        .flatten()
+        .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "bsmx"))
+        .count();
+    assert!(saved > 0 && saved <= 40, "{}", out);
+    assert!(
+        out.contains(&format!("Newly Downloaded: {}", saved)),
+        "{}",
+        out
+    );
+}
@@ -0,0 +1,108 @@
+use beer_scape::throttle::{ByteLimiter, ByteSize, RequestGate, TransferMeter};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+#[test]
+fn parses_sizes_and_rates() {
+    assert_eq!("2MB/s".parse(), Ok(ByteSize(2_000_000)));
+    assert_eq!("512KiB".parse(), Ok(ByteSize(524_288)));
+    assert_eq!("1.5 mb".parse(), Ok(ByteSize(1_500_000)));
+    assert_eq!("4096".parse(), Ok(ByteSize(4096)));
+    assert!("2 parsecs".parse::<ByteSize>().is_err());
+    assert!("0MB/s".parse::<ByteSize>().is_err());
+    assert!("fast".parse::<ByteSize>().is_err());
+    assert_eq!(ByteSize(2_500_000).to_string(), "2.5 MB");
+}
+
+#[tokio::test]
+async fn holds_transfers_to_the_rate() {
+    let limiter = ByteLimiter::new(ByteSize(100_000), ByteSize(10_000));
+    let started = Instant::now();
+    // The first 10 KB fit in the bucket; the other 40 KB take 0.4s
+    for _ in 0..5 {
+        limiter.take(10_000).await;
+    }
+    let elapsed = started.elapsed();
+    assert!(elapsed >= Duration::from_millis(350), "{:?}", elapsed);
+    assert!(elapsed < Duration::from_millis(800), "{:?}", elapsed);
+}
+
+#[tokio::test]
+async fn a_large_transfer_does_not_starve_a_small_one() {
+    let limiter = Arc::new(ByteLimiter::new(ByteSize(100_000), ByteSize(1_000)));
+    let large = {
+        let limiter = limiter.clone();
+        tokio::spawn(async move {
+            for _ in 0..60 {
+                limiter.take(1_000).await;
+            }
+            Instant::now()
+        })
+    };
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    let small = {
+        let limiter = limiter.clone();
+        tokio::spawn(async move {
+            for _ in 0..5 {
+                limiter.take(1_000).await;
+            }
+            Instant::now()
+        })
+    };
+    let small_done = small.await.unwrap();
+    let large_done = large.await.unwrap();
+    // Taking turns, the small one needs about 0.1s more, not the large
+    // one's remaining 0.5s
+    assert!(large_done - small_done > Duration::from_millis(200));
+}
+
+#[tokio::test]
+async fn spaces_requests_to_the_cap() {
+    let gate = Arc::new(RequestGate::new(Some(20.0)));
+    let started = Instant::now();
+    // Sent together, as a batch would be: the first goes at once and the
+    // other nine a twentieth of a second apart
+    let requests: Vec<_> = (0..10)
+        .map(|_| {
+            let gate = gate.clone();
+            tokio::spawn(async move { gate.pass().await })
+        })
+        .collect();
+    for request in requests {
+        request.await.unwrap();
+    }
+    let elapsed = started.elapsed();
+    assert!(elapsed >= Duration::from_millis(430), "{:?}", elapsed);
+    assert!(elapsed < Duration::from_millis(900), "{:?}", elapsed);
+    // Measured over at least a second, so a handful of requests can't look
+    // like a flood
+    assert!(gate.recent_rate() <= 10.0, "{}", gate.recent_rate());
+}
+
+#[tokio::test]
+async fn counts_requests_without_a_cap() {
+    let gate = RequestGate::new(None);
+    let started = Instant::now();
+    for _ in 0..50 {
+        gate.pass().await;
+    }
+    assert!(started.elapsed() < Duration::from_millis(100));
+    assert_eq!(gate.max_rps(), None);
+    assert_eq!(gate.recent_rate(), 50.0);
+}
+
+#[test]
+fn meters_bytes_received_and_recipes_saved() {
+    let meter = TransferMeter::default();
+    assert_eq!(meter.rate(), 0.0);
+    assert_eq!(meter.average_saved(), None);
+
+    meter.record(3000);
+    meter.record(1000);
+    meter.saved(4000);
+    meter.saved(2000);
+    assert_eq!(meter.received(), 4000);
+    // Under a second in, the rate is taken over a whole second
+    assert_eq!(meter.rate(), 4000.0);
+    assert_eq!(meter.average_saved(), Some(3000));
+}
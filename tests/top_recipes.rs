@@ -0,0 +1,78 @@
+use beer_scape::bsmx::{Fermentable, Hop, Recipe, Yeast};
+use beer_scape::cli::RankBy;
+use beer_scape::commands::top_recipes::rank;
+use std::path::PathBuf;
+
+fn recipe(name: &str, fermentables: usize, hops: usize) -> Recipe {
+    Recipe {
+        name: name.to_string(),
+        fermentables: vec![Fermentable::default(); fermentables],
+        hops: vec![Hop::default(); hops],
+        ..Recipe::default()
+    }
+}
+
+fn collection() -> Vec<(PathBuf, Option<u32>, Recipe)> {
+    let mut full = recipe("Full", 2, 2);
+    full.brewer = "Ann".to_string();
+    full.style.name = "Porter".to_string();
+    full.og = 1.050;
+    full.fg = 1.012;
+    full.yeasts = vec![Yeast::default()];
+    vec![
+        (PathBuf::from("recipes/300.bsmx"), Some(300), full),
+        (
+            PathBuf::from("recipes/100.bsmx"),
+            Some(100),
+            recipe("Old", 1, 0),
+        ),
+        (
+            PathBuf::from("recipes/Stray.bsmx"),
+            None,
+            recipe("Stray", 3, 4),
+        ),
+        (
+            PathBuf::from("recipes/200.bsmx"),
+            Some(200),
+            recipe("Middle", 0, 0),
+        ),
+    ]
+}
+
+fn names(ranked: &[beer_scape::commands::top_recipes::Ranked]) -> Vec<&str> {
+    ranked.iter().map(|r| r.name.as_str()).collect()
+}
+
+#[test]
+fn ranks_by_id_oldest_first() {
+    let ranked = rank(&collection(), RankBy::Id);
+    assert_eq!(names(&ranked), ["Old", "Middle", "Full"]);
+    assert_eq!(ranked[0].score, 1.0);
+    assert_eq!(ranked[1].score, 0.5);
+    assert_eq!(ranked[2].score, 0.0);
+}
+
+#[test]
+fn ranks_by_ingredients() {
+    let ranked = rank(&collection(), RankBy::Ingredients);
+    assert_eq!(names(&ranked), ["Stray", "Full", "Old", "Middle"]);
+    assert_eq!(ranked[0].score, 7.0);
+}
+
+#[test]
+fn ranks_by_completeness() {
+    let ranked = rank(&collection(), RankBy::Completeness);
+    assert_eq!(ranked[0].name, "Full");
+    assert_eq!(names(&ranked[1..]), ["Stray", "Old", "Middle"]);
+    assert_eq!(ranked[1].score, 0.5);
+}
+
+#[test]
+fn combined_weighs_each_signal_equally() {
+    let ranked = rank(&collection(), RankBy::Combined);
+    assert_eq!(ranked.len(), 4);
+    let old = ranked.iter().find(|r| r.name == "Old").unwrap();
+    let expected = (1.0 + 0.3 + 1.0 / 7.0) / 3.0;
+    assert!((old.score - expected).abs() < 1e-9, "{}", old.score);
+    assert!(ranked.windows(2).all(|w| w[0].score >= w[1].score));
+}
@@ -0,0 +1,130 @@
+mod common;
+
+use beer_scape::download::{build_client, download_recipe, DownloadConfig, DownloadStats};
+use common::fixture;
+use flate2::write::GzEncoder;
+use std::fs;
+use std::io::Write;
+use tempfile::TempDir;
+use wiremock::matchers::{header, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+fn gzip(bytes: &[u8]) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(bytes).unwrap();
+    encoder.finish().unwrap()
+}
+
+fn brotli(bytes: &[u8]) -> Vec<u8> {
+    let mut compressed = Vec::new();
+    let mut encoder = brotli::CompressorWriter::new(&mut compressed, 4096, 9, 22);
+    encoder.write_all(bytes).unwrap();
+    drop(encoder);
+    compressed
+}
+
+async fn serve(server: &MockServer, encoding: &str, body: Vec<u8>) {
+    Mock::given(method("GET"))
+        .and(path("/download.php"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("content-encoding", encoding)
+                .set_body_raw(body, "application/octet-stream"),
+        )
+        .mount(server)
+        .await;
+}
+
+#[tokio::test]
+async fn saves_gzip_and_brotli_transfers_decompressed() {
+    for (encoding, compressed) in [("gzip", gzip(&fixture())), ("br", brotli(&fixture()))] {
+        let server = MockServer::start().await;
+        let dir = TempDir::new().unwrap();
+        serve(&server, encoding, compressed.clone()).await;
+        let config = DownloadConfig {
+            base_url: server.uri(),
+            output_dir: dir.path().to_path_buf(),
+            ..Default::default()
+        };
+
+        let client = build_client(&config).unwrap();
+        download_recipe(&client, &config, 7).await.unwrap().unwrap();
+
+        assert_eq!(fs::read(dir.path().join("7.bsmx")).unwrap(), fixture());
+        let sent = server.received_requests().await.unwrap();
+        assert_eq!(sent[0].headers.get("accept-encoding").unwrap(), "gzip, br");
+        assert_eq!(config.transfers.received(), compressed.len() as u64);
+        assert_eq!(
+            config.transfers.compression(),
+            (compressed.len() as u64, fixture().len() as u64)
+        );
+    }
+}
+
+#[tokio::test]
+async fn no_compression_asks_for_recipes_plain() {
+    let server = MockServer::start().await;
+    let dir = TempDir::new().unwrap();
+    Mock::given(method("GET"))
+        .and(path("/download.php"))
+        .and(header("accept-encoding", "gzip, br"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/download.php"))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(fixture()))
+        .mount(&server)
+        .await;
+    let config = DownloadConfig {
+        base_url: server.uri(),
+        output_dir: dir.path().to_path_buf(),
+        transfer_compression: false,
+        ..Default::default()
+    };
+
+    let client = build_client(&config).unwrap();
+    download_recipe(&client, &config, 7).await.unwrap().unwrap();
+
+    assert_eq!(fs::read(dir.path().join("7.bsmx")).unwrap(), fixture());
+    assert_eq!(config.transfers.compression(), (0, 0));
+}
+
+#[tokio::test]
+async fn a_broken_encoding_is_not_a_recipe() {
+    let server = MockServer::start().await;
+    let dir = TempDir::new().unwrap();
+    // Sniffed as XML if it were saved as it came
+    serve(&server, "gzip", fixture()).await;
+    let config = DownloadConfig {
+        base_url: server.uri(),
+        output_dir: dir.path().to_path_buf(),
+        ..Default::default()
+    };
+
+    let client = build_client(&config).unwrap();
+    assert!(download_recipe(&client, &config, 7)
+        .await
+        .unwrap()
+        .is_none());
+    assert!(!dir.path().join("7.bsmx").exists());
+}
+
+#[test]
+fn the_summary_shows_what_compression_saved() {
+    let stats = DownloadStats {
+        downloaded: 1,
+        attempted: 1,
+        bytes: 40_960,
+        transferred: 10_240,
+        compressed: 10_240,
+        decompressed: 40_960,
+        ..Default::default()
+    };
+    let summary = stats.summary();
+    assert!(
+        summary.contains("\nCompressed in Transit: 10.0 KiB for 40.0 KiB (75.0% saved)\n"),
+        "{}",
+        summary
+    );
+}
@@ -0,0 +1,131 @@
+use beer_scape::cli::UaStrategy;
+use beer_scape::user_agents::{read_user_agents, UserAgents, BUILT_IN_USER_AGENTS};
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+use std::process::Stdio;
+use tempfile::TempDir;
+use tokio::process::Command;
+use wiremock::matchers::{header, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+fn fixed(agents: &UserAgents) -> &str {
+    match agents {
+        UserAgents::Fixed(ua) => ua.to_str().unwrap(),
+        UserAgents::Rotating(_) => panic!("rotating: {:?}", agents),
+    }
+}
+
+#[test]
+fn keeps_to_one_user_agent_unless_asked_to_rotate() {
+    let default = UserAgents::from_args(None, None, None).unwrap();
+    assert_eq!(fixed(&default), BUILT_IN_USER_AGENTS[0]);
+    assert_eq!(default.pick(), default.default_value());
+
+    let custom = UserAgents::from_args(Some("ArchiveBot/2.0"), None, None).unwrap();
+    assert_eq!(fixed(&custom), "ArchiveBot/2.0");
+    assert!(UserAgents::from_args(Some("bad\nvalue"), None, None).is_err());
+
+    let per_run = UserAgents::from_args(None, None, Some(UaStrategy::PerRun)).unwrap();
+    assert!(BUILT_IN_USER_AGENTS.contains(&fixed(&per_run)));
+
+    let per_request = UserAgents::from_args(None, None, Some(UaStrategy::PerRequest)).unwrap();
+    let UserAgents::Rotating(pool) = &per_request else {
+        panic!("{:?}", per_request);
+    };
+    assert_eq!(pool.len(), BUILT_IN_USER_AGENTS.len());
+    assert!(pool.contains(per_request.pick()));
+}
+
+#[test]
+fn reads_user_agent_files() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("agents.txt");
+    fs::write(&path, "# desktop\nAgent/1\n\n  Agent/2  \n").unwrap();
+    let pool = read_user_agents(&path).unwrap();
+    assert_eq!(pool, ["Agent/1", "Agent/2"]);
+
+    // A file alone keeps to one of its agents for the run
+    let agents = UserAgents::from_args(None, Some(&path), None).unwrap();
+    assert!(["Agent/1", "Agent/2"].contains(&fixed(&agents)));
+
+    fs::write(&path, "# nothing\n").unwrap();
+    assert!(read_user_agents(&path)
+        .unwrap_err()
+        .contains("lists no User-Agents"));
+    assert!(read_user_agents(Path::new("missing.txt")).is_err());
+}
+
+fn beerscape(dir: &TempDir, args: &[&str]) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_beerscape"));
+    command
+        .current_dir(dir.path())
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    command
+}
+
+#[tokio::test]
+async fn sidecars_record_the_user_agent() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/download.php"))
+        .and(header("user-agent", "ArchiveBot/2.0"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_bytes(fs::read("tests/fixtures/utf8.bsmx").unwrap()),
+        )
+        .mount(&server)
+        .await;
+    let dir = TempDir::new().unwrap();
+    let output = beerscape(
+        &dir,
+        &[
+            "--base-url",
+            &server.uri(),
+            "--user-agent",
+            "ArchiveBot/2.0",
+            "--sidecar",
+            "--ids",
+            "7",
+        ],
+    )
+    .output()
+    .await
+    .unwrap();
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr);
+    let sidecar: Value =
+        serde_json::from_slice(&fs::read(dir.path().join("recipes/7.json")).unwrap()).unwrap();
+    assert_eq!(sidecar["user_agent"], "ArchiveBot/2.0");
+}
+
+#[tokio::test]
+async fn a_user_agent_header_cant_be_rotated() {
+    let dir = TempDir::new().unwrap();
+    let output = beerscape(
+        &dir,
+        &[
+            "--header",
+            "User-Agent: Mine/1.0",
+            "--ua-strategy",
+            "per-request",
+            "--ids",
+            "1",
+        ],
+    )
+    .output()
+    .await
+    .unwrap();
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("can't be combined with --user-agent"),
+        "{}",
+        stderr
+    );
+}
@@ -0,0 +1,73 @@
+use beer_scape::beerxml::{export_beerxml, parse_beerxml};
+use beer_scape::bsmx::{parse_bsmx, Flocculation, Recipe, Yeast};
+use beer_scape::commands::report::yeast_usage;
+
+const RECIPE: &str = "<Recipe><F_R_NAME>Test</F_R_NAME><Ingredients><Data>\
+    <Yeast><F_Y_NAME>California Ale</F_Y_NAME><F_Y_LAB>White Labs</F_Y_LAB>\
+    <F_Y_PRODUCT_ID>WLP001</F_Y_PRODUCT_ID><F_Y_MIN_TEMP>68</F_Y_MIN_TEMP>\
+    <F_Y_MAX_TEMP>73.4</F_Y_MAX_TEMP><F_Y_MIN_ATTENUATION>73</F_Y_MIN_ATTENUATION>\
+    <F_Y_MAX_ATTENUATION>80</F_Y_MAX_ATTENUATION><F_Y_FLOCCULATION>2</F_Y_FLOCCULATION></Yeast>\
+    </Data></Ingredients></Recipe>";
+
+#[test]
+fn parses_yeast_details() {
+    let recipe = parse_bsmx(RECIPE.as_bytes()).unwrap();
+    let yeast = &recipe.yeasts[0];
+    assert_eq!(yeast.lab, "White Labs");
+    assert_eq!(yeast.product_id, "WLP001");
+    assert!((yeast.min_temp_c - 20.0).abs() < 1e-9);
+    assert!((yeast.max_temp_c - 23.0).abs() < 1e-9);
+    assert_eq!(yeast.attenuation_pct, 76.5);
+    assert_eq!(yeast.flocculation, Flocculation::High);
+
+    let back = parse_beerxml(export_beerxml(&recipe).as_bytes()).unwrap();
+    assert_eq!(back.yeasts, recipe.yeasts);
+}
+
+fn yeast(name: &str, lab: &str, product_id: &str, attenuation_pct: f64) -> Yeast {
+    Yeast {
+        name: name.to_string(),
+        lab: lab.to_string(),
+        product_id: product_id.to_string(),
+        attenuation_pct,
+        min_temp_c: 18.0,
+        max_temp_c: 22.0,
+        ..Default::default()
+    }
+}
+
+fn recipe(yeasts: Vec<Yeast>) -> Recipe {
+    Recipe {
+        yeasts,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn counts_strains_by_lab_and_product() {
+    let recipes = [
+        recipe(vec![yeast("California Ale", "White Labs", "WLP001", 76.0)]),
+        // Same strain under another name, pitched twice
+        recipe(vec![
+            yeast("Cal Ale", "white labs", "wlp001", 0.0),
+            yeast("Cal Ale", "white labs", "wlp001", 0.0),
+        ]),
+        recipe(vec![
+            yeast("American Ale", "Wyeast", "1056", 75.0),
+            yeast("Kveik", "", "", 80.0),
+        ]),
+    ];
+
+    let usage = yeast_usage(&recipes, None);
+    assert_eq!(usage.len(), 3);
+    assert_eq!(usage[0].name, "California Ale");
+    assert_eq!(usage[0].recipes, 2);
+    // The recipe without an attenuation doesn't drag the mean down
+    assert_eq!(usage[0].attenuation_pct, 76.0);
+    assert_eq!(usage[1].name, "American Ale");
+    assert_eq!(usage[2].name, "Kveik");
+
+    let wyeast = yeast_usage(&recipes, Some("WYEAST"));
+    assert_eq!(wyeast.len(), 1);
+    assert_eq!(wyeast[0].product_id, "1056");
+}